@@ -1,14 +1,82 @@
-use core::ops::{BitOr, BitOrAssign};
+use alloc::vec::Vec;
+use core::{
+    fmt, mem,
+    ops::{BitOr, BitOrAssign, Range},
+    ptr, slice,
+};
 
 use r_efi::efi;
 
 use crate::{boxed::BootServicesBox, BootServices};
 
+/// Size of a page allocated by [`BootServices::allocate_pages`].
+pub const UEFI_PAGE_SIZE: usize = 0x1000;
+
+/// Highest address of the first 4GB, for [`AllocType::below_4gb`].
+pub const MAX_ADDRESS_BELOW_4GB: usize = 0xFFFF_FFFF;
+
+// Allocations returned below the range by the firmware that are kept while retrying, before giving up.
+const IN_RANGE_MAX_RETRIES: usize = 8;
+
 #[derive(Debug)]
 pub enum AllocType {
     AnyPage,
     MaxAddress(usize),
     Address(usize),
+    /// Pages entirely within the range, see [`AllocType::in_range`].
+    InRange(Range<usize>),
+}
+
+impl AllocType {
+    /// Pages entirely below 4GB, e.g. for DMA buffers of devices limited to 32-bit addresses.
+    pub const fn below_4gb() -> Self {
+        AllocType::MaxAddress(MAX_ADDRESS_BELOW_4GB)
+    }
+
+    /// Pages entirely within *range*.
+    ///
+    /// The pages are allocated below the end of the range, and allocated again if the firmware returns pages starting
+    /// before the range, while keeping the rejected pages so they are not returned again. `efi::Status::NOT_FOUND` is
+    /// returned if the pages can not be allocated within the range after a few attempts.
+    pub const fn in_range(range: Range<usize>) -> Self {
+        AllocType::InRange(range)
+    }
+}
+
+// Allocates *nb_pages* pages of *memory_type* within *range*, see AllocType::in_range.
+pub(crate) fn allocate_pages_in_range<B: BootServices + ?Sized>(
+    boot_services: &B,
+    range: Range<usize>,
+    memory_type: MemoryType,
+    nb_pages: usize,
+) -> Result<usize, efi::Status> {
+    let size = nb_pages.checked_mul(UEFI_PAGE_SIZE).ok_or(efi::Status::INVALID_PARAMETER)?;
+    if size == 0 || range.end.saturating_sub(range.start) < size {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+
+    let mut rejected = Vec::new();
+    let result = loop {
+        let address = match boot_services.allocate_pages(AllocType::MaxAddress(range.end - 1), memory_type, nb_pages) {
+            Ok(address) => address,
+            Err(status) => break Err(status),
+        };
+        if address >= range.start && address.checked_add(size).is_some_and(|end| end <= range.end) {
+            break Ok(address);
+        }
+        rejected.push(address);
+        if rejected.len() == IN_RANGE_MAX_RETRIES {
+            break Err(efi::Status::NOT_FOUND);
+        }
+    };
+    for address in rejected {
+        let _ = boot_services.free_pages(address, nb_pages);
+    }
+    match result {
+        // Running out of pages below the end of the range after rejecting some means none is left in the range.
+        Err(efi::Status::OUT_OF_RESOURCES | efi::Status::NOT_FOUND) => Err(efi::Status::NOT_FOUND),
+        result => result,
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,11 +100,60 @@ impl MemoryType {
     pub const PAL_CODE: MemoryType = MemoryType(efi::PAL_CODE);
     pub const PERSISTENT_MEMORY: MemoryType = MemoryType(efi::PERSISTENT_MEMORY);
     pub const UNACCEPTED_MEMORY_TYPE: MemoryType = MemoryType(efi::UNACCEPTED_MEMORY_TYPE);
+
+    /// First memory type of the range reserved for OEM use.
+    pub const OEM_RESERVED_MIN: u32 = 0x70000000;
+    /// Last memory type of the range reserved for OEM use.
+    pub const OEM_RESERVED_MAX: u32 = 0x7FFFFFFF;
+    /// First memory type of the range reserved for UEFI OS loaders provided by operating system vendors.
+    pub const OS_RESERVED_MIN: u32 = 0x80000000;
+    /// Last memory type of the range reserved for UEFI OS loaders provided by operating system vendors.
+    pub const OS_RESERVED_MAX: u32 = 0xFFFFFFFF;
+
+    /// Create an OEM or OS specific memory type.
+    ///
+    /// Returns None if *memory_type* is not in the OEM or OS reserved ranges.
+    pub const fn custom(memory_type: u32) -> Option<MemoryType> {
+        match memory_type {
+            Self::OEM_RESERVED_MIN..=Self::OS_RESERVED_MAX => Some(MemoryType(memory_type)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is a memory type defined by the UEFI specification.
+    pub const fn is_standard(&self) -> bool {
+        self.0 <= efi::UNACCEPTED_MEMORY_TYPE
+    }
+
+    /// Returns true if this is a memory type in the OEM reserved range.
+    pub const fn is_oem_reserved(&self) -> bool {
+        matches!(self.0, Self::OEM_RESERVED_MIN..=Self::OEM_RESERVED_MAX)
+    }
+
+    /// Returns true if this is a memory type in the OS reserved range.
+    pub const fn is_os_reserved(&self) -> bool {
+        self.0 >= Self::OS_RESERVED_MIN
+    }
 }
 
-impl Into<u32> for MemoryType {
-    fn into(self) -> u32 {
-        self.0
+impl From<MemoryType> for u32 {
+    fn from(memory_type: MemoryType) -> Self {
+        memory_type.0
+    }
+}
+
+/// Converts a raw memory type, e.g. from a memory map descriptor.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` for the values between the standard memory types and the OEM reserved
+/// range, which are reserved by the UEFI specification.
+impl TryFrom<u32> for MemoryType {
+    type Error = efi::Status;
+
+    fn try_from(memory_type: u32) -> Result<Self, Self::Error> {
+        match MemoryType(memory_type) {
+            memory_type if memory_type.is_standard() => Ok(memory_type),
+            _ => MemoryType::custom(memory_type).ok_or(efi::Status::INVALID_PARAMETER),
+        }
     }
 }
 
@@ -47,6 +164,152 @@ pub struct MemoryMap<'a, B: BootServices + ?Sized> {
     pub descriptor_version: u32,
 }
 
+impl<B: BootServices> MemoryMap<'_, B> {
+    /// Returns true if the map key is still the key of the current memory map, i.e. it can be given to
+    /// [`BootServices::exit_boot_services`].
+    ///
+    /// The key is queried without retrieving the map, which is only reported by firmware setting the key along
+    /// `efi::Status::BUFFER_TOO_SMALL`, as EDK II does. With other firmware this conservatively returns false.
+    pub fn is_current<T: BootServices>(&self, boot_services: &T) -> bool {
+        is_current_map_key(boot_services, self.map_key)
+    }
+}
+
+fn is_current_map_key<B: BootServices>(boot_services: &B, map_key: usize) -> bool {
+    // The firmware leaves the key untouched when it does not report it, which can not be mistaken for the stored key.
+    boot_services.get_memory_map_key_unchecked(!map_key) == map_key
+}
+
+/// Pages allocated by [`BootServices::allocate_pages`], freed with [`BootServices::free_pages`] when dropped.
+///
+/// This is the page counterpart of [`BootServicesBox`] for pool memory.
+#[derive(Debug)]
+#[must_use]
+pub struct PageAllocation<'a, B: BootServices + ?Sized> {
+    address: usize,
+    nb_pages: usize,
+    boot_services: &'a B,
+}
+
+impl<'a, B: BootServices> PageAllocation<'a, B> {
+    /// Allocate *nb_pages* pages of *memory_type*.
+    pub fn new(
+        boot_services: &'a B,
+        alloc_type: AllocType,
+        memory_type: MemoryType,
+        nb_pages: usize,
+    ) -> Result<Self, efi::Status> {
+        let address = boot_services.allocate_pages(alloc_type, memory_type, nb_pages)?;
+        Ok(Self { address, nb_pages, boot_services })
+    }
+
+    /// Take ownership of the *nb_pages* pages at *address*.
+    ///
+    /// # Safety
+    ///
+    /// The pages must have been allocated with [`BootServices::allocate_pages`] and not be owned by anything else.
+    pub unsafe fn from_raw(address: usize, nb_pages: usize, boot_services: &'a B) -> Self {
+        Self { address, nb_pages, boot_services }
+    }
+
+    /// Returns the address of the first page.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Returns the number of pages allocated.
+    pub fn nb_pages(&self) -> usize {
+        self.nb_pages
+    }
+
+    /// Returns the size of the allocation in bytes.
+    pub fn len(&self) -> usize {
+        self.nb_pages * UEFI_PAGE_SIZE
+    }
+
+    /// Returns true if no page is allocated.
+    pub fn is_empty(&self) -> bool {
+        self.nb_pages == 0
+    }
+
+    /// Returns the pages as bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: The allocation owns the pages, and AllocatePages returns memory that is readable.
+        unsafe { slice::from_raw_parts(self.address as *const u8, self.len()) }
+    }
+
+    /// Returns the pages as bytes.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: The allocation owns the pages exclusively.
+        unsafe { slice::from_raw_parts_mut(self.address as *mut u8, self.len()) }
+    }
+
+    /// Returns the address and number of pages, which are no longer freed.
+    pub fn into_raw(self) -> (usize, usize) {
+        let raw = (self.address, self.nb_pages);
+        mem::forget(self);
+        raw
+    }
+
+    /// Leak the pages, e.g. to hand them over to the OS.
+    pub fn leak(self) -> &'a mut [u8] {
+        let (address, nb_pages) = self.into_raw();
+        // SAFETY: The pages are never freed and no longer owned by the allocation.
+        unsafe { slice::from_raw_parts_mut(address as *mut u8, nb_pages * UEFI_PAGE_SIZE) }
+    }
+}
+
+impl<B: BootServices + ?Sized> Drop for PageAllocation<'_, B> {
+    fn drop(&mut self) {
+        let _ = self.boot_services.free_pages(self.address, self.nb_pages);
+    }
+}
+
+/// Memory map stored in a caller supplied buffer, see [`BootServicesExt::get_memory_map_into`].
+///
+/// [`BootServicesExt::get_memory_map_into`]: crate::BootServicesExt::get_memory_map_into
+#[derive(Debug)]
+pub struct MemoryMapView<'a> {
+    buffer: &'a [u8],
+    pub map_key: usize,
+    /// Size of a descriptor in the buffer, it can be bigger than [`efi::MemoryDescriptor`].
+    pub descriptor_size: usize,
+    pub descriptor_version: u32,
+}
+
+impl<'a> MemoryMapView<'a> {
+    pub(crate) fn new(buffer: &'a [u8], map_key: usize, descriptor_size: usize, descriptor_version: u32) -> Self {
+        Self { buffer, map_key, descriptor_size, descriptor_version }
+    }
+
+    /// Returns true if the map key is still the key of the current memory map, see [`MemoryMap::is_current`].
+    pub fn is_current<B: BootServices>(&self, boot_services: &B) -> bool {
+        is_current_map_key(boot_services, self.map_key)
+    }
+
+    /// Returns the number of descriptors in the memory map.
+    pub fn len(&self) -> usize {
+        if self.descriptor_size < mem::size_of::<efi::MemoryDescriptor>() {
+            return 0;
+        }
+        self.buffer.len() / self.descriptor_size
+    }
+
+    /// Returns true if the memory map has no descriptor.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the descriptors of the memory map.
+    pub fn descriptors(&self) -> impl Iterator<Item = efi::MemoryDescriptor> + 'a {
+        let buffer = self.buffer;
+        let descriptor_size = self.descriptor_size;
+        //SAFETY: Every descriptor is within the buffer, the buffer has no alignment requirement.
+        (0..self.len())
+            .map(move |idx| unsafe { ptr::read_unaligned(buffer[idx * descriptor_size..].as_ptr() as *const _) })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MemoryAttribute(u64);
 
@@ -67,6 +330,92 @@ impl MemoryAttribute {
     pub const RUNTIME: MemoryAttribute = MemoryAttribute(efi::MEMORY_RUNTIME);
     pub const ISA_VALID: MemoryAttribute = MemoryAttribute(efi::MEMORY_ISA_VALID);
     pub const ISA_MASK: MemoryAttribute = MemoryAttribute(efi::MEMORY_ISA_MASK);
+
+    // Name of the flags, in bit order. ISA_MASK is a field holding an ISA specific value rather than a flag.
+    const FLAGS: [(&'static str, MemoryAttribute); 15] = [
+        ("UC", Self::UC),
+        ("WC", Self::WC),
+        ("WT", Self::WT),
+        ("WB", Self::WB),
+        ("UCE", Self::UCE),
+        ("WP", Self::WP),
+        ("RP", Self::RP),
+        ("XP", Self::XP),
+        ("NV", Self::NV),
+        ("MORE_RELIABLE", Self::MORE_RELIABLE),
+        ("RO", Self::RO),
+        ("SP", Self::SP),
+        ("CPU_CRYPTO", Self::CPU_CRYPTO),
+        ("ISA_VALID", Self::ISA_VALID),
+        ("RUNTIME", Self::RUNTIME),
+    ];
+
+    /// Every bit defined by the UEFI specification.
+    pub const ALL: MemoryAttribute = MemoryAttribute(
+        efi::MEMORY_UC
+            | efi::MEMORY_WC
+            | efi::MEMORY_WT
+            | efi::MEMORY_WB
+            | efi::MEMORY_UCE
+            | efi::MEMORY_WP
+            | efi::MEMORY_RP
+            | efi::MEMORY_XP
+            | efi::MEMORY_NV
+            | efi::MEMORY_MORE_RELIABLE
+            | efi::MEMORY_RO
+            | efi::MEMORY_SP
+            | efi::MEMORY_CPU_CRYPTO
+            | efi::MEMORY_RUNTIME
+            | efi::MEMORY_ISA_VALID
+            | efi::MEMORY_ISA_MASK,
+    );
+
+    /// Returns the raw bits of the attributes.
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns true if no bit is set.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every bit of *other* is set.
+    pub const fn contains(&self, other: MemoryAttribute) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the names of the flags set, e.g. `"WB"` and `"XP"`, in bit order.
+    ///
+    /// The ISA specific value of [`Self::ISA_MASK`] is not a flag and has no name.
+    pub fn iter_flags(&self) -> impl Iterator<Item = &'static str> {
+        let attributes = *self;
+        Self::FLAGS.into_iter().filter(move |(_, flag)| attributes.contains(*flag)).map(|(name, _)| name)
+    }
+}
+
+/// Bits of a raw memory attribute value that are not defined by the UEFI specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnrecognizedMemoryAttributes(u64);
+
+impl UnrecognizedMemoryAttributes {
+    /// Returns the unrecognized bits.
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns each unrecognized bit, lowest first.
+    pub fn flags(&self) -> impl Iterator<Item = u64> {
+        let bits = self.0;
+        (0..u64::BITS).map(|bit| 1 << bit).filter(move |flag| bits & flag != 0)
+    }
+}
+
+impl fmt::Display for UnrecognizedMemoryAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unrecognized memory attributes:")?;
+        self.flags().try_for_each(|flag| write!(f, " {flag:#x}"))
+    }
 }
 
 impl BitOr for MemoryAttribute {
@@ -89,12 +438,155 @@ impl Into<efi::AllocateType> for AllocType {
             AllocType::AnyPage => efi::ALLOCATE_ANY_PAGES,
             AllocType::MaxAddress(_) => efi::ALLOCATE_MAX_ADDRESS,
             AllocType::Address(_) => efi::ALLOCATE_ADDRESS,
+            AllocType::InRange(_) => efi::ALLOCATE_MAX_ADDRESS,
         }
     }
 }
 
-impl Into<u64> for MemoryAttribute {
-    fn into(self) -> u64 {
-        self.0
+impl From<MemoryAttribute> for u64 {
+    fn from(value: MemoryAttribute) -> Self {
+        value.0
+    }
+}
+
+/// Converts a raw attribute value, e.g. from a memory map descriptor.
+///
+/// Returns the bits that are not defined by the UEFI specification, e.g. from a newer specification or a corrupted
+/// descriptor.
+impl TryFrom<u64> for MemoryAttribute {
+    type Error = UnrecognizedMemoryAttributes;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value & !Self::ALL.0 {
+            0 => Ok(MemoryAttribute(value)),
+            unrecognized => Err(UnrecognizedMemoryAttributes(unrecognized)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_memory_type_conversions() {
+        assert_eq!(Ok(MemoryType::BOOT_SERVICES_DATA), MemoryType::try_from(efi::BOOT_SERVICES_DATA));
+        assert_eq!(Ok(MemoryType::UNACCEPTED_MEMORY_TYPE), MemoryType::try_from(efi::UNACCEPTED_MEMORY_TYPE));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), MemoryType::try_from(efi::UNACCEPTED_MEMORY_TYPE + 1));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), MemoryType::try_from(MemoryType::OEM_RESERVED_MIN - 1));
+
+        let oem = MemoryType::try_from(0x70000001).unwrap();
+        assert_eq!(Some(oem), MemoryType::custom(0x70000001));
+        assert_eq!(0x70000001_u32, oem.into());
+        assert!(oem.is_oem_reserved() && !oem.is_os_reserved() && !oem.is_standard());
+
+        let os = MemoryType::try_from(MemoryType::OS_RESERVED_MAX).unwrap();
+        assert!(os.is_os_reserved() && !os.is_oem_reserved() && !os.is_standard());
+
+        assert!(MemoryType::CONVENTIONAL_MEMORY.is_standard());
+        assert_eq!(None, MemoryType::custom(efi::CONVENTIONAL_MEMORY));
+    }
+
+    #[test]
+    fn test_memory_attribute_conversions() {
+        let attributes = MemoryAttribute::try_from(efi::MEMORY_WB | efi::MEMORY_XP | efi::MEMORY_RUNTIME).unwrap();
+        assert_eq!(efi::MEMORY_WB | efi::MEMORY_XP | efi::MEMORY_RUNTIME, u64::from(attributes));
+        assert!(attributes.contains(MemoryAttribute::WB | MemoryAttribute::XP));
+        assert!(!attributes.contains(MemoryAttribute::WB | MemoryAttribute::RO));
+        assert_eq!(vec!["WB", "XP", "RUNTIME"], attributes.iter_flags().collect::<Vec<_>>());
+
+        let isa = MemoryAttribute::try_from(efi::MEMORY_ISA_VALID | 0x0000_1000_0000_0000).unwrap();
+        assert_eq!(vec!["ISA_VALID"], isa.iter_flags().collect::<Vec<_>>());
+        assert!(MemoryAttribute::try_from(0).unwrap().is_empty());
+
+        let unrecognized = MemoryAttribute::try_from(efi::MEMORY_UC | 0x20 | 0x0010_0000).unwrap_err();
+        assert_eq!(0x0010_0020, unrecognized.bits());
+        assert_eq!(vec![0x20, 0x0010_0000], unrecognized.flags().collect::<Vec<_>>());
+        assert_eq!("unrecognized memory attributes: 0x20 0x100000", format!("{unrecognized}"));
+    }
+
+    #[test]
+    fn test_allocate_pages_in_range() {
+        static FREED: AtomicUsize = AtomicUsize::new(0);
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        // The firmware returns pages below the range first.
+        const ADDRESSES: [usize; 3] = [0x1000, 0x3000, 0x10_0000];
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pages().returning(|alloc_type, _, nb_pages| {
+            assert!(matches!(alloc_type, AllocType::MaxAddress(0x1F_FFFF)));
+            assert_eq!(2, nb_pages);
+            ADDRESSES.get(NEXT.fetch_add(1, Ordering::Relaxed)).copied().ok_or(efi::Status::OUT_OF_RESOURCES)
+        });
+        boot_services.expect_free_pages().returning(|address, nb_pages| {
+            assert_eq!((0, 2), (address % 0x1000, nb_pages));
+            FREED.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        });
+
+        let range = 0x10_0000..0x20_0000;
+        let allocate =
+            |range, nb_pages| allocate_pages_in_range(&boot_services, range, MemoryType::BOOT_SERVICES_DATA, nb_pages);
+        assert_eq!(Ok(0x10_0000), allocate(range.clone(), 2));
+        assert_eq!(2, FREED.load(Ordering::Relaxed));
+
+        // Every page returned is below the range until the firmware runs out of pages.
+        NEXT.store(0, Ordering::Relaxed);
+        assert_eq!(Err(efi::Status::NOT_FOUND), allocate(0x1F_E000..0x20_0000, 2));
+        assert_eq!(5, FREED.load(Ordering::Relaxed));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), allocate(0x10_0000..0x10_1000, 2));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), allocate(range, 0));
+
+        assert!(matches!(AllocType::below_4gb(), AllocType::MaxAddress(0xFFFF_FFFF)));
+        assert!(matches!(
+            AllocType::in_range(0x1000..0x2000),
+            AllocType::InRange(Range { start: 0x1000, end: 0x2000 })
+        ));
+    }
+
+    #[test]
+    fn test_page_allocation() {
+        static FREED: AtomicUsize = AtomicUsize::new(0);
+
+        let mut pages = vec![0_u8; 3 * UEFI_PAGE_SIZE];
+        let address = pages.as_mut_ptr() as usize;
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pages().returning(move |alloc_type, memory_type, nb_pages| {
+            assert!(matches!(alloc_type, AllocType::AnyPage));
+            assert_eq!(MemoryType::BOOT_SERVICES_DATA, memory_type);
+            match nb_pages {
+                0..=3 => Ok(address),
+                _ => Err(efi::Status::OUT_OF_RESOURCES),
+            }
+        });
+        boot_services.expect_free_pages().returning(move |free_address, nb_pages| {
+            assert_eq!(address, free_address);
+            FREED.fetch_add(nb_pages, Ordering::Relaxed);
+            Ok(())
+        });
+
+        let allocate = |nb_pages| {
+            PageAllocation::new(&boot_services, AllocType::AnyPage, MemoryType::BOOT_SERVICES_DATA, nb_pages)
+        };
+        assert_eq!(Some(efi::Status::OUT_OF_RESOURCES), allocate(4).err());
+
+        let mut allocation = allocate(2).unwrap();
+        assert_eq!((address, 2, 2 * UEFI_PAGE_SIZE), (allocation.address(), allocation.nb_pages(), allocation.len()));
+        allocation.as_mut_slice()[UEFI_PAGE_SIZE] = 0xa5;
+        assert_eq!(0xa5, allocation.as_slice()[UEFI_PAGE_SIZE]);
+        drop(allocation);
+        assert_eq!(2, FREED.load(Ordering::Relaxed));
+
+        let leaked = allocate(3).unwrap().leak();
+        assert_eq!(3 * UEFI_PAGE_SIZE, leaked.len());
+        assert_eq!((address, 1), allocate(1).unwrap().into_raw());
+        assert_eq!(2, FREED.load(Ordering::Relaxed));
+
+        drop(unsafe { PageAllocation::from_raw(address, 1, &boot_services) });
+        assert_eq!(3, FREED.load(Ordering::Relaxed));
+        assert_eq!(0xa5, pages[UEFI_PAGE_SIZE]);
     }
 }
@@ -0,0 +1,150 @@
+//! Bump allocator for short-lived scratch memory.
+//!
+//! An [`Arena`] hands out memory from a single pool allocation and frees it all at once when dropped, which suits
+//! parsing work (e.g. walking firmware file systems or ACPI tables) that allocates many small temporary objects.
+//!
+//! ```ignore
+//! let arena = Arena::new(&BOOT_SERVICES, MemoryType::BOOT_SERVICES_DATA, 0x1000)?;
+//! let header = arena.alloc(parse_header(data)?)?;
+//! let sections = arena.alloc_slice(&section_offsets)?;
+//! ```
+
+use core::{cell::Cell, mem, ptr, slice};
+
+use r_efi::efi;
+
+use crate::{allocation::MemoryType, BootServices};
+
+/// Bump allocator backed by a single pool allocation.
+///
+/// The values allocated in the arena are never dropped, only their memory is freed when the arena is.
+#[derive(Debug)]
+pub struct Arena<'a, B: BootServices> {
+    boot_services: &'a B,
+    buffer: *mut u8,
+    capacity: usize,
+    used: Cell<usize>,
+}
+
+impl<'a, B: BootServices> Arena<'a, B> {
+    /// Create an arena of *capacity* bytes allocated from pool.
+    pub fn new(boot_services: &'a B, memory_type: MemoryType, capacity: usize) -> Result<Self, efi::Status> {
+        let buffer = boot_services.allocate_pool(memory_type, capacity)?;
+        Ok(Self { boot_services, buffer, capacity, used: Cell::new(0) })
+    }
+
+    /// Moves *value* in the arena.
+    ///
+    /// Returns `efi::Status::OUT_OF_RESOURCES` if the arena has no room left for it.
+    // The allocations never overlap, so each reference is unique.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T>(&self, value: T) -> Result<&mut T, efi::Status> {
+        let ptr = self.bump(mem::size_of::<T>(), mem::align_of::<T>())? as *mut T;
+        //SAFETY: The memory is in the arena, aligned for T and not used by another allocation.
+        unsafe {
+            ptr.write(value);
+            Ok(&mut *ptr)
+        }
+    }
+
+    /// Copies *values* in the arena.
+    ///
+    /// Returns `efi::Status::OUT_OF_RESOURCES` if the arena has no room left for them.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Copy>(&self, values: &[T]) -> Result<&mut [T], efi::Status> {
+        let size = mem::size_of_val(values);
+        let ptr = self.bump(size, mem::align_of::<T>())? as *mut T;
+        //SAFETY: The memory is in the arena, aligned for T and not used by another allocation.
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), ptr, values.len());
+            Ok(slice::from_raw_parts_mut(ptr, values.len()))
+        }
+    }
+
+    /// Returns the number of bytes of the arena, used or not.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of bytes used by the allocations, including the alignment padding.
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    /// Makes the whole arena available again. This needs the arena borrowed mutably, so no allocation is still in use.
+    pub fn reset(&mut self) {
+        self.used.set(0);
+    }
+
+    fn bump(&self, size: usize, align: usize) -> Result<*mut u8, efi::Status> {
+        let start = self.buffer as usize + self.used.get();
+        let offset =
+            (start.checked_add(align - 1).ok_or(efi::Status::OUT_OF_RESOURCES)? & !(align - 1)) - self.buffer as usize;
+        let end = offset.checked_add(size).ok_or(efi::Status::OUT_OF_RESOURCES)?;
+        if end > self.capacity {
+            return Err(efi::Status::OUT_OF_RESOURCES);
+        }
+        self.used.set(end);
+        //SAFETY: offset is within the buffer.
+        Ok(unsafe { self.buffer.add(offset) })
+    }
+}
+
+impl<B: BootServices> Drop for Arena<'_, B> {
+    fn drop(&mut self) {
+        let _ = self.boot_services.free_pool(self.buffer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+
+    fn boot_services(capacity: usize) -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pool().returning(move |memory_type, size| {
+            assert_eq!((MemoryType::BOOT_SERVICES_DATA, capacity), (memory_type, size));
+            // u64 to get the 8 bytes alignment of the pool allocations.
+            Ok(Box::into_raw(vec![0_u64; capacity.div_ceil(8)].into_boxed_slice()) as *mut u8)
+        });
+        boot_services.expect_free_pool().times(1).returning(move |buffer| {
+            let buffer = ptr::slice_from_raw_parts_mut(buffer as *mut u64, capacity.div_ceil(8));
+            drop(unsafe { Box::from_raw(buffer) });
+            Ok(())
+        });
+        boot_services
+    }
+
+    #[test]
+    fn test_alloc() {
+        let boot_services = boot_services(32);
+        let arena = Arena::new(&boot_services, MemoryType::BOOT_SERVICES_DATA, 32).unwrap();
+
+        let a = arena.alloc(1_u8).unwrap();
+        let b = arena.alloc(2_u32).unwrap();
+        let c = arena.alloc_slice(&[3_u16, 4, 5]).unwrap();
+        assert_eq!(0, (b as *mut u32 as usize) % mem::align_of::<u32>());
+        assert_eq!(14, arena.used());
+
+        *a += 1;
+        c[0] = 6;
+        assert_eq!((2, 2, [6, 4, 5].as_slice()), (*a, *b, &*c));
+    }
+
+    #[test]
+    fn test_alloc_out_of_resources() {
+        let boot_services = boot_services(16);
+        let mut arena = Arena::new(&boot_services, MemoryType::BOOT_SERVICES_DATA, 16).unwrap();
+
+        arena.alloc(1_u8).unwrap();
+        assert_eq!(Err(efi::Status::OUT_OF_RESOURCES), arena.alloc([0_u64; 2]).map(|_| ()));
+        assert_eq!(Err(efi::Status::OUT_OF_RESOURCES), arena.alloc_slice(&[0_u8; 16]).map(|_| ()));
+        arena.alloc(2_u64).unwrap();
+        assert_eq!(16, arena.used());
+
+        arena.reset();
+        assert_eq!(0, arena.used());
+        assert_eq!([0_u8; 16], *arena.alloc_slice(&[0_u8; 16]).unwrap());
+    }
+}
@@ -0,0 +1,535 @@
+//! Boot manager helpers to boot the load options of the platform, e.g. the `Boot####` variables in `BootOrder` order.
+//!
+//! [`boot`] tries the options in order like the UEFI boot manager: it connects the device path of the option, loads
+//! and starts its image with the boot watchdog armed, and falls through to the next option if the image can not be
+//! loaded or returns an error.
+//!
+//! ```ignore
+//! let options = boot_order.iter().map(|n| LoadOption::parse(&read_boot_variable(n)?)).collect::<Result<Vec<_>, _>>()?;
+//! match boot_manager::boot(&BOOT_SERVICES, image_handle, &options) {
+//!     Ok(option) => log::info!("Boot option {option} returned."),
+//!     Err(failures) => failures.iter().for_each(|f| log::error!("{f:?}")),
+//! }
+//! ```
+//!
+//! [UEFI Spec Documentation: 3.1. Firmware Boot Manager](https://uefi.org/specs/UEFI/2.10/03_Boot_Manager.html#firmware-boot-manager)
+
+use alloc::{string::String, vec::Vec};
+use core::char;
+
+use r_efi::efi::{self, protocols::device_path};
+
+use crate::{device_path::device_path_size, protocol_handler::DevicePath, BootServices};
+
+/// The option is active, only active options are booted.
+pub const LOAD_OPTION_ACTIVE: u32 = 0x00000001;
+pub const LOAD_OPTION_FORCE_RECONNECT: u32 = 0x00000002;
+pub const LOAD_OPTION_HIDDEN: u32 = 0x00000008;
+pub const LOAD_OPTION_CATEGORY: u32 = 0x00001F00;
+pub const LOAD_OPTION_CATEGORY_BOOT: u32 = 0x00000000;
+pub const LOAD_OPTION_CATEGORY_APP: u32 = 0x00000100;
+
+// Attributes reserved by the specification, which must be zero.
+const LOAD_OPTION_RESERVED: u32 =
+    !(LOAD_OPTION_ACTIVE | LOAD_OPTION_FORCE_RECONNECT | LOAD_OPTION_HIDDEN | LOAD_OPTION_CATEGORY);
+
+/// Timeout in seconds of the watchdog armed while a boot option runs, as required by the specification.
+pub const BOOT_WATCHDOG_TIMEOUT: usize = 5 * 60;
+
+/// A parsed EFI_LOAD_OPTION, the content of a `Boot####`, `Driver####` or `SysPrep####` variable.
+///
+/// [UEFI Spec Documentation: 3.1.3. Load Options](https://uefi.org/specs/UEFI/2.10/03_Boot_Manager.html#load-options)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOption {
+    pub attributes: u32,
+    pub description: String,
+    /// The device path instances of the option, the first one is the path of the image to load.
+    pub file_path_list: Vec<u8>,
+    pub optional_data: Vec<u8>,
+}
+
+impl LoadOption {
+    /// Parses a serialized EFI_LOAD_OPTION.
+    ///
+    /// Returns [`efi::Status::INVALID_PARAMETER`] if the option is truncated, its description is not terminated or
+    /// its file path list is not a well formed device path.
+    pub fn parse(data: &[u8]) -> Result<Self, efi::Status> {
+        let attributes = u32::from_le_bytes(data.get(0..4).ok_or(efi::Status::INVALID_PARAMETER)?.try_into().unwrap());
+        let file_path_list_length =
+            u16::from_le_bytes(data.get(4..6).ok_or(efi::Status::INVALID_PARAMETER)?.try_into().unwrap()) as usize;
+
+        let description_length =
+            data[6..].chunks_exact(2).position(|c| c == [0, 0]).ok_or(efi::Status::INVALID_PARAMETER)?;
+        let description = decode_ucs2(&data[6..6 + description_length * 2]);
+
+        let file_path_list_start = 6 + (description_length + 1) * 2;
+        let file_path_list = data
+            .get(file_path_list_start..file_path_list_start + file_path_list_length)
+            .ok_or(efi::Status::INVALID_PARAMETER)?;
+        if device_path_size(file_path_list).is_none() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        Ok(Self {
+            attributes,
+            description,
+            file_path_list: file_path_list.to_vec(),
+            optional_data: data[file_path_list_start + file_path_list_length..].to_vec(),
+        })
+    }
+
+    /// Checks that the option can be serialized into an EFI_LOAD_OPTION that parses back to the same option.
+    ///
+    /// Returns [`efi::Status::INVALID_PARAMETER`] if:
+    /// - the description contains a null character, which would terminate it early,
+    /// - the file path list is not a sequence of well formed device paths or is longer than `u16::MAX` bytes,
+    /// - reserved attributes are set or the category is neither [`LOAD_OPTION_CATEGORY_BOOT`] nor
+    ///   [`LOAD_OPTION_CATEGORY_APP`].
+    pub fn validate(&self) -> Result<(), efi::Status> {
+        if self.description.contains('\0')
+            || u16::try_from(self.file_path_list.len()).is_err()
+            || !is_valid_file_path_list(&self.file_path_list)
+            || self.attributes & LOAD_OPTION_RESERVED != 0
+            || !matches!(self.category(), LOAD_OPTION_CATEGORY_BOOT | LOAD_OPTION_CATEGORY_APP)
+        {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        Ok(())
+    }
+
+    /// Serializes the option into an EFI_LOAD_OPTION, after checking it with [`LoadOption::validate`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, efi::Status> {
+        self.validate()?;
+        let mut data = Vec::with_capacity(
+            6 + (self.description.len() + 1) * 2 + self.file_path_list.len() + self.optional_data.len(),
+        );
+        data.extend_from_slice(&self.attributes.to_le_bytes());
+        data.extend_from_slice(&(self.file_path_list.len() as u16).to_le_bytes());
+        self.description.encode_utf16().chain([0]).for_each(|c| data.extend_from_slice(&c.to_le_bytes()));
+        data.extend_from_slice(&self.file_path_list);
+        data.extend_from_slice(&self.optional_data);
+        Ok(data)
+    }
+
+    /// Returns true if the option is active.
+    pub fn is_active(&self) -> bool {
+        self.attributes & LOAD_OPTION_ACTIVE != 0
+    }
+
+    /// Returns the category of the option, [`LOAD_OPTION_CATEGORY_BOOT`] or [`LOAD_OPTION_CATEGORY_APP`].
+    pub fn category(&self) -> u32 {
+        self.attributes & LOAD_OPTION_CATEGORY
+    }
+
+    /// Returns the device path of the image to load.
+    ///
+    /// The device path is only read by the boot services, the pointer must not be used to modify it.
+    pub fn device_path(&self) -> *mut device_path::Protocol {
+        self.file_path_list.as_ptr() as *mut device_path::Protocol
+    }
+}
+
+// Returns true if *data* is a non empty sequence of device paths.
+fn is_valid_file_path_list(data: &[u8]) -> bool {
+    let mut offset = 0;
+    while offset < data.len() {
+        match device_path_size(&data[offset..]) {
+            Some(size) => offset += size,
+            None => return false,
+        }
+    }
+    offset != 0
+}
+
+/// Builder of a serialized EFI_LOAD_OPTION, e.g. the content of a new `Boot####` variable.
+///
+/// ```ignore
+/// let data = LoadOptionBuilder::new("Network", &device_path).optional_data(b"PXE").build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoadOptionBuilder {
+    option: LoadOption,
+}
+
+impl LoadOptionBuilder {
+    /// Create a builder of an active boot option with *description*, booting the first device path of
+    /// *file_path_list*.
+    pub fn new(description: &str, file_path_list: &[u8]) -> Self {
+        Self {
+            option: LoadOption {
+                attributes: LOAD_OPTION_ACTIVE,
+                description: String::from(description),
+                file_path_list: file_path_list.to_vec(),
+                optional_data: Vec::new(),
+            },
+        }
+    }
+
+    /// Sets the attributes of the option, replacing the default [`LOAD_OPTION_ACTIVE`].
+    pub fn attributes(mut self, attributes: u32) -> Self {
+        self.option.attributes = attributes;
+        self
+    }
+
+    /// Sets the data passed to the image in the load options of its loaded image protocol.
+    pub fn optional_data(mut self, optional_data: &[u8]) -> Self {
+        self.option.optional_data = optional_data.to_vec();
+        self
+    }
+
+    /// Serializes the option, see [`LoadOption::to_bytes`].
+    pub fn build(self) -> Result<Vec<u8>, efi::Status> {
+        self.option.to_bytes()
+    }
+}
+
+fn decode_ucs2(data: &[u8]) -> String {
+    char::decode_utf16(data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])))
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Step of a boot attempt that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootStep {
+    /// The image of the option could not be loaded.
+    Load,
+    /// The image was started and returned an error.
+    Start,
+}
+
+/// A failed attempt to boot a load option.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootFailure {
+    /// Index of the option in the options given to [`boot`].
+    pub option: usize,
+    pub step: BootStep,
+    pub status: efi::Status,
+    /// The description at the start of the exit data of the image, if any.
+    pub exit_data: Option<String>,
+}
+
+/// Connects the controllers along *device_path*, so the device of the image to load is available.
+///
+/// Like the boot manager, the controller of the longest device path prefix is connected until the full device path is
+/// found or no further controller can be connected. Connection failures are ignored, loading the image reports
+/// whether the device is reachable.
+pub fn connect_device_path<B: BootServices>(boot_services: &B, device_path: *mut device_path::Protocol) {
+    let mut previous_handle = None;
+    loop {
+        let mut remaining_device_path = device_path;
+        // SAFETY: The device path is a well formed device path.
+        let Ok(handle) = (unsafe { boot_services.locate_device_path(&DevicePath, &mut remaining_device_path) }) else {
+            return;
+        };
+        // SAFETY: The remaining device path points to a node of the device path.
+        let node = unsafe { &*remaining_device_path };
+        if (node.r#type == device_path::TYPE_END && node.sub_type == device_path::End::SUBTYPE_ENTIRE)
+            || previous_handle == Some(handle)
+        {
+            return;
+        }
+        // SAFETY: The remaining device path is the part of the device path not yet produced by the controller.
+        let _ = unsafe { boot_services.connect_controller(handle, Vec::new(), remaining_device_path, false) };
+        previous_handle = Some(handle);
+    }
+}
+
+/// Boots *option* on behalf of the boot manager image *parent_image_handle*.
+///
+/// The device path of the option is connected, its image loaded and started with the watchdog set to
+/// [`BOOT_WATCHDOG_TIMEOUT`]. The watchdog is disabled when the image returns. The option index of the returned
+/// failure is 0.
+pub fn boot_option<B: BootServices>(
+    boot_services: &B,
+    parent_image_handle: efi::Handle,
+    option: &LoadOption,
+) -> Result<(), BootFailure> {
+    let failure = |step, status, exit_data| BootFailure { option: 0, step, status, exit_data };
+
+    connect_device_path(boot_services, option.device_path());
+    let image_handle = boot_services
+        .load_image(true, parent_image_handle, option.device_path(), None)
+        .map_err(|status| failure(BootStep::Load, status, None))?;
+
+    // A failure to arm the watchdog does not prevent booting.
+    let _ = boot_services.set_watchdog_timer(BOOT_WATCHDOG_TIMEOUT);
+    let result = boot_services.start_image(image_handle).map_err(|(status, exit_data)| {
+        let exit_data = exit_data.map(|data| {
+            let length = data.chunks_exact(2).position(|c| c == [0, 0]).unwrap_or(data.len() / 2);
+            decode_ucs2(&data[..length * 2])
+        });
+        failure(BootStep::Start, status, exit_data)
+    });
+    let _ = boot_services.set_watchdog_timer(0);
+    result
+}
+
+/// Boots the active boot category *options* in order, until one returns successfully.
+///
+/// Returns the index of the option that returned [`efi::Status::SUCCESS`], or the failed attempts if no option
+/// could be booted. Options that are not active or that are applications are skipped.
+pub fn boot<B: BootServices>(
+    boot_services: &B,
+    parent_image_handle: efi::Handle,
+    options: &[LoadOption],
+) -> Result<usize, Vec<BootFailure>> {
+    let mut failures = Vec::new();
+    for (index, option) in options.iter().enumerate() {
+        if !option.is_active() || option.category() != LOAD_OPTION_CATEGORY_BOOT {
+            continue;
+        }
+        match boot_option(boot_services, parent_image_handle, option) {
+            Ok(()) => return Ok(index),
+            Err(failure) => failures.push(BootFailure { option: index, ..failure }),
+        }
+    }
+    Err(failures)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{device_path::DEVICE_PATH_NODE_HEADER_SIZE, MockBootServices};
+    use alloc::vec;
+
+    const END_NODE: [u8; 4] = [device_path::TYPE_END, device_path::End::SUBTYPE_ENTIRE, 4, 0];
+
+    // A load option with a hardware node before the end node.
+    fn load_option_bytes(attributes: u32, description: &str, optional_data: &[u8]) -> Vec<u8> {
+        let mut data = attributes.to_le_bytes().to_vec();
+        data.extend_from_slice(&12_u16.to_le_bytes());
+        description.encode_utf16().chain([0]).for_each(|c| data.extend_from_slice(&c.to_le_bytes()));
+        data.extend_from_slice(&[device_path::TYPE_HARDWARE, 0x01, 8, 0, 1, 2, 3, 4]);
+        data.extend_from_slice(&END_NODE);
+        data.extend_from_slice(optional_data);
+        data
+    }
+
+    fn load_option(attributes: u32) -> LoadOption {
+        LoadOption::parse(&load_option_bytes(attributes, "Disk", &[])).unwrap()
+    }
+
+    // A xorshift generator, so the randomized tests are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn bytes(&mut self, length: usize) -> Vec<u8> {
+            (0..length).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    // A valid load option with up to 3 device paths of up to 3 nodes each.
+    fn random_load_option(rng: &mut Rng) -> LoadOption {
+        let description = (0..rng.below(40))
+            .map(|_| match rng.below(8) {
+                0 => 'é',
+                1 => '🚀',
+                _ => char::from(b'a' + rng.below(26) as u8),
+            })
+            .collect();
+        let mut file_path_list = Vec::new();
+        for _ in 0..1 + rng.below(3) {
+            for _ in 0..rng.below(4) {
+                let length = DEVICE_PATH_NODE_HEADER_SIZE + rng.below(20);
+                file_path_list.extend_from_slice(&[device_path::TYPE_HARDWARE + rng.below(4) as u8, rng.next() as u8]);
+                file_path_list.extend_from_slice(&(length as u16).to_le_bytes());
+                file_path_list.extend(rng.bytes(length - DEVICE_PATH_NODE_HEADER_SIZE));
+            }
+            file_path_list.extend_from_slice(&END_NODE);
+        }
+        let category = [LOAD_OPTION_CATEGORY_BOOT, LOAD_OPTION_CATEGORY_APP][rng.below(2)];
+        let flags = rng.next() as u32 & (LOAD_OPTION_ACTIVE | LOAD_OPTION_FORCE_RECONNECT | LOAD_OPTION_HIDDEN);
+        let optional_data_length = rng.below(32);
+        LoadOption {
+            attributes: category | flags,
+            description,
+            file_path_list,
+            optional_data: rng.bytes(optional_data_length),
+        }
+    }
+
+    #[test]
+    fn test_parse_load_option() {
+        let option =
+            LoadOption::parse(&load_option_bytes(LOAD_OPTION_ACTIVE, "Windows Boot Manager", b"WINDOWS")).unwrap();
+        assert!(option.is_active());
+        assert_eq!(LOAD_OPTION_CATEGORY_BOOT, option.category());
+        assert_eq!("Windows Boot Manager", option.description);
+        assert_eq!(12, option.file_path_list.len());
+        assert_eq!(b"WINDOWS", option.optional_data.as_slice());
+
+        let data = load_option_bytes(LOAD_OPTION_CATEGORY_APP, "", &[]);
+        let option = LoadOption::parse(&data).unwrap();
+        assert!(!option.is_active());
+        assert_eq!(LOAD_OPTION_CATEGORY_APP, option.category());
+
+        // Truncated file path list, no end node and unterminated description.
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), LoadOption::parse(&data[..data.len() - 1]));
+        let mut no_end = data.clone();
+        no_end[data.len() - 4] = device_path::TYPE_MEDIA;
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), LoadOption::parse(&no_end));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), LoadOption::parse(&[1, 0, 0, 0, 4, 0, b'a', 0]));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), LoadOption::parse(&[1, 0, 0]));
+    }
+
+    #[test]
+    fn test_load_option_builder() {
+        let file_path_list = &load_option_bytes(0, "", &[])[8..20];
+        assert_eq!(
+            Ok(load_option_bytes(LOAD_OPTION_ACTIVE, "Windows Boot Manager", b"WINDOWS")),
+            LoadOptionBuilder::new("Windows Boot Manager", file_path_list).optional_data(b"WINDOWS").build()
+        );
+        assert_eq!(
+            Ok(load_option_bytes(LOAD_OPTION_CATEGORY_APP | LOAD_OPTION_HIDDEN, "Shell", &[])),
+            LoadOptionBuilder::new("Shell", file_path_list)
+                .attributes(LOAD_OPTION_CATEGORY_APP | LOAD_OPTION_HIDDEN)
+                .build()
+        );
+
+        // Several device paths are allowed, the first one is booted.
+        let two_paths = [file_path_list, &END_NODE].concat();
+        let option = LoadOption::parse(&LoadOptionBuilder::new("Disk", &two_paths).build().unwrap()).unwrap();
+        assert_eq!(two_paths, option.file_path_list);
+
+        let invalid = [
+            LoadOptionBuilder::new("Dis\0k", file_path_list),
+            LoadOptionBuilder::new("Disk", &[]),
+            LoadOptionBuilder::new("Disk", &file_path_list[..8]),
+            LoadOptionBuilder::new("Disk", &[file_path_list, &[0]].concat()),
+            LoadOptionBuilder::new("Disk", &[file_path_list, &file_path_list[..8]].concat()),
+            LoadOptionBuilder::new("Disk", &END_NODE.repeat(0x4000)),
+            LoadOptionBuilder::new("Disk", file_path_list).attributes(LOAD_OPTION_ACTIVE | 0x4),
+            LoadOptionBuilder::new("Disk", file_path_list).attributes(LOAD_OPTION_ACTIVE | 0x80000000),
+            LoadOptionBuilder::new("Disk", file_path_list).attributes(0x00000200),
+        ];
+        for builder in invalid {
+            assert_eq!(Err(efi::Status::INVALID_PARAMETER), builder.build());
+        }
+    }
+
+    #[test]
+    fn test_load_option_round_trip() {
+        let mut rng = Rng(0x4c4f_4144_4f50_5431);
+        for _ in 0..1000 {
+            let option = random_load_option(&mut rng);
+            assert_eq!(Ok(&option), LoadOption::parse(&option.to_bytes().unwrap()).as_ref());
+        }
+    }
+
+    #[test]
+    fn test_parse_malformed_load_options() {
+        let mut rng = Rng(0x4d41_4c46_4f52_4d44);
+        for _ in 0..5000 {
+            let mut data = random_load_option(&mut rng).to_bytes().unwrap();
+            for _ in 0..1 + rng.below(4) {
+                match rng.below(3) {
+                    0 => data.truncate(rng.below(data.len() + 1)),
+                    1 if !data.is_empty() => {
+                        let index = rng.below(data.len());
+                        data[index] = rng.next() as u8;
+                    }
+                    _ => {
+                        let index = rng.below(data.len() + 1);
+                        data.truncate(index);
+                        data.extend(rng.bytes(8));
+                    }
+                }
+            }
+            // Parsing never panics, and a parsed option that passes validation round trips.
+            if let Ok(option) = LoadOption::parse(&data) {
+                if option.validate().is_ok() {
+                    assert_eq!(Ok(&option), LoadOption::parse(&option.to_bytes().unwrap()).as_ref());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_connect_device_path() {
+        let option = load_option(LOAD_OPTION_ACTIVE);
+        let device_path = option.device_path() as usize;
+
+        let mut boot_services = MockBootServices::new();
+        // The first call finds the controller of the hardware node, the second the full device path.
+        let mut calls = 0;
+        boot_services.expect_locate_device_path().times(2).returning(move |_, remaining_device_path| {
+            calls += 1;
+            let remaining = if calls == 1 { device_path } else { device_path + 8 };
+            unsafe { remaining_device_path.write(remaining as *mut _) };
+            Ok(calls as efi::Handle)
+        });
+        boot_services.expect_connect_controller().times(1).returning(
+            move |handle, driver_image_handles, remaining_device_path, recursive| {
+                assert_eq!(1, handle as usize);
+                assert!(driver_image_handles.is_empty());
+                assert_eq!(device_path, remaining_device_path as usize);
+                assert!(!recursive);
+                Ok(())
+            },
+        );
+        boot_services.expect_load_image().never();
+
+        connect_device_path(&boot_services, option.device_path());
+    }
+
+    #[test]
+    fn test_boot() {
+        let options = vec![
+            load_option(0),
+            load_option(LOAD_OPTION_ACTIVE | LOAD_OPTION_CATEGORY_APP),
+            load_option(LOAD_OPTION_ACTIVE),
+            load_option(LOAD_OPTION_ACTIVE),
+            load_option(LOAD_OPTION_ACTIVE),
+        ];
+        let device_paths = options.iter().map(|o| o.device_path() as usize).collect::<Vec<_>>();
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_device_path().returning(|_, _| Err(efi::Status::NOT_FOUND));
+        boot_services.expect_load_image().times(3).returning(move |boot_policy, parent, device_path, source| {
+            assert!(boot_policy);
+            assert_eq!(1, parent as usize);
+            assert!(source.is_none());
+            match device_paths.iter().position(|&p| p == device_path as usize) {
+                Some(2) => Err(efi::Status::NOT_FOUND),
+                Some(index) => Ok(index as efi::Handle),
+                None => panic!("Unexpected device path."),
+            }
+        });
+        let mut watchdog = vec![BOOT_WATCHDOG_TIMEOUT, 0, BOOT_WATCHDOG_TIMEOUT, 0];
+        boot_services.expect_set_watchdog_timer().times(4).returning(move |timeout| {
+            assert_eq!(watchdog.remove(0), timeout);
+            Ok(())
+        });
+        boot_services.expect_start_image().times(2).returning(|image_handle| match image_handle as usize {
+            3 => Err((efi::Status::LOAD_ERROR, None)),
+            _ => Ok(()),
+        });
+
+        assert_eq!(Ok(4), boot(&boot_services, 1_usize as efi::Handle, &options));
+
+        boot_services.checkpoint();
+        boot_services.expect_locate_device_path().returning(|_, _| Err(efi::Status::NOT_FOUND));
+        boot_services.expect_load_image().returning(|_, _, _, _| Err(efi::Status::SECURITY_VIOLATION));
+        assert_eq!(
+            Err(vec![BootFailure {
+                option: 2,
+                step: BootStep::Load,
+                status: efi::Status::SECURITY_VIOLATION,
+                exit_data: None
+            }]),
+            boot(&boot_services, 1_usize as efi::Handle, &options[..3])
+        );
+    }
+}
@@ -3,14 +3,56 @@
 #[cfg(feature = "global_allocator")]
 pub mod global_allocator;
 
+#[cfg(any(test, feature = "host"))]
+pub mod host;
+
+#[cfg(any(test, feature = "protocol_tracing"))]
+pub mod protocol_tracing;
+
 extern crate alloc;
 
 pub mod allocation;
+pub mod arena;
+pub mod boot_manager;
 pub mod boxed;
 pub mod c_ptr;
+pub mod crc32;
+pub mod deferred_work;
+pub mod device_path;
+pub mod driver_health;
+pub mod driver_supported_efi_version;
+pub mod esrt;
 pub mod event;
+pub mod event_registry;
+pub mod file;
+pub mod firmware_management;
+pub mod handle;
+pub mod image_context;
+pub mod interface_registry;
+pub mod loaded_image;
+pub mod memory_protection;
+pub mod once_guard;
+pub mod partition_info;
+pub mod pci_io;
+pub mod performance_measurement;
 pub mod protocol_handler;
+pub mod protocol_installer;
+pub mod reset_notification;
+pub mod scoped_protocol;
+pub mod security2;
+pub mod serial_io;
+pub mod service_binding;
+pub mod shell_dynamic_command;
+pub mod shell_parameters;
+pub mod table_header;
+pub mod text_output;
+pub mod text_ui;
 pub mod tpl;
+pub mod unicode_collation;
+pub mod unique_id;
+pub mod versioned_table;
+
+pub use image_context::{image_handle, init_image_context, system_table, system_table_ptr};
 
 #[cfg(any(test, feature = "mockall"))]
 use mockall::automock;
@@ -24,15 +66,18 @@ use core::{
     mem::{self, MaybeUninit},
     option::Option,
     ptr::{self, NonNull},
+    slice,
     sync::atomic::{AtomicPtr, Ordering},
+    time::Duration,
 };
 
 use r_efi::efi;
 
-use allocation::{AllocType, MemoryMap, MemoryType};
+use allocation::{AllocType, MemoryMap, MemoryMapView, MemoryType};
 use boxed::BootServicesBox;
-use event::{EventNotifyCallback, EventTimerType, EventType};
-use protocol_handler::{HandleSearchType, Protocol, Registration};
+use event::{AnyEventNotifyCallback, BootStage, ContextEvent, EventNotifyCallback, EventTimerType, EventType};
+use protocol_handler::{HandleSearchType, Protocol, ProtocolHandle, Registration};
+use scoped_protocol::ScopedProtocolInstallation;
 use tpl::{Tpl, TplGuard};
 
 /// This is the boot services used in the UEFI.
@@ -53,6 +98,33 @@ impl<'a> StandardBootServices<'a> {
         }
     }
 
+    /// Create a new StandardBootServices from a raw [efi::BootServices] pointer.
+    ///
+    /// # Safety
+    ///
+    /// *efi_boot_services* must either be null, in which case the struct is uninitialized, or point to a valid
+    /// [efi::BootServices] that outlives `'a`.
+    pub const unsafe fn from_raw_ptr(efi_boot_services: *mut efi::BootServices) -> Self {
+        Self { efi_boot_services: AtomicPtr::new(efi_boot_services), _lifetime_marker: PhantomData }
+    }
+
+    /// Create a new StandardBootServices from the boot services table of a raw [efi::SystemTable] pointer.
+    ///
+    /// # Safety
+    ///
+    /// *system_table* must point to a valid [efi::SystemTable] whose boot services table outlives `'a`.
+    pub unsafe fn from_system_table(system_table: *mut efi::SystemTable) -> Self {
+        Self::from_raw_ptr((*system_table).boot_services)
+    }
+
+    /// Returns the raw [efi::BootServices] pointer, null if uninitialized.
+    ///
+    /// This is meant for interoperability with other crates that need the raw table. The raw system table pointer of
+    /// the image is returned by [`system_table_ptr`].
+    pub fn as_raw_ptr(&self) -> *mut efi::BootServices {
+        self.efi_boot_services.load(Ordering::SeqCst)
+    }
+
     /// Create a new StandardBootServices that is uninitialized.
     /// The struct need to be initialize later with [Self::initialize], otherwise, subsequent call will panic.
     pub const fn new_uninit() -> Self {
@@ -71,6 +143,57 @@ impl<'a> StandardBootServices<'a> {
         }
     }
 
+    /// Initialize the StandardBootServices with a raw [efi::BootServices] pointer, after validating the signature,
+    /// revision and CRC32 of its header, see [`table_header::validate_table_header`].
+    ///
+    /// # Safety
+    ///
+    /// *efi_boot_services* must either be null or readable for the size of a table header, and once validated point
+    /// to a valid [efi::BootServices] that outlives `'a`.
+    ///
+    /// # Panics
+    /// This function will panic if already initialize.
+    pub unsafe fn initialize_checked(&'a self, efi_boot_services: *const efi::BootServices) -> Result<(), efi::Status> {
+        table_header::validate_table_header(
+            efi_boot_services as *const efi::TableHeader,
+            efi::BOOT_SERVICES_SIGNATURE,
+            mem::size_of::<efi::BootServices>(),
+        )?;
+        self.initialize(&*efi_boot_services);
+        Ok(())
+    }
+
+    /// Raises a task's priority level and returns a [`TplGuard`] that will restore the tpl when dropped.
+    ///
+    /// Same as [`BootServices::raise_tpl_guarded`], without having to import the trait.
+    pub fn raise_tpl_guarded(&self, tpl: Tpl) -> TplGuard<'_, Self> {
+        BootServices::raise_tpl_guarded(self, tpl)
+    }
+
+    /// Returns the current [`Tpl`], see [`tpl::current_tpl`] for its cost.
+    pub fn current_tpl(&self) -> Tpl {
+        tpl::current_tpl(self)
+    }
+
+    /// Panics if the current [`Tpl`] is above *tpl*, in debug builds only.
+    ///
+    /// See [`tpl::debug_assert_tpl_at_most`].
+    #[track_caller]
+    pub fn debug_assert_tpl_at_most(&self, tpl: Tpl) {
+        tpl::debug_assert_tpl_at_most(self, tpl)
+    }
+
+    /// Fails the calls made after boot services were exited, see [`boot_services_exited`].
+    fn ensure_not_exited(&self) -> Result<(), efi::Status> {
+        match boot_services_exited() {
+            true => {
+                debug_assert!(false, "Boot services used after ExitBootServices.");
+                Err(efi::Status::UNSUPPORTED)
+            }
+            false => Ok(()),
+        }
+    }
+
     /// # Panics
     /// This function will panic if it was not initialize.
     fn efi_boot_services(&self) -> &efi::BootServices {
@@ -86,7 +209,52 @@ unsafe impl Sync for StandardBootServices<'static> {}
 ///SAFETY: When the lifetime is `'static`, the pointer is guaranteed to stay valid.
 unsafe impl Send for StandardBootServices<'static> {}
 
+/// Returns true once [`BootServices::exit_boot_services`] succeeded through a [`StandardBootServices`].
+///
+/// From then on, the allocation and event functions of every [`StandardBootServices`] no longer call into the boot
+/// services table, which is no longer valid. They panic in debug builds, so the code still using boot services after
+/// ExitBootServices, e.g. from a notify function or a global allocator, is caught at the offending call instead of
+/// crashing the firmware far from it. Release builds return `efi::Status::UNSUPPORTED` instead, which callers can not
+/// tell apart from a service the firmware does not support, so check this function first where it matters.
+pub fn boot_services_exited() -> bool {
+    exit_boot_services_state::get()
+}
+
+// Tests run in parallel threads of the same process, so each test gets its own state.
+mod exit_boot_services_state {
+    #[cfg(not(test))]
+    static EXITED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+    #[cfg(test)]
+    std::thread_local! {
+        static EXITED: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+    }
+
+    #[cfg(not(test))]
+    pub fn get() -> bool {
+        EXITED.load(core::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[cfg(not(test))]
+    pub fn set() {
+        EXITED.store(true, core::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub fn get() -> bool {
+        EXITED.get()
+    }
+
+    #[cfg(test)]
+    pub fn set() {
+        EXITED.set(true)
+    }
+}
+
 /// Functions that are available *before* a successful call to EFI_BOOT_SERVICES.ExitBootServices().
+///
+/// The helpers taking closures or returning borrows of their arguments are in [`BootServicesExt`], implemented for
+/// every boot services.
 #[cfg_attr(any(test, feature = "mockall"), automock)]
 pub trait BootServices {
     /// Create an event.
@@ -159,6 +327,86 @@ pub trait BootServices {
         }
     }
 
+    /// Creates an event notified when the boot reaches *stage*.
+    ///
+    /// This is [`BootServices::create_event_ex`] with the event group of the stage, close the event to cancel the
+    /// notification.
+    fn on_boot_stage<T>(
+        &self,
+        stage: BootStage,
+        notify_tpl: Tpl,
+        notify_function: EventNotifyCallback<T>,
+        notify_context: T,
+    ) -> Result<efi::Event, efi::Status>
+    where
+        T: CPtr<'static> + 'static,
+    {
+        self.create_event_ex(
+            EventType::NOTIFY_SIGNAL,
+            notify_tpl,
+            Some(notify_function),
+            notify_context,
+            stage.event_group(),
+        )
+    }
+
+    /// Creates an event whose *notify_function* is queued at *notify_tpl* when the event is signaled.
+    ///
+    /// This is [`BootServices::create_event`] with [`EventType::NOTIFY_SIGNAL`]. *notify_tpl* must be
+    /// [`Tpl::CALLBACK`] or [`Tpl::NOTIFY`], the firmware rejects the other levels with `INVALID_PARAMETER`.
+    fn create_signal_event<T>(
+        &self,
+        notify_tpl: Tpl,
+        notify_function: EventNotifyCallback<T>,
+        notify_context: T,
+    ) -> Result<efi::Event, efi::Status>
+    where
+        T: CPtr<'static> + 'static,
+    {
+        debug_assert!(Tpl::APPLICATION < notify_tpl && notify_tpl < Tpl::HIGH_LEVEL, "Invalid notify TPL.");
+        self.create_event(EventType::NOTIFY_SIGNAL, notify_tpl, Some(notify_function), notify_context)
+    }
+
+    /// Creates an event whose *notify_function* is queued at *notify_tpl* when the event is waited on or checked while
+    /// not signaled, the notify function then signals the event when its condition is met.
+    ///
+    /// This is [`BootServices::create_event`] with [`EventType::NOTIFY_WAIT`], see [`BootServices::wait_for_event`]
+    /// and [`BootServices::check_event`]. *notify_tpl* must be [`Tpl::CALLBACK`] or [`Tpl::NOTIFY`], the firmware
+    /// rejects the other levels with `INVALID_PARAMETER`.
+    fn create_wait_event<T>(
+        &self,
+        notify_tpl: Tpl,
+        notify_function: EventNotifyCallback<T>,
+        notify_context: T,
+    ) -> Result<efi::Event, efi::Status>
+    where
+        T: CPtr<'static> + 'static,
+    {
+        debug_assert!(Tpl::APPLICATION < notify_tpl && notify_tpl < Tpl::HIGH_LEVEL, "Invalid notify TPL.");
+        self.create_event(EventType::NOTIFY_WAIT, notify_tpl, Some(notify_function), notify_context)
+    }
+
+    /// Creates a timer event without notify function, signaled after *trigger_time*, and every *trigger_time* for
+    /// [`EventTimerType::Periodic`].
+    ///
+    /// Wait for the event with [`BootServices::wait_for_event`] or poll it with [`BootServices::check_event`].
+    /// *trigger_time* is rounded up to the 100ns unit of the timer, the event is closed if the timer can not be set.
+    fn create_timer_event(
+        &self,
+        timer_type: EventTimerType,
+        trigger_time: Duration,
+    ) -> Result<efi::Event, efi::Status> {
+        //SAFETY: The event has no notify function nor context.
+        let event = unsafe {
+            self.create_event_unchecked::<c_void>(EventType::TIMER, Tpl::APPLICATION, None, ptr::null_mut())?
+        };
+        if let Err(status) = self.set_timer(event, timer_type, event::timer_trigger_time(trigger_time)) {
+            let _ = self.close_event(event);
+            return Err(status);
+        }
+        Ok(event)
+    }
+
     /// Use [`BootServices::create_event_ex`] when possible.
     ///
     /// # Safety
@@ -185,6 +433,27 @@ pub trait BootServices {
     /// [UEFI Spec Documentation: 7.1.4. EFI_BOOT_SERVICES.SignalEvent()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-signalevent)
     fn signal_event(&self, event: efi::Event) -> Result<(), efi::Status>;
 
+    /// Signals every event of *event_group* by creating, signaling and closing an event in that group.
+    ///
+    /// [UEFI Spec Documentation: 7.1.2. EFI_BOOT_SERVICES.CreateEventEx()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-createeventex)
+    fn signal_event_group(&self, event_group: &'static efi::Guid) -> Result<(), efi::Status> {
+        extern "efiapi" fn empty_notify(_event: efi::Event, _context: *mut c_void) {}
+
+        //SAFETY: The notify function does not use its context.
+        let event = unsafe {
+            self.create_event_ex_unchecked(
+                EventType::NOTIFY_SIGNAL,
+                Tpl::CALLBACK,
+                empty_notify,
+                ptr::null_mut(),
+                event_group,
+            )?
+        };
+        let status = self.signal_event(event);
+        self.close_event(event)?;
+        status
+    }
+
     /// Stops execution until an event is signaled.
     ///
     /// [UEFI Spec Documentation: 7.1.5. EFI_BOOT_SERVICES.WaitForEvent()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-waitforevent)
@@ -237,6 +506,21 @@ pub trait BootServices {
     /// [UEFI Spec Documentation: 7.2.3. EFI_BOOT_SERVICES.GetMemoryMap()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-getmemorymap)
     fn get_memory_map<'a>(&'a self) -> Result<MemoryMap<'a, Self>, (efi::Status, usize)>;
 
+    /// Use [`BootServicesExt::get_memory_map_into`] when possible.
+    ///
+    /// Returns the size of the memory map written in *buffer*, the map key, the descriptor size and the descriptor
+    /// version.
+    fn get_memory_map_into_unchecked(
+        &self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, usize, usize, u32), (efi::Status, usize)>;
+
+    /// Use [`MemoryMap::is_current`](allocation::MemoryMap::is_current) when possible.
+    ///
+    /// Calls GetMemoryMap without a buffer and returns the map key reported by the firmware along
+    /// `efi::Status::BUFFER_TOO_SMALL`, or *map_key* if the firmware does not report it.
+    fn get_memory_map_key_unchecked(&self, map_key: usize) -> usize;
+
     /// Allocates pool memory.
     ///
     /// [UEFI Spec Documentation: 7.2.4. EFI_BOOT_SERVICES.AllocatePool()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-allocatepool)
@@ -253,6 +537,35 @@ pub trait BootServices {
     /// [UEFI Spec Documentation: 7.2.5. EFI_BOOT_SERVICES.FreePool()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-freepool)
     fn free_pool(&self, buffer: *mut u8) -> Result<(), efi::Status>;
 
+    /// Allocates pool memory aligned on *align* bytes, *align* must be a power of two.
+    ///
+    /// The pool allocation is oversized to fit the alignment and the original pointer is stored right before the
+    /// returned buffer, so the memory must be freed with [`BootServices::free_pool_aligned`].
+    fn allocate_pool_aligned(&self, pool_type: MemoryType, size: usize, align: usize) -> Result<*mut u8, efi::Status> {
+        if !align.is_power_of_two() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let header_size = mem::size_of::<*mut u8>();
+        let pool_size = size.checked_add(header_size + align - 1).ok_or(efi::Status::INVALID_PARAMETER)?;
+        let pool_buffer = self.allocate_pool(pool_type, pool_size)?;
+        let buffer = ((pool_buffer as usize + header_size + align - 1) & !(align - 1)) as *mut u8;
+        //SAFETY: The header fits between the start of the pool buffer and the aligned buffer.
+        unsafe { (buffer as *mut *mut u8).sub(1).write_unaligned(pool_buffer) };
+        Ok(buffer)
+    }
+
+    /// Returns pool memory allocated with [`BootServices::allocate_pool_aligned`] to the system.
+    ///
+    /// # Safety
+    ///
+    /// *buffer* must have been returned by [`BootServices::allocate_pool_aligned`] and not freed already.
+    unsafe fn free_pool_aligned(&self, buffer: *mut u8) -> Result<(), efi::Status> {
+        if buffer.is_null() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        self.free_pool((buffer as *mut *mut u8).sub(1).read_unaligned())
+    }
+
     /// Installs a protocol interface on a device handle.
     /// If the handle does not exist, it is created and added to the list of handles in the system.
     ///
@@ -409,9 +722,11 @@ pub trait BootServices {
     /// Returns an array of handles that support a specified protocol.
     ///
     /// [UEFI Spec Documentation: 7.3.6. EFI_BOOT_SERVICES.LocateHandle()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-locatehandle)
-    fn locate_handle<'a>(
+    // The explicit lifetime is needed by mockall.
+    #[allow(clippy::needless_lifetimes)]
+    fn locate_handle<'a, 'b>(
         &'a self,
-        search_type: HandleSearchType,
+        search_type: HandleSearchType<'b>,
     ) -> Result<BootServicesBox<'a, [efi::Handle], Self>, efi::Status>;
 
     /// Queries a handle to determine if it supports a specified protocol and return a mutable reference to the interface.
@@ -473,11 +788,16 @@ pub trait BootServices {
     /// Queries a handle to determine if it supports a specified protocol.
     /// If the protocol is supported by the handle, it opens the protocol on behalf of the calling agent.
     ///
+    /// [`StandardBootServices`] opens it on behalf of the image when *agent_handle* is null, see
+    /// [`image_context::or_image_handle`].
+    ///
     /// [UEFI Spec Documentation: 7.3.9. EFI_BOOT_SERVICES.OpenProtocol()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-openprotocol)
     ///
     /// # Safety
     ///
     /// Do not create more than one mutable reference to the interface.
+    // automock copies the attributes of the trait functions to its expectations, where track_caller is rejected.
+    #[cfg_attr(all(feature = "protocol_tracing", not(any(test, feature = "mockall"))), track_caller)]
     unsafe fn open_protocol<P: Protocol<Interface = I> + 'static, I: 'static>(
         &self,
         handle: efi::Handle,
@@ -502,6 +822,7 @@ pub trait BootServices {
     /// If the protocol is supported by the handle, it opens the protocol on behalf of the calling agent.
     ///
     /// [UEFI Spec Documentation: 7.3.9. EFI_BOOT_SERVICES.OpenProtocol()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-openprotocol)
+    #[cfg_attr(all(feature = "protocol_tracing", not(any(test, feature = "mockall"))), track_caller)]
     fn open_protocol_marker<P: Protocol<Interface = ()> + 'static>(
         &self,
         handle: efi::Handle,
@@ -533,6 +854,8 @@ pub trait BootServices {
 
     /// Closes a protocol on a handle that was previously opened.
     ///
+    /// Like [`BootServices::open_protocol`], [`StandardBootServices`] uses the image handle if *agent_handle* is null.
+    ///
     /// [UEFI Spec Documentation: 7.3.10. EFI_BOOT_SERVICES.CloseProtocol()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-closeprotocol)
     fn close_protocol(
         &self,
@@ -576,6 +899,45 @@ pub trait BootServices {
         child_handle: Option<efi::Handle>,
     ) -> Result<(), efi::Status>;
 
+    /// Recursively connects drivers to every handle, like the shell `connect -r` command.
+    ///
+    /// Failures to connect a handle are ignored, most handles are not controllers that a driver can manage.
+    fn connect_all_controllers(&self) -> Result<(), efi::Status>
+    where
+        Self: Sized,
+    {
+        let handles = self.locate_handle_buffer(HandleSearchType::AllHandle)?;
+        for &handle in handles.iter() {
+            //SAFETY: No driver image handles are given.
+            let _ = unsafe { self.connect_controller(handle, Vec::new(), ptr::null_mut(), true) };
+        }
+        Ok(())
+    }
+
+    /// Disconnects then recursively reconnects drivers to every handle supporting *protocol*.
+    ///
+    /// Every handle is processed even if one fails, the first error is then returned. A handle with no driver to
+    /// connect is not an error.
+    fn reconnect_controllers_with_protocol<P>(&self, protocol: &P) -> Result<(), efi::Status>
+    where
+        Self: Sized,
+        P: Protocol + 'static,
+    {
+        let handles = self.locate_handle_buffer(HandleSearchType::ByProtocol(protocol.protocol_guid()))?;
+        let mut result = Ok(());
+        for &handle in handles.iter() {
+            let status = self.disconnect_controller(handle, None, None).and_then(|_| {
+                //SAFETY: No driver image handles are given.
+                match unsafe { self.connect_controller(handle, Vec::new(), ptr::null_mut(), true) } {
+                    Err(efi::Status::NOT_FOUND) => Ok(()),
+                    status => status,
+                }
+            });
+            result = result.and(status);
+        }
+        result
+    }
+
     /// Retrieves the list of protocol interface GUIDs that are installed on a handle in a buffer allocated from pool.
     ///
     /// [UEFI Spec Documentation: 7.3.14. EFI_BOOT_SERVICES.ProtocolsPerHandle()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-protocolsperhandle)
@@ -587,11 +949,50 @@ pub trait BootServices {
     /// Returns an array of handles that support the requested protocol in a buffer allocated from pool.
     ///
     /// [UEFI Spec Documentation: 7.3.15. EFI_BOOT_SERVICES.LocateHandleBuffer()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-locatehandlebuffer)
-    fn locate_handle_buffer<'a>(
+    // The explicit lifetime is needed by mockall.
+    #[allow(clippy::needless_lifetimes)]
+    fn locate_handle_buffer<'a, 'b>(
         &'a self,
-        search_type: HandleSearchType,
+        search_type: HandleSearchType<'b>,
     ) -> Result<BootServicesBox<'a, [efi::Handle], Self>, efi::Status>;
 
+    /// Returns the handles that support *protocol*.
+    ///
+    /// When *agent_handle* is provided, the protocol is also opened on each handle with
+    /// `efi::OPEN_PROTOCOL_GET_PROTOCOL` on behalf of the agent.
+    ///
+    /// # Safety
+    ///
+    /// When opening the interfaces, make sure to not create multiple mutable reference to the same interface.
+    unsafe fn locate_handles_for_protocol<P, I>(
+        &self,
+        protocol: &P,
+        agent_handle: Option<efi::Handle>,
+    ) -> Result<Vec<ProtocolHandle<I>>, efi::Status>
+    where
+        Self: Sized,
+        P: Protocol<Interface = I> + 'static,
+        I: 'static,
+    {
+        let handles = self.locate_handle_buffer(HandleSearchType::ByProtocol(protocol))?;
+        handles
+            .iter()
+            .map(|&handle| {
+                let interface = match agent_handle {
+                    Some(agent_handle) => Some(self.open_protocol(
+                        handle,
+                        protocol,
+                        agent_handle,
+                        ptr::null_mut(),
+                        efi::OPEN_PROTOCOL_GET_PROTOCOL,
+                    )?),
+                    None => None,
+                };
+                Ok(ProtocolHandle { handle, interface })
+            })
+            .collect()
+    }
+
     /// Returns the first protocol instance that matches the given protocol.
     ///
     /// [UEFI Spec Documentation: 7.3.16. EFI_BOOT_SERVICES.LocateProtocol()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-locateprotocol)
@@ -679,6 +1080,9 @@ pub trait BootServices {
 
     /// Loads an EFI image into memory.
     ///
+    /// [`StandardBootServices`] uses the image handle as the parent if *parent_image_handle* is null, see
+    /// [`image_context::or_image_handle`].
+    ///
     /// [UEFI Spec Documentation: 7.4.1. EFI_BOOT_SERVICES.LoadImage()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-loadimage)
     ///
     fn load_image<'a>(
@@ -717,6 +1121,12 @@ pub trait BootServices {
 
     /// Terminates all boot services.
     ///
+    /// *map_key* must be the key of the current memory map, otherwise `efi::Status::INVALID_PARAMETER` is returned.
+    /// Any allocation, including the ones made by event notify functions or by the first call to ExitBootServices
+    /// itself, makes the key stale. Get the map with [`BootServicesExt::get_memory_map_into`] in a buffer allocated
+    /// beforehand, and on failure check [`MemoryMapView::is_current`](allocation::MemoryMapView::is_current) to know
+    /// whether the map must be retrieved again before retrying.
+    ///
     /// [UEFI Spec Documentation: EFI_BOOT_SERVICES.ExitBootServices()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-exitbootservices)
     ///
     fn exit_boot_services(&self, image_handle: efi::Handle, map_key: usize) -> Result<(), efi::Status>;
@@ -735,6 +1145,29 @@ pub trait BootServices {
     /// [UEFI Spec Documentation: 7.5.2. EFI_BOOT_SERVICES.Stall()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-stall)
     fn stall(&self, microseconds: usize) -> Result<(), efi::Status>;
 
+    /// Induces a fine-grained stall of at least *duration*.
+    ///
+    /// The duration is rounded up to the microsecond and stalled in chunks of at most `u32::MAX` microseconds, so long
+    /// durations neither overflow `usize` nor the conversion of the microseconds to timer ticks by the firmware.
+    fn stall_duration(&self, duration: Duration) -> Result<(), efi::Status> {
+        let mut microseconds = duration.as_nanos().div_ceil(1_000);
+        while microseconds > 0 {
+            let chunk = microseconds.min(u32::MAX as u128);
+            self.stall(chunk as usize)?;
+            microseconds -= chunk;
+        }
+        Ok(())
+    }
+
+    /// Induces a fine-grained stall until *deadline*, returns immediately if it has already passed.
+    #[cfg(feature = "perf_timer")]
+    fn stall_until(&self, deadline: perf_timer::Instant) -> Result<(), efi::Status> {
+        match deadline.checked_duration_since(&perf_timer::Instant::now()) {
+            Some(remaining) => self.stall_duration(remaining),
+            None => Ok(()),
+        }
+    }
+
     /// Copies the contents of one buffer to another buffer.
     ///
     /// [UEFI Spec Documentation: 7.5.3. EFI_BOOT_SERVICES.CopyMem()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-copymem)
@@ -783,6 +1216,9 @@ pub trait BootServices {
 
     /// Computes and returns a 32-bit CRC for a data buffer.
     ///
+    /// [`StandardBootServices`] computes it with [`crc32::crc32`] once [`boot_services_exited`], or when the firmware
+    /// does not provide the service.
+    ///
     /// [UEFI Spec Documentation: 7.5.7. EFI_BOOT_SERVICES.CalculateCrc32()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-calculatecrc32)
     fn calculate_crc_32<T: 'static>(&self, data: &T) -> Result<u32, efi::Status> {
         unsafe { self.calculate_crc_32_unchecked(data as *const T as _, mem::size_of::<T>()) }
@@ -791,6 +1227,113 @@ pub trait BootServices {
     unsafe fn calculate_crc_32_unchecked(&self, data: *const c_void, data_size: usize) -> Result<u32, efi::Status>;
 }
 
+/// Generic helpers of [`BootServices`], implemented for every boot services including the mocked ones.
+///
+/// They take closures or return borrows of their arguments, which mockall can not mock, so they are built on the
+/// mockable methods of [`BootServices`] instead.
+pub trait BootServicesExt: BootServices {
+    /// Runs *f* at *tpl*, and restores the previous [`Tpl`] when *f* returns or panics.
+    ///
+    /// This is a lighter-weight alternative to holding the [`TplGuard`] of [`BootServices::raise_tpl_guarded`] over a
+    /// scope.
+    fn with_tpl<F: FnOnce() -> R, R>(&self, tpl: Tpl, f: F) -> R {
+        let _guard = TplGuard { boot_services: self, retore_tpl: self.raise_tpl(tpl) };
+        f()
+    }
+
+    /// Runs *f* at *tpl* like [`BootServicesExt::with_tpl`], for closures using `?` to return an error early.
+    fn with_tpl_try<F: FnOnce() -> Result<T, E>, T, E>(&self, tpl: Tpl, f: F) -> Result<T, E> {
+        self.with_tpl(tpl, f)
+    }
+
+    /// Opens *protocol* on every handle that supports it and calls *f* with the handle and its interface.
+    ///
+    /// Each interface is opened with `efi::OPEN_PROTOCOL_GET_PROTOCOL` on behalf of *agent_handle*, or of the image if
+    /// None, see [`image_context::or_image_handle`]. It is closed as soon as *f* returns, so the interface reference
+    /// must not escape the callback.
+    fn for_each_protocol<P, I, F>(
+        &self,
+        protocol: &P,
+        agent_handle: Option<efi::Handle>,
+        f: F,
+    ) -> Result<(), efi::Status>
+    where
+        Self: Sized,
+        P: Protocol<Interface = I> + 'static,
+        I: 'static,
+        F: FnMut(efi::Handle, &mut I),
+    {
+        protocol_handler::for_each_protocol(self, protocol, image_context::or_image_handle(agent_handle)?, f)
+    }
+
+    /// Returns the current memory map in the caller supplied *buffer*.
+    ///
+    /// Unlike [`BootServices::get_memory_map`], this does not allocate memory, so it can be used right before
+    /// [`BootServices::exit_boot_services`] without changing the map key. On `efi::Status::BUFFER_TOO_SMALL`, the
+    /// size needed is returned along the status.
+    ///
+    /// [UEFI Spec Documentation: 7.2.3. EFI_BOOT_SERVICES.GetMemoryMap()](https://uefi.org/specs/UEFI/2.10/07_Services_Boot_Services.html#efi-boot-services-getmemorymap)
+    fn get_memory_map_into<'a>(&self, buffer: &'a mut [u8]) -> Result<MemoryMapView<'a>, (efi::Status, usize)> {
+        let (memory_map_size, map_key, descriptor_size, descriptor_version) =
+            self.get_memory_map_into_unchecked(buffer)?;
+        Ok(MemoryMapView::new(&buffer[..memory_map_size], map_key, descriptor_size, descriptor_version))
+    }
+
+    /// Installs a protocol interface like [`BootServices::install_protocol_interface`], and returns a
+    /// [`ScopedProtocolInstallation`] that uninstalls it when dropped.
+    fn install_protocol_scoped<'a, P, R>(
+        &'a self,
+        handle: Option<efi::Handle>,
+        protocol: &'a P,
+        interface: R,
+    ) -> Result<ScopedProtocolInstallation<'a, Self, P, R>, efi::Status>
+    where
+        Self: Sized,
+        P: Protocol + 'static,
+        R: CMutRef<'static, Type = P::Interface> + 'static,
+        P::Interface: 'static,
+    {
+        let (handle, key) = self.install_protocol_interface(handle, protocol, interface)?;
+        Ok(ScopedProtocolInstallation::new(self, handle, protocol, key))
+    }
+
+    /// Creates an event like [`BootServices::create_event`], whose notify function gets *context* as an
+    /// [`AnyEventContext`](event::AnyEventContext).
+    ///
+    /// Unlike [`BootServices::create_event`], the notify function does not depend on the context type, so no
+    /// function signature has to be transmuted, and the context lives as long as the returned [`ContextEvent`].
+    fn create_event_with_context<T: Any>(
+        &self,
+        event_type: EventType,
+        notify_tpl: Tpl,
+        notify_function: AnyEventNotifyCallback,
+        context: T,
+    ) -> Result<ContextEvent<'_, Self>, efi::Status>
+    where
+        Self: Sized,
+    {
+        event::create_event_with_context(self, event_type, notify_tpl, notify_function, context, None)
+    }
+
+    /// Creates an event in *event_group* like [`BootServices::create_event_ex`], whose notify function gets *context*
+    /// as an [`AnyEventContext`](event::AnyEventContext), see [`BootServicesExt::create_event_with_context`].
+    fn create_event_ex_with_context<T: Any>(
+        &self,
+        event_type: EventType,
+        notify_tpl: Tpl,
+        notify_function: AnyEventNotifyCallback,
+        context: T,
+        event_group: &'static efi::Guid,
+    ) -> Result<ContextEvent<'_, Self>, efi::Status>
+    where
+        Self: Sized,
+    {
+        event::create_event_with_context(self, event_type, notify_tpl, notify_function, context, Some(event_group))
+    }
+}
+
+impl<B: BootServices + ?Sized> BootServicesExt for B {}
+
 macro_rules! efi_boot_services_fn {
     ($efi_boot_services:expr, $fn_name:ident) => {{
         match $efi_boot_services.$fn_name {
@@ -808,6 +1351,7 @@ impl BootServices for StandardBootServices<'_> {
         notify_function: Option<EventNotifyCallback<*mut T>>,
         notify_context: *mut T,
     ) -> Result<efi::Event, efi::Status> {
+        self.ensure_not_exited()?;
         let mut event = MaybeUninit::zeroed();
         let status = efi_boot_services_fn!(self.efi_boot_services(), create_event)(
             event_type.into(),
@@ -831,6 +1375,7 @@ impl BootServices for StandardBootServices<'_> {
         notify_context: *mut T,
         event_group: &'static efi::Guid,
     ) -> Result<efi::Event, efi::Status> {
+        self.ensure_not_exited()?;
         let mut event = MaybeUninit::zeroed();
         let status = efi_boot_services_fn!(self.efi_boot_services(), create_event_ex)(
             event_type.into(),
@@ -848,6 +1393,7 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     fn close_event(&self, event: efi::Event) -> Result<(), efi::Status> {
+        self.ensure_not_exited()?;
         match efi_boot_services_fn!(self.efi_boot_services(), close_event)(event) {
             s if s.is_error() => Err(s),
             _ => Ok(()),
@@ -855,6 +1401,7 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     fn signal_event(&self, event: efi::Event) -> Result<(), efi::Status> {
+        self.ensure_not_exited()?;
         match efi_boot_services_fn!(self.efi_boot_services(), signal_event)(event) {
             s if s.is_error() => Err(s),
             _ => Ok(()),
@@ -862,6 +1409,7 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     fn wait_for_event(&self, events: &mut [efi::Event]) -> Result<usize, efi::Status> {
+        self.ensure_not_exited()?;
         let mut index = MaybeUninit::zeroed();
         let status = efi_boot_services_fn!(self.efi_boot_services(), wait_for_event)(
             events.len(),
@@ -876,6 +1424,7 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     fn check_event(&self, event: efi::Event) -> Result<(), efi::Status> {
+        self.ensure_not_exited()?;
         match efi_boot_services_fn!(self.efi_boot_services(), check_event)(event) {
             s if s.is_error() => Err(s),
             _ => Ok(()),
@@ -883,6 +1432,7 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     fn set_timer(&self, event: efi::Event, timer_type: EventTimerType, trigger_time: u64) -> Result<(), efi::Status> {
+        self.ensure_not_exited()?;
         match efi_boot_services_fn!(self.efi_boot_services(), set_timer)(event, timer_type.into(), trigger_time) {
             s if s.is_error() => Err(s),
             _ => Ok(()),
@@ -903,9 +1453,13 @@ impl BootServices for StandardBootServices<'_> {
         memory_type: MemoryType,
         nb_pages: usize,
     ) -> Result<usize, efi::Status> {
+        self.ensure_not_exited()?;
         let mut memory_address = match alloc_type {
             AllocType::Address(address) => address,
             AllocType::MaxAddress(address) => address,
+            AllocType::InRange(range) => {
+                return allocation::allocate_pages_in_range(self, range, memory_type, nb_pages);
+            }
             _ => 0,
         };
         match efi_boot_services_fn!(self.efi_boot_services(), allocate_pages)(
@@ -920,6 +1474,7 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     fn free_pages(&self, address: usize, nb_pages: usize) -> Result<(), efi::Status> {
+        self.ensure_not_exited()?;
         match efi_boot_services_fn!(self.efi_boot_services(), free_pages)(address as u64, nb_pages) {
             s if s.is_error() => Err(s),
             _ => Ok(()),
@@ -965,7 +1520,45 @@ impl BootServices for StandardBootServices<'_> {
         })
     }
 
+    fn get_memory_map_into_unchecked(
+        &self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, usize, usize, u32), (efi::Status, usize)> {
+        let mut memory_map_size = buffer.len();
+        let mut map_key = 0;
+        let mut descriptor_size = 0;
+        let mut descriptor_version = 0;
+
+        match efi_boot_services_fn!(self.efi_boot_services(), get_memory_map)(
+            ptr::addr_of_mut!(memory_map_size),
+            buffer.as_mut_ptr() as *mut _,
+            ptr::addr_of_mut!(map_key),
+            ptr::addr_of_mut!(descriptor_size),
+            ptr::addr_of_mut!(descriptor_version),
+        ) {
+            s if s == efi::Status::BUFFER_TOO_SMALL => Err((s, memory_map_size)),
+            s if s.is_error() => Err((s, 0)),
+            _ => Ok((usize::min(memory_map_size, buffer.len()), map_key, descriptor_size, descriptor_version)),
+        }
+    }
+
+    fn get_memory_map_key_unchecked(&self, mut map_key: usize) -> usize {
+        let mut memory_map_size = 0;
+        let mut descriptor_size = 0;
+        let mut descriptor_version = 0;
+
+        efi_boot_services_fn!(self.efi_boot_services(), get_memory_map)(
+            ptr::addr_of_mut!(memory_map_size),
+            ptr::null_mut(),
+            ptr::addr_of_mut!(map_key),
+            ptr::addr_of_mut!(descriptor_size),
+            ptr::addr_of_mut!(descriptor_version),
+        );
+        map_key
+    }
+
     fn allocate_pool(&self, memory_type: MemoryType, size: usize) -> Result<*mut u8, efi::Status> {
+        self.ensure_not_exited()?;
         let mut buffer = ptr::null_mut();
         match efi_boot_services_fn!(self.efi_boot_services(), allocate_pool)(
             memory_type.into(),
@@ -978,6 +1571,7 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     fn free_pool(&self, buffer: *mut u8) -> Result<(), efi::Status> {
+        self.ensure_not_exited()?;
         match efi_boot_services_fn!(self.efi_boot_services(), free_pool)(buffer as *mut c_void) {
             s if s.is_error() => return Err(s),
             _ => Ok(()),
@@ -1050,7 +1644,7 @@ impl BootServices for StandardBootServices<'_> {
 
     fn locate_handle(
         &self,
-        search_type: HandleSearchType,
+        search_type: HandleSearchType<'_>,
     ) -> Result<BootServicesBox<[efi::Handle], Self>, efi::Status> {
         let locate_handle = efi_boot_services_fn!(self.efi_boot_services(), locate_handle);
 
@@ -1119,6 +1713,7 @@ impl BootServices for StandardBootServices<'_> {
         }
     }
 
+    #[cfg_attr(feature = "protocol_tracing", track_caller)]
     unsafe fn open_protocol_unchecked(
         &self,
         handle: efi::Handle,
@@ -1127,20 +1722,36 @@ impl BootServices for StandardBootServices<'_> {
         controller_handle: efi::Handle,
         attribute: u32,
     ) -> Result<*mut c_void, efi::Status> {
+        let agent_handle = image_context::or_image_handle_if_null(agent_handle);
         let mut interface = ptr::null_mut();
-        match efi_boot_services_fn!(self.efi_boot_services(), open_protocol)(
+        let status = efi_boot_services_fn!(self.efi_boot_services(), open_protocol)(
             handle,
             protocol as *const _ as *mut _,
             ptr::addr_of_mut!(interface),
             agent_handle,
             controller_handle,
             attribute,
-        ) {
+        );
+        #[cfg(feature = "protocol_tracing")]
+        protocol_tracing::PROTOCOL_TRACER.trace_open(
+            self,
+            protocol_tracing::TracedOpen {
+                handle,
+                protocol: *protocol,
+                agent_handle,
+                controller_handle,
+                attributes: attribute,
+                location: core::panic::Location::caller(),
+            },
+            status,
+        );
+        match status {
             s if s.is_error() => Err(s),
             _ => Ok(interface),
         }
     }
 
+    #[cfg_attr(feature = "protocol_tracing", track_caller)]
     fn close_protocol(
         &self,
         handle: efi::Handle,
@@ -1148,12 +1759,24 @@ impl BootServices for StandardBootServices<'_> {
         agent_handle: efi::Handle,
         controller_handle: efi::Handle,
     ) -> Result<(), efi::Status> {
-        match efi_boot_services_fn!(self.efi_boot_services(), close_protocol)(
+        let agent_handle = image_context::or_image_handle_if_null(agent_handle);
+        let status = efi_boot_services_fn!(self.efi_boot_services(), close_protocol)(
             handle,
             protocol as *const _ as *mut _,
             agent_handle,
             controller_handle,
-        ) {
+        );
+        #[cfg(feature = "protocol_tracing")]
+        protocol_tracing::PROTOCOL_TRACER.trace_close(
+            self,
+            handle,
+            protocol,
+            agent_handle,
+            controller_handle,
+            core::panic::Location::caller(),
+            status,
+        );
+        match status {
             s if s.is_error() => Err(s),
             _ => Ok(()),
         }
@@ -1240,7 +1863,7 @@ impl BootServices for StandardBootServices<'_> {
 
     fn locate_handle_buffer(
         &self,
-        search_type: HandleSearchType,
+        search_type: HandleSearchType<'_>,
     ) -> Result<BootServicesBox<[efi::Handle], Self>, efi::Status>
     where
         Self: Sized,
@@ -1298,7 +1921,7 @@ impl BootServices for StandardBootServices<'_> {
         let mut image_handle = MaybeUninit::uninit();
         match efi_boot_services_fn!(self.efi_boot_services(), load_image)(
             boot_policy.into(),
-            parent_image_handle,
+            image_context::or_image_handle_if_null(parent_image_handle),
             device_path,
             source_buffer_ptr,
             source_buffer_size,
@@ -1363,7 +1986,10 @@ impl BootServices for StandardBootServices<'_> {
     fn exit_boot_services(&self, image_handle: efi::Handle, map_key: usize) -> Result<(), efi::Status> {
         match efi_boot_services_fn!(self.efi_boot_services(), exit_boot_services)(image_handle, map_key) {
             s if s.is_error() => Err(s),
-            _ => Ok(()),
+            _ => {
+                exit_boot_services_state::set();
+                Ok(())
+            }
         }
     }
 
@@ -1416,14 +2042,20 @@ impl BootServices for StandardBootServices<'_> {
     }
 
     unsafe fn calculate_crc_32_unchecked(&self, data: *const c_void, data_size: usize) -> Result<u32, efi::Status> {
-        let mut crc32 = MaybeUninit::uninit();
-        match efi_boot_services_fn!(self.efi_boot_services(), calculate_crc32)(
-            data as *mut _,
-            data_size,
-            crc32.as_mut_ptr(),
-        ) {
-            s if s.is_error() => Err(s),
-            _ => Ok(unsafe { crc32.assume_init() }),
+        // Fall back to the software implementation when boot services are gone or the service is not provided.
+        let calculate_crc32 = match boot_services_exited() {
+            true => None,
+            false => self.efi_boot_services.load(Ordering::SeqCst).as_ref().map(|bs| bs.calculate_crc32),
+        };
+        match calculate_crc32 {
+            Some(f) if f as usize != 0 => {
+                let mut crc32 = MaybeUninit::uninit();
+                match f(data as *mut _, data_size, crc32.as_mut_ptr()) {
+                    s if s.is_error() => Err(s),
+                    _ => Ok(unsafe { crc32.assume_init() }),
+                }
+            }
+            _ => Ok(crc32::crc32(slice::from_raw_parts(data as *const u8, data_size))),
         }
     }
 }
@@ -1434,7 +2066,13 @@ mod test {
     use efi::{protocols::device_path, Boolean, Char16, OpenProtocolInformationEntry};
 
     use super::*;
-    use core::{mem::MaybeUninit, ops::Deref, slice, sync::atomic::AtomicUsize, u32, u64};
+    use core::{
+        mem::MaybeUninit,
+        ops::Deref,
+        slice,
+        sync::atomic::{AtomicBool, AtomicU64, AtomicUsize},
+        u32, u64,
+    };
     use std::os::raw::c_void;
 
     macro_rules! boot_services {
@@ -1453,6 +2091,13 @@ mod test {
         }};
     }
 
+    // TPL functions for the tests of functions raising the TPL internally, e.g. with the protocol_tracing feature.
+    extern "efiapi" fn efi_raise_tpl_noop(tpl: efi::Tpl) -> efi::Tpl {
+        tpl
+    }
+
+    extern "efiapi" fn efi_restore_tpl_noop(_tpl: efi::Tpl) {}
+
     static TEST_PROTOCOL_GUID: efi::Guid =
         efi::Guid::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
 
@@ -1533,11 +2178,40 @@ mod test {
     }
 
     #[test]
-    #[should_panic = "Boot services function create_event is not initialized."]
-    fn test_create_event_not_init() {
-        let boot_services = boot_services!();
-        let _ = boot_services.create_event(EventType::RUNTIME, Tpl::APPLICATION, None, ());
-    }
+    fn test_raw_ptr_interop() {
+        let mut efi_bs = MaybeUninit::<efi::BootServices>::zeroed();
+        let mut efi_st = unsafe { MaybeUninit::<efi::SystemTable>::zeroed().assume_init() };
+        efi_st.boot_services = efi_bs.as_mut_ptr();
+
+        let bs = unsafe { StandardBootServices::from_system_table(ptr::addr_of_mut!(efi_st)) };
+        assert_eq!(efi_bs.as_mut_ptr(), bs.as_raw_ptr());
+
+        let bs = unsafe { StandardBootServices::from_raw_ptr(ptr::null_mut()) };
+        assert!(bs.as_raw_ptr().is_null());
+    }
+
+    #[test]
+    fn test_initialize_checked() {
+        let mut efi_bs = table_header::zeroed_table::<efi::BootServices>(efi::BOOT_SERVICES_SIGNATURE);
+        let header = efi_bs.as_mut_ptr() as *mut efi::TableHeader;
+
+        let bs = StandardBootServices::new_uninit();
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), unsafe { bs.initialize_checked(ptr::null()) });
+        unsafe { (*header).crc32 ^= 1 };
+        assert_eq!(Err(efi::Status::CRC_ERROR), unsafe { bs.initialize_checked(efi_bs.as_ptr()) });
+        assert!(bs.as_raw_ptr().is_null());
+
+        unsafe { (*header).crc32 ^= 1 };
+        assert_eq!(Ok(()), unsafe { bs.initialize_checked(efi_bs.as_ptr()) });
+        assert_eq!(efi_bs.as_mut_ptr(), bs.as_raw_ptr());
+    }
+
+    #[test]
+    #[should_panic = "Boot services function create_event is not initialized."]
+    fn test_create_event_not_init() {
+        let boot_services = boot_services!();
+        let _ = boot_services.create_event(EventType::RUNTIME, Tpl::APPLICATION, None, ());
+    }
 
     #[test]
     fn test_create_event() {
@@ -1651,6 +2325,103 @@ mod test {
         assert!(matches!(status, Ok(_)));
     }
 
+    #[test]
+    fn test_on_boot_stage() {
+        let boot_services = boot_services!(create_event_ex = efi_create_event_ex);
+
+        extern "efiapi" fn notify_callback(_e: efi::Event, _ctx: Box<i32>) {}
+
+        extern "efiapi" fn efi_create_event_ex(
+            event_type: u32,
+            notify_tpl: efi::Tpl,
+            notify_function: Option<efi::EventNotify>,
+            _notify_context: *const c_void,
+            event_group: *const efi::Guid,
+            event: *mut efi::Event,
+        ) -> efi::Status {
+            assert_eq!(efi::EVT_NOTIFY_SIGNAL, event_type);
+            assert_eq!(efi::TPL_CALLBACK, notify_tpl);
+            assert_eq!(Some(notify_callback as usize), notify_function.map(|f| f as usize));
+            assert_eq!(efi::EVENT_GROUP_READY_TO_BOOT, unsafe { *event_group });
+            unsafe { ptr::write(event, 1_usize as _) };
+            efi::Status::SUCCESS
+        }
+
+        let event = boot_services.on_boot_stage(BootStage::ReadyToBoot, Tpl::CALLBACK, notify_callback, Box::new(10));
+        assert_eq!(Ok(1), event.map(|e| e as usize));
+    }
+
+    #[test]
+    fn test_create_signal_and_wait_event() {
+        let boot_services = boot_services!(create_event = efi_create_event);
+
+        extern "efiapi" fn notify_callback(_e: efi::Event, _ctx: Box<i32>) {}
+
+        extern "efiapi" fn efi_create_event(
+            event_type: u32,
+            notify_tpl: efi::Tpl,
+            notify_function: Option<efi::EventNotify>,
+            _notify_context: *mut c_void,
+            event: *mut efi::Event,
+        ) -> efi::Status {
+            assert_eq!(efi::TPL_NOTIFY, notify_tpl);
+            assert_eq!(Some(notify_callback as usize), notify_function.map(|f| f as usize));
+            unsafe { ptr::write(event, event_type as usize as _) };
+            efi::Status::SUCCESS
+        }
+
+        let event = boot_services.create_signal_event(Tpl::NOTIFY, notify_callback, Box::new(10));
+        assert_eq!(Ok(efi::EVT_NOTIFY_SIGNAL as usize), event.map(|e| e as usize));
+        let event = boot_services.create_wait_event(Tpl::NOTIFY, notify_callback, Box::new(10));
+        assert_eq!(Ok(efi::EVT_NOTIFY_WAIT as usize), event.map(|e| e as usize));
+    }
+
+    #[test]
+    fn test_create_timer_event() {
+        let boot_services =
+            boot_services!(create_event = efi_create_event, set_timer = efi_set_timer, close_event = efi_close_event);
+
+        static CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+        extern "efiapi" fn efi_create_event(
+            event_type: u32,
+            _notify_tpl: efi::Tpl,
+            notify_function: Option<efi::EventNotify>,
+            notify_context: *mut c_void,
+            event: *mut efi::Event,
+        ) -> efi::Status {
+            assert_eq!(efi::EVT_TIMER, event_type);
+            assert_eq!(None, notify_function);
+            assert!(notify_context.is_null());
+            unsafe { ptr::write(event, 1_usize as _) };
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_set_timer(
+            _event: efi::Event,
+            r#type: efi::TimerDelay,
+            trigger_time: u64,
+        ) -> efi::Status {
+            match (r#type, trigger_time) {
+                (efi::TIMER_RELATIVE, 10_001) => efi::Status::SUCCESS,
+                _ => efi::Status::INVALID_PARAMETER,
+            }
+        }
+
+        extern "efiapi" fn efi_close_event(event: efi::Event) -> efi::Status {
+            CLOSED.store(event as usize, Ordering::Relaxed);
+            efi::Status::SUCCESS
+        }
+
+        let event = boot_services.create_timer_event(EventTimerType::Relative, Duration::from_nanos(1_000_001));
+        assert_eq!(Ok(1), event.map(|e| e as usize));
+        assert_eq!(0, CLOSED.load(Ordering::Relaxed));
+
+        let event = boot_services.create_timer_event(EventTimerType::Periodic, Duration::from_millis(1));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), event);
+        assert_eq!(1, CLOSED.load(Ordering::Relaxed));
+    }
+
     #[test]
     fn test_create_event_ex_no_notify() {
         let boot_services = boot_services!(create_event_ex = efi_create_event_ex);
@@ -1711,6 +2482,48 @@ mod test {
         let _ = boot_services.signal_event(ptr::null_mut());
     }
 
+    #[test]
+    fn test_signal_event_group() {
+        let boot_services = boot_services!(
+            create_event_ex = efi_create_event_ex,
+            signal_event = efi_signal_event,
+            close_event = efi_close_event
+        );
+
+        static GUID: efi::Guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        static CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+        extern "efiapi" fn efi_create_event_ex(
+            event_type: u32,
+            notify_tpl: efi::Tpl,
+            notify_function: Option<efi::EventNotify>,
+            _notify_context: *const c_void,
+            event_group: *const efi::Guid,
+            event: *mut efi::Event,
+        ) -> efi::Status {
+            assert_eq!(efi::EVT_NOTIFY_SIGNAL, event_type);
+            assert_eq!(efi::TPL_CALLBACK, notify_tpl);
+            assert!(notify_function.is_some());
+            assert_eq!(ptr::addr_of!(GUID), event_group);
+            unsafe { ptr::write(event, 1_usize as efi::Event) };
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_signal_event(event: efi::Event) -> efi::Status {
+            assert_eq!(1, event as usize);
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_close_event(event: efi::Event) -> efi::Status {
+            assert_eq!(1, event as usize);
+            CLOSED.fetch_add(1, Ordering::Relaxed);
+            efi::Status::SUCCESS
+        }
+
+        boot_services.signal_event_group(&GUID).unwrap();
+        assert_eq!(1, CLOSED.load(Ordering::Relaxed));
+    }
+
     #[test]
     fn test_signal_event() {
         let boot_services = boot_services!(signal_event = efi_signal_event);
@@ -1821,6 +2634,65 @@ mod test {
         assert_eq!(efi::TPL_APPLICATION, CURRENT_TPL.load(Ordering::Relaxed));
     }
 
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_current_tpl() {
+        let boot_services = boot_services!(raise_tpl = efi_raise_tpl, restore_tpl = efi_restore_tpl);
+
+        static CURRENT_TPL: AtomicUsize = AtomicUsize::new(efi::TPL_CALLBACK);
+
+        extern "efiapi" fn efi_raise_tpl(tpl: efi::Tpl) -> efi::Tpl {
+            assert_eq!(efi::TPL_HIGH_LEVEL, tpl);
+            CURRENT_TPL.swap(tpl, Ordering::Relaxed)
+        }
+
+        extern "efiapi" fn efi_restore_tpl(tpl: efi::Tpl) {
+            CURRENT_TPL.swap(tpl, Ordering::Relaxed);
+        }
+
+        assert_eq!(Tpl::CALLBACK, boot_services.current_tpl());
+        assert_eq!(efi::TPL_CALLBACK, CURRENT_TPL.load(Ordering::Relaxed));
+        boot_services.debug_assert_tpl_at_most(Tpl::CALLBACK);
+        assert!(std::panic::catch_unwind(|| boot_services.debug_assert_tpl_at_most(Tpl::APPLICATION)).is_err());
+    }
+
+    #[test]
+    fn test_with_tpl() {
+        let boot_services = boot_services!(raise_tpl = efi_raise_tpl, restore_tpl = efi_restore_tpl);
+
+        static CURRENT_TPL: AtomicUsize = AtomicUsize::new(efi::TPL_APPLICATION);
+
+        extern "efiapi" fn efi_raise_tpl(tpl: efi::Tpl) -> efi::Tpl {
+            assert_eq!(efi::TPL_NOTIFY, tpl);
+            CURRENT_TPL.swap(tpl, Ordering::Relaxed)
+        }
+
+        extern "efiapi" fn efi_restore_tpl(tpl: efi::Tpl) {
+            assert_eq!(efi::TPL_APPLICATION, tpl);
+            CURRENT_TPL.swap(tpl, Ordering::Relaxed);
+        }
+
+        let current_tpl = || CURRENT_TPL.load(Ordering::Relaxed);
+        assert_eq!(efi::TPL_NOTIFY, boot_services.with_tpl(Tpl::NOTIFY, current_tpl));
+        assert_eq!(efi::TPL_APPLICATION, current_tpl());
+
+        // The tpl is restored on early returns and panics.
+        let result = boot_services.with_tpl_try(Tpl::NOTIFY, || {
+            Err(efi::Status::NOT_READY)?;
+            Ok(current_tpl())
+        });
+        assert_eq!(Err(efi::Status::NOT_READY), result);
+        assert_eq!(efi::TPL_APPLICATION, current_tpl());
+        assert!(std::panic::catch_unwind(|| boot_services.with_tpl(Tpl::NOTIFY, || panic!())).is_err());
+        assert_eq!(efi::TPL_APPLICATION, current_tpl());
+
+        // Boot services mocks run the closure too.
+        let mut mock = MockBootServices::new();
+        mock.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        mock.expect_restore_tpl().withf(|tpl| *tpl == Tpl::APPLICATION).times(1).return_const(());
+        assert_eq!(Ok::<_, efi::Status>(42), mock.with_tpl_try(Tpl::CALLBACK, || Ok(42)));
+    }
+
     #[test]
     #[should_panic = "Boot services function raise_tpl is not initialized."]
     fn test_raise_tpl_not_init() {
@@ -2486,7 +3358,11 @@ mod test {
 
     #[test]
     fn test_close_protocol() {
-        let boot_services = boot_services!(close_protocol = efi_close_protocol);
+        let boot_services = boot_services!(
+            close_protocol = efi_close_protocol,
+            raise_tpl = efi_raise_tpl_noop,
+            restore_tpl = efi_restore_tpl_noop
+        );
 
         extern "efiapi" fn efi_close_protocol(
             handle: efi::Handle,
@@ -2505,6 +3381,325 @@ mod test {
         _ = boot_services.close_protocol(1 as usize as _, &TestProtocol, 2 as usize as _, 3 as usize as _).unwrap();
     }
 
+    #[test]
+    fn test_allocate_pool_aligned() {
+        let boot_services =
+            boot_services!(allocate_pool = efi_allocate_pool_use_box, free_pool = efi_free_pool_use_box);
+
+        for align in [1, 8, 64, 4096] {
+            let buffer = boot_services.allocate_pool_aligned(MemoryType::BOOT_SERVICES_DATA, 100, align).unwrap();
+            assert_eq!(0, buffer as usize % align);
+            unsafe { ptr::write_bytes(buffer, 0xAA, 100) };
+            unsafe { boot_services.free_pool_aligned(buffer) }.unwrap();
+        }
+
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            boot_services.allocate_pool_aligned(MemoryType::BOOT_SERVICES_DATA, 100, 3)
+        );
+    }
+
+    #[test]
+    fn test_boot_services_box_aligned() {
+        let boot_services =
+            boot_services!(allocate_pool = efi_allocate_pool_use_box, free_pool = efi_free_pool_use_box);
+
+        let b = BootServicesBox::new_aligned([1_u8; 10], MemoryType::BOOT_SERVICES_DATA, 64, boot_services);
+        assert_eq!(0, b.as_ptr() as usize % 64);
+        assert_eq!([1_u8; 10], *b);
+    }
+
+    #[test]
+    fn test_boot_services_box_into_raw_mut_keeps_the_memory() {
+        static FREE_COUNT: AtomicUsize = AtomicUsize::new(0);
+        extern "efiapi" fn efi_free_pool_counted(buffer: *mut c_void) -> efi::Status {
+            FREE_COUNT.fetch_add(1, Ordering::SeqCst);
+            efi_free_pool_use_box(buffer)
+        }
+        let boot_services =
+            boot_services!(allocate_pool = efi_allocate_pool_use_box, free_pool = efi_free_pool_counted);
+
+        let b = BootServicesBox::new(42_u64, MemoryType::BOOT_SERVICES_DATA, boot_services);
+        let ptr = unsafe { b.into_raw_mut() };
+        assert_eq!(0, FREE_COUNT.load(Ordering::SeqCst));
+        assert_eq!(42, unsafe { *ptr });
+
+        drop(unsafe { BootServicesBox::from_raw(ptr, boot_services) });
+        assert_eq!(1, FREE_COUNT.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[should_panic(expected = "An aligned box can not be converted to a raw pointer.")]
+    fn test_boot_services_box_aligned_into_raw_panics() {
+        let boot_services =
+            boot_services!(allocate_pool = efi_allocate_pool_use_box, free_pool = efi_free_pool_use_box);
+
+        let b = BootServicesBox::new_aligned([1_u8; 10], MemoryType::BOOT_SERVICES_DATA, 64, boot_services);
+        let _ = unsafe { b.into_raw() };
+    }
+
+    #[test]
+    fn test_locate_handles_for_protocol() {
+        let boot_services = boot_services!(
+            locate_handle_buffer = efi_locate_handle_buffer,
+            open_protocol = efi_open_protocol,
+            free_pool = efi_free_pool_use_box,
+            raise_tpl = efi_raise_tpl_noop,
+            restore_tpl = efi_restore_tpl_noop
+        );
+
+        extern "efiapi" fn efi_locate_handle_buffer(
+            search_type: efi::LocateSearchType,
+            protocol: *mut efi::Guid,
+            _search_key: *mut c_void,
+            nb_handles: *mut usize,
+            buffer: *mut *mut efi::Handle,
+        ) -> efi::Status {
+            assert_eq!(efi::BY_PROTOCOL, search_type);
+            assert_eq!(TEST_PROTOCOL_GUID, unsafe { ptr::read(protocol) });
+            let handles = vec![1_usize as efi::Handle, 2_usize as efi::Handle].into_boxed_slice();
+            unsafe {
+                ptr::write(nb_handles, handles.len());
+                ptr::write(buffer, Box::into_raw(handles) as *mut efi::Handle);
+            }
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_open_protocol(
+            handle: efi::Handle,
+            _protocol: *mut efi::Guid,
+            interface: *mut *mut c_void,
+            agent_handle: efi::Handle,
+            _controller_handle: efi::Handle,
+            attributes: u32,
+        ) -> efi::Status {
+            assert_eq!(3, agent_handle as usize);
+            assert_eq!(efi::OPEN_PROTOCOL_GET_PROTOCOL, attributes);
+            let b = Box::new(handle as u32 * 10);
+            unsafe { ptr::write(interface, Box::into_raw(b) as _) };
+            efi::Status::SUCCESS
+        }
+
+        let handles = unsafe { boot_services.locate_handles_for_protocol(&TestProtocol, None) }.unwrap();
+        assert_eq!(vec![1, 2], handles.iter().map(|h| h.handle as usize).collect::<Vec<_>>());
+        assert!(handles.iter().all(|h| h.interface.is_none()));
+
+        let handles = unsafe { boot_services.locate_handles_for_protocol(&TestProtocol, Some(3_usize as _)) }.unwrap();
+        assert_eq!(
+            vec![(1, Some(10)), (2, Some(20))],
+            handles.into_iter().map(|h| (h.handle as usize, h.interface.map(|i| *i))).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_connect_all_controllers() {
+        let boot_services = boot_services!(
+            locate_handle_buffer = efi_locate_handle_buffer,
+            connect_controller = efi_connect_controller,
+            free_pool = efi_free_pool_use_box
+        );
+
+        static CONNECTED: AtomicUsize = AtomicUsize::new(0);
+
+        extern "efiapi" fn efi_locate_handle_buffer(
+            search_type: efi::LocateSearchType,
+            protocol: *mut efi::Guid,
+            _search_key: *mut c_void,
+            nb_handles: *mut usize,
+            buffer: *mut *mut efi::Handle,
+        ) -> efi::Status {
+            assert_eq!(efi::ALL_HANDLES, search_type);
+            assert_eq!(ptr::null_mut(), protocol);
+            let handles = vec![1_usize as efi::Handle, 2_usize as efi::Handle, 4_usize as efi::Handle];
+            unsafe {
+                ptr::write(nb_handles, handles.len());
+                ptr::write(buffer, Box::into_raw(handles.into_boxed_slice()) as *mut efi::Handle);
+            }
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_connect_controller(
+            controller_handle: efi::Handle,
+            driver_image_handle: *mut efi::Handle,
+            remaining_device_path: *mut efi::protocols::device_path::Protocol,
+            recursive: efi::Boolean,
+        ) -> efi::Status {
+            assert_eq!(ptr::null_mut(), driver_image_handle);
+            assert_eq!(ptr::null_mut(), remaining_device_path);
+            assert!(bool::from(recursive));
+            CONNECTED.fetch_or(controller_handle as usize, Ordering::Relaxed);
+            match controller_handle as usize {
+                2 => efi::Status::NOT_FOUND,
+                _ => efi::Status::SUCCESS,
+            }
+        }
+
+        boot_services.connect_all_controllers().unwrap();
+        assert_eq!(0b111, CONNECTED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_reconnect_controllers_with_protocol() {
+        let boot_services = boot_services!(
+            locate_handle_buffer = efi_locate_handle_buffer,
+            connect_controller = efi_connect_controller,
+            disconnect_controller = efi_disconnect_controller,
+            free_pool = efi_free_pool_use_box
+        );
+
+        static DISCONNECTED: AtomicUsize = AtomicUsize::new(0);
+        static CONNECTED: AtomicUsize = AtomicUsize::new(0);
+
+        extern "efiapi" fn efi_locate_handle_buffer(
+            search_type: efi::LocateSearchType,
+            protocol: *mut efi::Guid,
+            _search_key: *mut c_void,
+            nb_handles: *mut usize,
+            buffer: *mut *mut efi::Handle,
+        ) -> efi::Status {
+            assert_eq!(efi::BY_PROTOCOL, search_type);
+            assert_eq!(TEST_PROTOCOL_GUID, unsafe { ptr::read(protocol) });
+            let handles = vec![1_usize as efi::Handle, 2_usize as efi::Handle, 4_usize as efi::Handle];
+            unsafe {
+                ptr::write(nb_handles, handles.len());
+                ptr::write(buffer, Box::into_raw(handles.into_boxed_slice()) as *mut efi::Handle);
+            }
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_disconnect_controller(
+            controller_handle: efi::Handle,
+            driver_image_handle: efi::Handle,
+            child_handle: efi::Handle,
+        ) -> efi::Status {
+            assert_eq!(ptr::null_mut(), driver_image_handle);
+            assert_eq!(ptr::null_mut(), child_handle);
+            DISCONNECTED.fetch_or(controller_handle as usize, Ordering::Relaxed);
+            match controller_handle as usize {
+                2 => efi::Status::DEVICE_ERROR,
+                _ => efi::Status::SUCCESS,
+            }
+        }
+
+        extern "efiapi" fn efi_connect_controller(
+            controller_handle: efi::Handle,
+            _driver_image_handle: *mut efi::Handle,
+            _remaining_device_path: *mut efi::protocols::device_path::Protocol,
+            recursive: efi::Boolean,
+        ) -> efi::Status {
+            assert!(bool::from(recursive));
+            CONNECTED.fetch_or(controller_handle as usize, Ordering::Relaxed);
+            match controller_handle as usize {
+                4 => efi::Status::NOT_FOUND,
+                _ => efi::Status::SUCCESS,
+            }
+        }
+
+        // Handle 2 fails to disconnect, so it is not reconnected, but handle 4 is still processed.
+        assert_eq!(Err(efi::Status::DEVICE_ERROR), boot_services.reconnect_controllers_with_protocol(&TestProtocol));
+        assert_eq!(0b111, DISCONNECTED.load(Ordering::Relaxed));
+        assert_eq!(0b101, CONNECTED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_locate_handle_with_non_static_guid() {
+        let boot_services =
+            boot_services!(locate_handle_buffer = efi_locate_handle_buffer, free_pool = efi_free_pool_use_box);
+
+        extern "efiapi" fn efi_locate_handle_buffer(
+            _search_type: efi::LocateSearchType,
+            protocol: *mut efi::Guid,
+            _search_key: *mut c_void,
+            nb_handles: *mut usize,
+            buffer: *mut *mut efi::Handle,
+        ) -> efi::Status {
+            assert_eq!(efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]), unsafe { ptr::read(protocol) });
+            unsafe {
+                ptr::write(nb_handles, 0);
+                ptr::write(buffer, Box::into_raw(Box::new(0_u64)) as *mut efi::Handle);
+            }
+            efi::Status::SUCCESS
+        }
+
+        let guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        let handles = boot_services.locate_handle_buffer(HandleSearchType::ByProtocol(&guid)).unwrap();
+        assert!(handles.is_empty());
+    }
+
+    #[test]
+    fn test_for_each_protocol() {
+        let boot_services = boot_services!(
+            locate_handle_buffer = efi_locate_handle_buffer,
+            open_protocol = efi_open_protocol,
+            close_protocol = efi_close_protocol,
+            free_pool = efi_free_pool_use_box,
+            raise_tpl = efi_raise_tpl_noop,
+            restore_tpl = efi_restore_tpl_noop
+        );
+
+        static OPEN_COUNT: AtomicUsize = AtomicUsize::new(0);
+        static CLOSE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        extern "efiapi" fn efi_locate_handle_buffer(
+            search_type: efi::LocateSearchType,
+            protocol: *mut efi::Guid,
+            _search_key: *mut c_void,
+            nb_handles: *mut usize,
+            buffer: *mut *mut efi::Handle,
+        ) -> efi::Status {
+            assert_eq!(efi::BY_PROTOCOL, search_type);
+            assert_eq!(TEST_PROTOCOL_GUID, unsafe { ptr::read(protocol) });
+            let handles = vec![1_usize as efi::Handle, 2_usize as efi::Handle].into_boxed_slice();
+            unsafe {
+                ptr::write(nb_handles, handles.len());
+                ptr::write(buffer, Box::into_raw(handles) as *mut efi::Handle);
+            }
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_open_protocol(
+            handle: efi::Handle,
+            protocol: *mut efi::Guid,
+            interface: *mut *mut c_void,
+            agent_handle: efi::Handle,
+            controller_handle: efi::Handle,
+            attributes: u32,
+        ) -> efi::Status {
+            assert_eq!(TEST_PROTOCOL_GUID, unsafe { ptr::read(protocol) });
+            assert_eq!(3, agent_handle as usize);
+            assert_eq!(ptr::null_mut(), controller_handle);
+            assert_eq!(efi::OPEN_PROTOCOL_GET_PROTOCOL, attributes);
+            OPEN_COUNT.fetch_add(1, Ordering::Relaxed);
+
+            let b = Box::new(handle as u32 * 10);
+            unsafe { ptr::write(interface, Box::into_raw(b) as _) };
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_close_protocol(
+            _handle: efi::Handle,
+            protocol: *mut efi::Guid,
+            agent_handle: efi::Handle,
+            _controller_handle: efi::Handle,
+        ) -> efi::Status {
+            assert_eq!(TEST_PROTOCOL_GUID, unsafe { ptr::read(protocol) });
+            assert_eq!(3, agent_handle as usize);
+            CLOSE_COUNT.fetch_add(1, Ordering::Relaxed);
+            efi::Status::SUCCESS
+        }
+
+        let mut visited = Vec::new();
+        boot_services
+            .for_each_protocol(&TestProtocol, Some(3_usize as _), |handle, interface| {
+                visited.push((handle as usize, *interface));
+            })
+            .unwrap();
+
+        assert_eq!(vec![(1, 10), (2, 20)], visited);
+        assert_eq!(2, OPEN_COUNT.load(Ordering::Relaxed));
+        assert_eq!(2, CLOSE_COUNT.load(Ordering::Relaxed));
+    }
+
     #[test]
     #[should_panic = "Boot services function open_protocol_information is not initialized."]
     fn test_open_protocol_information_not_init() {
@@ -2929,7 +4124,35 @@ mod test {
             efi::Status::SUCCESS
         }
 
+        assert!(!boot_services_exited());
         _ = boot_services.exit_boot_services(1 as usize as _, 2).unwrap();
+        assert!(boot_services_exited());
+    }
+
+    // Every instance stops calling into its table, not only the one used to exit boot services.
+    fn exit_boot_services_and_allocate() -> Result<*mut u8, efi::Status> {
+        let boot_services = boot_services!(exit_boot_services = efi_exit_boot_services);
+
+        extern "efiapi" fn efi_exit_boot_services(_image_handle: efi::Handle, _map_key: usize) -> efi::Status {
+            efi::Status::SUCCESS
+        }
+
+        boot_services.exit_boot_services(ptr::null_mut(), 0).unwrap();
+        let other = boot_services!(allocate_pool = efi_allocate_pool_use_box, free_pool = efi_free_pool_use_box);
+        other.allocate_pool(MemoryType::BOOT_SERVICES_DATA, 8)
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "Boot services used after ExitBootServices."]
+    fn test_use_after_exit_boot_services_panics() {
+        _ = exit_boot_services_and_allocate();
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_use_after_exit_boot_services_fails() {
+        assert_eq!(Err(efi::Status::UNSUPPORTED), exit_boot_services_and_allocate());
     }
 
     #[test]
@@ -2979,6 +4202,102 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_get_memory_map_into() {
+        let boot_services = boot_services!(get_memory_map = efi_get_memory_map);
+
+        // Descriptors are usually bigger than the efi::MemoryDescriptor struct.
+        const DESCRIPTOR_SIZE: usize = mem::size_of::<efi::MemoryDescriptor>() + 8;
+
+        extern "efiapi" fn efi_get_memory_map(
+            memory_map_size: *mut usize,
+            memory_map: *mut efi::MemoryDescriptor,
+            map_key: *mut usize,
+            descriptor_size: *mut usize,
+            descriptor_version: *mut u32,
+        ) -> efi::Status {
+            unsafe {
+                *descriptor_size = DESCRIPTOR_SIZE;
+                if *memory_map_size < 2 * DESCRIPTOR_SIZE {
+                    *memory_map_size = 2 * DESCRIPTOR_SIZE;
+                    return efi::Status::BUFFER_TOO_SMALL;
+                }
+                *memory_map_size = 2 * DESCRIPTOR_SIZE;
+                for idx in 0..2 {
+                    let descriptor = efi::MemoryDescriptor {
+                        r#type: efi::CONVENTIONAL_MEMORY,
+                        physical_start: 0x1000 * (idx as u64 + 1),
+                        virtual_start: 0,
+                        number_of_pages: 1,
+                        attribute: efi::MEMORY_WB,
+                    };
+                    ptr::write_unaligned((memory_map as *mut u8).add(idx * DESCRIPTOR_SIZE) as *mut _, descriptor);
+                }
+                *map_key = 42;
+                *descriptor_version = 1;
+            }
+            efi::Status::SUCCESS
+        }
+
+        let mut buffer = [0_u8; DESCRIPTOR_SIZE];
+        assert_eq!(
+            Err((efi::Status::BUFFER_TOO_SMALL, 2 * DESCRIPTOR_SIZE)),
+            boot_services.get_memory_map_into(&mut buffer).map(|_| ())
+        );
+
+        let mut buffer = [0_u8; 4 * DESCRIPTOR_SIZE];
+        let memory_map = boot_services.get_memory_map_into(&mut buffer).unwrap();
+        assert_eq!(
+            (42, DESCRIPTOR_SIZE, 1),
+            (memory_map.map_key, memory_map.descriptor_size, memory_map.descriptor_version)
+        );
+        assert_eq!(2, memory_map.len());
+        assert_eq!(vec![0x1000, 0x2000], memory_map.descriptors().map(|d| d.physical_start).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_memory_map_is_current() {
+        let boot_services = boot_services!(get_memory_map = efi_get_memory_map);
+
+        // Current map key, and whether it is reported along BUFFER_TOO_SMALL.
+        static MAP_KEY: AtomicUsize = AtomicUsize::new(1);
+        static REPORT_KEY: AtomicBool = AtomicBool::new(true);
+
+        extern "efiapi" fn efi_get_memory_map(
+            memory_map_size: *mut usize,
+            _memory_map: *mut efi::MemoryDescriptor,
+            map_key: *mut usize,
+            descriptor_size: *mut usize,
+            descriptor_version: *mut u32,
+        ) -> efi::Status {
+            unsafe {
+                *descriptor_size = mem::size_of::<efi::MemoryDescriptor>();
+                *descriptor_version = 1;
+                let status = match *memory_map_size {
+                    0 => efi::Status::BUFFER_TOO_SMALL,
+                    _ => efi::Status::SUCCESS,
+                };
+                *memory_map_size = 0;
+                if status == efi::Status::SUCCESS || REPORT_KEY.load(Ordering::SeqCst) {
+                    *map_key = MAP_KEY.load(Ordering::SeqCst);
+                }
+                status
+            }
+        }
+
+        let mut buffer = [0_u8; mem::size_of::<efi::MemoryDescriptor>()];
+        let memory_map = boot_services.get_memory_map_into(&mut buffer).unwrap();
+        assert!(memory_map.is_current(boot_services));
+
+        MAP_KEY.store(2, Ordering::SeqCst);
+        assert!(!memory_map.is_current(boot_services));
+
+        // Firmware not reporting the key is never considered current.
+        let memory_map = boot_services.get_memory_map_into(&mut buffer).unwrap();
+        REPORT_KEY.store(false, Ordering::SeqCst);
+        assert!(!memory_map.is_current(boot_services));
+    }
+
     #[test]
     #[should_panic = "Boot services function set_watchdog_timer is not initialized."]
     fn test_set_watchdog_timer_not_init() {
@@ -3024,6 +4343,31 @@ mod test {
         assert_eq!(Ok(()), status);
     }
 
+    #[test]
+    fn test_stall_duration() {
+        let boot_services = boot_services!(stall = efi_stall);
+        static STALLS: AtomicUsize = AtomicUsize::new(0);
+        static MICROSECONDS: AtomicU64 = AtomicU64::new(0);
+        extern "efiapi" fn efi_stall(microsecondes: usize) -> efi::Status {
+            assert!(microsecondes <= u32::MAX as usize);
+            STALLS.fetch_add(1, Ordering::Relaxed);
+            MICROSECONDS.fetch_add(microsecondes as u64, Ordering::Relaxed);
+            efi::Status::SUCCESS
+        }
+
+        for (duration, stalls, microseconds) in [
+            (Duration::ZERO, 0, 0),
+            (Duration::from_nanos(1), 1, 1),
+            (Duration::from_nanos(10_001), 1, 11),
+            (Duration::from_micros(u32::MAX as u64 * 2 + 1), 3, u32::MAX as u64 * 2 + 1),
+        ] {
+            STALLS.store(0, Ordering::Relaxed);
+            MICROSECONDS.store(0, Ordering::Relaxed);
+            assert_eq!(Ok(()), boot_services.stall_duration(duration));
+            assert_eq!((stalls, microseconds), (STALLS.load(Ordering::Relaxed), MICROSECONDS.load(Ordering::Relaxed)));
+        }
+    }
+
     #[test]
     #[should_panic = "Boot services function copy_mem is not initialized."]
     fn test_copy_mem_not_init() {
@@ -3117,10 +4461,30 @@ mod test {
     }
 
     #[test]
-    #[should_panic = "Boot services function calculate_crc32 is not initialized."]
-    fn test_calculate_crc32_not_init() {
+    fn test_calculate_crc32_not_init_use_software_fallback() {
         let boot_services = boot_services!();
-        _ = boot_services.calculate_crc_32(&[0]);
+        assert_eq!(Ok(0xCBF4_3926), boot_services.calculate_crc_32(b"123456789"));
+    }
+
+    #[test]
+    fn test_calculate_crc32_after_exit_boot_services_use_software_fallback() {
+        let boot_services =
+            boot_services!(exit_boot_services = efi_exit_boot_services, calculate_crc32 = efi_calculate_crc32);
+
+        extern "efiapi" fn efi_exit_boot_services(_image_handle: efi::Handle, _map_key: usize) -> efi::Status {
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn efi_calculate_crc32(
+            _buffer_ptr: *mut c_void,
+            _buffer_size: usize,
+            _crc: *mut u32,
+        ) -> efi::Status {
+            panic!("Boot services should not be used after exit boot services.")
+        }
+
+        boot_services.exit_boot_services(ptr::null_mut(), 0).unwrap();
+        assert_eq!(Ok(0xCBF4_3926), boot_services.calculate_crc_32(b"123456789"));
     }
 
     #[test]
@@ -11,6 +11,8 @@ use crate::{allocation::MemoryType, BootServices};
 pub struct BootServicesBox<'a, T: ?Sized, B: BootServices + ?Sized> {
     ptr: *mut T,
     boot_services: &'a B,
+    // Alignment of the allocation when allocated with `allocate_pool_aligned`.
+    align: Option<usize>,
 }
 
 impl<'a, T, B: BootServices> BootServicesBox<'a, T, B> {
@@ -18,19 +20,42 @@ impl<'a, T, B: BootServices> BootServicesBox<'a, T, B> {
         let size = mem::size_of_val(&value);
         let ptr = boot_services.allocate_pool(memory_type, size).unwrap() as *mut T;
         unsafe { ptr::write(ptr, value) };
-        Self { boot_services, ptr }
+        Self { boot_services, ptr, align: None }
+    }
+
+    /// Allocate a box from pool with at least the alignment *align*, or the alignment of `T` if bigger.
+    pub fn new_aligned(value: T, memory_type: MemoryType, align: usize, boot_services: &'a B) -> Self {
+        let size = mem::size_of_val(&value);
+        let align = align.max(mem::align_of::<T>());
+        let ptr = boot_services.allocate_pool_aligned(memory_type, size, align).unwrap() as *mut T;
+        unsafe { ptr::write(ptr, value) };
+        Self { boot_services, ptr, align: Some(align) }
     }
 
     pub unsafe fn from_raw(ptr: *mut T, boot_services: &'a B) -> Self {
-        Self { boot_services, ptr }
+        Self { boot_services, ptr, align: None }
     }
 
+    /// Returns the alignment the box was allocated with, if allocated with [`Self::new_aligned`].
+    pub fn align(&self) -> Option<usize> {
+        self.align
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the box was allocated with [`Self::new_aligned`], as [`Self::from_raw`] could not free its pointer.
     pub unsafe fn into_raw(self) -> *const T {
-        self.ptr as *const T
+        self.into_raw_mut() as *const T
     }
 
+    /// # Panics
+    ///
+    /// Panics if the box was allocated with [`Self::new_aligned`], as [`Self::from_raw`] could not free its pointer.
     pub unsafe fn into_raw_mut(self) -> *mut T {
-        self.ptr
+        assert!(self.align.is_none(), "An aligned box can not be converted to a raw pointer.");
+        let ptr = self.ptr;
+        mem::forget(self);
+        ptr
     }
 
     pub fn leak(self) -> &'a mut T {
@@ -43,13 +68,16 @@ impl<'a, T, B: BootServices> BootServicesBox<'a, T, B> {
 impl<'a, T, B: BootServices> BootServicesBox<'a, [T], B> {
     pub unsafe fn from_raw_parts_mut(ptr: *mut T, len: usize, boot_services: &'a B) -> Self {
         let ptr = slice::from_raw_parts_mut(ptr, len) as *mut [T];
-        Self { boot_services, ptr }
+        Self { boot_services, ptr, align: None }
     }
 }
 
 impl<T: ?Sized, B: BootServices + ?Sized> Drop for BootServicesBox<'_, T, B> {
     fn drop(&mut self) {
-        let _ = self.boot_services.free_pool(self.ptr as *mut u8);
+        let _ = match self.align {
+            Some(_) => unsafe { self.boot_services.free_pool_aligned(self.ptr as *mut u8) },
+            None => self.boot_services.free_pool(self.ptr as *mut u8),
+        };
     }
 }
 
@@ -14,6 +14,12 @@ pub struct PtrMetadata<'a, T> {
     _container: PhantomData<&'a T>,
 }
 
+impl<'a, T> PtrMetadata<'a, T> {
+    pub(crate) const fn from_ptr_value(ptr_value: usize) -> Self {
+        Self { ptr_value, _container: PhantomData }
+    }
+}
+
 impl<'a, R: CPtr<'a, Type = T>, T> PtrMetadata<'a, R> {
     pub unsafe fn into_original_ptr(self) -> R {
         mem::transmute_copy(&self.ptr_value)
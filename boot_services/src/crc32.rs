@@ -0,0 +1,46 @@
+//! Software implementation of the CRC32 used by UEFI.
+//!
+//! This is the same CRC as the one returned by `EFI_BOOT_SERVICES.CalculateCrc32()` (IEEE 802.3, reflected polynomial
+//! `0xEDB88320`). It is used by [`StandardBootServices`](crate::StandardBootServices) when the boot services are no
+//! longer available, so table header checksums (GPT, ACPI, ...) can still be computed at runtime.
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < table.len() {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes the 32-bit CRC of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_chunks([data])
+}
+
+/// Computes the 32-bit CRC of the concatenation of `chunks`, without copying them.
+pub fn crc32_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> u32 {
+    !chunks.into_iter().flatten().fold(!0, |crc, &byte| TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0, crc32(&[]));
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+        assert_eq!(0x414F_A339, crc32(b"The quick brown fox jumps over the lazy dog"));
+        assert_eq!(0xCBF4_3926, crc32_chunks([&b"1234"[..], b"", b"56789"]));
+    }
+}
@@ -0,0 +1,230 @@
+//! Work queued by event notify functions and run later at [`Tpl::APPLICATION`].
+//!
+//! Notify functions run at [`Tpl::CALLBACK`] or [`Tpl::NOTIFY`] and should return quickly. A [`DeferredWorkQueue`] lets
+//! them queue the heavy work, closures or typed messages, for the main loop of the application to drain. The queue can
+//! be a static, and can signal a wake up event the main loop waits for.
+//!
+//! ```ignore
+//! static WORK: DeferredWorkQueue = DeferredWorkQueue::new(16);
+//!
+//! extern "efiapi" fn on_media_change(_event: efi::Event, handle: Box<efi::Handle>) {
+//!     let _ = WORK.defer(&BOOT_SERVICES, move || rescan_partitions(*handle));
+//! }
+//!
+//! WORK.set_wakeup_event(wakeup_event);
+//! loop {
+//!     BOOT_SERVICES.wait_for_event(&mut [wakeup_event])?;
+//!     WORK.run(&BOOT_SERVICES);
+//! }
+//! ```
+//!
+//! Notify functions can not run at [`Tpl::APPLICATION`], so the queue is drained by the code running at that TPL, not
+//! from another event.
+
+use alloc::{boxed::Box, collections::VecDeque};
+use core::{
+    fmt,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::{
+    tpl::{Tpl, TplLock},
+    BootServices,
+};
+
+/// A work item of a [`DeferredWorkQueue`] running closures.
+pub type DeferredWork = Box<dyn FnOnce() + Send>;
+
+/// A bounded FIFO queue of work items *T*, enqueued at up to [`Tpl::NOTIFY`] and drained at [`Tpl::APPLICATION`].
+///
+/// The queue is protected by raising the TPL to [`Tpl::NOTIFY`], so the main loop is not preempted by the notify
+/// functions while it takes an item. Items are run without the queue locked, so they can enqueue more work.
+pub struct DeferredWorkQueue<T = DeferredWork> {
+    items: TplLock<VecDeque<T>>,
+    capacity: usize,
+    wakeup_event: AtomicPtr<core::ffi::c_void>,
+}
+
+impl<T> DeferredWorkQueue<T> {
+    /// Create an empty queue holding at most *capacity* items.
+    ///
+    /// The storage of the items is allocated by the first enqueue, which like any allocation must be at
+    /// [`Tpl::NOTIFY`] or below.
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            items: TplLock::new(Tpl::NOTIFY, VecDeque::new()),
+            capacity,
+            wakeup_event: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Signals *event* each time an item is enqueued, so a main loop waiting for it wakes up.
+    pub fn set_wakeup_event(&self, event: efi::Event) {
+        self.wakeup_event.store(event, Ordering::SeqCst);
+    }
+
+    // Runs *f* with the queue locked at Tpl::NOTIFY, None if the lock is held by a call it preempted.
+    fn with_items<B, F, R>(&self, boot_services: &B, f: F) -> Option<R>
+    where
+        B: BootServices,
+        F: FnOnce(&mut VecDeque<T>) -> R,
+    {
+        self.items.try_with(boot_services, f)
+    }
+
+    /// Queues *item* and signals the wake up event, if any.
+    ///
+    /// Returns the item if the queue is full, or if the queue is locked because the call preempted another call on the
+    /// queue, which only happens when called at a TPL above [`Tpl::NOTIFY`].
+    pub fn enqueue<B: BootServices>(&self, boot_services: &B, item: T) -> Result<(), T> {
+        let mut item = Some(item);
+        self.with_items(boot_services, |items| {
+            if items.len() < self.capacity {
+                items.extend(item.take());
+            }
+        });
+        // The item is left if the queue is full or locked.
+        if let Some(item) = item {
+            return Err(item);
+        }
+        let event = self.wakeup_event.load(Ordering::SeqCst);
+        if !event.is_null() {
+            // Signaling an event only fails for an invalid event, the item is queued anyway.
+            let _ = boot_services.signal_event(event);
+        }
+        Ok(())
+    }
+
+    /// Takes the items queued when called, oldest first, and passes them to *handler*. Returns the number of items
+    /// handled.
+    ///
+    /// The items enqueued by *handler* are left for the next drain, so an item enqueuing itself does not loop forever.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if called above [`Tpl::APPLICATION`].
+    #[track_caller]
+    pub fn drain<B, F>(&self, boot_services: &B, mut handler: F) -> usize
+    where
+        B: BootServices,
+        F: FnMut(T),
+    {
+        crate::tpl::debug_assert_tpl_at_most(boot_services, Tpl::APPLICATION);
+        let count = self.len(boot_services);
+        for handled in 0..count {
+            match self.with_items(boot_services, |items| items.pop_front()).flatten() {
+                Some(item) => handler(item),
+                None => return handled,
+            }
+        }
+        count
+    }
+
+    /// Returns the number of items in the queue, 0 if it is locked by a call it preempted.
+    pub fn len<B: BootServices>(&self, boot_services: &B) -> usize {
+        self.with_items(boot_services, |items| items.len()).unwrap_or(0)
+    }
+
+    /// Returns true if the queue has no items.
+    pub fn is_empty<B: BootServices>(&self, boot_services: &B) -> bool {
+        self.len(boot_services) == 0
+    }
+
+    /// Returns the maximum number of items in the queue.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl DeferredWorkQueue<DeferredWork> {
+    /// Queues *work* to run at the next [`Self::run`], see [`Self::enqueue`] for the errors.
+    pub fn defer<B, F>(&self, boot_services: &B, work: F) -> Result<(), DeferredWork>
+    where
+        B: BootServices,
+        F: FnOnce() + Send + 'static,
+    {
+        self.enqueue(boot_services, Box::new(work))
+    }
+
+    /// Runs the work queued when called, see [`Self::drain`]. Returns the number of work items run.
+    #[track_caller]
+    pub fn run<B: BootServices>(&self, boot_services: &B) -> usize {
+        self.drain(boot_services, |work| work())
+    }
+}
+
+impl<T> fmt::Debug for DeferredWorkQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeferredWorkQueue")
+            .field("capacity", &self.capacity)
+            .field("wakeup_event", &self.wakeup_event.load(Ordering::SeqCst))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use alloc::{sync::Arc, vec::Vec};
+    use core::sync::atomic::AtomicUsize;
+
+    fn boot_services(tpl: Tpl) -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(tpl);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services
+    }
+
+    #[test]
+    fn test_messages_are_drained_in_order() {
+        let mut boot_services = boot_services(Tpl::APPLICATION);
+        boot_services.expect_signal_event().withf(|event| *event as usize == 0x10).times(3).returning(|_| Ok(()));
+        let queue = DeferredWorkQueue::new(3);
+        queue.set_wakeup_event(0x10 as efi::Event);
+
+        for message in 1..=3 {
+            assert_eq!(Ok(()), queue.enqueue(&boot_services, message));
+        }
+        assert_eq!(Err(4), queue.enqueue(&boot_services, 4));
+        assert_eq!(3, queue.len(&boot_services));
+
+        let mut messages = Vec::new();
+        assert_eq!(3, queue.drain(&boot_services, |message| messages.push(message)));
+        assert_eq!(vec![1, 2, 3], messages);
+        assert!(queue.is_empty(&boot_services));
+    }
+
+    #[test]
+    fn test_deferred_work_runs_once() {
+        static WORK: DeferredWorkQueue = DeferredWorkQueue::new(4);
+        let boot_services = Arc::new(boot_services(Tpl::APPLICATION));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let (bs, counter) = (boot_services.clone(), runs.clone());
+        WORK.defer(&*boot_services, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            // Work enqueued by a work item runs at the next drain.
+            let counter = counter.clone();
+            let _ = WORK.defer(&*bs, move || _ = counter.fetch_add(10, Ordering::SeqCst));
+        })
+        .ok()
+        .expect("The queue is empty.");
+
+        assert_eq!(1, WORK.run(&*boot_services));
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+        assert_eq!(1, WORK.run(&*boot_services));
+        assert_eq!(11, runs.load(Ordering::SeqCst));
+        assert_eq!(0, WORK.run(&*boot_services));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Current TPL 8 is above the expected maximum 4.")]
+    fn test_drain_above_application_panics() {
+        let queue = DeferredWorkQueue::<u32>::new(1);
+        queue.drain(&boot_services(Tpl::CALLBACK), |_| ());
+    }
+}
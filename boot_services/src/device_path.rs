@@ -0,0 +1,584 @@
+//! This module defines [`DevicePath`], an owned device path with conversions from and to its text representation.
+//!
+//! The conversions and [`DevicePath::append_path`] use the EFI_DEVICE_PATH_FROM_TEXT_PROTOCOL,
+//! EFI_DEVICE_PATH_TO_TEXT_PROTOCOL and EFI_DEVICE_PATH_UTILITIES_PROTOCOL when the firmware produces them, and fall
+//! back to a pure Rust implementation otherwise, e.g. before the protocols are installed or in host tools.
+//!
+//! The pure Rust implementation knows the `Pci`, `PciRoot`, `PcieRoot`, `Fv`, `FvFile` and file path nodes, the other
+//! nodes are represented with the generic `Path(Type,SubType,Data)` forms of the specification.
+//!
+//! ```ignore
+//! let disk = DevicePath::from_text(&BOOT_SERVICES, "PciRoot(0x0)/Pci(0x1f,0x2)")?;
+//! let loader = disk.append_path(&BOOT_SERVICES, &DevicePath::from_text(&BOOT_SERVICES, r"\EFI\BOOT\BOOTX64.EFI")?)?;
+//! log::info!("Loading {}", loader.to_text(&BOOT_SERVICES)?);
+//! ```
+//!
+//! [UEFI Spec Documentation: 10.6. EFI Device Path Display Format Overview](https://uefi.org/specs/UEFI/2.10/10_Protocols_Device_Path_Protocol.html#efi-device-path-display-format-overview)
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, iter, mem, slice};
+
+use r_efi::efi::{
+    self,
+    protocols::device_path::{self, End, Hardware, Media},
+};
+
+use crate::{
+    protocol_handler::{DevicePathFromText, DevicePathToText, DevicePathUtilities},
+    BootServices,
+};
+
+pub(crate) const DEVICE_PATH_NODE_HEADER_SIZE: usize = mem::size_of::<device_path::Protocol>();
+const END_INSTANCE_NODE: [u8; 4] = [device_path::TYPE_END, End::SUBTYPE_INSTANCE, 4, 0];
+const END_ENTIRE_NODE: [u8; 4] = [device_path::TYPE_END, End::SUBTYPE_ENTIRE, 4, 0];
+
+const ACPI_SUBTYPE_ACPI: u8 = 0x01;
+// EISA ids of the PNP0A03 and PNP0A08 PCI and PCI Express root bridges.
+const PCI_ROOT_HID: u32 = 0x0A0341D0;
+const PCIE_ROOT_HID: u32 = 0x0A0841D0;
+
+/// Returns the size of the device path at the start of *data*, None if its nodes are not all within *data*.
+pub(crate) fn device_path_size(data: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(node) = data.get(offset..offset + DEVICE_PATH_NODE_HEADER_SIZE) {
+        let length = u16::from_le_bytes([node[2], node[3]]) as usize;
+        if length < DEVICE_PATH_NODE_HEADER_SIZE || offset + length > data.len() {
+            return None;
+        }
+        offset += length;
+        if node[0] == device_path::TYPE_END && node[1] == End::SUBTYPE_ENTIRE {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// An owned, well formed device path, made of one or more instances.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevicePath {
+    data: Vec<u8>,
+}
+
+impl DevicePath {
+    /// Create a device path from its serialized nodes, including the end node.
+    ///
+    /// Returns [`efi::Status::INVALID_PARAMETER`] if *data* is not a single well formed device path.
+    pub fn new(data: &[u8]) -> Result<Self, efi::Status> {
+        match device_path_size(data) {
+            Some(size) if size == data.len() => Ok(Self { data: data.to_vec() }),
+            _ => Err(efi::Status::INVALID_PARAMETER),
+        }
+    }
+
+    /// Create a device path from a copy of the device path at *device_path*.
+    ///
+    /// # Safety
+    ///
+    /// *device_path* must be null or point to a device path whose nodes are all readable.
+    pub unsafe fn from_ptr(device_path: *const device_path::Protocol) -> Result<Self, efi::Status> {
+        if device_path.is_null() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let start = device_path as *const u8;
+        let mut size = 0;
+        loop {
+            let node = slice::from_raw_parts(start.add(size), DEVICE_PATH_NODE_HEADER_SIZE);
+            let length = u16::from_le_bytes([node[2], node[3]]) as usize;
+            if length < DEVICE_PATH_NODE_HEADER_SIZE {
+                return Err(efi::Status::INVALID_PARAMETER);
+            }
+            size += length;
+            if node[0] == device_path::TYPE_END && node[1] == End::SUBTYPE_ENTIRE {
+                return Self::new(slice::from_raw_parts(start, size));
+            }
+        }
+    }
+
+    /// Parses the text representation of a device path with the pure Rust implementation.
+    ///
+    /// Returns [`efi::Status::UNSUPPORTED`] for nodes the implementation does not know and
+    /// [`efi::Status::INVALID_PARAMETER`] if the text is malformed.
+    pub fn parse_text(text: &str) -> Result<Self, efi::Status> {
+        if text.contains('\0') {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let mut data = Vec::new();
+        let mut depth = 0_usize;
+        let mut start = 0;
+        // Nodes are separated by '/' and instances by ',', outside of the parentheses of the node arguments.
+        for (index, c) in text.char_indices().chain(iter::once((text.len(), '\0'))) {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.checked_sub(1).ok_or(efi::Status::INVALID_PARAMETER)?,
+                '/' | ',' | '\0' if depth == 0 => {
+                    let node = text[start..index].trim();
+                    if !node.is_empty() {
+                        parse_node(node, &mut data)?;
+                    }
+                    if c == ',' {
+                        data.extend_from_slice(&END_INSTANCE_NODE);
+                    }
+                    start = index + 1;
+                }
+                _ => (),
+            }
+        }
+        if depth != 0 {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        data.extend_from_slice(&END_ENTIRE_NODE);
+        Self::new(&data)
+    }
+
+    /// Parses the text representation of a device path.
+    ///
+    /// Uses the EFI_DEVICE_PATH_FROM_TEXT_PROTOCOL if the firmware produces it, [`DevicePath::parse_text`] otherwise.
+    pub fn from_text<B: BootServices>(boot_services: &B, text: &str) -> Result<Self, efi::Status> {
+        // SAFETY: The interface is only read.
+        let Ok(protocol) = (unsafe { boot_services.locate_protocol(&DevicePathFromText, None) }) else {
+            return Self::parse_text(text);
+        };
+        if text.contains('\0') {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let text = text.encode_utf16().chain([0]).collect::<Vec<_>>();
+        let device_path = (protocol.convert_text_to_device_path)(text.as_ptr());
+        // SAFETY: The protocol returns a device path allocated from pool, or null if the text is not valid.
+        unsafe { take_device_path(boot_services, device_path, efi::Status::INVALID_PARAMETER) }
+    }
+
+    /// Returns the text representation of the device path.
+    ///
+    /// Uses the EFI_DEVICE_PATH_TO_TEXT_PROTOCOL if the firmware produces it, the [`fmt::Display`] implementation of
+    /// the device path otherwise. Shortcut forms and display only forms are not used, so the text parses back to the
+    /// same device path.
+    pub fn to_text<B: BootServices>(&self, boot_services: &B) -> Result<String, efi::Status> {
+        // SAFETY: The interface is only read.
+        let Ok(protocol) = (unsafe { boot_services.locate_protocol(&DevicePathToText, None) }) else {
+            return Ok(self.to_string());
+        };
+        let text = (protocol.convert_device_path_to_text)(self.as_ptr(), efi::Boolean::FALSE, efi::Boolean::FALSE);
+        if text.is_null() {
+            return Err(efi::Status::OUT_OF_RESOURCES);
+        }
+        // SAFETY: The protocol returns a null-terminated string allocated from pool.
+        let result = unsafe {
+            let length = (0..).take_while(|&i| *text.add(i) != 0).count();
+            String::from_utf16_lossy(slice::from_raw_parts(text, length))
+        };
+        let _ = boot_services.free_pool(text as *mut u8);
+        Ok(result)
+    }
+
+    /// Returns a new device path made of this device path followed by *other*.
+    ///
+    /// Uses the EFI_DEVICE_PATH_UTILITIES_PROTOCOL if the firmware produces it, [`DevicePath::append`] otherwise.
+    pub fn append_path<B: BootServices>(&self, boot_services: &B, other: &DevicePath) -> Result<Self, efi::Status> {
+        // SAFETY: The interface is only read.
+        let Ok(protocol) = (unsafe { boot_services.locate_protocol(&DevicePathUtilities, None) }) else {
+            return Ok(self.append(other));
+        };
+        let device_path = (protocol.append_device_path)(self.as_ptr(), other.as_ptr());
+        // SAFETY: The protocol returns a device path allocated from pool, or null if it could not allocate it.
+        unsafe { take_device_path(boot_services, device_path, efi::Status::OUT_OF_RESOURCES) }
+    }
+
+    /// Returns a new device path made of this device path followed by *other*, with the pure Rust implementation.
+    ///
+    /// The nodes of *other* follow the last node of this device path, i.e. they are only appended to its last
+    /// instance.
+    pub fn append(&self, other: &DevicePath) -> Self {
+        let size = self.nodes().map(<[u8]>::len).sum();
+        let mut data = self.data[..size].to_vec();
+        data.extend_from_slice(&other.data);
+        Self { data }
+    }
+
+    /// Returns the serialized nodes of the device path, including the end node.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns a pointer to the device path, for the boot services and protocols taking a device path.
+    ///
+    /// The device path is only read by the boot services, the pointer must not be used to modify it.
+    pub fn as_ptr(&self) -> *mut device_path::Protocol {
+        self.data.as_ptr() as *mut device_path::Protocol
+    }
+
+    // Returns the nodes of the device path before its end node.
+    fn nodes(&self) -> impl Iterator<Item = &[u8]> {
+        let mut rest = self.data.as_slice();
+        iter::from_fn(move || {
+            let (node, tail) = rest.split_at(u16::from_le_bytes([rest[2], rest[3]]) as usize);
+            rest = tail;
+            Some(node)
+        })
+        .take_while(|node| node[..2] != [device_path::TYPE_END, End::SUBTYPE_ENTIRE])
+    }
+}
+
+// Copies the device path returned by a protocol and frees it, *null_status* is returned if it is null.
+unsafe fn take_device_path<B: BootServices>(
+    boot_services: &B,
+    device_path: *mut device_path::Protocol,
+    null_status: efi::Status,
+) -> Result<DevicePath, efi::Status> {
+    if device_path.is_null() {
+        return Err(null_status);
+    }
+    let result = DevicePath::from_ptr(device_path);
+    let _ = boot_services.free_pool(device_path as *mut u8);
+    result
+}
+
+fn push_node(data: &mut Vec<u8>, r#type: u8, sub_type: u8, payload: &[u8]) -> Result<(), efi::Status> {
+    let length =
+        u16::try_from(DEVICE_PATH_NODE_HEADER_SIZE + payload.len()).map_err(|_| efi::Status::INVALID_PARAMETER)?;
+    data.extend_from_slice(&[r#type, sub_type]);
+    data.extend_from_slice(&length.to_le_bytes());
+    data.extend_from_slice(payload);
+    Ok(())
+}
+
+fn parse_node(text: &str, data: &mut Vec<u8>) -> Result<(), efi::Status> {
+    let Some((name, arguments)) = text.strip_suffix(')').and_then(|text| text.split_once('(')) else {
+        return push_file_path(text, data);
+    };
+    let arguments = arguments.split(',').map(str::trim).collect::<Vec<_>>();
+    match (name, arguments.as_slice()) {
+        ("Pci", [device, function]) => {
+            push_node(data, device_path::TYPE_HARDWARE, Hardware::SUBTYPE_PCI, &[number(function)?, number(device)?])
+        }
+        ("PciRoot", [uid]) => push_acpi_node(data, PCI_ROOT_HID, number(uid)?),
+        ("PcieRoot", [uid]) => push_acpi_node(data, PCIE_ROOT_HID, number(uid)?),
+        ("Fv", [guid]) => {
+            push_node(data, device_path::TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_VOLUME, &parse_guid(guid)?)
+        }
+        ("FvFile", [guid]) => {
+            push_node(data, device_path::TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE, &parse_guid(guid)?)
+        }
+        ("Path", [r#type, sub_type, payload @ ..]) => push_generic_node(data, number(r#type)?, sub_type, payload),
+        (name, [sub_type, payload @ ..]) if generic_node_type(name).is_some() => {
+            push_generic_node(data, generic_node_type(name).unwrap(), sub_type, payload)
+        }
+        ("Pci" | "PciRoot" | "PcieRoot" | "Fv" | "FvFile" | "Path", _) => Err(efi::Status::INVALID_PARAMETER),
+        // Node names are identifiers, anything else is a file name with parentheses.
+        (name, _)
+            if name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            Err(efi::Status::UNSUPPORTED)
+        }
+        _ => push_file_path(text, data),
+    }
+}
+
+fn push_acpi_node(data: &mut Vec<u8>, hid: u32, uid: u32) -> Result<(), efi::Status> {
+    push_node(data, device_path::TYPE_ACPI, ACPI_SUBTYPE_ACPI, &[hid.to_le_bytes(), uid.to_le_bytes()].concat())
+}
+
+fn push_file_path(text: &str, data: &mut Vec<u8>) -> Result<(), efi::Status> {
+    let path = text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect::<Vec<_>>();
+    push_node(data, device_path::TYPE_MEDIA, Media::SUBTYPE_FILE_PATH, &path)
+}
+
+fn push_generic_node(data: &mut Vec<u8>, r#type: u8, sub_type: &str, payload: &[&str]) -> Result<(), efi::Status> {
+    let payload = match payload {
+        [] => Vec::new(),
+        [hex] if hex.len() % 2 == 0 && hex.is_ascii() => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| efi::Status::INVALID_PARAMETER))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(efi::Status::INVALID_PARAMETER),
+    };
+    push_node(data, r#type, number(sub_type)?, &payload)
+}
+
+// Returns the type of the generic node form *name*, e.g. `HardwarePath(SubType,Data)`.
+fn generic_node_type(name: &str) -> Option<u8> {
+    match name {
+        "HardwarePath" => Some(device_path::TYPE_HARDWARE),
+        "AcpiPath" => Some(device_path::TYPE_ACPI),
+        "Msg" => Some(device_path::TYPE_MESSAGING),
+        "MediaPath" => Some(device_path::TYPE_MEDIA),
+        "BbsPath" => Some(device_path::TYPE_BIOS),
+        _ => None,
+    }
+}
+
+// Parses a decimal number, or a hexadecimal number with a `0x` prefix.
+fn number<T: TryFrom<u64>>(text: &str) -> Result<T, efi::Status> {
+    let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    value.ok().and_then(|value| T::try_from(value).ok()).ok_or(efi::Status::INVALID_PARAMETER)
+}
+
+// Parses a GUID in the registry format, e.g. `7cb8bdc9-f8eb-4f34-aaea-3ee4af6516a1`.
+fn parse_guid(text: &str) -> Result<[u8; 16], efi::Status> {
+    let fields = text.split('-').collect::<Vec<_>>();
+    let [time_low, time_mid, time_hi, clk, node] = fields.as_slice() else {
+        return Err(efi::Status::INVALID_PARAMETER);
+    };
+    if [time_low.len(), time_mid.len(), time_hi.len(), clk.len(), node.len()] != [8, 4, 4, 4, 12] || !text.is_ascii() {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+    let hex = |text: &str| u64::from_str_radix(text, 16).map_err(|_| efi::Status::INVALID_PARAMETER);
+    let clk = (hex(clk)? as u16).to_be_bytes();
+    let node = hex(node)?.to_be_bytes();
+    let node = node[2..].try_into().unwrap();
+    Ok(*efi::Guid::from_fields(
+        hex(time_low)? as u32,
+        hex(time_mid)? as u16,
+        hex(time_hi)? as u16,
+        clk[0],
+        clk[1],
+        node,
+    )
+    .as_bytes())
+}
+
+fn write_guid(f: &mut fmt::Formatter<'_>, guid: &[u8]) -> fmt::Result {
+    let guid = efi::Guid::from_bytes(guid.try_into().unwrap());
+    let (time_low, time_mid, time_hi, clk_hi, clk_low, node) = guid.as_fields();
+    write!(f, "{time_low:08x}-{time_mid:04x}-{time_hi:04x}-{clk_hi:02x}{clk_low:02x}-")?;
+    node.iter().try_for_each(|b| write!(f, "{b:02x}"))
+}
+
+fn write_node(f: &mut fmt::Formatter<'_>, node: &[u8]) -> fmt::Result {
+    let (r#type, sub_type, payload) = (node[0], node[1], &node[DEVICE_PATH_NODE_HEADER_SIZE..]);
+    let u32_at = |offset: usize| u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap());
+    match (r#type, sub_type, payload.len()) {
+        (device_path::TYPE_HARDWARE, Hardware::SUBTYPE_PCI, 2) => {
+            return write!(f, "Pci({:#x},{:#x})", payload[1], payload[0]);
+        }
+        (device_path::TYPE_ACPI, ACPI_SUBTYPE_ACPI, 8) if u32_at(0) == PCI_ROOT_HID => {
+            return write!(f, "PciRoot({:#x})", u32_at(4));
+        }
+        (device_path::TYPE_ACPI, ACPI_SUBTYPE_ACPI, 8) if u32_at(0) == PCIE_ROOT_HID => {
+            return write!(f, "PcieRoot({:#x})", u32_at(4));
+        }
+        (device_path::TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_VOLUME, 16) => {
+            f.write_str("Fv(")?;
+            write_guid(f, payload)?;
+            return f.write_str(")");
+        }
+        (device_path::TYPE_MEDIA, Media::SUBTYPE_PIWG_FIRMWARE_FILE, 16) => {
+            f.write_str("FvFile(")?;
+            write_guid(f, payload)?;
+            return f.write_str(")");
+        }
+        (device_path::TYPE_MEDIA, Media::SUBTYPE_FILE_PATH, _) => {
+            let path = payload.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>();
+            // Paths that would not parse back to the same node use the generic form.
+            if let Some((&0, path)) = path.split_last().filter(|_| payload.len() % 2 == 0) {
+                if let Ok(path) = String::from_utf16(path) {
+                    if DevicePath::parse_text(&path).is_ok_and(|parsed| parsed.nodes().eq([node])) {
+                        return f.write_str(&path);
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+    match r#type {
+        device_path::TYPE_HARDWARE => write!(f, "HardwarePath({sub_type}")?,
+        device_path::TYPE_ACPI => write!(f, "AcpiPath({sub_type}")?,
+        device_path::TYPE_MESSAGING => write!(f, "Msg({sub_type}")?,
+        device_path::TYPE_MEDIA => write!(f, "MediaPath({sub_type}")?,
+        device_path::TYPE_BIOS => write!(f, "BbsPath({sub_type}")?,
+        _ => write!(f, "Path({type},{sub_type}")?,
+    }
+    if !payload.is_empty() {
+        f.write_str(",")?;
+        payload.iter().try_for_each(|b| write!(f, "{b:02x}"))?;
+    }
+    f.write_str(")")
+}
+
+/// Writes the text representation of the device path with the pure Rust implementation.
+impl fmt::Display for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for node in self.nodes() {
+            if node[0] == device_path::TYPE_END {
+                separator = ",";
+                continue;
+            }
+            f.write_str(separator)?;
+            write_node(f, node)?;
+            separator = "/";
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use alloc::{boxed::Box, vec};
+    use efi::protocols::{device_path_from_text, device_path_to_text, device_path_utilities};
+
+    const BOOT_LOADER: &str = r"PciRoot(0x0)/Pci(0x1f,0x2)/\EFI\BOOT\BOOTX64.EFI";
+
+    #[test]
+    fn test_parse_text() {
+        let device_path = DevicePath::parse_text(BOOT_LOADER).unwrap();
+        let mut expected = vec![device_path::TYPE_ACPI, ACPI_SUBTYPE_ACPI, 12, 0, 0xD0, 0x41, 0x03, 0x0A, 0, 0, 0, 0];
+        expected.extend_from_slice(&[device_path::TYPE_HARDWARE, Hardware::SUBTYPE_PCI, 6, 0, 2, 0x1f]);
+        expected.extend_from_slice(&[device_path::TYPE_MEDIA, Media::SUBTYPE_FILE_PATH, 48, 0]);
+        expected.extend(r"\EFI\BOOT\BOOTX64.EFI".encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+        expected.extend_from_slice(&END_ENTIRE_NODE);
+        assert_eq!(expected, device_path.as_bytes());
+        assert_eq!(BOOT_LOADER, device_path.to_string());
+
+        for text in [
+            "",
+            "PcieRoot(0x2)/Pci(0x0,0x0),PciRoot(0x0)/Pci(0x1,0x3)",
+            "Fv(7cb8bdc9-f8eb-4f34-aaea-3ee4af6516a1)/FvFile(462caa21-7614-4503-836e-8ab6f4662331)",
+            "HardwarePath(4,00ff),AcpiPath(3),Msg(5,0102)/MediaPath(9,01)/BbsPath(1,ab)/Path(192,7,abcd)",
+            r"\EFI\Microsoft\Boot\bootmgfw(1).efi",
+        ] {
+            assert_eq!(text, DevicePath::parse_text(text).unwrap().to_string());
+        }
+        assert_eq!("Pci(0x1f,0x2)", DevicePath::parse_text(" /Pci( 31, 0X2 )/").unwrap().to_string());
+        assert_eq!(
+            "Fv(7cb8bdc9-f8eb-4f34-aaea-3ee4af6516a1)",
+            DevicePath::parse_text("Fv(7CB8BDC9-F8EB-4F34-AAEA-3EE4AF6516A1)").unwrap().to_string()
+        );
+        // File paths that would not parse back use the generic form.
+        let mut data = Vec::new();
+        push_node(&mut data, device_path::TYPE_MEDIA, Media::SUBTYPE_FILE_PATH, &[b'/', 0, 0, 0]).unwrap();
+        data.extend_from_slice(&END_ENTIRE_NODE);
+        assert_eq!("MediaPath(4,2f000000)", DevicePath::new(&data).unwrap().to_string());
+        let device_path = DevicePath::parse_text("MediaPath(4,66006f006f002800310029000000)").unwrap();
+        assert_eq!("MediaPath(4,66006f006f002800310029000000)", device_path.to_string());
+
+        assert_eq!(Err(efi::Status::UNSUPPORTED), DevicePath::parse_text("PciRoot(0x0)/Sata(0x0,0xFFFF,0x0)"));
+        for text in ["Pci(0x1)", "Pci(0x100,0x0)", "Pci(0x1,0x0", "Pci(0x1,0x0))", "Msg(5,012)", "Fv(7cb8bdc9)", "a\0"]
+        {
+            assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePath::parse_text(text), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_new_and_append() {
+        let root = DevicePath::parse_text("PciRoot(0x0),PciRoot(0x1)").unwrap();
+        let file = DevicePath::parse_text(r"Pci(0x1,0x0)/\a.efi").unwrap();
+        assert_eq!(r"PciRoot(0x0),PciRoot(0x1)/Pci(0x1,0x0)/\a.efi", root.append(&file).to_string());
+        assert_eq!(Ok(&root), DevicePath::new(root.as_bytes()).as_ref());
+        assert_eq!(Ok(&root), unsafe { DevicePath::from_ptr(root.as_ptr()) }.as_ref());
+
+        let bytes = root.as_bytes();
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePath::new(&bytes[..bytes.len() - 1]));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePath::new(&[bytes, &[0]].concat()));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePath::new(&END_INSTANCE_NODE));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), unsafe { DevicePath::from_ptr(core::ptr::null()) });
+    }
+
+    fn leak_device_path(text: &str) -> *mut device_path::Protocol {
+        Box::leak(DevicePath::parse_text(text).unwrap().data.into_boxed_slice()).as_mut_ptr() as *mut _
+    }
+
+    extern "efiapi" fn efi_text_to_device_path(text: *const efi::Char16) -> *mut device_path::Protocol {
+        match unsafe { *text } {
+            0 => core::ptr::null_mut(),
+            _ => leak_device_path("Pci(0x2,0x0)"),
+        }
+    }
+
+    extern "efiapi" fn efi_device_path_to_text(
+        _device_path: *mut device_path::Protocol,
+        display_only: efi::Boolean,
+        allow_shortcuts: efi::Boolean,
+    ) -> *mut efi::Char16 {
+        assert!(!bool::from(display_only) && !bool::from(allow_shortcuts));
+        Box::leak("Firmware".encode_utf16().chain([0]).collect::<Vec<_>>().into_boxed_slice()).as_mut_ptr()
+    }
+
+    extern "efiapi" fn efi_append_device_path(
+        _first: *const device_path::Protocol,
+        _second: *const device_path::Protocol,
+    ) -> *mut device_path::Protocol {
+        leak_device_path("Pci(0x3,0x0)")
+    }
+
+    extern "efiapi" fn efi_unused_size(_device_path: *const device_path::Protocol) -> usize {
+        unreachable!()
+    }
+
+    extern "efiapi" fn efi_unused_duplicate(_device_path: *const device_path::Protocol) -> *mut device_path::Protocol {
+        unreachable!()
+    }
+
+    extern "efiapi" fn efi_unused_next_instance(
+        _device_path: *mut *mut device_path::Protocol,
+        _size: *mut usize,
+    ) -> *mut device_path::Protocol {
+        unreachable!()
+    }
+
+    extern "efiapi" fn efi_unused_is_multi_instance(_device_path: *const device_path::Protocol) -> efi::Boolean {
+        unreachable!()
+    }
+
+    extern "efiapi" fn efi_unused_create_node(_type: u8, _sub_type: u8, _length: u16) -> *mut device_path::Protocol {
+        unreachable!()
+    }
+
+    #[test]
+    fn test_firmware_conversions() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_protocol::<DevicePathFromText, device_path_from_text::Protocol>().returning(
+            |_, _| {
+                Ok(Box::leak(Box::new(device_path_from_text::Protocol {
+                    convert_text_to_device_node: efi_text_to_device_path,
+                    convert_text_to_device_path: efi_text_to_device_path,
+                })))
+            },
+        );
+        boot_services.expect_locate_protocol::<DevicePathToText, device_path_to_text::Protocol>().returning(|_, _| {
+            Ok(Box::leak(Box::new(device_path_to_text::Protocol {
+                convert_device_node_to_text: efi_device_path_to_text,
+                convert_device_path_to_text: efi_device_path_to_text,
+            })))
+        });
+        boot_services
+            .expect_locate_protocol::<DevicePathUtilities, device_path_utilities::Protocol>()
+            .returning(|_, _| Err(efi::Status::NOT_FOUND));
+        boot_services.expect_free_pool().times(2).returning(|_| Ok(()));
+
+        let device_path = DevicePath::from_text(&boot_services, "anything").unwrap();
+        assert_eq!("Pci(0x2,0x0)", device_path.to_string());
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePath::from_text(&boot_services, ""));
+        assert_eq!("Firmware", device_path.to_text(&boot_services).unwrap());
+        // Without the utilities protocol, the pure Rust implementation appends the device paths.
+        let appended = device_path.append_path(&boot_services, &device_path).unwrap();
+        assert_eq!("Pci(0x2,0x0)/Pci(0x2,0x0)", appended.to_string());
+
+        boot_services.checkpoint();
+        boot_services.expect_locate_protocol::<DevicePathUtilities, device_path_utilities::Protocol>().returning(
+            |_, _| {
+                Ok(Box::leak(Box::new(device_path_utilities::Protocol {
+                    get_device_path_size: efi_unused_size,
+                    duplicate_device_path: efi_unused_duplicate,
+                    append_device_path: efi_append_device_path,
+                    append_device_node: efi_append_device_path,
+                    append_device_path_instance: efi_append_device_path,
+                    get_next_device_path_instance: efi_unused_next_instance,
+                    is_device_path_multi_instance: efi_unused_is_multi_instance,
+                    create_device_node: efi_unused_create_node,
+                })))
+            },
+        );
+        boot_services.expect_free_pool().times(1).returning(|_| Ok(()));
+        let appended = device_path.append_path(&boot_services, &device_path).unwrap();
+        assert_eq!("Pci(0x3,0x0)", appended.to_string());
+    }
+}
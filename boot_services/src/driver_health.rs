@@ -0,0 +1,378 @@
+//! This module defines the EFI_DRIVER_HEALTH_PROTOCOL and a [`DriverHealthBuilder`] to report the health of the
+//! controllers of a Rust driver, displayed by the platform driver health UIs.
+//!
+//! ```ignore
+//! fn health(controller: Option<efi::Handle>, _child: Option<efi::Handle>) -> Result<Health, efi::Status> {
+//!     match DEVICE.lock().needs_configuration() {
+//!         true => Ok(Health::new(HealthStatus::ConfigurationRequired).form(hii_handle)),
+//!         false => Ok(Health::new(HealthStatus::Healthy)),
+//!     }
+//! }
+//!
+//! DriverHealthBuilder::new(health).install(&BOOT_SERVICES, Some(driver_binding_handle))?;
+//! ```
+//!
+//! [UEFI Spec Documentation: 11.10. EFI Driver Health Protocol](https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-driver-health-protocol)
+
+use alloc::vec::Vec;
+use core::{mem, ptr};
+
+use r_efi::efi;
+
+use crate::{
+    allocation::MemoryType,
+    protocol_handler::DriverHealth,
+    protocol_installer::{InstalledProtocol, ProtocolInstaller, ProtocolInstance},
+    BootServices,
+};
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x2a534210, 0x9280, 0x41d8, 0xae, 0x79, &[0xca, 0xda, 0x01, 0xa2, 0xb1, 0x27]);
+
+pub const STATUS_HEALTHY: u32 = 0;
+pub const STATUS_REPAIR_REQUIRED: u32 = 1;
+pub const STATUS_CONFIGURATION_REQUIRED: u32 = 2;
+pub const STATUS_FAILED: u32 = 3;
+pub const STATUS_RECONNECT_REQUIRED: u32 = 4;
+pub const STATUS_REBOOT_REQUIRED: u32 = 5;
+
+/// A message of the health of a controller, terminated by an entry with a null HII handle in a message list.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HiiMessage {
+    pub hii_handle: efi::hii::Handle,
+    pub string_id: efi::hii::StringId,
+    pub message_code: u64,
+}
+
+pub type GetHealthStatus = extern "efiapi" fn(
+    *mut Protocol,
+    efi::Handle,
+    efi::Handle,
+    *mut u32,
+    *mut *mut HiiMessage,
+    *mut efi::hii::Handle,
+) -> efi::Status;
+
+pub type RepairNotify = extern "efiapi" fn(usize, usize) -> efi::Status;
+
+pub type Repair = extern "efiapi" fn(*mut Protocol, efi::Handle, efi::Handle, Option<RepairNotify>) -> efi::Status;
+
+#[repr(C)]
+pub struct Protocol {
+    pub get_health_status: GetHealthStatus,
+    pub repair: Repair,
+}
+
+/// The health status of a controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    /// The controller can be repaired with the Repair function.
+    RepairRequired,
+    /// The controller must be configured by the user with the form of the driver.
+    ConfigurationRequired,
+    /// The controller failed and can not be repaired.
+    Failed,
+    /// The controller must be disconnected and connected again.
+    ReconnectRequired,
+    /// The platform must be rebooted.
+    RebootRequired,
+}
+
+impl From<HealthStatus> for u32 {
+    fn from(status: HealthStatus) -> Self {
+        match status {
+            HealthStatus::Healthy => STATUS_HEALTHY,
+            HealthStatus::RepairRequired => STATUS_REPAIR_REQUIRED,
+            HealthStatus::ConfigurationRequired => STATUS_CONFIGURATION_REQUIRED,
+            HealthStatus::Failed => STATUS_FAILED,
+            HealthStatus::ReconnectRequired => STATUS_RECONNECT_REQUIRED,
+            HealthStatus::RebootRequired => STATUS_REBOOT_REQUIRED,
+        }
+    }
+}
+
+/// The health of a controller, as reported by a [`DriverHealthHandler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    pub status: HealthStatus,
+    /// Messages displayed to the user, as strings of the HII packages of the driver.
+    pub messages: Vec<HiiMessage>,
+    /// HII handle of the form to configure the controller, for [`HealthStatus::ConfigurationRequired`].
+    pub form: Option<efi::hii::Handle>,
+}
+
+impl Health {
+    /// Create the health of a controller with *status*, without messages nor form.
+    pub fn new(status: HealthStatus) -> Self {
+        Self { status, messages: Vec::new(), form: None }
+    }
+
+    /// Adds the message of *string_id* in the HII package list of *hii_handle*, with a driver specific *message_code*.
+    pub fn message(mut self, hii_handle: efi::hii::Handle, string_id: efi::hii::StringId, message_code: u64) -> Self {
+        self.messages.push(HiiMessage { hii_handle, string_id, message_code });
+        self
+    }
+
+    /// Sets the HII handle of the form to configure the controller.
+    pub fn form(mut self, hii_handle: efi::hii::Handle) -> Self {
+        self.form = Some(hii_handle);
+        self
+    }
+}
+
+/// Rust handler returning the health of a controller, or of all the controllers of the driver if None, and of one of
+/// its children if given.
+pub type DriverHealthHandler =
+    fn(controller: Option<efi::Handle>, child: Option<efi::Handle>) -> Result<Health, efi::Status>;
+
+/// Rust handler repairing a controller, or one of its children if given, calling *notify* with the progress of the
+/// repair as a value out of a limit.
+pub type RepairHandler =
+    fn(controller: efi::Handle, child: Option<efi::Handle>, notify: &dyn Fn(usize, usize)) -> Result<(), efi::Status>;
+
+/// Rust state of an installed driver health protocol.
+pub struct DriverHealthHandlers<B: BootServices + 'static> {
+    boot_services: &'static B,
+    health: DriverHealthHandler,
+    repair: Option<RepairHandler>,
+}
+
+/// A driver health protocol installed by [`DriverHealthBuilder::install`].
+pub type InstalledDriverHealth<B> = InstalledProtocol<'static, B, Protocol, DriverHealthHandlers<B>>;
+
+extern "efiapi" fn get_health_status_thunk<B: BootServices + 'static>(
+    this: *mut Protocol,
+    controller: efi::Handle,
+    child: efi::Handle,
+    health_status: *mut u32,
+    message_list: *mut *mut HiiMessage,
+    form_hii_handle: *mut efi::hii::Handle,
+) -> efi::Status {
+    // SAFETY: The protocol was installed by DriverHealthBuilder::install.
+    let Some(instance) = (unsafe { ProtocolInstance::<_, DriverHealthHandlers<B>>::from_interface(this) }) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if health_status.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let handlers = instance.state();
+    let health =
+        match (handlers.health)((!controller.is_null()).then_some(controller), (!child.is_null()).then_some(child)) {
+            Ok(health) => health,
+            Err(status) => return status,
+        };
+
+    // SAFETY: The output pointers are provided by the caller, the optional ones may be null.
+    unsafe {
+        if !message_list.is_null() {
+            *message_list = match allocate_message_list(handlers.boot_services, &health.messages) {
+                Ok(list) => list,
+                Err(status) => return status,
+            };
+        }
+        if !form_hii_handle.is_null() {
+            *form_hii_handle = health.form.unwrap_or(ptr::null_mut());
+        }
+        *health_status = health.status.into();
+    }
+    efi::Status::SUCCESS
+}
+
+// Copies *messages* in a pool buffer freed by the caller, terminated by an entry with a null HII handle, or returns
+// null if there are no messages.
+fn allocate_message_list<B: BootServices>(
+    boot_services: &B,
+    messages: &[HiiMessage],
+) -> Result<*mut HiiMessage, efi::Status> {
+    if messages.is_empty() {
+        return Ok(ptr::null_mut());
+    }
+    let size = (messages.len() + 1) * mem::size_of::<HiiMessage>();
+    let list = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, size)? as *mut HiiMessage;
+    // SAFETY: The buffer was allocated for the messages and the terminator.
+    unsafe {
+        list.copy_from_nonoverlapping(messages.as_ptr(), messages.len());
+        list.add(messages.len()).write(HiiMessage { hii_handle: ptr::null_mut(), string_id: 0, message_code: 0 });
+    }
+    Ok(list)
+}
+
+extern "efiapi" fn repair_thunk<B: BootServices + 'static>(
+    this: *mut Protocol,
+    controller: efi::Handle,
+    child: efi::Handle,
+    repair_notify: Option<RepairNotify>,
+) -> efi::Status {
+    // SAFETY: The protocol was installed by DriverHealthBuilder::install.
+    let Some(instance) = (unsafe { ProtocolInstance::<_, DriverHealthHandlers<B>>::from_interface(this) }) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    if controller.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Some(repair) = instance.state().repair else {
+        return efi::Status::UNSUPPORTED;
+    };
+    let notify = |value, limit| {
+        if let Some(repair_notify) = repair_notify {
+            repair_notify(value, limit);
+        }
+    };
+    match repair(controller, (!child.is_null()).then_some(child), &notify) {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(status) => status,
+    }
+}
+
+/// Builder of a driver health protocol.
+pub struct DriverHealthBuilder {
+    health: DriverHealthHandler,
+    repair: Option<RepairHandler>,
+}
+
+impl DriverHealthBuilder {
+    /// Create a builder of a protocol reporting the health returned by *health*.
+    pub fn new(health: DriverHealthHandler) -> Self {
+        Self { health, repair: None }
+    }
+
+    /// Sets the handler repairing the controllers reporting [`HealthStatus::RepairRequired`].
+    ///
+    /// Without it, the Repair function returns `efi::Status::UNSUPPORTED`.
+    pub fn repair(mut self, repair: RepairHandler) -> Self {
+        self.repair = Some(repair);
+        self
+    }
+
+    /// Installs the protocol on *handle*, usually the handle of the driver binding protocol of the driver, or on a new
+    /// handle if None.
+    ///
+    /// The protocol stays installed when dropped, as drivers usually provide it until they are unloaded.
+    pub fn install<B: BootServices + 'static>(
+        self,
+        boot_services: &'static B,
+        handle: Option<efi::Handle>,
+    ) -> Result<InstalledDriverHealth<B>, efi::Status> {
+        let protocol = Protocol { get_health_status: get_health_status_thunk::<B>, repair: repair_thunk::<B> };
+        let handlers = DriverHealthHandlers { boot_services, health: self.health, repair: self.repair };
+        ProtocolInstaller::new(&DriverHealth, protocol, handlers).install(boot_services, handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use core::slice;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const HII_HANDLE: efi::hii::Handle = 0x10 as efi::hii::Handle;
+
+    fn health(controller: Option<efi::Handle>, child: Option<efi::Handle>) -> Result<Health, efi::Status> {
+        match (controller.map(|h| h as usize), child) {
+            (None, _) => Ok(Health::new(HealthStatus::Healthy)),
+            (Some(1), None) => Ok(Health::new(HealthStatus::ConfigurationRequired)
+                .message(HII_HANDLE, 3, 0xE0)
+                .message(HII_HANDLE, 4, 0xE1)
+                .form(HII_HANDLE)),
+            (Some(1), Some(_)) => Ok(Health::new(HealthStatus::RepairRequired)),
+            _ => Err(efi::Status::UNSUPPORTED),
+        }
+    }
+
+    fn repair(
+        controller: efi::Handle,
+        child: Option<efi::Handle>,
+        notify: &dyn Fn(usize, usize),
+    ) -> Result<(), efi::Status> {
+        assert_eq!((1, None), (controller as usize, child));
+        notify(1, 2);
+        notify(2, 2);
+        Ok(())
+    }
+
+    fn boot_services() -> &'static MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_install_protocol_interface_unchecked().returning(|handle, guid, _| {
+            assert_eq!(&PROTOCOL_GUID, guid);
+            Ok(handle.unwrap_or(2_usize as efi::Handle))
+        });
+        boot_services.expect_allocate_pool().returning(|memory_type, size| {
+            assert_eq!(MemoryType::BOOT_SERVICES_DATA, memory_type);
+            Ok(Box::leak(vec![0_u64; size.div_ceil(8)].into_boxed_slice()).as_mut_ptr() as *mut u8)
+        });
+        Box::leak(Box::new(boot_services))
+    }
+
+    #[test]
+    fn test_get_health_status() {
+        let installed = DriverHealthBuilder::new(health).install(boot_services(), None).unwrap();
+        assert_eq!(2, installed.handle() as usize);
+        let protocol = installed.interface();
+        let get_health_status = unsafe { &*protocol }.get_health_status;
+        let controller = 1_usize as efi::Handle;
+
+        let mut status = u32::MAX;
+        let mut messages = ptr::null_mut();
+        let mut form = ptr::null_mut();
+        assert_eq!(
+            efi::Status::SUCCESS,
+            get_health_status(protocol, controller, ptr::null_mut(), &mut status, &mut messages, &mut form)
+        );
+        assert_eq!((STATUS_CONFIGURATION_REQUIRED, HII_HANDLE), (status, form));
+        let messages = unsafe { slice::from_raw_parts(messages, 3) };
+        assert_eq!([(3, 0xE0), (4, 0xE1)], [0, 1].map(|i| (messages[i].string_id, messages[i].message_code)));
+        assert!(messages[2].hii_handle.is_null());
+
+        // The message list and the form are optional.
+        let child = 3_usize as efi::Handle;
+        assert_eq!(
+            efi::Status::SUCCESS,
+            get_health_status(protocol, controller, child, &mut status, ptr::null_mut(), ptr::null_mut())
+        );
+        assert_eq!(STATUS_REPAIR_REQUIRED, status);
+        let mut messages = 1_usize as *mut HiiMessage;
+        assert_eq!(
+            efi::Status::SUCCESS,
+            get_health_status(protocol, ptr::null_mut(), ptr::null_mut(), &mut status, &mut messages, &mut form)
+        );
+        assert_eq!((STATUS_HEALTHY, true, true), (status, messages.is_null(), form.is_null()));
+
+        let other = 4_usize as efi::Handle;
+        assert_eq!(
+            efi::Status::UNSUPPORTED,
+            get_health_status(protocol, other, ptr::null_mut(), &mut status, ptr::null_mut(), ptr::null_mut())
+        );
+        assert_eq!(
+            efi::Status::INVALID_PARAMETER,
+            get_health_status(protocol, controller, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut())
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        static NOTIFIED: AtomicUsize = AtomicUsize::new(0);
+        extern "efiapi" fn repair_notify(value: usize, limit: usize) -> efi::Status {
+            assert_eq!(2, limit);
+            NOTIFIED.store(value, Ordering::Relaxed);
+            efi::Status::SUCCESS
+        }
+
+        let controller = 1_usize as efi::Handle;
+        let installed = DriverHealthBuilder::new(health).install(boot_services(), Some(controller)).unwrap();
+        let protocol = installed.interface();
+        let repair_fn = unsafe { &*protocol }.repair;
+        assert_eq!(efi::Status::UNSUPPORTED, repair_fn(protocol, controller, ptr::null_mut(), Some(repair_notify)));
+
+        let installed =
+            DriverHealthBuilder::new(health).repair(repair).install(boot_services(), Some(controller)).unwrap();
+        let protocol = installed.interface();
+        let repair_fn = unsafe { &*protocol }.repair;
+        assert_eq!(efi::Status::SUCCESS, repair_fn(protocol, controller, ptr::null_mut(), Some(repair_notify)));
+        assert_eq!(2, NOTIFIED.load(Ordering::Relaxed));
+        assert_eq!(efi::Status::SUCCESS, repair_fn(protocol, controller, ptr::null_mut(), None));
+        assert_eq!(efi::Status::INVALID_PARAMETER, repair_fn(protocol, ptr::null_mut(), ptr::null_mut(), None));
+    }
+}
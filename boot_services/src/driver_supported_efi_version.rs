@@ -0,0 +1,69 @@
+//! This module defines the EFI_DRIVER_SUPPORTED_EFI_VERSION_PROTOCOL and [`install`] to publish it, so the platform
+//! knows which version of the UEFI specification a driver complies with.
+//!
+//! ```ignore
+//! driver_supported_efi_version::install(&BOOT_SERVICES, image_handle, efi::SPECIFICATION_REVISION)?;
+//! ```
+//!
+//! [UEFI Spec Documentation: 11.13. EFI Driver Supported EFI Version Protocol](https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-driver-supported-efi-version-protocol)
+
+use core::mem;
+
+use r_efi::efi;
+
+use crate::{
+    protocol_handler::DriverSupportedEfiVersion,
+    protocol_installer::{InstalledProtocol, ProtocolInstaller},
+    BootServices,
+};
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x5c198761, 0x16a8, 0x4e69, 0x97, 0x2c, &[0x89, 0xd6, 0x79, 0x54, 0xf8, 0x1d]);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protocol {
+    pub length: u32,
+    pub firmware_version: u32,
+}
+
+impl Protocol {
+    /// Create the protocol of a driver complying with *firmware_version*, encoded like `efi::SPECIFICATION_REVISION`.
+    pub const fn new(firmware_version: u32) -> Self {
+        Self { length: mem::size_of::<Self>() as u32, firmware_version }
+    }
+}
+
+/// Installs the protocol on the *image_handle* of the driver, for a driver complying with *firmware_version*.
+///
+/// The protocol stays installed when dropped, as drivers usually provide it until they are unloaded.
+pub fn install<B: BootServices>(
+    boot_services: &B,
+    image_handle: efi::Handle,
+    firmware_version: u32,
+) -> Result<InstalledProtocol<'_, B, Protocol, ()>, efi::Status> {
+    ProtocolInstaller::new(&DriverSupportedEfiVersion, Protocol::new(firmware_version), ())
+        .install(boot_services, Some(image_handle))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+
+    #[test]
+    fn test_install() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_install_protocol_interface_unchecked().times(1).returning(|handle, guid, interface| {
+            assert_eq!(Some(1), handle.map(|h| h as usize));
+            assert_eq!(&PROTOCOL_GUID, guid);
+            let protocol = unsafe { &*(interface as *const Protocol) };
+            assert_eq!(&Protocol { length: 8, firmware_version: efi::SYSTEM_TABLE_REVISION_2_70 }, protocol);
+            Ok(1_usize as efi::Handle)
+        });
+
+        let installed = install(&boot_services, 1_usize as efi::Handle, efi::SYSTEM_TABLE_REVISION_2_70).unwrap();
+        assert_eq!(1, installed.handle() as usize);
+        assert_eq!(efi::SYSTEM_TABLE_REVISION_2_70, unsafe { &*installed.interface() }.firmware_version);
+    }
+}
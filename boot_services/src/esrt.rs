@@ -0,0 +1,315 @@
+//! This module defines the EFI System Resource Table (ESRT), with functions to read it from the configuration table
+//! and an [`EsrtBuilder`] to publish it from the firmware image descriptors of the Firmware Management Protocol.
+//!
+//! ```ignore
+//! let mut builder = EsrtBuilder::new();
+//! BOOT_SERVICES.for_each_protocol(&FirmwareManagement, None, |_, fmp| {
+//!     let info = FmpDevice::new(fmp, &BOOT_SERVICES).get_image_info().unwrap();
+//!     builder.add_image_info(&info, FW_TYPE_SYSTEM_FIRMWARE);
+//! })?;
+//! builder.install(&BOOT_SERVICES)?;
+//! ```
+//!
+//! [UEFI Spec Documentation: 23.4. EFI System Resource Table](https://uefi.org/specs/UEFI/2.10/23_Firmware_Update_and_Reporting.html#efi-system-resource-table)
+
+use alloc::vec::Vec;
+use core::{ffi::c_void, mem, ptr, slice};
+
+use r_efi::efi;
+
+use crate::{allocation::MemoryType, firmware_management::ImageInfo, BootServices};
+
+pub const ESRT_GUID: efi::Guid =
+    efi::Guid::from_fields(0xb122a263, 0x3661, 0x4f68, 0x99, 0x29, &[0x78, 0xf8, 0xb0, 0xd6, 0x21, 0x80]);
+
+pub const FIRMWARE_RESOURCE_VERSION: u64 = 1;
+
+pub const FW_TYPE_UNKNOWN: u32 = 0;
+pub const FW_TYPE_SYSTEM_FIRMWARE: u32 = 1;
+pub const FW_TYPE_DEVICE_FIRMWARE: u32 = 2;
+pub const FW_TYPE_UEFI_DRIVER: u32 = 3;
+
+pub const LAST_ATTEMPT_STATUS_SUCCESS: u32 = 0;
+pub const LAST_ATTEMPT_STATUS_ERROR_UNSUCCESSFUL: u32 = 1;
+pub const LAST_ATTEMPT_STATUS_ERROR_INSUFFICIENT_RESOURCES: u32 = 2;
+pub const LAST_ATTEMPT_STATUS_ERROR_INCORRECT_VERSION: u32 = 3;
+pub const LAST_ATTEMPT_STATUS_ERROR_INVALID_FORMAT: u32 = 4;
+pub const LAST_ATTEMPT_STATUS_ERROR_AUTH_ERROR: u32 = 5;
+pub const LAST_ATTEMPT_STATUS_ERROR_PWR_EVT_AC: u32 = 6;
+pub const LAST_ATTEMPT_STATUS_ERROR_PWR_EVT_BATT: u32 = 7;
+pub const LAST_ATTEMPT_STATUS_ERROR_UNSATISFIED_DEPENDENCIES: u32 = 8;
+
+/// EFI_SYSTEM_RESOURCE_TABLE, followed by its entries.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableHeader {
+    pub fw_resource_count: u32,
+    pub fw_resource_count_max: u32,
+    pub fw_resource_version: u64,
+}
+
+/// EFI_SYSTEM_RESOURCE_ENTRY, describing a firmware resource that can be updated with a capsule.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// Firmware class, the image type id of the firmware image descriptor of the resource.
+    pub fw_class: efi::Guid,
+    /// One of the `FW_TYPE_*` constants.
+    pub fw_type: u32,
+    pub fw_version: u32,
+    pub lowest_supported_fw_version: u32,
+    pub capsule_flags: u32,
+    pub last_attempt_version: u32,
+    /// One of the `LAST_ATTEMPT_STATUS_*` constants.
+    pub last_attempt_status: u32,
+}
+
+/// Reads the entries of the ESRT at *table*.
+///
+/// Returns `efi::Status::INCOMPATIBLE_VERSION` if the table is not of [`FIRMWARE_RESOURCE_VERSION`] and
+/// `efi::Status::VOLUME_CORRUPTED` if it has more entries than its maximum.
+///
+/// # Safety
+///
+/// *table* must point to a valid ESRT, whose entries are readable.
+pub unsafe fn read(table: *const c_void) -> Result<Vec<Entry>, efi::Status> {
+    let header = ptr::read_unaligned(table as *const TableHeader);
+    if header.fw_resource_version != FIRMWARE_RESOURCE_VERSION {
+        return Err(efi::Status::INCOMPATIBLE_VERSION);
+    }
+    if header.fw_resource_count > header.fw_resource_count_max {
+        return Err(efi::Status::VOLUME_CORRUPTED);
+    }
+    let entries = (table as *const u8).add(mem::size_of::<TableHeader>()) as *const Entry;
+    Ok((0..header.fw_resource_count as usize).map(|idx| ptr::read_unaligned(entries.add(idx))).collect())
+}
+
+/// Reads the entries of the ESRT of the configuration table of *system_table*.
+///
+/// Returns `efi::Status::NOT_FOUND` if there is no ESRT, see [`read`] for the other errors.
+///
+/// # Safety
+///
+/// *system_table* must point to a valid [efi::SystemTable], whose ESRT is valid.
+pub unsafe fn read_from_system_table(system_table: *const efi::SystemTable) -> Result<Vec<Entry>, efi::Status> {
+    let system_table = &*system_table;
+    let configuration_table =
+        slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries);
+    let esrt = configuration_table
+        .iter()
+        .find(|table| table.vendor_guid == ESRT_GUID)
+        .ok_or(efi::Status::NOT_FOUND)?
+        .vendor_table;
+    read(esrt)
+}
+
+/// Builder of an ESRT, installed in the configuration table with [`EsrtBuilder::install`].
+///
+/// The ESRT has a single entry per firmware class, adding an entry of a class already added replaces it.
+#[derive(Debug, Clone, Default)]
+pub struct EsrtBuilder {
+    entries: Vec<Entry>,
+}
+
+impl EsrtBuilder {
+    /// Create a builder of an ESRT without entries.
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Adds *entry*, or replaces the entry of its firmware class.
+    pub fn add_entry(&mut self, entry: Entry) -> &mut Self {
+        match self.entries.iter_mut().find(|e| e.fw_class == entry.fw_class) {
+            Some(e) => *e = entry,
+            None => self.entries.push(entry),
+        }
+        self
+    }
+
+    /// Adds an entry of *fw_type* for each image descriptor of *info*, as returned by
+    /// [`FmpDevice::get_image_info`](crate::firmware_management::FmpDevice::get_image_info).
+    ///
+    /// The versions and the last attempt status missing from older descriptor versions are reported as 0.
+    pub fn add_image_info(&mut self, info: &ImageInfo, fw_type: u32) -> &mut Self {
+        for descriptor in &info.descriptors {
+            self.add_entry(Entry {
+                fw_class: descriptor.image_type_id,
+                fw_type,
+                fw_version: descriptor.version,
+                lowest_supported_fw_version: descriptor.lowest_supported_image_version.unwrap_or(0),
+                capsule_flags: 0,
+                last_attempt_version: descriptor.last_attempt_version.unwrap_or(0),
+                last_attempt_status: descriptor.last_attempt_status.unwrap_or(LAST_ATTEMPT_STATUS_SUCCESS),
+            });
+        }
+        self
+    }
+
+    /// Returns the entries added so far.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns the bytes of the ESRT, a header followed by the entries.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = TableHeader {
+            fw_resource_count: self.entries.len() as u32,
+            fw_resource_count_max: self.entries.len() as u32,
+            fw_resource_version: FIRMWARE_RESOURCE_VERSION,
+        };
+        let mut bytes = Vec::with_capacity(mem::size_of::<TableHeader>() + mem::size_of_val(self.entries.as_slice()));
+        // SAFETY: The header and the entries are plain repr(C) structs without padding.
+        unsafe {
+            bytes.extend_from_slice(slice::from_raw_parts(
+                &header as *const TableHeader as *const u8,
+                mem::size_of::<TableHeader>(),
+            ));
+            bytes.extend_from_slice(slice::from_raw_parts(
+                self.entries.as_ptr() as *const u8,
+                mem::size_of_val(self.entries.as_slice()),
+            ));
+        }
+        bytes
+    }
+
+    /// Installs the ESRT in the configuration table, in a boot services data pool allocation as required by the
+    /// specification.
+    ///
+    /// An ESRT already installed is replaced, its memory is not freed as it may not have been allocated from pool.
+    pub fn install<B: BootServices>(&self, boot_services: &B) -> Result<(), efi::Status> {
+        let bytes = self.to_bytes();
+        let table = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, bytes.len())?;
+        // SAFETY: The pool was allocated for the bytes of the table, which is of the type of the ESRT GUID.
+        unsafe {
+            table.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+            boot_services.install_configuration_table_unchecked(&ESRT_GUID, table as *mut c_void).inspect_err(|_| {
+                let _ = boot_services.free_pool(table);
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{firmware_management::ImageDescriptor, MockBootServices};
+    use alloc::string::String;
+    use std::cell::Cell;
+
+    const SYSTEM_FIRMWARE: efi::Guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+    const DEVICE_FIRMWARE: efi::Guid = efi::Guid::from_fields(7, 8, 9, 10, 11, &[12; 6]);
+
+    fn descriptor(image_type_id: efi::Guid, version: u32, last_attempt_status: Option<u32>) -> ImageDescriptor {
+        ImageDescriptor {
+            image_index: 1,
+            image_type_id,
+            image_id: 0,
+            image_id_name: String::new(),
+            version,
+            version_name: String::new(),
+            size: 0,
+            attributes_supported: 0,
+            attributes_setting: 0,
+            compatibilities: 0,
+            lowest_supported_image_version: last_attempt_status.map(|_| 2),
+            last_attempt_version: last_attempt_status.map(|_| version + 1),
+            last_attempt_status,
+            hardware_instance: None,
+        }
+    }
+
+    #[test]
+    fn test_build_and_read() {
+        let info = ImageInfo {
+            descriptor_version: 3,
+            package_version: 0,
+            package_version_name: String::new(),
+            descriptors: vec![
+                descriptor(SYSTEM_FIRMWARE, 5, Some(LAST_ATTEMPT_STATUS_ERROR_AUTH_ERROR)),
+                descriptor(DEVICE_FIRMWARE, 9, None),
+            ],
+        };
+        let mut builder = EsrtBuilder::new();
+        builder.add_image_info(&info, FW_TYPE_SYSTEM_FIRMWARE);
+        builder.add_entry(Entry { fw_type: FW_TYPE_DEVICE_FIRMWARE, capsule_flags: 0x10000, ..builder.entries()[1] });
+
+        let bytes = builder.to_bytes();
+        assert_eq!(16 + 2 * 40, bytes.len());
+        let entries = unsafe { read(bytes.as_ptr() as *const c_void) }.unwrap();
+        assert_eq!(builder.entries(), entries);
+        assert_eq!(
+            Entry {
+                fw_class: SYSTEM_FIRMWARE,
+                fw_type: FW_TYPE_SYSTEM_FIRMWARE,
+                fw_version: 5,
+                lowest_supported_fw_version: 2,
+                capsule_flags: 0,
+                last_attempt_version: 6,
+                last_attempt_status: LAST_ATTEMPT_STATUS_ERROR_AUTH_ERROR,
+            },
+            entries[0]
+        );
+        assert_eq!((FW_TYPE_DEVICE_FIRMWARE, 9, 0, 0x10000), {
+            let e = entries[1];
+            (e.fw_type, e.fw_version, e.lowest_supported_fw_version, e.capsule_flags)
+        });
+
+        let mut corrupted = bytes.clone();
+        corrupted[0] = 3;
+        assert_eq!(Err(efi::Status::VOLUME_CORRUPTED), unsafe { read(corrupted.as_ptr() as *const c_void) });
+        corrupted[8] = 2;
+        assert_eq!(Err(efi::Status::INCOMPATIBLE_VERSION), unsafe { read(corrupted.as_ptr() as *const c_void) });
+    }
+
+    #[test]
+    fn test_install() {
+        std::thread_local! {
+            static INSTALLED: Cell<*mut c_void> = const { Cell::new(ptr::null_mut()) };
+        }
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pool().returning(|memory_type, size| {
+            assert_eq!(MemoryType::BOOT_SERVICES_DATA, memory_type);
+            Ok(Box::leak(vec![0_u64; size.div_ceil(8)].into_boxed_slice()).as_mut_ptr() as *mut u8)
+        });
+        boot_services.expect_install_configuration_table_unchecked().returning(|guid, table| {
+            assert_eq!(&ESRT_GUID, guid);
+            INSTALLED.set(table);
+            Ok(())
+        });
+
+        let mut builder = EsrtBuilder::new();
+        builder.add_entry(Entry {
+            fw_class: SYSTEM_FIRMWARE,
+            fw_type: FW_TYPE_SYSTEM_FIRMWARE,
+            fw_version: 1,
+            lowest_supported_fw_version: 1,
+            capsule_flags: 0,
+            last_attempt_version: 0,
+            last_attempt_status: LAST_ATTEMPT_STATUS_SUCCESS,
+        });
+        builder.install(&boot_services).unwrap();
+
+        let mut configuration_table =
+            [efi::ConfigurationTable { vendor_guid: ESRT_GUID, vendor_table: INSTALLED.get() }];
+        let mut system_table: efi::SystemTable = unsafe { mem::zeroed() };
+        system_table.number_of_table_entries = configuration_table.len();
+        system_table.configuration_table = configuration_table.as_mut_ptr();
+        assert_eq!(Ok(builder.entries().to_vec()), unsafe { read_from_system_table(&system_table) });
+
+        system_table.number_of_table_entries = 0;
+        assert_eq!(Err(efi::Status::NOT_FOUND), unsafe { read_from_system_table(&system_table) });
+    }
+
+    #[test]
+    fn test_failed_install_frees_the_table() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_allocate_pool()
+            .returning(|_, _| Ok(Box::leak(Box::new([0_u64; 2])).as_mut_ptr() as *mut u8));
+        boot_services
+            .expect_install_configuration_table_unchecked()
+            .returning(|_, _| Err(efi::Status::OUT_OF_RESOURCES));
+        boot_services.expect_free_pool().times(1).returning(|_| Ok(()));
+        assert_eq!(Err(efi::Status::OUT_OF_RESOURCES), EsrtBuilder::new().install(&boot_services));
+    }
+}
@@ -1,9 +1,12 @@
 //! This module defined every struct related to event in boot services.
 
-use core::ops;
+use alloc::boxed::Box;
+use core::{any::Any, fmt, mem, ops, time::Duration};
 
 use r_efi::efi;
 
+use crate::{tpl::Tpl, BootServices};
+
 /// Function signature for event notify function.
 pub type EventNotifyCallback<T> = extern "efiapi" fn(efi::Event, T);
 
@@ -32,6 +35,11 @@ impl Into<u32> for EventTimerType {
     }
 }
 
+// Converts *trigger_time* to the 100ns unit of the timer, rounded up.
+pub(crate) fn timer_trigger_time(trigger_time: Duration) -> u64 {
+    u64::try_from(trigger_time.as_nanos().div_ceil(100)).unwrap_or(u64::MAX)
+}
+
 /// Type of event to create and its mode and attributes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
@@ -88,3 +96,362 @@ impl Into<u32> for EventType {
         self.0
     }
 }
+
+/// GUID of the event group signaled when the platform is about to run third party code, see [`BootStage::EndOfDxe`].
+pub const END_OF_DXE_EVENT_GROUP_GUID: efi::Guid =
+    efi::Guid::from_fields(0x02ce967a, 0xdd7e, 0x4ffc, 0x9e, 0xe7, &[0x81, 0x0c, 0xf0, 0x47, 0x08, 0x80]);
+
+/// Boot milestones signaled to an event group, see [`BootServices::on_boot_stage`](super::BootServices::on_boot_stage).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootStage {
+    /// The platform is done with the DXE phase and about to connect consoles and run third party code.
+    /// This is a PI event group, not every firmware signals it.
+    EndOfDxe,
+    /// The boot manager is about to load and start a boot option.
+    ReadyToBoot,
+    /// Signaled right after [`Self::ReadyToBoot`], once every ready to boot notification ran.
+    AfterReadyToBoot,
+    /// The OS loader called `ExitBootServices()`.
+    ExitBootServices,
+}
+
+impl BootStage {
+    /// Returns the GUID of the event group signaled at this stage.
+    pub fn event_group(self) -> &'static efi::Guid {
+        match self {
+            BootStage::EndOfDxe => &END_OF_DXE_EVENT_GROUP_GUID,
+            BootStage::ReadyToBoot => &efi::EVENT_GROUP_READY_TO_BOOT,
+            BootStage::AfterReadyToBoot => &efi::EVENT_GROUP_AFTER_READY_TO_BOOT,
+            BootStage::ExitBootServices => &efi::EVENT_GROUP_EXIT_BOOT_SERVICES,
+        }
+    }
+}
+
+/// A timer event whose timer is cancelled before the event is closed when dropped.
+///
+/// Closing a timer event with a pending timer is enough for the firmware, but its notify function may already be
+/// queued, cancelling the timer first keeps the notify function from running once the guard is out of scope.
+///
+/// ```ignore
+/// let timer = TimerEvent::new(&BOOT_SERVICES, BOOT_SERVICES.create_event(EventType::TIMER | EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, Some(on_tick), context)?);
+/// timer.set(EventTimerType::Periodic, Duration::from_millis(10))?;
+/// ```
+#[must_use = "if unused the timer will immediately be cancelled and the event closed"]
+pub struct TimerEvent<'a, B: BootServices> {
+    boot_services: &'a B,
+    event: efi::Event,
+}
+
+impl<'a, B: BootServices> TimerEvent<'a, B> {
+    /// Create a guard of *event*, an event created with [`EventType::TIMER`].
+    pub fn new(boot_services: &'a B, event: efi::Event) -> Self {
+        Self { boot_services, event }
+    }
+
+    /// Creates a timer event without notify function, see
+    /// [`BootServices::create_timer_event`](super::BootServices::create_timer_event).
+    pub fn create(
+        boot_services: &'a B,
+        timer_type: EventTimerType,
+        trigger_time: Duration,
+    ) -> Result<Self, efi::Status> {
+        boot_services.create_timer_event(timer_type, trigger_time).map(|event| Self::new(boot_services, event))
+    }
+
+    /// Returns the event, to wait for it or check it.
+    pub fn event(&self) -> efi::Event {
+        self.event
+    }
+
+    /// Sets the timer to signal the event after *trigger_time*, and every *trigger_time* for
+    /// [`EventTimerType::Periodic`].
+    ///
+    /// *trigger_time* is rounded up to the 100ns unit of the timer.
+    pub fn set(&self, timer_type: EventTimerType, trigger_time: Duration) -> Result<(), efi::Status> {
+        self.boot_services.set_timer(self.event, timer_type, timer_trigger_time(trigger_time))
+    }
+
+    /// Cancels the timer, the event is not signaled until the timer is set again.
+    pub fn cancel(&self) -> Result<(), efi::Status> {
+        self.boot_services.set_timer(self.event, EventTimerType::Cancel, 0)
+    }
+
+    /// Cancels the timer and closes the event.
+    ///
+    /// The event is closed even if the timer can not be cancelled, the error of the cancellation is returned then.
+    pub fn close(self) -> Result<(), efi::Status> {
+        let this = mem::ManuallyDrop::new(self);
+        let cancelled = this.cancel();
+        this.boot_services.close_event(this.event)?;
+        cancelled
+    }
+
+    /// Keeps the event open with its timer running.
+    pub fn leak(self) -> efi::Event {
+        mem::ManuallyDrop::new(self).event
+    }
+}
+
+impl<B: BootServices> Drop for TimerEvent<'_, B> {
+    fn drop(&mut self) {
+        let _ = self.cancel();
+        let _ = self.boot_services.close_event(self.event);
+    }
+}
+
+impl<B: BootServices> fmt::Debug for TimerEvent<'_, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimerEvent").field("event", &self.event).finish_non_exhaustive()
+    }
+}
+
+/// Notify function of an event created with [`BootServicesExt::create_event_with_context`](crate::BootServicesExt::create_event_with_context).
+pub type AnyEventNotifyCallback = fn(efi::Event, &mut AnyEventContext);
+
+/// A type erased event context, retrieved with its type in the notify function.
+///
+/// ```ignore
+/// fn on_ready_to_boot(_event: efi::Event, context: &mut AnyEventContext) {
+///     if let Some(counter) = context.downcast_mut::<u32>() {
+///         *counter += 1;
+///     }
+/// }
+/// ```
+pub struct AnyEventContext(Box<dyn Any>);
+
+impl AnyEventContext {
+    pub fn new<T: Any>(context: T) -> Self {
+        Self(Box::new(context))
+    }
+
+    /// Returns true if the context is a `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        self.0.is::<T>()
+    }
+
+    /// Returns the context if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Returns the context if it is a `T`.
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.0.downcast_mut()
+    }
+
+    /// Returns the context if it is a `T`, or itself otherwise.
+    pub fn into_inner<T: Any>(self) -> Result<T, Self> {
+        self.0.downcast().map(|context| *context).map_err(Self)
+    }
+}
+
+impl fmt::Debug for AnyEventContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnyEventContext").finish_non_exhaustive()
+    }
+}
+
+// The notify context of every event created with a type erased context.
+struct AnyEventRegistration {
+    notify_function: AnyEventNotifyCallback,
+    context: AnyEventContext,
+}
+
+extern "efiapi" fn any_event_notify(event: efi::Event, registration: *mut AnyEventRegistration) {
+    // SAFETY: The registration lives until the event is closed, and the notify function of an event is not re-entered.
+    let registration = unsafe { &mut *registration };
+    (registration.notify_function)(event, &mut registration.context);
+}
+
+/// An event created by [`BootServicesExt::create_event_with_context`], closed when dropped.
+///
+/// The context is dropped with the event. If the event can not be closed the context is leaked, since the event may
+/// still be notified.
+///
+/// [`BootServicesExt::create_event_with_context`]: crate::BootServicesExt::create_event_with_context
+#[must_use = "if unused the event will immediately be closed"]
+pub struct ContextEvent<'a, B: BootServices> {
+    boot_services: &'a B,
+    event: efi::Event,
+    registration: *mut AnyEventRegistration,
+}
+
+impl<B: BootServices> ContextEvent<'_, B> {
+    /// Returns the event, to signal or wait for it.
+    pub fn event(&self) -> efi::Event {
+        self.event
+    }
+
+    /// Closes the event and returns its context.
+    pub fn close(self) -> Result<AnyEventContext, efi::Status> {
+        let this = mem::ManuallyDrop::new(self);
+        this.boot_services.close_event(this.event)?;
+        // SAFETY: The event is closed, so its notify function can no longer use the registration.
+        Ok(unsafe { Box::from_raw(this.registration) }.context)
+    }
+
+    /// Keeps the event open, its context is leaked.
+    pub fn leak(self) -> efi::Event {
+        mem::ManuallyDrop::new(self).event
+    }
+}
+
+impl<B: BootServices> Drop for ContextEvent<'_, B> {
+    fn drop(&mut self) {
+        if self.boot_services.close_event(self.event).is_ok() {
+            // SAFETY: The event is closed, so its notify function can no longer use the registration.
+            drop(unsafe { Box::from_raw(self.registration) });
+        }
+    }
+}
+
+// See BootServicesExt::create_event_with_context and BootServicesExt::create_event_ex_with_context.
+pub(crate) fn create_event_with_context<'a, B: BootServices, T: Any>(
+    boot_services: &'a B,
+    event_type: EventType,
+    notify_tpl: Tpl,
+    notify_function: AnyEventNotifyCallback,
+    context: T,
+    event_group: Option<&'static efi::Guid>,
+) -> Result<ContextEvent<'a, B>, efi::Status> {
+    let registration =
+        Box::into_raw(Box::new(AnyEventRegistration { notify_function, context: AnyEventContext::new(context) }));
+    //SAFETY: The registration is freed only once the event is closed.
+    let event = unsafe {
+        match event_group {
+            Some(event_group) => boot_services.create_event_ex_unchecked(
+                event_type,
+                notify_tpl,
+                any_event_notify,
+                registration,
+                event_group,
+            ),
+            None => boot_services.create_event_unchecked(event_type, notify_tpl, Some(any_event_notify), registration),
+        }
+    };
+    match event {
+        Ok(event) => Ok(ContextEvent { boot_services, event, registration }),
+        Err(status) => {
+            // SAFETY: The event was not created.
+            drop(unsafe { Box::from_raw(registration) });
+            Err(status)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BootServicesExt, MockBootServices};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counter(u32);
+
+    fn count(_event: efi::Event, context: &mut AnyEventContext) {
+        assert!(context.downcast_ref::<u32>().is_none());
+        context.downcast_mut::<Counter>().unwrap().0 += 1;
+    }
+
+    #[test]
+    fn test_create_event_with_context() {
+        // The notify function and context given to create_event.
+        static NOTIFY: AtomicUsize = AtomicUsize::new(0);
+        static CONTEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_create_event_unchecked::<AnyEventRegistration>().times(1).returning(
+            |event_type, notify_tpl, notify_function, notify_context| {
+                assert_eq!(EventType::NOTIFY_SIGNAL, event_type);
+                assert_eq!(Tpl::CALLBACK, notify_tpl);
+                NOTIFY.store(notify_function.unwrap() as usize, Ordering::Relaxed);
+                CONTEXT.store(notify_context as usize, Ordering::Relaxed);
+                Ok(1_usize as efi::Event)
+            },
+        );
+        boot_services.expect_close_event().times(1).returning(|event| {
+            assert_eq!(1, event as usize);
+            Ok(())
+        });
+
+        let event = boot_services
+            .create_event_with_context(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, count, Counter(0))
+            .unwrap();
+        assert_eq!(1, event.event() as usize);
+
+        // SAFETY: The function was created from an EventNotifyCallback<*mut AnyEventRegistration>.
+        let notify: EventNotifyCallback<*mut AnyEventRegistration> =
+            unsafe { mem::transmute(NOTIFY.load(Ordering::Relaxed)) };
+        let context = CONTEXT.load(Ordering::Relaxed) as *mut AnyEventRegistration;
+        notify(event.event(), context);
+        notify(event.event(), context);
+
+        let context = event.close().unwrap();
+        assert!(context.is::<Counter>());
+        assert_eq!(2, context.into_inner::<Counter>().unwrap().0);
+    }
+
+    #[test]
+    fn test_timer_event_cancels_before_closing() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_set_timer().times(3).returning(|event, timer_type, trigger_time| {
+            assert_eq!(1, event as usize);
+            let call = CALLS.fetch_add(1, Ordering::Relaxed);
+            match (call, timer_type, trigger_time) {
+                (0, EventTimerType::Periodic, 11) => Ok(()),
+                (1, EventTimerType::Cancel, 0) => Ok(()),
+                (2, EventTimerType::Cancel, 0) => Err(efi::Status::INVALID_PARAMETER),
+                call => panic!("unexpected set_timer call {call:?}"),
+            }
+        });
+        boot_services.expect_close_event().times(2).returning(|event| {
+            // The timer is cancelled first, by the second and third calls.
+            assert!(CALLS.load(Ordering::Relaxed) >= 2);
+            assert_eq!(1, event as usize);
+            Ok(())
+        });
+
+        let timer = TimerEvent::new(&boot_services, 1_usize as efi::Event);
+        assert_eq!(Ok(()), timer.set(EventTimerType::Periodic, Duration::from_nanos(1_001)));
+        drop(timer);
+
+        // The event is closed even if the timer can not be cancelled.
+        let timer = TimerEvent::new(&boot_services, 1_usize as efi::Event);
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), timer.close());
+        assert_eq!(1, TimerEvent::new(&boot_services, 1_usize as efi::Event).leak() as usize);
+    }
+
+    #[test]
+    fn test_context_event_drop() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_ex_unchecked::<AnyEventRegistration>()
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(1_usize as efi::Event));
+        boot_services.expect_close_event().times(1).returning(|_| Err(efi::Status::INVALID_PARAMETER));
+
+        let event = boot_services
+            .create_event_ex_with_context(
+                EventType::NOTIFY_SIGNAL,
+                Tpl::CALLBACK,
+                count,
+                Counter(0),
+                &efi::EVENT_GROUP_READY_TO_BOOT,
+            )
+            .unwrap();
+        // The context is leaked since the event could not be closed.
+        drop(event);
+
+        boot_services.checkpoint();
+        boot_services
+            .expect_create_event_unchecked::<AnyEventRegistration>()
+            .returning(|_, _, _, _| Err(efi::Status::OUT_OF_RESOURCES));
+        assert_eq!(
+            Err(efi::Status::OUT_OF_RESOURCES),
+            boot_services
+                .create_event_with_context(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, count, 0_u32)
+                .map(|e| e.leak())
+        );
+    }
+}
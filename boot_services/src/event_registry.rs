@@ -0,0 +1,301 @@
+//! Registry of the events created through it.
+//!
+//! An [`EventRegistry`] creates events with the [`BootServices`] event functions and remembers their type, notify TPL
+//! and the GUID of the module creating them until they are closed, so the live events can be listed or dumped to debug
+//! event storms and leaked events. Tracking is opt-in: only the events created through a registry are recorded.
+//!
+//! [`EVENT_REGISTRY`] records the events of the image:
+//!
+//! ```ignore
+//! let event =
+//!     EVENT_REGISTRY.create_event(&BOOT_SERVICES, &MODULE_GUID, EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, None, ())?;
+//! ...
+//! EVENT_REGISTRY.dump(&BOOT_SERVICES, &mut serial)?;
+//! // 1 live events
+//! // Event 0x6bf9c018: type 0x200, tpl 8, module 2e8a6d87-0dc9-4a6e-a2b5-8f04a8f8c2c9
+//! ```
+//!
+//! Each image links its own copy of the static, so to record the events of several images one of them installs a
+//! registry as the interface of a protocol, and the others, built with the same version of this crate, create their
+//! events through it:
+//!
+//! ```ignore
+//! // In the image sharing the registry.
+//! BOOT_SERVICES.install_protocol_interface(None, &EVENT_REGISTRY_PROTOCOL, Box::new(EventRegistry::new()))?;
+//!
+//! // In the other images.
+//! let registry = unsafe { BOOT_SERVICES.locate_protocol(&EVENT_REGISTRY_PROTOCOL, None)? };
+//! let event = registry.create_event(&BOOT_SERVICES, &MODULE_GUID, EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, None, ())?;
+//! ```
+
+use alloc::vec::Vec;
+use core::{fmt, time::Duration};
+
+use r_efi::efi;
+
+use crate::{
+    c_ptr::CPtr,
+    event::{EventNotifyCallback, EventTimerType, EventType},
+    tpl::{Tpl, TplLock},
+    BootServices,
+};
+
+/// Event registry shared by the code of the image.
+///
+/// Each image links its own copy of the static, so it only records the events created through it by the image, see
+/// the [module](self) documentation to share a registry between images.
+pub static EVENT_REGISTRY: EventRegistry = EventRegistry::new();
+
+/// An event created through an [`EventRegistry`].
+#[derive(Debug, Clone, Copy)]
+pub struct RegisteredEvent {
+    pub event: efi::Event,
+    pub event_type: EventType,
+    pub notify_tpl: Tpl,
+    /// GUID of the module that created the event.
+    pub module: efi::Guid,
+    /// Event group of the event, for events created with [`EventRegistry::create_event_ex`].
+    pub event_group: Option<efi::Guid>,
+}
+
+impl fmt::Display for RegisteredEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let event_type: u32 = self.event_type.into();
+        write!(f, "Event {:#x}: type {event_type:#x}, tpl {}, module ", self.event as usize, self.notify_tpl.0)?;
+        write_guid(f, &self.module)?;
+        if let Some(event_group) = &self.event_group {
+            write!(f, ", group ")?;
+            write_guid(f, event_group)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_guid(f: &mut fmt::Formatter<'_>, guid: &efi::Guid) -> fmt::Result {
+    let (time_low, time_mid, time_hi, clk_hi, clk_low, node) = guid.as_fields();
+    write!(f, "{time_low:08x}-{time_mid:04x}-{time_hi:04x}-{clk_hi:02x}{clk_low:02x}-")?;
+    node.iter().try_for_each(|b| write!(f, "{b:02x}"))
+}
+
+/// Remembers the events created through it until they are closed through it.
+///
+/// The registry is protected by raising the TPL to [`Tpl::NOTIFY`], so it can be used from event notify functions.
+#[derive(Debug)]
+pub struct EventRegistry {
+    entries: TplLock<Vec<RegisteredEvent>>,
+}
+
+// SAFETY: The entries are only accessed with the lock held, the handles they hold are opaque.
+unsafe impl Sync for EventRegistry {}
+
+impl EventRegistry {
+    /// Create an empty registry.
+    pub const fn new() -> Self {
+        Self { entries: TplLock::new(Tpl::NOTIFY, Vec::new()) }
+    }
+
+    fn with_entries<B, F, T>(&self, boot_services: &B, f: F) -> T
+    where
+        B: BootServices,
+        F: FnOnce(&mut Vec<RegisteredEvent>) -> T,
+    {
+        self.entries.with(boot_services, f)
+    }
+
+    fn register<B: BootServices>(&self, boot_services: &B, entry: RegisteredEvent) -> efi::Event {
+        self.with_entries(boot_services, |entries| entries.push(entry));
+        entry.event
+    }
+
+    /// Creates an event with [`BootServices::create_event`] and registers it for *module*.
+    pub fn create_event<B, T>(
+        &self,
+        boot_services: &B,
+        module: &efi::Guid,
+        event_type: EventType,
+        notify_tpl: Tpl,
+        notify_function: Option<EventNotifyCallback<T>>,
+        notify_context: T,
+    ) -> Result<efi::Event, efi::Status>
+    where
+        B: BootServices,
+        T: CPtr<'static> + 'static,
+    {
+        let event = boot_services.create_event(event_type, notify_tpl, notify_function, notify_context)?;
+        let entry = RegisteredEvent { event, event_type, notify_tpl, module: *module, event_group: None };
+        Ok(self.register(boot_services, entry))
+    }
+
+    /// Creates an event in *event_group* with [`BootServices::create_event_ex`] and registers it for *module*.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_event_ex<B, T>(
+        &self,
+        boot_services: &B,
+        module: &efi::Guid,
+        event_type: EventType,
+        notify_tpl: Tpl,
+        notify_function: Option<EventNotifyCallback<T>>,
+        notify_context: T,
+        event_group: &'static efi::Guid,
+    ) -> Result<efi::Event, efi::Status>
+    where
+        B: BootServices,
+        T: CPtr<'static> + 'static,
+    {
+        let event =
+            boot_services.create_event_ex(event_type, notify_tpl, notify_function, notify_context, event_group)?;
+        let entry = RegisteredEvent { event, event_type, notify_tpl, module: *module, event_group: Some(*event_group) };
+        Ok(self.register(boot_services, entry))
+    }
+
+    /// Creates a timer event with [`BootServices::create_timer_event`] and registers it for *module*.
+    pub fn create_timer_event<B: BootServices>(
+        &self,
+        boot_services: &B,
+        module: &efi::Guid,
+        timer_type: EventTimerType,
+        trigger_time: Duration,
+    ) -> Result<efi::Event, efi::Status> {
+        let event = boot_services.create_timer_event(timer_type, trigger_time)?;
+        let entry = RegisteredEvent {
+            event,
+            event_type: EventType::TIMER,
+            notify_tpl: Tpl::APPLICATION,
+            module: *module,
+            event_group: None,
+        };
+        Ok(self.register(boot_services, entry))
+    }
+
+    /// Closes an event with [`BootServices::close_event`] and unregisters it.
+    ///
+    /// Returns `efi::Status::NOT_FOUND` if the event is not registered, the event is then not closed.
+    pub fn close_event<B: BootServices>(&self, boot_services: &B, event: efi::Event) -> Result<(), efi::Status> {
+        // The entry is taken out so the lock is not held while calling the boot services.
+        let entry = self.with_entries(boot_services, |entries| {
+            let idx = entries.iter().position(|e| e.event == event).ok_or(efi::Status::NOT_FOUND)?;
+            Ok(entries.remove(idx))
+        })?;
+
+        boot_services.close_event(event).map_err(|status| {
+            self.with_entries(boot_services, |entries| entries.push(entry));
+            status
+        })
+    }
+
+    /// Returns the events currently registered, e.g. to report the ones never closed.
+    pub fn registered_events<B: BootServices>(&self, boot_services: &B) -> Vec<RegisteredEvent> {
+        self.with_entries(boot_services, |entries| entries.clone())
+    }
+
+    /// Writes the number of events currently registered to *out*, followed by one line per event.
+    ///
+    /// The events are copied out of the registry first, so *out* can itself create or close registered events.
+    pub fn dump<B: BootServices, W: fmt::Write>(&self, boot_services: &B, out: &mut W) -> fmt::Result {
+        let events = self.registered_events(boot_services);
+        writeln!(out, "{} live events", events.len())?;
+        events.iter().try_for_each(|event| writeln!(out, "{event}"))
+    }
+}
+
+impl Default for EventRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use alloc::string::String;
+
+    const MODULE_GUID: efi::Guid =
+        efi::Guid::from_fields(0x2e8a6d87, 0x0dc9, 0x4a6e, 0xa2, 0xb5, &[0x8f, 0x04, 0xa8, 0xf8, 0xc2, 0xc9]);
+    static GROUP_GUID: efi::Guid = efi::Guid::from_fields(0x1, 0x2, 0x3, 0x4, 0x5, &[0x6; 6]);
+
+    extern "efiapi" fn notify(_event: efi::Event, _context: Box<u32>) {}
+
+    fn boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services.expect_create_event::<Box<u32>>().returning(|_, _, _, _| Ok(1_usize as efi::Event));
+        boot_services.expect_create_event_ex::<Box<u32>>().returning(|_, _, _, _, _| Ok(2_usize as efi::Event));
+        boot_services.expect_create_timer_event().returning(|_, _| Ok(3_usize as efi::Event));
+        boot_services
+    }
+
+    #[test]
+    fn test_create_and_close() {
+        let mut boot_services = boot_services();
+        boot_services.expect_close_event().returning(|event| match event as usize {
+            3 => Err(efi::Status::INVALID_PARAMETER),
+            _ => Ok(()),
+        });
+        let registry = EventRegistry::new();
+
+        let event = registry
+            .create_event(
+                &boot_services,
+                &MODULE_GUID,
+                EventType::NOTIFY_SIGNAL,
+                Tpl::CALLBACK,
+                Some(notify),
+                Box::new(1),
+            )
+            .unwrap();
+        registry
+            .create_event_ex(
+                &boot_services,
+                &MODULE_GUID,
+                EventType::NOTIFY_SIGNAL,
+                Tpl::NOTIFY,
+                Some(notify),
+                Box::new(2),
+                &GROUP_GUID,
+            )
+            .unwrap();
+        let timer = registry
+            .create_timer_event(&boot_services, &MODULE_GUID, EventTimerType::Relative, Duration::from_millis(1))
+            .unwrap();
+
+        let registered = registry.registered_events(&boot_services);
+        assert_eq!(vec![1, 2, 3], registered.iter().map(|e| e.event as usize).collect::<Vec<_>>());
+        assert_eq!(
+            (EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, None),
+            (registered[0].event_type, registered[0].notify_tpl, registered[0].event_group)
+        );
+        assert_eq!(Some(GROUP_GUID), registered[1].event_group);
+        assert_eq!((EventType::TIMER, MODULE_GUID), (registered[2].event_type, registered[2].module));
+
+        assert_eq!(Ok(()), registry.close_event(&boot_services, event));
+        assert_eq!(Err(efi::Status::NOT_FOUND), registry.close_event(&boot_services, event));
+        // A failed close keeps the entry.
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), registry.close_event(&boot_services, timer));
+
+        let mut dump = String::new();
+        registry.dump(&boot_services, &mut dump).unwrap();
+        assert_eq!(
+            "2 live events\n\
+             Event 0x2: type 0x200, tpl 16, module 2e8a6d87-0dc9-4a6e-a2b5-8f04a8f8c2c9, group 00000001-0002-0003-0405-060606060606\n\
+             Event 0x3: type 0x80000000, tpl 4, module 2e8a6d87-0dc9-4a6e-a2b5-8f04a8f8c2c9\n",
+            dump
+        );
+    }
+
+    #[test]
+    fn test_failed_create_is_not_registered() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services.expect_create_timer_event().returning(|_, _| Err(efi::Status::OUT_OF_RESOURCES));
+        let registry = EventRegistry::new();
+
+        assert_eq!(
+            Err(efi::Status::OUT_OF_RESOURCES),
+            registry.create_timer_event(&boot_services, &MODULE_GUID, EventTimerType::Periodic, Duration::from_secs(1))
+        );
+        assert!(registry.registered_events(&boot_services).is_empty());
+    }
+}
@@ -0,0 +1,445 @@
+//! This module defines a rust friendly [`File`] wrapper around the EFI_FILE_PROTOCOL.
+//!
+//! Unlike the other protocols, file interfaces are not installed on handles: the root directory of a volume is opened
+//! from its [`SimpleFileSystem`](crate::protocol_handler::SimpleFileSystem) interface, and every other file is opened
+//! relative to an opened directory.
+//!
+//! ```ignore
+//! let file_system = unsafe { BOOT_SERVICES.handle_protocol(handle, &SimpleFileSystem)? };
+//! let mut root = File::open_volume(file_system)?;
+//! let mut file = root.open("\\EFI\\Boot\\config.bin", FileMode::Read, 0)?;
+//! let config = file.read_to_end()?;
+//! ```
+//!
+//! [UEFI Spec Documentation: 13.5. File Protocol](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#file-protocol)
+
+use alloc::{vec, vec::Vec};
+use core::{
+    ffi::c_void,
+    iter, mem,
+    ptr::{self, NonNull},
+};
+
+use r_efi::efi::{
+    self,
+    protocols::{file, simple_file_system},
+};
+
+/// Position passed to [`File::set_position`] to move to the end of the file.
+pub const END_OF_FILE: u64 = u64::MAX;
+
+/// Mode a file is opened with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum FileMode {
+    Read = file::MODE_READ,
+    ReadWrite = file::MODE_READ | file::MODE_WRITE,
+    /// Open the file for reading and writing, creating it if it does not exist.
+    Create = file::MODE_READ | file::MODE_WRITE | file::MODE_CREATE,
+}
+
+/// An opened EFI_FILE_PROTOCOL instance, closed when dropped.
+#[derive(Debug)]
+pub struct File {
+    protocol: NonNull<file::Protocol>,
+}
+
+impl File {
+    /// Opens the root directory of the volume of *file_system*.
+    ///
+    /// [UEFI Spec Documentation: 13.4.2. EFI_SIMPLE_FILE_SYSTEM_PROTOCOL.OpenVolume()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-simple-file-system-protocol-openvolume)
+    pub fn open_volume(file_system: &mut simple_file_system::Protocol) -> Result<Self, efi::Status> {
+        let mut root = ptr::null_mut();
+        match (file_system.open_volume)(file_system, &mut root) {
+            s if s.is_error() => Err(s),
+            _ => NonNull::new(root).map(|protocol| Self { protocol }).ok_or(efi::Status::DEVICE_ERROR),
+        }
+    }
+
+    /// Takes ownership of an opened file interface.
+    ///
+    /// # Safety
+    /// *protocol* must be a valid opened file interface that is not closed by anyone else.
+    pub unsafe fn from_raw(protocol: NonNull<file::Protocol>) -> Self {
+        Self { protocol }
+    }
+
+    /// Returns the file interface without closing it.
+    pub fn into_raw(self) -> NonNull<file::Protocol> {
+        let protocol = self.protocol;
+        mem::forget(self);
+        protocol
+    }
+
+    fn protocol(&mut self) -> (&file::Protocol, *mut file::Protocol) {
+        // SAFETY: The interface is valid until closed, which only happens when the file is consumed.
+        (unsafe { self.protocol.as_ref() }, self.protocol.as_ptr())
+    }
+
+    /// Opens the file *file_name*, relative to this directory unless it starts with a `\`.
+    ///
+    /// *attributes* are only used when the file is created, see [`file::READ_ONLY`], [`file::DIRECTORY`], etc.
+    ///
+    /// [UEFI Spec Documentation: 13.5.2. EFI_FILE_PROTOCOL.Open()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-open)
+    pub fn open(&mut self, file_name: &str, mode: FileMode, attributes: u64) -> Result<File, efi::Status> {
+        if file_name.contains('\0') {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let mut file_name = file_name.encode_utf16().chain(iter::once(0)).collect::<Vec<_>>();
+        let (protocol, this) = self.protocol();
+        let mut new_file = ptr::null_mut();
+        match (protocol.open)(this, &mut new_file, file_name.as_mut_ptr(), mode as u64, attributes) {
+            s if s.is_error() => Err(s),
+            _ => NonNull::new(new_file).map(|protocol| File { protocol }).ok_or(efi::Status::DEVICE_ERROR),
+        }
+    }
+
+    /// Deletes the file, which is closed even if it can not be deleted.
+    ///
+    /// [UEFI Spec Documentation: 13.5.4. EFI_FILE_PROTOCOL.Delete()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-delete)
+    pub fn delete(self) -> Result<(), efi::Status> {
+        let this = self.into_raw().as_ptr();
+        // SAFETY: The interface is valid, and closed by this call.
+        match (unsafe { &*this }.delete)(this) {
+            efi::Status::WARN_DELETE_FAILURE => Err(efi::Status::WARN_DELETE_FAILURE),
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads data from the file and returns the number of bytes read, 0 at the end of the file.
+    ///
+    /// Reading a directory returns the [`file::Info`] of its next entry.
+    ///
+    /// [UEFI Spec Documentation: 13.5.5. EFI_FILE_PROTOCOL.Read()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-read)
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, efi::Status> {
+        let (protocol, this) = self.protocol();
+        let mut buffer_size = buffer.len();
+        match (protocol.read)(this, &mut buffer_size, buffer.as_mut_ptr() as *mut c_void) {
+            s if s.is_error() => Err(s),
+            _ => Ok(buffer_size),
+        }
+    }
+
+    /// Reads the file from the current position to its end.
+    pub fn read_to_end(&mut self) -> Result<Vec<u8>, efi::Status> {
+        let mut data = Vec::new();
+        let mut chunk = vec![0; 0x1000];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(data),
+                read => data.extend_from_slice(&chunk[..read]),
+            }
+        }
+    }
+
+    /// Writes data to the file and returns the number of bytes written.
+    ///
+    /// [UEFI Spec Documentation: 13.5.6. EFI_FILE_PROTOCOL.Write()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-write)
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, efi::Status> {
+        let (protocol, this) = self.protocol();
+        let mut buffer_size = buffer.len();
+        match (protocol.write)(this, &mut buffer_size, buffer.as_ptr() as *mut c_void) {
+            s if s.is_error() => Err(s),
+            _ => Ok(buffer_size),
+        }
+    }
+
+    /// Writes the entire buffer to the file.
+    pub fn write_all(&mut self, mut buffer: &[u8]) -> Result<(), efi::Status> {
+        while !buffer.is_empty() {
+            let written = self.write(buffer)?;
+            buffer = &buffer[written..];
+        }
+        Ok(())
+    }
+
+    /// Returns the current position in the file.
+    ///
+    /// [UEFI Spec Documentation: 13.5.11. EFI_FILE_PROTOCOL.GetPosition()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-getposition)
+    pub fn position(&mut self) -> Result<u64, efi::Status> {
+        let (protocol, this) = self.protocol();
+        let mut position = 0;
+        match (protocol.get_position)(this, &mut position) {
+            s if s.is_error() => Err(s),
+            _ => Ok(position),
+        }
+    }
+
+    /// Sets the current position in the file, [`END_OF_FILE`] moves to the end of the file.
+    ///
+    /// [UEFI Spec Documentation: 13.5.12. EFI_FILE_PROTOCOL.SetPosition()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-setposition)
+    pub fn set_position(&mut self, position: u64) -> Result<(), efi::Status> {
+        let (protocol, this) = self.protocol();
+        match (protocol.set_position)(this, position) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the information of type *information_type* about the file or its file system, e.g.
+    /// [`file::SYSTEM_INFO_ID`].
+    ///
+    /// [UEFI Spec Documentation: 13.5.13. EFI_FILE_PROTOCOL.GetInfo()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-getinfo)
+    pub fn get_info(&mut self, information_type: &efi::Guid) -> Result<Vec<u8>, efi::Status> {
+        let (protocol, this) = self.protocol();
+        let mut information_type = *information_type;
+        let mut buffer = Vec::new();
+        loop {
+            let mut buffer_size = buffer.len();
+            match (protocol.get_info)(this, &mut information_type, &mut buffer_size, buffer.as_mut_ptr() as *mut c_void)
+            {
+                efi::Status::BUFFER_TOO_SMALL if buffer_size > buffer.len() => buffer.resize(buffer_size, 0),
+                s if s.is_error() => return Err(s),
+                _ => {
+                    buffer.truncate(buffer_size);
+                    return Ok(buffer);
+                }
+            }
+        }
+    }
+
+    /// Returns the information about the file, without its name.
+    pub fn info(&mut self) -> Result<file::Info, efi::Status> {
+        let info = self.get_info(&file::INFO_ID)?;
+        if info.len() < mem::size_of::<file::Info>() {
+            return Err(efi::Status::DEVICE_ERROR);
+        }
+        // SAFETY: The buffer holds at least a file::Info, which is plain old data.
+        Ok(unsafe { ptr::read_unaligned(info.as_ptr() as *const file::Info) })
+    }
+
+    /// Returns true if the file is a directory.
+    pub fn is_directory(&mut self) -> Result<bool, efi::Status> {
+        Ok(self.info()?.attribute & file::DIRECTORY != 0)
+    }
+
+    /// Writes the modified data of the file to the device.
+    ///
+    /// [UEFI Spec Documentation: 13.5.15. EFI_FILE_PROTOCOL.Flush()](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#efi-file-protocol-flush)
+    pub fn flush(&mut self) -> Result<(), efi::Status> {
+        let (protocol, this) = self.protocol();
+        match (protocol.flush)(this) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let (protocol, this) = self.protocol();
+        // Close always succeeds.
+        (protocol.close)(this);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::slice;
+    use std::{boxed::Box, sync::Mutex};
+
+    // A fake file system with a single file, whose interfaces are leaked boxes.
+    #[repr(C)]
+    struct FakeFile {
+        protocol: file::Protocol,
+        data: Vec<u8>,
+        position: usize,
+        directory: bool,
+    }
+
+    static CLOSED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+    fn fake(this: *mut file::Protocol) -> &'static mut FakeFile {
+        // SAFETY: The protocol is the first field of a leaked FakeFile.
+        unsafe { &mut *(this as *mut FakeFile) }
+    }
+
+    fn new_fake(data: &[u8], directory: bool) -> *mut file::Protocol {
+        let protocol = file::Protocol {
+            revision: file::REVISION,
+            open: efi_open,
+            close: efi_close,
+            delete: efi_delete,
+            read: efi_read,
+            write: efi_write,
+            get_position: efi_get_position,
+            set_position: efi_set_position,
+            get_info: efi_get_info,
+            set_info: efi_set_info,
+            flush: efi_flush,
+            open_ex: efi_open_ex,
+            read_ex: efi_io_ex,
+            write_ex: efi_io_ex,
+            flush_ex: efi_io_ex,
+        };
+        let fake = Box::leak(Box::new(FakeFile { protocol, data: data.to_vec(), position: 0, directory }));
+        &mut fake.protocol
+    }
+
+    extern "efiapi" fn efi_open(
+        _this: *mut file::Protocol,
+        new_handle: *mut *mut file::Protocol,
+        file_name: *mut efi::Char16,
+        open_mode: u64,
+        _attributes: u64,
+    ) -> efi::Status {
+        let file_name = unsafe { slice::from_raw_parts(file_name, 7) };
+        if file_name != "\\a.bin\0".encode_utf16().collect::<Vec<_>>() {
+            return efi::Status::NOT_FOUND;
+        }
+        assert_eq!(FileMode::Read as u64, open_mode);
+        unsafe { new_handle.write(new_fake(b"0123456789", false)) };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_close(this: *mut file::Protocol) -> efi::Status {
+        CLOSED.lock().unwrap().push(this as usize);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_delete(this: *mut file::Protocol) -> efi::Status {
+        efi_close(this);
+        efi::Status::WARN_DELETE_FAILURE
+    }
+
+    // Read at most 4 bytes per call.
+    extern "efiapi" fn efi_read(
+        this: *mut file::Protocol,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> efi::Status {
+        let file = fake(this);
+        unsafe {
+            let size = (*buffer_size).min(4).min(file.data.len() - file.position);
+            ptr::copy_nonoverlapping(file.data[file.position..].as_ptr(), buffer as *mut u8, size);
+            file.position += size;
+            *buffer_size = size;
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_write(
+        this: *mut file::Protocol,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> efi::Status {
+        let file = fake(this);
+        unsafe {
+            let data = slice::from_raw_parts(buffer as *const u8, *buffer_size);
+            file.data.splice(file.position.., data.iter().copied());
+            file.position += data.len();
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_get_position(this: *mut file::Protocol, position: *mut u64) -> efi::Status {
+        unsafe { position.write(fake(this).position as u64) };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_set_position(this: *mut file::Protocol, position: u64) -> efi::Status {
+        let file = fake(this);
+        file.position = match position {
+            END_OF_FILE => file.data.len(),
+            position => position as usize,
+        };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_get_info(
+        this: *mut file::Protocol,
+        information_type: *mut efi::Guid,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> efi::Status {
+        if unsafe { *information_type } != file::INFO_ID {
+            return efi::Status::UNSUPPORTED;
+        }
+        let file = fake(this);
+        let mut info: file::Info = unsafe { mem::zeroed() };
+        info.size = mem::size_of::<file::Info>() as u64 + 2;
+        info.file_size = file.data.len() as u64;
+        info.attribute = if file.directory { file::DIRECTORY } else { 0 };
+        unsafe {
+            if *buffer_size < info.size as usize {
+                *buffer_size = info.size as usize;
+                return efi::Status::BUFFER_TOO_SMALL;
+            }
+            *buffer_size = info.size as usize;
+            ptr::write_unaligned(buffer as *mut file::Info, info);
+            ptr::write_unaligned((buffer as *mut file::Info).add(1) as *mut u16, 0);
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_set_info(
+        _this: *mut file::Protocol,
+        _information_type: *mut efi::Guid,
+        _buffer_size: usize,
+        _buffer: *mut c_void,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn efi_flush(_this: *mut file::Protocol) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_open_ex(
+        _this: *mut file::Protocol,
+        _new_handle: *mut *mut file::Protocol,
+        _file_name: *mut efi::Char16,
+        _open_mode: u64,
+        _attributes: u64,
+        _token: *mut file::IoToken,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn efi_io_ex(_this: *mut file::Protocol, _token: *mut file::IoToken) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn efi_open_volume(
+        _this: *mut simple_file_system::Protocol,
+        root: *mut *mut file::Protocol,
+    ) -> efi::Status {
+        unsafe { root.write(new_fake(&[], true)) };
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_open_read_write() {
+        let mut file_system =
+            simple_file_system::Protocol { revision: simple_file_system::REVISION, open_volume: efi_open_volume };
+        let mut root = File::open_volume(&mut file_system).unwrap();
+        assert_eq!(Ok(true), root.is_directory());
+        assert_eq!(efi::Status::NOT_FOUND, root.open("\\b.bin", FileMode::Read, 0).unwrap_err());
+        assert_eq!(efi::Status::INVALID_PARAMETER, root.open("\\a\0.bin", FileMode::Read, 0).unwrap_err());
+
+        let mut file = root.open("\\a.bin", FileMode::Read, 0).unwrap();
+        assert_eq!(Ok(false), file.is_directory());
+        assert_eq!(10, file.info().unwrap().file_size);
+        assert_eq!(Ok(()), file.set_position(2));
+        assert_eq!(b"23456789", file.read_to_end().unwrap().as_slice());
+        assert_eq!(Ok(10), file.position());
+
+        assert_eq!(Ok(()), file.set_position(8));
+        assert_eq!(Ok(()), file.write_all(b"ab"));
+        assert_eq!(Ok(()), file.set_position(END_OF_FILE));
+        assert_eq!(Ok(()), file.write_all(b"cd"));
+        assert_eq!(Ok(()), file.flush());
+        assert_eq!(Ok(()), file.set_position(0));
+        assert_eq!(b"01234567abcd", file.read_to_end().unwrap().as_slice());
+        assert_eq!(Err(efi::Status::UNSUPPORTED), file.get_info(&file::SYSTEM_INFO_ID));
+
+        let file_ptr = file.protocol.as_ptr() as usize;
+        let root_ptr = root.protocol.as_ptr() as usize;
+        assert_eq!(Err(efi::Status::WARN_DELETE_FAILURE), file.delete());
+        assert!(CLOSED.lock().unwrap().contains(&file_ptr));
+        drop(root);
+        assert!(CLOSED.lock().unwrap().contains(&root_ptr));
+    }
+}
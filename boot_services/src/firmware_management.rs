@@ -0,0 +1,518 @@
+//! This module defined the EFI_FIRMWARE_MANAGEMENT_PROTOCOL and a rust friendly [`FmpDevice`] wrapper around it.
+//!
+//! [UEFI Spec Documentation: 23.1. Firmware Management Protocol](https://uefi.org/specs/UEFI/2.10/23_Firmware_Update_and_Reporting.html#firmware-management-protocol)
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    ffi::c_void,
+    mem::{self, MaybeUninit},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::BootServices;
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x86c77a67, 0x0b97, 0x4633, 0xa1, 0x87, &[0x49, 0x10, 0x4d, 0x06, 0x85, 0xc7]);
+
+pub const IMAGE_DESCRIPTOR_VERSION: u32 = 4;
+
+pub const IMAGE_ATTRIBUTE_IMAGE_UPDATABLE: u64 = 0x0000000000000001;
+pub const IMAGE_ATTRIBUTE_RESET_REQUIRED: u64 = 0x0000000000000002;
+pub const IMAGE_ATTRIBUTE_AUTHENTICATION_REQUIRED: u64 = 0x0000000000000004;
+pub const IMAGE_ATTRIBUTE_IN_USE: u64 = 0x0000000000000008;
+pub const IMAGE_ATTRIBUTE_UEFI_IMAGE: u64 = 0x0000000000000010;
+pub const IMAGE_ATTRIBUTE_DEPENDENCY: u64 = 0x0000000000000020;
+
+pub const IMAGE_UPDATABLE_VALID: u32 = 0x0000000000000001;
+pub const IMAGE_UPDATABLE_INVALID: u32 = 0x0000000000000002;
+pub const IMAGE_UPDATABLE_INVALID_TYPE: u32 = 0x0000000000000004;
+pub const IMAGE_UPDATABLE_INVALID_OLD: u32 = 0x0000000000000008;
+pub const IMAGE_UPDATABLE_VALID_WITH_VENDOR_CODE: u32 = 0x0000000000000010;
+
+pub type Progress = extern "efiapi" fn(usize) -> efi::Status;
+
+pub type ProtocolGetImageInfo = extern "efiapi" fn(
+    *mut Protocol,
+    *mut usize,
+    *mut ImageDescriptorRaw,
+    *mut u32,
+    *mut u8,
+    *mut usize,
+    *mut u32,
+    *mut *mut efi::Char16,
+) -> efi::Status;
+
+pub type ProtocolGetImage = extern "efiapi" fn(*mut Protocol, u8, *mut c_void, *mut usize) -> efi::Status;
+
+pub type ProtocolSetImage = extern "efiapi" fn(
+    *mut Protocol,
+    u8,
+    *const c_void,
+    usize,
+    *const c_void,
+    Option<Progress>,
+    *mut *mut efi::Char16,
+) -> efi::Status;
+
+pub type ProtocolCheckImage = extern "efiapi" fn(*mut Protocol, u8, *const c_void, usize, *mut u32) -> efi::Status;
+
+pub type ProtocolGetPackageInfo =
+    extern "efiapi" fn(*mut Protocol, *mut u32, *mut *mut efi::Char16, *mut u32, *mut u64, *mut u64) -> efi::Status;
+
+pub type ProtocolSetPackageInfo =
+    extern "efiapi" fn(*mut Protocol, *const c_void, usize, *const c_void, u32, *const efi::Char16) -> efi::Status;
+
+/// EFI_FIRMWARE_IMAGE_DESCRIPTOR, as of descriptor version 4.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImageDescriptorRaw {
+    pub image_index: u8,
+    pub image_type_id: efi::Guid,
+    pub image_id: u64,
+    pub image_id_name: *mut efi::Char16,
+    pub version: u32,
+    pub version_name: *mut efi::Char16,
+    pub size: usize,
+    pub attributes_supported: u64,
+    pub attributes_setting: u64,
+    pub compatibilities: u64,
+    // Added in version 2.
+    pub lowest_supported_image_version: u32,
+    // Added in version 3.
+    pub last_attempt_version: u32,
+    pub last_attempt_status: u32,
+    pub hardware_instance: u64,
+    // Added in version 4.
+    pub dependencies: *mut c_void,
+}
+
+#[repr(C)]
+pub struct Protocol {
+    pub get_image_info: ProtocolGetImageInfo,
+    pub get_image: ProtocolGetImage,
+    pub set_image: ProtocolSetImage,
+    pub check_image: ProtocolCheckImage,
+    pub get_package_info: ProtocolGetPackageInfo,
+    pub set_package_info: ProtocolSetPackageInfo,
+}
+
+/// Parsed firmware image descriptor.
+///
+/// Fields added in later descriptor versions are [`None`] when the device reports an older version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageDescriptor {
+    pub image_index: u8,
+    pub image_type_id: efi::Guid,
+    pub image_id: u64,
+    pub image_id_name: String,
+    pub version: u32,
+    pub version_name: String,
+    pub size: usize,
+    pub attributes_supported: u64,
+    pub attributes_setting: u64,
+    pub compatibilities: u64,
+    pub lowest_supported_image_version: Option<u32>,
+    pub last_attempt_version: Option<u32>,
+    pub last_attempt_status: Option<u32>,
+    pub hardware_instance: Option<u64>,
+}
+
+/// Information returned by [`FmpDevice::get_image_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub descriptor_version: u32,
+    pub package_version: u32,
+    pub package_version_name: String,
+    pub descriptors: Vec<ImageDescriptor>,
+}
+
+// Progress callback of the set_image call in progress, the protocol progress function does not take a context.
+static PROGRESS_CALLBACK: AtomicPtr<&mut dyn FnMut(usize)> = AtomicPtr::new(ptr::null_mut());
+
+extern "efiapi" fn progress_trampoline(completion: usize) -> efi::Status {
+    // SAFETY: The pointer is only set for the duration of the set_image call that owns the callback.
+    if let Some(callback) = unsafe { PROGRESS_CALLBACK.load(Ordering::SeqCst).as_mut() } {
+        callback(completion);
+    }
+    efi::Status::SUCCESS
+}
+
+/// Rust friendly wrapper around an EFI_FIRMWARE_MANAGEMENT_PROTOCOL instance.
+///
+/// Strings allocated by the protocol are freed with the provided boot services.
+///
+/// # Example
+/// ```ignore
+/// BOOT_SERVICES.for_each_protocol(&FirmwareManagement, None, |_, fmp| {
+///     let info = FmpDevice::new(fmp, &BOOT_SERVICES).get_image_info().unwrap();
+///     for descriptor in info.descriptors {
+///         log::info!("{:?} version {}", descriptor.image_type_id, descriptor.version_name);
+///     }
+/// })?;
+/// ```
+pub struct FmpDevice<'a, B: BootServices> {
+    protocol: &'a mut Protocol,
+    boot_services: &'a B,
+}
+
+impl<'a, B: BootServices> FmpDevice<'a, B> {
+    /// Create a new FmpDevice from a firmware management protocol interface.
+    pub fn new(protocol: &'a mut Protocol, boot_services: &'a B) -> Self {
+        Self { protocol, boot_services }
+    }
+
+    /// Returns information about the current firmware images of the device.
+    ///
+    /// [UEFI Spec Documentation: 23.1.2. EFI_FIRMWARE_MANAGEMENT_PROTOCOL.GetImageInfo()](https://uefi.org/specs/UEFI/2.10/23_Firmware_Update_and_Reporting.html#efi-firmware-management-protocol-getimageinfo)
+    pub fn get_image_info(&mut self) -> Result<ImageInfo, efi::Status> {
+        let mut image_info_size = 0;
+        let mut descriptor_version = 0;
+        let mut descriptor_count = 0;
+        let mut descriptor_size = 0;
+        let mut package_version = 0;
+        let mut package_version_name = ptr::null_mut();
+
+        // Use u64 for descriptor alignment.
+        let mut buffer = Vec::<u64>::new();
+        loop {
+            match (self.protocol.get_image_info)(
+                self.protocol,
+                &mut image_info_size,
+                buffer.as_mut_ptr() as *mut ImageDescriptorRaw,
+                &mut descriptor_version,
+                &mut descriptor_count,
+                &mut descriptor_size,
+                &mut package_version,
+                &mut package_version_name,
+            ) {
+                s if s == efi::Status::BUFFER_TOO_SMALL && buffer.is_empty() => {
+                    buffer.resize(image_info_size.div_ceil(mem::size_of::<u64>()).max(1), 0)
+                }
+                s if s.is_error() => return Err(s),
+                _ => break,
+            }
+        }
+
+        // SAFETY: The strings are produced by the protocol and are null-terminated.
+        let package_version_name = unsafe { self.take_string(package_version_name) };
+
+        let bytes = buffer.as_ptr() as *const u8;
+        let descriptors = (0..descriptor_count as usize)
+            .filter(|i| (i + 1) * descriptor_size <= image_info_size)
+            .map(|i| {
+                // Only copy what the device reported, fields of newer versions stay zeroed.
+                let mut raw = MaybeUninit::<ImageDescriptorRaw>::zeroed();
+                // SAFETY: The descriptor is within the buffer filled by the protocol.
+                let raw = unsafe {
+                    ptr::copy_nonoverlapping(
+                        bytes.add(i * descriptor_size),
+                        raw.as_mut_ptr() as *mut u8,
+                        descriptor_size.min(mem::size_of::<ImageDescriptorRaw>()),
+                    );
+                    raw.assume_init()
+                };
+                ImageDescriptor {
+                    image_index: raw.image_index,
+                    image_type_id: raw.image_type_id,
+                    image_id: raw.image_id,
+                    // SAFETY: The descriptor strings are owned by the protocol and are null-terminated.
+                    image_id_name: unsafe { string_from_ptr(raw.image_id_name) },
+                    version: raw.version,
+                    version_name: unsafe { string_from_ptr(raw.version_name) },
+                    size: raw.size,
+                    attributes_supported: raw.attributes_supported,
+                    attributes_setting: raw.attributes_setting,
+                    compatibilities: raw.compatibilities,
+                    lowest_supported_image_version: (descriptor_version >= 2)
+                        .then_some(raw.lowest_supported_image_version),
+                    last_attempt_version: (descriptor_version >= 3).then_some(raw.last_attempt_version),
+                    last_attempt_status: (descriptor_version >= 3).then_some(raw.last_attempt_status),
+                    hardware_instance: (descriptor_version >= 3).then_some(raw.hardware_instance),
+                }
+            })
+            .collect();
+
+        Ok(ImageInfo { descriptor_version, package_version, package_version_name, descriptors })
+    }
+
+    /// Checks if *image* is a valid update for the firmware image at *image_index*.
+    ///
+    /// Returns the image updatable flags (`IMAGE_UPDATABLE_*`).
+    ///
+    /// [UEFI Spec Documentation: 23.1.5. EFI_FIRMWARE_MANAGEMENT_PROTOCOL.CheckImage()](https://uefi.org/specs/UEFI/2.10/23_Firmware_Update_and_Reporting.html#efi-firmware-management-protocol-checkimage)
+    pub fn check_image(&mut self, image_index: u8, image: &[u8]) -> Result<u32, efi::Status> {
+        let mut image_updatable = 0;
+        match (self.protocol.check_image)(
+            self.protocol,
+            image_index,
+            image.as_ptr() as *const c_void,
+            image.len(),
+            &mut image_updatable,
+        ) {
+            s if s.is_error() => Err(s),
+            _ => Ok(image_updatable),
+        }
+    }
+
+    /// Updates the firmware image at *image_index* with *image*.
+    ///
+    /// *progress* is called with the completion percentage (1 to 100) as the update goes on. On failure, the abort
+    /// reason reported by the device, if any, is returned with the status.
+    ///
+    /// [UEFI Spec Documentation: 23.1.4. EFI_FIRMWARE_MANAGEMENT_PROTOCOL.SetImage()](https://uefi.org/specs/UEFI/2.10/23_Firmware_Update_and_Reporting.html#efi-firmware-management-protocol-setimage)
+    pub fn set_image(
+        &mut self,
+        image_index: u8,
+        image: &[u8],
+        vendor_code: Option<&[u8]>,
+        progress: Option<&mut dyn FnMut(usize)>,
+    ) -> Result<(), (efi::Status, Option<String>)> {
+        let mut abort_reason = ptr::null_mut();
+
+        let (progress_fn, mut callback) = match progress {
+            Some(callback) => (Some(progress_trampoline as Progress), Some(callback)),
+            None => (None, None),
+        };
+        let previous_callback = PROGRESS_CALLBACK.swap(
+            callback.as_mut().map_or(ptr::null_mut(), |c| c as *mut &mut dyn FnMut(usize) as *mut _),
+            Ordering::SeqCst,
+        );
+
+        let status = (self.protocol.set_image)(
+            self.protocol,
+            image_index,
+            image.as_ptr() as *const c_void,
+            image.len(),
+            vendor_code.map_or(ptr::null(), |v| v.as_ptr() as *const c_void),
+            progress_fn,
+            &mut abort_reason,
+        );
+
+        PROGRESS_CALLBACK.store(previous_callback, Ordering::SeqCst);
+
+        // SAFETY: The abort reason is produced by the protocol and is null-terminated.
+        let abort_reason = unsafe { self.take_string(abort_reason) };
+        match status {
+            s if s.is_error() => Err((s, Some(abort_reason).filter(|r| !r.is_empty()))),
+            _ => Ok(()),
+        }
+    }
+
+    // Convert a string allocated from pool by the protocol and free it.
+    unsafe fn take_string(&self, string: *mut efi::Char16) -> String {
+        let s = string_from_ptr(string);
+        if !string.is_null() {
+            let _ = self.boot_services.free_pool(string as *mut u8);
+        }
+        s
+    }
+}
+
+unsafe fn string_from_ptr(string: *const efi::Char16) -> String {
+    if string.is_null() {
+        return String::new();
+    }
+    let mut len = 0;
+    while *string.add(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(core::slice::from_raw_parts(string, len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+
+    const IMAGE_TYPE_ID: efi::Guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+
+    fn leak_string(s: &str) -> *mut efi::Char16 {
+        Box::leak(s.encode_utf16().chain([0]).collect::<Vec<_>>().into_boxed_slice()).as_mut_ptr()
+    }
+
+    extern "efiapi" fn efi_get_image_info(
+        _this: *mut Protocol,
+        image_info_size: *mut usize,
+        image_info: *mut ImageDescriptorRaw,
+        descriptor_version: *mut u32,
+        descriptor_count: *mut u8,
+        descriptor_size: *mut usize,
+        package_version: *mut u32,
+        package_version_name: *mut *mut efi::Char16,
+    ) -> efi::Status {
+        // Report a version 1 descriptor to exercise the version handling.
+        let size = mem::offset_of!(ImageDescriptorRaw, lowest_supported_image_version);
+        unsafe {
+            if *image_info_size < 2 * size {
+                *image_info_size = 2 * size;
+                return efi::Status::BUFFER_TOO_SMALL;
+            }
+            for i in 0..2 {
+                let descriptor = ImageDescriptorRaw {
+                    image_index: i as u8 + 1,
+                    image_type_id: IMAGE_TYPE_ID,
+                    image_id: 0x10 + i as u64,
+                    image_id_name: leak_string("image"),
+                    version: 0x100 + i as u32,
+                    version_name: leak_string("1.0"),
+                    size: 0x1000,
+                    attributes_supported: IMAGE_ATTRIBUTE_IMAGE_UPDATABLE,
+                    attributes_setting: IMAGE_ATTRIBUTE_IMAGE_UPDATABLE,
+                    compatibilities: 0,
+                    lowest_supported_image_version: 0xFFFF,
+                    last_attempt_version: 0,
+                    last_attempt_status: 0,
+                    hardware_instance: 0,
+                    dependencies: ptr::null_mut(),
+                };
+                ptr::copy_nonoverlapping(
+                    &descriptor as *const _ as *const u8,
+                    (image_info as *mut u8).add(i * size),
+                    size,
+                );
+            }
+            *image_info_size = 2 * size;
+            *descriptor_version = 1;
+            *descriptor_count = 2;
+            *descriptor_size = size;
+            *package_version = 7;
+            *package_version_name = leak_string("package");
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_get_image(
+        _this: *mut Protocol,
+        _image_index: u8,
+        _image: *mut c_void,
+        _image_size: *mut usize,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn efi_set_image(
+        _this: *mut Protocol,
+        image_index: u8,
+        _image: *const c_void,
+        image_size: usize,
+        vendor_code: *const c_void,
+        progress: Option<Progress>,
+        abort_reason: *mut *mut efi::Char16,
+    ) -> efi::Status {
+        assert!(vendor_code.is_null());
+        if let Some(progress) = progress {
+            progress(50);
+            progress(100);
+        }
+        if image_index != 1 {
+            unsafe { *abort_reason = leak_string("bad index") };
+            return efi::Status::INVALID_PARAMETER;
+        }
+        assert_eq!(4, image_size);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_check_image(
+        _this: *mut Protocol,
+        image_index: u8,
+        _image: *const c_void,
+        _image_size: usize,
+        image_updatable: *mut u32,
+    ) -> efi::Status {
+        unsafe {
+            *image_updatable = if image_index == 1 { IMAGE_UPDATABLE_VALID } else { IMAGE_UPDATABLE_INVALID_TYPE }
+        };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_get_package_info(
+        _this: *mut Protocol,
+        _package_version: *mut u32,
+        _package_version_name: *mut *mut efi::Char16,
+        _package_version_name_max_len: *mut u32,
+        _attributes_supported: *mut u64,
+        _attributes_setting: *mut u64,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn efi_set_package_info(
+        _this: *mut Protocol,
+        _image: *const c_void,
+        _image_size: usize,
+        _vendor_code: *const c_void,
+        _package_version: u32,
+        _package_version_name: *const efi::Char16,
+    ) -> efi::Status {
+        efi::Status::UNSUPPORTED
+    }
+
+    fn protocol() -> Protocol {
+        Protocol {
+            get_image_info: efi_get_image_info,
+            get_image: efi_get_image,
+            set_image: efi_set_image,
+            check_image: efi_check_image,
+            get_package_info: efi_get_package_info,
+            set_package_info: efi_set_package_info,
+        }
+    }
+
+    fn boot_services(nb_free: usize) -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_free_pool().times(nb_free).returning(|_| Ok(()));
+        boot_services
+    }
+
+    #[test]
+    fn test_get_image_info() {
+        let boot_services = boot_services(1);
+        let mut protocol = protocol();
+        let mut fmp = FmpDevice::new(&mut protocol, &boot_services);
+
+        let info = fmp.get_image_info().unwrap();
+        assert_eq!(1, info.descriptor_version);
+        assert_eq!(7, info.package_version);
+        assert_eq!("package", info.package_version_name);
+        assert_eq!(2, info.descriptors.len());
+
+        let descriptor = &info.descriptors[1];
+        assert_eq!(2, descriptor.image_index);
+        assert_eq!(IMAGE_TYPE_ID, descriptor.image_type_id);
+        assert_eq!(0x11, descriptor.image_id);
+        assert_eq!("image", descriptor.image_id_name);
+        assert_eq!(0x101, descriptor.version);
+        assert_eq!("1.0", descriptor.version_name);
+        assert_eq!(None, descriptor.lowest_supported_image_version);
+        assert_eq!(None, descriptor.hardware_instance);
+    }
+
+    #[test]
+    fn test_check_image() {
+        let boot_services = boot_services(0);
+        let mut protocol = protocol();
+        let mut fmp = FmpDevice::new(&mut protocol, &boot_services);
+
+        assert_eq!(Ok(IMAGE_UPDATABLE_VALID), fmp.check_image(1, &[0; 4]));
+        assert_eq!(Ok(IMAGE_UPDATABLE_INVALID_TYPE), fmp.check_image(2, &[0; 4]));
+    }
+
+    #[test]
+    fn test_set_image() {
+        let boot_services = boot_services(1);
+        let mut protocol = protocol();
+        let mut fmp = FmpDevice::new(&mut protocol, &boot_services);
+
+        let mut completions = Vec::new();
+        fmp.set_image(1, &[0; 4], None, Some(&mut |completion| completions.push(completion))).unwrap();
+        assert_eq!(vec![50, 100], completions);
+
+        assert_eq!(
+            Err((efi::Status::INVALID_PARAMETER, Some(String::from("bad index")))),
+            fmp.set_image(2, &[0; 4], None, None)
+        );
+    }
+}
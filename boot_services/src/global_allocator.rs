@@ -1,10 +1,41 @@
 use core::{
     alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
     ops::Deref,
     ptr,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::{allocation::MemoryType, BootServices};
+use crate::{allocation::MemoryType, tpl::Tpl, BootServices};
+
+/// Maximum number of nested [`BootServicesGlobalAllocator::with_memory_type`] scopes.
+pub const MAX_MEMORY_TYPE_OVERRIDES: usize = 16;
+
+// Stack of the memory type overrides, with the TPL of the scope that pushed each one. It is only modified at
+// TPL_HIGH_LEVEL, so no other code can run in between.
+struct MemoryTypeOverrides {
+    depth: AtomicUsize,
+    stack: UnsafeCell<[(MemoryType, Tpl); MAX_MEMORY_TYPE_OVERRIDES]>,
+}
+
+// SAFETY: The stack is only accessed at TPL_HIGH_LEVEL.
+unsafe impl Sync for MemoryTypeOverrides {}
+
+static MEMORY_TYPE_OVERRIDES: MemoryTypeOverrides = MemoryTypeOverrides {
+    depth: AtomicUsize::new(0),
+    stack: UnsafeCell::new([(MemoryType::BOOT_SERVICES_DATA, Tpl::APPLICATION); MAX_MEMORY_TYPE_OVERRIDES]),
+};
+
+// Pops the memory type override pushed by with_memory_type, even if the closure panics.
+struct MemoryTypeOverrideGuard<'a, T: BootServices>(&'a T);
+
+impl<T: BootServices> Drop for MemoryTypeOverrideGuard<'_, T> {
+    fn drop(&mut self) {
+        let tpl = self.0.raise_tpl(Tpl::HIGH_LEVEL);
+        MEMORY_TYPE_OVERRIDES.depth.fetch_sub(1, Ordering::Relaxed);
+        self.0.restore_tpl(tpl);
+    }
+}
 
 pub struct BootServicesGlobalAllocator<T: BootServices + 'static>(pub &'static T);
 
@@ -17,15 +48,64 @@ impl<T: BootServices> Deref for BootServicesGlobalAllocator<T> {
 }
 
 impl<T: BootServices> BootServicesGlobalAllocator<T> {
+    /// Runs *f* with the allocations of the global allocator made from *memory_type* pool, e.g.
+    /// [`MemoryType::RUNTIME_SERVICES_DATA`] for data that must survive exit boot services.
+    ///
+    /// The override only applies to the allocations made at the TPL *f* is called at, so the event notify functions
+    /// interrupting *f* keep allocating from [`MemoryType::BOOT_SERVICES_DATA`]. Scopes can be nested up to
+    /// [`MAX_MEMORY_TYPE_OVERRIDES`] deep, the innermost one applies.
+    ///
+    /// ```ignore
+    /// let runtime_data = ALLOCATOR.with_memory_type(MemoryType::RUNTIME_SERVICES_DATA, || Box::new(RuntimeData::new()));
+    /// ```
+    ///
+    /// # Panics
+    /// If the scopes are nested more than [`MAX_MEMORY_TYPE_OVERRIDES`] deep.
+    pub fn with_memory_type<R>(&self, memory_type: MemoryType, f: impl FnOnce() -> R) -> R {
+        let tpl = self.raise_tpl(Tpl::HIGH_LEVEL);
+        let depth = MEMORY_TYPE_OVERRIDES.depth.load(Ordering::Relaxed);
+        if depth == MAX_MEMORY_TYPE_OVERRIDES {
+            self.restore_tpl(tpl);
+            panic!("Too many nested memory type overrides.");
+        }
+        // SAFETY: The TPL is raised to TPL_HIGH_LEVEL.
+        unsafe { (*MEMORY_TYPE_OVERRIDES.stack.get())[depth] = (memory_type, tpl) };
+        MEMORY_TYPE_OVERRIDES.depth.store(depth + 1, Ordering::Relaxed);
+        self.restore_tpl(tpl);
+
+        let _override_guard = MemoryTypeOverrideGuard(self.0);
+        f()
+    }
+
+    // Returns the memory type to allocate from at the current TPL.
+    fn memory_type(&self) -> MemoryType {
+        if MEMORY_TYPE_OVERRIDES.depth.load(Ordering::Relaxed) == 0 {
+            return MemoryType::BOOT_SERVICES_DATA;
+        }
+        let tpl = self.raise_tpl(Tpl::HIGH_LEVEL);
+        let memory_type = match MEMORY_TYPE_OVERRIDES.depth.load(Ordering::Relaxed) {
+            0 => MemoryType::BOOT_SERVICES_DATA,
+            depth => {
+                // SAFETY: The TPL is raised to TPL_HIGH_LEVEL.
+                match unsafe { (*MEMORY_TYPE_OVERRIDES.stack.get())[depth - 1] } {
+                    (memory_type, override_tpl) if override_tpl == tpl => memory_type,
+                    _ => MemoryType::BOOT_SERVICES_DATA,
+                }
+            }
+        };
+        self.restore_tpl(tpl);
+        memory_type
+    }
+
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         match layout.align() {
-            0..=8 => self.allocate_pool(MemoryType::BOOT_SERVICES_DATA, layout.size()).unwrap_or(ptr::null_mut()),
+            0..=8 => self.allocate_pool(self.memory_type(), layout.size()).unwrap_or(ptr::null_mut()),
             _ => {
                 let Ok((extended_layout, tracker_offset)) = layout.extend(Layout::new::<*mut *mut u8>()) else {
                     return ptr::null_mut();
                 };
                 let alloc_size = extended_layout.align() + extended_layout.size();
-                let Ok(original_ptr) = self.allocate_pool(MemoryType::BOOT_SERVICES_DATA, alloc_size) else {
+                let Ok(original_ptr) = self.allocate_pool(self.memory_type(), alloc_size) else {
                     return ptr::null_mut();
                 };
                 let ptr = original_ptr.add(original_ptr.align_offset(extended_layout.align()));
@@ -61,3 +141,59 @@ unsafe impl<T: BootServices> GlobalAlloc for BootServicesGlobalAllocator<T> {
         BootServicesGlobalAllocator::dealloc(&self, ptr, layout)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use std::{
+        boxed::Box,
+        sync::{Arc, Mutex},
+        vec::Vec,
+    };
+
+    #[test]
+    fn test_with_memory_type() {
+        // The TPL raise_tpl returns, and the memory type of each allocation.
+        let tpl = Arc::new(Mutex::new(Tpl::APPLICATION));
+        let memory_types = Arc::new(Mutex::new(Vec::new()));
+
+        let mut boot_services = MockBootServices::new();
+        let current_tpl = tpl.clone();
+        boot_services.expect_raise_tpl().returning(move |_| *current_tpl.lock().unwrap());
+        boot_services.expect_restore_tpl().return_const(());
+        let allocated_types = memory_types.clone();
+        boot_services.expect_allocate_pool().returning(move |memory_type, size| {
+            allocated_types.lock().unwrap().push(memory_type);
+            Ok(Box::leak(std::vec![0_u8; size].into_boxed_slice()).as_mut_ptr())
+        });
+        let allocator = BootServicesGlobalAllocator(Box::leak(Box::new(boot_services)));
+        let layout = Layout::new::<u64>();
+
+        unsafe {
+            allocator.alloc(layout);
+            allocator.with_memory_type(MemoryType::RUNTIME_SERVICES_DATA, || {
+                allocator.alloc(layout);
+                allocator.with_memory_type(MemoryType::ACPI_RECLAIM_MEMORY, || allocator.alloc(layout));
+                allocator.alloc(Layout::from_size_align(64, 64).unwrap());
+                // An event notify function interrupting the scope.
+                *tpl.lock().unwrap() = Tpl::CALLBACK;
+                allocator.alloc(layout);
+                *tpl.lock().unwrap() = Tpl::APPLICATION;
+            });
+            allocator.alloc(layout);
+        }
+
+        assert_eq!(
+            std::vec![
+                MemoryType::BOOT_SERVICES_DATA,
+                MemoryType::RUNTIME_SERVICES_DATA,
+                MemoryType::ACPI_RECLAIM_MEMORY,
+                MemoryType::RUNTIME_SERVICES_DATA,
+                MemoryType::BOOT_SERVICES_DATA,
+                MemoryType::BOOT_SERVICES_DATA,
+            ],
+            *memory_types.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! This module defines the [`Handle`] type, a non null EFI handle that can not be mixed up with an interface pointer.
+//!
+//! A nullable handle is an `Option<Handle>`, which has the same representation as an [`efi::Handle`].
+//!
+//! With the `typed_handle` feature, [`TypedHandles`] provides the handle related boot services with [`Handle`] in
+//! their signatures, to migrate call sites one at a time.
+//!
+//! ```ignore
+//! let handles = BOOT_SERVICES.locate_handles(HandleSearchType::ByProtocol(&efi::protocols::block_io::PROTOCOL_GUID))?;
+//! for handle in handles {
+//!     BOOT_SERVICES.connect_handle(handle, true)?;
+//! }
+//! ```
+
+use core::{ffi::c_void, fmt, ptr::NonNull};
+
+use r_efi::efi;
+
+#[cfg(feature = "typed_handle")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "typed_handle")]
+use crate::{
+    protocol_handler::{HandleSearchType, Protocol},
+    BootServices,
+};
+
+/// A non null EFI handle.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(NonNull<c_void>);
+
+// SAFETY: A handle is an opaque identifier that is never dereferenced.
+unsafe impl Send for Handle {}
+// SAFETY: A handle is an opaque identifier that is never dereferenced.
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    /// Create a handle from an [`efi::Handle`], None if it is null.
+    pub fn new(handle: efi::Handle) -> Option<Self> {
+        NonNull::new(handle).map(Self)
+    }
+
+    /// Create a handle from an [`efi::Handle`] without checking it.
+    ///
+    /// # Safety
+    ///
+    /// *handle* must not be null.
+    pub const unsafe fn new_unchecked(handle: efi::Handle) -> Self {
+        Self(NonNull::new_unchecked(handle))
+    }
+
+    /// Returns the [`efi::Handle`] of this handle.
+    pub const fn as_ptr(self) -> efi::Handle {
+        self.0.as_ptr()
+    }
+
+    /// Returns the [`efi::Handle`] of *handle*, null if None.
+    pub fn option_as_ptr(handle: Option<Self>) -> efi::Handle {
+        handle.map_or(core::ptr::null_mut(), Self::as_ptr)
+    }
+}
+
+impl From<Handle> for efi::Handle {
+    fn from(handle: Handle) -> Self {
+        handle.as_ptr()
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({:p})", self.0)
+    }
+}
+
+// Converts a handle returned by a successful boot service call, which is never null.
+#[cfg(feature = "typed_handle")]
+fn returned_handle(handle: efi::Handle) -> Result<Handle, efi::Status> {
+    debug_assert!(!handle.is_null(), "Boot service returned a null handle.");
+    Handle::new(handle).ok_or(efi::Status::DEVICE_ERROR)
+}
+
+/// The handle related [`BootServices`] with [`Handle`] rather than [`efi::Handle`] in their signatures.
+///
+/// Implemented for every [`BootServices`], see the wrapped method for the documentation of each method.
+#[cfg(feature = "typed_handle")]
+pub trait TypedHandles: BootServices + Sized {
+    /// [`BootServices::locate_handle_buffer`] returning the handles in a [`Vec`].
+    fn locate_handles(&self, search_type: HandleSearchType) -> Result<Vec<Handle>, efi::Status> {
+        let handles = self.locate_handle_buffer(search_type)?;
+        handles.iter().map(|&handle| returned_handle(handle)).collect()
+    }
+
+    /// [`BootServices::handle_protocol`] on *handle*.
+    ///
+    /// # Safety
+    /// Make sure to not create multiple mutable reference of interface.
+    unsafe fn protocol_of_handle<P: Protocol<Interface = I> + 'static, I: 'static>(
+        &self,
+        handle: Handle,
+        protocol: &P,
+    ) -> Result<&'static mut I, efi::Status> {
+        self.handle_protocol(handle.as_ptr(), protocol)
+    }
+
+    /// [`BootServices::connect_controller`] on *controller_handle* with every driver.
+    fn connect_handle(&self, controller_handle: Handle, recursive: bool) -> Result<(), efi::Status> {
+        //SAFETY: No driver image handles are given.
+        unsafe { self.connect_controller(controller_handle.as_ptr(), Vec::new(), core::ptr::null_mut(), recursive) }
+    }
+
+    /// [`BootServices::disconnect_controller`] on *controller_handle*.
+    fn disconnect_handle(
+        &self,
+        controller_handle: Handle,
+        driver_image_handle: Option<Handle>,
+        child_handle: Option<Handle>,
+    ) -> Result<(), efi::Status> {
+        self.disconnect_controller(
+            controller_handle.as_ptr(),
+            driver_image_handle.map(Handle::as_ptr),
+            child_handle.map(Handle::as_ptr),
+        )
+    }
+
+    /// [`BootServices::load_image`] returning the handle of the loaded image.
+    fn load_image_handle(
+        &self,
+        boot_policy: bool,
+        parent_image_handle: Handle,
+        device_path: *mut efi::protocols::device_path::Protocol,
+        source_buffer: Option<&[u8]>,
+    ) -> Result<Handle, efi::Status> {
+        returned_handle(self.load_image(boot_policy, parent_image_handle.as_ptr(), device_path, source_buffer)?)
+    }
+
+    /// [`BootServices::start_image`] on *image_handle*, dropping the exit data.
+    fn start_image_handle(&self, image_handle: Handle) -> Result<(), efi::Status> {
+        self.start_image(image_handle.as_ptr()).map_err(|(status, _)| status)
+    }
+
+    /// [`BootServices::unload_image`] on *image_handle*.
+    fn unload_image_handle(&self, image_handle: Handle) -> Result<(), efi::Status> {
+        self.unload_image(image_handle.as_ptr())
+    }
+}
+
+#[cfg(feature = "typed_handle")]
+impl<B: BootServices> TypedHandles for B {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::mem;
+
+    #[test]
+    fn test_handle() {
+        assert_eq!(mem::size_of::<efi::Handle>(), mem::size_of::<Option<Handle>>());
+        assert_eq!(None, Handle::new(core::ptr::null_mut()));
+
+        let handle = Handle::new(0x1000 as efi::Handle).unwrap();
+        assert_eq!(0x1000, handle.as_ptr() as usize);
+        assert_eq!(0x1000, efi::Handle::from(handle) as usize);
+        assert_eq!("Handle(0x1000)", std::format!("{handle:?}"));
+        assert_eq!(0x1000, Handle::option_as_ptr(Some(handle)) as usize);
+        assert!(Handle::option_as_ptr(None).is_null());
+    }
+
+    #[test]
+    #[cfg(feature = "typed_handle")]
+    fn test_typed_handles() {
+        use crate::MockBootServices;
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_load_image().returning(|_, parent, _, _| {
+            assert_eq!(1, parent as usize);
+            Ok(2_usize as efi::Handle)
+        });
+        boot_services.expect_disconnect_controller().returning(|controller, driver, child| {
+            assert_eq!((2, None), (controller as usize, driver));
+            assert_eq!(Some(3), child.map(|h| h as usize));
+            Ok(())
+        });
+        boot_services.expect_unload_image().returning(|image| match image as usize {
+            1 => Ok(()),
+            _ => Err(efi::Status::INVALID_PARAMETER),
+        });
+
+        let parent = Handle::new(1_usize as efi::Handle).unwrap();
+        let image = boot_services.load_image_handle(false, parent, core::ptr::null_mut(), Some(&[0])).unwrap();
+        let handles = [parent, image];
+        assert_eq!(Ok(()), boot_services.disconnect_handle(handles[1], None, Handle::new(3_usize as efi::Handle)));
+        assert_eq!(Ok(()), boot_services.unload_image_handle(handles[0]));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), boot_services.unload_image_handle(handles[1]));
+    }
+}
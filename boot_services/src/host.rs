@@ -0,0 +1,1289 @@
+//! Boot services emulated on the host, so integration tests and host-based emulation environments run Rust firmware
+//! code unmodified through [`StandardBootServices`].
+//!
+//! [`HostBootServices`] provides an [`efi::BootServices`] table backed by the standard library: pool and pages are
+//! allocated on the host heap and protocols are installed in an in-memory handle database. The services with no host
+//! equivalent, such as LoadImage or ConnectController, return `efi::Status::UNSUPPORTED`.
+//!
+//! ```ignore
+//! let host = HostBootServices::new();
+//! let boot_services = host.standard_boot_services();
+//! let event = boot_services.create_event(EventType::TIMER, Tpl::CALLBACK, None, ())?;
+//! boot_services.set_timer(event, EventTimerType::Relative, 10_000)?;
+//! boot_services.wait_for_event(&mut [event])?;
+//! ```
+//!
+//! The events follow the TPL rules of the DXE core: the notify functions are queued when their event is signaled, and
+//! called on the calling thread once the TPL drops below their notify TPL, the highest TPL first. The timers are
+//! checked when the TPL is restored and while waiting, stalling, signaling or checking events, which stands for the
+//! timer interrupt of the firmware.
+//!
+//! Each instance has its own TPL, events and handle database. The table of an instance only works on the thread that
+//! created it, which can create a single instance at a time, so the tests running on their own threads never see the
+//! state of each other.
+
+extern crate std;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use core::{cell::RefCell, ffi::c_void, marker::PhantomData, mem, ptr};
+use std::{
+    alloc::{self as heap, Layout},
+    thread,
+    time::{Duration, Instant},
+};
+
+use r_efi::efi;
+
+use crate::{allocation::UEFI_PAGE_SIZE, crc32::crc32, StandardBootServices};
+
+// Pool allocations are prefixed by their size, on a header keeping the allocation aligned on 16 bytes.
+const POOL_HEADER_SIZE: usize = 16;
+
+// The shortest period of a periodic timer, the timer tick of the emulation.
+const TIMER_TICK: Duration = Duration::from_millis(1);
+
+std::thread_local! {
+    // The state of the instance created by the thread, if any.
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    deadline: Instant,
+    period: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Event {
+    event_type: u32,
+    notify_tpl: efi::Tpl,
+    notify_function: Option<efi::EventNotify>,
+    notify_context: *mut c_void,
+    group: Option<efi::Guid>,
+    // Set when signaled, until checked or until its notify function is called.
+    signaled: bool,
+    timer: Option<Timer>,
+}
+
+#[derive(Debug)]
+struct ProtocolEntry {
+    guid: &'static efi::Guid,
+    interface: *mut c_void,
+}
+
+#[derive(Debug)]
+struct State {
+    tpl: efi::Tpl,
+    monotonic_count: u64,
+    next_id: usize,
+    map_key: usize,
+    events: BTreeMap<usize, Event>,
+    // Events whose notify function is to be called, in the order they were signaled.
+    pending: VecDeque<usize>,
+    // Handles in installation order, with their protocols.
+    handles: Vec<(usize, Vec<ProtocolEntry>)>,
+    // Address to number of pages and memory type.
+    pages: BTreeMap<usize, (usize, efi::MemoryType)>,
+    configuration_table: Vec<efi::ConfigurationTable>,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            tpl: efi::TPL_APPLICATION,
+            monotonic_count: 0,
+            next_id: 1,
+            map_key: 0,
+            events: BTreeMap::new(),
+            pending: VecDeque::new(),
+            handles: Vec::new(),
+            pages: BTreeMap::new(),
+            configuration_table: Vec::new(),
+        }
+    }
+
+    // Handles and events are identified by non null ids, never reused.
+    fn new_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn protocols(&mut self, handle: efi::Handle) -> Option<&mut Vec<ProtocolEntry>> {
+        self.handles.iter_mut().find(|(h, _)| *h == handle as usize).map(|(_, protocols)| protocols)
+    }
+
+    fn interface(&mut self, handle: efi::Handle, guid: &efi::Guid) -> Option<*mut c_void> {
+        self.protocols(handle)?.iter().find(|p| p.guid == guid).map(|p| p.interface)
+    }
+
+    fn find_handles(
+        &self,
+        search_type: efi::LocateSearchType,
+        guid: *const efi::Guid,
+    ) -> Result<Vec<usize>, efi::Status> {
+        let handles: Vec<usize> = match search_type {
+            efi::ALL_HANDLES => self.handles.iter().map(|(handle, _)| *handle).collect(),
+            efi::BY_PROTOCOL if !guid.is_null() => {
+                // SAFETY: The GUID is provided by the caller of the service.
+                let guid = unsafe { &*guid };
+                self.handles.iter().filter(|(_, p)| p.iter().any(|p| p.guid == guid)).map(|(h, _)| *h).collect()
+            }
+            efi::BY_PROTOCOL => return Err(efi::Status::INVALID_PARAMETER),
+            _ => return Err(efi::Status::UNSUPPORTED),
+        };
+        match handles.is_empty() {
+            true => Err(efi::Status::NOT_FOUND),
+            false => Ok(handles),
+        }
+    }
+
+    // Signals the event *id* if it is not signaled yet, queuing its notify function for a notify signal event.
+    fn signal(&mut self, id: usize) {
+        let Some(event) = self.events.get_mut(&id).filter(|event| !event.signaled) else {
+            return;
+        };
+        event.signaled = true;
+        if event.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+            self.queue_notify(id);
+        }
+    }
+
+    fn queue_notify(&mut self, id: usize) {
+        if !self.pending.contains(&id) {
+            self.pending.push_back(id);
+        }
+    }
+
+    // Signals the timer events whose trigger time passed.
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .events
+            .iter()
+            .filter(|(_, event)| event.timer.is_some_and(|timer| timer.deadline <= now))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        for id in expired {
+            let event = self.events.get_mut(&id).unwrap();
+            // A periodic timer late by several periods is signaled once.
+            event.timer = event.timer.and_then(|timer| {
+                let period = timer.period?;
+                Some(Timer { deadline: (timer.deadline + period).max(now), period: Some(period) })
+            });
+            self.signal(id);
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.events.values().filter_map(|event| event.timer).map(|timer| timer.deadline).min()
+    }
+
+    // Takes the pending notification with the highest notify TPL above *tpl*, the first signaled of that TPL.
+    fn take_notification(&mut self, tpl: efi::Tpl) -> Option<(usize, efi::Tpl, efi::EventNotify, *mut c_void)> {
+        let (idx, _) = self
+            .pending
+            .iter()
+            .enumerate()
+            .map(|(idx, id)| (idx, self.events[id].notify_tpl))
+            .filter(|&(_, notify_tpl)| notify_tpl > tpl)
+            .min_by_key(|&(idx, notify_tpl)| (core::cmp::Reverse(notify_tpl), idx))?;
+        let id = self.pending.remove(idx)?;
+        let event = self.events.get_mut(&id)?;
+        if event.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+            event.signaled = false;
+        }
+        Some((id, event.notify_tpl, event.notify_function?, event.notify_context))
+    }
+}
+
+// Runs *f* on the state of the instance of the current thread.
+//
+// The state is borrowed for the duration of *f*, which must not call the notify functions.
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    STATE.with_borrow_mut(|state| f(state.as_mut().expect("No HostBootServices was created by this thread.")))
+}
+
+// Calls the notify functions pending above *tpl*, each at its notify TPL, then sets the TPL to *tpl*, as RestoreTPL
+// does in the DXE core.
+fn dispatch(tpl: efi::Tpl) {
+    loop {
+        let notification = with_state(|state| {
+            state.tick();
+            let notification = state.take_notification(tpl);
+            state.tpl = notification.map_or(tpl, |(_, notify_tpl, _, _)| notify_tpl);
+            notification
+        });
+        match notification {
+            Some((id, _, notify_function, notify_context)) => notify_function(id as efi::Event, notify_context),
+            None => return,
+        }
+    }
+}
+
+fn current_tpl() -> efi::Tpl {
+    with_state(|state| state.tpl)
+}
+
+/// Boot services emulated on the host, see the [module](self) documentation.
+///
+/// The state of the emulation lives as long as the instance. The pages and pool still allocated when it is dropped are
+/// left to the host heap.
+pub struct HostBootServices {
+    table: Box<efi::BootServices>,
+    boot_services: StandardBootServices<'static>,
+    // The table only works on the thread owning the state.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl HostBootServices {
+    /// Create the emulated boot services of the current thread.
+    ///
+    /// # Panics
+    /// This function will panic if the thread already has an instance.
+    pub fn new() -> Self {
+        STATE.with_borrow_mut(|state| {
+            assert!(state.is_none(), "This thread already has a HostBootServices.");
+            *state = Some(State::new());
+        });
+        let table = Box::new(efi_boot_services_table());
+        // SAFETY: The table is boxed, so it does not move and lives as long as the instance.
+        let boot_services = unsafe { StandardBootServices::from_raw_ptr(&*table as *const _ as *mut _) };
+        Self { table, boot_services, _not_send: PhantomData }
+    }
+
+    /// Returns the emulated boot services table, with a valid table header.
+    pub fn efi_boot_services(&self) -> &efi::BootServices {
+        &self.table
+    }
+
+    /// Returns a [`StandardBootServices`] using the emulated boot services table.
+    pub fn standard_boot_services(&self) -> &StandardBootServices<'_> {
+        &self.boot_services
+    }
+
+    /// Returns the vendor tables installed with InstallConfigurationTable.
+    pub fn configuration_table(&self) -> Vec<efi::ConfigurationTable> {
+        with_state(|state| state.configuration_table.iter().map(|t| efi::ConfigurationTable { ..*t }).collect())
+    }
+}
+
+impl Default for HostBootServices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HostBootServices {
+    fn drop(&mut self) {
+        STATE.set(None);
+    }
+}
+
+fn efi_boot_services_table() -> efi::BootServices {
+    let mut table = efi::BootServices {
+        hdr: efi::TableHeader {
+            signature: efi::BOOT_SERVICES_SIGNATURE,
+            revision: efi::SPECIFICATION_REVISION,
+            header_size: mem::size_of::<efi::BootServices>() as u32,
+            crc32: 0,
+            reserved: 0,
+        },
+        raise_tpl,
+        restore_tpl,
+        allocate_pages,
+        free_pages,
+        get_memory_map,
+        allocate_pool,
+        free_pool,
+        create_event,
+        set_timer,
+        wait_for_event,
+        signal_event,
+        close_event,
+        check_event,
+        install_protocol_interface,
+        reinstall_protocol_interface,
+        uninstall_protocol_interface,
+        handle_protocol,
+        reserved: ptr::null_mut(),
+        register_protocol_notify,
+        locate_handle,
+        locate_device_path,
+        install_configuration_table,
+        load_image,
+        start_image,
+        exit,
+        unload_image,
+        exit_boot_services,
+        get_next_monotonic_count,
+        stall,
+        set_watchdog_timer,
+        connect_controller,
+        disconnect_controller,
+        open_protocol,
+        close_protocol,
+        open_protocol_information,
+        protocols_per_handle,
+        locate_handle_buffer,
+        locate_protocol,
+        install_multiple_protocol_interfaces,
+        uninstall_multiple_protocol_interfaces,
+        calculate_crc32,
+        copy_mem,
+        set_mem,
+        create_event_ex,
+    };
+    // SAFETY: The table is a plain structure of the size of its header.
+    table.hdr.crc32 =
+        crc32(unsafe { core::slice::from_raw_parts(&table as *const _ as *const u8, mem::size_of_val(&table)) });
+    table
+}
+
+extern "efiapi" fn raise_tpl(new_tpl: efi::Tpl) -> efi::Tpl {
+    with_state(|state| mem::replace(&mut state.tpl, new_tpl))
+}
+
+extern "efiapi" fn restore_tpl(old_tpl: efi::Tpl) {
+    dispatch(old_tpl)
+}
+
+// Layout of *pages* pages, None if their size overflows.
+fn pages_layout(pages: usize) -> Option<Layout> {
+    Layout::from_size_align(pages.checked_mul(UEFI_PAGE_SIZE)?, UEFI_PAGE_SIZE).ok()
+}
+
+extern "efiapi" fn allocate_pages(
+    allocate_type: efi::AllocateType,
+    memory_type: efi::MemoryType,
+    pages: usize,
+    memory: *mut efi::PhysicalAddress,
+) -> efi::Status {
+    if memory.is_null() || pages == 0 {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Some(layout) = pages_layout(pages) else {
+        return efi::Status::OUT_OF_RESOURCES;
+    };
+    // SAFETY: The layout is not empty.
+    let address = unsafe { heap::alloc_zeroed(layout) } as usize;
+    if address == 0 {
+        return efi::Status::OUT_OF_RESOURCES;
+    }
+    // SAFETY: The address is written by the caller for the allocated pages.
+    let max_address = unsafe { *memory } as usize;
+    let accepted = match allocate_type {
+        efi::ALLOCATE_ANY_PAGES => true,
+        efi::ALLOCATE_MAX_ADDRESS => address + layout.size() - 1 <= max_address,
+        // The host heap can not allocate at a given address.
+        _ => false,
+    };
+    if !accepted {
+        // SAFETY: The pages were just allocated with this layout.
+        unsafe { heap::dealloc(address as *mut u8, layout) };
+        return efi::Status::NOT_FOUND;
+    }
+
+    with_state(|state| {
+        state.pages.insert(address, (pages, memory_type));
+        state.map_key += 1;
+        // SAFETY: The caller provides the address to write.
+        unsafe { memory.write(address as efi::PhysicalAddress) };
+        efi::Status::SUCCESS
+    })
+}
+
+extern "efiapi" fn free_pages(memory: efi::PhysicalAddress, pages: usize) -> efi::Status {
+    with_state(|state| {
+        match (state.pages.get(&(memory as usize)), pages_layout(pages)) {
+            (Some(&(allocated, _)), Some(layout)) if allocated == pages => {
+                state.pages.remove(&(memory as usize));
+                state.map_key += 1;
+                // SAFETY: The pages were allocated with this layout by allocate_pages.
+                unsafe { heap::dealloc(memory as usize as *mut u8, layout) };
+                efi::Status::SUCCESS
+            }
+            _ => efi::Status::NOT_FOUND,
+        }
+    })
+}
+
+// The memory map only describes the pages allocated by allocate_pages, the rest of the host memory is not owned by the
+// emulation.
+extern "efiapi" fn get_memory_map(
+    memory_map_size: *mut usize,
+    memory_map: *mut efi::MemoryDescriptor,
+    map_key: *mut usize,
+    descriptor_size: *mut usize,
+    descriptor_version: *mut u32,
+) -> efi::Status {
+    if memory_map_size.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    with_state(|state| {
+        let size = state.pages.len() * mem::size_of::<efi::MemoryDescriptor>();
+        // SAFETY: The pointers are provided by the caller, the map buffer is checked against its size.
+        unsafe {
+            let buffer_size = memory_map_size.replace(size);
+            if !map_key.is_null() {
+                map_key.write(state.map_key);
+            }
+            if !descriptor_size.is_null() {
+                descriptor_size.write(mem::size_of::<efi::MemoryDescriptor>());
+            }
+            if !descriptor_version.is_null() {
+                descriptor_version.write(efi::MEMORY_DESCRIPTOR_VERSION);
+            }
+            if buffer_size < size {
+                return efi::Status::BUFFER_TOO_SMALL;
+            }
+            if memory_map.is_null() {
+                return efi::Status::INVALID_PARAMETER;
+            }
+            for (idx, (&address, &(pages, memory_type))) in state.pages.iter().enumerate() {
+                memory_map.add(idx).write(efi::MemoryDescriptor {
+                    r#type: memory_type,
+                    physical_start: address as u64,
+                    virtual_start: 0,
+                    number_of_pages: pages as u64,
+                    attribute: efi::MEMORY_WB,
+                });
+            }
+        }
+        efi::Status::SUCCESS
+    })
+}
+
+extern "efiapi" fn allocate_pool(_pool_type: efi::MemoryType, size: usize, buffer: *mut *mut c_void) -> efi::Status {
+    if buffer.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let Ok(layout) = Layout::from_size_align(POOL_HEADER_SIZE + size, POOL_HEADER_SIZE) else {
+        return efi::Status::OUT_OF_RESOURCES;
+    };
+    // SAFETY: The layout is not empty, the size is stored in the header of the allocation.
+    unsafe {
+        let allocation = heap::alloc(layout);
+        if allocation.is_null() {
+            return efi::Status::OUT_OF_RESOURCES;
+        }
+        (allocation as *mut usize).write(size);
+        buffer.write(allocation.add(POOL_HEADER_SIZE) as *mut c_void);
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn free_pool(buffer: *mut c_void) -> efi::Status {
+    if buffer.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The buffer was allocated by allocate_pool, after a header holding its size.
+    unsafe {
+        let allocation = (buffer as *mut u8).sub(POOL_HEADER_SIZE);
+        let size = (allocation as *const usize).read();
+        heap::dealloc(allocation, Layout::from_size_align_unchecked(POOL_HEADER_SIZE + size, POOL_HEADER_SIZE));
+    }
+    efi::Status::SUCCESS
+}
+
+// Allocates a pool buffer holding *items*.
+fn allocate_pool_for<T: Copy>(items: &[T]) -> Result<*mut T, efi::Status> {
+    let mut buffer = ptr::null_mut();
+    match allocate_pool(efi::BOOT_SERVICES_DATA, mem::size_of_val(items), &mut buffer) {
+        s if s.is_error() => Err(s),
+        // SAFETY: The buffer was allocated for the items.
+        _ => Ok(unsafe {
+            ptr::copy_nonoverlapping(items.as_ptr(), buffer as *mut T, items.len());
+            buffer as *mut T
+        }),
+    }
+}
+
+extern "efiapi" fn create_event(
+    event_type: u32,
+    notify_tpl: efi::Tpl,
+    notify_function: Option<efi::EventNotify>,
+    notify_context: *mut c_void,
+    event: *mut efi::Event,
+) -> efi::Status {
+    create_event_ex(event_type, notify_tpl, notify_function, notify_context, ptr::null(), event)
+}
+
+extern "efiapi" fn create_event_ex(
+    event_type: u32,
+    notify_tpl: efi::Tpl,
+    notify_function: Option<efi::EventNotify>,
+    notify_context: *const c_void,
+    event_group: *const efi::Guid,
+    event: *mut efi::Event,
+) -> efi::Status {
+    let notify = event_type & (efi::EVT_NOTIFY_SIGNAL | efi::EVT_NOTIFY_WAIT) != 0;
+    if event.is_null() || (notify && notify_function.is_none()) {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let group = match event_type {
+        efi::EVT_SIGNAL_EXIT_BOOT_SERVICES => Some(efi::EVENT_GROUP_EXIT_BOOT_SERVICES),
+        efi::EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE => Some(efi::EVENT_GROUP_VIRTUAL_ADDRESS_CHANGE),
+        // SAFETY: The group GUID is provided by the caller.
+        _ => unsafe { event_group.as_ref() }.copied(),
+    };
+
+    let id = with_state(|state| {
+        let id = state.new_id();
+        state.events.insert(
+            id,
+            Event {
+                event_type,
+                notify_tpl,
+                notify_function,
+                notify_context: notify_context as *mut c_void,
+                group,
+                signaled: false,
+                timer: None,
+            },
+        );
+        id
+    });
+    // SAFETY: The caller provides the event to write.
+    unsafe { event.write(id as efi::Event) };
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn set_timer(event: efi::Event, timer_type: efi::TimerDelay, trigger_time: u64) -> efi::Status {
+    // The trigger time is in 100ns units, a zero periodic time signals the event on every timer tick.
+    let delay = Duration::from_nanos(trigger_time.saturating_mul(100));
+    let timer = match timer_type {
+        efi::TIMER_CANCEL => None,
+        efi::TIMER_RELATIVE => Some(Timer { deadline: Instant::now() + delay, period: None }),
+        efi::TIMER_PERIODIC => {
+            let period = delay.max(TIMER_TICK);
+            Some(Timer { deadline: Instant::now() + period, period: Some(period) })
+        }
+        _ => return efi::Status::INVALID_PARAMETER,
+    };
+    with_state(|state| match state.events.get_mut(&(event as usize)) {
+        Some(event) if event.event_type & efi::EVT_TIMER != 0 => {
+            event.timer = timer;
+            efi::Status::SUCCESS
+        }
+        _ => efi::Status::INVALID_PARAMETER,
+    })
+}
+
+extern "efiapi" fn wait_for_event(number_of_events: usize, event: *mut efi::Event, index: *mut usize) -> efi::Status {
+    if number_of_events == 0 || event.is_null() || index.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    if current_tpl() != efi::TPL_APPLICATION {
+        return efi::Status::UNSUPPORTED;
+    }
+    // SAFETY: The caller provides the events to wait for.
+    let events = unsafe { core::slice::from_raw_parts(event, number_of_events) };
+    loop {
+        for (idx, &event) in events.iter().enumerate() {
+            match check_event(event) {
+                efi::Status::NOT_READY => (),
+                status => {
+                    // SAFETY: The caller provides the index to write.
+                    unsafe { index.write(idx) };
+                    return status;
+                }
+            }
+        }
+        // Sleep until the next timer expires, checking again on every tick for the events signaled by other means.
+        let now = Instant::now();
+        let deadline = with_state(|state| state.next_deadline()).unwrap_or(now + TIMER_TICK);
+        thread::sleep(deadline.saturating_duration_since(now).min(TIMER_TICK));
+    }
+}
+
+extern "efiapi" fn signal_event(event: efi::Event) -> efi::Status {
+    let signaled = with_state(|state| {
+        let signaled = state.events.get(&(event as usize)).copied()?;
+        // Signaling an event of a group signals every event of the group.
+        let group = state
+            .events
+            .iter()
+            .filter(|&(&id, e)| id == event as usize || (signaled.group.is_some() && e.group == signaled.group))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        group.into_iter().for_each(|id| state.signal(id));
+        Some(state.tpl)
+    });
+    match signaled {
+        // The notify functions above the current TPL are called now, the others when the TPL is restored.
+        Some(tpl) => {
+            dispatch(tpl);
+            efi::Status::SUCCESS
+        }
+        None => efi::Status::INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn close_event(event: efi::Event) -> efi::Status {
+    with_state(|state| match state.events.remove(&(event as usize)) {
+        Some(_) => {
+            state.pending.retain(|&id| id != event as usize);
+            efi::Status::SUCCESS
+        }
+        None => efi::Status::INVALID_PARAMETER,
+    })
+}
+
+extern "efiapi" fn check_event(event: efi::Event) -> efi::Status {
+    let tpl = with_state(|state| {
+        let e = state.events.get(&(event as usize)).copied()?;
+        if e.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+            return None;
+        }
+        // The notify function of a wait event is queued until the event is signaled.
+        if !e.signaled && e.event_type & efi::EVT_NOTIFY_WAIT != 0 {
+            state.queue_notify(event as usize);
+        }
+        Some(state.tpl)
+    });
+    let Some(tpl) = tpl else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    dispatch(tpl);
+    let signaled = with_state(|state| state.events.get_mut(&(event as usize)).map(|e| mem::take(&mut e.signaled)));
+    match signaled {
+        Some(true) => efi::Status::SUCCESS,
+        Some(false) => efi::Status::NOT_READY,
+        // Closed by its notify function.
+        None => efi::Status::INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn install_protocol_interface(
+    handle: *mut efi::Handle,
+    protocol: *mut efi::Guid,
+    interface_type: efi::InterfaceType,
+    interface: *mut c_void,
+) -> efi::Status {
+    if handle.is_null() || protocol.is_null() || interface_type != efi::NATIVE_INTERFACE {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The pointers are provided by the caller.
+    let (requested, guid) = unsafe { (*handle, *protocol) };
+    with_state(|state| {
+        let handle_id = match requested.is_null() {
+            true => {
+                let id = state.new_id();
+                state.handles.push((id, Vec::new()));
+                id
+            }
+            false => requested as usize,
+        };
+        let Some(protocols) = state.protocols(handle_id as efi::Handle) else {
+            return efi::Status::INVALID_PARAMETER;
+        };
+        if protocols.iter().any(|p| *p.guid == guid) {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        // The GUIDs are returned by ProtocolsPerHandle, so they are kept for the lifetime of the process.
+        protocols.push(ProtocolEntry { guid: Box::leak(Box::new(guid)), interface });
+        // SAFETY: The caller provides the handle to write.
+        unsafe { handle.write(handle_id as efi::Handle) };
+        efi::Status::SUCCESS
+    })
+}
+
+extern "efiapi" fn reinstall_protocol_interface(
+    handle: efi::Handle,
+    protocol: *mut efi::Guid,
+    old_interface: *mut c_void,
+    new_interface: *mut c_void,
+) -> efi::Status {
+    if protocol.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    with_state(|state| {
+        // SAFETY: The GUID is provided by the caller.
+        let guid = unsafe { &*protocol };
+        match state
+            .protocols(handle)
+            .and_then(|p| p.iter_mut().find(|p| p.guid == guid && p.interface == old_interface))
+        {
+            Some(entry) => {
+                entry.interface = new_interface;
+                efi::Status::SUCCESS
+            }
+            None => efi::Status::NOT_FOUND,
+        }
+    })
+}
+
+extern "efiapi" fn uninstall_protocol_interface(
+    handle: efi::Handle,
+    protocol: *mut efi::Guid,
+    interface: *mut c_void,
+) -> efi::Status {
+    if protocol.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    with_state(|state| {
+        // SAFETY: The GUID is provided by the caller.
+        let guid = unsafe { &*protocol };
+        let Some(protocols) = state.protocols(handle) else {
+            return efi::Status::NOT_FOUND;
+        };
+        let Some(idx) = protocols.iter().position(|p| p.guid == guid && p.interface == interface) else {
+            return efi::Status::NOT_FOUND;
+        };
+        protocols.remove(idx);
+        // A handle is removed with its last protocol.
+        if protocols.is_empty() {
+            state.handles.retain(|(h, _)| *h != handle as usize);
+        }
+        efi::Status::SUCCESS
+    })
+}
+
+extern "efiapi" fn handle_protocol(
+    handle: efi::Handle,
+    protocol: *mut efi::Guid,
+    interface: *mut *mut c_void,
+) -> efi::Status {
+    open_protocol(handle, protocol, interface, ptr::null_mut(), ptr::null_mut(), efi::OPEN_PROTOCOL_BY_HANDLE_PROTOCOL)
+}
+
+extern "efiapi" fn locate_handle(
+    search_type: efi::LocateSearchType,
+    protocol: *mut efi::Guid,
+    _search_key: *mut c_void,
+    buffer_size: *mut usize,
+    buffer: *mut efi::Handle,
+) -> efi::Status {
+    if buffer_size.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let handles = match with_state(|state| state.find_handles(search_type, protocol)) {
+        Ok(handles) => handles,
+        Err(status) => return status,
+    };
+    // SAFETY: The pointers are provided by the caller, the buffer is checked against its size.
+    unsafe {
+        let size = mem::size_of_val(handles.as_slice());
+        if buffer_size.replace(size) < size {
+            return efi::Status::BUFFER_TOO_SMALL;
+        }
+        if buffer.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        ptr::copy_nonoverlapping(handles.as_ptr() as *const efi::Handle, buffer, handles.len());
+    }
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn install_configuration_table(guid: *mut efi::Guid, table: *mut c_void) -> efi::Status {
+    if guid.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The GUID is provided by the caller.
+    let vendor_guid = unsafe { *guid };
+    with_state(|state| {
+        let configuration_table = &mut state.configuration_table;
+        let position = configuration_table.iter().position(|t| t.vendor_guid == vendor_guid);
+        match (position, table.is_null()) {
+            (Some(idx), true) => _ = configuration_table.remove(idx),
+            (None, true) => return efi::Status::NOT_FOUND,
+            (Some(idx), false) => configuration_table[idx].vendor_table = table,
+            (None, false) => configuration_table.push(efi::ConfigurationTable { vendor_guid, vendor_table: table }),
+        }
+        efi::Status::SUCCESS
+    })
+}
+
+extern "efiapi" fn exit_boot_services(_image_handle: efi::Handle, map_key: usize) -> efi::Status {
+    match map_key == with_state(|state| state.map_key) {
+        true => efi::Status::SUCCESS,
+        false => efi::Status::INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn get_next_monotonic_count(count: *mut u64) -> efi::Status {
+    if count.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let next = with_state(|state| {
+        state.monotonic_count += 1;
+        state.monotonic_count - 1
+    });
+    // SAFETY: The caller provides the count to write.
+    unsafe { count.write(next) };
+    efi::Status::SUCCESS
+}
+
+// The timers expiring during the stall notify their events, as the timer interrupt does in the firmware.
+extern "efiapi" fn stall(microseconds: usize) -> efi::Status {
+    let deadline = Instant::now() + Duration::from_micros(microseconds as u64);
+    loop {
+        dispatch(current_tpl());
+        let now = Instant::now();
+        if now >= deadline {
+            return efi::Status::SUCCESS;
+        }
+        thread::sleep(deadline.min(with_state(|state| state.next_deadline()).unwrap_or(deadline)).duration_since(now));
+    }
+}
+
+extern "efiapi" fn set_watchdog_timer(
+    _timeout: usize,
+    _watchdog_code: u64,
+    _data_size: usize,
+    _data: *mut efi::Char16,
+) -> efi::Status {
+    efi::Status::SUCCESS
+}
+
+// The attributes are not enforced, every open succeeds and is not recorded.
+extern "efiapi" fn open_protocol(
+    handle: efi::Handle,
+    protocol: *mut efi::Guid,
+    interface: *mut *mut c_void,
+    _agent_handle: efi::Handle,
+    _controller_handle: efi::Handle,
+    attributes: u32,
+) -> efi::Status {
+    if protocol.is_null() || (interface.is_null() && attributes != efi::OPEN_PROTOCOL_TEST_PROTOCOL) {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    with_state(|state| {
+        if state.protocols(handle).is_none() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        // SAFETY: The GUID is provided by the caller.
+        match state.interface(handle, unsafe { &*protocol }) {
+            Some(found) => {
+                if !interface.is_null() {
+                    // SAFETY: The caller provides the interface to write.
+                    unsafe { interface.write(found) };
+                }
+                efi::Status::SUCCESS
+            }
+            None => efi::Status::UNSUPPORTED,
+        }
+    })
+}
+
+extern "efiapi" fn close_protocol(
+    handle: efi::Handle,
+    protocol: *mut efi::Guid,
+    _agent_handle: efi::Handle,
+    _controller_handle: efi::Handle,
+) -> efi::Status {
+    if protocol.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The GUID is provided by the caller.
+    match with_state(|state| state.interface(handle, unsafe { &*protocol })) {
+        Some(_) => efi::Status::SUCCESS,
+        None => efi::Status::NOT_FOUND,
+    }
+}
+
+// The opens are not recorded, so no handle has open information.
+extern "efiapi" fn open_protocol_information(
+    handle: efi::Handle,
+    protocol: *mut efi::Guid,
+    entry_buffer: *mut *mut efi::OpenProtocolInformationEntry,
+    entry_count: *mut usize,
+) -> efi::Status {
+    if protocol.is_null() || entry_buffer.is_null() || entry_count.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The GUID is provided by the caller.
+    if with_state(|state| state.interface(handle, unsafe { &*protocol })).is_none() {
+        return efi::Status::NOT_FOUND;
+    }
+    match allocate_pool_for::<efi::OpenProtocolInformationEntry>(&[]) {
+        // SAFETY: The caller provides the buffer and count to write.
+        Ok(buffer) => unsafe {
+            entry_buffer.write(buffer);
+            entry_count.write(0);
+            efi::Status::SUCCESS
+        },
+        Err(status) => status,
+    }
+}
+
+extern "efiapi" fn protocols_per_handle(
+    handle: efi::Handle,
+    protocol_buffer: *mut *mut *mut efi::Guid,
+    protocol_buffer_count: *mut usize,
+) -> efi::Status {
+    if protocol_buffer.is_null() || protocol_buffer_count.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let guids: Option<Vec<*mut efi::Guid>> = with_state(|state| {
+        state.protocols(handle).map(|p| p.iter().map(|p| p.guid as *const efi::Guid as *mut efi::Guid).collect())
+    });
+    let Some(guids) = guids else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    match allocate_pool_for(&guids) {
+        // SAFETY: The caller provides the buffer and count to write.
+        Ok(buffer) => unsafe {
+            protocol_buffer.write(buffer);
+            protocol_buffer_count.write(guids.len());
+            efi::Status::SUCCESS
+        },
+        Err(status) => status,
+    }
+}
+
+extern "efiapi" fn locate_handle_buffer(
+    search_type: efi::LocateSearchType,
+    protocol: *mut efi::Guid,
+    _search_key: *mut c_void,
+    no_handles: *mut usize,
+    buffer: *mut *mut efi::Handle,
+) -> efi::Status {
+    if no_handles.is_null() || buffer.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    let handles = match with_state(|state| state.find_handles(search_type, protocol)) {
+        Ok(handles) => handles.into_iter().map(|h| h as efi::Handle).collect::<Vec<_>>(),
+        Err(status) => return status,
+    };
+    match allocate_pool_for(&handles) {
+        // SAFETY: The caller provides the buffer and count to write.
+        Ok(handle_buffer) => unsafe {
+            buffer.write(handle_buffer);
+            no_handles.write(handles.len());
+            efi::Status::SUCCESS
+        },
+        Err(status) => status,
+    }
+}
+
+extern "efiapi" fn locate_protocol(
+    protocol: *mut efi::Guid,
+    _registration: *mut c_void,
+    interface: *mut *mut c_void,
+) -> efi::Status {
+    if protocol.is_null() || interface.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The GUID is provided by the caller.
+    let guid = unsafe { &*protocol };
+    with_state(|state| {
+        match state.handles.iter().flat_map(|(_, p)| p).find(|p| p.guid == guid) {
+            Some(entry) => {
+                // SAFETY: The caller provides the interface to write.
+                unsafe { interface.write(entry.interface) };
+                efi::Status::SUCCESS
+            }
+            None => efi::Status::NOT_FOUND,
+        }
+    })
+}
+
+extern "efiapi" fn calculate_crc32(data: *mut c_void, data_size: usize, crc32_out: *mut u32) -> efi::Status {
+    if data.is_null() || data_size == 0 || crc32_out.is_null() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    // SAFETY: The caller provides the data to checksum and the CRC to write.
+    unsafe { crc32_out.write(crc32(core::slice::from_raw_parts(data as *const u8, data_size))) };
+    efi::Status::SUCCESS
+}
+
+extern "efiapi" fn copy_mem(destination: *mut c_void, source: *mut c_void, length: usize) {
+    // SAFETY: The caller provides the buffers, which may overlap.
+    unsafe { ptr::copy(source as *const u8, destination as *mut u8, length) }
+}
+
+extern "efiapi" fn set_mem(buffer: *mut c_void, size: usize, value: u8) {
+    // SAFETY: The caller provides the buffer.
+    unsafe { ptr::write_bytes(buffer as *mut u8, value, size) }
+}
+
+// The services below have no host equivalent.
+
+extern "efiapi" fn register_protocol_notify(
+    _protocol: *mut efi::Guid,
+    _event: efi::Event,
+    _registration: *mut *mut c_void,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn locate_device_path(
+    _protocol: *mut efi::Guid,
+    _device_path: *mut *mut efi::protocols::device_path::Protocol,
+    _device: *mut efi::Handle,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn load_image(
+    _boot_policy: efi::Boolean,
+    _parent_image_handle: efi::Handle,
+    _device_path: *mut efi::protocols::device_path::Protocol,
+    _source_buffer: *mut c_void,
+    _source_size: usize,
+    _image_handle: *mut efi::Handle,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn start_image(
+    _image_handle: efi::Handle,
+    _exit_data_size: *mut usize,
+    _exit_data: *mut *mut efi::Char16,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn exit(
+    _image_handle: efi::Handle,
+    _exit_status: efi::Status,
+    _exit_data_size: usize,
+    _exit_data: *mut efi::Char16,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn unload_image(_image_handle: efi::Handle) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn connect_controller(
+    _controller_handle: efi::Handle,
+    _driver_image_handle: *mut efi::Handle,
+    _remaining_device_path: *mut efi::protocols::device_path::Protocol,
+    _recursive: efi::Boolean,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn disconnect_controller(
+    _controller_handle: efi::Handle,
+    _driver_image_handle: efi::Handle,
+    _child_handle: efi::Handle,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn install_multiple_protocol_interfaces(
+    _handle: *mut efi::Handle,
+    _arg1: *mut c_void,
+    _arg2: *mut c_void,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+extern "efiapi" fn uninstall_multiple_protocol_interfaces(
+    _handle: efi::Handle,
+    _arg1: *mut c_void,
+    _arg2: *mut c_void,
+) -> efi::Status {
+    efi::Status::UNSUPPORTED
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        allocation::{AllocType, MemoryType},
+        event::{EventTimerType, EventType},
+        tpl::Tpl,
+        BootServices,
+    };
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    const TEST_GUID: efi::Guid =
+        efi::Guid::from_fields(0x4057d1f3, 0x5d19, 0x4b3a, 0x9a, 0x4b, &[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]);
+
+    #[test]
+    fn test_table_header_is_valid() {
+        let host = HostBootServices::new();
+        let boot_services = StandardBootServices::new_uninit();
+        unsafe { boot_services.initialize_checked(host.efi_boot_services()) }.unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "already has a HostBootServices")]
+    fn test_single_instance_per_thread() {
+        let _host = HostBootServices::new();
+        let _other = HostBootServices::new();
+    }
+
+    #[test]
+    fn test_memory() {
+        let host = HostBootServices::new();
+        let boot_services = host.standard_boot_services();
+
+        let pool = boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, 24).unwrap();
+        assert_eq!(0, pool as usize % 8);
+        unsafe { pool.write_bytes(0xA5, 24) };
+        boot_services.free_pool(pool).unwrap();
+
+        let address = boot_services.allocate_pages(AllocType::AnyPage, MemoryType::LOADER_DATA, 2).unwrap();
+        assert_eq!(0, address % UEFI_PAGE_SIZE);
+        let memory_map = boot_services.get_memory_map().unwrap();
+        assert!(memory_map.descriptors.iter().any(|d| d.physical_start == address as u64 && d.number_of_pages == 2));
+        assert_eq!(Err(efi::Status::NOT_FOUND), boot_services.free_pages(address, 1));
+        boot_services.free_pages(address, 2).unwrap();
+        assert_eq!(
+            Err(efi::Status::NOT_FOUND),
+            boot_services.allocate_pages(AllocType::Address(address), MemoryType::LOADER_DATA, 1)
+        );
+        assert_eq!(
+            Err(efi::Status::OUT_OF_RESOURCES),
+            boot_services.allocate_pages(AllocType::AnyPage, MemoryType::LOADER_DATA, usize::MAX)
+        );
+        assert_eq!(Err(efi::Status::NOT_FOUND), boot_services.free_pages(address, usize::MAX));
+    }
+
+    #[test]
+    fn test_timer_event() {
+        extern "efiapi" fn notify(_: efi::Event, notified: &AtomicUsize) {
+            notified.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let host = HostBootServices::new();
+        let boot_services = host.standard_boot_services();
+        let timer = boot_services.create_event(EventType::TIMER, Tpl::CALLBACK, None, ()).unwrap();
+        assert_eq!(Err(efi::Status::NOT_READY), boot_services.check_event(timer));
+        boot_services.set_timer(timer, EventTimerType::Relative, 10_000).unwrap();
+        assert_eq!(Ok(0), boot_services.wait_for_event(&mut [timer]));
+        boot_services.close_event(timer).unwrap();
+
+        let notified = Box::leak(Box::new(AtomicUsize::new(0)));
+        let event =
+            boot_services.create_event(EventType::NOTIFY_SIGNAL, Tpl::NOTIFY, Some(notify), &*notified).unwrap();
+        boot_services.signal_event(event).unwrap();
+        assert_eq!(1, notified.load(Ordering::SeqCst));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), boot_services.check_event(event));
+        boot_services.close_event(event).unwrap();
+    }
+
+    #[test]
+    fn test_notifications_are_dispatched_on_restore_tpl() {
+        static ORDER: Mutex<Vec<(usize, efi::Tpl)>> = Mutex::new(Vec::new());
+        extern "efiapi" fn notify(_: efi::Event, id: *mut c_void) {
+            ORDER.lock().unwrap().push((id as usize, current_tpl()));
+        }
+
+        let host = HostBootServices::new();
+        let boot_services = host.efi_boot_services();
+        let mut events = [ptr::null_mut(); 3];
+        for (idx, (event_type, notify_tpl)) in [
+            (efi::EVT_NOTIFY_SIGNAL, efi::TPL_CALLBACK),
+            (efi::EVT_NOTIFY_SIGNAL, efi::TPL_NOTIFY),
+            (efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL, efi::TPL_CALLBACK),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let context = idx as *mut c_void;
+            let status = (boot_services.create_event)(event_type, notify_tpl, Some(notify), context, &mut events[idx]);
+            assert_eq!(efi::Status::SUCCESS, status);
+        }
+
+        // Nothing is notified on the signaling thread while the TPL is at the notify TPL of the events.
+        let old_tpl = (boot_services.raise_tpl)(efi::TPL_NOTIFY);
+        assert_eq!(efi::Status::SUCCESS, (boot_services.signal_event)(events[0]));
+        assert_eq!(efi::Status::SUCCESS, (boot_services.signal_event)(events[1]));
+        assert_eq!(efi::Status::SUCCESS, (boot_services.signal_event)(events[0]));
+        // A periodic timer expiring several times during the stall notifies its event once.
+        assert_eq!(efi::Status::SUCCESS, (boot_services.set_timer)(events[2], efi::TIMER_PERIODIC, 10_000));
+        assert_eq!(efi::Status::SUCCESS, (boot_services.stall)(5_000));
+        assert!(ORDER.lock().unwrap().is_empty());
+
+        // Restoring the TPL calls the notify functions at their notify TPL, the highest first.
+        (boot_services.restore_tpl)(old_tpl);
+        assert_eq!(
+            vec![(1, efi::TPL_NOTIFY), (0, efi::TPL_CALLBACK), (2, efi::TPL_CALLBACK)],
+            mem::take(&mut *ORDER.lock().unwrap())
+        );
+        assert_eq!(efi::TPL_APPLICATION, current_tpl());
+
+        // Above the current TPL, the notify function is called when the event is signaled.
+        assert_eq!(efi::Status::SUCCESS, (boot_services.close_event)(events[2]));
+        (boot_services.raise_tpl)(efi::TPL_CALLBACK);
+        assert_eq!(efi::Status::SUCCESS, (boot_services.signal_event)(events[1]));
+        assert_eq!(vec![(1, efi::TPL_NOTIFY)], mem::take(&mut *ORDER.lock().unwrap()));
+        let mut index = 0;
+        assert_eq!(efi::Status::UNSUPPORTED, (boot_services.wait_for_event)(1, &mut events[0], &mut index));
+        (boot_services.restore_tpl)(efi::TPL_APPLICATION);
+    }
+
+    #[test]
+    fn test_instances_are_per_thread() {
+        let host = HostBootServices::new();
+        let mut guid = TEST_GUID;
+        let mut handle = ptr::null_mut();
+        let install = host.efi_boot_services().install_protocol_interface;
+        assert_eq!(efi::Status::SUCCESS, install(&mut handle, &mut guid, efi::NATIVE_INTERFACE, ptr::null_mut()));
+        (host.efi_boot_services().raise_tpl)(efi::TPL_NOTIFY);
+
+        thread::spawn(|| {
+            let host = HostBootServices::new();
+            let boot_services = host.standard_boot_services();
+            let handles = boot_services.locate_handle_buffer(crate::HandleSearchType::AllHandle);
+            assert_eq!(Some(efi::Status::NOT_FOUND), handles.err());
+            assert_eq!(efi::TPL_APPLICATION, current_tpl());
+        })
+        .join()
+        .unwrap();
+        (host.efi_boot_services().restore_tpl)(efi::TPL_APPLICATION);
+    }
+
+    #[test]
+    fn test_protocols() {
+        let host = HostBootServices::new();
+        let boot_services = host.efi_boot_services();
+        let mut guid = TEST_GUID;
+        let mut interface = 42_u32;
+        let interface_ptr = &mut interface as *mut u32 as *mut c_void;
+
+        let mut handle = ptr::null_mut();
+        let status =
+            (boot_services.install_protocol_interface)(&mut handle, &mut guid, efi::NATIVE_INTERFACE, interface_ptr);
+        assert_eq!(efi::Status::SUCCESS, status);
+        assert!(!handle.is_null());
+        let status =
+            (boot_services.install_protocol_interface)(&mut handle, &mut guid, efi::NATIVE_INTERFACE, interface_ptr);
+        assert_eq!(efi::Status::INVALID_PARAMETER, status);
+
+        let mut found = ptr::null_mut();
+        assert_eq!(efi::Status::SUCCESS, (boot_services.handle_protocol)(handle, &mut guid, &mut found));
+        assert_eq!(interface_ptr, found);
+        found = ptr::null_mut();
+        assert_eq!(efi::Status::SUCCESS, (boot_services.locate_protocol)(&mut guid, ptr::null_mut(), &mut found));
+        assert_eq!(interface_ptr, found);
+
+        let standard = host.standard_boot_services();
+        let handles = standard.locate_handle_buffer(crate::HandleSearchType::ByProtocol(&TEST_GUID)).unwrap();
+        assert_eq!(&[handle], &handles[..]);
+        drop(handles);
+
+        assert_eq!(
+            efi::Status::SUCCESS,
+            (boot_services.uninstall_protocol_interface)(handle, &mut guid, interface_ptr)
+        );
+        assert_eq!(efi::Status::NOT_FOUND, (boot_services.locate_protocol)(&mut guid, ptr::null_mut(), &mut found));
+    }
+
+    #[test]
+    fn test_configuration_table() {
+        let host = HostBootServices::new();
+        let mut guid = efi::Guid::from_fields(0x4057d1f4, 0, 0, 0, 0, &[0; 6]);
+        let table = Box::leak(Box::new(7_u64)) as *mut u64 as *mut c_void;
+        let install = host.efi_boot_services().install_configuration_table;
+
+        assert_eq!(efi::Status::SUCCESS, install(&mut guid, table));
+        assert!(host.configuration_table().iter().any(|t| t.vendor_guid == guid && t.vendor_table == table));
+        assert_eq!(efi::Status::SUCCESS, install(&mut guid, ptr::null_mut()));
+        assert!(host.configuration_table().is_empty());
+        assert_eq!(efi::Status::NOT_FOUND, install(&mut guid, ptr::null_mut()));
+    }
+}
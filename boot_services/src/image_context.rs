@@ -0,0 +1,136 @@
+//! The image handle and system table of the running image, registered by its entry point.
+//!
+//! Many services need the handle of the image calling them: the agent handle of `OpenProtocol()` with
+//! `BY_DRIVER`, the parent image handle of `LoadImage()`, the image exiting boot services. Registering the image
+//! handle once with [`init_image_context`] lets the helpers default to it, rather than threading it through every
+//! call. [`StandardBootServices`] also uses it in place of a null agent handle or parent image handle.
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub extern "efiapi" fn efi_main(image_handle: efi::Handle, system_table: *mut efi::SystemTable) -> efi::Status {
+//!     // SAFETY: The firmware passes a valid system table to the entry point.
+//!     efi_try!(unsafe { boot_services::init_image_context(image_handle, system_table) });
+//!     ...
+//! }
+//!
+//! let image = BOOT_SERVICES.load_image(false, image_context::or_image_handle(None)?, device_path, None)?;
+//! ```
+//!
+//! Each image has its own copy of the context, so a module only sees the handle it was loaded with.
+
+use core::{
+    ffi::c_void,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::StandardBootServices;
+
+static IMAGE_HANDLE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+static SYSTEM_TABLE: AtomicPtr<efi::SystemTable> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers the *image_handle* and the *system_table* passed to the entry point of the image.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if one of them is null, and `efi::Status::ALREADY_STARTED` if a context
+/// is already registered.
+///
+/// # Safety
+///
+/// *system_table* must point to a valid [`efi::SystemTable`] for as long as the image is loaded, as the one passed to
+/// the entry point does.
+pub unsafe fn init_image_context(
+    image_handle: efi::Handle,
+    system_table: *mut efi::SystemTable,
+) -> Result<(), efi::Status> {
+    if image_handle.is_null() || system_table.is_null() {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+    // The system table is registered first, so it is available as soon as the image handle is.
+    if SYSTEM_TABLE.compare_exchange(ptr::null_mut(), system_table, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        return Err(efi::Status::ALREADY_STARTED);
+    }
+    IMAGE_HANDLE.store(image_handle, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Returns the handle of the image, None until [`init_image_context`] is called.
+pub fn image_handle() -> Option<efi::Handle> {
+    let image_handle = IMAGE_HANDLE.load(Ordering::SeqCst);
+    (!image_handle.is_null()).then_some(image_handle)
+}
+
+/// Returns the system table of the image, None until [`init_image_context`] is called.
+///
+/// The table is shared with the firmware, which updates it e.g. when the consoles are connected, so it is returned as
+/// a pointer rather than a reference.
+pub fn system_table() -> Option<NonNull<efi::SystemTable>> {
+    NonNull::new(SYSTEM_TABLE.load(Ordering::SeqCst))
+}
+
+/// Returns the raw system table pointer of the image, null until [`init_image_context`] is called.
+///
+/// This is meant for interoperability with other crates that take a `*mut efi::SystemTable`, e.g. to share the tables
+/// of the image with them.
+pub fn system_table_ptr() -> *mut efi::SystemTable {
+    SYSTEM_TABLE.load(Ordering::SeqCst)
+}
+
+/// Returns the boot services of the system table of the image, None until [`init_image_context`] is called.
+pub fn boot_services() -> Option<StandardBootServices<'static>> {
+    // SAFETY: The system table was registered as valid for as long as the image is loaded.
+    system_table().map(|system_table| unsafe { StandardBootServices::from_system_table(system_table.as_ptr()) })
+}
+
+/// Returns *handle*, or the image handle if None, e.g. for the agent handle of `OpenProtocol()` or the parent image
+/// handle of `LoadImage()`.
+///
+/// Returns `efi::Status::NOT_READY` if *handle* is None and [`init_image_context`] was not called.
+pub fn or_image_handle(handle: Option<efi::Handle>) -> Result<efi::Handle, efi::Status> {
+    handle.or_else(image_handle).ok_or(efi::Status::NOT_READY)
+}
+
+// Resolves the null agent and parent image handles given to StandardBootServices, left null if no image is registered.
+pub(crate) fn or_image_handle_if_null(handle: efi::Handle) -> efi::Handle {
+    or_image_handle((!handle.is_null()).then_some(handle)).unwrap_or(handle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::boxed::Box;
+    use core::mem::MaybeUninit;
+
+    #[test]
+    fn test_init_image_context() {
+        let other_handle = 0x20 as efi::Handle;
+        assert_eq!((None, None), (image_handle(), system_table()));
+        assert!(system_table_ptr().is_null());
+        assert_eq!(Err(efi::Status::NOT_READY), or_image_handle(None));
+        assert_eq!(Ok(other_handle), or_image_handle(Some(other_handle)));
+        assert_eq!(ptr::null_mut(), or_image_handle_if_null(ptr::null_mut()));
+
+        // SAFETY: A system table only holds pointers, which can be null.
+        let mut system_table = Box::new(unsafe { MaybeUninit::<efi::SystemTable>::zeroed().assume_init() });
+        system_table.boot_services = 0x1000 as *mut efi::BootServices;
+        let system_table = Box::leak(system_table) as *mut efi::SystemTable;
+        let image = 0x10 as efi::Handle;
+
+        // SAFETY: The system table is leaked.
+        unsafe {
+            assert_eq!(Err(efi::Status::INVALID_PARAMETER), init_image_context(ptr::null_mut(), system_table));
+            assert_eq!(Err(efi::Status::INVALID_PARAMETER), init_image_context(image, ptr::null_mut()));
+            assert_eq!(Ok(()), init_image_context(image, system_table));
+            assert_eq!(Err(efi::Status::ALREADY_STARTED), init_image_context(other_handle, system_table));
+        }
+
+        assert_eq!((Some(image), NonNull::new(system_table)), (image_handle(), super::system_table()));
+        assert_eq!(system_table, system_table_ptr());
+        assert_eq!(Ok(image), or_image_handle(None));
+        assert_eq!(Ok(other_handle), or_image_handle(Some(other_handle)));
+        assert_eq!(image, or_image_handle_if_null(ptr::null_mut()));
+        assert_eq!(other_handle, or_image_handle_if_null(other_handle));
+        assert_eq!(0x1000 as *mut efi::BootServices, boot_services().unwrap().as_raw_ptr());
+    }
+}
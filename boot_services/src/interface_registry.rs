@@ -0,0 +1,234 @@
+//! Registry of the protocol interfaces installed through it.
+//!
+//! [`BootServices::install_protocol_interface`] returns a [`PtrMetadata`] that must be kept to uninstall the interface
+//! and get it back. An [`InterfaceRegistry`] keeps it instead, keyed by handle and protocol, so the interface can be
+//! uninstalled from anywhere in the image, and the interfaces that are still installed can be listed to debug leaks.
+//!
+//! ```ignore
+//! let handle = INTERFACE_REGISTRY.install_protocol_interface(&BOOT_SERVICES, None, &MyProtocol, Box::new(interface))?;
+//! ...
+//! let interface: Box<_> = INTERFACE_REGISTRY.uninstall_protocol_interface(&BOOT_SERVICES, handle, &MyProtocol)?;
+//! ```
+
+use alloc::vec::Vec;
+use core::any::{self, TypeId};
+
+use r_efi::efi;
+
+use crate::{
+    c_ptr::{CMutRef, PtrMetadata},
+    protocol_handler::Protocol,
+    tpl::{Tpl, TplLock},
+    BootServices,
+};
+
+/// Interface registry shared by the code of the image.
+///
+/// Each image links its own copy of the static, so the interfaces can only be uninstalled through it by the image that
+/// installed them.
+pub static INTERFACE_REGISTRY: InterfaceRegistry = InterfaceRegistry::new();
+
+/// An interface installed through an [`InterfaceRegistry`].
+#[derive(Debug, Clone)]
+pub struct RegisteredInterface {
+    pub handle: efi::Handle,
+    pub protocol: efi::Guid,
+    /// Address of the interface.
+    pub interface: usize,
+    /// Name of the pointer type the interface was installed with, e.g. `alloc::boxed::Box<u32>`.
+    pub type_name: &'static str,
+    type_id: TypeId,
+}
+
+/// Remembers the interfaces installed through it by (handle, protocol).
+///
+/// The registry is protected by raising the TPL to [`Tpl::NOTIFY`], so it can be used from event notify functions.
+#[derive(Debug)]
+pub struct InterfaceRegistry {
+    entries: TplLock<Vec<RegisteredInterface>>,
+}
+
+// SAFETY: The entries are only accessed with the lock held, the handles they hold are opaque.
+unsafe impl Sync for InterfaceRegistry {}
+
+impl InterfaceRegistry {
+    /// Create an empty registry.
+    pub const fn new() -> Self {
+        Self { entries: TplLock::new(Tpl::NOTIFY, Vec::new()) }
+    }
+
+    fn with_entries<B, F, T>(&self, boot_services: &B, f: F) -> T
+    where
+        B: BootServices,
+        F: FnOnce(&mut Vec<RegisteredInterface>) -> T,
+    {
+        self.entries.with(boot_services, f)
+    }
+
+    /// Installs a protocol interface with [`BootServices::install_protocol_interface`] and registers it.
+    pub fn install_protocol_interface<B, P, R, I>(
+        &self,
+        boot_services: &B,
+        handle: Option<efi::Handle>,
+        protocol: &P,
+        interface: R,
+    ) -> Result<efi::Handle, efi::Status>
+    where
+        B: BootServices,
+        P: Protocol<Interface = I> + 'static,
+        R: CMutRef<'static, Type = I> + 'static,
+        I: 'static,
+    {
+        let (handle, key) = boot_services.install_protocol_interface(handle, protocol, interface)?;
+        self.with_entries(boot_services, |entries| {
+            entries.push(RegisteredInterface {
+                handle,
+                protocol: *protocol.protocol_guid(),
+                interface: key.ptr_value,
+                type_name: any::type_name::<R>(),
+                type_id: TypeId::of::<R>(),
+            })
+        });
+        Ok(handle)
+    }
+
+    /// Uninstalls a protocol interface registered on *handle* and returns it.
+    ///
+    /// Returns `efi::Status::NOT_FOUND` if the interface is not registered and `efi::Status::INVALID_PARAMETER` if it
+    /// was installed with another pointer type than `R`.
+    pub fn uninstall_protocol_interface<B, P, R, I>(
+        &self,
+        boot_services: &B,
+        handle: efi::Handle,
+        protocol: &P,
+    ) -> Result<R, efi::Status>
+    where
+        B: BootServices,
+        P: Protocol<Interface = I> + 'static,
+        R: CMutRef<'static, Type = I> + 'static,
+        I: 'static,
+    {
+        // The entry is taken out so the lock is not held while calling the boot services.
+        let entry = self.with_entries(boot_services, |entries| {
+            let idx = entries
+                .iter()
+                .position(|e| e.handle == handle && e.protocol == *protocol.protocol_guid())
+                .ok_or(efi::Status::NOT_FOUND)?;
+            if entries[idx].type_id != TypeId::of::<R>() {
+                return Err(efi::Status::INVALID_PARAMETER);
+            }
+            Ok(entries.remove(idx))
+        })?;
+
+        let key = PtrMetadata::from_ptr_value(entry.interface);
+        boot_services.uninstall_protocol_interface(handle, protocol, key).map_err(|status| {
+            self.with_entries(boot_services, |entries| entries.push(entry));
+            status
+        })
+    }
+
+    /// Returns the interfaces currently registered, e.g. to report the ones never uninstalled.
+    pub fn registered_interfaces<B: BootServices>(&self, boot_services: &B) -> Vec<RegisteredInterface> {
+        self.with_entries(boot_services, |entries| entries.clone())
+    }
+}
+
+impl Default for InterfaceRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{c_ptr::CPtr, MockBootServices};
+    use core::ops::Deref;
+
+    const TEST_GUID: efi::Guid = efi::Guid::from_fields(0x1, 0x2, 0x3, 0x4, 0x5, &[0x6; 6]);
+
+    struct TestProtocol;
+    unsafe impl Protocol for TestProtocol {
+        type Interface = u32;
+        fn protocol_guid(&self) -> &'static efi::Guid {
+            &TEST_GUID
+        }
+    }
+    impl Deref for TestProtocol {
+        type Target = efi::Guid;
+        fn deref(&self) -> &Self::Target {
+            self.protocol_guid()
+        }
+    }
+
+    fn boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services.expect_install_protocol_interface::<TestProtocol, Box<u32>, u32>().returning(
+            |handle, _, interface| {
+                let key = interface.metadata();
+                interface.into_ptr();
+                Ok((handle.unwrap_or(1_usize as _), key))
+            },
+        );
+        boot_services
+    }
+
+    #[test]
+    fn test_install_and_uninstall_without_metadata() {
+        let mut boot_services = boot_services();
+        boot_services
+            .expect_uninstall_protocol_interface::<TestProtocol, Box<u32>, u32>()
+            .returning(|_, _, key| Ok(unsafe { key.into_original_ptr() }));
+        let registry = InterfaceRegistry::new();
+
+        let handle = registry.install_protocol_interface(&boot_services, None, &TestProtocol, Box::new(42)).unwrap();
+        registry.install_protocol_interface(&boot_services, Some(2_usize as _), &TestProtocol, Box::new(7)).unwrap();
+
+        let registered = registry.registered_interfaces(&boot_services);
+        assert_eq!(2, registered.len());
+        assert_eq!((handle, TEST_GUID), (registered[0].handle, registered[0].protocol));
+        assert_eq!(any::type_name::<Box<u32>>(), registered[0].type_name);
+
+        let interface: Box<u32> = registry.uninstall_protocol_interface(&boot_services, handle, &TestProtocol).unwrap();
+        assert_eq!(42, *interface);
+        assert_eq!(
+            Err(efi::Status::NOT_FOUND),
+            registry.uninstall_protocol_interface::<_, _, Box<u32>, _>(&boot_services, handle, &TestProtocol)
+        );
+
+        // The interface left is reported.
+        let registered = registry.registered_interfaces(&boot_services);
+        assert_eq!(vec![2], registered.iter().map(|r| r.handle as usize).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_uninstall_with_other_pointer_type() {
+        let boot_services = boot_services();
+        let registry = InterfaceRegistry::new();
+
+        let handle = registry.install_protocol_interface(&boot_services, None, &TestProtocol, Box::new(42)).unwrap();
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            registry.uninstall_protocol_interface::<_, _, &'static mut u32, _>(&boot_services, handle, &TestProtocol)
+        );
+        assert_eq!(1, registry.registered_interfaces(&boot_services).len());
+    }
+
+    #[test]
+    fn test_failed_uninstall_keeps_the_entry() {
+        let mut boot_services = boot_services();
+        boot_services
+            .expect_uninstall_protocol_interface::<TestProtocol, Box<u32>, u32>()
+            .returning(|_, _, _| Err(efi::Status::ACCESS_DENIED));
+        let registry = InterfaceRegistry::new();
+
+        let handle = registry.install_protocol_interface(&boot_services, None, &TestProtocol, Box::new(42)).unwrap();
+        assert_eq!(
+            Err(efi::Status::ACCESS_DENIED),
+            registry.uninstall_protocol_interface::<_, _, Box<u32>, _>(&boot_services, handle, &TestProtocol)
+        );
+        assert_eq!(1, registry.registered_interfaces(&boot_services).len());
+    }
+}
@@ -0,0 +1,174 @@
+//! Unload support for Rust drivers.
+//!
+//! [`set_unload_callback`] points the `unload` function of the driver loaded image to a thunk calling a Rust closure,
+//! so the driver can be unloaded after its teardown (uninstalling protocols, closing events) succeeds.
+//!
+//! ```ignore
+//! let (handle, key) = installation.leak();
+//! // SAFETY: The image handle is the one passed to the entry point.
+//! unsafe {
+//!     set_unload_callback(&BOOT_SERVICES, image_handle, move |_| {
+//!         BOOT_SERVICES.uninstall_protocol_interface(handle, &SerialIo, key)?;
+//!         Ok(())
+//!     })?
+//! };
+//! ```
+//!
+//! [UEFI Spec Documentation: 9.1. EFI Loaded Image Protocol](https://uefi.org/specs/UEFI/2.10/09_Protocols_EFI_Loaded_Image.html#efi-loaded-image-protocol)
+
+use alloc::boxed::Box;
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::{
+    protocol_handler::LoadedImage,
+    tpl::{Tpl, TplLock},
+    BootServices, StandardBootServices,
+};
+
+type UnloadCallback = Box<dyn FnMut(efi::Handle) -> Result<(), efi::Status> + Send>;
+
+// Each image has its own copy of these statics, so they hold the callback of the image they are linked in.
+//
+// The address of the image handle and its callback.
+static UNLOAD_CALLBACK: TplLock<Option<(usize, UnloadCallback)>> = TplLock::new(Tpl::NOTIFY, None);
+// The system table of the image, whose boot services protect the callback when the image is unloaded.
+static SYSTEM_TABLE: AtomicPtr<efi::SystemTable> = AtomicPtr::new(ptr::null_mut());
+
+/// Makes the image *image_handle* unloadable, calling *callback* with the image handle when it is unloaded.
+///
+/// The image is only unloaded if *callback* succeeds, otherwise its error is returned by `UnloadImage()` and the
+/// callback will be called again on the next attempt. Setting a new callback replaces the previous one.
+///
+/// The callback is called at the TPL of the `UnloadImage()` caller, which is at most `TPL_CALLBACK`.
+///
+/// # Safety
+///
+/// *image_handle* must be the handle of the image this code is linked in, as passed to its entry point. Its loaded
+/// image protocol is updated without being opened.
+pub unsafe fn set_unload_callback<B, F>(
+    boot_services: &B,
+    image_handle: efi::Handle,
+    callback: F,
+) -> Result<(), efi::Status>
+where
+    B: BootServices,
+    F: FnMut(efi::Handle) -> Result<(), efi::Status> + Send + 'static,
+{
+    // SAFETY: The loaded image protocol is never uninstalled while the image is loaded.
+    let loaded_image = unsafe { boot_services.handle_protocol(image_handle, &LoadedImage) }?;
+    SYSTEM_TABLE.store(loaded_image.system_table, Ordering::SeqCst);
+    UNLOAD_CALLBACK.with(boot_services, |state| *state = Some((image_handle as usize, Box::new(callback))));
+    loaded_image.unload = Some(efi_unload);
+    Ok(())
+}
+
+extern "efiapi" fn efi_unload(image_handle: efi::Handle) -> efi::Status {
+    let system_table = SYSTEM_TABLE.load(Ordering::SeqCst);
+    if system_table.is_null() {
+        return efi::Status::UNSUPPORTED;
+    }
+    // SAFETY: The system table of the image is valid while the image is loaded.
+    let boot_services = unsafe { StandardBootServices::from_system_table(system_table) };
+
+    // The callback is taken out during the call, so it can set a new callback.
+    let callback = UNLOAD_CALLBACK.with(&boot_services, |state| match state.take() {
+        Some((handle, callback)) if handle == image_handle as usize => Ok(callback),
+        Some(state_callback) => {
+            *state = Some(state_callback);
+            Err(efi::Status::INVALID_PARAMETER)
+        }
+        None => Err(efi::Status::UNSUPPORTED),
+    });
+    let mut callback = match callback {
+        Ok(callback) => callback,
+        Err(status) => return status,
+    };
+
+    match callback(image_handle) {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(status) => {
+            UNLOAD_CALLBACK.with(&boot_services, |state| {
+                state.get_or_insert((image_handle as usize, callback));
+            });
+            status
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use core::mem::{self, MaybeUninit};
+    use std::sync::{atomic::AtomicUsize, Arc};
+
+    static RAISED_TPL: AtomicUsize = AtomicUsize::new(0);
+
+    extern "efiapi" fn efi_raise_tpl(tpl: efi::Tpl) -> efi::Tpl {
+        RAISED_TPL.store(tpl, Ordering::Relaxed);
+        efi::TPL_APPLICATION
+    }
+
+    extern "efiapi" fn efi_restore_tpl(_tpl: efi::Tpl) {}
+
+    // The callback is global, so every case is tested in a single test.
+    #[test]
+    fn test_set_unload_callback() {
+        let efi_boot_services = unsafe {
+            let mut bs = MaybeUninit::<efi::BootServices>::zeroed();
+            bs.assume_init_mut().raise_tpl = efi_raise_tpl;
+            bs.assume_init_mut().restore_tpl = efi_restore_tpl;
+            Box::leak(Box::new(bs))
+        };
+        let system_table: &'static mut efi::SystemTable = Box::leak(Box::new(unsafe { mem::zeroed() }));
+        system_table.boot_services = efi_boot_services.as_mut_ptr();
+        let loaded_image: &'static mut efi::protocols::loaded_image::Protocol =
+            Box::leak(Box::new(unsafe { mem::zeroed() }));
+        loaded_image.system_table = system_table;
+        let loaded_image_ptr = loaded_image as *mut efi::protocols::loaded_image::Protocol as usize;
+
+        let unload = loaded_image.unload;
+        assert_eq!(efi::Status::UNSUPPORTED, efi_unload(1_usize as efi::Handle));
+        assert!(unload.is_none());
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().with(mockall::predicate::eq(Tpl::NOTIFY)).return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services.expect_handle_protocol::<LoadedImage, efi::protocols::loaded_image::Protocol>().returning(
+            move |handle, _| {
+                assert_eq!(1, handle as usize);
+                Ok(unsafe { &mut *(loaded_image_ptr as *mut efi::protocols::loaded_image::Protocol) })
+            },
+        );
+
+        let image_handle = 1_usize as efi::Handle;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let callback_calls = calls.clone();
+        unsafe {
+            set_unload_callback(&boot_services, image_handle, move |handle| {
+                assert_eq!(1, handle as usize);
+                // The first unload attempt fails.
+                match callback_calls.fetch_add(1, Ordering::Relaxed) {
+                    0 => Err(efi::Status::ACCESS_DENIED),
+                    _ => Ok(()),
+                }
+            })
+        }
+        .unwrap();
+
+        let unload = loaded_image.unload.unwrap();
+        assert_eq!(efi::Status::INVALID_PARAMETER, unload(2_usize as efi::Handle));
+        assert_eq!(efi::Status::ACCESS_DENIED, unload(image_handle));
+        // The callback is protected by the boot services of the system table of the image.
+        assert_eq!(efi::TPL_NOTIFY, RAISED_TPL.load(Ordering::Relaxed));
+        assert_eq!(efi::Status::SUCCESS, unload(image_handle));
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+        assert_eq!(efi::Status::UNSUPPORTED, unload(image_handle));
+        assert_eq!(efi::Status::UNSUPPORTED, unload(ptr::null_mut()));
+    }
+}
@@ -0,0 +1,320 @@
+//! Project Mu DXE memory protection settings.
+//!
+//! The platform publishes its memory protection policy in a GUID extension HOB, which [`MemoryProtectionSettings`]
+//! reads from the HOB list so drivers can adjust their allocations and page attributes to it.
+//!
+//! ```ignore
+//! let settings = unsafe { MemoryProtectionSettings::from_system_table(system_table) }?;
+//! if settings.nx_protection.contains(MemoryType::BOOT_SERVICES_DATA) {
+//!     // Code must not be copied to boot services data.
+//! }
+//! ```
+
+use core::{ffi::c_void, mem, ptr, slice};
+
+use r_efi::efi;
+
+use crate::allocation::MemoryType;
+
+/// GUID of the configuration table pointing to the HOB list.
+pub const HOB_LIST_GUID: efi::Guid =
+    efi::Guid::from_fields(0x7739f24c, 0x93d7, 0x11d4, 0x9a, 0x3a, &[0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+
+/// GUID of the HOB holding the DXE memory protection settings.
+pub const DXE_MEMORY_PROTECTION_SETTINGS_GUID: efi::Guid =
+    efi::Guid::from_fields(0x9abfd639, 0xd1d0, 0x4eff, 0xbd, 0xb6, &[0x7e, 0xc4, 0x19, 0x0d, 0x17, 0xd5]);
+
+/// Oldest version of the settings with the layout read by [`MemoryProtectionSettings::from_bytes`], later versions
+/// only append fields.
+pub const MIN_SUPPORTED_VERSION: u8 = 6;
+
+const HOB_TYPE_GUID_EXTENSION: u16 = 0x0004;
+const HOB_TYPE_END_OF_HOB_LIST: u16 = 0xFFFF;
+
+#[repr(C)]
+struct HobHeader {
+    hob_type: u16,
+    hob_length: u16,
+    reserved: u32,
+}
+
+// Layout of the settings shared by the supported versions.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawSettings {
+    struct_version: u8,
+    cpu_stack_guard: u8,
+    stack_execution_protection: u8,
+    null_pointer_detection_policy: u8,
+    heap_guard_policy: u8,
+    image_protection_policy: u8,
+    heap_guard_pool_type: u32,
+    heap_guard_page_type: u32,
+    nx_protection_policy: u32,
+}
+
+/// Null pointer detection policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NullDetectionPolicy {
+    /// Page 0 is not present.
+    pub uefi_null_detection: bool,
+    /// The detection is disabled at end of DXE.
+    pub disable_end_of_dxe: bool,
+    /// The detection is disabled at ready to boot.
+    pub disable_ready_to_boot: bool,
+}
+
+/// Heap guard policy, the memory types guarded are in [`MemoryProtectionSettings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapGuardPolicy {
+    /// Page allocations are surrounded by guard pages.
+    pub uefi_page_guard: bool,
+    /// Pool allocations are next to a guard page.
+    pub uefi_pool_guard: bool,
+    /// Freed memory is kept not present.
+    pub uefi_freed_memory_guard: bool,
+    /// Pool allocations are aligned to the guard page after them, instead of the one before them.
+    pub direction_tail: bool,
+}
+
+/// Image protection policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageProtectionPolicy {
+    /// Images from an unknown source are protected.
+    pub protect_image_from_unknown: bool,
+    /// Images from a firmware volume are protected.
+    pub protect_image_from_fv: bool,
+    /// Loading an image fails if it can not be protected.
+    pub raise_error_if_protection_fails: bool,
+    /// Images not compatible with NX are not loaded.
+    pub block_images_without_nx_flag: bool,
+}
+
+/// Set of memory types, one bit per standard memory type followed by a bit for each of the OEM and OS reserved ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryTypes(pub u32);
+
+impl MemoryTypes {
+    const OEM_RESERVED_BIT: u32 = efi::UNACCEPTED_MEMORY_TYPE + 1;
+    const OS_RESERVED_BIT: u32 = efi::UNACCEPTED_MEMORY_TYPE + 2;
+
+    /// Returns true if *memory_type* is in the set.
+    pub fn contains(&self, memory_type: MemoryType) -> bool {
+        let bit = match memory_type {
+            m if m.is_oem_reserved() => Self::OEM_RESERVED_BIT,
+            m if m.is_os_reserved() => Self::OS_RESERVED_BIT,
+            m if m.is_standard() => m.into(),
+            _ => return false,
+        };
+        self.0 & (1 << bit) != 0
+    }
+}
+
+/// DXE memory protection settings of the platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryProtectionSettings {
+    pub struct_version: u8,
+    /// Stack overflows are caught by a guard page.
+    pub cpu_stack_guard: bool,
+    /// The stack is not executable.
+    pub stack_execution_protection: bool,
+    pub null_pointer_detection: NullDetectionPolicy,
+    pub heap_guard: HeapGuardPolicy,
+    pub image_protection: ImageProtectionPolicy,
+    /// Memory types of the pool allocations guarded when `heap_guard.uefi_pool_guard` is set.
+    pub heap_guard_pool_types: MemoryTypes,
+    /// Memory types of the page allocations guarded when `heap_guard.uefi_page_guard` is set.
+    pub heap_guard_page_types: MemoryTypes,
+    /// Memory types that are not executable.
+    pub nx_protection: MemoryTypes,
+}
+
+impl MemoryProtectionSettings {
+    /// Parses the data of the settings HOB.
+    ///
+    /// Returns `efi::Status::INCOMPATIBLE_VERSION` if the settings are older than [`MIN_SUPPORTED_VERSION`], and
+    /// `efi::Status::BAD_BUFFER_SIZE` if *data* is too small for them.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, efi::Status> {
+        match data.first() {
+            Some(&version) if version < MIN_SUPPORTED_VERSION => return Err(efi::Status::INCOMPATIBLE_VERSION),
+            _ if data.len() < mem::size_of::<RawSettings>() => return Err(efi::Status::BAD_BUFFER_SIZE),
+            _ => (),
+        }
+        // SAFETY: data is large enough for the settings, which are only made of integers.
+        let raw = unsafe { ptr::read_unaligned(data.as_ptr() as *const RawSettings) };
+        let bit = |policy: u8, bit: u8| policy & (1 << bit) != 0;
+        Ok(Self {
+            struct_version: raw.struct_version,
+            cpu_stack_guard: raw.cpu_stack_guard != 0,
+            stack_execution_protection: raw.stack_execution_protection != 0,
+            null_pointer_detection: NullDetectionPolicy {
+                uefi_null_detection: bit(raw.null_pointer_detection_policy, 0),
+                disable_end_of_dxe: bit(raw.null_pointer_detection_policy, 1),
+                disable_ready_to_boot: bit(raw.null_pointer_detection_policy, 2),
+            },
+            heap_guard: HeapGuardPolicy {
+                uefi_page_guard: bit(raw.heap_guard_policy, 0),
+                uefi_pool_guard: bit(raw.heap_guard_policy, 1),
+                uefi_freed_memory_guard: bit(raw.heap_guard_policy, 2),
+                direction_tail: bit(raw.heap_guard_policy, 3),
+            },
+            image_protection: ImageProtectionPolicy {
+                protect_image_from_unknown: bit(raw.image_protection_policy, 0),
+                protect_image_from_fv: bit(raw.image_protection_policy, 1),
+                raise_error_if_protection_fails: bit(raw.image_protection_policy, 2),
+                block_images_without_nx_flag: bit(raw.image_protection_policy, 3),
+            },
+            heap_guard_pool_types: MemoryTypes(raw.heap_guard_pool_type),
+            heap_guard_page_types: MemoryTypes(raw.heap_guard_page_type),
+            nx_protection: MemoryTypes(raw.nx_protection_policy),
+        })
+    }
+
+    /// Reads the settings from the HOB list.
+    ///
+    /// Returns `efi::Status::NOT_FOUND` if the HOB list has no settings HOB.
+    ///
+    /// # Safety
+    ///
+    /// *hob_list* must point to a valid HOB list, terminated by an end of HOB list HOB.
+    pub unsafe fn from_hob_list(hob_list: *const c_void) -> Result<Self, efi::Status> {
+        Self::from_bytes(find_guid_hob(hob_list, &DXE_MEMORY_PROTECTION_SETTINGS_GUID).ok_or(efi::Status::NOT_FOUND)?)
+    }
+
+    /// Reads the settings from the HOB list of the configuration table of *system_table*.
+    ///
+    /// Returns `efi::Status::NOT_FOUND` if there is no HOB list or no settings HOB.
+    ///
+    /// # Safety
+    ///
+    /// *system_table* must point to a valid [efi::SystemTable], whose HOB list is valid.
+    pub unsafe fn from_system_table(system_table: *const efi::SystemTable) -> Result<Self, efi::Status> {
+        let system_table = &*system_table;
+        let configuration_table =
+            slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries);
+        let hob_list = configuration_table
+            .iter()
+            .find(|table| table.vendor_guid == HOB_LIST_GUID)
+            .ok_or(efi::Status::NOT_FOUND)?
+            .vendor_table;
+        Self::from_hob_list(hob_list)
+    }
+}
+
+/// Returns the data of the first GUID extension HOB named *guid* in *hob_list*.
+///
+/// # Safety
+///
+/// *hob_list* must point to a valid HOB list, terminated by an end of HOB list HOB.
+pub unsafe fn find_guid_hob<'a>(hob_list: *const c_void, guid: &efi::Guid) -> Option<&'a [u8]> {
+    let mut hob = hob_list as *const u8;
+    loop {
+        let header = ptr::read_unaligned(hob as *const HobHeader);
+        let length = header.hob_length as usize;
+        match header.hob_type {
+            HOB_TYPE_END_OF_HOB_LIST => return None,
+            // A zero length would loop forever on a corrupted list.
+            _ if length < mem::size_of::<HobHeader>() => return None,
+            HOB_TYPE_GUID_EXTENSION if length >= mem::size_of::<HobHeader>() + mem::size_of::<efi::Guid>() => {
+                let name = hob.add(mem::size_of::<HobHeader>());
+                if ptr::read_unaligned(name as *const efi::Guid) == *guid {
+                    let data = name.add(mem::size_of::<efi::Guid>());
+                    return Some(slice::from_raw_parts(data, length - (data as usize - hob as usize)));
+                }
+            }
+            _ => (),
+        }
+        hob = hob.add(length);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn settings_bytes(version: u8) -> Vec<u8> {
+        let mut data = vec![version, 1, 0, 0b101, 0b1010, 0b0011, 0, 0];
+        data.extend((1_u32 << efi::BOOT_SERVICES_DATA).to_le_bytes());
+        data.extend(0_u32.to_le_bytes());
+        data.extend(((1_u32 << efi::LOADER_DATA) | (1 << 16)).to_le_bytes());
+        data
+    }
+
+    fn hob(hob_type: u16, guid: Option<&efi::Guid>, data: &[u8]) -> Vec<u8> {
+        let length = 8 + guid.map_or(0, |_| 16) + data.len();
+        // HOBs are 8 bytes aligned.
+        let mut hob = Vec::new();
+        hob.extend(hob_type.to_le_bytes());
+        hob.extend((length.next_multiple_of(8) as u16).to_le_bytes());
+        hob.extend(0_u32.to_le_bytes());
+        hob.extend(guid.map_or(&[][..], |guid| guid.as_bytes()));
+        hob.extend(data);
+        hob.resize(length.next_multiple_of(8), 0);
+        hob
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let settings = MemoryProtectionSettings::from_bytes(&settings_bytes(7)).unwrap();
+        assert_eq!(7, settings.struct_version);
+        assert!(settings.cpu_stack_guard && !settings.stack_execution_protection);
+        assert_eq!(
+            NullDetectionPolicy { uefi_null_detection: true, disable_end_of_dxe: false, disable_ready_to_boot: true },
+            settings.null_pointer_detection
+        );
+        assert!(settings.heap_guard.uefi_pool_guard && settings.heap_guard.direction_tail);
+        assert!(!settings.heap_guard.uefi_page_guard && !settings.heap_guard.uefi_freed_memory_guard);
+        assert!(
+            settings.image_protection.protect_image_from_unknown && settings.image_protection.protect_image_from_fv
+        );
+        assert!(settings.heap_guard_pool_types.contains(MemoryType::BOOT_SERVICES_DATA));
+        assert!(!settings.heap_guard_pool_types.contains(MemoryType::BOOT_SERVICES_CODE));
+        assert!(settings.nx_protection.contains(MemoryType::LOADER_DATA));
+        assert!(settings.nx_protection.contains(MemoryType::custom(0x70000000).unwrap()));
+        assert!(!settings.nx_protection.contains(MemoryType::custom(0x80000000).unwrap()));
+
+        assert_eq!(Err(efi::Status::INCOMPATIBLE_VERSION), MemoryProtectionSettings::from_bytes(&settings_bytes(5)));
+        assert_eq!(Err(efi::Status::BAD_BUFFER_SIZE), MemoryProtectionSettings::from_bytes(&settings_bytes(7)[..19]));
+    }
+
+    #[test]
+    fn test_from_hob_list() {
+        let other_guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        let mut hob_list = Vec::new();
+        hob_list.extend(hob(0x0001, None, &[0; 0x30]));
+        hob_list.extend(hob(HOB_TYPE_GUID_EXTENSION, Some(&other_guid), &[1]));
+        hob_list.extend(hob(HOB_TYPE_GUID_EXTENSION, Some(&DXE_MEMORY_PROTECTION_SETTINGS_GUID), &settings_bytes(7)));
+        hob_list.extend(hob(HOB_TYPE_END_OF_HOB_LIST, None, &[]));
+
+        let settings = unsafe { MemoryProtectionSettings::from_hob_list(hob_list.as_ptr() as *const c_void) }.unwrap();
+        assert!(settings.cpu_stack_guard);
+        assert_eq!(Some(&[1, 0, 0, 0, 0, 0, 0, 0][..]), unsafe {
+            find_guid_hob(hob_list.as_ptr() as *const c_void, &other_guid)
+        });
+
+        let end = hob(HOB_TYPE_END_OF_HOB_LIST, None, &[]);
+        assert_eq!(Err(efi::Status::NOT_FOUND), unsafe {
+            MemoryProtectionSettings::from_hob_list(end.as_ptr() as *const c_void)
+        });
+    }
+
+    #[test]
+    fn test_from_system_table() {
+        let mut hob_list = hob(HOB_TYPE_GUID_EXTENSION, Some(&DXE_MEMORY_PROTECTION_SETTINGS_GUID), &settings_bytes(6));
+        hob_list.extend(hob(HOB_TYPE_END_OF_HOB_LIST, None, &[]));
+        let mut configuration_table = [efi::ConfigurationTable {
+            vendor_guid: HOB_LIST_GUID,
+            vendor_table: hob_list.as_mut_ptr() as *mut c_void,
+        }];
+        let mut system_table: efi::SystemTable = unsafe { mem::zeroed() };
+        system_table.number_of_table_entries = configuration_table.len();
+        system_table.configuration_table = configuration_table.as_mut_ptr();
+
+        let settings = unsafe { MemoryProtectionSettings::from_system_table(&system_table) }.unwrap();
+        assert_eq!(6, settings.struct_version);
+
+        system_table.number_of_table_entries = 0;
+        assert_eq!(Err(efi::Status::NOT_FOUND), unsafe { MemoryProtectionSettings::from_system_table(&system_table) });
+    }
+}
@@ -0,0 +1,148 @@
+//! Once per boot markers to coordinate one-time initialization between modules.
+//!
+//! Statics are per image, so a library linked in several drivers can not use them to know if some initialization was
+//! already done by another driver. [`OnceGuard`] instead records the marker in the protocol database by installing a
+//! null interface of the marker GUID on a new handle, which every module can see.
+//!
+//! ```ignore
+//! const MY_LIB_INIT_GUID: efi::Guid = ...;
+//! if let Some(_guard) = OnceGuard::try_acquire(&BOOT_SERVICES, &MY_LIB_INIT_GUID)? {
+//!     // First module to get here this boot.
+//! }
+//! ```
+
+use core::ptr;
+
+use r_efi::efi;
+
+use crate::{tpl::Tpl, BootServices};
+
+/// Proof that the caller is the first to acquire a once per boot marker.
+///
+/// The marker stays installed when the guard is dropped, use [`OnceGuard::release`] to let another module acquire it
+/// again (e.g. if the initialization failed).
+#[derive(Debug)]
+#[must_use = "use OnceGuard::is_acquired to only check the marker"]
+pub struct OnceGuard {
+    guid: &'static efi::Guid,
+    handle: efi::Handle,
+}
+
+impl OnceGuard {
+    /// Installs the marker *guid* if no module installed it yet this boot.
+    ///
+    /// Returns `None` if the marker was already installed.
+    pub fn try_acquire<B: BootServices>(
+        boot_services: &B,
+        guid: &'static efi::Guid,
+    ) -> Result<Option<Self>, efi::Status> {
+        // The check and the install must not be interleaved with an event notify function doing the same.
+        let old_tpl = boot_services.raise_tpl(Tpl::NOTIFY);
+        let result = match Self::is_acquired(boot_services, guid) {
+            Ok(false) => {
+                //SAFETY: A marker has no interface.
+                unsafe { boot_services.install_protocol_interface_unchecked(None, guid, ptr::null_mut()) }
+                    .map(|handle| Some(Self { guid, handle }))
+            }
+            Ok(true) => Ok(None),
+            Err(status) => Err(status),
+        };
+        boot_services.restore_tpl(old_tpl);
+        result
+    }
+
+    /// Returns true if the marker *guid* is installed.
+    pub fn is_acquired<B: BootServices>(boot_services: &B, guid: &'static efi::Guid) -> Result<bool, efi::Status> {
+        //SAFETY: The interface is not used.
+        match unsafe { boot_services.locate_protocol_unchecked(guid, ptr::null_mut()) } {
+            Ok(_) => Ok(true),
+            Err(efi::Status::NOT_FOUND) => Ok(false),
+            Err(status) => Err(status),
+        }
+    }
+
+    /// The handle the marker is installed on.
+    pub fn handle(&self) -> efi::Handle {
+        self.handle
+    }
+
+    /// Uninstalls the marker so it can be acquired again.
+    pub fn release<B: BootServices>(self, boot_services: &B) -> Result<(), efi::Status> {
+        //SAFETY: The marker was installed with a null interface.
+        unsafe { boot_services.uninstall_protocol_interface_unchecked(self.handle, self.guid, ptr::null_mut()) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    const MARKER_GUID: efi::Guid = efi::Guid::from_fields(0x1, 0x2, 0x3, 0x4, 0x5, &[0x6; 6]);
+
+    // Mock of a protocol database holding a single marker.
+    fn boot_services(installed: Arc<AtomicBool>) -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        let locate_installed = installed.clone();
+        boot_services.expect_locate_protocol_unchecked().returning(move |guid, _| {
+            assert_eq!(MARKER_GUID, *guid);
+            match locate_installed.load(Ordering::Relaxed) {
+                true => Ok(ptr::null_mut()),
+                false => Err(efi::Status::NOT_FOUND),
+            }
+        });
+        let install_installed = installed.clone();
+        boot_services.expect_install_protocol_interface_unchecked().returning(move |handle, guid, interface| {
+            assert_eq!((None, MARKER_GUID, ptr::null_mut()), (handle, *guid, interface));
+            install_installed.store(true, Ordering::Relaxed);
+            Ok(1_usize as _)
+        });
+        boot_services.expect_uninstall_protocol_interface_unchecked().returning(move |handle, guid, interface| {
+            assert_eq!((1_usize as _, MARKER_GUID, ptr::null_mut()), (handle, *guid, interface));
+            installed.store(false, Ordering::Relaxed);
+            Ok(())
+        });
+        boot_services
+    }
+
+    #[test]
+    fn test_try_acquire_once() {
+        let boot_services = boot_services(Arc::new(AtomicBool::new(false)));
+
+        assert_eq!(Ok(false), OnceGuard::is_acquired(&boot_services, &MARKER_GUID));
+        let guard = OnceGuard::try_acquire(&boot_services, &MARKER_GUID).unwrap().unwrap();
+        assert_eq!(1, guard.handle() as usize);
+        assert_eq!(Ok(true), OnceGuard::is_acquired(&boot_services, &MARKER_GUID));
+        assert!(OnceGuard::try_acquire(&boot_services, &MARKER_GUID).unwrap().is_none());
+
+        // Dropping the guard keeps the marker, releasing it does not.
+        drop(guard);
+        assert!(OnceGuard::try_acquire(&boot_services, &MARKER_GUID).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_release() {
+        let boot_services = boot_services(Arc::new(AtomicBool::new(false)));
+
+        let guard = OnceGuard::try_acquire(&boot_services, &MARKER_GUID).unwrap().unwrap();
+        guard.release(&boot_services).unwrap();
+        assert!(OnceGuard::try_acquire(&boot_services, &MARKER_GUID).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_try_acquire_error() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services.expect_locate_protocol_unchecked().returning(|_, _| Err(efi::Status::INVALID_PARAMETER));
+
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            OnceGuard::try_acquire(&boot_services, &MARKER_GUID).map(|g| g.is_some())
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! This module defined the EFI_PARTITION_INFO_PROTOCOL and [`find_esp`] to locate the EFI System Partitions.
+//!
+//! [UEFI Spec Documentation: 13.17. Partition Information Protocol](https://uefi.org/specs/UEFI/2.10/13_Protocols_Media_Access.html#partition-information-protocol)
+
+use alloc::vec::Vec;
+use core::ptr;
+
+use r_efi::efi;
+
+use crate::{
+    protocol_handler::{DevicePath, PartitionInfo, SimpleFileSystem},
+    BootServices,
+};
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x8cf2f62c, 0xbc9b, 0x4821, 0x80, 0x8d, &[0xec, 0x9e, 0xc4, 0x21, 0xa1, 0xa0]);
+
+pub const REVISION: u32 = 0x00001000;
+
+pub const TYPE_OTHER: u32 = 0x00;
+pub const TYPE_MBR: u32 = 0x01;
+pub const TYPE_GPT: u32 = 0x02;
+
+/// GPT partition type of the EFI System Partition.
+pub const EFI_SYSTEM_PARTITION_GUID: efi::Guid =
+    efi::Guid::from_fields(0xc12a7328, 0xf81f, 0x11d2, 0xba, 0x4b, &[0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b]);
+
+/// MBR OS type of the EFI System Partition.
+pub const EFI_SYSTEM_PARTITION_MBR_TYPE: u8 = 0xEF;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MbrPartitionRecord {
+    pub boot_indicator: u8,
+    pub start_head: u8,
+    pub start_sector: u8,
+    pub start_track: u8,
+    pub os_indicator: u8,
+    pub end_head: u8,
+    pub end_sector: u8,
+    pub end_track: u8,
+    pub starting_lba: [u8; 4],
+    pub size_in_lba: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GptPartitionEntry {
+    pub partition_type_guid: efi::Guid,
+    pub unique_partition_guid: efi::Guid,
+    pub starting_lba: u64,
+    pub ending_lba: u64,
+    pub attributes: u64,
+    pub partition_name: [u16; 36],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union Info {
+    pub mbr: MbrPartitionRecord,
+    pub gpt: GptPartitionEntry,
+}
+
+// The layout is packed in the spec, but every field is naturally aligned.
+#[repr(C)]
+pub struct Protocol {
+    pub revision: u32,
+    pub r#type: u32,
+    pub system: u8,
+    pub reserved: [u8; 7],
+    pub info: Info,
+}
+
+impl Protocol {
+    /// Returns the MBR partition record, if this is an MBR partition.
+    pub fn mbr(&self) -> Option<MbrPartitionRecord> {
+        // SAFETY: The type tells which member of the union is valid.
+        match self.r#type {
+            TYPE_MBR => Some(unsafe { self.info.mbr }),
+            _ => None,
+        }
+    }
+
+    /// Returns the GPT partition entry, if this is a GPT partition.
+    pub fn gpt(&self) -> Option<GptPartitionEntry> {
+        // SAFETY: The type tells which member of the union is valid.
+        match self.r#type {
+            TYPE_GPT => Some(unsafe { self.info.gpt }),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the partition is an EFI System Partition.
+    ///
+    /// The partition driver reports it in `system`, the partition type is also checked for drivers that do not.
+    pub fn is_system_partition(&self) -> bool {
+        self.system == 1
+            || self.mbr().is_some_and(|mbr| mbr.os_indicator == EFI_SYSTEM_PARTITION_MBR_TYPE)
+            || self.gpt().is_some_and(|gpt| gpt.partition_type_guid == EFI_SYSTEM_PARTITION_GUID)
+    }
+}
+
+/// An EFI System Partition found by [`find_esp`].
+#[derive(Debug)]
+pub struct EfiSystemPartition {
+    pub handle: efi::Handle,
+    pub device_path: *mut efi::protocols::device_path::Protocol,
+    /// The root directory of the partition file system.
+    pub root: *mut efi::protocols::file::Protocol,
+}
+
+/// Returns the EFI System Partitions with a file system, in the order of their handles.
+///
+/// The handles supporting both the simple file system and the partition information protocols are scanned, and the
+/// root directory of each EFI System Partition is opened. The partitions with no device path or whose volume can not
+/// be opened are skipped.
+pub fn find_esp<B: BootServices>(boot_services: &B) -> Result<Vec<EfiSystemPartition>, efi::Status> {
+    // SAFETY: The interfaces are not opened.
+    let handles = match unsafe { boot_services.locate_handles_for_protocol(&SimpleFileSystem, None) } {
+        Ok(handles) => handles,
+        Err(efi::Status::NOT_FOUND) => return Ok(Vec::new()),
+        Err(status) => return Err(status),
+    };
+
+    let mut partitions = Vec::new();
+    for handle in handles.into_iter().map(|h| h.handle) {
+        // SAFETY: The interfaces are only used in this loop iteration.
+        let Ok(partition_info) = (unsafe { boot_services.handle_protocol(handle, &PartitionInfo) }) else {
+            continue;
+        };
+        if !partition_info.is_system_partition() {
+            continue;
+        }
+        let Ok(file_system) = (unsafe { boot_services.handle_protocol(handle, &SimpleFileSystem) }) else {
+            continue;
+        };
+        let Ok(device_path) = (unsafe { boot_services.handle_protocol(handle, &DevicePath) }) else {
+            continue;
+        };
+
+        let mut root = ptr::null_mut();
+        match (file_system.open_volume)(file_system, &mut root) {
+            s if s.is_error() => continue,
+            _ => partitions.push(EfiSystemPartition { handle, device_path, root }),
+        }
+    }
+    Ok(partitions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{protocol_handler::ProtocolHandle, MockBootServices};
+    use core::mem;
+
+    fn partition_info(r#type: u32, system: u8, partition_type_guid: efi::Guid) -> Protocol {
+        let mut gpt: GptPartitionEntry = unsafe { mem::zeroed() };
+        gpt.partition_type_guid = partition_type_guid;
+        Protocol { revision: REVISION, r#type, system, reserved: [0; 7], info: Info { gpt } }
+    }
+
+    extern "efiapi" fn efi_open_volume(
+        this: *mut efi::protocols::simple_file_system::Protocol,
+        root: *mut *mut efi::protocols::file::Protocol,
+    ) -> efi::Status {
+        // The fake root is the file system interface address plus one.
+        unsafe { root.write((this as usize + 1) as *mut _) };
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_is_system_partition() {
+        assert!(partition_info(TYPE_GPT, 1, efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6])).is_system_partition());
+        assert!(partition_info(TYPE_GPT, 0, EFI_SYSTEM_PARTITION_GUID).is_system_partition());
+        assert!(!partition_info(TYPE_OTHER, 0, EFI_SYSTEM_PARTITION_GUID).is_system_partition());
+
+        let mut mbr = partition_info(TYPE_MBR, 0, EFI_SYSTEM_PARTITION_GUID);
+        assert!(!mbr.is_system_partition());
+        mbr.info.mbr.os_indicator = EFI_SYSTEM_PARTITION_MBR_TYPE;
+        assert!(mbr.is_system_partition());
+    }
+
+    #[test]
+    fn test_find_esp() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_handles_for_protocol::<SimpleFileSystem, efi::protocols::simple_file_system::Protocol>()
+            .returning(|_, agent_handle| {
+                assert!(agent_handle.is_none());
+                Ok((1..=3).map(|h| ProtocolHandle { handle: h as efi::Handle, interface: None }).collect())
+            });
+        boot_services.expect_handle_protocol::<PartitionInfo, Protocol>().returning(|handle, _| {
+            match handle as usize {
+                1 => Ok(Box::leak(Box::new(partition_info(TYPE_GPT, 1, EFI_SYSTEM_PARTITION_GUID)))),
+                2 => {
+                    Ok(Box::leak(Box::new(partition_info(TYPE_GPT, 0, efi::Guid::from_fields(0, 0, 0, 0, 0, &[0; 6])))))
+                }
+                _ => Err(efi::Status::UNSUPPORTED),
+            }
+        });
+        boot_services
+            .expect_handle_protocol::<SimpleFileSystem, efi::protocols::simple_file_system::Protocol>()
+            .returning(|handle, _| {
+                assert_eq!(1, handle as usize);
+                Ok(Box::leak(Box::new(efi::protocols::simple_file_system::Protocol {
+                    revision: efi::protocols::simple_file_system::REVISION,
+                    open_volume: efi_open_volume,
+                })))
+            });
+        boot_services.expect_handle_protocol::<DevicePath, efi::protocols::device_path::Protocol>().returning(
+            |handle, _| {
+                assert_eq!(1, handle as usize);
+                Ok(Box::leak(Box::new(efi::protocols::device_path::Protocol {
+                    r#type: 0x7f,
+                    sub_type: 0xff,
+                    length: [4, 0],
+                })))
+            },
+        );
+
+        let partitions = find_esp(&boot_services).unwrap();
+        assert_eq!(1, partitions.len());
+        assert_eq!(1, partitions[0].handle as usize);
+        assert_eq!(0x7f, unsafe { (*partitions[0].device_path).r#type });
+        assert!(!partitions[0].root.is_null());
+    }
+
+    #[test]
+    fn test_find_esp_no_file_system() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_handles_for_protocol::<SimpleFileSystem, efi::protocols::simple_file_system::Protocol>()
+            .returning(|_, _| Err(efi::Status::NOT_FOUND));
+
+        assert!(find_esp(&boot_services).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+//! This module wraps the EFI_PCI_IO_PROTOCOL in [`PciDevice`], with [`find_pci_devices`] to find the PCI devices by
+//! vendor, device or class, e.g. for a driver looking for its controllers or a tool listing the devices.
+//!
+//! ```ignore
+//! const MASS_STORAGE: u8 = 0x01;
+//! const NVME: u8 = 0x08;
+//!
+//! for nvme in find_pci_devices(&BOOT_SERVICES, |_, _, class| (class.base_class, class.sub_class) == (MASS_STORAGE, NVME))? {
+//!     log::info!("NVMe controller {:04x}:{:04x} at {}", nvme.vendor_id, nvme.device_id, nvme.location);
+//! }
+//! ```
+//!
+//! [UEFI Spec Documentation: 14.4. EFI PCI I/O Protocol](https://uefi.org/specs/UEFI/2.10/14_Protocols_PCI_Bus_Support.html#efi-pci-i-o-protocol)
+
+use alloc::vec::Vec;
+use core::{
+    ffi::c_void,
+    fmt::{self, Display},
+};
+
+use r_efi::efi::{
+    self,
+    protocols::pci_io::{self, Width},
+};
+
+use crate::{protocol_handler::PciIo, BootServices};
+
+pub use pci_io::Protocol;
+
+/// Offset of the vendor ID in the configuration space header, followed by the device ID.
+pub const CONFIG_VENDOR_ID_OFFSET: u32 = 0x00;
+/// Offset of the revision ID in the configuration space header, followed by the class code.
+pub const CONFIG_REVISION_ID_OFFSET: u32 = 0x08;
+
+/// Vendor ID read from a function that does not exist.
+pub const INVALID_VENDOR_ID: u16 = 0xffff;
+
+/// Class code of a PCI function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassCode {
+    pub base_class: u8,
+    pub sub_class: u8,
+    pub programming_interface: u8,
+}
+
+/// Location of a PCI function, as returned by `EFI_PCI_IO_PROTOCOL.GetLocation()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PciLocation {
+    pub segment: usize,
+    pub bus: usize,
+    pub device: usize,
+    pub function: usize,
+}
+
+impl Display for PciLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:02x}:{:02x}.{:x}", self.segment, self.bus, self.device, self.function)
+    }
+}
+
+/// A PCI function and its PCI I/O protocol, with the IDs read from its configuration space.
+#[derive(Debug)]
+pub struct PciDevice {
+    pub handle: efi::Handle,
+    protocol: *mut Protocol,
+    pub location: PciLocation,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub revision_id: u8,
+    pub class_code: ClassCode,
+}
+
+impl PciDevice {
+    /// Wraps the PCI I/O *protocol* installed on *handle*, reading its location and IDs.
+    ///
+    /// # Safety
+    ///
+    /// *protocol* must be a valid PCI I/O protocol interface that stays installed while the device is used.
+    pub unsafe fn new(handle: efi::Handle, protocol: *mut Protocol) -> Result<Self, efi::Status> {
+        let mut location = PciLocation { segment: 0, bus: 0, device: 0, function: 0 };
+        // SAFETY: The caller guarantees that the protocol is valid.
+        match unsafe {
+            ((*protocol).get_location)(
+                protocol,
+                &mut location.segment,
+                &mut location.bus,
+                &mut location.device,
+                &mut location.function,
+            )
+        } {
+            s if s.is_error() => return Err(s),
+            _ => (),
+        }
+
+        let mut device = Self {
+            handle,
+            protocol,
+            location,
+            vendor_id: 0,
+            device_id: 0,
+            revision_id: 0,
+            class_code: ClassCode { base_class: 0, sub_class: 0, programming_interface: 0 },
+        };
+        let [vendor_id, device_id] = device.read_config::<2, 2>(CONFIG_VENDOR_ID_OFFSET)?.map(u16::from_ne_bytes);
+        let [revision_id, programming_interface, sub_class, base_class] =
+            device.read_config::<4, 1>(CONFIG_REVISION_ID_OFFSET)?.map(|[b]| b);
+        device.vendor_id = vendor_id;
+        device.device_id = device_id;
+        device.revision_id = revision_id;
+        device.class_code = ClassCode { base_class, sub_class, programming_interface };
+        Ok(device)
+    }
+
+    /// Returns the PCI I/O protocol of the device, for the accesses not wrapped here.
+    pub fn protocol(&self) -> *mut Protocol {
+        self.protocol
+    }
+
+    // Reads or writes COUNT values of SIZE bytes in the configuration space.
+    fn config<const COUNT: usize, const SIZE: usize>(
+        &self,
+        write: bool,
+        offset: u32,
+        buffer: &mut [[u8; SIZE]; COUNT],
+    ) -> Result<(), efi::Status> {
+        let width: Width = match SIZE {
+            1 => pci_io::WIDTH_UINT8,
+            2 => pci_io::WIDTH_UINT16,
+            4 => pci_io::WIDTH_UINT32,
+            _ => pci_io::WIDTH_UINT64,
+        };
+        // SAFETY: The protocol is valid while the device is used, as required by new().
+        let access = unsafe {
+            if write {
+                (*self.protocol).pci.write
+            } else {
+                (*self.protocol).pci.read
+            }
+        };
+        match access(self.protocol, width, offset, COUNT, buffer.as_mut_ptr() as *mut c_void) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    fn read_config<const COUNT: usize, const SIZE: usize>(
+        &self,
+        offset: u32,
+    ) -> Result<[[u8; SIZE]; COUNT], efi::Status> {
+        let mut buffer = [[0; SIZE]; COUNT];
+        self.config(false, offset, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Reads the byte at *offset* in the configuration space.
+    pub fn read_config_u8(&self, offset: u32) -> Result<u8, efi::Status> {
+        self.read_config::<1, 1>(offset).map(|[value]| u8::from_ne_bytes(value))
+    }
+
+    /// Reads the 16-bit value at *offset* in the configuration space.
+    pub fn read_config_u16(&self, offset: u32) -> Result<u16, efi::Status> {
+        self.read_config::<1, 2>(offset).map(|[value]| u16::from_ne_bytes(value))
+    }
+
+    /// Reads the 32-bit value at *offset* in the configuration space.
+    pub fn read_config_u32(&self, offset: u32) -> Result<u32, efi::Status> {
+        self.read_config::<1, 4>(offset).map(|[value]| u32::from_ne_bytes(value))
+    }
+
+    /// Writes the byte *value* at *offset* in the configuration space.
+    pub fn write_config_u8(&self, offset: u32, value: u8) -> Result<(), efi::Status> {
+        self.config(true, offset, &mut [value.to_ne_bytes()])
+    }
+
+    /// Writes the 16-bit *value* at *offset* in the configuration space.
+    pub fn write_config_u16(&self, offset: u32, value: u16) -> Result<(), efi::Status> {
+        self.config(true, offset, &mut [value.to_ne_bytes()])
+    }
+
+    /// Writes the 32-bit *value* at *offset* in the configuration space.
+    pub fn write_config_u32(&self, offset: u32, value: u32) -> Result<(), efi::Status> {
+        self.config(true, offset, &mut [value.to_ne_bytes()])
+    }
+}
+
+/// Returns the PCI devices for which *filter* returns true, in the order of their handles.
+///
+/// Every handle supporting the PCI I/O protocol is scanned, and *filter* is called with the vendor ID, device ID and
+/// class code read from the configuration space. The handles whose location or IDs can not be read, or reading
+/// [`INVALID_VENDOR_ID`], are skipped.
+pub fn find_pci_devices<B, F>(boot_services: &B, mut filter: F) -> Result<Vec<PciDevice>, efi::Status>
+where
+    B: BootServices,
+    F: FnMut(u16, u16, ClassCode) -> bool,
+{
+    // SAFETY: The interfaces are not opened.
+    let handles = match unsafe { boot_services.locate_handles_for_protocol(&PciIo, None) } {
+        Ok(handles) => handles,
+        Err(efi::Status::NOT_FOUND) => return Ok(Vec::new()),
+        Err(status) => return Err(status),
+    };
+
+    let mut devices = Vec::new();
+    for handle in handles.into_iter().map(|h| h.handle) {
+        // SAFETY: The interface is only accessed through the device, while the protocol is installed.
+        let Ok(device) = (unsafe { boot_services.handle_protocol(handle, &PciIo) })
+            .and_then(|protocol| unsafe { PciDevice::new(handle, protocol) })
+        else {
+            continue;
+        };
+        if device.vendor_id != INVALID_VENDOR_ID && filter(device.vendor_id, device.device_id, device.class_code) {
+            devices.push(device);
+        }
+    }
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{protocol_handler::ProtocolHandle, MockBootServices};
+    use core::{mem::MaybeUninit, ptr, slice};
+
+    // Configuration space of the fake devices, the device number is the handle and the index in the array.
+    static mut CONFIG_SPACES: [[u8; 16]; 3] = [
+        [0x86, 0x80, 0x34, 0x12, 0, 0, 0, 0, 0x01, 0x02, 0x08, 0x01, 0, 0, 0, 0],
+        [0x86, 0x80, 0x78, 0x56, 0, 0, 0, 0, 0x02, 0x00, 0x00, 0x03, 0, 0, 0, 0],
+        [0xde, 0x1a, 0x00, 0x10, 0, 0, 0, 0, 0x03, 0x02, 0x08, 0x01, 0, 0, 0, 0],
+    ];
+
+    fn config_space(this: *mut Protocol) -> &'static mut [u8; 16] {
+        // SAFETY: Each test only accesses the configuration space of its own devices.
+        unsafe { &mut (*ptr::addr_of_mut!(CONFIG_SPACES))[(*this).rom_size as usize] }
+    }
+
+    fn access_size(width: Width) -> usize {
+        1 << width
+    }
+
+    extern "efiapi" fn efi_config_read(
+        this: *mut Protocol,
+        width: Width,
+        offset: u32,
+        count: usize,
+        buffer: *mut c_void,
+    ) -> efi::Status {
+        let len = access_size(width) * count;
+        let buffer = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, len) };
+        buffer.copy_from_slice(&config_space(this)[offset as usize..offset as usize + len]);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_config_write(
+        this: *mut Protocol,
+        width: Width,
+        offset: u32,
+        count: usize,
+        buffer: *mut c_void,
+    ) -> efi::Status {
+        let len = access_size(width) * count;
+        let buffer = unsafe { slice::from_raw_parts(buffer as *const u8, len) };
+        config_space(this)[offset as usize..offset as usize + len].copy_from_slice(buffer);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_get_location(
+        this: *mut Protocol,
+        segment: *mut usize,
+        bus: *mut usize,
+        device: *mut usize,
+        function: *mut usize,
+    ) -> efi::Status {
+        unsafe {
+            *segment = 0;
+            *bus = 1;
+            *device = (*this).rom_size as usize;
+            *function = 0;
+        }
+        efi::Status::SUCCESS
+    }
+
+    // The fake protocol of a device keeps its number in rom_size.
+    fn pci_io(device: usize) -> &'static mut Protocol {
+        let mut protocol = MaybeUninit::<Protocol>::zeroed();
+        unsafe {
+            let protocol = protocol.assume_init_mut();
+            protocol.pci.read = efi_config_read;
+            protocol.pci.write = efi_config_write;
+            protocol.get_location = efi_get_location;
+            protocol.rom_size = device as u64;
+        }
+        Box::leak(Box::new(unsafe { protocol.assume_init() }))
+    }
+
+    #[test]
+    fn test_find_pci_devices() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_handles_for_protocol::<PciIo, Protocol>().returning(|_, agent_handle| {
+            assert!(agent_handle.is_none());
+            Ok((0..3).map(|h| ProtocolHandle { handle: h as efi::Handle, interface: None }).collect())
+        });
+        boot_services.expect_handle_protocol::<PciIo, Protocol>().returning(|handle, _| Ok(pci_io(handle as usize)));
+
+        // NVMe controllers.
+        let devices =
+            find_pci_devices(&boot_services, |_, _, class| (class.base_class, class.sub_class) == (0x01, 0x08))
+                .unwrap();
+        assert_eq!(2, devices.len());
+        assert_eq!((0x8086, 0x1234, 0x01), (devices[0].vendor_id, devices[0].device_id, devices[0].revision_id));
+        assert_eq!(ClassCode { base_class: 0x01, sub_class: 0x08, programming_interface: 0x02 }, devices[0].class_code);
+        assert_eq!((0x1ade, 0x1000), (devices[1].vendor_id, devices[1].device_id));
+        assert_eq!("0000:01:02.0", devices[1].location.to_string());
+        assert_eq!(2, devices[1].handle as usize);
+
+        let devices = find_pci_devices(&boot_services, |vendor_id, _, _| vendor_id == 0x8086).unwrap();
+        assert_eq!(vec![0x1234, 0x5678], devices.iter().map(|d| d.device_id).collect::<Vec<_>>());
+
+        devices[1].write_config_u32(0x04, 0xaabbccdd).unwrap();
+        assert_eq!(0xccdd, devices[1].read_config_u16(0x04).unwrap());
+        assert_eq!(0xaa, devices[1].read_config_u8(0x07).unwrap());
+        devices[1].write_config_u8(0x06, 0x11).unwrap();
+        assert_eq!(0xaa11ccdd, devices[1].read_config_u32(0x04).unwrap());
+    }
+
+    #[test]
+    fn test_find_pci_devices_none() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_handles_for_protocol::<PciIo, Protocol>()
+            .returning(|_, _| Err(efi::Status::NOT_FOUND));
+        assert!(find_pci_devices(&boot_services, |_, _, _| true).unwrap().is_empty());
+    }
+}
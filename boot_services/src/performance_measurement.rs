@@ -0,0 +1,258 @@
+//! This module defines the EDKII_PERFORMANCE_MEASUREMENT_PROTOCOL and a rust friendly [`Performance`] wrapper around
+//! it, to record PERF_START and PERF_END measurements in the firmware performance data table (FPDT) alongside the C
+//! modules using the EDK2 PerformanceLib.
+//!
+//! ```ignore
+//! let performance = Performance::locate(&BOOT_SERVICES)?;
+//! performance.start("LoadConfig")?;
+//! let config = load_config()?;
+//! performance.end("LoadConfig")?;
+//!
+//! // Or end the measurement when the guard is dropped.
+//! let _measurement = performance.measure("Enumerate")?;
+//! ```
+//!
+//! The measurements are recorded for the module GUID [`guid::CALLER_ID`] unless another caller id is set with
+//! [`Performance::with_caller_id`], so they are attributed to the module like the ones of the C macros.
+//!
+//! [EDK2 Documentation: MdeModulePkg/Include/Guid/PerformanceMeasurement.h](https://github.com/tianocore/edk2/blob/master/MdeModulePkg/Include/Guid/PerformanceMeasurement.h)
+
+use core::{
+    ffi::{c_char, c_void},
+    ptr,
+};
+
+use r_efi::efi;
+
+use crate::{protocol_handler::PerformanceMeasurement, BootServices};
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0xc85d06be, 0x5f75, 0x48ce, 0xa8, 0x0f, &[0x12, 0x36, 0xba, 0x3b, 0x87, 0xb1]);
+
+/// Longest token recorded, in bytes. The dynamic string records of the FPDT hold at most 255 characters.
+pub const MAX_TOKEN_LENGTH: usize = 255;
+
+pub type CreatePerformanceMeasurement =
+    extern "efiapi" fn(*const c_void, *const c_void, *const c_char, u64, u64, u32, u32) -> efi::Status;
+
+#[repr(C)]
+pub struct Protocol {
+    pub create_performance_measurement: CreatePerformanceMeasurement,
+}
+
+/// Kind of a performance measurement record, PERF_MEASUREMENT_ATTRIBUTE in EDK2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MeasurementAttribute {
+    /// The start of a measurement, as recorded by PERF_START.
+    Start = 0,
+    /// The end of a measurement, as recorded by PERF_END.
+    End = 1,
+    /// A single measurement, as recorded by LogPerformanceMeasurement.
+    Log = 2,
+}
+
+/// Rust friendly wrapper around an EDKII_PERFORMANCE_MEASUREMENT_PROTOCOL instance.
+///
+/// The interface can be retrieved with [`Performance::locate`], or with
+/// [`BootServices::locate_protocol`](crate::BootServices::locate_protocol) using
+/// [`PerformanceMeasurement`](crate::protocol_handler::PerformanceMeasurement).
+#[derive(Clone, Copy)]
+pub struct Performance<'a> {
+    protocol: &'a Protocol,
+    caller_id: &'a efi::Guid,
+}
+
+impl Performance<'static> {
+    /// Locates the performance measurement protocol, installed by the DXE core when performance measurements are
+    /// enabled. Returns `efi::Status::NOT_FOUND` otherwise.
+    pub fn locate<B: BootServices>(boot_services: &B) -> Result<Self, efi::Status> {
+        // SAFETY: The interface is only read, and the protocol is never uninstalled by the DXE core.
+        let protocol = unsafe { boot_services.locate_protocol(&PerformanceMeasurement, None)? };
+        Ok(Self::new(protocol))
+    }
+}
+
+impl<'a> Performance<'a> {
+    /// Create a new Performance from a performance measurement protocol interface, recording the measurements for
+    /// [`guid::CALLER_ID`].
+    pub fn new(protocol: &'a Protocol) -> Self {
+        Self { protocol, caller_id: &guid::CALLER_ID }
+    }
+
+    /// Records the measurements for *caller_id* rather than [`guid::CALLER_ID`], e.g. for the GUID of a driver
+    /// loaded by the module.
+    pub fn with_caller_id(self, caller_id: &'a efi::Guid) -> Self {
+        Self { caller_id, ..self }
+    }
+
+    /// Returns the caller id the measurements are recorded for.
+    pub fn caller_id(&self) -> &'a efi::Guid {
+        self.caller_id
+    }
+
+    /// Records the start of the measurement *token* now, like PERF_START.
+    pub fn start(&self, token: &str) -> Result<(), efi::Status> {
+        self.record(MeasurementAttribute::Start, token, 0, 0, 0)
+    }
+
+    /// Records the end of the measurement *token* now, like PERF_END.
+    pub fn end(&self, token: &str) -> Result<(), efi::Status> {
+        self.record(MeasurementAttribute::End, token, 0, 0, 0)
+    }
+
+    /// Records the start of the measurement *token* now, and returns a guard recording its end when dropped.
+    pub fn measure<'t>(&self, token: &'t str) -> Result<Measurement<'a, 't>, efi::Status> {
+        self.start(token)?;
+        Ok(Measurement { performance: *self, token })
+    }
+
+    /// Records a measurement *token* of kind *attribute*.
+    ///
+    /// *identifier* is one of the FPDT record identifiers, 0 to let the protocol derive it from the token as the
+    /// PerformanceLib macros do. *timestamp* is a value of the performance counter, 0 for the current value, and
+    /// *address* is the address of the code the record is about, if any.
+    ///
+    /// The token is passed to the protocol as a null-terminated ASCII string, truncated to [`MAX_TOKEN_LENGTH`] bytes.
+    /// Returns `efi::Status::INVALID_PARAMETER` if it is empty, or holds a null or non-ASCII character.
+    pub fn record(
+        &self,
+        attribute: MeasurementAttribute,
+        token: &str,
+        identifier: u32,
+        timestamp: u64,
+        address: u64,
+    ) -> Result<(), efi::Status> {
+        if token.is_empty() || !token.bytes().all(|b| b.is_ascii() && b != 0) {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let mut string = [0u8; MAX_TOKEN_LENGTH + 1];
+        let len = token.len().min(MAX_TOKEN_LENGTH);
+        string[..len].copy_from_slice(&token.as_bytes()[..len]);
+
+        match (self.protocol.create_performance_measurement)(
+            self.caller_id as *const efi::Guid as *const c_void,
+            ptr::null(),
+            string.as_ptr() as *const c_char,
+            timestamp,
+            address,
+            identifier,
+            attribute as u32,
+        ) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A measurement started with [`Performance::measure`], whose end is recorded when dropped.
+#[must_use = "if unused the end of the measurement is immediately recorded"]
+pub struct Measurement<'a, 't> {
+    performance: Performance<'a>,
+    token: &'t str,
+}
+
+impl Measurement<'_, '_> {
+    /// Records the end of the measurement, returning the error of the protocol if it fails.
+    pub fn end(self) -> Result<(), efi::Status> {
+        let result = self.performance.end(self.token);
+        core::mem::forget(self);
+        result
+    }
+}
+
+impl Drop for Measurement<'_, '_> {
+    fn drop(&mut self) {
+        let _ = self.performance.end(self.token);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use alloc::{boxed::Box, string::String, vec::Vec};
+    use core::ffi::CStr;
+    use std::sync::Mutex;
+
+    // The caller id, token, identifier, timestamp and attribute of the records created with the fake protocol.
+    type Record = (efi::Guid, String, u32, u64, u32);
+    static RECORDS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+
+    extern "efiapi" fn create_performance_measurement(
+        caller_id: *const c_void,
+        guid: *const c_void,
+        string: *const c_char,
+        timestamp: u64,
+        _address: u64,
+        identifier: u32,
+        attribute: u32,
+    ) -> efi::Status {
+        assert!(guid.is_null());
+        // SAFETY: The wrapper passes a GUID and a null-terminated string.
+        let (caller_id, string) = unsafe { (*(caller_id as *const efi::Guid), CStr::from_ptr(string)) };
+        let token = String::from(string.to_str().unwrap());
+        RECORDS.lock().unwrap().push((caller_id, token, identifier, timestamp, attribute));
+        efi::Status::SUCCESS
+    }
+
+    fn protocol() -> &'static mut Protocol {
+        Box::leak(Box::new(Protocol { create_performance_measurement }))
+    }
+
+    // Takes the records of *caller_id*, the tests running in parallel use different ones.
+    fn take_records(caller_id: efi::Guid) -> Vec<Record> {
+        let mut records = RECORDS.lock().unwrap();
+        let (taken, others) = records.drain(..).partition(|record| record.0 == caller_id);
+        *records = others;
+        taken
+    }
+
+    #[test]
+    fn test_start_and_end() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_protocol::<PerformanceMeasurement, Protocol>().returning(|_, _| Ok(protocol()));
+
+        let performance = Performance::locate(&boot_services).unwrap();
+        assert_eq!(&guid::CALLER_ID, performance.caller_id());
+        performance.start("LoadConfig").unwrap();
+        performance.end("LoadConfig").unwrap();
+        performance.record(MeasurementAttribute::Log, "Event", 0x1000, 42, 0).unwrap();
+
+        let start = |token: &str| (guid::CALLER_ID, String::from(token), 0, 0, 0);
+        let end = |token: &str| (guid::CALLER_ID, String::from(token), 0, 0, 1);
+        assert_eq!(
+            vec![start("LoadConfig"), end("LoadConfig"), (guid::CALLER_ID, String::from("Event"), 0x1000, 42, 2)],
+            take_records(guid::CALLER_ID)
+        );
+    }
+
+    #[test]
+    fn test_measure_with_caller_id() {
+        let caller_id = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        let performance = Performance::new(protocol()).with_caller_id(&caller_id);
+
+        let measurement = performance.measure("Enumerate").unwrap();
+        drop(performance.measure("Nested").unwrap());
+        measurement.end().unwrap();
+        let long_token = "T".repeat(MAX_TOKEN_LENGTH + 10);
+        performance.start(&long_token).unwrap();
+
+        let tokens = take_records(caller_id).into_iter().map(|record| (record.1, record.4)).collect::<Vec<_>>();
+        let truncated = String::from(&long_token[..MAX_TOKEN_LENGTH]);
+        assert_eq!(
+            vec![
+                (String::from("Enumerate"), 0),
+                (String::from("Nested"), 0),
+                (String::from("Nested"), 1),
+                (String::from("Enumerate"), 1),
+                (truncated, 0)
+            ],
+            tokens
+        );
+
+        for token in ["", "Load\0Config", "Décompresser"] {
+            assert_eq!(Err(efi::Status::INVALID_PARAMETER), performance.start(token));
+        }
+    }
+}
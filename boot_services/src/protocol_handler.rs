@@ -1,7 +1,13 @@
-use core::{ffi::c_void, ops::Deref, ptr::NonNull};
+use core::{
+    ffi::c_void,
+    ops::Deref,
+    ptr::{self, NonNull},
+};
 
 use r_efi::efi;
 
+use crate::BootServices;
+
 pub unsafe trait Protocol: Deref<Target = efi::Guid> {
     type Interface;
     fn protocol_guid(&self) -> &'static efi::Guid;
@@ -10,13 +16,13 @@ pub unsafe trait Protocol: Deref<Target = efi::Guid> {
 pub type Registration = NonNull<c_void>;
 
 #[derive(Debug, Clone, Copy)]
-pub enum HandleSearchType {
+pub enum HandleSearchType<'a> {
     AllHandle,
     ByRegisterNotify(Registration),
-    ByProtocol(&'static efi::Guid),
+    ByProtocol(&'a efi::Guid),
 }
 
-impl Into<efi::LocateSearchType> for HandleSearchType {
+impl Into<efi::LocateSearchType> for HandleSearchType<'_> {
     fn into(self) -> efi::LocateSearchType {
         match self {
             HandleSearchType::AllHandle => efi::ALL_HANDLES,
@@ -26,6 +32,45 @@ impl Into<efi::LocateSearchType> for HandleSearchType {
     }
 }
 
+/// A handle supporting a protocol, see [`BootServices::locate_handles_for_protocol`].
+#[derive(Debug)]
+pub struct ProtocolHandle<I: 'static> {
+    pub handle: efi::Handle,
+    /// The protocol interface, if it was opened during the search.
+    pub interface: Option<&'static mut I>,
+}
+
+// See BootServicesExt::for_each_protocol.
+pub(crate) fn for_each_protocol<B, P, I, F>(
+    boot_services: &B,
+    protocol: &P,
+    agent_handle: efi::Handle,
+    mut f: F,
+) -> Result<(), efi::Status>
+where
+    B: BootServices,
+    P: Protocol<Interface = I> + 'static,
+    I: 'static,
+    F: FnMut(efi::Handle, &mut I),
+{
+    let handles = boot_services.locate_handle_buffer(HandleSearchType::ByProtocol(protocol.protocol_guid()))?;
+    for &handle in handles.iter() {
+        //SAFETY: The interface is only lent to the callback and closed right after, so no other reference exists.
+        let interface = unsafe {
+            boot_services.open_protocol(
+                handle,
+                protocol,
+                agent_handle,
+                ptr::null_mut(),
+                efi::OPEN_PROTOCOL_GET_PROTOCOL,
+            )?
+        };
+        f(handle, interface);
+        boot_services.close_protocol(handle, protocol, agent_handle, ptr::null_mut())?;
+    }
+    Ok(())
+}
+
 macro_rules! impl_protocol {
     ($protocol_struct:ident, $protocol_type:ty, $guid:expr) => {
         pub struct $protocol_struct;
@@ -62,18 +107,26 @@ impl_r_efi_protocol!(DebugPort, debugport);
 impl_r_efi_protocol!(Decompress, decompress);
 impl_r_efi_protocol!(DevicePath, device_path);
 impl_r_efi_protocol!(DevicePathFromText, device_path_from_text);
+impl_r_efi_protocol!(DevicePathToText, device_path_to_text);
 impl_r_efi_protocol!(DevicePathUtilities, device_path_utilities);
 impl_r_efi_protocol!(DiskIo, disk_io);
 impl_r_efi_protocol!(DiskIo2, disk_io2);
 impl_r_efi_protocol!(DriverBinding, driver_binding);
 impl_r_efi_protocol!(DriverDiagnostic2, driver_diagnostics2);
 impl_r_efi_protocol!(DriverFamilyOverride, driver_family_override);
-// protocol file ???;
+impl_protocol!(DriverHealth, crate::driver_health::Protocol, crate::driver_health::PROTOCOL_GUID);
+impl_protocol!(
+    DriverSupportedEfiVersion,
+    crate::driver_supported_efi_version::Protocol,
+    crate::driver_supported_efi_version::PROTOCOL_GUID
+);
+// The file protocol is not installed on handles, see crate::file::File.
+impl_protocol!(FirmwareManagement, crate::firmware_management::Protocol, crate::firmware_management::PROTOCOL_GUID);
 impl_r_efi_protocol!(GraphicOutput, graphics_output);
 impl_r_efi_protocol!(HiiDatabase, hii_database);
 impl_r_efi_protocol!(HiiFont, hii_font);
 impl_r_efi_protocol!(HiiFontEx, hii_font_ex);
-// protocol hii_package_list ???;
+impl_protocol!(HiiPackageList, efi::hii::PackageListHeader, efi::protocols::hii_package_list::PROTOCOL_GUID);
 impl_r_efi_protocol!(HiiString, hii_string);
 impl_r_efi_protocol!(Ip4, ip4);
 impl_r_efi_protocol!(Ip6, ip6);
@@ -87,12 +140,25 @@ impl_protocol!(
 );
 impl_r_efi_protocol!(ManagedNetwork, managed_network);
 impl_r_efi_protocol!(MpService, mp_services);
+impl_protocol!(PartitionInfo, crate::partition_info::Protocol, crate::partition_info::PROTOCOL_GUID);
 impl_r_efi_protocol!(PciIo, pci_io);
+impl_protocol!(
+    PerformanceMeasurement,
+    crate::performance_measurement::Protocol,
+    crate::performance_measurement::PROTOCOL_GUID
+);
 impl_r_efi_protocol!(PlatformDriverOverride, platform_driver_override);
+impl_protocol!(ResetNotification, crate::reset_notification::Protocol, crate::reset_notification::PROTOCOL_GUID);
 impl_r_efi_protocol!(Rng, rng);
-// protocol service_binding ???
+impl_protocol!(Security2, crate::security2::Protocol, crate::security2::PROTOCOL_GUID);
+impl_protocol!(SerialIo, crate::serial_io::Protocol, crate::serial_io::PROTOCOL_GUID);
+// Service bindings are identified by the GUID of their service, see crate::service_binding.
 impl_r_efi_protocol!(Shell, shell);
-impl_r_efi_protocol!(ShellDynamicCommand, shell_dynamic_command);
+impl_protocol!(
+    ShellDynamicCommand,
+    crate::shell_dynamic_command::Protocol,
+    crate::shell_dynamic_command::PROTOCOL_GUID
+);
 impl_r_efi_protocol!(ShellParameters, shell_parameters);
 impl_r_efi_protocol!(SimpleFileSystem, simple_file_system);
 impl_r_efi_protocol!(SimpleNetwork, simple_network);
@@ -104,3 +170,4 @@ impl_r_efi_protocol!(Tcp6, tcp6);
 impl_r_efi_protocol!(Timerstamp, timestamp);
 impl_r_efi_protocol!(Udp4, udp4);
 impl_r_efi_protocol!(Udp6, udp6);
+impl_protocol!(UnicodeCollation2, crate::unicode_collation::Protocol, crate::unicode_collation::PROTOCOL_GUID);
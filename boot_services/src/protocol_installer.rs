@@ -0,0 +1,241 @@
+//! Protocols implemented in Rust whose interface functions need per-instance Rust state.
+//!
+//! A [`Protocol`] only maps a GUID to the type of its interface, the C function table. A [`ProtocolInstaller`] installs
+//! the function table as the first field of a [`ProtocolInstance`] followed by a Rust state, so the interface functions
+//! get back to the state from the `This` pointer they are called with, like `CR()`/`BASE_CR()` in EDK2.
+//!
+//! ```ignore
+//! extern "efiapi" fn read(this: *mut serial_io::Protocol, size: *mut usize, buffer: *mut c_void) -> efi::Status {
+//!     // SAFETY: The interface was installed by ProtocolInstaller with a Uart state.
+//!     let Some(instance) = (unsafe { ProtocolInstance::<_, Uart>::from_interface_mut(this) }) else {
+//!         return efi::Status::INVALID_PARAMETER;
+//!     };
+//!     instance.state_mut().read(size, buffer)
+//! }
+//!
+//! let installed = ProtocolInstaller::new(&protocol_handler::SerialIo, serial_io_table(read), Uart::new(base))
+//!     .install(&BOOT_SERVICES, None)?;
+//! ```
+
+use alloc::boxed::Box;
+use core::{ffi::c_void, ptr::NonNull};
+
+use r_efi::efi;
+
+use crate::{protocol_handler::Protocol, BootServices};
+
+/// A protocol interface followed by the Rust state of its functions, as installed by [`ProtocolInstaller`].
+#[repr(C)]
+pub struct ProtocolInstance<I, S> {
+    // The interface is the first field, so its address is the address of the instance.
+    interface: I,
+    state: S,
+}
+
+impl<I, S> ProtocolInstance<I, S> {
+    /// Returns the instance of the interface at *this*, as passed to the interface functions, None if null.
+    ///
+    /// # Safety
+    ///
+    /// *this* must either be null or be the interface of a `ProtocolInstance<I, S>` installed by
+    /// [`ProtocolInstaller::install`] that is not uninstalled while the reference is used.
+    pub unsafe fn from_interface<'a>(this: *const I) -> Option<&'a Self> {
+        (this as *const Self).as_ref()
+    }
+
+    /// Returns the instance of the interface at *this*, as passed to the interface functions, None if null.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`ProtocolInstance::from_interface`], and no other reference to the instance may be used at the same
+    /// time, e.g. by an interface function it preempted.
+    pub unsafe fn from_interface_mut<'a>(this: *mut I) -> Option<&'a mut Self> {
+        (this as *mut Self).as_mut()
+    }
+
+    /// Returns the protocol interface.
+    pub fn interface(&self) -> &I {
+        &self.interface
+    }
+
+    /// Returns the Rust state of the interface functions.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the Rust state of the interface functions.
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+}
+
+/// Installer of a protocol interface paired with the Rust state of its functions, see [`ProtocolInstance`].
+pub struct ProtocolInstaller<I, S> {
+    protocol: &'static efi::Guid,
+    instance: ProtocolInstance<I, S>,
+}
+
+impl<I: 'static, S> ProtocolInstaller<I, S> {
+    /// Create an installer of *interface* for *protocol*, whose functions use *state*.
+    pub fn new<P: Protocol<Interface = I>>(protocol: &P, interface: I, state: S) -> Self {
+        Self { protocol: protocol.protocol_guid(), instance: ProtocolInstance { interface, state } }
+    }
+
+    /// Installs the interface on *handle*, or on a new handle if None.
+    pub fn install<B: BootServices>(
+        self,
+        boot_services: &B,
+        handle: Option<efi::Handle>,
+    ) -> Result<InstalledProtocol<'_, B, I, S>, efi::Status> {
+        let instance = Box::into_raw(Box::new(self.instance));
+        // SAFETY: The instance starts with the interface of the protocol and lives until it is uninstalled.
+        match unsafe {
+            boot_services.install_protocol_interface_unchecked(handle, self.protocol, instance as *mut c_void)
+        } {
+            Ok(handle) => Ok(InstalledProtocol {
+                boot_services,
+                handle,
+                protocol: self.protocol,
+                // SAFETY: The pointer comes from Box::into_raw.
+                instance: unsafe { NonNull::new_unchecked(instance) },
+            }),
+            Err(status) => {
+                // SAFETY: The instance was not installed.
+                drop(unsafe { Box::from_raw(instance) });
+                Err(status)
+            }
+        }
+    }
+}
+
+/// A protocol instance installed by [`ProtocolInstaller::install`].
+///
+/// The instance stays installed when dropped, use [`InstalledProtocol::uninstall`] to remove it and get its state
+/// back.
+pub struct InstalledProtocol<'a, B: BootServices, I, S> {
+    boot_services: &'a B,
+    handle: efi::Handle,
+    protocol: &'static efi::Guid,
+    instance: NonNull<ProtocolInstance<I, S>>,
+}
+
+impl<B: BootServices, I, S> InstalledProtocol<'_, B, I, S> {
+    /// Returns the handle the interface is installed on.
+    pub fn handle(&self) -> efi::Handle {
+        self.handle
+    }
+
+    /// Returns the installed interface, as passed to the interface functions.
+    pub fn interface(&self) -> *mut I {
+        self.instance.as_ptr() as *mut I
+    }
+
+    /// Uninstalls the interface and returns the state of the instance.
+    ///
+    /// The installed protocol is returned if it can not be uninstalled, e.g. because it is still opened by a driver.
+    pub fn uninstall(self) -> Result<S, (Self, efi::Status)> {
+        // SAFETY: The instance is the interface installed on the handle.
+        match unsafe {
+            self.boot_services.uninstall_protocol_interface_unchecked(
+                self.handle,
+                self.protocol,
+                self.instance.as_ptr() as *mut c_void,
+            )
+        } {
+            // SAFETY: The instance is no longer installed.
+            Ok(()) => Ok(unsafe { Box::from_raw(self.instance.as_ptr()) }.state),
+            Err(status) => Err((self, status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use core::ops::Deref;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER_PROTOCOL_GUID: efi::Guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+
+    #[repr(C)]
+    struct CounterInterface {
+        increment: extern "efiapi" fn(*mut CounterInterface) -> u32,
+    }
+
+    struct CounterProtocol;
+
+    unsafe impl Protocol for CounterProtocol {
+        type Interface = CounterInterface;
+
+        fn protocol_guid(&self) -> &'static efi::Guid {
+            &COUNTER_PROTOCOL_GUID
+        }
+    }
+
+    impl Deref for CounterProtocol {
+        type Target = efi::Guid;
+
+        fn deref(&self) -> &Self::Target {
+            self.protocol_guid()
+        }
+    }
+
+    extern "efiapi" fn increment(this: *mut CounterInterface) -> u32 {
+        // SAFETY: The interface is installed by ProtocolInstaller with a u32 state.
+        let Some(instance) = (unsafe { ProtocolInstance::<_, u32>::from_interface_mut(this) }) else {
+            return 0;
+        };
+        *instance.state_mut() += 1;
+        *instance.state()
+    }
+
+    #[test]
+    fn test_install_call_and_uninstall() {
+        static INTERFACE: AtomicUsize = AtomicUsize::new(0);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_install_protocol_interface_unchecked().times(1).returning(|handle, guid, interface| {
+            assert!(handle.is_none());
+            assert_eq!(&COUNTER_PROTOCOL_GUID, guid);
+            INTERFACE.store(interface as usize, Ordering::Relaxed);
+            Ok(1_usize as efi::Handle)
+        });
+        boot_services.expect_uninstall_protocol_interface_unchecked().times(2).returning(|handle, guid, interface| {
+            assert_eq!(&COUNTER_PROTOCOL_GUID, guid);
+            assert_eq!(INTERFACE.load(Ordering::Relaxed), interface as usize);
+            match handle as usize {
+                1 => Ok(()),
+                _ => Err(efi::Status::ACCESS_DENIED),
+            }
+        });
+
+        let mut installed = ProtocolInstaller::new(&CounterProtocol, CounterInterface { increment }, 40)
+            .install(&boot_services, None)
+            .unwrap();
+        assert_eq!(1, installed.handle() as usize);
+        assert_eq!(INTERFACE.load(Ordering::Relaxed), installed.interface() as usize);
+
+        let interface = installed.interface();
+        assert_eq!(41, (unsafe { &*interface }.increment)(interface));
+        assert_eq!(42, (unsafe { &*interface }.increment)(interface));
+        assert_eq!(0, increment(core::ptr::null_mut()));
+
+        installed.handle = 2_usize as efi::Handle;
+        let (mut installed, status) = installed.uninstall().err().unwrap();
+        assert_eq!(efi::Status::ACCESS_DENIED, status);
+        installed.handle = 1_usize as efi::Handle;
+        assert_eq!(Ok(42), installed.uninstall().map_err(|(_, status)| status));
+    }
+
+    #[test]
+    fn test_install_failure() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_install_protocol_interface_unchecked()
+            .returning(|_, _, _| Err(efi::Status::OUT_OF_RESOURCES));
+
+        let installer = ProtocolInstaller::new(&CounterProtocol, CounterInterface { increment }, 0);
+        assert!(matches!(installer.install(&boot_services, None), Err(efi::Status::OUT_OF_RESOURCES)));
+    }
+}
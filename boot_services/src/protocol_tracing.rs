@@ -0,0 +1,199 @@
+//! Tracing of the protocols opened and closed through [`StandardBootServices`](crate::StandardBootServices), to debug
+//! agents leaking protocol opens.
+//!
+//! With the `protocol_tracing` feature, every OpenProtocol and CloseProtocol call made through a StandardBootServices is
+//! logged with its handles and attributes, and the Rust call site of each open is recorded in [`PROTOCOL_TRACER`] until
+//! the matching close. The opens left are the same as the ones reported by
+//! [`BootServices::open_protocol_information`], with the code that opened them.
+//!
+//! ```ignore
+//! for open in PROTOCOL_TRACER.leaks(&BOOT_SERVICES) {
+//!     log::warn!("Protocol still open: {}", open);
+//! }
+//! ```
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Display},
+    panic::Location,
+};
+
+use r_efi::efi;
+
+use crate::{
+    tpl::{Tpl, TplLock},
+    BootServices,
+};
+
+/// Global tracer, recording the opens of every [`StandardBootServices`](crate::StandardBootServices).
+pub static PROTOCOL_TRACER: ProtocolTracer = ProtocolTracer::new();
+
+/// A protocol opened with OpenProtocol and not closed yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracedOpen {
+    pub handle: efi::Handle,
+    pub protocol: efi::Guid,
+    pub agent_handle: efi::Handle,
+    pub controller_handle: efi::Handle,
+    pub attributes: u32,
+    /// Rust call site of the open.
+    pub location: &'static Location<'static>,
+}
+
+impl Display for TracedOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} on handle {:?} by agent {:?} for controller {:?} with attributes {:#x} at {}",
+            self.protocol, self.handle, self.agent_handle, self.controller_handle, self.attributes, self.location
+        )
+    }
+}
+
+/// Remembers the protocols opened and not closed yet, with their call site.
+///
+/// The tracer is protected by raising the TPL to [`Tpl::NOTIFY`], so protocols can be opened from event notify
+/// functions.
+#[derive(Debug)]
+pub struct ProtocolTracer {
+    opens: TplLock<Vec<TracedOpen>>,
+}
+
+// SAFETY: The opens are only accessed with the lock held, the handles they hold are opaque.
+unsafe impl Sync for ProtocolTracer {}
+
+impl ProtocolTracer {
+    /// Create an empty tracer.
+    pub const fn new() -> Self {
+        Self { opens: TplLock::new(Tpl::NOTIFY, Vec::new()) }
+    }
+
+    fn with_opens<B, F, T>(&self, boot_services: &B, f: F) -> T
+    where
+        B: BootServices,
+        F: FnOnce(&mut Vec<TracedOpen>) -> T,
+    {
+        self.opens.with(boot_services, f)
+    }
+
+    /// Logs an OpenProtocol call that returned *status* and records it if it succeeded.
+    ///
+    /// Opens with `efi::OPEN_PROTOCOL_TEST_PROTOCOL` are not recorded, as they do not need to be closed.
+    pub fn trace_open<B: BootServices>(&self, boot_services: &B, open: TracedOpen, status: efi::Status) {
+        log::debug!("OpenProtocol {}: {:?}", open, status);
+        if !status.is_error() && open.attributes & efi::OPEN_PROTOCOL_TEST_PROTOCOL == 0 {
+            self.with_opens(boot_services, |opens| opens.push(open));
+        }
+    }
+
+    /// Logs a CloseProtocol call made at *location* that returned *status*, and forgets the matching opens if it
+    /// succeeded.
+    #[allow(clippy::too_many_arguments)]
+    pub fn trace_close<B: BootServices>(
+        &self,
+        boot_services: &B,
+        handle: efi::Handle,
+        protocol: &efi::Guid,
+        agent_handle: efi::Handle,
+        controller_handle: efi::Handle,
+        location: &'static Location<'static>,
+        status: efi::Status,
+    ) {
+        log::debug!(
+            "CloseProtocol {:?} on handle {:?} by agent {:?} for controller {:?} at {}: {:?}",
+            protocol,
+            handle,
+            agent_handle,
+            controller_handle,
+            location,
+            status
+        );
+        if !status.is_error() {
+            // Opening the same protocol several times with the same agent and controller shares one open entry, which
+            // is removed by a single close.
+            self.with_opens(boot_services, |opens| {
+                opens.retain(|open| {
+                    (open.handle, &open.protocol, open.agent_handle, open.controller_handle)
+                        != (handle, protocol, agent_handle, controller_handle)
+                })
+            });
+        }
+    }
+
+    /// Returns the opens without a matching close, oldest first.
+    ///
+    /// Protocols opened with `efi::OPEN_PROTOCOL_GET_PROTOCOL` or `efi::OPEN_PROTOCOL_BY_HANDLE_PROTOCOL` are not
+    /// required to be closed, filter them out with [`TracedOpen::attributes`] if they are expected.
+    pub fn leaks<B: BootServices>(&self, boot_services: &B) -> Vec<TracedOpen> {
+        self.with_opens(boot_services, |opens| opens.clone())
+    }
+
+    /// Forgets the recorded opens, e.g. to only report the leaks of a given phase.
+    pub fn clear<B: BootServices>(&self, boot_services: &B) {
+        self.with_opens(boot_services, |opens| opens.clear())
+    }
+}
+
+impl Default for ProtocolTracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+
+    const TEST_GUID: efi::Guid = efi::Guid::from_fields(0x1, 0x2, 0x3, 0x4, 0x5, &[0x6; 6]);
+
+    fn boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services
+    }
+
+    #[track_caller]
+    fn open(handle: usize, agent_handle: usize, attributes: u32) -> TracedOpen {
+        TracedOpen {
+            handle: handle as efi::Handle,
+            protocol: TEST_GUID,
+            agent_handle: agent_handle as efi::Handle,
+            controller_handle: 9_usize as efi::Handle,
+            attributes,
+            location: Location::caller(),
+        }
+    }
+
+    #[test]
+    fn test_leaks() {
+        let boot_services = boot_services();
+        let tracer = ProtocolTracer::new();
+        let controller = 9_usize as efi::Handle;
+
+        tracer.trace_open(&boot_services, open(1, 2, efi::OPEN_PROTOCOL_BY_DRIVER), efi::Status::SUCCESS);
+        tracer.trace_open(&boot_services, open(1, 2, efi::OPEN_PROTOCOL_BY_DRIVER), efi::Status::SUCCESS);
+        let leaked = open(1, 3, efi::OPEN_PROTOCOL_GET_PROTOCOL);
+        tracer.trace_open(&boot_services, leaked.clone(), efi::Status::SUCCESS);
+        tracer.trace_open(&boot_services, open(1, 4, efi::OPEN_PROTOCOL_TEST_PROTOCOL), efi::Status::SUCCESS);
+        tracer.trace_open(&boot_services, open(1, 5, efi::OPEN_PROTOCOL_BY_DRIVER), efi::Status::ACCESS_DENIED);
+        assert_eq!(3, tracer.leaks(&boot_services).len());
+
+        // A failed close keeps the opens, a successful one removes all the opens of the agent.
+        let handle = 1_usize as efi::Handle;
+        let agent = 2_usize as efi::Handle;
+        let location = Location::caller();
+        tracer.trace_close(&boot_services, handle, &TEST_GUID, agent, controller, location, efi::Status::NOT_FOUND);
+        assert_eq!(3, tracer.leaks(&boot_services).len());
+        tracer.trace_close(&boot_services, handle, &TEST_GUID, agent, controller, location, efi::Status::SUCCESS);
+
+        let leaks = tracer.leaks(&boot_services);
+        assert_eq!(vec![leaked], leaks);
+        assert_eq!(file!(), leaks[0].location.file());
+        assert!(leaks[0].to_string().ends_with(&std::format!("with attributes 0x2 at {}", leaks[0].location)));
+
+        tracer.clear(&boot_services);
+        assert!(tracer.leaks(&boot_services).is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+//! This module defines the EFI_RESET_NOTIFICATION_PROTOCOL and [`register`] to run Rust functions before the platform
+//! resets, e.g. to flush caches or quiesce devices when a reset is initiated by another component.
+//!
+//! ```ignore
+//! fn flush_cache(reset: &Reset) {
+//!     log::info!("{:?} reset: {:?}", reset.reset_type, reset.description());
+//!     CACHE.lock().flush();
+//! }
+//!
+//! reset_notification::register(&BOOT_SERVICES, flush_cache)?.leak();
+//! ```
+//!
+//! [UEFI Spec Documentation: 8.5.1.1. EFI_RESET_NOTIFICATION_PROTOCOL](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#efi-reset-notification-protocol)
+
+use alloc::string::String;
+use core::{
+    ffi::c_void,
+    mem, ptr, slice,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::{protocol_handler::ResetNotification, BootServices};
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x9da34ae0, 0xeaf9, 0x4bbf, 0x8e, 0xc3, &[0xfd, 0x60, 0x22, 0x6c, 0x44, 0xbe]);
+
+pub type RegisterResetNotify = extern "efiapi" fn(*mut Protocol, efi::RuntimeResetSystem) -> efi::Status;
+
+pub type UnregisterResetNotify = extern "efiapi" fn(*mut Protocol, efi::RuntimeResetSystem) -> efi::Status;
+
+#[repr(C)]
+pub struct Protocol {
+    pub register_reset_notify: RegisterResetNotify,
+    pub unregister_reset_notify: UnregisterResetNotify,
+}
+
+/// Type of a platform reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetType {
+    Cold,
+    Warm,
+    Shutdown,
+    /// A reset whose type is given by the GUID of the reset data, see [`Reset::platform_specific_guid`].
+    PlatformSpecific,
+    /// A reset type not defined by the specification.
+    Other(efi::ResetType),
+}
+
+impl From<efi::ResetType> for ResetType {
+    fn from(reset_type: efi::ResetType) -> Self {
+        match reset_type {
+            efi::RESET_COLD => ResetType::Cold,
+            efi::RESET_WARM => ResetType::Warm,
+            efi::RESET_SHUTDOWN => ResetType::Shutdown,
+            efi::RESET_PLATFORM_SPECIFIC => ResetType::PlatformSpecific,
+            other => ResetType::Other(other),
+        }
+    }
+}
+
+/// A platform reset about to happen, as reported to a [`ResetNotify`] function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reset<'a> {
+    pub reset_type: ResetType,
+    /// Status of the reset, an error if the reset is caused by an error.
+    pub status: efi::Status,
+    /// Reset data, a null-terminated UCS-2 description optionally followed by binary data.
+    pub data: &'a [u8],
+}
+
+impl Reset<'_> {
+    // Returns the UCS-2 description and the length of its encoding with the null terminator.
+    fn split_description(&self) -> Option<(&[u8], usize)> {
+        let len = self.data.chunks_exact(2).position(|c| c == [0, 0])? * 2;
+        Some((&self.data[..len], len + 2))
+    }
+
+    /// Returns the description at the start of the reset data, or None if the data does not start with a
+    /// null-terminated UCS-2 string.
+    pub fn description(&self) -> Option<String> {
+        let (description, _) = self.split_description()?;
+        char::decode_utf16(description.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])))
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect::<String>()
+            .into()
+    }
+
+    /// Returns the GUID following the description of a [`ResetType::PlatformSpecific`] reset, if any.
+    pub fn platform_specific_guid(&self) -> Option<efi::Guid> {
+        if self.reset_type != ResetType::PlatformSpecific {
+            return None;
+        }
+        let (_, offset) = self.split_description()?;
+        let guid = self.data.get(offset..offset + mem::size_of::<efi::Guid>())?;
+        Some(efi::Guid::from_bytes(guid.try_into().unwrap()))
+    }
+}
+
+/// Rust function notified of a platform reset, see [`register`].
+///
+/// It runs right before the reset, usually at [`Tpl::HIGH_LEVEL`](crate::tpl::Tpl::HIGH_LEVEL) when called from
+/// runtime, so it must not block nor allocate memory.
+pub type ResetNotify = fn(reset: &Reset);
+
+/// Number of Rust functions that can be registered at the same time.
+pub const MAX_RESET_NOTIFY: usize = 8;
+
+// The reset notify functions of the protocol do not take a context, so each registered Rust function gets one of a
+// fixed set of trampolines, reading the function from its slot.
+static SLOTS: [AtomicPtr<()>; MAX_RESET_NOTIFY] = [const { AtomicPtr::new(ptr::null_mut()) }; MAX_RESET_NOTIFY];
+
+const TRAMPOLINES: [efi::RuntimeResetSystem; MAX_RESET_NOTIFY] = [
+    trampoline::<0>,
+    trampoline::<1>,
+    trampoline::<2>,
+    trampoline::<3>,
+    trampoline::<4>,
+    trampoline::<5>,
+    trampoline::<6>,
+    trampoline::<7>,
+];
+
+extern "efiapi" fn trampoline<const SLOT: usize>(
+    reset_type: efi::ResetType,
+    status: efi::Status,
+    data_size: usize,
+    data: *mut c_void,
+) {
+    let notify = SLOTS[SLOT].load(Ordering::SeqCst);
+    if notify.is_null() {
+        return;
+    }
+    // SAFETY: The slots only hold ResetNotify functions, and the reset data is provided by the caller of ResetSystem.
+    let (notify, data) = unsafe {
+        (
+            mem::transmute::<*mut (), ResetNotify>(notify),
+            if data.is_null() { &[][..] } else { slice::from_raw_parts(data as *const u8, data_size) },
+        )
+    };
+    notify(&Reset { reset_type: reset_type.into(), status, data });
+}
+
+/// A [`ResetNotify`] function registered with [`register`], unregistered when dropped.
+#[must_use = "if unused the reset notify function is immediately unregistered"]
+#[derive(Debug)]
+pub struct ResetNotifyRegistration {
+    protocol: *mut Protocol,
+    slot: usize,
+}
+
+impl ResetNotifyRegistration {
+    /// Unregisters the function, returning the error of the protocol if it fails.
+    pub fn unregister(self) -> Result<(), efi::Status> {
+        let result = self.unregister_slot();
+        mem::forget(self);
+        result
+    }
+
+    /// Keeps the function registered until the platform resets.
+    pub fn leak(self) {
+        mem::forget(self)
+    }
+
+    fn unregister_slot(&self) -> Result<(), efi::Status> {
+        // SAFETY: The protocol was located when the function was registered, it is not uninstalled while used.
+        let protocol = unsafe { &*self.protocol };
+        match (protocol.unregister_reset_notify)(self.protocol, TRAMPOLINES[self.slot]) {
+            status if status.is_error() => Err(status),
+            _ => {
+                SLOTS[self.slot].store(ptr::null_mut(), Ordering::SeqCst);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for ResetNotifyRegistration {
+    fn drop(&mut self) {
+        let _ = self.unregister_slot();
+    }
+}
+
+/// Registers *notify* to be called before the platform resets, with the reset notification protocol.
+///
+/// Returns `efi::Status::OUT_OF_RESOURCES` if [`MAX_RESET_NOTIFY`] functions are already registered.
+pub fn register<B: BootServices>(
+    boot_services: &B,
+    notify: ResetNotify,
+) -> Result<ResetNotifyRegistration, efi::Status> {
+    // SAFETY: The interface is only used through the registration.
+    let protocol = unsafe { boot_services.locate_protocol(&ResetNotification, None)? };
+    register_with_protocol(protocol, notify)
+}
+
+/// Registers *notify* like [`register`], with a *protocol* instance already located.
+pub fn register_with_protocol(
+    protocol: &'static mut Protocol,
+    notify: ResetNotify,
+) -> Result<ResetNotifyRegistration, efi::Status> {
+    let notify = notify as *mut ();
+    let slot = SLOTS
+        .iter()
+        .position(|slot| slot.compare_exchange(ptr::null_mut(), notify, Ordering::SeqCst, Ordering::SeqCst).is_ok())
+        .ok_or(efi::Status::OUT_OF_RESOURCES)?;
+    match (protocol.register_reset_notify)(protocol, TRAMPOLINES[slot]) {
+        status if status.is_error() => {
+            SLOTS[slot].store(ptr::null_mut(), Ordering::SeqCst);
+            Err(status)
+        }
+        _ => Ok(ResetNotifyRegistration { protocol, slot }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use alloc::{boxed::Box, vec::Vec};
+    use std::sync::Mutex;
+
+    // Reset notify functions registered with the fake protocol, and the resets notified to the test functions.
+    static REGISTERED: Mutex<Vec<efi::RuntimeResetSystem>> = Mutex::new(Vec::new());
+    type Notified = (ResetType, efi::Status, Option<String>, Option<efi::Guid>);
+    static NOTIFIED: Mutex<Vec<Notified>> = Mutex::new(Vec::new());
+
+    extern "efiapi" fn register_reset_notify(_: *mut Protocol, function: efi::RuntimeResetSystem) -> efi::Status {
+        REGISTERED.lock().unwrap().push(function);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn unregister_reset_notify(_: *mut Protocol, function: efi::RuntimeResetSystem) -> efi::Status {
+        let mut registered = REGISTERED.lock().unwrap();
+        match registered.iter().position(|&f| f as usize == function as usize) {
+            Some(idx) => {
+                registered.remove(idx);
+                efi::Status::SUCCESS
+            }
+            None => efi::Status::INVALID_PARAMETER,
+        }
+    }
+
+    fn reset_system(reset_type: efi::ResetType, status: efi::Status, data: &mut [u8]) {
+        let registered = REGISTERED.lock().unwrap().clone();
+        let data_ptr = if data.is_empty() { ptr::null_mut() } else { data.as_mut_ptr() as *mut c_void };
+        for function in registered {
+            function(reset_type, status, data.len(), data_ptr);
+        }
+    }
+
+    fn notify(reset: &Reset) {
+        NOTIFIED.lock().unwrap().push((
+            reset.reset_type,
+            reset.status,
+            reset.description(),
+            reset.platform_specific_guid(),
+        ));
+    }
+
+    fn protocol() -> &'static mut Protocol {
+        Box::leak(Box::new(Protocol { register_reset_notify, unregister_reset_notify }))
+    }
+
+    #[test]
+    fn test_register_and_notify() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_locate_protocol::<ResetNotification, Protocol>().returning(|_, _| Ok(protocol()));
+
+        let registration = register(&boot_services, notify).unwrap();
+        reset_system(efi::RESET_WARM, efi::Status::SUCCESS, &mut []);
+
+        let guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        let mut data = "Update\0".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>();
+        data.extend_from_slice(guid.as_bytes());
+        reset_system(efi::RESET_PLATFORM_SPECIFIC, efi::Status::ABORTED, &mut data);
+        reset_system(efi::RESET_COLD, efi::Status::SUCCESS, &mut data);
+        reset_system(9, efi::Status::SUCCESS, &mut [0x41]);
+
+        registration.unregister().unwrap();
+        reset_system(efi::RESET_SHUTDOWN, efi::Status::SUCCESS, &mut []);
+
+        let update = Some(String::from("Update"));
+        assert_eq!(
+            vec![
+                (ResetType::Warm, efi::Status::SUCCESS, None, None),
+                (ResetType::PlatformSpecific, efi::Status::ABORTED, update.clone(), Some(guid)),
+                (ResetType::Cold, efi::Status::SUCCESS, update, None),
+                (ResetType::Other(9), efi::Status::SUCCESS, None, None),
+            ],
+            *NOTIFIED.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_registrations_are_limited() {
+        fn ignore(_: &Reset) {}
+
+        let registrations =
+            (0..MAX_RESET_NOTIFY).map_while(|_| register_with_protocol(protocol(), ignore).ok()).collect::<Vec<_>>();
+        assert!(!registrations.is_empty());
+        assert_eq!(Err(efi::Status::OUT_OF_RESOURCES), register_with_protocol(protocol(), ignore).map(|r| r.leak()));
+
+        // Dropping a registration frees its slot.
+        drop(registrations);
+        register_with_protocol(protocol(), ignore).unwrap().unregister().unwrap();
+    }
+}
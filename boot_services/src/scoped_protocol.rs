@@ -0,0 +1,215 @@
+//! Protocol interfaces uninstalled at the end of their scope.
+//!
+//! A [`ScopedProtocolInstallation`] uninstalls its protocol interface and reclaims the interface object when dropped,
+//! so a driver that returns early with an error or unloads does not leave a dangling interface on a handle.
+//!
+//! ```ignore
+//! let installation = BOOT_SERVICES.install_protocol_scoped(None, &SerialIo, Box::new(serial_io))?;
+//! initialize_device(installation.handle())?; // The interface is uninstalled if this fails.
+//! installation.leak();
+//! ```
+
+use r_efi::efi;
+
+use crate::{
+    c_ptr::{CMutRef, PtrMetadata},
+    protocol_handler::Protocol,
+    BootServices,
+};
+
+/// A protocol interface installed by [`BootServicesExt::install_protocol_scoped`], uninstalled when dropped.
+///
+/// If the interface can not be uninstalled when dropped, e.g. because it is still opened by a driver, it is leaked
+/// since it may still be in use.
+///
+/// [`BootServicesExt::install_protocol_scoped`]: crate::BootServicesExt::install_protocol_scoped
+#[must_use = "if unused the protocol interface will immediately be uninstalled"]
+pub struct ScopedProtocolInstallation<'a, B, P, R>
+where
+    B: BootServices,
+    P: Protocol + 'static,
+    R: CMutRef<'static, Type = P::Interface> + 'static,
+    P::Interface: 'static,
+{
+    boot_services: &'a B,
+    handle: efi::Handle,
+    protocol: &'a P,
+    key: Option<PtrMetadata<'static, R>>,
+}
+
+impl<'a, B, P, R> ScopedProtocolInstallation<'a, B, P, R>
+where
+    B: BootServices,
+    P: Protocol + 'static,
+    R: CMutRef<'static, Type = P::Interface> + 'static,
+    P::Interface: 'static,
+{
+    pub(crate) fn new(
+        boot_services: &'a B,
+        handle: efi::Handle,
+        protocol: &'a P,
+        key: PtrMetadata<'static, R>,
+    ) -> Self {
+        Self { boot_services, handle, protocol, key: Some(key) }
+    }
+
+    /// Returns the handle the protocol interface is installed on.
+    pub fn handle(&self) -> efi::Handle {
+        self.handle
+    }
+
+    /// Keeps the protocol interface installed.
+    ///
+    /// Returns the handle and the key to uninstall the interface with [`BootServices::uninstall_protocol_interface`].
+    pub fn leak(mut self) -> (efi::Handle, PtrMetadata<'static, R>) {
+        (self.handle, self.key.take().unwrap())
+    }
+
+    /// Uninstalls the protocol interface and returns the interface object.
+    ///
+    /// The interface is leaked if it can not be uninstalled and the error is returned.
+    pub fn uninstall(mut self) -> Result<R, efi::Status> {
+        let key = self.key.take().unwrap();
+        self.boot_services.uninstall_protocol_interface(self.handle, self.protocol, key)
+    }
+}
+
+impl<B, P, R> Drop for ScopedProtocolInstallation<'_, B, P, R>
+where
+    B: BootServices,
+    P: Protocol + 'static,
+    R: CMutRef<'static, Type = P::Interface> + 'static,
+    P::Interface: 'static,
+{
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let _ = self.boot_services.uninstall_protocol_interface(self.handle, self.protocol, key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{c_ptr::CPtr, BootServicesExt, MockBootServices};
+    use alloc::boxed::Box;
+    use core::ops::Deref;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    static TEST_PROTOCOL_GUID: efi::Guid =
+        efi::Guid::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+
+    struct TestProtocol;
+    unsafe impl Protocol for TestProtocol {
+        type Interface = TestInterface;
+
+        fn protocol_guid(&self) -> &'static efi::Guid {
+            &TEST_PROTOCOL_GUID
+        }
+    }
+
+    impl Deref for TestProtocol {
+        type Target = efi::Guid;
+
+        fn deref(&self) -> &Self::Target {
+            self.protocol_guid()
+        }
+    }
+
+    // Counts its drops.
+    struct TestInterface(Arc<AtomicUsize>);
+
+    impl Drop for TestInterface {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Uninstalling is not expected if *uninstall_status* is None.
+    fn boot_services(uninstall_status: Option<efi::Status>) -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_install_protocol_interface::<TestProtocol, Box<TestInterface>, TestInterface>().returning(
+            |handle, _, interface| {
+                assert!(handle.is_none());
+                let key = interface.metadata();
+                let _ = interface.into_ptr();
+                Ok((1_usize as _, key))
+            },
+        );
+        let uninstall =
+            boot_services.expect_uninstall_protocol_interface::<TestProtocol, Box<TestInterface>, TestInterface>();
+        match uninstall_status {
+            None => {
+                uninstall.never();
+            }
+            Some(uninstall_status) => {
+                uninstall.times(1).returning(move |handle, _, key| {
+                    assert_eq!(1, handle as usize);
+                    match uninstall_status {
+                        s if s.is_error() => Err(s),
+                        _ => Ok(unsafe { key.into_original_ptr() }),
+                    }
+                });
+            }
+        }
+        boot_services
+    }
+
+    #[test]
+    fn test_install_protocol_scoped_drop() {
+        let boot_services = boot_services(Some(efi::Status::SUCCESS));
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        let installation =
+            boot_services.install_protocol_scoped(None, &TestProtocol, Box::new(TestInterface(drops.clone()))).unwrap();
+        assert_eq!(1, installation.handle() as usize);
+        assert_eq!(0, drops.load(Ordering::Relaxed));
+
+        drop(installation);
+        assert_eq!(1, drops.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_install_protocol_scoped_uninstall() {
+        let boot_services = boot_services(Some(efi::Status::SUCCESS));
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        let installation =
+            boot_services.install_protocol_scoped(None, &TestProtocol, Box::new(TestInterface(drops.clone()))).unwrap();
+        let interface = installation.uninstall().unwrap();
+        assert_eq!(0, drops.load(Ordering::Relaxed));
+
+        drop(interface);
+        assert_eq!(1, drops.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_install_protocol_scoped_leak() {
+        let boot_services = boot_services(None);
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        let installation =
+            boot_services.install_protocol_scoped(None, &TestProtocol, Box::new(TestInterface(drops.clone()))).unwrap();
+        let (handle, key) = installation.leak();
+        assert_eq!(1, handle as usize);
+        assert_eq!(0, drops.load(Ordering::Relaxed));
+
+        drop(unsafe { key.into_original_ptr() });
+        assert_eq!(1, drops.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_install_protocol_scoped_uninstall_error() {
+        let boot_services = boot_services(Some(efi::Status::ACCESS_DENIED));
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        let installation =
+            boot_services.install_protocol_scoped(None, &TestProtocol, Box::new(TestInterface(drops.clone()))).unwrap();
+        assert_eq!(Err(efi::Status::ACCESS_DENIED), installation.uninstall().map(|_| ()));
+        // The interface may still be used by whoever prevented the uninstall.
+        assert_eq!(0, drops.load(Ordering::Relaxed));
+    }
+}
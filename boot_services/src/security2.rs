@@ -0,0 +1,290 @@
+//! This module defines the EFI_SECURITY2_ARCH_PROTOCOL, a [`Security2`] wrapper to authenticate images with the
+//! platform policy before loading them, and [`install_authentication_handler`] to provide the protocol with a Rust
+//! handler in environments without one, such as emulators and tests.
+//!
+//! ```ignore
+//! let security = Security2::new(unsafe { BOOT_SERVICES.locate_protocol(&protocol_handler::Security2, None)? });
+//! match security.file_authentication(option.device_path(), Some(&image), true)? {
+//!     ImageAuthentication::Authenticated => BOOT_SERVICES.load_image(true, image_handle, option.device_path(), Some(&image))?,
+//!     _ => return Err(efi::Status::SECURITY_VIOLATION),
+//! };
+//! ```
+//!
+//! See the PI Specification, Volume 2, Security2 Architectural Protocol.
+
+use alloc::boxed::Box;
+use core::{ffi::c_void, ptr::NonNull, slice};
+
+use r_efi::efi::{self, protocols::device_path};
+
+use crate::{protocol_handler, BootServices};
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x94ab2f58, 0x1438, 0x4ef1, 0x91, 0x52, &[0x18, 0x94, 0x1a, 0x3a, 0x0e, 0x68]);
+
+pub type FileAuthentication =
+    extern "efiapi" fn(*const Protocol, *const device_path::Protocol, *mut c_void, usize, efi::Boolean) -> efi::Status;
+
+#[repr(C)]
+pub struct Protocol {
+    pub file_authentication: FileAuthentication,
+}
+
+/// The result of the authentication of an image with the platform policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageAuthentication {
+    /// The image is authenticated, or the platform policy does not require it to be.
+    Authenticated,
+    /// The image did not authenticate and the platform policy places it in the untrusted state, it can be loaded but
+    /// not started.
+    Untrusted,
+    /// The image did not authenticate and the platform policy forbids its use.
+    Denied,
+}
+
+impl ImageAuthentication {
+    /// Returns the status of the authentication, as returned by FileAuthentication.
+    pub fn status(self) -> efi::Status {
+        match self {
+            ImageAuthentication::Authenticated => efi::Status::SUCCESS,
+            ImageAuthentication::Untrusted => efi::Status::SECURITY_VIOLATION,
+            ImageAuthentication::Denied => efi::Status::ACCESS_DENIED,
+        }
+    }
+
+    fn from_status(status: efi::Status) -> Result<Self, efi::Status> {
+        match status {
+            efi::Status::SUCCESS => Ok(ImageAuthentication::Authenticated),
+            efi::Status::SECURITY_VIOLATION => Ok(ImageAuthentication::Untrusted),
+            efi::Status::ACCESS_DENIED => Ok(ImageAuthentication::Denied),
+            status => Err(status),
+        }
+    }
+}
+
+/// Rust friendly wrapper around an EFI_SECURITY2_ARCH_PROTOCOL instance.
+///
+/// The interface can be retrieved with [`BootServices::locate_protocol`](crate::BootServices::locate_protocol) using
+/// [`Security2`](crate::protocol_handler::Security2).
+pub struct Security2<'a> {
+    protocol: &'a Protocol,
+}
+
+impl<'a> Security2<'a> {
+    /// Create a new Security2 from a security2 arch protocol interface.
+    pub fn new(protocol: &'a Protocol) -> Self {
+        Self { protocol }
+    }
+
+    /// Authenticates the image at *device_path* and/or in *file* with the platform policy.
+    ///
+    /// *device_path* may be null if *file* is given, and *file* may be None to only apply the policy of the device.
+    /// *boot_policy* is true when the image is loaded by the boot manager for a boot option.
+    ///
+    /// Errors other than the authentication failures of [`ImageAuthentication`] are returned as is, e.g.
+    /// [`efi::Status::INVALID_PARAMETER`] if neither the device path nor the file are given.
+    pub fn file_authentication(
+        &self,
+        device_path: *const device_path::Protocol,
+        file: Option<&[u8]>,
+        boot_policy: bool,
+    ) -> Result<ImageAuthentication, efi::Status> {
+        let (file_buffer, file_size) =
+            file.map_or((core::ptr::null_mut(), 0), |f| (f.as_ptr() as *mut c_void, f.len()));
+        ImageAuthentication::from_status((self.protocol.file_authentication)(
+            self.protocol,
+            device_path,
+            file_buffer,
+            file_size,
+            boot_policy.into(),
+        ))
+    }
+}
+
+/// Rust handler of an authentication handler installed with [`install_authentication_handler`], called with the
+/// parameters of [`Security2::file_authentication`].
+pub type AuthenticationHandler = fn(
+    device_path: *const device_path::Protocol,
+    file: Option<&[u8]>,
+    boot_policy: bool,
+) -> Result<ImageAuthentication, efi::Status>;
+
+// The installed interface, the protocol is the first field so the thunk can get back to the handler.
+#[repr(C)]
+struct AuthenticationInterface {
+    protocol: Protocol,
+    handler: AuthenticationHandler,
+}
+
+extern "efiapi" fn file_authentication_thunk(
+    this: *const Protocol,
+    device_path: *const device_path::Protocol,
+    file_buffer: *mut c_void,
+    file_size: usize,
+    boot_policy: efi::Boolean,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of the interface installed by install_authentication_handler.
+    let Some(interface) = (unsafe { (this as *const AuthenticationInterface).as_ref() }) else {
+        return efi::Status::INVALID_PARAMETER;
+    };
+    let file = match file_buffer.is_null() {
+        true => None,
+        // SAFETY: The caller provides a buffer of file_size bytes.
+        false => Some(unsafe { slice::from_raw_parts(file_buffer as *const u8, file_size) }),
+    };
+    if device_path.is_null() && file.is_none() {
+        return efi::Status::INVALID_PARAMETER;
+    }
+    match (interface.handler)(device_path, file, boot_policy.into()) {
+        Ok(authentication) => authentication.status(),
+        Err(status) => status,
+    }
+}
+
+/// Installs the EFI_SECURITY2_ARCH_PROTOCOL on a new handle with *handler* authenticating the images.
+///
+/// This is meant for environments without a platform security driver, such as emulators and tests, returns
+/// [`efi::Status::ALREADY_STARTED`] if the protocol is already installed.
+pub fn install_authentication_handler<B: BootServices>(
+    boot_services: &B,
+    handler: AuthenticationHandler,
+) -> Result<InstalledAuthenticationHandler<'_, B>, efi::Status> {
+    // SAFETY: The interface is not used.
+    if unsafe { boot_services.locate_protocol(&protocol_handler::Security2, None) }.is_ok() {
+        return Err(efi::Status::ALREADY_STARTED);
+    }
+    let interface = Box::into_raw(Box::new(AuthenticationInterface {
+        protocol: Protocol { file_authentication: file_authentication_thunk },
+        handler,
+    }));
+    // SAFETY: The interface starts with the protocol and lives until the handler is uninstalled.
+    match unsafe { boot_services.install_protocol_interface_unchecked(None, &PROTOCOL_GUID, interface as *mut c_void) }
+    {
+        // SAFETY: The pointer comes from Box::into_raw.
+        Ok(handle) => Ok(InstalledAuthenticationHandler {
+            boot_services,
+            handle,
+            interface: unsafe { NonNull::new_unchecked(interface) },
+        }),
+        Err(status) => {
+            // SAFETY: The interface was not installed.
+            drop(unsafe { Box::from_raw(interface) });
+            Err(status)
+        }
+    }
+}
+
+/// An authentication handler installed by [`install_authentication_handler`].
+///
+/// The handler stays installed when dropped, use [`InstalledAuthenticationHandler::uninstall`] to remove it.
+pub struct InstalledAuthenticationHandler<'a, B: BootServices> {
+    boot_services: &'a B,
+    handle: efi::Handle,
+    interface: NonNull<AuthenticationInterface>,
+}
+
+impl<B: BootServices> InstalledAuthenticationHandler<'_, B> {
+    /// Returns the handle the protocol is installed on.
+    pub fn handle(&self) -> efi::Handle {
+        self.handle
+    }
+
+    /// Uninstalls the protocol and frees its interface.
+    ///
+    /// The handler is returned if it can not be uninstalled.
+    pub fn uninstall(self) -> Result<(), (Self, efi::Status)> {
+        // SAFETY: The interface is the one installed on the handle.
+        match unsafe {
+            self.boot_services.uninstall_protocol_interface_unchecked(
+                self.handle,
+                &PROTOCOL_GUID,
+                self.interface.as_ptr() as *mut c_void,
+            )
+        } {
+            Ok(()) => {
+                // SAFETY: The interface is no longer installed.
+                drop(unsafe { Box::from_raw(self.interface.as_ptr()) });
+                Ok(())
+            }
+            Err(status) => Err((self, status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn signed_only(
+        device_path: *const device_path::Protocol,
+        file: Option<&[u8]>,
+        boot_policy: bool,
+    ) -> Result<ImageAuthentication, efi::Status> {
+        match (device_path.is_null(), file) {
+            (_, Some(b"signed")) => Ok(ImageAuthentication::Authenticated),
+            (_, Some(b"unsigned")) if boot_policy => Ok(ImageAuthentication::Denied),
+            (_, Some(_)) => Ok(ImageAuthentication::Untrusted),
+            (false, None) => Err(efi::Status::NOT_FOUND),
+            (true, None) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_install_authentication_handler() {
+        static INTERFACE: AtomicUsize = AtomicUsize::new(0);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_protocol::<protocol_handler::Security2, Protocol>()
+            .times(1)
+            .returning(|_, _| Err(efi::Status::NOT_FOUND));
+        boot_services.expect_install_protocol_interface_unchecked().times(1).returning(|handle, guid, interface| {
+            assert!(handle.is_none());
+            assert_eq!(&PROTOCOL_GUID, guid);
+            INTERFACE.store(interface as usize, Ordering::Relaxed);
+            Ok(1_usize as efi::Handle)
+        });
+        boot_services.expect_uninstall_protocol_interface_unchecked().times(1).returning(|handle, guid, interface| {
+            assert_eq!(1, handle as usize);
+            assert_eq!(&PROTOCOL_GUID, guid);
+            assert_eq!(INTERFACE.load(Ordering::Relaxed), interface as usize);
+            Ok(())
+        });
+
+        let installed = install_authentication_handler(&boot_services, signed_only).unwrap();
+        assert_eq!(1, installed.handle() as usize);
+
+        let security = Security2::new(unsafe { &*(INTERFACE.load(Ordering::Relaxed) as *const Protocol) });
+        let device_path = [0x7f_u8, 0xff, 4, 0];
+        let device_path = device_path.as_ptr() as *const device_path::Protocol;
+        assert_eq!(
+            Ok(ImageAuthentication::Authenticated),
+            security.file_authentication(device_path, Some(b"signed"), true)
+        );
+        assert_eq!(Ok(ImageAuthentication::Denied), security.file_authentication(device_path, Some(b"unsigned"), true));
+        assert_eq!(
+            Ok(ImageAuthentication::Untrusted),
+            security.file_authentication(core::ptr::null(), Some(b"unsigned"), false)
+        );
+        assert_eq!(Err(efi::Status::NOT_FOUND), security.file_authentication(device_path, None, false));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), security.file_authentication(core::ptr::null(), None, false));
+
+        assert!(installed.uninstall().is_ok());
+    }
+
+    #[test]
+    fn test_install_authentication_handler_already_installed() {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_locate_protocol::<protocol_handler::Security2, Protocol>()
+            .returning(|_, _| Ok(Box::leak(Box::new(Protocol { file_authentication: file_authentication_thunk }))));
+        boot_services.expect_install_protocol_interface_unchecked().never();
+
+        assert!(matches!(
+            install_authentication_handler(&boot_services, signed_only),
+            Err(efi::Status::ALREADY_STARTED)
+        ));
+    }
+}
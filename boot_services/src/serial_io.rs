@@ -0,0 +1,417 @@
+//! This module defined the EFI_SERIAL_IO_PROTOCOL and a rust friendly [`SerialPort`] wrapper around it.
+//!
+//! [UEFI Spec Documentation: 12.8. Serial I/O Protocol](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#serial-i-o-protocol)
+
+use core::{ffi::c_void, fmt};
+
+use r_efi::efi;
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0xbb25cf6f, 0xf1d4, 0x11d2, 0x9a, 0x0c, &[0x00, 0x90, 0x27, 0x3f, 0xc1, 0xfd]);
+
+pub const REVISION: u32 = 0x00010000;
+pub const REVISION1P1: u32 = 0x00010001;
+
+pub const CLEAR_TO_SEND: u32 = 0x0010;
+pub const DATA_SET_READY: u32 = 0x0020;
+pub const RING_INDICATE: u32 = 0x0040;
+pub const CARRIER_DETECT: u32 = 0x0080;
+pub const REQUEST_TO_SEND: u32 = 0x0002;
+pub const DATA_TERMINAL_READY: u32 = 0x0001;
+pub const INPUT_BUFFER_EMPTY: u32 = 0x0100;
+pub const OUTPUT_BUFFER_EMPTY: u32 = 0x0200;
+pub const HARDWARE_LOOPBACK_ENABLE: u32 = 0x1000;
+pub const SOFTWARE_LOOPBACK_ENABLE: u32 = 0x2000;
+pub const HARDWARE_FLOW_CONTROL_ENABLE: u32 = 0x4000;
+
+pub type ProtocolReset = extern "efiapi" fn(*mut Protocol) -> efi::Status;
+
+pub type ProtocolSetAttributes = extern "efiapi" fn(*mut Protocol, u64, u32, u32, u32, u8, u32) -> efi::Status;
+
+pub type ProtocolSetControlBits = extern "efiapi" fn(*mut Protocol, u32) -> efi::Status;
+
+pub type ProtocolGetControlBits = extern "efiapi" fn(*mut Protocol, *mut u32) -> efi::Status;
+
+pub type ProtocolWrite = extern "efiapi" fn(*mut Protocol, *mut usize, *mut c_void) -> efi::Status;
+
+pub type ProtocolRead = extern "efiapi" fn(*mut Protocol, *mut usize, *mut c_void) -> efi::Status;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Mode {
+    pub control_mask: u32,
+    pub timeout: u32,
+    pub baud_rate: u64,
+    pub receive_fifo_depth: u32,
+    pub data_bits: u32,
+    pub parity: u32,
+    pub stop_bits: u32,
+}
+
+#[repr(C)]
+pub struct Protocol {
+    pub revision: u32,
+    pub reset: ProtocolReset,
+    pub set_attributes: ProtocolSetAttributes,
+    pub set_control: ProtocolSetControlBits,
+    pub get_control: ProtocolGetControlBits,
+    pub write: ProtocolWrite,
+    pub read: ProtocolRead,
+    pub mode: *mut Mode,
+    pub device_type_guid: *const efi::Guid,
+}
+
+/// Parity setting of a serial line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Parity {
+    /// Use the default parity of the device.
+    Default = 0,
+    No = 1,
+    Even = 2,
+    Odd = 3,
+    Mark = 4,
+    Space = 5,
+}
+
+/// Number of stop bits of a serial line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum StopBits {
+    /// Use the default number of stop bits of the device.
+    Default = 0,
+    One = 1,
+    OneFive = 2,
+    Two = 3,
+}
+
+/// Rust friendly wrapper around an EFI_SERIAL_IO_PROTOCOL instance.
+///
+/// The interface can be retrieved with [`BootServices::locate_protocol`](crate::BootServices::locate_protocol) using
+/// [`SerialIo`](crate::protocol_handler::SerialIo).
+///
+/// # Example
+/// ```ignore
+/// let serial_io = unsafe { BOOT_SERVICES.locate_protocol(&SerialIo, None)? };
+/// let mut port = SerialPort::new(serial_io);
+/// port.set_attributes(115200, Parity::No, StopBits::One)?;
+/// writeln!(port, "Hello from the firmware serial port.")?;
+/// ```
+pub struct SerialPort<'a> {
+    protocol: &'a mut Protocol,
+}
+
+impl<'a> SerialPort<'a> {
+    /// Create a new SerialPort from a serial io protocol interface.
+    pub fn new(protocol: &'a mut Protocol) -> Self {
+        Self { protocol }
+    }
+
+    /// Return the current mode of the device, if the device exposes one.
+    pub fn mode(&self) -> Option<&Mode> {
+        // SAFETY: The mode pointer is owned by the protocol producer and is valid for the lifetime of the interface.
+        unsafe { self.protocol.mode.as_ref() }
+    }
+
+    /// Resets the serial device.
+    ///
+    /// [UEFI Spec Documentation: 12.8.2. EFI_SERIAL_IO_PROTOCOL.Reset()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-serial-io-protocol-reset)
+    pub fn reset(&mut self) -> Result<(), efi::Status> {
+        match (self.protocol.reset)(self.protocol) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the baud rate, parity and number of stop bits of the serial device.
+    ///
+    /// The receive fifo depth, timeout and data bits are kept to their current value.
+    ///
+    /// [UEFI Spec Documentation: 12.8.3. EFI_SERIAL_IO_PROTOCOL.SetAttributes()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-serial-io-protocol-setattributes)
+    pub fn set_attributes(&mut self, baud_rate: u64, parity: Parity, stop_bits: StopBits) -> Result<(), efi::Status> {
+        let (receive_fifo_depth, timeout, data_bits) =
+            self.mode().map_or((0, 0, 0), |m| (m.receive_fifo_depth, m.timeout, m.data_bits as u8));
+        match (self.protocol.set_attributes)(
+            self.protocol,
+            baud_rate,
+            receive_fifo_depth,
+            timeout,
+            parity as u32,
+            data_bits,
+            stop_bits as u32,
+        ) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the control bits on the serial device.
+    ///
+    /// [UEFI Spec Documentation: 12.8.4. EFI_SERIAL_IO_PROTOCOL.SetControl()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-serial-io-protocol-setcontrol)
+    pub fn set_control(&mut self, control: u32) -> Result<(), efi::Status> {
+        match (self.protocol.set_control)(self.protocol, control) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Retrieves the status of the control bits on the serial device.
+    ///
+    /// [UEFI Spec Documentation: 12.8.5. EFI_SERIAL_IO_PROTOCOL.GetControl()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-serial-io-protocol-getcontrol)
+    pub fn get_control(&mut self) -> Result<u32, efi::Status> {
+        let mut control = 0;
+        match (self.protocol.get_control)(self.protocol, &mut control) {
+            s if s.is_error() => Err(s),
+            _ => Ok(control),
+        }
+    }
+
+    /// Writes data to the serial device and returns the number of bytes written.
+    ///
+    /// This call blocks until the device timeout expires. A [`efi::Status::TIMEOUT`] is reported as a partial write if
+    /// some bytes were written, and returned as an error otherwise.
+    ///
+    /// [UEFI Spec Documentation: 12.8.6. EFI_SERIAL_IO_PROTOCOL.Write()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-serial-io-protocol-write)
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, efi::Status> {
+        let mut buffer_size = buffer.len();
+        match (self.protocol.write)(self.protocol, &mut buffer_size, buffer.as_ptr() as *mut c_void) {
+            s if s == efi::Status::TIMEOUT && buffer_size > 0 => Ok(buffer_size),
+            s if s.is_error() => Err(s),
+            _ => Ok(buffer_size),
+        }
+    }
+
+    /// Writes data to the serial device without blocking, and returns the number of bytes written.
+    ///
+    /// Returns `Ok(0)` when the output buffer of the device is not empty, or when no byte could be written before the
+    /// device timeout.
+    pub fn try_write(&mut self, buffer: &[u8]) -> Result<usize, efi::Status> {
+        if buffer.is_empty() || self.get_control()? & OUTPUT_BUFFER_EMPTY == 0 {
+            return Ok(0);
+        }
+        match self.write(buffer) {
+            Err(efi::Status::TIMEOUT) => Ok(0),
+            result => result,
+        }
+    }
+
+    /// Writes the entire buffer to the serial device, blocking until every byte has been written.
+    ///
+    /// Returns [`efi::Status::TIMEOUT`] if the device timeout expires with no byte written.
+    pub fn write_all(&mut self, mut buffer: &[u8]) -> Result<(), efi::Status> {
+        while !buffer.is_empty() {
+            let written = self.write(buffer)?;
+            buffer = &buffer[written..];
+        }
+        Ok(())
+    }
+
+    /// Reads data from the serial device and returns the number of bytes read.
+    ///
+    /// This call blocks until the device timeout expires. A [`efi::Status::TIMEOUT`] is reported as a partial read if
+    /// some bytes were read, and returned as an error otherwise.
+    ///
+    /// [UEFI Spec Documentation: 12.8.7. EFI_SERIAL_IO_PROTOCOL.Read()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-serial-io-protocol-read)
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, efi::Status> {
+        let mut buffer_size = buffer.len();
+        match (self.protocol.read)(self.protocol, &mut buffer_size, buffer.as_mut_ptr() as *mut c_void) {
+            s if s == efi::Status::TIMEOUT && buffer_size > 0 => Ok(buffer_size),
+            s if s.is_error() => Err(s),
+            _ => Ok(buffer_size),
+        }
+    }
+
+    /// Reads the data available on the serial device without blocking, and returns the number of bytes read.
+    ///
+    /// Returns `Ok(0)` when the input buffer of the device is empty.
+    pub fn try_read(&mut self, buffer: &mut [u8]) -> Result<usize, efi::Status> {
+        if buffer.is_empty() || self.get_control()? & INPUT_BUFFER_EMPTY != 0 {
+            return Ok(0);
+        }
+        match self.read(buffer) {
+            Err(efi::Status::TIMEOUT) => Ok(0),
+            result => result,
+        }
+    }
+
+    /// Fills the entire buffer with data from the serial device, blocking until every byte has been read.
+    ///
+    /// Returns [`efi::Status::TIMEOUT`] if the device timeout expires with no byte read.
+    pub fn read_exact(&mut self, mut buffer: &mut [u8]) -> Result<(), efi::Status> {
+        while !buffer.is_empty() {
+            let read = self.read(buffer)?;
+            buffer = &mut buffer[read..];
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for SerialPort<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::{fmt::Write, ptr, slice};
+    use std::sync::Mutex;
+
+    extern "efiapi" fn efi_reset(_this: *mut Protocol) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_set_attributes(
+        _this: *mut Protocol,
+        baud_rate: u64,
+        receive_fifo_depth: u32,
+        timeout: u32,
+        parity: u32,
+        data_bits: u8,
+        stop_bits: u32,
+    ) -> efi::Status {
+        assert_eq!(115200, baud_rate);
+        assert_eq!(16, receive_fifo_depth);
+        assert_eq!(1000, timeout);
+        assert_eq!(1, parity);
+        assert_eq!(8, data_bits);
+        assert_eq!(1, stop_bits);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_set_control(_this: *mut Protocol, _control: u32) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_get_control(_this: *mut Protocol, control: *mut u32) -> efi::Status {
+        unsafe { ptr::write(control, OUTPUT_BUFFER_EMPTY | INPUT_BUFFER_EMPTY) };
+        efi::Status::SUCCESS
+    }
+
+    static WRITTEN: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+    // Write at most 2 bytes per call to exercise partial writes.
+    extern "efiapi" fn efi_write(_this: *mut Protocol, buffer_size: *mut usize, buffer: *mut c_void) -> efi::Status {
+        unsafe {
+            let size = (*buffer_size).min(2);
+            WRITTEN.lock().unwrap().extend_from_slice(slice::from_raw_parts(buffer as *const u8, size));
+            *buffer_size = size;
+            if size == 2 {
+                efi::Status::TIMEOUT
+            } else {
+                efi::Status::SUCCESS
+            }
+        }
+    }
+
+    // Read at most 3 bytes per call to exercise partial reads.
+    extern "efiapi" fn efi_read(_this: *mut Protocol, buffer_size: *mut usize, buffer: *mut c_void) -> efi::Status {
+        unsafe {
+            let size = (*buffer_size).min(3);
+            slice::from_raw_parts_mut(buffer as *mut u8, size).fill(0xA5);
+            *buffer_size = size;
+            if size == 3 {
+                efi::Status::TIMEOUT
+            } else {
+                efi::Status::SUCCESS
+            }
+        }
+    }
+
+    // A device with data to read, whose output buffer is full and whose write times out.
+    extern "efiapi" fn efi_get_control_busy(_this: *mut Protocol, control: *mut u32) -> efi::Status {
+        unsafe { ptr::write(control, 0) };
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_write_timeout(_this: *mut Protocol, buffer_size: *mut usize, _: *mut c_void) -> efi::Status {
+        unsafe { *buffer_size = 0 };
+        efi::Status::TIMEOUT
+    }
+
+    fn protocol(mode: &mut Mode) -> Protocol {
+        Protocol {
+            revision: REVISION,
+            reset: efi_reset,
+            set_attributes: efi_set_attributes,
+            set_control: efi_set_control,
+            get_control: efi_get_control,
+            write: efi_write,
+            read: efi_read,
+            mode,
+            device_type_guid: ptr::null(),
+        }
+    }
+
+    fn mode() -> Mode {
+        Mode {
+            control_mask: 0,
+            timeout: 1000,
+            baud_rate: 9600,
+            receive_fifo_depth: 16,
+            data_bits: 8,
+            parity: Parity::Default as u32,
+            stop_bits: StopBits::Default as u32,
+        }
+    }
+
+    #[test]
+    fn test_set_attributes_should_keep_current_mode_values() {
+        let mut mode = mode();
+        let mut protocol = protocol(&mut mode);
+        let mut port = SerialPort::new(&mut protocol);
+        assert_eq!(Ok(()), port.reset());
+        assert_eq!(Ok(()), port.set_attributes(115200, Parity::No, StopBits::One));
+        assert_eq!(Ok(OUTPUT_BUFFER_EMPTY | INPUT_BUFFER_EMPTY), port.get_control());
+        assert_eq!(Ok(()), port.set_control(REQUEST_TO_SEND));
+    }
+
+    #[test]
+    fn test_write_and_fmt_write() {
+        let mut mode = mode();
+        let mut protocol = protocol(&mut mode);
+        let mut port = SerialPort::new(&mut protocol);
+
+        assert_eq!(Ok(2), port.write(b"abcde"));
+        write!(port, "{}-xyz", 12).unwrap();
+        assert_eq!(b"ab12-xyz", WRITTEN.lock().unwrap().as_slice());
+    }
+
+    #[test]
+    fn test_read_and_read_exact() {
+        let mut mode = mode();
+        let mut protocol = protocol(&mut mode);
+        let mut port = SerialPort::new(&mut protocol);
+
+        let mut buffer = [0; 8];
+        assert_eq!(Ok(3), port.read(&mut buffer));
+        assert_eq!([0xA5, 0xA5, 0xA5, 0, 0, 0, 0, 0], buffer);
+
+        let mut buffer = [0; 8];
+        assert_eq!(Ok(()), port.read_exact(&mut buffer));
+        assert_eq!([0xA5; 8], buffer);
+
+        // The input buffer of the device is empty.
+        let mut buffer = [0; 8];
+        assert_eq!(Ok(0), port.try_read(&mut buffer));
+        assert_eq!([0; 8], buffer);
+    }
+
+    #[test]
+    fn test_timeout_without_progress() {
+        let mut mode = mode();
+        let mut protocol =
+            Protocol { get_control: efi_get_control_busy, write: efi_write_timeout, ..protocol(&mut mode) };
+        let mut port = SerialPort::new(&mut protocol);
+
+        assert_eq!(Err(efi::Status::TIMEOUT), port.write(b"abc"));
+        assert_eq!(Err(efi::Status::TIMEOUT), port.write_all(b"abc"));
+        assert_eq!(Ok(0), port.try_write(b"abc"));
+        assert!(write!(port, "abc").is_err());
+
+        let mut buffer = [0; 2];
+        assert_eq!(Ok(2), port.try_read(&mut buffer));
+        assert_eq!([0xA5; 2], buffer);
+    }
+}
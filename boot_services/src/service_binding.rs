@@ -0,0 +1,170 @@
+//! This module defines the [`ServiceBinding`] protocols and helpers to create and destroy their children.
+//!
+//! Every network service has its own service binding protocol GUID, installed on the handle of the controller it
+//! manages. Creating a child installs the service protocol on a new child handle.
+//!
+//! ```ignore
+//! let child = unsafe { create_child(&BOOT_SERVICES, controller, &service_binding::TCP4, None)? };
+//! let tcp4 = unsafe { BOOT_SERVICES.handle_protocol(child, &Tcp4)? };
+//! // ...
+//! unsafe { destroy_child(&BOOT_SERVICES, controller, &service_binding::TCP4, child)? };
+//! ```
+//!
+//! [UEFI Spec Documentation: 11.6. EFI Service Binding Protocol](https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-service-binding-protocol)
+
+use core::{ops::Deref, ptr};
+
+use r_efi::efi::{self, protocols};
+
+use crate::{protocol_handler::Protocol, BootServices};
+
+/// The service binding protocol of a service, identified by its GUID.
+#[derive(Debug, Clone, Copy)]
+pub struct ServiceBinding {
+    guid: &'static efi::Guid,
+}
+
+impl ServiceBinding {
+    /// Create the service binding protocol identified by *guid*.
+    pub const fn new(guid: &'static efi::Guid) -> Self {
+        Self { guid }
+    }
+}
+
+unsafe impl Protocol for ServiceBinding {
+    type Interface = protocols::service_binding::Protocol;
+
+    fn protocol_guid(&self) -> &'static efi::Guid {
+        self.guid
+    }
+}
+
+impl Deref for ServiceBinding {
+    type Target = efi::Guid;
+
+    fn deref(&self) -> &Self::Target {
+        self.guid
+    }
+}
+
+pub const IP4: ServiceBinding = ServiceBinding::new(&protocols::ip4::SERVICE_BINDING_PROTOCOL_GUID);
+pub const IP6: ServiceBinding = ServiceBinding::new(&protocols::ip6::SERVICE_BINDING_PROTOCOL_GUID);
+pub const MANAGED_NETWORK: ServiceBinding =
+    ServiceBinding::new(&protocols::managed_network::SERVICE_BINDING_PROTOCOL_GUID);
+pub const TCP4: ServiceBinding = ServiceBinding::new(&protocols::tcp4::SERVICE_BINDING_PROTOCOL_GUID);
+pub const TCP6: ServiceBinding = ServiceBinding::new(&protocols::tcp6::SERVICE_BINDING_PROTOCOL_GUID);
+pub const UDP4: ServiceBinding = ServiceBinding::new(&protocols::udp4::SERVICE_BINDING_PROTOCOL_GUID);
+pub const UDP6: ServiceBinding = ServiceBinding::new(&protocols::udp6::SERVICE_BINDING_PROTOCOL_GUID);
+
+/// Creates a child of the service whose *service_binding* protocol is installed on *service_handle*.
+///
+/// The service protocol is installed on *child_handle*, or on a new handle if None, and the child handle is returned.
+///
+/// [UEFI Spec Documentation: 11.6.2. EFI_SERVICE_BINDING_PROTOCOL.CreateChild()](https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-service-binding-protocol-createchild)
+///
+/// # Safety
+///
+/// *service_handle* must be a valid handle, and its service binding protocol must not be uninstalled during the call,
+/// as the interface is used without being opened.
+pub unsafe fn create_child<B: BootServices>(
+    boot_services: &B,
+    service_handle: efi::Handle,
+    service_binding: &ServiceBinding,
+    child_handle: Option<efi::Handle>,
+) -> Result<efi::Handle, efi::Status> {
+    // SAFETY: The caller guarantees the interface stays installed for this call.
+    let interface = unsafe { boot_services.handle_protocol(service_handle, service_binding) }?;
+    let mut child_handle = child_handle.unwrap_or(ptr::null_mut());
+    match (interface.create_child)(interface, &mut child_handle) {
+        s if s.is_error() => Err(s),
+        _ => Ok(child_handle),
+    }
+}
+
+/// Destroys the *child_handle* child created by [`create_child`].
+///
+/// [UEFI Spec Documentation: 11.6.3. EFI_SERVICE_BINDING_PROTOCOL.DestroyChild()](https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-service-binding-protocol-destroychild)
+///
+/// # Safety
+///
+/// *service_handle* must be a valid handle, and its service binding protocol must not be uninstalled during the call,
+/// as the interface is used without being opened.
+pub unsafe fn destroy_child<B: BootServices>(
+    boot_services: &B,
+    service_handle: efi::Handle,
+    service_binding: &ServiceBinding,
+    child_handle: efi::Handle,
+) -> Result<(), efi::Status> {
+    // SAFETY: The caller guarantees the interface stays installed for this call.
+    let interface = unsafe { boot_services.handle_protocol(service_handle, service_binding) }?;
+    match (interface.destroy_child)(interface, child_handle) {
+        s if s.is_error() => Err(s),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use std::boxed::Box;
+
+    extern "efiapi" fn efi_create_child(
+        _this: *mut protocols::service_binding::Protocol,
+        child_handle: *mut efi::Handle,
+    ) -> efi::Status {
+        unsafe {
+            if (*child_handle).is_null() {
+                child_handle.write(3_usize as efi::Handle);
+            }
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_destroy_child(
+        _this: *mut protocols::service_binding::Protocol,
+        child_handle: efi::Handle,
+    ) -> efi::Status {
+        match child_handle as usize {
+            3 => efi::Status::SUCCESS,
+            _ => efi::Status::INVALID_PARAMETER,
+        }
+    }
+
+    #[test]
+    fn test_create_and_destroy_child() {
+        let interface = Box::leak(Box::new(protocols::service_binding::Protocol {
+            create_child: efi_create_child,
+            destroy_child: efi_destroy_child,
+        }));
+        let interface_ptr = interface as *mut protocols::service_binding::Protocol as usize;
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_handle_protocol::<ServiceBinding, protocols::service_binding::Protocol>().returning(
+            move |handle, service_binding| {
+                assert_eq!(1, handle as usize);
+                match service_binding.protocol_guid() {
+                    guid if guid == TCP4.protocol_guid() => {
+                        Ok(unsafe { &mut *(interface_ptr as *mut protocols::service_binding::Protocol) })
+                    }
+                    _ => Err(efi::Status::UNSUPPORTED),
+                }
+            },
+        );
+
+        let service_handle = 1_usize as efi::Handle;
+        unsafe {
+            assert_eq!(Ok(3), create_child(&boot_services, service_handle, &TCP4, None).map(|h| h as usize));
+            assert_eq!(
+                Ok(2),
+                create_child(&boot_services, service_handle, &TCP4, Some(2_usize as efi::Handle)).map(|h| h as usize)
+            );
+            assert_eq!(Err(efi::Status::UNSUPPORTED), create_child(&boot_services, service_handle, &UDP4, None));
+            assert_eq!(Ok(()), destroy_child(&boot_services, service_handle, &TCP4, 3_usize as efi::Handle));
+            assert_eq!(
+                Err(efi::Status::INVALID_PARAMETER),
+                destroy_child(&boot_services, service_handle, &TCP4, 2_usize as efi::Handle)
+            );
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! This module defines the EFI_SHELL_DYNAMIC_COMMAND_PROTOCOL and a [`DynamicCommandBuilder`] to implement shell
+//! commands in Rust, installed by drivers and run by the shell like its built-in commands.
+//!
+//! ```ignore
+//! fn hello(command_line: &mut CommandLine, _: &mut efi::SystemTable, _: &mut shell::Protocol) -> Result<(), efi::Status> {
+//!     let mut stdout = command_line.stdout().ok_or(efi::Status::UNSUPPORTED)?;
+//!     writeln!(stdout, "Hello {:?}", command_line.args()).map_err(|_| efi::Status::DEVICE_ERROR)
+//! }
+//!
+//! DynamicCommandBuilder::new("hello", hello).help(".TH hello 0 \"Says hello.\"\n").install(&BOOT_SERVICES, None)?;
+//! ```
+//!
+//! See the UEFI Shell Specification, 2.4. EFI_SHELL_DYNAMIC_COMMAND_PROTOCOL.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{ffi::c_void, ptr::NonNull};
+
+use r_efi::efi::{
+    self,
+    protocols::{shell, shell_parameters},
+};
+
+use crate::{allocation::MemoryType, shell_parameters::CommandLine, BootServices};
+
+pub const PROTOCOL_GUID: efi::Guid = efi::protocols::shell_dynamic_command::PROTOCOL_GUID;
+
+pub type CommandHandler = extern "efiapi" fn(
+    *mut Protocol,
+    *mut efi::SystemTable,
+    *mut shell_parameters::Protocol,
+    *mut shell::Protocol,
+) -> efi::Status;
+
+/// Returns the help text of the command in a pool buffer freed by the caller, or null.
+pub type CommandGetHelp = extern "efiapi" fn(*mut Protocol, *const efi::Char8) -> *mut efi::Char16;
+
+#[repr(C)]
+pub struct Protocol {
+    pub command_name: *const efi::Char16,
+    pub handler: CommandHandler,
+    pub get_help: CommandGetHelp,
+}
+
+/// Rust handler of a [`DynamicCommand`], called with the command line, the system table and the shell protocol.
+pub type DynamicCommandHandler =
+    fn(&mut CommandLine<'_>, &mut efi::SystemTable, &mut shell::Protocol) -> Result<(), efi::Status>;
+
+// The installed interface, the protocol is the first field so the thunks can get back to the handler.
+#[repr(C)]
+struct DynamicCommandInterface<B: BootServices + 'static> {
+    protocol: Protocol,
+    boot_services: &'static B,
+    name: Vec<u16>,
+    help: Option<Vec<u16>>,
+    handler: DynamicCommandHandler,
+}
+
+extern "efiapi" fn handler_thunk<B: BootServices + 'static>(
+    this: *mut Protocol,
+    system_table: *mut efi::SystemTable,
+    shell_parameters: *mut shell_parameters::Protocol,
+    shell: *mut shell::Protocol,
+) -> efi::Status {
+    // SAFETY: The protocol is the first field of the interface installed by DynamicCommandBuilder::install and the
+    // other pointers are provided by the shell.
+    let (interface, system_table, shell_parameters, shell) = unsafe {
+        match (
+            (this as *const DynamicCommandInterface<B>).as_ref(),
+            system_table.as_mut(),
+            shell_parameters.as_mut(),
+            shell.as_mut(),
+        ) {
+            (Some(i), Some(st), Some(sp), Some(s)) => (i, st, sp, s),
+            _ => return efi::Status::INVALID_PARAMETER,
+        }
+    };
+    match (interface.handler)(&mut CommandLine::new(shell_parameters), system_table, shell) {
+        Ok(()) => efi::Status::SUCCESS,
+        Err(status) => status,
+    }
+}
+
+extern "efiapi" fn get_help_thunk<B: BootServices + 'static>(
+    this: *mut Protocol,
+    _language: *const efi::Char8,
+) -> *mut efi::Char16 {
+    // SAFETY: The protocol is the first field of the interface installed by DynamicCommandBuilder::install.
+    let Some(interface) = (unsafe { (this as *const DynamicCommandInterface<B>).as_ref() }) else {
+        return core::ptr::null_mut();
+    };
+    let Some(help) = &interface.help else {
+        return core::ptr::null_mut();
+    };
+    match interface.boot_services.allocate_pool(MemoryType::BOOT_SERVICES_DATA, help.len() * 2) {
+        Ok(buffer) => {
+            let buffer = buffer as *mut efi::Char16;
+            // SAFETY: The buffer was allocated for the help text.
+            unsafe { buffer.copy_from_nonoverlapping(help.as_ptr(), help.len()) };
+            buffer
+        }
+        Err(_) => core::ptr::null_mut(),
+    }
+}
+
+fn ucs2(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain([0]).collect()
+}
+
+/// Builder of a [`DynamicCommand`].
+pub struct DynamicCommandBuilder<'a> {
+    name: &'a str,
+    help: Option<&'a str>,
+    handler: DynamicCommandHandler,
+}
+
+impl<'a> DynamicCommandBuilder<'a> {
+    /// Create a builder of a command named *name*, calling *handler* when run by the shell.
+    pub fn new(name: &'a str, handler: DynamicCommandHandler) -> Self {
+        Self { name, help: None, handler }
+    }
+
+    /// Sets the help text displayed by the shell `help` command, in the format of the shell manuals.
+    ///
+    /// The text is returned whatever the language requested by the shell.
+    pub fn help(mut self, help: &'a str) -> Self {
+        self.help = Some(help);
+        self
+    }
+
+    /// Installs the command on *handle*, or on a new handle if None.
+    ///
+    /// Returns `efi::Status::INVALID_PARAMETER` if the name is empty or contains a white space or a null character.
+    pub fn install<B: BootServices + 'static>(
+        self,
+        boot_services: &'static B,
+        handle: Option<efi::Handle>,
+    ) -> Result<DynamicCommand<B>, efi::Status> {
+        if self.name.is_empty() || self.name.chars().any(|c| c.is_whitespace() || c == '\0') {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let mut interface = Box::new(DynamicCommandInterface {
+            protocol: Protocol {
+                command_name: core::ptr::null(),
+                handler: handler_thunk::<B>,
+                get_help: get_help_thunk::<B>,
+            },
+            boot_services,
+            name: ucs2(self.name),
+            help: self.help.map(ucs2),
+            handler: self.handler,
+        });
+        interface.protocol.command_name = interface.name.as_ptr();
+        let interface = Box::into_raw(interface);
+        // SAFETY: The interface starts with the protocol and lives until the command is uninstalled.
+        match unsafe {
+            boot_services.install_protocol_interface_unchecked(handle, &PROTOCOL_GUID, interface as *mut c_void)
+        } {
+            // SAFETY: The pointer comes from Box::into_raw.
+            Ok(handle) => Ok(DynamicCommand { handle, interface: unsafe { NonNull::new_unchecked(interface) } }),
+            Err(status) => {
+                // SAFETY: The interface was not installed.
+                drop(unsafe { Box::from_raw(interface) });
+                Err(status)
+            }
+        }
+    }
+}
+
+/// A shell dynamic command installed by [`DynamicCommandBuilder::install`].
+///
+/// The command stays installed when dropped, as drivers usually provide their commands until they are unloaded, use
+/// [`DynamicCommand::uninstall`] to remove it.
+pub struct DynamicCommand<B: BootServices + 'static> {
+    handle: efi::Handle,
+    interface: NonNull<DynamicCommandInterface<B>>,
+}
+
+impl<B: BootServices + 'static> DynamicCommand<B> {
+    /// Returns the handle the command is installed on.
+    pub fn handle(&self) -> efi::Handle {
+        self.handle
+    }
+
+    /// Uninstalls the command and frees its interface.
+    ///
+    /// The command is returned if it can not be uninstalled, for example while the shell runs it.
+    pub fn uninstall(self) -> Result<(), (Self, efi::Status)> {
+        // SAFETY: The interface is the one installed on the handle.
+        let boot_services = unsafe { self.interface.as_ref() }.boot_services;
+        match unsafe {
+            boot_services.uninstall_protocol_interface_unchecked(
+                self.handle,
+                &PROTOCOL_GUID,
+                self.interface.as_ptr() as *mut c_void,
+            )
+        } {
+            Ok(()) => {
+                // SAFETY: The interface is no longer installed.
+                drop(unsafe { Box::from_raw(self.interface.as_ptr()) });
+                Ok(())
+            }
+            Err(status) => Err((self, status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use core::{mem, ptr, slice};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn hello(
+        command_line: &mut CommandLine,
+        _system_table: &mut efi::SystemTable,
+        _shell: &mut shell::Protocol,
+    ) -> Result<(), efi::Status> {
+        match command_line.args().len() {
+            0 => Err(efi::Status::ABORTED),
+            _ => Ok(()),
+        }
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_names() {
+        let boot_services = Box::leak(Box::new(MockBootServices::new()));
+        for name in ["", "two words", "nul\0"] {
+            assert!(matches!(
+                DynamicCommandBuilder::new(name, hello).install(boot_services, None),
+                Err(efi::Status::INVALID_PARAMETER)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_install_run_and_uninstall() {
+        static INTERFACE: AtomicUsize = AtomicUsize::new(0);
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_install_protocol_interface_unchecked().times(1).returning(|handle, guid, interface| {
+            assert!(handle.is_none());
+            assert_eq!(&PROTOCOL_GUID, guid);
+            INTERFACE.store(interface as usize, Ordering::Relaxed);
+            Ok(1_usize as efi::Handle)
+        });
+        boot_services.expect_allocate_pool().returning(|memory_type, size| {
+            assert_eq!(MemoryType::BOOT_SERVICES_DATA, memory_type);
+            Ok(Box::leak(vec![0_u8; size].into_boxed_slice()).as_mut_ptr())
+        });
+        boot_services.expect_uninstall_protocol_interface_unchecked().times(2).returning(|handle, _, interface| {
+            assert_eq!(INTERFACE.load(Ordering::Relaxed), interface as usize);
+            match handle as usize {
+                1 => Ok(()),
+                _ => Err(efi::Status::ACCESS_DENIED),
+            }
+        });
+        let boot_services = Box::leak(Box::new(boot_services));
+
+        let mut command =
+            DynamicCommandBuilder::new("hello", hello).help("Says hello.").install(boot_services, None).unwrap();
+        assert_eq!(1, command.handle() as usize);
+
+        let protocol = unsafe { &mut *(INTERFACE.load(Ordering::Relaxed) as *mut Protocol) };
+        let name = unsafe { slice::from_raw_parts(protocol.command_name, 6) };
+        assert_eq!(ucs2("hello"), name);
+
+        let help = (protocol.get_help)(protocol, b"en-US\0".as_ptr());
+        assert_eq!(ucs2("Says hello."), unsafe { slice::from_raw_parts(help, 12) });
+
+        let mut arg = ucs2("hello");
+        let mut argv = [arg.as_mut_ptr()];
+        let mut shell_parameters = shell_parameters::Protocol {
+            argv: argv.as_mut_ptr(),
+            argc: 1,
+            std_in: ptr::null_mut(),
+            std_out: ptr::null_mut(),
+            std_err: ptr::null_mut(),
+        };
+        let mut system_table = unsafe { mem::MaybeUninit::<efi::SystemTable>::zeroed().assume_init() };
+        // The shell protocol is not used by the handler.
+        let mut shell = mem::MaybeUninit::<shell::Protocol>::zeroed();
+        let shell = shell.as_mut_ptr();
+        assert_eq!(efi::Status::SUCCESS, (protocol.handler)(protocol, &mut system_table, &mut shell_parameters, shell));
+        shell_parameters.argc = 0;
+        assert_eq!(efi::Status::ABORTED, (protocol.handler)(protocol, &mut system_table, &mut shell_parameters, shell));
+        assert_eq!(
+            efi::Status::INVALID_PARAMETER,
+            (protocol.handler)(protocol, ptr::null_mut(), &mut shell_parameters, shell)
+        );
+
+        command.handle = 2_usize as efi::Handle;
+        let (mut command, status) = command.uninstall().err().unwrap();
+        assert_eq!(efi::Status::ACCESS_DENIED, status);
+        command.handle = 1_usize as efi::Handle;
+        assert!(command.uninstall().is_ok());
+    }
+}
@@ -0,0 +1,157 @@
+//! This module defines a rust friendly [`CommandLine`] wrapper around the EFI_SHELL_PARAMETERS_PROTOCOL, installed by
+//! the UEFI shell on the image handle of the applications it launches.
+//!
+//! ```ignore
+//! let shell_parameters = unsafe { BOOT_SERVICES.handle_protocol(image_handle, &ShellParameters)? };
+//! let mut command_line = CommandLine::new(shell_parameters);
+//! let options = Options::parse_from(command_line.args());
+//! if let Some(mut stdout) = command_line.stdout() {
+//!     writeln!(stdout, "Hello from the shell.")?;
+//! }
+//! ```
+//!
+//! See the UEFI Shell Specification, 2.3. EFI_SHELL_PARAMETERS_PROTOCOL.
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    char, fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+
+use r_efi::efi::protocols::{file, shell, shell_parameters};
+
+use crate::file::File;
+
+/// Rust friendly wrapper around an EFI_SHELL_PARAMETERS_PROTOCOL instance.
+///
+/// The interface can be retrieved with [`BootServices::handle_protocol`](crate::BootServices::handle_protocol) on the
+/// application image handle using [`ShellParameters`](crate::protocol_handler::ShellParameters).
+pub struct CommandLine<'a> {
+    protocol: &'a mut shell_parameters::Protocol,
+}
+
+impl<'a> CommandLine<'a> {
+    /// Create a new CommandLine from a shell parameters protocol interface.
+    pub fn new(protocol: &'a mut shell_parameters::Protocol) -> Self {
+        Self { protocol }
+    }
+
+    /// Returns the arguments of the command line, starting with the application name.
+    ///
+    /// The arguments are split and unquoted by the shell, so they can be given as is to an argument parser. Invalid
+    /// UTF-16 is replaced with [`char::REPLACEMENT_CHARACTER`].
+    pub fn args(&self) -> Vec<String> {
+        if self.protocol.argv.is_null() {
+            return Vec::new();
+        }
+        // SAFETY: The shell provides argc NUL terminated arguments.
+        unsafe {
+            slice::from_raw_parts(self.protocol.argv, self.protocol.argc)
+                .iter()
+                .filter(|arg| !arg.is_null())
+                .map(|&arg| {
+                    let length = (0..).take_while(|&i| *arg.add(i) != 0).count();
+                    char::decode_utf16(slice::from_raw_parts(arg, length).iter().copied())
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect()
+                })
+                .collect()
+        }
+    }
+
+    /// Returns the standard input of the application, if any.
+    pub fn stdin(&mut self) -> Option<StdFile<'_>> {
+        StdFile::new(self.protocol.std_in)
+    }
+
+    /// Returns the standard output of the application, if any.
+    pub fn stdout(&mut self) -> Option<StdFile<'_>> {
+        StdFile::new(self.protocol.std_out)
+    }
+
+    /// Returns the standard error of the application, if any.
+    pub fn stderr(&mut self) -> Option<StdFile<'_>> {
+        StdFile::new(self.protocol.std_err)
+    }
+}
+
+/// A standard handle of a shell application, which is a [`File`] owned by the shell.
+///
+/// The shell expects UCS-2 text on its standard handles and converts it when the output is redirected to an ASCII
+/// file, so [`fmt::Write`] writes UCS-2.
+pub struct StdFile<'a> {
+    file: ManuallyDrop<File>,
+    _command_line: PhantomData<&'a mut shell_parameters::Protocol>,
+}
+
+impl StdFile<'_> {
+    fn new(handle: shell::FileHandle) -> Option<Self> {
+        // SAFETY: The shell standard handles are file interfaces, which are never closed as the file is not dropped.
+        let file = unsafe { File::from_raw(NonNull::new(handle as *mut file::Protocol)?) };
+        Some(Self { file: ManuallyDrop::new(file), _command_line: PhantomData })
+    }
+
+    /// Returns true if the handle is redirected to a file rather than the console.
+    ///
+    /// The console handles of the shell do not provide file information, unlike the files used by redirections.
+    pub fn is_redirected(&mut self) -> bool {
+        self.file.get_info(&file::INFO_ID).is_ok()
+    }
+}
+
+impl Deref for StdFile<'_> {
+    type Target = File;
+
+    fn deref(&self) -> &Self::Target {
+        &self.file
+    }
+}
+
+impl DerefMut for StdFile<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.file
+    }
+}
+
+impl fmt::Write for StdFile<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let data = s.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>();
+        self.file.write_all(&data).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::ptr;
+
+    fn arg(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain([0]).collect()
+    }
+
+    #[test]
+    fn test_args() {
+        let mut args = [arg("tool.efi"), arg("-v"), arg("fs0:\\a b.txt"), vec![0xD800, 0]];
+        let mut argv = args.iter_mut().map(|a| a.as_mut_ptr()).collect::<Vec<_>>();
+        let mut protocol = shell_parameters::Protocol {
+            argv: argv.as_mut_ptr(),
+            argc: argv.len(),
+            std_in: ptr::null_mut(),
+            std_out: ptr::null_mut(),
+            std_err: ptr::null_mut(),
+        };
+
+        let mut command_line = CommandLine::new(&mut protocol);
+        assert_eq!(vec!["tool.efi", "-v", "fs0:\\a b.txt", "\u{FFFD}"], command_line.args());
+        assert!(command_line.stdin().is_none());
+        assert!(command_line.stdout().is_none());
+        assert!(command_line.stderr().is_none());
+
+        protocol.argv = ptr::null_mut();
+        assert!(CommandLine::new(&mut protocol).args().is_empty());
+    }
+}
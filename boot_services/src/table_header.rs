@@ -0,0 +1,104 @@
+//! Validation of the header of the UEFI tables, so a bogus table pointer passed from C code is rejected when it is
+//! received rather than crashing on the first service call.
+//!
+//! ```ignore
+//! pub static BOOT_SERVICES: StandardBootServices = StandardBootServices::new_uninit();
+//! unsafe { BOOT_SERVICES.initialize_checked((*system_table).boot_services)? };
+//! ```
+//!
+//! See the UEFI Specification, 4.2. EFI Table Header.
+
+use core::mem;
+
+use r_efi::efi;
+
+use crate::crc32::crc32_chunks;
+
+// Offset of the CRC32 field in the header, it is zeroed to compute the CRC of the table.
+const CRC32_OFFSET: usize = mem::offset_of!(efi::TableHeader, crc32);
+
+/// Validates the header of the table at *table*, a table of *table_size* bytes identified by *signature*.
+///
+/// Returns:
+/// - `efi::Status::INVALID_PARAMETER` if *table* is null or the signature does not match.
+/// - `efi::Status::INCOMPATIBLE_VERSION` if the table is not a UEFI 2.x table.
+/// - `efi::Status::BAD_BUFFER_SIZE` if the header size is not *table_size*.
+/// - `efi::Status::CRC_ERROR` if the CRC32 of the table does not match the header.
+///
+/// # Safety
+///
+/// *table* must either be null or readable for the size of a table header, and for *table_size* bytes once the
+/// signature matched.
+pub unsafe fn validate_table_header(
+    table: *const efi::TableHeader,
+    signature: u64,
+    table_size: usize,
+) -> Result<(), efi::Status> {
+    // SAFETY: The caller guarantees the header is readable if the pointer is not null.
+    let Some(header) = (unsafe { table.as_ref() }) else {
+        return Err(efi::Status::INVALID_PARAMETER);
+    };
+    if header.signature != signature {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+    if header.revision >> 16 != efi::SPECIFICATION_REVISION >> 16 {
+        return Err(efi::Status::INCOMPATIBLE_VERSION);
+    }
+    // The header size is not trusted beyond the table, this runs before any allocator is available.
+    if header.header_size as usize != table_size {
+        return Err(efi::Status::BAD_BUFFER_SIZE);
+    }
+
+    // SAFETY: The caller guarantees the table is readable for table_size bytes.
+    let bytes = unsafe { core::slice::from_raw_parts(table as *const u8, table_size) };
+    let crc32_end = CRC32_OFFSET + mem::size_of::<u32>();
+    match crc32_chunks([&bytes[..CRC32_OFFSET], &[0; 4], &bytes[crc32_end..]]) == header.crc32 {
+        true => Ok(()),
+        false => Err(efi::Status::CRC_ERROR),
+    }
+}
+
+/// Returns a zeroed table of type *T* with a valid header, as built by the firmware.
+#[cfg(test)]
+pub(crate) fn zeroed_table<T>(signature: u64) -> mem::MaybeUninit<T> {
+    let mut table = mem::MaybeUninit::<T>::zeroed();
+    let size = mem::size_of::<T>();
+    let header = table.as_mut_ptr() as *mut efi::TableHeader;
+    // SAFETY: The tables start with their header, and the zeroed bytes of the table are initialized.
+    unsafe {
+        (*header).signature = signature;
+        (*header).revision = efi::SPECIFICATION_REVISION;
+        (*header).header_size = size as u32;
+        (*header).crc32 = crc32_chunks([core::slice::from_raw_parts(header as *const u8, size)]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::ptr;
+
+    #[test]
+    fn test_validate_table_header() {
+        let mut table = zeroed_table::<efi::BootServices>(efi::BOOT_SERVICES_SIGNATURE);
+        let size = mem::size_of::<efi::BootServices>();
+        let header = table.as_mut_ptr() as *mut efi::TableHeader;
+        let validate = move |signature| unsafe { validate_table_header(header, signature, size) };
+
+        assert_eq!(Ok(()), validate(efi::BOOT_SERVICES_SIGNATURE));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), validate(efi::RUNTIME_SERVICES_SIGNATURE));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), unsafe {
+            validate_table_header(ptr::null(), efi::BOOT_SERVICES_SIGNATURE, size)
+        });
+
+        unsafe { (*header).reserved = 1 };
+        assert_eq!(Err(efi::Status::CRC_ERROR), validate(efi::BOOT_SERVICES_SIGNATURE));
+        unsafe { (*header).header_size -= 8 };
+        assert_eq!(Err(efi::Status::BAD_BUFFER_SIZE), validate(efi::BOOT_SERVICES_SIGNATURE));
+        unsafe { (*header).header_size = u32::MAX };
+        assert_eq!(Err(efi::Status::BAD_BUFFER_SIZE), validate(efi::BOOT_SERVICES_SIGNATURE));
+        unsafe { (*header).revision = 1 << 16 };
+        assert_eq!(Err(efi::Status::INCOMPATIBLE_VERSION), validate(efi::BOOT_SERVICES_SIGNATURE));
+    }
+}
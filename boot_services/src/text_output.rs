@@ -0,0 +1,309 @@
+//! This module defines a rust friendly [`TextOutput`] wrapper around the EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL, used by the
+//! console out and standard error of the system table.
+//!
+//! [UEFI Spec Documentation: 12.4. Simple Text Output Protocol](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#simple-text-output-protocol)
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use r_efi::efi::{self, protocols::simple_text_output};
+
+/// Text colors of the console, only the first 8 can be used as background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Color {
+    Black = 0x00,
+    Blue = 0x01,
+    Green = 0x02,
+    Cyan = 0x03,
+    Red = 0x04,
+    Magenta = 0x05,
+    Brown = 0x06,
+    LightGray = 0x07,
+    DarkGray = 0x08,
+    LightBlue = 0x09,
+    LightGreen = 0x0A,
+    LightCyan = 0x0B,
+    LightRed = 0x0C,
+    LightMagenta = 0x0D,
+    Yellow = 0x0E,
+    White = 0x0F,
+}
+
+/// Rust friendly wrapper around an EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL instance.
+///
+/// The interface is usually the `con_out` of the system table, it can also be retrieved with
+/// [`BootServices::handle_protocol`](crate::BootServices::handle_protocol) using
+/// [`SimpleTextOutput`](crate::protocol_handler::SimpleTextOutput).
+///
+/// # Example
+/// ```ignore
+/// let mut con_out = TextOutput::new(unsafe { &mut *system_table.con_out });
+/// con_out.set_colors(Color::Yellow, Color::Black)?;
+/// writeln!(con_out, "Hello from the firmware console.")?;
+/// ```
+pub struct TextOutput<'a> {
+    protocol: &'a mut simple_text_output::Protocol,
+}
+
+impl<'a> TextOutput<'a> {
+    /// Create a new TextOutput from a simple text output protocol interface.
+    pub fn new(protocol: &'a mut simple_text_output::Protocol) -> Self {
+        Self { protocol }
+    }
+
+    /// Return the current mode of the device, if the device exposes one.
+    pub fn mode(&self) -> Option<&simple_text_output::Mode> {
+        // SAFETY: The mode pointer is owned by the protocol producer and is valid for the lifetime of the interface.
+        unsafe { self.protocol.mode.as_ref() }
+    }
+
+    /// Resets the text output device.
+    ///
+    /// [UEFI Spec Documentation: 12.4.2. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.Reset()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-reset)
+    pub fn reset(&mut self, extended_verification: bool) -> Result<(), efi::Status> {
+        match (self.protocol.reset)(self.protocol, extended_verification.into()) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes *string* at the cursor position.
+    ///
+    /// Line feeds are preceded by a carriage return, as the device only moves the cursor down on a line feed.
+    /// Characters outside the Basic Multilingual Plane are replaced with [`char::REPLACEMENT_CHARACTER`]. Characters
+    /// the device can not render are skipped, which is not reported as an error.
+    ///
+    /// [UEFI Spec Documentation: 12.4.3. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.OutputString()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-outputstring)
+    pub fn output_string(&mut self, string: &str) -> Result<(), efi::Status> {
+        let mut buffer = Vec::with_capacity(string.len() + 1);
+        for c in string.chars() {
+            match c {
+                '\n' => buffer.extend_from_slice(&['\r' as u16, '\n' as u16]),
+                c if c as u32 > u16::MAX as u32 => buffer.push(char::REPLACEMENT_CHARACTER as u16),
+                c => buffer.push(c as u16),
+            }
+        }
+        buffer.push(0);
+        match (self.protocol.output_string)(self.protocol, buffer.as_mut_ptr()) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the number of columns and rows of the text output *mode_number*.
+    ///
+    /// [UEFI Spec Documentation: 12.4.5. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.QueryMode()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-querymode)
+    pub fn query_mode(&mut self, mode_number: usize) -> Result<(usize, usize), efi::Status> {
+        let (mut columns, mut rows) = (0, 0);
+        match (self.protocol.query_mode)(self.protocol, mode_number, &mut columns, &mut rows) {
+            s if s.is_error() => Err(s),
+            _ => Ok((columns, rows)),
+        }
+    }
+
+    /// Returns the number of columns and rows of the current mode.
+    pub fn size(&mut self) -> Result<(usize, usize), efi::Status> {
+        let mode_number = self.mode().ok_or(efi::Status::UNSUPPORTED)?.mode;
+        self.query_mode(usize::try_from(mode_number).map_err(|_| efi::Status::UNSUPPORTED)?)
+    }
+
+    /// Sets the text output mode, which also clears the screen.
+    ///
+    /// [UEFI Spec Documentation: 12.4.6. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.SetMode()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-setmode)
+    pub fn set_mode(&mut self, mode_number: usize) -> Result<(), efi::Status> {
+        match (self.protocol.set_mode)(self.protocol, mode_number) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the foreground and background colors of the text written next.
+    ///
+    /// Only the first 8 colors can be used as background, the intensity bit of *background* is ignored.
+    ///
+    /// [UEFI Spec Documentation: 12.4.7. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.SetAttribute()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-setattribute)
+    pub fn set_colors(&mut self, foreground: Color, background: Color) -> Result<(), efi::Status> {
+        let attribute = foreground as usize | ((background as usize & 0x07) << 4);
+        match (self.protocol.set_attribute)(self.protocol, attribute) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Clears the screen with the background color and moves the cursor to the top left corner.
+    ///
+    /// [UEFI Spec Documentation: 12.4.8. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.ClearScreen()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-clearscreen)
+    pub fn clear_screen(&mut self) -> Result<(), efi::Status> {
+        match (self.protocol.clear_screen)(self.protocol) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the column and row of the cursor, if the device exposes a mode.
+    pub fn cursor_position(&self) -> Option<(usize, usize)> {
+        let mode = self.mode()?;
+        Some((usize::try_from(mode.cursor_column).ok()?, usize::try_from(mode.cursor_row).ok()?))
+    }
+
+    /// Moves the cursor to *column* and *row*, starting from the top left corner.
+    ///
+    /// [UEFI Spec Documentation: 12.4.9. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.SetCursorPosition()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-setcursorposition)
+    pub fn set_cursor_position(&mut self, column: usize, row: usize) -> Result<(), efi::Status> {
+        match (self.protocol.set_cursor_position)(self.protocol, column, row) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Shows or hides the cursor.
+    ///
+    /// [UEFI Spec Documentation: 12.4.10. EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL.EnableCursor()](https://uefi.org/specs/UEFI/2.10/12_Protocols_Console_Support.html#efi-simple-text-output-protocol-enablecursor)
+    pub fn enable_cursor(&mut self, visible: bool) -> Result<(), efi::Status> {
+        match (self.protocol.enable_cursor)(self.protocol, visible.into()) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Write for TextOutput<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.output_string(s).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use core::fmt::Write;
+    use std::{boxed::Box, string::String};
+
+    // A fake device recording its output and cursor moves, the protocol must be the first field.
+    #[repr(C)]
+    pub(crate) struct FakeTextOutput {
+        pub(crate) protocol: simple_text_output::Protocol,
+        pub(crate) mode: simple_text_output::Mode,
+        pub(crate) output: String,
+    }
+
+    fn fake<'a>(this: *mut simple_text_output::Protocol) -> &'a mut FakeTextOutput {
+        unsafe { &mut *(this as *mut FakeTextOutput) }
+    }
+
+    extern "efiapi" fn efi_reset(_this: *mut simple_text_output::Protocol, _extended: efi::Boolean) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_output_string(this: *mut simple_text_output::Protocol, string: *mut u16) -> efi::Status {
+        let string = unsafe {
+            let length = (0..).take_while(|&i| *string.add(i) != 0).count();
+            String::from_utf16(core::slice::from_raw_parts(string, length)).unwrap()
+        };
+        let fake = fake(this);
+        fake.mode.cursor_column += string.chars().count() as i32;
+        fake.output.push_str(&string);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_test_string(_this: *mut simple_text_output::Protocol, _string: *mut u16) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_query_mode(
+        _this: *mut simple_text_output::Protocol,
+        mode_number: usize,
+        columns: *mut usize,
+        rows: *mut usize,
+    ) -> efi::Status {
+        match mode_number {
+            0 => unsafe {
+                columns.write(80);
+                rows.write(25);
+                efi::Status::SUCCESS
+            },
+            _ => efi::Status::UNSUPPORTED,
+        }
+    }
+
+    extern "efiapi" fn efi_set_mode(_this: *mut simple_text_output::Protocol, _mode_number: usize) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_set_attribute(this: *mut simple_text_output::Protocol, attribute: usize) -> efi::Status {
+        fake(this).mode.attribute = attribute as i32;
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_clear_screen(_this: *mut simple_text_output::Protocol) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_set_cursor_position(
+        this: *mut simple_text_output::Protocol,
+        column: usize,
+        row: usize,
+    ) -> efi::Status {
+        let fake = fake(this);
+        fake.mode.cursor_column = column as i32;
+        fake.mode.cursor_row = row as i32;
+        fake.output.push_str(&std::format!("<{column},{row}>"));
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn efi_enable_cursor(
+        _this: *mut simple_text_output::Protocol,
+        _visible: efi::Boolean,
+    ) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
+    pub(crate) fn fake_text_output() -> &'static mut FakeTextOutput {
+        let fake = Box::leak(Box::new(FakeTextOutput {
+            protocol: simple_text_output::Protocol {
+                reset: efi_reset,
+                output_string: efi_output_string,
+                test_string: efi_test_string,
+                query_mode: efi_query_mode,
+                set_mode: efi_set_mode,
+                set_attribute: efi_set_attribute,
+                clear_screen: efi_clear_screen,
+                set_cursor_position: efi_set_cursor_position,
+                enable_cursor: efi_enable_cursor,
+                mode: core::ptr::null_mut(),
+            },
+            mode: simple_text_output::Mode {
+                max_mode: 1,
+                mode: 0,
+                attribute: 0,
+                cursor_column: 0,
+                cursor_row: 0,
+                cursor_visible: efi::Boolean::TRUE,
+            },
+            output: String::new(),
+        }));
+        fake.protocol.mode = &mut fake.mode;
+        fake
+    }
+
+    #[test]
+    fn test_text_output() {
+        let fake = fake_text_output();
+        let fake_ptr = fake as *mut FakeTextOutput;
+        let mut output = TextOutput::new(&mut fake.protocol);
+
+        assert_eq!(Ok(()), output.reset(false));
+        assert_eq!(Ok((80, 25)), output.size());
+        assert_eq!(Err(efi::Status::UNSUPPORTED), output.query_mode(1));
+        assert_eq!(Ok(()), output.set_colors(Color::Yellow, Color::LightBlue));
+        assert_eq!(0x1E, output.mode().unwrap().attribute);
+        assert_eq!(Ok(()), output.set_cursor_position(2, 3));
+        write!(output, "a\n{}\u{1F600}", 1).unwrap();
+        assert_eq!(Some((7, 3)), output.cursor_position());
+        assert_eq!("<2,3>a\r\n1\u{FFFD}", unsafe { (*fake_ptr).output.as_str() });
+
+        unsafe { (*fake_ptr).protocol.mode = core::ptr::null_mut() };
+        assert_eq!(None, output.cursor_position());
+    }
+}
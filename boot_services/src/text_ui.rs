@@ -0,0 +1,215 @@
+//! This module defines text mode UI helpers for tools without a graphics stack, such as firmware update and
+//! diagnostic tools: a [`ProgressBar`], an aligned [`KeyValueTable`] and a [`Spinner`] animated by a timer event.
+//!
+//! The progress bar and the table write to any [`fmt::Write`], like a [`TextOutput`] or a serial port, and only use
+//! ASCII characters so they render on serial terminals too.
+//!
+//! ```ignore
+//! let mut con_out = TextOutput::new(unsafe { &mut *system_table.con_out });
+//! write!(con_out, "{}", KeyValueTable::new().row("Version", version).row("Lowest supported", lowest))?;
+//!
+//! let mut progress = ProgressBar::new(40);
+//! for (written, chunk) in image.chunks(CHUNK_SIZE).enumerate() {
+//!     flash(chunk)?;
+//!     progress.update(&mut con_out, written as u64 + 1, chunk_count)?;
+//! }
+//! progress.finish(&mut con_out)?;
+//! ```
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt, time::Duration};
+
+use r_efi::efi::{self, protocols::simple_text_output};
+
+use crate::{
+    event::{AnyEventContext, ContextEvent, EventTimerType, EventType},
+    text_output::TextOutput,
+    tpl::Tpl,
+    BootServices, BootServicesExt,
+};
+
+/// A single line progress bar, redrawn in place with a carriage return.
+///
+/// ```text
+/// [################                        ]  40%
+/// ```
+#[derive(Debug)]
+pub struct ProgressBar {
+    width: usize,
+    percent: Option<u64>,
+}
+
+impl ProgressBar {
+    /// Create a progress bar of *width* characters between the brackets.
+    pub fn new(width: usize) -> Self {
+        Self { width, percent: None }
+    }
+
+    /// Redraws the bar for *done* out of *total* units.
+    ///
+    /// The bar is only redrawn when the percentage changes, as slow consoles like serial ports would otherwise slow
+    /// down the operation being reported.
+    pub fn update<W: fmt::Write>(&mut self, out: &mut W, done: u64, total: u64) -> fmt::Result {
+        let percent = match total {
+            0 => 100,
+            total => (done.min(total) as u128 * 100 / total as u128) as u64,
+        };
+        if self.percent == Some(percent) {
+            return Ok(());
+        }
+        self.percent = Some(percent);
+        let filled = self.width * percent as usize / 100;
+        write!(out, "\r[{:#<filled$}{:<empty$}] {percent:>3}%", "", "", empty = self.width - filled)
+    }
+
+    /// Draws the full bar and ends its line.
+    pub fn finish<W: fmt::Write>(&mut self, out: &mut W) -> fmt::Result {
+        self.update(out, 1, 1)?;
+        out.write_str("\n")
+    }
+}
+
+/// Key value pairs displayed as a table, with the values aligned.
+///
+/// ```text
+/// Version          : 1.2.3
+/// Lowest supported : 1.0.0
+/// ```
+#[derive(Debug, Default)]
+pub struct KeyValueTable {
+    rows: Vec<(String, String)>,
+}
+
+impl KeyValueTable {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a row with *key* and the display of *value*.
+    pub fn row(&mut self, key: &str, value: impl fmt::Display) -> &mut Self {
+        self.rows.push((String::from(key), alloc::format!("{value}")));
+        self
+    }
+}
+
+impl fmt::Display for KeyValueTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.rows.iter().map(|(key, _)| key.chars().count()).max().unwrap_or(0);
+        for (key, value) in &self.rows {
+            writeln!(f, "{key:<width$} : {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Frames of the [`Spinner`] animation.
+pub const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+// The context of the spinner timer event.
+struct SpinnerState {
+    protocol: *mut simple_text_output::Protocol,
+    column: usize,
+    row: usize,
+    frame: usize,
+}
+
+fn spin(_event: efi::Event, context: &mut AnyEventContext) {
+    let Some(state) = context.downcast_mut::<SpinnerState>() else {
+        return;
+    };
+    // SAFETY: The interface outlives the spinner, see Spinner::start.
+    let mut output = TextOutput::new(unsafe { &mut *state.protocol });
+    let cursor_position = output.cursor_position();
+    let mut frame = [0; 4];
+    let _ = output
+        .set_cursor_position(state.column, state.row)
+        .and_then(|_| output.output_string(SPINNER_FRAMES[state.frame].encode_utf8(&mut frame)));
+    if let Some((column, row)) = cursor_position {
+        let _ = output.set_cursor_position(column, row);
+    }
+    state.frame = (state.frame + 1) % SPINNER_FRAMES.len();
+}
+
+/// A spinner animated at a fixed position of the screen by a periodic timer event, while the caller keeps working.
+///
+/// The animation runs at [`Tpl::CALLBACK`], so it only progresses while the caller runs at a lower TPL. The cursor is
+/// restored after each frame, so the caller can keep writing to the console. The spinner stops when dropped.
+#[must_use = "if unused the spinner will immediately stop"]
+pub struct Spinner<'a, B: BootServices> {
+    event: ContextEvent<'a, B>,
+}
+
+impl<'a, B: BootServices> Spinner<'a, B> {
+    /// Starts a spinner at *column* and *row* of the screen of *protocol*, drawing a new frame every *period*.
+    ///
+    /// # Safety
+    ///
+    /// *protocol* must be a valid simple text output interface until the spinner is dropped.
+    pub unsafe fn start(
+        boot_services: &'a B,
+        protocol: *mut simple_text_output::Protocol,
+        column: usize,
+        row: usize,
+        period: Duration,
+    ) -> Result<Self, efi::Status> {
+        let event = boot_services.create_event_with_context(
+            EventType::TIMER | EventType::NOTIFY_SIGNAL,
+            Tpl::CALLBACK,
+            spin,
+            SpinnerState { protocol, column, row, frame: 0 },
+        )?;
+        let trigger_time = u64::try_from(period.as_nanos() / 100).unwrap_or(u64::MAX).max(1);
+        boot_services.set_timer(event.event(), EventTimerType::Periodic, trigger_time)?;
+        Ok(Self { event })
+    }
+
+    /// Stops the spinner, leaving its last frame on the screen.
+    pub fn stop(self) -> Result<(), efi::Status> {
+        self.event.close().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::text_output::test::fake_text_output;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_progress_bar() {
+        let mut out = String::new();
+        let mut progress = ProgressBar::new(10);
+        progress.update(&mut out, 0, 200).unwrap();
+        progress.update(&mut out, 1, 200).unwrap();
+        progress.update(&mut out, 90, 200).unwrap();
+        progress.update(&mut out, 300, 200).unwrap();
+        progress.finish(&mut out).unwrap();
+        assert_eq!("\r[          ]   0%\r[####      ]  45%\r[##########] 100%\n", out);
+
+        let mut out = String::new();
+        ProgressBar::new(4).update(&mut out, 0, 0).unwrap();
+        assert_eq!("\r[####] 100%", out);
+    }
+
+    #[test]
+    fn test_key_value_table() {
+        let mut table = KeyValueTable::new();
+        table.row("Version", "1.2.3").row("Lowest supported", 0x10).row("État", true);
+        assert_eq!("Version          : 1.2.3\nLowest supported : 16\nÉtat             : true\n", table.to_string());
+        assert_eq!("", KeyValueTable::new().to_string());
+    }
+
+    #[test]
+    fn test_spin() {
+        let fake = fake_text_output();
+        fake.mode.cursor_column = 5;
+        fake.mode.cursor_row = 1;
+        let mut context =
+            AnyEventContext::new(SpinnerState { protocol: &mut fake.protocol, column: 70, row: 0, frame: 0 });
+        for _ in 0..5 {
+            spin(1_usize as efi::Event, &mut context);
+        }
+        assert_eq!("<70,0>|<5,1><70,0>/<5,1><70,0>-<5,1><70,0>\\<5,1><70,0>|<5,1>", fake.output);
+    }
+}
@@ -1,5 +1,11 @@
 //! This module defined every struct related to Tpl in boot services.
 
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
 use r_efi::efi;
 
 use crate::BootServices;
@@ -41,6 +47,94 @@ impl Tpl {
     /// If code requires more processing, it needs to signal an event to wait to obtain control again at whatever level it requires.
     /// This level is typically used to process low level IO to or from a device.
     pub const NOTIFY: Tpl = Tpl(efi::TPL_NOTIFY);
+
+    /// Interrupts are disabled at this level.
+    /// It is only used by the firmware to synchronize with code running at any level, it should be held briefly.
+    pub const HIGH_LEVEL: Tpl = Tpl(efi::TPL_HIGH_LEVEL);
+}
+
+/// Returns the current [`Tpl`].
+///
+/// UEFI has no service to read the TPL, so this raises it to [`Tpl::HIGH_LEVEL`] and restores it right away. This
+/// costs two boot services calls and disables interrupts between them, do not use it on hot paths.
+pub fn current_tpl<B: BootServices + ?Sized>(boot_services: &B) -> Tpl {
+    let tpl = boot_services.raise_tpl(Tpl::HIGH_LEVEL);
+    boot_services.restore_tpl(tpl);
+    tpl
+}
+
+/// Panics if the current [`Tpl`] is above *tpl*, in debug builds only.
+///
+/// This is meant to check the preconditions of code that can not run at a high TPL, e.g. code allocating memory must
+/// run at [`Tpl::NOTIFY`] or below. The TPL is read with [`current_tpl`], release builds do not pay for it.
+#[track_caller]
+pub fn debug_assert_tpl_at_most<B: BootServices + ?Sized>(boot_services: &B, tpl: Tpl) {
+    if cfg!(debug_assertions) {
+        let current_tpl = current_tpl(boot_services);
+        assert!(current_tpl <= tpl, "Current TPL {} is above the expected maximum {}.", current_tpl.0, tpl.0);
+    }
+}
+
+/// A lock protecting *T* by raising the TPL to a fixed level while held, for the statics of this crate shared with event
+/// notify functions.
+///
+/// Code preempting the holder runs to completion at a higher TPL and can not wait for the lock, so locking it again
+/// fails instead of spinning.
+pub(crate) struct TplLock<T> {
+    tpl: Tpl,
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: The data is only accessed with the lock held.
+unsafe impl<T: Send> Sync for TplLock<T> {}
+
+impl<T> TplLock<T> {
+    /// Create a lock raising the TPL to *tpl* while held.
+    pub(crate) const fn new(tpl: Tpl, data: T) -> Self {
+        Self { tpl, locked: AtomicBool::new(false), data: UnsafeCell::new(data) }
+    }
+
+    /// Runs *f* with the lock held, None if the lock is held by the code the call preempted.
+    pub(crate) fn try_with<B, F, R>(&self, boot_services: &B, f: F) -> Option<R>
+    where
+        B: BootServices + ?Sized,
+        F: FnOnce(&mut T) -> R,
+    {
+        // The TPL is raised first, so the code it masks can not preempt the lock once it is taken.
+        let release_tpl = boot_services.raise_tpl(self.tpl);
+        if self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            boot_services.restore_tpl(release_tpl);
+            return None;
+        }
+        // SAFETY: The lock is held.
+        let result = f(unsafe { &mut *self.data.get() });
+        self.locked.store(false, Ordering::Release);
+        boot_services.restore_tpl(release_tpl);
+        Some(result)
+    }
+
+    /// Runs *f* with the lock held.
+    ///
+    /// # Panics
+    /// This call will panic if the lock is held by the code the call preempted.
+    #[track_caller]
+    pub(crate) fn with<B, F, R>(&self, boot_services: &B, f: F) -> R
+    where
+        B: BootServices + ?Sized,
+        F: FnOnce(&mut T) -> R,
+    {
+        self.try_with(boot_services, f).expect("Re-entrant lock")
+    }
+}
+
+impl<T> fmt::Debug for TplLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TplLock")
+            .field("tpl", &self.tpl.0)
+            .field("locked", &self.locked.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
 }
 
 impl Into<usize> for Tpl {
@@ -0,0 +1,270 @@
+//! This module defined the EFI_UNICODE_COLLATION_PROTOCOL2 and a rust friendly [`UnicodeCollation`] wrapper around it.
+//!
+//! [UEFI Spec Documentation: 21.1. Unicode Collation Protocol](https://uefi.org/specs/UEFI/2.10/21_Protocols_String_Services.html#unicode-collation-protocol)
+
+use alloc::{vec, vec::Vec};
+use core::{cmp::Ordering, ffi::CStr};
+
+use r_efi::efi;
+
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0xa4c751fc, 0x23ae, 0x4c3e, 0x92, 0xe9, &[0x49, 0x64, 0xcf, 0x63, 0xf3, 0x49]);
+
+pub type ProtocolStriColl = extern "efiapi" fn(*mut Protocol, *mut u16, *mut u16) -> isize;
+
+pub type ProtocolMetaiMatch = extern "efiapi" fn(*mut Protocol, *mut u16, *mut u16) -> efi::Boolean;
+
+pub type ProtocolStrLwr = extern "efiapi" fn(*mut Protocol, *mut u16);
+
+pub type ProtocolStrUpr = extern "efiapi" fn(*mut Protocol, *mut u16);
+
+pub type ProtocolFatToStr = extern "efiapi" fn(*mut Protocol, usize, *mut u8, *mut u16);
+
+pub type ProtocolStrToFat = extern "efiapi" fn(*mut Protocol, *mut u16, usize, *mut u8) -> efi::Boolean;
+
+#[repr(C)]
+pub struct Protocol {
+    pub stri_coll: ProtocolStriColl,
+    pub metai_match: ProtocolMetaiMatch,
+    pub str_lwr: ProtocolStrLwr,
+    pub str_upr: ProtocolStrUpr,
+    pub fat_to_str: ProtocolFatToStr,
+    pub str_to_fat: ProtocolStrToFat,
+    pub supported_languages: *const u8,
+}
+
+/// Rust friendly wrapper around an EFI_UNICODE_COLLATION_PROTOCOL2 instance.
+///
+/// The strings are UCS-2 and may or may not be null-terminated, they are copied to add the null terminator the
+/// protocol needs.
+///
+/// The interface can be retrieved with [`BootServices::locate_protocol`](crate::BootServices::locate_protocol) using
+/// [`UnicodeCollation2`](crate::protocol_handler::UnicodeCollation2).
+///
+/// # Example
+/// ```ignore
+/// let collation = UnicodeCollation::new(unsafe { BOOT_SERVICES.locate_protocol(&UnicodeCollation2, None)? });
+/// if collation.metai_match(&file_name, &utf16!("*.EFI")) {
+///     ...
+/// }
+/// ```
+pub struct UnicodeCollation<'a> {
+    protocol: &'a Protocol,
+}
+
+impl<'a> UnicodeCollation<'a> {
+    /// Create a new UnicodeCollation from a unicode collation protocol interface.
+    pub fn new(protocol: &'a Protocol) -> Self {
+        Self { protocol }
+    }
+
+    fn this(&self) -> *mut Protocol {
+        self.protocol as *const Protocol as *mut Protocol
+    }
+
+    /// Returns the RFC 4646 languages supported by the protocol, e.g. `en;fr`.
+    pub fn supported_languages(&self) -> Option<&CStr> {
+        if self.protocol.supported_languages.is_null() {
+            return None;
+        }
+        // SAFETY: The languages are a null-terminated ASCII string owned by the protocol producer.
+        Some(unsafe { CStr::from_ptr(self.protocol.supported_languages as *const _) })
+    }
+
+    /// Compares two strings without regard to case.
+    ///
+    /// [UEFI Spec Documentation: 21.1.2. EFI_UNICODE_COLLATION_PROTOCOL.StriColl()](https://uefi.org/specs/UEFI/2.10/21_Protocols_String_Services.html#efi-unicode-collation-protocol-stricoll)
+    pub fn str_caseless_cmp(&self, s1: &[u16], s2: &[u16]) -> Ordering {
+        let mut s1 = null_terminated(s1);
+        let mut s2 = null_terminated(s2);
+        (self.protocol.stri_coll)(self.this(), s1.as_mut_ptr(), s2.as_mut_ptr()).cmp(&0)
+    }
+
+    /// Returns true if *string* matches *pattern* without regard to case.
+    ///
+    /// The pattern may contain the `*` (any string), `?` (any character) and `[...]` (character set or range)
+    /// wildcards.
+    ///
+    /// [UEFI Spec Documentation: 21.1.3. EFI_UNICODE_COLLATION_PROTOCOL.MetaiMatch()](https://uefi.org/specs/UEFI/2.10/21_Protocols_String_Services.html#efi-unicode-collation-protocol-metaimatch)
+    pub fn metai_match(&self, string: &[u16], pattern: &[u16]) -> bool {
+        let mut string = null_terminated(string);
+        let mut pattern = null_terminated(pattern);
+        (self.protocol.metai_match)(self.this(), string.as_mut_ptr(), pattern.as_mut_ptr()).into()
+    }
+
+    /// Returns *string* converted to lower case, without the null terminator.
+    ///
+    /// [UEFI Spec Documentation: 21.1.4. EFI_UNICODE_COLLATION_PROTOCOL.StrLwr()](https://uefi.org/specs/UEFI/2.10/21_Protocols_String_Services.html#efi-unicode-collation-protocol-strlwr)
+    pub fn to_lowercase(&self, string: &[u16]) -> Vec<u16> {
+        let mut string = null_terminated(string);
+        (self.protocol.str_lwr)(self.this(), string.as_mut_ptr());
+        string.pop();
+        string
+    }
+
+    /// Returns *string* converted to upper case, without the null terminator.
+    ///
+    /// [UEFI Spec Documentation: 21.1.5. EFI_UNICODE_COLLATION_PROTOCOL.StrUpr()](https://uefi.org/specs/UEFI/2.10/21_Protocols_String_Services.html#efi-unicode-collation-protocol-strupr)
+    pub fn to_uppercase(&self, string: &[u16]) -> Vec<u16> {
+        let mut string = null_terminated(string);
+        (self.protocol.str_upr)(self.this(), string.as_mut_ptr());
+        string.pop();
+        string
+    }
+
+    /// Converts an 8.3 FAT file name in an OEM character set to a string, without the null terminator.
+    ///
+    /// [UEFI Spec Documentation: 21.1.6. EFI_UNICODE_COLLATION_PROTOCOL.FatToStr()](https://uefi.org/specs/UEFI/2.10/21_Protocols_String_Services.html#efi-unicode-collation-protocol-fattostr)
+    pub fn fat_to_str(&self, fat: &[u8]) -> Vec<u16> {
+        let mut fat = fat.to_vec();
+        let mut string = vec![0; fat.len() + 1];
+        (self.protocol.fat_to_str)(self.this(), fat.len(), fat.as_mut_ptr(), string.as_mut_ptr());
+        string.truncate(string.iter().position(|&c| c == 0).unwrap_or(string.len()));
+        string
+    }
+
+    /// Converts a string to a FAT file name of *fat_size* bytes in an OEM character set.
+    ///
+    /// Returns the FAT file name and true if some characters were substituted because they can not be represented,
+    /// in which case a long file name entry is needed to keep the original name.
+    ///
+    /// [UEFI Spec Documentation: 21.1.7. EFI_UNICODE_COLLATION_PROTOCOL.StrToFat()](https://uefi.org/specs/UEFI/2.10/21_Protocols_String_Services.html#efi-unicode-collation-protocol-strtofat)
+    pub fn str_to_fat(&self, string: &[u16], fat_size: usize) -> (Vec<u8>, bool) {
+        let mut string = null_terminated(string);
+        let mut fat = vec![0; fat_size];
+        let substituted = (self.protocol.str_to_fat)(self.this(), string.as_mut_ptr(), fat_size, fat.as_mut_ptr());
+        (fat, substituted.into())
+    }
+}
+
+fn null_terminated(string: &[u16]) -> Vec<u16> {
+    let mut string = string[..string.iter().position(|&c| c == 0).unwrap_or(string.len())].to_vec();
+    string.push(0);
+    string
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::slice;
+
+    unsafe fn as_slice<'a>(string: *mut u16) -> &'a mut [u16] {
+        let mut len = 0;
+        while *string.add(len) != 0 {
+            len += 1;
+        }
+        slice::from_raw_parts_mut(string, len)
+    }
+
+    fn to_upper(c: u16) -> u16 {
+        (c as u8).to_ascii_uppercase() as u16
+    }
+
+    // ASCII only implementation of the protocol, the pattern only supports `*` and `?`.
+    extern "efiapi" fn efi_stri_coll(_this: *mut Protocol, s1: *mut u16, s2: *mut u16) -> isize {
+        let (s1, s2) = unsafe { (as_slice(s1), as_slice(s2)) };
+        s1.iter().map(|&c| to_upper(c)).cmp(s2.iter().map(|&c| to_upper(c))) as isize
+    }
+
+    extern "efiapi" fn efi_metai_match(_this: *mut Protocol, string: *mut u16, pattern: *mut u16) -> efi::Boolean {
+        fn matches(string: &[u16], pattern: &[u16]) -> bool {
+            match pattern.split_first() {
+                None => string.is_empty(),
+                Some((&c, rest)) if c == '*' as u16 => (0..=string.len()).any(|i| matches(&string[i..], rest)),
+                Some((&c, rest)) => match string.split_first() {
+                    Some((&s, string)) => (c == '?' as u16 || to_upper(c) == to_upper(s)) && matches(string, rest),
+                    None => false,
+                },
+            }
+        }
+        unsafe { matches(as_slice(string), as_slice(pattern)) }.into()
+    }
+
+    extern "efiapi" fn efi_str_lwr(_this: *mut Protocol, string: *mut u16) {
+        unsafe { as_slice(string) }.iter_mut().for_each(|c| *c = (*c as u8).to_ascii_lowercase() as u16);
+    }
+
+    extern "efiapi" fn efi_str_upr(_this: *mut Protocol, string: *mut u16) {
+        unsafe { as_slice(string) }.iter_mut().for_each(|c| *c = to_upper(*c));
+    }
+
+    extern "efiapi" fn efi_fat_to_str(_this: *mut Protocol, fat_size: usize, fat: *mut u8, string: *mut u16) {
+        let fat = unsafe { slice::from_raw_parts(fat, fat_size) };
+        for (i, &c) in fat.iter().take_while(|&&c| c != 0).enumerate() {
+            unsafe { string.add(i).write(c as u16) };
+        }
+    }
+
+    extern "efiapi" fn efi_str_to_fat(
+        _this: *mut Protocol,
+        string: *mut u16,
+        fat_size: usize,
+        fat: *mut u8,
+    ) -> efi::Boolean {
+        let string = unsafe { as_slice(string) };
+        let fat = unsafe { slice::from_raw_parts_mut(fat, fat_size) };
+        let mut substituted = false;
+        for (f, &c) in fat.iter_mut().zip(string.iter()) {
+            *f = match c {
+                c if c < 0x80 => to_upper(c) as u8,
+                _ => {
+                    substituted = true;
+                    b'_'
+                }
+            };
+        }
+        substituted.into()
+    }
+
+    fn protocol() -> Protocol {
+        Protocol {
+            stri_coll: efi_stri_coll,
+            metai_match: efi_metai_match,
+            str_lwr: efi_str_lwr,
+            str_upr: efi_str_upr,
+            fat_to_str: efi_fat_to_str,
+            str_to_fat: efi_str_to_fat,
+            supported_languages: b"en\0".as_ptr(),
+        }
+    }
+
+    fn ucs2(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn test_str_caseless_cmp_and_metai_match() {
+        let protocol = protocol();
+        let collation = UnicodeCollation::new(&protocol);
+
+        assert_eq!(Some(c"en"), collation.supported_languages());
+        assert_eq!(Ordering::Equal, collation.str_caseless_cmp(&ucs2("BootX64.efi"), &ucs2("BOOTX64.EFI\0")));
+        assert_eq!(Ordering::Less, collation.str_caseless_cmp(&ucs2("a"), &ucs2("B")));
+        assert_eq!(Ordering::Greater, collation.str_caseless_cmp(&ucs2("b"), &ucs2("A")));
+
+        assert!(collation.metai_match(&ucs2("BootX64.efi"), &ucs2("*.EFI")));
+        assert!(collation.metai_match(&ucs2("Boot0001"), &ucs2("boot????")));
+        assert!(!collation.metai_match(&ucs2("Boot0001.txt"), &ucs2("*.efi")));
+    }
+
+    #[test]
+    fn test_case_conversion() {
+        let protocol = protocol();
+        let collation = UnicodeCollation::new(&protocol);
+
+        assert_eq!(ucs2("bootx64.efi"), collation.to_lowercase(&ucs2("BootX64.EFI")));
+        assert_eq!(ucs2("BOOTX64.EFI"), collation.to_uppercase(&ucs2("BootX64.efi\0")));
+    }
+
+    #[test]
+    fn test_fat_conversion() {
+        let protocol = protocol();
+        let collation = UnicodeCollation::new(&protocol);
+
+        assert_eq!(ucs2("BOOTX64 EFI"), collation.fat_to_str(b"BOOTX64 EFI"));
+        assert_eq!(ucs2("A"), collation.fat_to_str(b"A\0\0"));
+
+        assert_eq!((b"BOOT\0\0\0\0".to_vec(), false), collation.str_to_fat(&ucs2("boot"), 8));
+        assert_eq!((b"F_LE".to_vec(), true), collation.str_to_fat(&ucs2("fîle"), 4));
+    }
+}
@@ -0,0 +1,143 @@
+//! Firmware unique identifiers built on the platform monotonic counter.
+//!
+//! Each value returned by [`BootServices::get_next_monotonic_count`] is unique across boots: its high 32 bits are
+//! persistent and incremented on every reset, and its low 32 bits are incremented on every call. A
+//! [`UniqueIdGenerator`] reserves one count and derives many [`UniqueId`] from it with a local sequence number, so
+//! tagging events or telemetry does not need a service call for each identifier.
+//!
+//! ```ignore
+//! static UNIQUE_IDS: UniqueIdGenerator = UniqueIdGenerator::new();
+//!
+//! let id = UNIQUE_IDS.next_id(&BOOT_SERVICES)?;
+//! ```
+
+use r_efi::efi;
+
+use crate::{
+    tpl::{Tpl, TplLock},
+    BootServices,
+};
+
+/// Identifier unique across the firmware and across boots.
+///
+/// Identifiers from the same [`UniqueIdGenerator`] are increasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UniqueId {
+    /// Monotonic count reserved by the generator.
+    pub monotonic_count: u64,
+    /// Sequence number of the identifier for this monotonic count.
+    pub sequence: u32,
+}
+
+impl UniqueId {
+    /// Returns the persistent high 32 bits of the monotonic count, which change on every boot.
+    pub fn boot_count(&self) -> u32 {
+        (self.monotonic_count >> 32) as u32
+    }
+
+    /// Returns the identifier as a single integer, the `monotonic_count` above the 32 bits of the `sequence`.
+    pub fn as_u128(&self) -> u128 {
+        ((self.monotonic_count as u128) << 32) | self.sequence as u128
+    }
+}
+
+/// Generator of [`UniqueId`] calling [`BootServices::get_next_monotonic_count`] once per `ids_per_count` identifiers.
+///
+/// The generator is protected by raising the TPL to [`Tpl::HIGH_LEVEL`], so it can be used at any TPL.
+#[derive(Debug)]
+pub struct UniqueIdGenerator {
+    // The monotonic count in use and the next sequence number, None until the first identifier.
+    state: TplLock<Option<(u64, u32)>>,
+    ids_per_count: u32,
+}
+
+impl UniqueIdGenerator {
+    /// Create a generator using each monotonic count for up to `u32::MAX` identifiers.
+    pub const fn new() -> Self {
+        Self::with_ids_per_count(u32::MAX)
+    }
+
+    /// Create a generator using each monotonic count for up to *ids_per_count* identifiers.
+    ///
+    /// # Panics
+    /// This function will panic if *ids_per_count* is 0.
+    pub const fn with_ids_per_count(ids_per_count: u32) -> Self {
+        assert!(ids_per_count > 0, "A monotonic count must be used for at least one identifier.");
+        Self { state: TplLock::new(Tpl::HIGH_LEVEL, None), ids_per_count }
+    }
+
+    /// Returns the next identifier.
+    ///
+    /// A new monotonic count is reserved on the first call and when the sequence of the current one is exhausted, the
+    /// error of [`BootServices::get_next_monotonic_count`] is returned if it fails.
+    pub fn next_id<B: BootServices>(&self, boot_services: &B) -> Result<UniqueId, efi::Status> {
+        self.state.with(boot_services, |state| {
+            match *state {
+                Some((monotonic_count, sequence)) if sequence < self.ids_per_count => Ok((monotonic_count, sequence)),
+                _ => boot_services.get_next_monotonic_count().map(|monotonic_count| (monotonic_count, 0)),
+            }
+            .map(|(monotonic_count, sequence)| {
+                *state = Some((monotonic_count, sequence + 1));
+                UniqueId { monotonic_count, sequence }
+            })
+        })
+    }
+}
+
+impl Default for UniqueIdGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MockBootServices;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    fn boot_services(count: Arc<AtomicU64>) -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services.expect_get_next_monotonic_count().returning(move || Ok(count.fetch_add(1, Ordering::Relaxed)));
+        boot_services
+    }
+
+    #[test]
+    fn test_next_id() {
+        let count = Arc::new(AtomicU64::new(0x0000_0005_FFFF_FFFF));
+        let boot_services = boot_services(count.clone());
+        let generator = UniqueIdGenerator::with_ids_per_count(2);
+
+        let ids = (0..5).map(|_| generator.next_id(&boot_services).unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0x0000_0005_FFFF_FFFF, 0),
+                (0x0000_0005_FFFF_FFFF, 1),
+                (0x0000_0006_0000_0000, 0),
+                (0x0000_0006_0000_0000, 1),
+                (0x0000_0006_0000_0001, 0)
+            ],
+            ids.iter().map(|id| (id.monotonic_count, id.sequence)).collect::<Vec<_>>()
+        );
+        assert!(ids.windows(2).all(|w| w[0] < w[1] && w[0].as_u128() < w[1].as_u128()));
+        assert_eq!((5, 6), (ids[0].boot_count(), ids[2].boot_count()));
+        assert_eq!(0x0000_0006_0000_0002, count.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_next_id_error() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services.expect_get_next_monotonic_count().times(2).returning(|| Err(efi::Status::DEVICE_ERROR));
+        let generator = UniqueIdGenerator::new();
+
+        assert_eq!(Err(efi::Status::DEVICE_ERROR), generator.next_id(&boot_services));
+        assert_eq!(Err(efi::Status::DEVICE_ERROR), generator.next_id(&boot_services));
+    }
+}
@@ -0,0 +1,271 @@
+//! Vendor configuration tables with a signed, versioned and CRC-checked header, so the consumers of a table published
+//! by a Rust component reject a foreign, outdated or corrupted table rather than misreading it.
+//!
+//! ```ignore
+//! #[repr(C)]
+//! #[derive(Clone, Copy)]
+//! struct PlatformInfo {
+//!     board_id: u32,
+//!     sku_id: u32,
+//! }
+//!
+//! const PLATFORM_INFO_SIGNATURE: u64 = u64::from_le_bytes(*b"PLATINFO");
+//!
+//! // Producer.
+//! VersionedTable::new(PLATFORM_INFO_SIGNATURE, 1, PlatformInfo { board_id: 1, sku_id: 2 })
+//!     .install(&BOOT_SERVICES, &PLATFORM_INFO_GUID, MemoryType::BOOT_SERVICES_DATA)?;
+//!
+//! // Consumer.
+//! let table = unsafe {
+//!     VersionedTable::<PlatformInfo>::locate_and_validate(&BOOT_SERVICES, system_table, &PLATFORM_INFO_GUID, PLATFORM_INFO_SIGNATURE, 1)?
+//! };
+//! ```
+
+use alloc::vec::Vec;
+use core::{ffi::c_void, mem, ptr, slice};
+
+use r_efi::efi;
+
+use crate::{allocation::MemoryType, BootServices};
+
+// Offset of the CRC32 field in the table, it is zeroed to compute the CRC of the table.
+const CRC32_OFFSET: usize = mem::offset_of!(VersionedTableHeader, crc32);
+
+/// Header of a [`VersionedTable`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionedTableHeader {
+    /// Identifies the type of the table.
+    pub signature: u64,
+    /// Revision of the table, newer revisions may only append fields.
+    pub revision: u32,
+    /// Size of the table in bytes, header included.
+    pub length: u32,
+    /// CRC32 of the *length* bytes of the table, computed with this field set to 0.
+    pub crc32: u32,
+    pub reserved: u32,
+}
+
+/// A configuration table of data *T*, after a [`VersionedTableHeader`].
+///
+/// The CRC covers every byte of the table, so *T* should be `#[repr(C)]` without padding bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VersionedTable<T> {
+    pub header: VersionedTableHeader,
+    pub data: T,
+}
+
+impl<T: Copy + 'static> VersionedTable<T> {
+    /// Create a table of *data* identified by *signature*, at *revision*.
+    ///
+    /// The CRC is computed when the table is installed.
+    pub fn new(signature: u64, revision: u32, data: T) -> Self {
+        let header =
+            VersionedTableHeader { signature, revision, length: mem::size_of::<Self>() as u32, crc32: 0, reserved: 0 };
+        Self { header, data }
+    }
+
+    /// Installs the table in the configuration table under *guid*, in pool memory of *memory_type*.
+    ///
+    /// The CRC is computed with [`BootServices::calculate_crc_32_unchecked`] and the pool is freed if the table can not
+    /// be installed.
+    pub fn install<B: BootServices>(
+        &self,
+        boot_services: &B,
+        guid: &efi::Guid,
+        memory_type: MemoryType,
+    ) -> Result<&'static Self, efi::Status> {
+        let size = mem::size_of::<Self>();
+        let table = boot_services.allocate_pool(memory_type, size)? as *mut Self;
+        // SAFETY: The pool was allocated for the table, and zeroed first so its padding bytes covered by the CRC are
+        // initialized.
+        unsafe {
+            ptr::write_bytes(table as *mut u8, 0, size);
+            ptr::addr_of_mut!((*table).header).write(VersionedTableHeader { crc32: 0, ..self.header });
+            ptr::addr_of_mut!((*table).data).write(self.data);
+            let result = boot_services.calculate_crc_32_unchecked(table as *const c_void, size).and_then(|crc32| {
+                (*table).header.crc32 = crc32;
+                boot_services.install_configuration_table_unchecked(guid, table as *mut c_void)
+            });
+            match result {
+                Ok(()) => Ok(&*table),
+                Err(status) => {
+                    let _ = boot_services.free_pool(table as *mut u8);
+                    Err(status)
+                }
+            }
+        }
+    }
+
+    /// Validates the table at *table*, identified by *signature* and of at least *revision*.
+    ///
+    /// Returns:
+    /// - `efi::Status::INVALID_PARAMETER` if *table* is null or the signature does not match.
+    /// - `efi::Status::INCOMPATIBLE_VERSION` if the table revision is older than *revision*.
+    /// - `efi::Status::BAD_BUFFER_SIZE` if the table length is smaller than the size of the table.
+    /// - `efi::Status::CRC_ERROR` if the CRC32 of the table does not match the header.
+    ///
+    /// # Safety
+    ///
+    /// *table* must either be null or readable for the size of a header, and for the table length once the signature
+    /// matched.
+    pub unsafe fn validate<B: BootServices>(
+        boot_services: &B,
+        table: *const Self,
+        signature: u64,
+        revision: u32,
+    ) -> Result<&'static Self, efi::Status> {
+        // SAFETY: The caller guarantees the header is readable if the pointer is not null.
+        let Some(header) = (unsafe { (table as *const VersionedTableHeader).as_ref() }) else {
+            return Err(efi::Status::INVALID_PARAMETER);
+        };
+        if header.signature != signature {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        if header.revision < revision {
+            return Err(efi::Status::INCOMPATIBLE_VERSION);
+        }
+        if (header.length as usize) < mem::size_of::<Self>() {
+            return Err(efi::Status::BAD_BUFFER_SIZE);
+        }
+
+        // SAFETY: The caller guarantees the table is readable for its length.
+        let mut bytes: Vec<u8> = unsafe { slice::from_raw_parts(table as *const u8, header.length as usize) }.to_vec();
+        bytes[CRC32_OFFSET..CRC32_OFFSET + mem::size_of::<u32>()].fill(0);
+        // SAFETY: The bytes are a copy of the table.
+        match unsafe { boot_services.calculate_crc_32_unchecked(bytes.as_ptr() as *const c_void, bytes.len()) }? {
+            // SAFETY: The table is valid, configuration tables are never freed while installed.
+            crc32 if crc32 == header.crc32 => Ok(unsafe { &*table }),
+            _ => Err(efi::Status::CRC_ERROR),
+        }
+    }
+
+    /// Locates the table of *guid* in the configuration table of *system_table* and validates it, see [`Self::validate`]
+    /// for the errors.
+    ///
+    /// Returns `efi::Status::NOT_FOUND` if there is no table of *guid*.
+    ///
+    /// # Safety
+    ///
+    /// *system_table* must point to a valid [efi::SystemTable], whose table of *guid* meets the requirements of
+    /// [`Self::validate`].
+    pub unsafe fn locate_and_validate<B: BootServices>(
+        boot_services: &B,
+        system_table: *const efi::SystemTable,
+        guid: &efi::Guid,
+        signature: u64,
+        revision: u32,
+    ) -> Result<&'static Self, efi::Status> {
+        let system_table = &*system_table;
+        let configuration_table =
+            slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries);
+        let table = configuration_table
+            .iter()
+            .find(|table| &table.vendor_guid == guid)
+            .ok_or(efi::Status::NOT_FOUND)?
+            .vendor_table;
+        Self::validate(boot_services, table as *const Self, signature, revision)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{crc32::crc32, MockBootServices};
+    use std::cell::Cell;
+
+    const TEST_GUID: efi::Guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+    const TEST_SIGNATURE: u64 = u64::from_le_bytes(*b"TESTTABL");
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct TestData {
+        board_id: u32,
+        sku_id: u32,
+    }
+
+    fn boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_calculate_crc_32_unchecked()
+            .returning(|data, data_size| Ok(crc32(unsafe { slice::from_raw_parts(data as *const u8, data_size) })));
+        boot_services
+    }
+
+    #[test]
+    fn test_install_and_locate() {
+        std::thread_local! {
+            static INSTALLED: Cell<*mut c_void> = const { Cell::new(ptr::null_mut()) };
+        }
+        let mut boot_services = boot_services();
+        boot_services.expect_allocate_pool().returning(|memory_type, size| {
+            assert_eq!(MemoryType::RUNTIME_SERVICES_DATA, memory_type);
+            Ok(Box::leak(vec![0xA5_u64; size.div_ceil(8)].into_boxed_slice()).as_mut_ptr() as *mut u8)
+        });
+        boot_services.expect_install_configuration_table_unchecked().returning(|guid, table| {
+            assert_eq!(&TEST_GUID, guid);
+            INSTALLED.set(table);
+            Ok(())
+        });
+
+        let data = TestData { board_id: 1, sku_id: 2 };
+        let table = VersionedTable::new(TEST_SIGNATURE, 2, data)
+            .install(&boot_services, &TEST_GUID, MemoryType::RUNTIME_SERVICES_DATA)
+            .unwrap();
+        assert_eq!(INSTALLED.get(), table as *const _ as *mut c_void);
+        assert_eq!(mem::size_of::<VersionedTable<TestData>>() as u32, table.header.length);
+
+        let mut configuration_table =
+            [efi::ConfigurationTable { vendor_guid: TEST_GUID, vendor_table: INSTALLED.get() }];
+        let mut system_table: efi::SystemTable = unsafe { mem::zeroed() };
+        system_table.number_of_table_entries = configuration_table.len();
+        system_table.configuration_table = configuration_table.as_mut_ptr();
+        let locate = |system_table: &efi::SystemTable, signature, revision| unsafe {
+            VersionedTable::<TestData>::locate_and_validate(
+                &boot_services,
+                system_table,
+                &TEST_GUID,
+                signature,
+                revision,
+            )
+            .map(|table| table.data)
+        };
+
+        assert_eq!(Ok(data), locate(&system_table, TEST_SIGNATURE, 1));
+        assert_eq!(Ok(data), locate(&system_table, TEST_SIGNATURE, 2));
+        assert_eq!(Err(efi::Status::INCOMPATIBLE_VERSION), locate(&system_table, TEST_SIGNATURE, 3));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), locate(&system_table, !TEST_SIGNATURE, 1));
+
+        let table = INSTALLED.get() as *mut VersionedTable<TestData>;
+        unsafe { (*table).data.sku_id = 3 };
+        assert_eq!(Err(efi::Status::CRC_ERROR), locate(&system_table, TEST_SIGNATURE, 1));
+        unsafe { (*table).header.length = mem::size_of::<VersionedTableHeader>() as u32 };
+        assert_eq!(Err(efi::Status::BAD_BUFFER_SIZE), locate(&system_table, TEST_SIGNATURE, 1));
+
+        system_table.number_of_table_entries = 0;
+        assert_eq!(Err(efi::Status::NOT_FOUND), locate(&system_table, TEST_SIGNATURE, 1));
+    }
+
+    #[test]
+    fn test_failed_install_frees_the_table() {
+        let mut boot_services = boot_services();
+        boot_services
+            .expect_allocate_pool()
+            .returning(|_, _| Ok(Box::leak(Box::new([0_u64; 4])).as_mut_ptr() as *mut u8));
+        boot_services
+            .expect_install_configuration_table_unchecked()
+            .returning(|_, _| Err(efi::Status::OUT_OF_RESOURCES));
+        boot_services.expect_free_pool().times(1).returning(|_| Ok(()));
+
+        let table = VersionedTable::new(TEST_SIGNATURE, 1, TestData { board_id: 1, sku_id: 2 });
+        assert_eq!(
+            Err(efi::Status::OUT_OF_RESOURCES),
+            table.install(&boot_services, &TEST_GUID, MemoryType::BOOT_SERVICES_DATA).map(|_| ())
+        );
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            unsafe { VersionedTable::<TestData>::validate(&boot_services, ptr::null(), TEST_SIGNATURE, 1) }.map(|_| ())
+        );
+    }
+}
@@ -28,6 +28,290 @@ macro_rules! guid_to_uuid {
     };
 }
 
+/// Macro defining named GUID constants and a [`GuidTable`] named `GUIDS` to look them up by name or by value.
+///
+/// The table is meant for diagnostics, such as protocol dumps, to print the names of the GUIDs with [`GuidName`].
+/// Platforms define their vendor GUIDs in a module of their own, so the tables of several modules can be searched
+/// together. A GUID defined twice in the same table fails the build.
+///
+/// ```ignore
+/// mod vendor {
+///     guid::define_guids! {
+///         /// The Advanced Logger protocol.
+///         ADVANCED_LOGGER_PROTOCOL = "434F695C-EF26-4A12-9EBA-DDEF0097497C",
+///         MS_WHEA_RSC_DATA_TYPE = "91DEEA05-8C0A-4DCD-B91E-F21CA0C68405",
+///     }
+/// }
+///
+/// log::info!("{} installed", GuidName::new(&protocol_guid, &[&vendor::GUIDS, &platform::GUIDS]));
+/// ```
+#[macro_export]
+macro_rules! define_guids {
+    ($($(#[$attr:meta])* $name:ident = $guid_str:expr),* $(,)?) => {
+        $($(#[$attr])* pub const $name: r_efi::efi::Guid = r_efi::efi::Guid::from_bytes(&$crate::uuid!($guid_str).to_bytes_le());)*
+
+        /// The names and values of the GUIDs defined in this module.
+        pub const GUIDS: $crate::GuidTable = $crate::GuidTable::new(&[$((stringify!($name), $name)),*], |name| match name {
+            $(stringify!($name) => Some($name),)*
+            _ => None,
+        });
+
+        const _: () = assert!(!GUIDS.has_duplicates(), "a GUID is defined twice");
+    };
+}
+
+/// A table of named GUIDs, generated by [`define_guids!`].
+#[derive(Clone, Copy)]
+pub struct GuidTable {
+    entries: &'static [(&'static str, efi::Guid)],
+    by_name: fn(&str) -> Option<efi::Guid>,
+}
+
+impl GuidTable {
+    /// Create a table of *entries*, *by_name* returning the GUID of a name of the entries.
+    ///
+    /// Use [`define_guids!`] rather than calling this directly, it generates the lookup as a `match` on the names.
+    pub const fn new(entries: &'static [(&'static str, efi::Guid)], by_name: fn(&str) -> Option<efi::Guid>) -> Self {
+        Self { entries, by_name }
+    }
+
+    /// Returns the GUID named *name*, None if it is not in the table.
+    pub fn guid(&self, name: &str) -> Option<efi::Guid> {
+        (self.by_name)(name)
+    }
+
+    /// Returns the name of *guid*, None if it is not in the table.
+    pub fn name(&self, guid: &efi::Guid) -> Option<&'static str> {
+        self.entries.iter().find(|(_, g)| g == guid).map(|(name, _)| *name)
+    }
+
+    /// Returns the names and values of the GUIDs of the table, in the order they are defined.
+    pub fn entries(&self) -> &'static [(&'static str, efi::Guid)] {
+        self.entries
+    }
+
+    /// Returns true if a GUID is in the table more than once, checked at compile time by [`define_guids!`].
+    pub const fn has_duplicates(&self) -> bool {
+        let mut i = 0;
+        while i < self.entries.len() {
+            let mut j = i + 1;
+            while j < self.entries.len() {
+                if u128::from_le_bytes(*self.entries[i].1.as_bytes())
+                    == u128::from_le_bytes(*self.entries[j].1.as_bytes())
+                {
+                    return true;
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        false
+    }
+}
+
+impl core::fmt::Debug for GuidTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let mut map = f.debug_map();
+        for (name, guid) in self.entries {
+            map.entry(name, &guid_fmt!(guid));
+        }
+        map.finish()
+    }
+}
+
+/// Formats a GUID with its name from the first of *tables* defining it, or as a string if none does.
+///
+/// Named GUIDs are printed as `NAME (434F695C-EF26-4A12-9EBA-DDEF0097497C)`.
+pub struct GuidName<'a> {
+    guid: &'a efi::Guid,
+    tables: &'a [&'a GuidTable],
+}
+
+impl<'a> GuidName<'a> {
+    /// Create a formatter of *guid* looking its name up in *tables*.
+    pub fn new(guid: &'a efi::Guid, tables: &'a [&'a GuidTable]) -> Self {
+        Self { guid, tables }
+    }
+
+    /// Returns the name of the GUID from the first of the tables defining it.
+    pub fn name(&self) -> Option<&'static str> {
+        self.tables.iter().find_map(|table| table.name(self.guid))
+    }
+}
+
+impl core::fmt::Display for GuidName<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name} ({})", guid_fmt!(self.guid)),
+            None => write!(f, "{}", guid_fmt!(self.guid)),
+        }
+    }
+}
+
+/// An `efi::Guid` that can be ordered, parsed, and serialized with the `serde` feature.
+///
+/// GUIDs are ordered, displayed and parsed in their string form, e.g. `434F695C-EF26-4A12-9EBA-DDEF0097497C`, so a
+/// sorted list of GUIDs reads sorted. Serialized, a GUID is its string in human readable formats such as JSON or TOML,
+/// and its 16 bytes in the layout of `efi::Guid` otherwise.
+///
+/// ```ignore
+/// #[derive(serde::Deserialize)]
+/// struct PlatformConfig {
+///     boot_options: BTreeMap<guid::Guid, BootOption>,
+/// }
+/// ```
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid(efi::Guid);
+
+impl Guid {
+    /// Create a GUID from an `efi::Guid`.
+    pub const fn new(guid: efi::Guid) -> Self {
+        Self(guid)
+    }
+
+    /// Create a GUID from its bytes in the layout of `efi::Guid`.
+    pub const fn from_bytes(bytes: &[u8; 16]) -> Self {
+        Self(efi::Guid::from_bytes(bytes))
+    }
+
+    /// Returns the bytes of the GUID in the layout of `efi::Guid`.
+    pub const fn as_bytes(&self) -> &[u8; 16] {
+        self.0.as_bytes()
+    }
+
+    /// Returns the `efi::Guid`.
+    pub const fn as_efi_guid(&self) -> &efi::Guid {
+        &self.0
+    }
+
+    /// Returns the GUID as a `uuid::Uuid`.
+    pub const fn to_uuid(&self) -> uuid::Uuid {
+        uuid::Uuid::from_bytes_le(*self.0.as_bytes())
+    }
+}
+
+impl From<efi::Guid> for Guid {
+    fn from(guid: efi::Guid) -> Self {
+        Self(guid)
+    }
+}
+
+impl From<Guid> for efi::Guid {
+    fn from(guid: Guid) -> Self {
+        guid.0
+    }
+}
+
+impl From<uuid::Uuid> for Guid {
+    fn from(uuid: uuid::Uuid) -> Self {
+        Self::from_bytes(&uuid.to_bytes_le())
+    }
+}
+
+impl From<Guid> for uuid::Uuid {
+    fn from(guid: Guid) -> Self {
+        guid.to_uuid()
+    }
+}
+
+impl AsRef<efi::Guid> for Guid {
+    fn as_ref(&self) -> &efi::Guid {
+        &self.0
+    }
+}
+
+impl Ord for Guid {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.to_uuid().cmp(&other.to_uuid())
+    }
+}
+
+impl PartialOrd for Guid {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl core::str::FromStr for Guid {
+    type Err = uuid::Error;
+
+    /// Parses a GUID in any of the forms accepted by `uuid::Uuid`, e.g. `434F695C-EF26-4A12-9EBA-DDEF0097497C`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        uuid::Uuid::try_parse(s).map(Self::from)
+    }
+}
+
+impl core::fmt::Display for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", guid_fmt!(self.0))
+    }
+}
+
+impl core::fmt::Debug for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Guid({self})")
+    }
+}
+
+#[cfg(any(test, feature = "serde"))]
+mod serde_impl {
+    use core::fmt;
+
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::Guid;
+
+    impl Serialize for Guid {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match serializer.is_human_readable() {
+                true => serializer.collect_str(self),
+                false => serializer.serialize_bytes(self.as_bytes()),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Guid {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            match deserializer.is_human_readable() {
+                true => deserializer.deserialize_str(GuidVisitor),
+                false => deserializer.deserialize_bytes(GuidVisitor),
+            }
+        }
+    }
+
+    // Accepts both forms, as some formats give the bytes as a sequence.
+    struct GuidVisitor;
+
+    impl<'de> de::Visitor<'de> for GuidVisitor {
+        type Value = Guid;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a GUID string or 16 bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Guid, E> {
+            v.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Guid, E> {
+            let bytes = v.try_into().map_err(|_| E::invalid_length(v.len(), &self))?;
+            Ok(Guid::from_bytes(bytes))
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Guid, A::Error> {
+            let mut bytes = [0; 16];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i, &self))?;
+            }
+            match seq.next_element::<u8>()? {
+                Some(_) => Err(de::Error::invalid_length(17, &self)),
+                None => Ok(Guid::from_bytes(&bytes)),
+            }
+        }
+    }
+}
+
 const ZERO_GUID_STR: &str = "00000000-0000-0000-0000-000000000000";
 
 pub const ZERO: efi::Guid = guid!(ZERO_GUID_STR);
@@ -45,7 +329,7 @@ mod tests {
     use r_efi::efi;
     use uuid::uuid;
 
-    use crate::{CALLER_ID, ZERO, ZERO_GUID_STR};
+    use crate::{Guid, GuidName, GuidTable, CALLER_ID, ZERO, ZERO_GUID_STR};
 
     const MS_WHEA_RSC_DATA_TYPE_GUID_FROM_MACRO: efi::Guid = guid!("91DEEA05-8C0A-4DCD-B91E-F21CA0C68405");
     const ADVANCED_LOGGER_PROTOCOL_GUID_FROM_MACRO: efi::Guid = guid!("434F695C-EF26-4A12-9EBA-DDEF0097497C");
@@ -74,6 +358,53 @@ mod tests {
         println!("Print GUID as string: {}", guid_fmt!(ADVANCED_LOGGER_PROTOCOL_GUID_FROM_FIELDS));
     }
 
+    mod vendor {
+        define_guids! {
+            /// The Advanced Logger protocol.
+            ADVANCED_LOGGER_PROTOCOL = "434F695C-EF26-4A12-9EBA-DDEF0097497C",
+            MS_WHEA_RSC_DATA_TYPE = "91DEEA05-8C0A-4DCD-B91E-F21CA0C68405",
+        }
+    }
+
+    mod platform {
+        define_guids! {
+            WHEA_RSC_DATA_TYPE = "91DEEA05-8C0A-4DCD-B91E-F21CA0C68405",
+            ZERO = "00000000-0000-0000-0000-000000000000",
+        }
+    }
+
+    #[test]
+    fn test_define_guids_macro() {
+        assert_eq!(ADVANCED_LOGGER_PROTOCOL_GUID_FROM_FIELDS, vendor::ADVANCED_LOGGER_PROTOCOL);
+        assert_eq!(Some(vendor::MS_WHEA_RSC_DATA_TYPE), vendor::GUIDS.guid("MS_WHEA_RSC_DATA_TYPE"));
+        assert_eq!(None, vendor::GUIDS.guid("ZERO"));
+        assert_eq!(Some("ADVANCED_LOGGER_PROTOCOL"), vendor::GUIDS.name(&ADVANCED_LOGGER_PROTOCOL_GUID_FROM_MACRO));
+        assert_eq!(None, vendor::GUIDS.name(&ZERO));
+        assert_eq!(2, vendor::GUIDS.entries().len());
+        assert!(!platform::GUIDS.has_duplicates());
+
+        let duplicates = GuidTable::new(&[("A", ZERO), ("B", CALLER_ID)], |_| None);
+        assert!(duplicates.has_duplicates());
+    }
+
+    #[test]
+    fn test_guid_name() {
+        let tables = [&vendor::GUIDS, &platform::GUIDS];
+        assert_eq!(
+            "MS_WHEA_RSC_DATA_TYPE (91DEEA05-8C0A-4DCD-B91E-F21CA0C68405)",
+            format!("{}", GuidName::new(&MS_WHEA_RSC_DATA_TYPE_GUID_FROM_MACRO, &tables))
+        );
+        assert_eq!(Some("ZERO"), GuidName::new(&ZERO, &tables).name());
+        assert_eq!(
+            "434F695C-EF26-4A12-9EBA-DDEF0097497C",
+            format!("{}", GuidName::new(&ADVANCED_LOGGER_PROTOCOL_GUID_FROM_FIELDS, &[&platform::GUIDS]))
+        );
+        assert_eq!(
+            "{\"WHEA_RSC_DATA_TYPE\": 91DEEA05-8C0A-4DCD-B91E-F21CA0C68405, \"ZERO\": 00000000-0000-0000-0000-000000000000}",
+            format!("{:?}", platform::GUIDS)
+        );
+    }
+
     #[test]
     fn test_guid_to_uuid_macro() {
         assert_eq!(
@@ -82,4 +413,42 @@ mod tests {
         );
         assert_ne!(guid_to_uuid!(guid!("434F695C-EF26-4A12-9EBA-DDEF0097497C")), uuid!(ZERO_GUID_STR));
     }
+
+    #[test]
+    fn test_guid_newtype() {
+        let guid = Guid::new(ADVANCED_LOGGER_PROTOCOL_GUID_FROM_FIELDS);
+        assert_eq!(Ok(guid), "434f695c-ef26-4a12-9eba-ddef0097497c".parse());
+        assert!("434F695C-EF26-4A12-9EBA".parse::<Guid>().is_err());
+        assert_eq!("434F695C-EF26-4A12-9EBA-DDEF0097497C", format!("{guid}"));
+        assert_eq!("Guid(434F695C-EF26-4A12-9EBA-DDEF0097497C)", format!("{guid:?}"));
+
+        assert_eq!(ADVANCED_LOGGER_PROTOCOL_GUID_FROM_FIELDS, efi::Guid::from(guid));
+        assert_eq!(uuid!("434F695C-EF26-4A12-9EBA-DDEF0097497C"), uuid::Uuid::from(guid));
+        assert_eq!(guid, Guid::from(uuid!("434F695C-EF26-4A12-9EBA-DDEF0097497C")));
+        assert_eq!(guid, Guid::from_bytes(guid.as_bytes()));
+
+        // Ordered as strings, not as the little endian fields of efi::Guid.
+        let guids = ["00000001-0000-0000-0000-000000000000", "00000100-0000-0000-0000-000000000000"]
+            .map(|s| s.parse::<Guid>().unwrap());
+        assert!(guids[0] < guids[1]);
+        assert!(guids[0].as_efi_guid() > guids[1].as_efi_guid());
+    }
+
+    #[test]
+    fn test_guid_serde() {
+        use serde::de::{value, Deserialize, IntoDeserializer};
+
+        let guid = Guid::new(MS_WHEA_RSC_DATA_TYPE_GUID_FROM_MACRO);
+        let json = serde_json::to_string(&guid).unwrap();
+        assert_eq!("\"91DEEA05-8C0A-4DCD-B91E-F21CA0C68405\"", json);
+        assert_eq!(guid, serde_json::from_str(&json).unwrap());
+        assert!(serde_json::from_str::<Guid>("\"not a guid\"").is_err());
+
+        let bytes = value::BytesDeserializer::<value::Error>::new(guid.as_bytes());
+        assert_eq!(Ok(guid), Guid::deserialize(bytes));
+        let bytes = value::BytesDeserializer::<value::Error>::new(&guid.as_bytes()[..15]);
+        assert!(Guid::deserialize(bytes).is_err());
+        let seq: value::SeqDeserializer<_, value::Error> = guid.as_bytes().to_vec().into_deserializer();
+        assert_eq!(Ok(guid), Guid::deserialize(seq));
+    }
 }
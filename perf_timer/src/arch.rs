@@ -1,6 +1,6 @@
 use core::u64;
 
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub use x64::X64 as Arch;
 
 #[cfg(target_arch = "aarch64")]
@@ -21,43 +21,79 @@ pub trait ArchFunctionality {
     }
 }
 
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub(crate) mod x64 {
     use super::*;
-    use core::arch::x86_64::{self, CpuidResult};
+    use crate::frequency::{self, FrequencyFallbacks};
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86 as cpu;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64 as cpu;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use cpu::CpuidResult;
 
+    /// Frequency resolved by [`X64::init_frequency`], 0 if not initialized.
+    static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+    /// x86_64, and 32-bit x86 (IA32) for platforms still building their PEI or DXE phase for IA32.
     pub struct X64;
+
+    impl X64 {
+        /// Resolve the counter frequency from CPUID leaf 0x15, falling back on *fallbacks* when the leaf does not
+        /// enumerate it, as on older CPUs and in most virtual machines.
+        ///
+        /// The resolved frequency is used by [`ArchFunctionality::cpu_count_frequency`] from then on.
+        pub fn init_frequency(fallbacks: &FrequencyFallbacks) -> Option<u64> {
+            let frequency = frequency::resolve(Self::cpuid_frequency(), fallbacks)?;
+            FREQUENCY.store(frequency, Ordering::Relaxed);
+            Some(frequency)
+        }
+
+        /// TSC frequency enumerated by CPUID leaf 0x15, 0 if not enumerated.
+        fn cpuid_frequency() -> u64 {
+            // https://en.wikipedia.org/wiki/CPUID
+            let CpuidResult {
+                eax, // Ratio of TSC frequency to Core Crystal Clock frequency, denominator.
+                ebx, // Ratio of TSC frequency to Core Crystal Clock frequency, numerator.
+                ecx, // Core Crystal Clock frequency, in units of Hz.
+                ..
+            } = unsafe { cpu::__cpuid(0x15) };
+
+            if eax == 0 || ecx == 0 {
+                return 0;
+            }
+            ecx as u64 * ebx as u64 / eax as u64
+        }
+    }
+
     impl ArchFunctionality for X64 {
         fn cpu_count() -> u64 {
             #[cfg(feature = "validate_cpu_features")]
             {
                 // TSC support in bit 4.
-                if (unsafe { x86_64::__cpuid(0x01) }.edx & 0x10) != 0x10 {
+                if (unsafe { cpu::__cpuid(0x01) }.edx & 0x10) != 0x10 {
                     panic!("CPU does not support TSC");
                 }
                 // Invariant TSC support in bit 8.
-                if (unsafe { x86_64::__cpuid(0x80000007) }.edx & 0x100) != 0x100 {
+                if (unsafe { cpu::__cpuid(0x80000007) }.edx & 0x100) != 0x100 {
                     panic!("CPU does not support Invariant TSC");
                 }
             }
-            unsafe { x86_64::_rdtsc() }
+            unsafe { cpu::_rdtsc() }
         }
 
         fn cpu_count_frequency() -> u64 {
-            // https://en.wikipedia.org/wiki/CPUID
-            let CpuidResult {
-                eax, // Ratio of TSC frequency to Core Crystal Clock frequency, denominator.
-                ebx, // Ratio of TSC frequency to Core Crystal Clock frequency, numerator.
-                ecx, // Core Crystal Clock frequency, in units of Hz.
-                ..
-            } = unsafe { x86_64::__cpuid(0x15) };
-
-            #[cfg(feature = "validate_cpu_features")]
-            if ecx == 0 {
-                panic!("CPU does not support CPUID-based frequency determination");
+            match FREQUENCY.load(Ordering::Relaxed) {
+                0 => {
+                    let frequency = Self::cpuid_frequency();
+                    #[cfg(feature = "validate_cpu_features")]
+                    if frequency == 0 {
+                        panic!("CPU does not support CPUID-based frequency determination");
+                    }
+                    frequency
+                }
+                frequency => frequency,
             }
-
-            (ecx * (ebx / eax)) as u64
         }
     }
 }
@@ -65,15 +101,37 @@ pub(crate) mod x64 {
 #[cfg(target_arch = "aarch64")]
 pub(crate) mod aarch64 {
     use super::*;
+    use crate::frequency::{self, FrequencyFallbacks};
     use aarch64_cpu::registers::{self, Readable};
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    /// Frequency resolved by [`Aarch64::init_frequency`], 0 if not initialized.
+    static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
     pub struct Aarch64;
+
+    impl Aarch64 {
+        /// Resolve the counter frequency from CNTFRQ_EL0, falling back on *fallbacks* when the register is not
+        /// plausible, as can happen in virtual machines.
+        ///
+        /// The resolved frequency is used by [`ArchFunctionality::cpu_count_frequency`] from then on.
+        pub fn init_frequency(fallbacks: &FrequencyFallbacks) -> Option<u64> {
+            let frequency = frequency::resolve(registers::CNTFRQ_EL0.get(), fallbacks)?;
+            FREQUENCY.store(frequency, Ordering::Relaxed);
+            Some(frequency)
+        }
+    }
+
     impl ArchFunctionality for Aarch64 {
         fn cpu_count() -> u64 {
             registers::CNTPCT_EL0.get()
         }
 
         fn cpu_count_frequency() -> u64 {
-            registers::CNTFRQ_EL0.get()
+            match FREQUENCY.load(Ordering::Relaxed) {
+                0 => registers::CNTFRQ_EL0.get(),
+                frequency => frequency,
+            }
         }
     }
 }
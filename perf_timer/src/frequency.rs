@@ -0,0 +1,98 @@
+//! Counter frequency detection for platforms whose frequency register can not be trusted.
+//!
+//! Some hypervisors misprogram the frequency register of the counter, e.g. `CNTFRQ_EL0` on aarch64, and most do not
+//! enumerate the TSC frequency in CPUID leaf 0x15 on x86. The frequency is then taken from the first plausible value
+//! of a fallback chain: the register, the value provided by the firmware description of the platform, and a
+//! calibration against another time source.
+//!
+//! ```ignore
+//! fn calibrate_with_stall() -> u64 {
+//!     frequency::calibrate(Arch::cpu_count, |d| BOOT_SERVICES.stall_duration(d).unwrap(), Duration::from_millis(10))
+//! }
+//!
+//! Arch::init_frequency(&FrequencyFallbacks { firmware: dt_timer_frequency, calibrate: Some(calibrate_with_stall) });
+//! ```
+
+use core::time::Duration;
+
+/// Lowest frequency in Hz considered plausible for a counter.
+pub const MIN_PLAUSIBLE_FREQUENCY: u64 = 1_000_000;
+
+/// Highest frequency in Hz considered plausible for a counter.
+pub const MAX_PLAUSIBLE_FREQUENCY: u64 = 10_000_000_000;
+
+/// Sources of the counter frequency used when the frequency register is not plausible, in order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrequencyFallbacks {
+    /// Frequency in Hz provided by the firmware description of the platform, e.g. the `clock-frequency` property of
+    /// the devicetree timer node.
+    pub firmware: Option<u64>,
+    /// Function measuring the frequency in Hz, e.g. with [`calibrate`].
+    pub calibrate: Option<fn() -> u64>,
+}
+
+/// Returns true if *frequency* is within [`MIN_PLAUSIBLE_FREQUENCY`] and [`MAX_PLAUSIBLE_FREQUENCY`].
+pub fn is_plausible(frequency: u64) -> bool {
+    (MIN_PLAUSIBLE_FREQUENCY..=MAX_PLAUSIBLE_FREQUENCY).contains(&frequency)
+}
+
+/// Returns the first plausible frequency of *register_frequency* and *fallbacks*.
+///
+/// The calibration only runs if no other value is plausible.
+pub fn resolve(register_frequency: u64, fallbacks: &FrequencyFallbacks) -> Option<u64> {
+    [Some(register_frequency), fallbacks.firmware]
+        .into_iter()
+        .flatten()
+        .find(|&frequency| is_plausible(frequency))
+        .or_else(|| fallbacks.calibrate.map(|calibrate| calibrate()).filter(|&frequency| is_plausible(frequency)))
+}
+
+/// Measures the frequency in Hz of *cpu_count* across a *stall* of *duration* by another time source.
+///
+/// The longer the duration, the more precise the measurement.
+pub fn calibrate(cpu_count: impl Fn() -> u64, stall: impl FnOnce(Duration), duration: Duration) -> u64 {
+    let start = cpu_count();
+    stall(duration);
+    let ticks = cpu_count().wrapping_sub(start);
+    (ticks as u128 * 1_000_000_000 / duration.as_nanos().max(1)) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_resolve() {
+        fn calibrate_24mhz() -> u64 {
+            24_000_000
+        }
+        fn calibrate_broken() -> u64 {
+            0
+        }
+
+        let firmware = FrequencyFallbacks { firmware: Some(50_000_000), calibrate: Some(calibrate_24mhz) };
+        assert_eq!(Some(1_000_000_000), resolve(1_000_000_000, &firmware));
+        assert_eq!(Some(50_000_000), resolve(0, &firmware));
+        assert_eq!(Some(50_000_000), resolve(u64::MAX, &firmware));
+
+        let calibrated = FrequencyFallbacks { firmware: Some(1), calibrate: Some(calibrate_24mhz) };
+        assert_eq!(Some(24_000_000), resolve(0, &calibrated));
+
+        let broken = FrequencyFallbacks { firmware: None, calibrate: Some(calibrate_broken) };
+        assert_eq!(None, resolve(0, &broken));
+        assert_eq!(None, resolve(0, &FrequencyFallbacks::default()));
+    }
+
+    #[test]
+    fn test_calibrate() {
+        // A counter at 25 MHz, advanced by the stall.
+        let count = Cell::new(u64::MAX - 1_000);
+        let frequency = calibrate(
+            || count.get(),
+            |duration| count.set(count.get().wrapping_add(duration.as_micros() as u64 * 25)),
+            Duration::from_millis(10),
+        );
+        assert_eq!(25_000_000, frequency);
+    }
+}
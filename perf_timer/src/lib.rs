@@ -1,13 +1,25 @@
 #![cfg_attr(not(test), no_std)]
 
 mod arch;
+pub mod frequency;
 
-use core::time::Duration;
+#[cfg(any(test, feature = "log"))]
+pub mod timed_log;
+
+use core::{marker::PhantomData, time::Duration};
 
 pub use arch::{Arch, ArchFunctionality};
 
+/// Return the frequency in Hz of the counter used by [`Instant`].
+pub fn frequency() -> u64 {
+    Arch::cpu_count_frequency()
+}
+
 /// This struct is used to calculate the duration between two instant.
 ///
+/// The counter is the one of [`Arch`] unless another [`ArchFunctionality`] is given, e.g. to test code measuring time
+/// off-target.
+///
 /// # Example
 /// ```no_run
 /// use perf_timer::Instant;
@@ -18,49 +30,274 @@ pub use arch::{Arch, ArchFunctionality};
 ///
 /// let duration = start.elapsed();
 /// ```
-pub struct Instant {
+pub struct Instant<A: ArchFunctionality = Arch> {
     cpu_count: u64,
     frequency: u64,
+    arch: PhantomData<A>,
 }
 
 impl Instant {
+    pub(crate) const fn new(cpu_count: u64, frequency: u64) -> Self {
+        Self { cpu_count, frequency, arch: PhantomData }
+    }
+
     /// Create a new instant.
     pub fn now() -> Self {
-        Self::from_cpu_count(Arch::cpu_count())
+        Self::arch_now()
     }
 
     /// Create a new instant from a cpu count.
     pub fn from_cpu_count(cpu_count: u64) -> Self {
-        Self { cpu_count, frequency: Arch::cpu_count_frequency() }
+        Self::arch_from_cpu_count(cpu_count)
+    }
+
+    /// Create a new instant from a tick count of the counter, see [`Instant::ticks`].
+    pub fn from_ticks(ticks: u64) -> Self {
+        Self::from_cpu_count(ticks)
     }
 
     /// Create a new instant from the start of the counter.
     pub fn beginning() -> Self {
-        Self { cpu_count: Arch::cpu_count_start(), frequency: Arch::cpu_count_frequency() }
+        Self::arch_beginning()
+    }
+}
+
+impl<A: ArchFunctionality> Instant<A> {
+    /// Create a new instant of the counter of `A`, see [`Instant::now`].
+    pub fn arch_now() -> Self {
+        Self::arch_from_cpu_count(A::cpu_count())
+    }
+
+    /// Create a new instant of the counter of `A` from a cpu count, see [`Instant::from_cpu_count`].
+    ///
+    /// The frequency of the counter is read once, when the instant is created.
+    pub fn arch_from_cpu_count(cpu_count: u64) -> Self {
+        Self { cpu_count, frequency: A::cpu_count_frequency(), arch: PhantomData }
+    }
+
+    /// Create a new instant from the start of the counter of `A`, see [`Instant::beginning`].
+    pub fn arch_beginning() -> Self {
+        Self::arch_from_cpu_count(A::cpu_count_start())
     }
 
-    /// Return the amount of time from `earlier` adn this instant.
+    /// Return the tick count of the counter at this instant.
+    ///
+    /// Tick counts can be stored and later converted back with [`Instant::from_ticks`], as long as the counter is not
+    /// reset in between, e.g. by a later boot phase on the same CPU.
+    pub fn ticks(&self) -> u64 {
+        self.cpu_count
+    }
+
+    /// Return the frequency in Hz of the counter of this instant.
+    pub fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    /// Return the amount of time from `earlier` and this instant.
+    ///
+    /// On a counter rolling over before the end of the u64 range, an `earlier` tick count greater than the one of this
+    /// instant is taken as a rollover from [`ArchFunctionality::cpu_count_end`] to
+    /// [`ArchFunctionality::cpu_count_start`] in between, so durations longer than a full period of the counter can
+    /// not be measured. A 64-bit counter does not roll over in practice, so `earlier` must not be later.
     ///
     /// # Panic
-    /// This function will panic if earlier is not in the past.
+    /// This function will panic if earlier is not in the past, if the tick count of either instant is out of the range
+    /// of the counter, or if the frequency is unknown.
     pub fn duration_since(&self, earlier: &Self) -> Duration {
-        if earlier.cpu_count > self.cpu_count {
-            panic!("earlier not in the past.");
+        self.checked_duration_since(earlier).expect("earlier not in the past.")
+    }
+
+    /// Return the amount of time from `earlier` to this instant, or None where [`Instant::duration_since`] panics.
+    pub fn checked_duration_since(&self, earlier: &Self) -> Option<Duration> {
+        let (start, end) = (A::cpu_count_start(), A::cpu_count_end());
+        if !(start..=end).contains(&earlier.cpu_count) || !(start..=end).contains(&self.cpu_count) {
+            return None;
+        }
+        let ticks = match earlier.cpu_count > self.cpu_count {
+            true if end == u64::MAX => return None,
+            // The tick from the end to the start of the counter counts as one.
+            true => (end - earlier.cpu_count) + (self.cpu_count - start) + 1,
+            false => self.cpu_count - earlier.cpu_count,
+        };
+        if self.frequency == 0 {
+            return None;
         }
-        let diff = (self.cpu_count - earlier.cpu_count) as f64;
-        Duration::from_secs_f64(diff / self.frequency as f64)
+        let nanos = (ticks % self.frequency) as u128 * 1_000_000_000 / self.frequency as u128;
+        Some(Duration::from_secs(ticks / self.frequency) + Duration::from_nanos(nanos as u64))
     }
 
     /// Return the amount of time that elapsed since now and this instant.
     pub fn elapsed(&self) -> Duration {
-        Instant::now().duration_since(self)
+        Self::arch_now().duration_since(self)
+    }
+}
+
+/// A measurement between two instants, in a compact format meant to be stored in HOBs or variables.
+///
+/// The tick counts are kept with the counter frequency, so a later boot phase or an OS tool can convert them to time
+/// and correlate them with its own measurements of the same counter.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimeStampPair {
+    /// Tick count at the start of the measurement.
+    pub start: u64,
+    /// Tick count at the end of the measurement.
+    pub end: u64,
+    /// Frequency in Hz of the counter.
+    pub frequency: u64,
+}
+
+impl TimeStampPair {
+    /// Size in bytes of the serialized format, see [`TimeStampPair::to_bytes`].
+    pub const SIZE: usize = 24;
+
+    /// Create a measurement from `start` to `end`.
+    pub fn new<A: ArchFunctionality>(start: &Instant<A>, end: &Instant<A>) -> Self {
+        Self { start: start.cpu_count, end: end.cpu_count, frequency: end.frequency }
+    }
+
+    /// Return the instant at the start of the measurement.
+    pub fn start(&self) -> Instant {
+        Instant::new(self.start, self.frequency)
+    }
+
+    /// Return the instant at the end of the measurement.
+    pub fn end(&self) -> Instant {
+        Instant::new(self.end, self.frequency)
+    }
+
+    /// Return the duration of the measurement, or None if it ends before it starts or the frequency is unknown.
+    pub fn duration(&self) -> Option<Duration> {
+        self.end().checked_duration_since(&self.start())
+    }
+
+    /// Serialize the measurement as its start, end and frequency in little endian.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.start.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.end.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.frequency.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a measurement from the start of `bytes`, or return None if it is too short.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let field = |offset: usize| Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().unwrap()));
+        Some(Self { start: field(0)?, end: field(8)?, frequency: field(16)? })
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::thread;
+    use std::{cell::Cell, thread};
+
+    thread_local! {
+        static COUNT: Cell<u64> = const { Cell::new(0) };
+        static FREQUENCY: Cell<u64> = const { Cell::new(1_000_000) };
+        static FREQUENCY_READS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    // Counter controlled by the test, each test runs on its own thread.
+    struct MockArch;
+
+    impl ArchFunctionality for MockArch {
+        fn cpu_count() -> u64 {
+            COUNT.get()
+        }
+
+        fn cpu_count_frequency() -> u64 {
+            FREQUENCY_READS.set(FREQUENCY_READS.get() + 1);
+            FREQUENCY.get()
+        }
+
+        fn cpu_count_start() -> u64 {
+            100
+        }
+
+        fn cpu_count_end() -> u64 {
+            0xFFFF
+        }
+    }
+
+    #[test]
+    fn test_duration_math() {
+        COUNT.set(1_000);
+        FREQUENCY.set(2_000_000);
+        let start = Instant::<MockArch>::arch_now();
+        assert_eq!(Duration::ZERO, start.elapsed());
+
+        COUNT.set(7_000);
+        assert_eq!(Duration::from_millis(3), start.elapsed());
+        assert_eq!(Duration::from_micros(500), Instant::arch_from_cpu_count(2_000).duration_since(&start));
+
+        let beginning = Instant::<MockArch>::arch_beginning();
+        assert_eq!(MockArch::cpu_count_start(), beginning.ticks());
+        assert_eq!(Some(Duration::from_micros(3_450)), Instant::arch_now().checked_duration_since(&beginning));
+    }
+
+    #[test]
+    fn test_rollover() {
+        COUNT.set(MockArch::cpu_count_end() - 1_000);
+        let before = Instant::<MockArch>::arch_now();
+
+        // The counter rolls over to its start, the ticks before and after the rollover both count.
+        COUNT.set(MockArch::cpu_count_start() + 1_000);
+        let after = Instant::<MockArch>::arch_now();
+        assert_eq!(Some(Duration::from_micros(2_001)), after.checked_duration_since(&before));
+        assert_eq!(Duration::from_micros(2_001), before.elapsed());
+        assert_eq!(Some(Duration::from_millis(1)), after.checked_duration_since(&Instant::arch_beginning()));
+
+        // Tick counts out of the range of the counter can not be measured.
+        let out_of_range = Instant::<MockArch>::arch_from_cpu_count(MockArch::cpu_count_end() + 1);
+        assert_eq!(None, out_of_range.checked_duration_since(&before));
+        assert!(std::panic::catch_unwind(|| before.duration_since(&out_of_range)).is_err());
+    }
+
+    #[test]
+    fn test_frequency_caching() {
+        FREQUENCY.set(1_000_000);
+        let start = Instant::<MockArch>::arch_from_cpu_count(1_000);
+        assert_eq!(1, FREQUENCY_READS.get());
+
+        // The instants keep the frequency they were created with, durations do not read it again.
+        FREQUENCY.set(4_000_000);
+        let end = Instant::<MockArch>::arch_from_cpu_count(5_000);
+        assert_eq!((1_000_000, 4_000_000), (start.frequency(), end.frequency()));
+        assert_eq!(Duration::from_millis(1), end.duration_since(&start));
+        assert_eq!(Some(Duration::from_millis(1)), TimeStampPair::new(&start, &end).duration());
+        assert_eq!(2, FREQUENCY_READS.get());
+    }
+
+    #[test]
+    fn test_checked_duration_since() {
+        let earlier = Instant::new(1_000, 1_000_000);
+        let later = Instant::new(3_000, 1_000_000);
+
+        assert_eq!(Some(Duration::from_millis(2)), later.checked_duration_since(&earlier));
+        assert_eq!(Some(Duration::ZERO), later.checked_duration_since(&later));
+        // The counter uses the full u64 range, a later instant is not taken as a rollover.
+        assert_eq!(None, earlier.checked_duration_since(&later));
+        assert!(std::panic::catch_unwind(|| earlier.duration_since(&later)).is_err());
+        assert_eq!(None, Instant::new(3_000, 0).checked_duration_since(&earlier));
+    }
+
+    #[test]
+    fn test_time_stamp_pair() {
+        let start = Instant::new(1_000, 1_000_000);
+        let end = Instant::new(5_000, 1_000_000);
+
+        let pair = TimeStampPair::new(&start, &end);
+        assert_eq!(Some(Duration::from_millis(4)), pair.duration());
+        assert_eq!((1_000, 1_000_000), (pair.start().ticks(), pair.start().frequency()));
+
+        let bytes = pair.to_bytes();
+        assert_eq!(Some(pair), TimeStampPair::from_bytes(&bytes));
+        assert_eq!(None, TimeStampPair::from_bytes(&bytes[..TimeStampPair::SIZE - 1]));
+
+        assert_eq!(None, TimeStampPair { start: 5, end: 1, frequency: 1 }.duration());
+        assert_eq!(None, TimeStampPair { start: 1, end: 5, frequency: 0 }.duration());
+    }
 
     #[ignore = "Register / instruction return nonsense in the Azure pipeline vm."]
     #[test]
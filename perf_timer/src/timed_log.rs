@@ -0,0 +1,175 @@
+//! Logging with boot relative timestamps, so the log lines of every component can be correlated with each other and
+//! with the performance measurements of [`Instant`].
+//!
+//! Log lines are prefixed with the time since [`Instant::beginning`] in seconds and microseconds, either per message
+//! with [`log_timed!`](crate::log_timed), or for every message by wrapping the logger in a [`TimedLogger`].
+//!
+//! ```ignore
+//! perf_timer::log_timed!(log::Level::Info, "Loading {}", name);
+//! // [     1.234567] Loading Shell.efi
+//!
+//! static LOGGER: TimedLogger<SerialLogger> = TimedLogger::new(SerialLogger::new());
+//! log::set_logger(&LOGGER)?;
+//! ```
+
+use core::fmt;
+
+use crate::Instant;
+
+#[doc(hidden)]
+pub use log;
+
+/// The time elapsed since [`Instant::beginning`], displayed as `[seconds.microseconds]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BootTimestamp {
+    micros: u64,
+}
+
+impl BootTimestamp {
+    /// Create the timestamp of the current instant.
+    pub fn now() -> Self {
+        Self::since(&Instant::beginning(), &Instant::now())
+    }
+
+    /// Create the timestamp of *instant* relative to *beginning*.
+    ///
+    /// The timestamp is 0 if *instant* is before *beginning* or the frequency of the counter is unknown.
+    pub fn since(beginning: &Instant, instant: &Instant) -> Self {
+        let ticks = instant.cpu_count.saturating_sub(beginning.cpu_count) as u128;
+        let micros = match instant.frequency {
+            0 => 0,
+            frequency => ticks * 1_000_000 / frequency as u128,
+        };
+        Self { micros: u64::try_from(micros).unwrap_or(u64::MAX) }
+    }
+
+    /// Return the number of microseconds since the beginning.
+    pub fn as_micros(&self) -> u64 {
+        self.micros
+    }
+}
+
+impl fmt::Display for BootTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:>6}.{:06}]", self.micros / 1_000_000, self.micros % 1_000_000)
+    }
+}
+
+/// Logs a message like [`log::log!`], prefixed with the [`BootTimestamp`] of the call.
+///
+/// The timestamp is only computed if the message is logged.
+#[macro_export]
+macro_rules! log_timed {
+    (target: $target:expr, $level:expr, $($arg:tt)+) => {
+        $crate::timed_log::log::log!(
+            target: $target,
+            $level,
+            "{} {}",
+            $crate::timed_log::BootTimestamp::now(),
+            format_args!($($arg)+)
+        )
+    };
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log_timed!(target: core::module_path!(), $level, $($arg)+)
+    };
+}
+
+/// A [`log::Log`] decorator prefixing every message with its [`BootTimestamp`] before passing it to the wrapped
+/// logger.
+pub struct TimedLogger<L> {
+    logger: L,
+    timestamp: fn() -> BootTimestamp,
+}
+
+impl<L: log::Log> TimedLogger<L> {
+    /// Create a logger timestamping the messages passed to *logger*.
+    pub const fn new(logger: L) -> Self {
+        Self { logger, timestamp: BootTimestamp::now }
+    }
+
+    /// Return the wrapped logger.
+    pub fn inner(&self) -> &L {
+        &self.logger
+    }
+}
+
+impl<L: log::Log> log::Log for TimedLogger<L> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.logger.enabled(record.metadata()) {
+            return;
+        }
+        self.logger.log(
+            &log::Record::builder()
+                .metadata(record.metadata().clone())
+                .args(format_args!("{} {}", (self.timestamp)(), record.args()))
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::Log;
+    use std::{string::String, sync::Mutex, vec::Vec};
+
+    #[derive(Default)]
+    struct TestLogger(Mutex<Vec<String>>);
+
+    impl Log for TestLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Info
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push(format!("{}", record.args()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_boot_timestamp() {
+        let beginning = Instant::new(1_000, 1_000_000);
+        let at = |cpu_count, frequency| BootTimestamp::since(&beginning, &Instant::new(cpu_count, frequency));
+
+        assert_eq!(1_234_567, at(1_235_567, 1_000_000).as_micros());
+        assert_eq!("[     1.234567]", format!("{}", at(1_235_567, 1_000_000)));
+        assert_eq!("[1234567.000001]", format!("{}", at(1_234_567_000_001 + 1_000, 1_000_000)));
+        assert_eq!(0, at(500, 1_000_000).as_micros());
+        assert_eq!(0, at(1_235_567, 0).as_micros());
+        assert_eq!(500, at(3_000, 4_000_000).as_micros());
+    }
+
+    #[test]
+    fn test_timed_logger() {
+        fn timestamp() -> BootTimestamp {
+            BootTimestamp { micros: 2_000_042 }
+        }
+        let logger = TimedLogger { logger: TestLogger::default(), timestamp };
+
+        let log = |level, message| {
+            logger.log(&log::Record::builder().level(level).args(format_args!("{message}")).build());
+        };
+        log(log::Level::Info, "Loading Shell.efi");
+        log(log::Level::Debug, "Filtered out");
+
+        assert!(logger.enabled(&log::Metadata::builder().level(log::Level::Warn).build()));
+        assert_eq!(vec!["[     2.000042] Loading Shell.efi"], *logger.inner().0.lock().unwrap());
+
+        // No logger is set, the timestamp is not computed.
+        log_timed!(log::Level::Error, "Not logged {}", 1);
+        log_timed!(target: "test", log::Level::Error, "Not logged");
+    }
+}
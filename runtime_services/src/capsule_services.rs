@@ -0,0 +1,202 @@
+use core::mem;
+
+use alloc::{string::String, vec::Vec};
+use r_efi::efi;
+
+/// GUID of capsules targeting the Firmware Management Protocol (EFI_FIRMWARE_MANAGEMENT_CAPSULE_ID_GUID).
+pub const FMP_CAPSULE_GUID: efi::Guid =
+    efi::Guid::from_fields(0x6dcbd5ed, 0xe82d, 0x4c44, 0xbd, 0xa1, &[0x71, 0x94, 0x19, 0x9a, 0xd9, 0x2a]);
+
+/// Capsule capabilities returned by [`RuntimeServices::query_capsule_capabilities`](crate::RuntimeServices::query_capsule_capabilities)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapsuleCapabilities {
+    /// The maximum size in bytes that update_capsule can support as an argument
+    pub maximum_capsule_size: u64,
+    /// The type of reset required for the capsule update
+    pub reset_type: efi::ResetType,
+}
+
+/// FMP specific part of a [`CapsuleResult`] (EFI_CAPSULE_RESULT_VARIABLE_FMP)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapsuleResultFmp {
+    /// Version of this structure
+    pub version: u16,
+    /// Index of the payload within the FMP capsule which was processed
+    pub payload_index: u8,
+    /// Update image index of the payload
+    pub update_image_index: u8,
+    /// Image type id of the payload
+    pub update_image_type_id: efi::Guid,
+    /// File name of the capsule, empty if the capsule was not delivered as a file
+    pub capsule_file_name: String,
+    /// Device path text of the device targeted by the update, may be empty
+    pub capsule_target: String,
+}
+
+/// Typed content of a `CapsuleXXXX` result variable (EFI_CAPSULE_RESULT_VARIABLE_HEADER)
+///
+/// UEFI Spec Documentation: [8.5.6. UEFI variable reporting on the Success or any Errors encountered in processing of capsules after restart](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#uefi-variable-reporting-on-the-success-or-any-errors-encountered-in-processing-of-capsules-after-restart)
+#[derive(Debug, Clone)]
+pub struct CapsuleResult {
+    /// GUID of the processed capsule
+    pub capsule_guid: efi::Guid,
+    /// Time at which the capsule was processed
+    pub capsule_processed: efi::Time,
+    /// Status of the capsule processing
+    pub capsule_status: efi::Status,
+    /// Additional information, present when the capsule is an FMP capsule
+    pub fmp: Option<CapsuleResultFmp>,
+}
+
+impl TryFrom<Vec<u8>> for CapsuleResult {
+    type Error = efi::Status;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let header_size = mem::size_of::<efi::CapsuleResultVariableHeader>();
+        if value.len() < header_size {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        // SAFETY: The buffer has been checked to be big enough for the header.
+        let header = unsafe { (value.as_ptr() as *const efi::CapsuleResultVariableHeader).read_unaligned() };
+        let total_size = header.variable_total_size as usize;
+        if total_size < header_size || total_size > value.len() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        let fmp = if header.capsule_guid == FMP_CAPSULE_GUID {
+            Some(parse_fmp(&value[header_size..total_size])?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            capsule_guid: header.capsule_guid,
+            capsule_processed: header.capsule_processed,
+            capsule_status: header.capsule_status,
+            fmp,
+        })
+    }
+}
+
+fn parse_fmp(data: &[u8]) -> Result<CapsuleResultFmp, efi::Status> {
+    let fmp_size = mem::size_of::<efi::CapsuleResultVariableFMP>();
+    if data.len() < fmp_size {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+
+    // SAFETY: The buffer has been checked to be big enough for the FMP structure.
+    let fmp = unsafe { (data.as_ptr() as *const efi::CapsuleResultVariableFMP).read_unaligned() };
+
+    // The FMP structure is followed by two null-terminated strings: the capsule file name and the capsule target.
+    let mut strings = data[fmp_size..].chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]]));
+    let mut next_string = || -> Result<String, efi::Status> {
+        let chars = strings.by_ref().take_while(|&c| c != 0).collect::<Vec<u16>>();
+        String::from_utf16(&chars).map_err(|_| efi::Status::INVALID_PARAMETER)
+    };
+    let capsule_file_name = next_string()?;
+    let capsule_target = next_string()?;
+
+    Ok(CapsuleResultFmp {
+        version: fmp.version,
+        payload_index: fmp.payload_index,
+        update_image_index: fmp.update_image_index,
+        update_image_type_id: fmp.update_image_type_id,
+        capsule_file_name,
+        capsule_target,
+    })
+}
+
+/// Returns the null-terminated name of the `CapsuleXXXX` result variable at *index*.
+pub fn capsule_result_variable_name(index: u16) -> [u16; 12] {
+    let mut name = [0; 12];
+    for (dst, src) in name.iter_mut().zip("Capsule".encode_utf16()) {
+        *dst = src;
+    }
+    for i in 0..4 {
+        let digit = (index >> ((3 - i) * 4)) & 0xF;
+        name[7 + i] = if digit < 10 { '0' as u16 + digit } else { 'A' as u16 + digit - 10 };
+    }
+    name
+}
+
+/// Parses the index out of a `CapsuleXXXX` name, as stored in the `CapsuleMax` and `CapsuleLast` variables.
+pub fn parse_capsule_result_variable_name(name: &[u16]) -> Option<u16> {
+    let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+    if name.len() != 11 || !name.starts_with(&capsule_result_variable_name(0)[..7]) {
+        return None;
+    }
+    name[7..].iter().try_fold(0u16, |index, &c| {
+        let digit = char::from_u32(c as u32)?.to_digit(16)?;
+        Some((index << 4) | digit as u16)
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    pub(crate) fn capsule_result_bytes(capsule_guid: efi::Guid, status: efi::Status, payload: &[u8]) -> Vec<u8> {
+        let header_size = mem::size_of::<efi::CapsuleResultVariableHeader>();
+        let header = efi::CapsuleResultVariableHeader {
+            variable_total_size: (header_size + payload.len()) as u32,
+            reserved: 0,
+            capsule_guid,
+            capsule_processed: efi::Time { year: 2024, month: 1, day: 2, ..Default::default() },
+            capsule_status: status,
+        };
+        let mut bytes = vec![0; header_size];
+        unsafe { (bytes.as_mut_ptr() as *mut efi::CapsuleResultVariableHeader).write_unaligned(header) };
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_capsule_result_variable_name() {
+        let name = capsule_result_variable_name(0x0A1F);
+        assert_eq!("Capsule0A1F\0".encode_utf16().collect::<Vec<_>>(), name);
+        assert_eq!(Some(0x0A1F), parse_capsule_result_variable_name(&name));
+        assert_eq!(Some(0x0A1F), parse_capsule_result_variable_name(&name[..11]));
+        assert_eq!(None, parse_capsule_result_variable_name(&"Capsule0A1".encode_utf16().collect::<Vec<_>>()));
+        assert_eq!(None, parse_capsule_result_variable_name(&"Capsule0A1G".encode_utf16().collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn test_capsule_result_try_from() {
+        let guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        let result = CapsuleResult::try_from(capsule_result_bytes(guid, efi::Status::ABORTED, &[])).unwrap();
+        assert_eq!(guid, result.capsule_guid);
+        assert_eq!(efi::Status::ABORTED, result.capsule_status);
+        assert_eq!(2024, result.capsule_processed.year);
+        assert!(result.fmp.is_none());
+
+        let mut bytes = capsule_result_bytes(guid, efi::Status::SUCCESS, &[]);
+        bytes.pop();
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), CapsuleResult::try_from(bytes).map(|_| ()));
+    }
+
+    #[test]
+    fn test_capsule_result_try_from_fmp() {
+        let image_type_id = efi::Guid::from_fields(7, 8, 9, 10, 11, &[12; 6]);
+        let fmp = efi::CapsuleResultVariableFMP::<0> {
+            version: 1,
+            payload_index: 2,
+            update_image_index: 3,
+            update_image_type_id: image_type_id,
+            capsule_file_name_and_target: [],
+        };
+        let mut payload = vec![0; mem::size_of_val(&fmp)];
+        unsafe { (payload.as_mut_ptr() as *mut efi::CapsuleResultVariableFMP).write_unaligned(fmp) };
+        payload.extend("fw.cap\0\0".encode_utf16().flat_map(u16::to_le_bytes));
+
+        let result =
+            CapsuleResult::try_from(capsule_result_bytes(FMP_CAPSULE_GUID, efi::Status::SUCCESS, &payload)).unwrap();
+        let fmp = result.fmp.unwrap();
+        assert_eq!(1, fmp.version);
+        assert_eq!(2, fmp.payload_index);
+        assert_eq!(3, fmp.update_image_index);
+        assert_eq!(image_type_id, fmp.update_image_type_id);
+        assert_eq!("fw.cap", fmp.capsule_file_name);
+        assert_eq!("", fmp.capsule_target);
+    }
+}
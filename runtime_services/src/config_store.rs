@@ -0,0 +1,306 @@
+//! Typed configuration stored in UEFI variables.
+//!
+//! A [`ConfigStore`] keeps the configuration of a component in variables of the vendor GUID shared by the components
+//! of a platform, named `<namespace>.<key>` so the components do not collide. Each value is stored with the version of
+//! its schema, so a value written by an older firmware is migrated when read by a newer one, see [`ConfigValue`].
+//!
+//! ```ignore
+//! let store = ConfigStore::new(&RUNTIME_SERVICES, &PLATFORM_CONFIG_GUID, "Network");
+//! let mut config: NetworkConfig = store.get("Ipv4")?.unwrap_or_default();
+//! config.dhcp = true;
+//! store.set("Ipv4", &config)?;
+//! ```
+//!
+//! With the `serde` feature, [`encode_serde`] and [`decode_serde`] implement the encoding of [`ConfigValue`] with the
+//! compact CBOR format for any serde type.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use r_efi::efi;
+
+use crate::{
+    variable_services::{self, VariableNameIterator},
+    RuntimeServices, RuntimeServicesExt,
+};
+
+/// Separator of the namespace and the key in the variable names.
+pub const NAMESPACE_SEPARATOR: char = '.';
+
+/// Attributes of the variables of a [`ConfigStore`] created with [`ConfigStore::new`].
+pub const DEFAULT_ATTRIBUTES: u32 = efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS;
+
+/// A value of a [`ConfigStore`].
+///
+/// The value is stored with [`ConfigValue::VERSION`], to be incremented each time the encoding of the value changes.
+/// Values stored with an older version are converted by [`ConfigValue::migrate`].
+///
+/// ```ignore
+/// impl ConfigValue for NetworkConfig {
+///     const VERSION: u16 = 2;
+///
+///     fn encode(&self) -> Result<Vec<u8>, efi::Status> {
+///         config_store::encode_serde(self)
+///     }
+///
+///     fn decode(data: &[u8]) -> Result<Self, efi::Status> {
+///         config_store::decode_serde(data)
+///     }
+///
+///     fn migrate(version: u16, data: &[u8]) -> Result<Self, efi::Status> {
+///         match version {
+///             1 => config_store::decode_serde::<NetworkConfigV1>(data).map(Self::from),
+///             _ => Err(efi::Status::INCOMPATIBLE_VERSION),
+///         }
+///     }
+/// }
+/// ```
+pub trait ConfigValue: Sized {
+    /// Version of the schema of the value.
+    const VERSION: u16;
+
+    /// Encodes the value, to be stored with [`ConfigValue::VERSION`].
+    fn encode(&self) -> Result<Vec<u8>, efi::Status>;
+
+    /// Decodes a value stored with [`ConfigValue::VERSION`].
+    fn decode(data: &[u8]) -> Result<Self, efi::Status>;
+
+    /// Decodes a value stored with an older *version*.
+    ///
+    /// Returns `efi::Status::INCOMPATIBLE_VERSION` by default, for values without older versions.
+    fn migrate(version: u16, data: &[u8]) -> Result<Self, efi::Status> {
+        let _ = (version, data);
+        Err(efi::Status::INCOMPATIBLE_VERSION)
+    }
+}
+
+/// Encodes *value* with CBOR, for [`ConfigValue::encode`].
+#[cfg(any(test, feature = "serde"))]
+pub fn encode_serde<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, efi::Status> {
+    let mut data = Vec::new();
+    ciborium::into_writer(value, &mut data).map_err(|_| efi::Status::INVALID_PARAMETER)?;
+    Ok(data)
+}
+
+/// Decodes a value encoded by [`encode_serde`], for [`ConfigValue::decode`] and [`ConfigValue::migrate`].
+///
+/// Returns `efi::Status::VOLUME_CORRUPTED` if *data* is not a valid encoding of a `T`.
+#[cfg(any(test, feature = "serde"))]
+pub fn decode_serde<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, efi::Status> {
+    ciborium::from_reader(data).map_err(|_| efi::Status::VOLUME_CORRUPTED)
+}
+
+/// Configuration values of a namespace, stored in UEFI variables of a vendor GUID.
+///
+/// The variables hold the little endian version of the value followed by its encoding.
+#[derive(Debug)]
+pub struct ConfigStore<'a, R: RuntimeServices + ?Sized> {
+    runtime_services: &'a R,
+    vendor_guid: efi::Guid,
+    namespace: &'a str,
+    attributes: u32,
+}
+
+impl<'a, R: RuntimeServices + ?Sized> ConfigStore<'a, R> {
+    /// Create a store of the values of *namespace*, in variables of *vendor_guid* with [`DEFAULT_ATTRIBUTES`].
+    ///
+    /// *namespace* must not be empty nor contain [`NAMESPACE_SEPARATOR`], the functions of the store return
+    /// `efi::Status::INVALID_PARAMETER` otherwise.
+    pub fn new(runtime_services: &'a R, vendor_guid: &efi::Guid, namespace: &'a str) -> Self {
+        Self { runtime_services, vendor_guid: *vendor_guid, namespace, attributes: DEFAULT_ATTRIBUTES }
+    }
+
+    /// Sets the attributes the values are written with, e.g. to make them accessible at runtime.
+    pub fn with_attributes(mut self, attributes: u32) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Returns the null-terminated UCS-2 name of the variable of *key*.
+    pub fn variable_name(&self, key: &str) -> Result<Vec<u16>, efi::Status> {
+        if self.namespace.is_empty() || self.namespace.contains(NAMESPACE_SEPARATOR) || key.is_empty() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        variable_services::variable_name(&[self.namespace, key].join(&NAMESPACE_SEPARATOR.to_string()))
+    }
+
+    /// Gets the value of *key*, or None if it is not set.
+    ///
+    /// A value stored with an older version is migrated with [`ConfigValue::migrate`], it is only written back with
+    /// the new version by [`ConfigStore::set`]. A value stored with a newer version returns
+    /// `efi::Status::INCOMPATIBLE_VERSION`.
+    pub fn get<T: ConfigValue>(&self, key: &str) -> Result<Option<T>, efi::Status> {
+        let data =
+            match self.runtime_services.get_variable::<Vec<u8>>(&self.variable_name(key)?, &self.vendor_guid, None) {
+                Ok((data, _)) => data,
+                Err(efi::Status::NOT_FOUND) => return Ok(None),
+                Err(status) => return Err(status),
+            };
+        let Some((version, data)) = data.split_first_chunk::<2>() else {
+            return Err(efi::Status::VOLUME_CORRUPTED);
+        };
+        match u16::from_le_bytes(*version) {
+            version if version == T::VERSION => T::decode(data).map(Some),
+            version if version < T::VERSION => T::migrate(version, data).map(Some),
+            _ => Err(efi::Status::INCOMPATIBLE_VERSION),
+        }
+    }
+
+    /// Sets the value of *key*, with the current version of `T`.
+    pub fn set<T: ConfigValue>(&self, key: &str, value: &T) -> Result<(), efi::Status> {
+        let mut data = T::VERSION.to_le_bytes().to_vec();
+        data.extend(value.encode()?);
+        self.runtime_services.set_variable(&self.variable_name(key)?, &self.vendor_guid, self.attributes, &data)
+    }
+
+    /// Deletes the value of *key*. Deleting a value that is not set is not an error.
+    pub fn delete(&self, key: &str) -> Result<(), efi::Status> {
+        match self.runtime_services.set_variable(&self.variable_name(key)?, &self.vendor_guid, 0, &Vec::new()) {
+            Err(efi::Status::NOT_FOUND) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Returns the keys of the values set in the namespace, in the order of the variable store.
+    pub fn keys(&self) -> Result<Vec<String>, efi::Status> {
+        let prefix = [self.namespace, &NAMESPACE_SEPARATOR.to_string()].concat();
+        let mut keys = Vec::new();
+        let mut iter = VariableNameIterator::new_from_first(self.runtime_services);
+        while let Some(variable) = iter.next()? {
+            if variable.namespace != self.vendor_guid {
+                continue;
+            }
+            let name = &variable.name[..variable.name.iter().position(|&c| c == 0).unwrap_or(variable.name.len())];
+            if let Some(key) =
+                String::from_utf16(name).ok().and_then(|name| name.strip_prefix(&prefix).map(String::from))
+            {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::FakeRuntimeServices;
+
+    const VENDOR_GUID: efi::Guid =
+        efi::Guid::from_fields(0x1b838190, 0x4625, 0x4ead, 0xab, 0xc9, &[0xcd, 0x5e, 0x6a, 0xf1, 0x8f, 0xe0]);
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct NetworkConfigV1 {
+        dhcp: bool,
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct NetworkConfig {
+        dhcp: bool,
+        retries: u8,
+        host_name: String,
+    }
+
+    impl ConfigValue for NetworkConfigV1 {
+        const VERSION: u16 = 1;
+
+        fn encode(&self) -> Result<Vec<u8>, efi::Status> {
+            encode_serde(self)
+        }
+
+        fn decode(data: &[u8]) -> Result<Self, efi::Status> {
+            decode_serde(data)
+        }
+    }
+
+    impl ConfigValue for NetworkConfig {
+        const VERSION: u16 = 2;
+
+        fn encode(&self) -> Result<Vec<u8>, efi::Status> {
+            encode_serde(self)
+        }
+
+        fn decode(data: &[u8]) -> Result<Self, efi::Status> {
+            decode_serde(data)
+        }
+
+        fn migrate(version: u16, data: &[u8]) -> Result<Self, efi::Status> {
+            match version {
+                1 => decode_serde::<NetworkConfigV1>(data).map(|v1| Self {
+                    dhcp: v1.dhcp,
+                    retries: 3,
+                    host_name: String::from("uefi"),
+                }),
+                _ => Err(efi::Status::INCOMPATIBLE_VERSION),
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        let rs = FakeRuntimeServices::new();
+        let store = ConfigStore::new(&rs, &VENDOR_GUID, "Network");
+        assert_eq!(Ok(None), store.get::<NetworkConfig>("Ipv4"));
+
+        let config = NetworkConfig { dhcp: true, retries: 5, host_name: String::from("host") };
+        store.set("Ipv4", &config).unwrap();
+        assert_eq!(Ok(Some(config)), store.get("Ipv4"));
+
+        let variable = rs.variables()[0].clone();
+        assert_eq!(variable_services::variable_name("Network.Ipv4").unwrap()[..12], variable.name);
+        assert_eq!((VENDOR_GUID, DEFAULT_ATTRIBUTES), (variable.namespace, variable.attributes));
+        assert_eq!([2, 0], variable.data[..2]);
+
+        // Corrupted values are reported.
+        rs.add_variable(&store.variable_name("Empty").unwrap(), &VENDOR_GUID, DEFAULT_ATTRIBUTES, &[2]);
+        assert_eq!(Err(efi::Status::VOLUME_CORRUPTED), store.get::<NetworkConfig>("Empty"));
+        rs.add_variable(&store.variable_name("Garbage").unwrap(), &VENDOR_GUID, DEFAULT_ATTRIBUTES, &[2, 0, 0xFF]);
+        assert_eq!(Err(efi::Status::VOLUME_CORRUPTED), store.get::<NetworkConfig>("Garbage"));
+
+        store.delete("Ipv4").unwrap();
+        store.delete("Ipv4").unwrap();
+        assert_eq!(Ok(None), store.get::<NetworkConfig>("Ipv4"));
+    }
+
+    #[test]
+    fn test_migration() {
+        let rs = FakeRuntimeServices::new();
+        let store = ConfigStore::new(&rs, &VENDOR_GUID, "Network");
+        store.set("Ipv4", &NetworkConfigV1 { dhcp: true }).unwrap();
+
+        let migrated = NetworkConfig { dhcp: true, retries: 3, host_name: String::from("uefi") };
+        assert_eq!(Ok(Some(migrated)), store.get("Ipv4"));
+        // The value is only written with the new version when set.
+        assert_eq!([1, 0], rs.variables()[0].data[..2]);
+
+        // Values of a newer firmware can not be read.
+        store.set("Ipv4", &NetworkConfig { dhcp: false, retries: 0, host_name: String::new() }).unwrap();
+        assert_eq!(Err(efi::Status::INCOMPATIBLE_VERSION), store.get::<NetworkConfigV1>("Ipv4"));
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let rs = FakeRuntimeServices::new();
+        let network = ConfigStore::new(&rs, &VENDOR_GUID, "Network");
+        let storage = ConfigStore::new(&rs, &VENDOR_GUID, "Storage").with_attributes(efi::VARIABLE_BOOTSERVICE_ACCESS);
+        let other_vendor = ConfigStore::new(&rs, &efi::Guid::from_bytes(&[0; 16]), "Network");
+
+        network.set("Ipv4", &NetworkConfigV1 { dhcp: true }).unwrap();
+        storage.set("Ipv4", &NetworkConfigV1 { dhcp: false }).unwrap();
+        other_vendor.set("Ipv6", &NetworkConfigV1 { dhcp: false }).unwrap();
+        network.set("Ipv6", &NetworkConfigV1 { dhcp: false }).unwrap();
+
+        assert_eq!(Ok(Some(NetworkConfigV1 { dhcp: true })), network.get("Ipv4"));
+        assert_eq!(Ok(Some(NetworkConfigV1 { dhcp: false })), storage.get("Ipv4"));
+        assert_eq!(efi::VARIABLE_BOOTSERVICE_ACCESS, rs.variables()[1].attributes);
+        assert_eq!(Ok(vec![String::from("Ipv4"), String::from("Ipv6")]), network.keys());
+        assert_eq!(Ok(vec![String::from("Ipv4")]), storage.keys());
+
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), network.get::<NetworkConfigV1>(""));
+        let invalid = ConfigStore::new(&rs, &VENDOR_GUID, "Net.work");
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), invalid.set("Ipv4", &NetworkConfigV1 { dhcp: true }));
+    }
+}
@@ -0,0 +1,218 @@
+//! Typed accessors of the global variables defined by the UEFI specification.
+//!
+//! Each [`GlobalVariable`] constant pairs the name of a variable with its attributes and the type of its data, under
+//! [`GLOBAL_VARIABLE_GUID`], so a caller can not read or write it with the wrong name, GUID or data layout.
+//!
+//! ```ignore
+//! let timeout = global_variables::TIMEOUT.get(&RUNTIME_SERVICES)?;
+//! global_variables::TIMEOUT.set(&RUNTIME_SERVICES, &(timeout + 1))?;
+//! let lang = global_variables::PLATFORM_LANG.get(&RUNTIME_SERVICES)?;
+//! ```
+//!
+//! UEFI Spec Documentation: [3.3. Globally Defined Variables](https://uefi.org/specs/UEFI/2.10/03_Boot_Manager.html#globally-defined-variables)
+
+use alloc::{string::String, vec::Vec};
+use core::marker::PhantomData;
+
+use r_efi::efi;
+
+use crate::{os_indications::GLOBAL_VARIABLE_GUID, ucs2, RuntimeServices, RuntimeServicesExt};
+
+const NV_BS_RT: u32 = efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_RUNTIME_ACCESS;
+const BS_RT: u32 = efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_RUNTIME_ACCESS;
+
+/// The `Boot####` option selected for the current boot.
+pub const BOOT_CURRENT: GlobalVariable<u16> = GlobalVariable::new(ucs2!("BootCurrent"), BS_RT);
+
+/// The firmware boot manager timeout in seconds before the default boot selection is started, 0xFFFF to wait for the
+/// user.
+pub const TIMEOUT: GlobalVariable<u16> = GlobalVariable::new(ucs2!("Timeout"), NV_BS_RT);
+
+/// The device path of the default input console.
+pub const CON_IN: GlobalVariable<DevicePathBytes> = GlobalVariable::new(ucs2!("ConIn"), NV_BS_RT);
+
+/// The device path of the default output console.
+pub const CON_OUT: GlobalVariable<DevicePathBytes> = GlobalVariable::new(ucs2!("ConOut"), NV_BS_RT);
+
+/// The device path of the default error output device.
+pub const ERR_OUT: GlobalVariable<DevicePathBytes> = GlobalVariable::new(ucs2!("ErrOut"), NV_BS_RT);
+
+/// The language code the system is configured for, as an RFC 4646 language tag such as `en-US`.
+pub const PLATFORM_LANG: GlobalVariable<String> = GlobalVariable::new(ucs2!("PlatformLang"), NV_BS_RT);
+
+/// Data of a [`GlobalVariable`], converted to and from the bytes of the variable.
+pub trait GlobalVariableData: Sized {
+    /// Returns the bytes of the variable holding *self*.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Returns the data held by the variable *bytes*, `efi::Status::INVALID_PARAMETER` if they are not valid.
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, efi::Status>;
+}
+
+impl GlobalVariableData for u16 {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, efi::Status> {
+        let bytes = <[u8; 2]>::try_from(bytes.as_slice()).map_err(|_| efi::Status::INVALID_PARAMETER)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+}
+
+/// A language tag is stored as a null-terminated ASCII string.
+impl GlobalVariableData for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.bytes().chain([0]).collect()
+    }
+
+    fn from_bytes(mut bytes: Vec<u8>) -> Result<Self, efi::Status> {
+        if bytes.pop() != Some(0) || !bytes.iter().all(|b| b.is_ascii() && *b != 0) {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        String::from_utf8(bytes).map_err(|_| efi::Status::INVALID_PARAMETER)
+    }
+}
+
+/// The bytes of a device path, possibly of several instances, ending with an end of entire device path node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevicePathBytes(Vec<u8>);
+
+impl DevicePathBytes {
+    /// Create a device path from its *bytes*.
+    ///
+    /// Returns `efi::Status::INVALID_PARAMETER` if a node is shorter than its header or overruns the bytes, or if the
+    /// last node is not an end of entire device path node.
+    pub fn new(bytes: Vec<u8>) -> Result<Self, efi::Status> {
+        let mut offset = 0;
+        loop {
+            let header = bytes.get(offset..offset + 4).ok_or(efi::Status::INVALID_PARAMETER)?;
+            let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+            if length < 4 || offset + length > bytes.len() {
+                return Err(efi::Status::INVALID_PARAMETER);
+            }
+            offset += length;
+            if header[0] == efi::protocols::device_path::TYPE_END
+                && header[1] == efi::protocols::device_path::End::SUBTYPE_ENTIRE
+            {
+                return match offset == bytes.len() {
+                    true => Ok(Self(bytes)),
+                    false => Err(efi::Status::INVALID_PARAMETER),
+                };
+            }
+        }
+    }
+
+    /// Returns the bytes of the device path.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl GlobalVariableData for DevicePathBytes {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, efi::Status> {
+        Self::new(bytes)
+    }
+}
+
+/// A variable of [`GLOBAL_VARIABLE_GUID`] holding data *T*.
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalVariable<T> {
+    name: &'static [u16],
+    attributes: u32,
+    _data: PhantomData<fn() -> T>,
+}
+
+impl<T: GlobalVariableData> GlobalVariable<T> {
+    const fn new(name: &'static [u16], attributes: u32) -> Self {
+        Self { name, attributes, _data: PhantomData }
+    }
+
+    /// Returns the null-terminated UCS-2 name of the variable.
+    pub const fn name(&self) -> &'static [u16] {
+        self.name
+    }
+
+    /// Returns the attributes the variable is set with.
+    pub const fn attributes(&self) -> u32 {
+        self.attributes
+    }
+
+    /// Gets the data of the variable.
+    ///
+    /// Returns `efi::Status::NOT_FOUND` if the variable is not set, and `efi::Status::INVALID_PARAMETER` if its bytes
+    /// are not valid data *T*.
+    pub fn get<R: RuntimeServices + ?Sized>(&self, runtime_services: &R) -> Result<T, efi::Status> {
+        let (bytes, _) = runtime_services.get_variable::<Vec<u8>>(self.name, &GLOBAL_VARIABLE_GUID, None)?;
+        T::from_bytes(bytes)
+    }
+
+    /// Sets the variable to *data*, with the attributes of the variable.
+    pub fn set<R: RuntimeServices + ?Sized>(&self, runtime_services: &R, data: &T) -> Result<(), efi::Status> {
+        runtime_services.set_variable(self.name, &GLOBAL_VARIABLE_GUID, self.attributes, &data.to_bytes())
+    }
+
+    /// Deletes the variable, `efi::Status::NOT_FOUND` if it is not set.
+    pub fn delete<R: RuntimeServices + ?Sized>(&self, runtime_services: &R) -> Result<(), efi::Status> {
+        runtime_services.set_variable(self.name, &GLOBAL_VARIABLE_GUID, self.attributes, &Vec::<u8>::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::FakeRuntimeServices;
+    use alloc::string::ToString;
+
+    // A PCI node followed by an end of instance node, then a second PCI node and the end of entire device path node.
+    const TWO_INSTANCES: [u8; 24] =
+        [1, 1, 6, 0, 0, 0x1F, 0x7F, 0x01, 4, 0, 1, 1, 6, 0, 0, 0x1C, 0x7F, 0xFF, 4, 0, 0xAA, 0xBB, 0xCC, 0xDD];
+
+    #[test]
+    fn test_get_and_set() {
+        let rs = FakeRuntimeServices::new();
+        assert_eq!(Err(efi::Status::NOT_FOUND), TIMEOUT.get(&rs));
+
+        TIMEOUT.set(&rs, &5).unwrap();
+        BOOT_CURRENT.set(&rs, &0x0003).unwrap();
+        PLATFORM_LANG.set(&rs, &"en-US".to_string()).unwrap();
+        let con_out = DevicePathBytes::new(TWO_INSTANCES[..20].to_vec()).unwrap();
+        CON_OUT.set(&rs, &con_out).unwrap();
+
+        assert_eq!(Ok(5), TIMEOUT.get(&rs));
+        assert_eq!(Ok(3), BOOT_CURRENT.get(&rs));
+        assert_eq!(Ok("en-US".to_string()), PLATFORM_LANG.get(&rs));
+        assert_eq!(Ok(con_out), CON_OUT.get(&rs));
+        assert_eq!(Err(efi::Status::NOT_FOUND), CON_IN.get(&rs));
+
+        let variables = rs.variables();
+        assert_eq!(TIMEOUT.name().split_last().unwrap().1, &variables[0].name[..]);
+        assert_eq!((NV_BS_RT, &[5, 0][..]), (variables[0].attributes, &variables[0].data[..]));
+        assert_eq!((BS_RT, GLOBAL_VARIABLE_GUID), (variables[1].attributes, variables[1].namespace));
+        assert_eq!(b"en-US\0", &variables[2].data[..]);
+        drop(variables);
+
+        TIMEOUT.delete(&rs).unwrap();
+        assert_eq!(Err(efi::Status::NOT_FOUND), TIMEOUT.get(&rs));
+    }
+
+    #[test]
+    fn test_invalid_data_is_rejected() {
+        let rs = FakeRuntimeServices::new();
+        rs.add_variable(ucs2!("Timeout"), &GLOBAL_VARIABLE_GUID, NV_BS_RT, &[5, 0, 0]);
+        rs.add_variable(ucs2!("PlatformLang"), &GLOBAL_VARIABLE_GUID, NV_BS_RT, b"en-US");
+        rs.add_variable(ucs2!("ConIn"), &GLOBAL_VARIABLE_GUID, NV_BS_RT, &TWO_INSTANCES[..10]);
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), TIMEOUT.get(&rs));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), PLATFORM_LANG.get(&rs));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), CON_IN.get(&rs));
+
+        // Bytes after the end of entire device path node, a zero length node.
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePathBytes::new(TWO_INSTANCES.to_vec()));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePathBytes::new(vec![1, 1, 0, 0, 0x7F, 0xFF, 4, 0]));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), DevicePathBytes::new(Vec::new()));
+    }
+}
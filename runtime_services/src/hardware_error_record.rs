@@ -0,0 +1,337 @@
+use alloc::{string::String, vec::Vec};
+use r_efi::efi;
+
+/// Namespace of the `HwErrRecXXXX` variables (EFI_HARDWARE_ERROR_VARIABLE).
+pub const HARDWARE_ERROR_VARIABLE_GUID: efi::Guid = efi::HARDWARE_ERROR_VARIABLE_GUID;
+
+/// Attributes of the `HwErrRecXXXX` variables, including `EFI_VARIABLE_HARDWARE_ERROR_RECORD`.
+pub const HARDWARE_ERROR_RECORD_ATTRIBUTES: u32 = efi::VARIABLE_NON_VOLATILE
+    | efi::VARIABLE_BOOTSERVICE_ACCESS
+    | efi::VARIABLE_RUNTIME_ACCESS
+    | efi::VARIABLE_HARDWARE_ERROR_RECORD;
+
+/// Signature at the start of a Common Platform Error Record.
+pub const CPER_SIGNATURE: [u8; 4] = *b"CPER";
+
+/// Size of the CPER record header.
+pub const CPER_RECORD_HEADER_SIZE: usize = 128;
+
+/// Size of a CPER section descriptor, the descriptors follow the record header.
+pub const CPER_SECTION_DESCRIPTOR_SIZE: usize = 72;
+
+// Value of the SignatureEnd field of the record header.
+const CPER_SIGNATURE_END: u32 = 0xFFFF_FFFF;
+
+/// Severity of a CPER record or section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ErrorSeverity(pub u32);
+
+impl ErrorSeverity {
+    /// The error is not corrected but the system may continue.
+    pub const RECOVERABLE: ErrorSeverity = ErrorSeverity(0);
+    /// The error is not corrected and the system can not continue.
+    pub const FATAL: ErrorSeverity = ErrorSeverity(1);
+    /// The error was corrected by the hardware or the firmware.
+    pub const CORRECTED: ErrorSeverity = ErrorSeverity(2);
+    /// The record only carries information.
+    pub const INFORMATIONAL: ErrorSeverity = ErrorSeverity(3);
+}
+
+/// Header of a Common Platform Error Record (EFI_COMMON_ERROR_RECORD_HEADER)
+///
+/// The optional fields are None when their bit of the validation bits is clear.
+///
+/// UEFI Spec Documentation: [N.2.1. Record Header](https://uefi.org/specs/UEFI/2.10/Apx_N_Common_Platform_Error_Record.html#record-header)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CperRecordHeader {
+    /// Revision of the record format
+    pub revision: u16,
+    /// Number of sections of the record
+    pub section_count: u16,
+    /// Severity of the most severe section
+    pub error_severity: ErrorSeverity,
+    /// Validation bits of the optional fields
+    pub validation_bits: u32,
+    /// Size of the whole record in bytes
+    pub record_length: u32,
+    /// Time at which the error was recorded, in the CPER timestamp format
+    pub timestamp: Option<u64>,
+    /// Identifier of the platform which recorded the error
+    pub platform_id: Option<efi::Guid>,
+    /// Identifier of the partition which recorded the error
+    pub partition_id: Option<efi::Guid>,
+    /// Identifier of the creator of the record
+    pub creator_id: efi::Guid,
+    /// Type of the notification of the error, such as machine check or corrected machine check
+    pub notification_type: efi::Guid,
+    /// Unique identifier of the record on the platform
+    pub record_id: u64,
+    /// Record flags, such as recovered or previous error
+    pub flags: u32,
+    /// Reserved for the creator of the record
+    pub persistence_information: u64,
+}
+
+/// Descriptor of a section of a Common Platform Error Record (EFI_ERROR_SECTION_DESCRIPTOR)
+///
+/// UEFI Spec Documentation: [N.2.2. Section Descriptor](https://uefi.org/specs/UEFI/2.10/Apx_N_Common_Platform_Error_Record.html#section-descriptor)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CperSectionDescriptor {
+    /// Offset of the section from the start of the record
+    pub section_offset: u32,
+    /// Size of the section in bytes
+    pub section_length: u32,
+    /// Revision of the section format
+    pub revision: u16,
+    /// Validation bits of the FRU fields
+    pub validation_bits: u8,
+    /// Section flags, such as primary or containment warning
+    pub flags: u32,
+    /// Type of the section, such as processor or memory error
+    pub section_type: efi::Guid,
+    /// Identifier of the Field Replaceable Unit
+    pub fru_id: Option<efi::Guid>,
+    /// Severity of the section
+    pub section_severity: ErrorSeverity,
+    /// Text describing the Field Replaceable Unit
+    pub fru_text: Option<String>,
+}
+
+/// Typed content of a `HwErrRecXXXX` variable, a Common Platform Error Record
+///
+/// UEFI Spec Documentation: [N.2. Error Record Format](https://uefi.org/specs/UEFI/2.10/Apx_N_Common_Platform_Error_Record.html#error-record-format)
+#[derive(Debug, Clone)]
+pub struct HardwareErrorRecord {
+    /// Header of the record
+    pub header: CperRecordHeader,
+    /// Descriptors of the sections of the record
+    pub sections: Vec<CperSectionDescriptor>,
+    data: Vec<u8>,
+}
+
+impl HardwareErrorRecord {
+    /// Returns the data of the section at *index*, to be parsed according to its section type.
+    pub fn section_data(&self, index: usize) -> Option<&[u8]> {
+        let section = self.sections.get(index)?;
+        let offset = section.section_offset as usize;
+        Some(&self.data[offset..offset + section.section_length as usize])
+    }
+
+    /// Returns the raw bytes of the record.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl TryFrom<Vec<u8>> for HardwareErrorRecord {
+    type Error = efi::Status;
+
+    fn try_from(mut value: Vec<u8>) -> Result<Self, Self::Error> {
+        if value.len() < CPER_RECORD_HEADER_SIZE
+            || value[..4] != CPER_SIGNATURE
+            || u32_at(&value, 6) != CPER_SIGNATURE_END
+        {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        let validation_bits = u32_at(&value, 16);
+        let header = CperRecordHeader {
+            revision: u16_at(&value, 4),
+            section_count: u16_at(&value, 10),
+            error_severity: ErrorSeverity(u32_at(&value, 12)),
+            validation_bits,
+            record_length: u32_at(&value, 20),
+            timestamp: (validation_bits & 0x2 != 0).then(|| u64_at(&value, 24)),
+            platform_id: (validation_bits & 0x1 != 0).then(|| guid_at(&value, 32)),
+            partition_id: (validation_bits & 0x4 != 0).then(|| guid_at(&value, 48)),
+            creator_id: guid_at(&value, 64),
+            notification_type: guid_at(&value, 80),
+            record_id: u64_at(&value, 96),
+            flags: u32_at(&value, 104),
+            persistence_information: u64_at(&value, 108),
+        };
+
+        let record_length = header.record_length as usize;
+        let descriptors_end = CPER_RECORD_HEADER_SIZE + header.section_count as usize * CPER_SECTION_DESCRIPTOR_SIZE;
+        if record_length < descriptors_end || record_length > value.len() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        value.truncate(record_length);
+
+        let sections = (CPER_RECORD_HEADER_SIZE..descriptors_end)
+            .step_by(CPER_SECTION_DESCRIPTOR_SIZE)
+            .map(|offset| parse_section_descriptor(&value, offset))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { header, sections, data: value })
+    }
+}
+
+fn parse_section_descriptor(record: &[u8], offset: usize) -> Result<CperSectionDescriptor, efi::Status> {
+    let descriptor = &record[offset..offset + CPER_SECTION_DESCRIPTOR_SIZE];
+    let section_offset = u32_at(descriptor, 0);
+    let section_length = u32_at(descriptor, 4);
+    match (section_offset as usize).checked_add(section_length as usize) {
+        Some(end) if end <= record.len() => (),
+        _ => return Err(efi::Status::INVALID_PARAMETER),
+    }
+
+    let validation_bits = descriptor[10];
+    let fru_text = &descriptor[52..72];
+    let fru_text = &fru_text[..fru_text.iter().position(|&c| c == 0).unwrap_or(fru_text.len())];
+    Ok(CperSectionDescriptor {
+        section_offset,
+        section_length,
+        revision: u16_at(descriptor, 8),
+        validation_bits,
+        flags: u32_at(descriptor, 12),
+        section_type: guid_at(descriptor, 16),
+        fru_id: (validation_bits & 0x1 != 0).then(|| guid_at(descriptor, 32)),
+        section_severity: ErrorSeverity(u32_at(descriptor, 48)),
+        fru_text: (validation_bits & 0x2 != 0).then(|| String::from_utf8_lossy(fru_text).into_owned()),
+    })
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn guid_at(data: &[u8], offset: usize) -> efi::Guid {
+    efi::Guid::from_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+/// Returns the null-terminated name of the `HwErrRecXXXX` variable at *index*.
+pub fn hardware_error_record_variable_name(index: u16) -> [u16; 13] {
+    let mut name = [0; 13];
+    for (dst, src) in name.iter_mut().zip("HwErrRec".encode_utf16()) {
+        *dst = src;
+    }
+    for i in 0..4 {
+        let digit = (index >> ((3 - i) * 4)) & 0xF;
+        name[8 + i] = if digit < 10 { '0' as u16 + digit } else { 'A' as u16 + digit - 10 };
+    }
+    name
+}
+
+/// Parses the index out of a `HwErrRecXXXX` name.
+pub fn parse_hardware_error_record_variable_name(name: &[u16]) -> Option<u16> {
+    let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+    if name.len() != 12 || !name.starts_with(&hardware_error_record_variable_name(0)[..8]) {
+        return None;
+    }
+    name[8..].iter().try_fold(0u16, |index, &c| {
+        let digit = char::from_u32(c as u32)?.to_digit(16)?;
+        Some((index << 4) | digit as u16)
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    pub(crate) const PROCESSOR_GENERIC_SECTION_GUID: efi::Guid =
+        efi::Guid::from_fields(0x9876ccad, 0x47b4, 0x4bdb, 0xb6, 0x5e, &[0x16, 0xf1, 0x93, 0xc4, 0xf3, 0xdb]);
+
+    // Builds a record with one section per payload, the first one with a FRU text.
+    pub(crate) fn hardware_error_record_bytes(record_id: u64, payloads: &[&[u8]]) -> Vec<u8> {
+        let descriptors_end = CPER_RECORD_HEADER_SIZE + payloads.len() * CPER_SECTION_DESCRIPTOR_SIZE;
+        let record_length = descriptors_end + payloads.iter().map(|p| p.len()).sum::<usize>();
+
+        let mut bytes = Vec::with_capacity(record_length);
+        bytes.extend(CPER_SIGNATURE);
+        bytes.extend(0x0101_u16.to_le_bytes());
+        bytes.extend(CPER_SIGNATURE_END.to_le_bytes());
+        bytes.extend((payloads.len() as u16).to_le_bytes());
+        bytes.extend(ErrorSeverity::CORRECTED.0.to_le_bytes());
+        bytes.extend(0x2_u32.to_le_bytes());
+        bytes.extend((record_length as u32).to_le_bytes());
+        bytes.extend(0x20_24_01_02_00_03_02_01_u64.to_le_bytes());
+        bytes.extend([0; 32]);
+        bytes.extend(efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]).as_bytes());
+        bytes.extend([0; 16]);
+        bytes.extend(record_id.to_le_bytes());
+        bytes.extend([0; 24]);
+
+        let mut section_offset = descriptors_end;
+        for (idx, payload) in payloads.iter().enumerate() {
+            bytes.extend((section_offset as u32).to_le_bytes());
+            bytes.extend((payload.len() as u32).to_le_bytes());
+            bytes.extend(0x0300_u16.to_le_bytes());
+            bytes.extend([if idx == 0 { 0x2 } else { 0 }, 0]);
+            bytes.extend(0x1_u32.to_le_bytes());
+            bytes.extend(PROCESSOR_GENERIC_SECTION_GUID.as_bytes());
+            bytes.extend([0; 16]);
+            bytes.extend(ErrorSeverity::CORRECTED.0.to_le_bytes());
+            bytes.extend(*b"CPU0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0");
+            section_offset += payload.len();
+        }
+        for payload in payloads {
+            bytes.extend(*payload);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_hardware_error_record_variable_name() {
+        let name = hardware_error_record_variable_name(0x0A1F);
+        assert_eq!("HwErrRec0A1F\0".encode_utf16().collect::<Vec<_>>(), name);
+        assert_eq!(Some(0x0A1F), parse_hardware_error_record_variable_name(&name));
+        assert_eq!(None, parse_hardware_error_record_variable_name(&"HwErrRec0A1".encode_utf16().collect::<Vec<_>>()));
+        assert_eq!(
+            None,
+            parse_hardware_error_record_variable_name(&"HwErrRecSupport".encode_utf16().collect::<Vec<_>>())
+        );
+    }
+
+    #[test]
+    fn test_hardware_error_record_try_from() {
+        let record = HardwareErrorRecord::try_from(hardware_error_record_bytes(7, &[b"first", b"second"])).unwrap();
+        assert_eq!(2, record.header.section_count);
+        assert_eq!(ErrorSeverity::CORRECTED, record.header.error_severity);
+        assert_eq!(Some(0x20_24_01_02_00_03_02_01), record.header.timestamp);
+        assert_eq!(None, record.header.platform_id);
+        assert_eq!(efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]), record.header.creator_id);
+        assert_eq!(7, record.header.record_id);
+
+        assert_eq!(PROCESSOR_GENERIC_SECTION_GUID, record.sections[0].section_type);
+        assert_eq!(Some("CPU0"), record.sections[0].fru_text.as_deref());
+        assert_eq!(None, record.sections[1].fru_text);
+        assert_eq!(Some(&b"first"[..]), record.section_data(0));
+        assert_eq!(Some(&b"second"[..]), record.section_data(1));
+        assert_eq!(None, record.section_data(2));
+    }
+
+    #[test]
+    fn test_hardware_error_record_try_from_malformed() {
+        let invalid = |bytes: Vec<u8>| HardwareErrorRecord::try_from(bytes).map(|_| ());
+
+        let mut bytes = hardware_error_record_bytes(7, &[b"first"]);
+        bytes.pop();
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), invalid(bytes));
+
+        let mut bytes = hardware_error_record_bytes(7, &[b"first"]);
+        bytes[0] = b'X';
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), invalid(bytes));
+
+        // The section ends after the record.
+        let mut bytes = hardware_error_record_bytes(7, &[b"first"]);
+        bytes[CPER_RECORD_HEADER_SIZE + 4] += 1;
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), invalid(bytes));
+
+        // Trailing bytes after the record length are ignored.
+        let mut bytes = hardware_error_record_bytes(7, &[b"first"]);
+        bytes.extend([0xFF; 3]);
+        assert_eq!(CPER_RECORD_HEADER_SIZE + CPER_SECTION_DESCRIPTOR_SIZE + 5, {
+            HardwareErrorRecord::try_from(bytes).unwrap().as_bytes().len()
+        });
+    }
+}
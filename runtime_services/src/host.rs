@@ -0,0 +1,189 @@
+//! Runtime services emulated on the host, with the non-volatile variables persisted to a file.
+//!
+//! [`HostRuntimeServices`] keeps its variables in a [`FakeRuntimeServices`] and writes the non-volatile ones to its
+//! file after each successful write, in the [`variable_backup`](crate::variable_backup) format, so a host-based
+//! emulation finds its variables again when restarted. The time is the time of the host, in UTC.
+//!
+//! ```ignore
+//! let rs = HostRuntimeServices::open("variables.bin")?;
+//! rs.set_variable(&name, &VENDOR_GUID, attributes, &data)?;
+//! ```
+
+extern crate std;
+
+use alloc::vec::Vec;
+use core::{
+    cell::Ref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+use r_efi::efi;
+
+use crate::{
+    capsule_services::CapsuleCapabilities,
+    testing::{FakeRuntimeServices, FakeVariable},
+    time,
+    variable_backup::ImportPolicy,
+    variable_services::{GetVariableStatus, VariableInfo},
+    RuntimeServices, RuntimeServicesExt,
+};
+
+/// Runtime services emulated on the host, see the [module](self) documentation.
+#[derive(Debug)]
+pub struct HostRuntimeServices {
+    variables: FakeRuntimeServices,
+    path: PathBuf,
+    // The file of the temporary runtime services is removed when they are dropped.
+    temporary: bool,
+}
+
+impl HostRuntimeServices {
+    /// Opens the runtime services persisting their variables to *path*, with the variables already in the file.
+    ///
+    /// Returns `efi::Status::DEVICE_ERROR` if the file exists but can not be read, and the errors of
+    /// [`RuntimeServices::import_variables`] if it is not a valid backup.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, efi::Status> {
+        let path = path.into();
+        let variables = FakeRuntimeServices::new();
+        match fs::read(&path) {
+            Ok(backup) => _ = variables.import_variables(&backup, ImportPolicy::Overwrite)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(_) => return Err(efi::Status::DEVICE_ERROR),
+        }
+        Ok(Self { variables, path, temporary: false })
+    }
+
+    /// Creates runtime services with no variables, persisting them to a new file of the temporary directory which is
+    /// removed when they are dropped.
+    pub fn temporary() -> Result<Self, efi::Status> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = std::format!("host_variables_{}_{}.bin", process::id(), COUNT.fetch_add(1, Ordering::SeqCst));
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        Ok(Self { variables: FakeRuntimeServices::new(), path, temporary: true })
+    }
+
+    /// Returns the path of the file the variables are persisted to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the variables currently in the store, volatile ones included.
+    pub fn variables(&self) -> Ref<'_, Vec<FakeVariable>> {
+        self.variables.variables()
+    }
+
+    fn persist(&self) -> Result<(), efi::Status> {
+        let backup = self.variables.export_variables(|v| v.attributes & efi::VARIABLE_NON_VOLATILE != 0)?;
+        fs::write(&self.path, backup).map_err(|_| efi::Status::DEVICE_ERROR)
+    }
+}
+
+impl Drop for HostRuntimeServices {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl RuntimeServices for HostRuntimeServices {
+    unsafe fn set_variable_unchecked(
+        &self,
+        name: &mut [u16],
+        namespace: &efi::Guid,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), efi::Status> {
+        self.variables.set_variable_unchecked(name, namespace, attributes, data)?;
+        self.persist()
+    }
+
+    unsafe fn get_variable_unchecked(
+        &self,
+        name: &mut [u16],
+        namespace: &efi::Guid,
+        data: Option<&mut [u8]>,
+    ) -> GetVariableStatus {
+        self.variables.get_variable_unchecked(name, namespace, data)
+    }
+
+    unsafe fn get_next_variable_name_unchecked(
+        &self,
+        prev_name: &[u16],
+        prev_namespace: &efi::Guid,
+        next_name: &mut Vec<u16>,
+        next_namespace: &mut efi::Guid,
+    ) -> Result<(), efi::Status> {
+        self.variables.get_next_variable_name_unchecked(prev_name, prev_namespace, next_name, next_namespace)
+    }
+
+    fn query_variable_info(&self, attributes: u32) -> Result<VariableInfo, efi::Status> {
+        self.variables.query_variable_info(attributes)
+    }
+
+    fn query_capsule_capabilities(
+        &self,
+        _capsule_headers: &[&efi::CapsuleHeader],
+    ) -> Result<CapsuleCapabilities, efi::Status> {
+        Err(efi::Status::UNSUPPORTED)
+    }
+
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities), efi::Status> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_err(|_| efi::Status::DEVICE_ERROR)?;
+        let mut time = time::from_unix_seconds(now.as_secs() as i64, efi::UNSPECIFIED_TIMEZONE, 0)?;
+        time.nanosecond = now.subsec_nanos();
+        Ok((time, efi::TimeCapabilities { resolution: 1, accuracy: 0, sets_to_zero: efi::Boolean::FALSE }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NAMESPACE: efi::Guid = efi::Guid::from_fields(0x8e2f1a6b, 0, 0, 0, 0, &[0; 6]);
+    const NON_VOLATILE: u32 = efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS;
+
+    #[test]
+    fn test_non_volatile_variables_are_persisted() {
+        let rs = HostRuntimeServices::temporary().unwrap();
+        rs.set_variable(&[0x41, 0], &NAMESPACE, NON_VOLATILE, &vec![1_u8, 2, 3]).unwrap();
+        rs.set_variable(&[0x42, 0], &NAMESPACE, efi::VARIABLE_BOOTSERVICE_ACCESS, &vec![4_u8]).unwrap();
+        assert_eq!(2, rs.variables().len());
+
+        let reopened = HostRuntimeServices::open(rs.path()).unwrap();
+        assert_eq!(&[FakeVariable::new(&[0x41], &NAMESPACE, NON_VOLATILE, &[1, 2, 3])], &reopened.variables()[..]);
+        assert_eq!(
+            (vec![1_u8, 2, 3], NON_VOLATILE),
+            reopened.get_variable::<Vec<u8>>(&[0x41, 0], &NAMESPACE, None).unwrap()
+        );
+
+        rs.set_variable(&[0x41, 0], &NAMESPACE, NON_VOLATILE, &Vec::<u8>::new()).unwrap();
+        assert!(HostRuntimeServices::open(rs.path()).unwrap().variables().is_empty());
+
+        let path = rs.path().to_path_buf();
+        drop(rs);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_open_invalid_file() {
+        let rs = HostRuntimeServices::temporary().unwrap();
+        assert!(HostRuntimeServices::open(rs.path()).unwrap().variables().is_empty());
+        fs::write(rs.path(), b"not a backup").unwrap();
+        assert!(HostRuntimeServices::open(rs.path()).is_err());
+    }
+
+    #[test]
+    fn test_get_time() {
+        let (now, _) = HostRuntimeServices::temporary().unwrap().get_time().unwrap();
+        let seconds = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as i64;
+        assert!((seconds - time::to_unix_seconds(&now).unwrap()).abs() <= 1);
+    }
+}
@@ -0,0 +1,168 @@
+use core::ops::{BitAnd, BitOr, Not};
+
+use alloc::vec::Vec;
+use r_efi::efi;
+
+use crate::{RuntimeServices, RuntimeServicesExt};
+
+/// GUID of the variables defined by the UEFI specification (EFI_GLOBAL_VARIABLE).
+pub const GLOBAL_VARIABLE_GUID: efi::Guid =
+    efi::Guid::from_fields(0x8be4df61, 0x93ca, 0x11d2, 0xaa, 0x0d, &[0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c]);
+
+/// Attributes of the `OsIndications` variable.
+pub const OS_INDICATIONS_ATTRIBUTES: u32 =
+    efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_RUNTIME_ACCESS;
+
+/// Bits of the `OsIndications` and `OsIndicationsSupported` variables.
+///
+/// UEFI Spec Documentation: [8.5.4. Exchanging information between the OS and Firmware](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#exchanging-information-between-the-os-and-firmware)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OsIndications(u64);
+
+impl OsIndications {
+    /// Stop the boot in the firmware user interface.
+    pub const BOOT_TO_FW_UI: Self = Self(efi::OS_INDICATIONS_BOOT_TO_FW_UI);
+    /// Certificates revoked by timestamp are supported.
+    pub const TIMESTAMP_REVOCATION: Self = Self(efi::OS_INDICATIONS_TIMESTAMP_REVOCATION);
+    /// Capsules are delivered as files on the EFI system partition.
+    pub const FILE_CAPSULE_DELIVERY_SUPPORTED: Self = Self(efi::OS_INDICATIONS_FILE_CAPSULE_DELIVERY_SUPPORTED);
+    /// Firmware Management Protocol capsules are supported.
+    pub const FMP_CAPSULE_SUPPORTED: Self = Self(efi::OS_INDICATIONS_FMP_CAPSULE_SUPPORTED);
+    /// Capsule results are reported in `CapsuleXXXX` variables.
+    pub const CAPSULE_RESULT_VAR_SUPPORTED: Self = Self(efi::OS_INDICATIONS_CAPSULE_RESULT_VAR_SUPPORTED);
+    /// Start the OS defined recovery on the next boot.
+    pub const START_OS_RECOVERY: Self = Self(efi::OS_INDICATIONS_START_OS_RECOVERY);
+    /// Start the platform defined recovery on the next boot.
+    pub const START_PLATFORM_RECOVERY: Self = Self(efi::OS_INDICATIONS_START_PLATFORM_RECOVERY);
+    /// Refresh the JSON configuration data on the next boot.
+    pub const JSON_CONFIG_DATA_REFRESH: Self = Self(0x80);
+
+    /// Create indications from the raw bits of the variable, unknown bits are kept.
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw bits of the indications.
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns true if no bit is set.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every bit of *other* is set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Sets the bits of *other*.
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Clears the bits of *other*.
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for OsIndications {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for OsIndications {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl Not for OsIndications {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(!self.0)
+    }
+}
+
+impl TryFrom<Vec<u8>> for OsIndications {
+    type Error = efi::Status;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let bytes = <[u8; 8]>::try_from(value.as_slice()).map_err(|_| efi::Status::INVALID_PARAMETER)?;
+        Ok(Self(u64::from_le_bytes(bytes)))
+    }
+}
+
+// Writes *new* to the `OsIndications` variable if it differs from its *current* value.
+pub(crate) fn write_os_indications<R: RuntimeServices + ?Sized>(
+    runtime_services: &R,
+    current: OsIndications,
+    new: OsIndications,
+) -> Result<(), efi::Status> {
+    if current == new {
+        return Ok(());
+    }
+    runtime_services.set_variable(
+        crate::ucs2!("OsIndications"),
+        &GLOBAL_VARIABLE_GUID,
+        OS_INDICATIONS_ATTRIBUTES,
+        &new.bits().to_le_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{testing::FakeRuntimeServices, ucs2};
+
+    #[test]
+    fn test_os_indications_bits() {
+        let mut indications = OsIndications::BOOT_TO_FW_UI | OsIndications::from_bits(0x8000_0000_0000_0000);
+        assert!(indications.contains(OsIndications::BOOT_TO_FW_UI));
+        assert!(!indications.contains(OsIndications::BOOT_TO_FW_UI | OsIndications::START_OS_RECOVERY));
+
+        indications.insert(OsIndications::START_OS_RECOVERY);
+        indications.remove(OsIndications::BOOT_TO_FW_UI);
+        assert_eq!(0x8000_0000_0000_0020, indications.bits());
+        assert_eq!(OsIndications::START_OS_RECOVERY, indications & !OsIndications::from_bits(0x8000_0000_0000_0000));
+        assert!(OsIndications::default().is_empty());
+
+        assert_eq!(Ok(OsIndications::FMP_CAPSULE_SUPPORTED), OsIndications::try_from(8_u64.to_le_bytes().to_vec()));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), OsIndications::try_from(vec![8_u8]));
+    }
+
+    #[test]
+    fn test_set_and_clear_os_indications() {
+        let rs = FakeRuntimeServices::new();
+        assert_eq!(Ok(OsIndications::default()), rs.get_os_indications());
+        assert_eq!(Err(efi::Status::NOT_FOUND), rs.get_os_indications_supported());
+
+        rs.add_variable(ucs2!("OsIndicationsSupported"), &GLOBAL_VARIABLE_GUID, 0x6, &0x19_u64.to_le_bytes());
+        assert_eq!(Ok(OsIndications::from_bits(0x19)), rs.get_os_indications_supported());
+
+        // Unknown bits set by someone else are preserved.
+        rs.add_variable(
+            ucs2!("OsIndications"),
+            &GLOBAL_VARIABLE_GUID,
+            OS_INDICATIONS_ATTRIBUTES,
+            &0x100_u64.to_le_bytes(),
+        );
+        rs.set_os_indications(OsIndications::BOOT_TO_FW_UI | OsIndications::START_OS_RECOVERY).unwrap();
+        assert_eq!(Ok(OsIndications::from_bits(0x121)), rs.get_os_indications());
+
+        rs.clear_os_indications(OsIndications::START_OS_RECOVERY).unwrap();
+        assert_eq!(Ok(OsIndications::from_bits(0x101)), rs.get_os_indications());
+        assert_eq!(OS_INDICATIONS_ATTRIBUTES, rs.variables()[1].attributes);
+
+        rs.add_variable(ucs2!("OsIndications"), &GLOBAL_VARIABLE_GUID, OS_INDICATIONS_ATTRIBUTES, &[1]);
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), rs.set_os_indications(OsIndications::BOOT_TO_FW_UI));
+    }
+}
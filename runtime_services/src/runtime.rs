@@ -0,0 +1,303 @@
+//! Runtime services usable after the switch to virtual addressing.
+//!
+//! The runtime services table pointer given to a driver is a physical address, it becomes invalid once the OS calls
+//! SetVirtualAddressMap. [`RuntimeServicesRuntime`] registers for the virtual address change event and converts its
+//! table pointer with ConvertPointer, so a runtime driver can keep using the variable services at OS runtime.
+//!
+//! The execution context is a type-state:
+//! - [`RuntimeServicesRuntime<Runtime>`] is what code reachable at OS runtime uses, typically through a static. It only
+//!   exposes operations that do not allocate memory or use the boot services.
+//! - [`RuntimeServicesRuntime<BootTime>`] is returned by [`RuntimeServicesRuntime::initialize`] to the entry point of
+//!   the driver. It gives access to the whole [`RuntimeServices`] API through [`StandardRuntimeServices`].
+//!
+//! ```ignore
+//! static RUNTIME_SERVICES: RuntimeServicesRuntime = RuntimeServicesRuntime::new_uninit();
+//!
+//! // In the driver entry point.
+//! let runtime_services = RUNTIME_SERVICES.initialize(&BOOT_SERVICES, (*system_table).runtime_services)?;
+//! let capsule_results = runtime_services.get_capsule_results()?;
+//!
+//! // In a runtime handler.
+//! RUNTIME_SERVICES.set_variable(&name, &namespace, attributes, &data)?;
+//! ```
+
+use core::{
+    ffi::c_void,
+    marker::PhantomData,
+    ops::Deref,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use boot_services::{event::EventType, tpl::Tpl, BootServices};
+use r_efi::efi;
+
+use crate::{
+    variable_services::{GetVariableStatus, VariableInfo},
+    RuntimeServices, StandardRuntimeServices,
+};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Execution context of a [`RuntimeServicesRuntime`].
+pub trait ExecutionContext: private::Sealed {}
+
+/// Boot services are available, from the driver entry point until exit boot services.
+#[derive(Debug)]
+pub struct BootTime;
+
+/// Boot services may no longer be available and the addressing may be virtual.
+#[derive(Debug)]
+pub struct Runtime;
+
+impl private::Sealed for BootTime {}
+impl private::Sealed for Runtime {}
+impl ExecutionContext for BootTime {}
+impl ExecutionContext for Runtime {}
+
+/// Runtime services that follow the virtual address change of the runtime services table.
+// repr(C) guarantees that both contexts have the same layout, so references can be cast from one to the other.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RuntimeServicesRuntime<C: ExecutionContext = Runtime> {
+    inner: StandardRuntimeServices<'static>,
+    virtual_mode: AtomicBool,
+    _context: PhantomData<C>,
+}
+
+impl RuntimeServicesRuntime<Runtime> {
+    /// Create a new RuntimeServicesRuntime that is uninitialized.
+    /// The struct need to be initialize with [Self::initialize] from the driver entry point.
+    pub const fn new_uninit() -> Self {
+        Self {
+            inner: StandardRuntimeServices::new_uninit(),
+            virtual_mode: AtomicBool::new(false),
+            _context: PhantomData,
+        }
+    }
+
+    /// Initialize with the physical *efi_runtime_services* table pointer and register for the virtual address change.
+    ///
+    /// Returns the boot time view of these runtime services, it must not be used after exit boot services.
+    pub fn initialize<B: BootServices>(
+        &'static self,
+        boot_services: &B,
+        efi_runtime_services: *mut efi::RuntimeServices,
+    ) -> Result<&'static RuntimeServicesRuntime<BootTime>, efi::Status> {
+        if efi_runtime_services.is_null() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        if self
+            .inner
+            .efi_runtime_services
+            .compare_exchange(ptr::null_mut(), efi_runtime_services, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(efi::Status::ALREADY_STARTED);
+        }
+
+        //SAFETY: self is static, so it is valid when the event is signaled.
+        let event = unsafe {
+            boot_services.create_event_unchecked(
+                EventType::SIGNAL_VIRTUAL_ADDRESS_CHANGE,
+                Tpl::NOTIFY,
+                Some(Self::virtual_address_change),
+                self as *const Self as *mut Self,
+            )
+        };
+        if let Err(status) = event {
+            self.inner.efi_runtime_services.store(ptr::null_mut(), Ordering::SeqCst);
+            return Err(status);
+        }
+
+        //SAFETY: Both contexts have the same repr(C) layout.
+        Ok(unsafe { &*(self as *const Self as *const RuntimeServicesRuntime<BootTime>) })
+    }
+
+    extern "efiapi" fn virtual_address_change(_event: efi::Event, context: *mut Self) {
+        //SAFETY: The context is the static registered in initialize.
+        let this = unsafe { &*context };
+        let mut address = this.inner.efi_runtime_services.load(Ordering::SeqCst) as *mut c_void;
+        // ConvertPointer is called through the physical table, the switch is only effective after the notifications.
+        let status = (this.inner.efi_runtime_services().convert_pointer)(0, ptr::addr_of_mut!(address));
+        if status.is_error() {
+            debug_assert!(false, "Failed to convert the runtime services table pointer: {:?}", status);
+            return;
+        }
+        this.inner.efi_runtime_services.store(address as *mut efi::RuntimeServices, Ordering::SeqCst);
+        this.virtual_mode.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true once the table pointer was converted to its virtual address.
+    pub fn is_virtual(&self) -> bool {
+        self.virtual_mode.load(Ordering::SeqCst)
+    }
+
+    /// Gets a UEFI variable into *data*.
+    ///
+    /// *name* must be null-terminated, it is not copied to add the terminator as the memory can not be allocated.
+    pub fn get_variable(&self, name: &[u16], namespace: &efi::Guid, data: &mut [u8]) -> GetVariableStatus {
+        if name.last() != Some(&0) {
+            return GetVariableStatus::Error(efi::Status::INVALID_PARAMETER);
+        }
+        let get_variable = self.inner.efi_runtime_services().get_variable;
+        let mut data_size = data.len();
+        let mut attributes = 0;
+        match get_variable(
+            name.as_ptr() as *mut u16,
+            namespace as *const _ as *mut _,
+            ptr::addr_of_mut!(attributes),
+            ptr::addr_of_mut!(data_size),
+            data.as_mut_ptr() as *mut c_void,
+        ) {
+            efi::Status::BUFFER_TOO_SMALL => GetVariableStatus::BufferTooSmall { data_size, attributes },
+            s if s.is_error() => GetVariableStatus::Error(s),
+            _ => GetVariableStatus::Success { data_size, attributes },
+        }
+    }
+
+    /// Sets a UEFI variable.
+    ///
+    /// *name* must be null-terminated, it is not copied to add the terminator as the memory can not be allocated.
+    pub fn set_variable(
+        &self,
+        name: &[u16],
+        namespace: &efi::Guid,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), efi::Status> {
+        if name.last() != Some(&0) {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let set_variable = self.inner.efi_runtime_services().set_variable;
+        match set_variable(
+            name.as_ptr() as *mut u16,
+            namespace as *const _ as *mut _,
+            attributes,
+            data.len(),
+            data.as_ptr() as *mut c_void,
+        ) {
+            s if s.is_error() => Err(s),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns information about the UEFI variable store.
+    pub fn query_variable_info(&self, attributes: u32) -> Result<VariableInfo, efi::Status> {
+        self.inner.query_variable_info(attributes)
+    }
+}
+
+impl Deref for RuntimeServicesRuntime<BootTime> {
+    type Target = StandardRuntimeServices<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl RuntimeServicesRuntime<BootTime> {
+    /// Returns the runtime view of these runtime services.
+    pub fn runtime(&self) -> &RuntimeServicesRuntime<Runtime> {
+        //SAFETY: Both contexts have the same repr(C) layout.
+        unsafe { &*(self as *const Self as *const RuntimeServicesRuntime<Runtime>) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+    use boot_services::MockBootServices;
+    use core::mem;
+
+    static mut VIRTUAL_RUNTIME_SERVICES: mem::MaybeUninit<efi::RuntimeServices> = mem::MaybeUninit::zeroed();
+
+    extern "efiapi" fn efi_convert_pointer(debug_disposition: usize, address: *mut *mut c_void) -> efi::Status {
+        assert_eq!(0, debug_disposition);
+        unsafe { ptr::write(address, ptr::addr_of_mut!(VIRTUAL_RUNTIME_SERVICES) as *mut c_void) };
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_runtime_services_follow_virtual_address_change() {
+        static RUNTIME_SERVICES: RuntimeServicesRuntime = RuntimeServicesRuntime::new_uninit();
+
+        let physical = Box::leak(Box::new(mem::MaybeUninit::<efi::RuntimeServices>::zeroed())).as_mut_ptr();
+        unsafe {
+            (*physical).convert_pointer = efi_convert_pointer;
+            let virtual_rs = VIRTUAL_RUNTIME_SERVICES.assume_init_mut();
+            virtual_rs.get_variable = mock_efi_get_variable;
+            virtual_rs.set_variable = mock_efi_set_variable;
+            virtual_rs.query_variable_info = mock_efi_query_variable_info;
+        }
+
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_create_event_unchecked::<RuntimeServicesRuntime>().returning(
+            |event_type, tpl, notify, context| {
+                assert_eq!((EventType::SIGNAL_VIRTUAL_ADDRESS_CHANGE, Tpl::NOTIFY), (event_type, tpl));
+                assert_eq!(ptr::addr_of!(RUNTIME_SERVICES), context as *const _);
+                // Signal the event right away to simulate SetVirtualAddressMap.
+                notify.unwrap()(ptr::null_mut(), context);
+                Ok(1_usize as _)
+            },
+        );
+
+        let runtime_services = RUNTIME_SERVICES.initialize(&boot_services, physical).unwrap();
+        assert!(RUNTIME_SERVICES.is_virtual());
+        assert_eq!(unsafe { ptr::addr_of_mut!(VIRTUAL_RUNTIME_SERVICES) } as *mut _, runtime_services.as_raw_ptr());
+
+        // Calls go through the virtual table.
+        let data = DUMMY_DATA.to_ne_bytes();
+        assert_eq!(
+            Ok(()),
+            RUNTIME_SERVICES.set_variable(&DUMMY_FIRST_NAME, &DUMMY_FIRST_NAMESPACE, DUMMY_ATTRIBUTES, &data)
+        );
+        let mut buffer = [0_u8; DUMMY_DATA_REPR_SIZE];
+        assert!(matches!(
+            RUNTIME_SERVICES.get_variable(&DUMMY_FIRST_NAME, &DUMMY_FIRST_NAMESPACE, &mut buffer[..1]),
+            GetVariableStatus::BufferTooSmall { data_size: DUMMY_DATA_REPR_SIZE, attributes: DUMMY_ATTRIBUTES }
+        ));
+        assert!(matches!(
+            RUNTIME_SERVICES.get_variable(&DUMMY_FIRST_NAME, &DUMMY_FIRST_NAMESPACE, &mut buffer),
+            GetVariableStatus::Success { data_size: DUMMY_DATA_REPR_SIZE, attributes: DUMMY_ATTRIBUTES }
+        ));
+        assert_eq!(DUMMY_DATA, u32::from_ne_bytes(buffer));
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            RUNTIME_SERVICES.set_variable(&DUMMY_NON_NULL_TERMINATED_NAME, &DUMMY_FIRST_NAMESPACE, 0, &data)
+        );
+        assert_eq!(
+            DUMMY_MAXIMUM_VARIABLE_SIZE,
+            runtime_services.runtime().query_variable_info(DUMMY_ATTRIBUTES).unwrap().maximum_variable_size
+        );
+
+        assert_eq!(
+            Err(efi::Status::ALREADY_STARTED),
+            RUNTIME_SERVICES.initialize(&boot_services, physical).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_initialize_failure() {
+        static RUNTIME_SERVICES: RuntimeServicesRuntime = RuntimeServicesRuntime::new_uninit();
+
+        let mut boot_services = MockBootServices::new();
+        boot_services
+            .expect_create_event_unchecked::<RuntimeServicesRuntime>()
+            .returning(|_, _, _, _| Err(efi::Status::OUT_OF_RESOURCES));
+
+        let mut efi_rs = mem::MaybeUninit::<efi::RuntimeServices>::zeroed();
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            RUNTIME_SERVICES.initialize(&boot_services, ptr::null_mut()).map(|_| ())
+        );
+        assert_eq!(
+            Err(efi::Status::OUT_OF_RESOURCES),
+            RUNTIME_SERVICES.initialize(&boot_services, efi_rs.as_mut_ptr()).map(|_| ())
+        );
+        assert!(RUNTIME_SERVICES.inner.as_raw_ptr().is_null());
+    }
+}
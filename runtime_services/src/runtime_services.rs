@@ -14,9 +14,49 @@
 
 extern crate alloc;
 
+/// Capsule-services-specific structs and utilities
+pub mod capsule_services;
+
+/// Typed and versioned configuration stored in UEFI variables
+pub mod config_store;
+
+/// Typed accessors of the global variables defined by the UEFI specification
+pub mod global_variables;
+
+/// HwErrRec-variable-specific structs and utilities
+pub mod hardware_error_record;
+
+/// OsIndications-specific structs and utilities
+pub mod os_indications;
+
+/// Conversions and arithmetic of `efi::Time`
+pub mod time;
+
 /// Variable-services-specific structs and utilities
 pub mod variable_services;
 
+/// Staged writes of related UEFI variables with rollback
+pub mod variable_transaction;
+
+/// Backup and restore of UEFI variables
+pub mod variable_backup;
+
+/// Boot services time cache of UEFI variables
+#[cfg(any(test, feature = "variable_cache"))]
+pub mod variable_cache;
+
+/// Runtime services usable after the virtual address change
+#[cfg(any(test, feature = "runtime"))]
+pub mod runtime;
+
+/// In-memory fake runtime services for use in tests
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+/// Runtime services emulated on the host, persisting their variables to a file
+#[cfg(any(test, feature = "host"))]
+pub mod host;
+
 #[cfg(any(test, feature = "mockall"))]
 use mockall::automock;
 
@@ -24,12 +64,19 @@ use alloc::vec::Vec;
 use core::{
     ffi::c_void,
     marker::PhantomData,
-    ptr,
+    mem, ptr,
     sync::atomic::{AtomicPtr, Ordering},
 };
 
+use capsule_services::{CapsuleCapabilities, CapsuleResult};
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use hardware_error_record::{HardwareErrorRecord, HARDWARE_ERROR_VARIABLE_GUID};
+use os_indications::OsIndications;
 use r_efi::efi;
-use variable_services::{GetVariableStatus, VariableInfo};
+use variable_backup::ImportPolicy;
+use variable_services::{
+    GetVariableStatus, Variable, VariableInfo, VariableNameIterator, VariableStorageError, VariableStorageReport,
+};
 
 /// The UEFI spec runtime services.
 /// It wraps an [`AtomicPtr`] around [`efi::RuntimeServices`]
@@ -52,6 +99,32 @@ impl<'a> StandardRuntimeServices<'a> {
         }
     }
 
+    /// Create a new StandardRuntimeServices from a raw [efi::RuntimeServices] pointer.
+    ///
+    /// # Safety
+    ///
+    /// *efi_runtime_services* must either be null, in which case the struct is uninitialized, or point to a valid
+    /// [efi::RuntimeServices] that outlives `'a`.
+    pub const unsafe fn from_raw_ptr(efi_runtime_services: *mut efi::RuntimeServices) -> Self {
+        Self { efi_runtime_services: AtomicPtr::new(efi_runtime_services), _lifetime_marker: PhantomData }
+    }
+
+    /// Create a new StandardRuntimeServices from the runtime services table of a raw [efi::SystemTable] pointer.
+    ///
+    /// # Safety
+    ///
+    /// *system_table* must point to a valid [efi::SystemTable] whose runtime services table outlives `'a`.
+    pub unsafe fn from_system_table(system_table: *mut efi::SystemTable) -> Self {
+        Self::from_raw_ptr((*system_table).runtime_services)
+    }
+
+    /// Returns the raw [efi::RuntimeServices] pointer, null if uninitialized.
+    ///
+    /// This is meant for interoperability with other crates that need the raw table.
+    pub fn as_raw_ptr(&self) -> *mut efi::RuntimeServices {
+        self.efi_runtime_services.load(Ordering::SeqCst)
+    }
+
     /// Create a new StandardRuntimeServices that is uninitialized.
     /// The struct need to be initialize later with [Self::initialize], otherwise, subsequent call will panic.
     pub const fn new_uninit() -> Self {
@@ -70,6 +143,35 @@ impl<'a> StandardRuntimeServices<'a> {
         }
     }
 
+    /// Initialize the StandardRuntimeServices with a raw [efi::RuntimeServices] pointer, after validating the
+    /// signature, revision and CRC32 of its header.
+    ///
+    /// Returns:
+    /// - `efi::Status::INVALID_PARAMETER` if the pointer is null or the signature does not match.
+    /// - `efi::Status::INCOMPATIBLE_VERSION` if the table is not a UEFI 2.x table.
+    /// - `efi::Status::BAD_BUFFER_SIZE` if the header size is not the size of [efi::RuntimeServices].
+    /// - `efi::Status::CRC_ERROR` if the CRC32 of the table does not match the header.
+    ///
+    /// # Safety
+    ///
+    /// *efi_runtime_services* must either be null or readable for the size of a table header, and once validated
+    /// point to a valid [efi::RuntimeServices] that outlives `'a`.
+    ///
+    /// # Debug asserts
+    /// This function will assert on debug if already initialized.
+    pub unsafe fn initialize_checked(
+        &'a self,
+        efi_runtime_services: *const efi::RuntimeServices,
+    ) -> Result<(), efi::Status> {
+        boot_services::table_header::validate_table_header(
+            efi_runtime_services as *const efi::TableHeader,
+            efi::RUNTIME_SERVICES_SIGNATURE,
+            mem::size_of::<efi::RuntimeServices>(),
+        )?;
+        self.initialize(&*efi_runtime_services);
+        Ok(())
+    }
+
     /// # Panics
     /// This function will panic if it was not initialize.
     fn efi_runtime_services(&self) -> &efi::RuntimeServices {
@@ -91,91 +193,11 @@ unsafe impl Send for StandardRuntimeServices<'static> {}
 #[cfg_attr(any(test, feature = "mockall"), automock)]
 
 /// Interface for Rust-friendly wrappers of the UEFI Runtime Services
-pub trait RuntimeServices: Sized {
-    /// Sets a UEFI variable.
-    ///
-    /// UEFI Spec Documentation: [8.2.3. EFI_RUNTIME_SERVICES.SetVariable()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#setvariable)
-    ///
-    fn set_variable<T>(&self, name: &[u16], namespace: &efi::Guid, attributes: u32, data: &T) -> Result<(), efi::Status>
-    where
-        T: AsRef<[u8]> + 'static,
-    {
-        if !name.iter().position(|&c| c == 0).is_some() {
-            debug_assert!(false, "Name passed into set_variable is not null-terminated.");
-            return Err(efi::Status::INVALID_PARAMETER);
-        }
-
-        // Keep a local copy of name to unburden the caller of having to pass in a mutable slice
-        let mut name_vec = name.to_vec();
-
-        unsafe { self.set_variable_unchecked(name_vec.as_mut_slice(), namespace, attributes, data.as_ref()) }
-    }
-
-    /// Gets a UEFI variable.
-    ///
-    /// Returns a tuple of (data, attributes)
-    ///
-    /// UEFI Spec Documentation: [8.2.1. EFI_RUNTIME_SERVICES.GetVariable()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#getvariable)
-    ///
-    fn get_variable<T>(
-        &self,
-        name: &[u16],
-        namespace: &efi::Guid,
-        size_hint: Option<usize>,
-    ) -> Result<(T, u32), efi::Status>
-    where
-        T: TryFrom<Vec<u8>> + 'static,
-    {
-        if !name.iter().position(|&c| c == 0).is_some() {
-            debug_assert!(false, "Name passed into get_variable is not null-terminated.");
-            return Err(efi::Status::INVALID_PARAMETER);
-        }
-
-        // Keep a local copy of name to unburden the caller of having to pass in a mutable slice
-        let mut name_vec = name.to_vec();
-
-        // We can't simply allocate an empty buffer of size T because we can't assume
-        // the TryFrom representation of T will be the same as T
-        let mut data = Vec::<u8>::new();
-        if size_hint.is_some() {
-            data.resize(size_hint.unwrap(), 0);
-        }
-
-        // Do at most two calls to get_variable_unchecked.
-        //
-        // If size_hint was provided (and the size is sufficient), then only call to get_variable_unchecked is
-        // needed. Otherwise, the first check will determine the size of the buffer to allocate for the second
-        // call.
-        let mut first_attempt = true;
-        loop {
-            unsafe {
-                let status = self.get_variable_unchecked(
-                    name_vec.as_mut_slice(),
-                    namespace,
-                    if data.len() == 0 { None } else { Some(&mut data) },
-                );
-
-                match status {
-                    GetVariableStatus::Success { data_size: _, attributes } => match T::try_from(data) {
-                        Ok(d) => return Ok((d, attributes)),
-                        Err(_) => return Err(efi::Status::INVALID_PARAMETER),
-                    },
-                    GetVariableStatus::BufferTooSmall { data_size, attributes: _ } => {
-                        if first_attempt {
-                            first_attempt = false;
-                            data.resize(data_size, 10);
-                        } else {
-                            return Err(efi::Status::BUFFER_TOO_SMALL);
-                        }
-                    }
-                    GetVariableStatus::Error(e) => {
-                        return Err(e);
-                    }
-                }
-            }
-        }
-    }
-
+///
+/// The trait is object safe, so the runtime services can be selected at runtime, e.g. a `&dyn RuntimeServices` to the
+/// standard, host-emulated or cached runtime services. The generic helpers are in [`RuntimeServicesExt`], implemented
+/// for every runtime services.
+pub trait RuntimeServices {
     /// Helper function to get a UEFI variable's size and attributes
     fn get_variable_size_and_attributes(
         &self,
@@ -236,6 +258,163 @@ pub trait RuntimeServices: Sized {
     ///
     fn query_variable_info(&self, attributes: u32) -> Result<VariableInfo, efi::Status>;
 
+    /// Queries the usage of the variable storage for given UEFI variable attributes.
+    ///
+    fn variable_storage_report(&self, attributes: u32) -> Result<VariableStorageReport, efi::Status> {
+        self.query_variable_info(attributes).map(|info| VariableStorageReport::new(attributes, info))
+    }
+
+    /// Checks that *bytes* can be written in variables with *attributes*, so that flows writing several variables can
+    /// fail before their first write rather than in the middle, see [`VariableStorageReport::ensure_space`].
+    ///
+    fn ensure_space(&self, bytes: u64, attributes: u32) -> Result<VariableStorageReport, VariableStorageError> {
+        let report = self.variable_storage_report(attributes).map_err(VariableStorageError::Query)?;
+        report.ensure_space(bytes)?;
+        Ok(report)
+    }
+
+    /// Writes the variables of a *backup* produced by [`RuntimeServices::export_variables`], following *policy* for
+    /// the variables that already exist.
+    ///
+    /// Returns the number of variables written. The whole backup is validated before the first write, see
+    /// [`variable_backup::decode_variables`] for the errors.
+    ///
+    fn import_variables(&self, backup: &[u8], policy: ImportPolicy) -> Result<usize, efi::Status> {
+        variable_backup::import_variables(self, backup, policy)
+    }
+
+    /// Returns the current time and date, and the capabilities of the real time clock.
+    ///
+    /// See the [`time`] module to convert the time to Unix time or compute with it.
+    ///
+    /// UEFI Spec Documentation: [8.3.1. EFI_RUNTIME_SERVICES.GetTime()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#gettime)
+    ///
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities), efi::Status>;
+
+    /// Queries whether the capsules could be passed to update_capsule and how they would be processed.
+    ///
+    /// UEFI Spec Documentation: [8.5.3. EFI_RUNTIME_SERVICES.QueryCapsuleCapabilities()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#efi-runtime-services-querycapsulecapabilities)
+    ///
+    // The explicit lifetime is needed by mockall.
+    #[allow(clippy::needless_lifetimes)]
+    fn query_capsule_capabilities<'a>(
+        &self,
+        capsule_headers: &[&'a efi::CapsuleHeader],
+    ) -> Result<CapsuleCapabilities, efi::Status>;
+
+    /// Gets the result of a capsule processed by the firmware from the `CapsuleXXXX` variable at *index*.
+    ///
+    /// UEFI Spec Documentation: [8.5.6. UEFI variable reporting on the Success or any Errors encountered in processing of capsules after restart](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#uefi-variable-reporting-on-the-success-or-any-errors-encountered-in-processing-of-capsules-after-restart)
+    ///
+    fn get_capsule_result(&self, index: u16) -> Result<CapsuleResult, efi::Status> {
+        let name = capsule_services::capsule_result_variable_name(index);
+        self.get_variable::<CapsuleResult>(&name, &efi::CAPSULE_REPORT_GUID, None).map(|(result, _)| result)
+    }
+
+    /// Gets the results of every capsule reported by the firmware, from `Capsule0000` up to the `CapsuleMax` variable.
+    ///
+    /// Missing `CapsuleXXXX` variables are skipped.
+    ///
+    fn get_capsule_results(&self) -> Result<Vec<CapsuleResult>, efi::Status> {
+        let (capsule_max, _) =
+            self.get_variable::<Vec<u8>>(crate::ucs2!("CapsuleMax"), &efi::CAPSULE_REPORT_GUID, None)?;
+        let capsule_max = capsule_max.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<u16>>();
+        let max_index =
+            capsule_services::parse_capsule_result_variable_name(&capsule_max).ok_or(efi::Status::VOLUME_CORRUPTED)?;
+
+        let mut results = Vec::new();
+        for index in 0..=max_index {
+            match self.get_capsule_result(index) {
+                Ok(result) => results.push(result),
+                Err(efi::Status::NOT_FOUND) => continue,
+                Err(status) => return Err(status),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Gets the indices of the `HwErrRecXXXX` variables holding the hardware error records persisted by the platform,
+    /// in increasing order.
+    ///
+    /// UEFI Spec Documentation: [8.2.4.2. Hardware Error Record Persistence Usage](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#hardware-error-record-persistence-usage)
+    ///
+    fn get_hardware_error_record_indices(&self) -> Result<Vec<u16>, efi::Status> {
+        let mut indices = Vec::new();
+        let mut iter = VariableNameIterator::new_from_first(self);
+        while let Some(variable) = iter.next()? {
+            if variable.namespace != HARDWARE_ERROR_VARIABLE_GUID {
+                continue;
+            }
+            indices.extend(hardware_error_record::parse_hardware_error_record_variable_name(&variable.name));
+        }
+        indices.sort_unstable();
+        Ok(indices)
+    }
+
+    /// Gets and parses the hardware error record of the `HwErrRecXXXX` variable at *index*.
+    ///
+    /// Returns `efi::Status::INVALID_PARAMETER` if the variable is not a valid Common Platform Error Record.
+    ///
+    fn get_hardware_error_record(&self, index: u16) -> Result<HardwareErrorRecord, efi::Status> {
+        let name = hardware_error_record::hardware_error_record_variable_name(index);
+        self.get_variable::<HardwareErrorRecord>(&name, &HARDWARE_ERROR_VARIABLE_GUID, None).map(|(record, _)| record)
+    }
+
+    /// Deletes the `HwErrRecXXXX` variable at *index*, once its record has been consumed.
+    ///
+    fn clear_hardware_error_record(&self, index: u16) -> Result<(), efi::Status> {
+        let name = hardware_error_record::hardware_error_record_variable_name(index);
+        self.set_variable(
+            &name,
+            &HARDWARE_ERROR_VARIABLE_GUID,
+            hardware_error_record::HARDWARE_ERROR_RECORD_ATTRIBUTES,
+            &Vec::<u8>::new(),
+        )
+    }
+
+    /// Gets the indications the firmware supports from the `OsIndicationsSupported` variable.
+    ///
+    /// UEFI Spec Documentation: [8.5.4. Exchanging information between the OS and Firmware](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#exchanging-information-between-the-os-and-firmware)
+    ///
+    fn get_os_indications_supported(&self) -> Result<OsIndications, efi::Status> {
+        self.get_variable::<OsIndications>(
+            crate::ucs2!("OsIndicationsSupported"),
+            &os_indications::GLOBAL_VARIABLE_GUID,
+            None,
+        )
+        .map(|(indications, _)| indications)
+    }
+
+    /// Gets the indications requested from the firmware from the `OsIndications` variable, empty if not set.
+    ///
+    /// UEFI Spec Documentation: [8.5.4. Exchanging information between the OS and Firmware](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#exchanging-information-between-the-os-and-firmware)
+    ///
+    fn get_os_indications(&self) -> Result<OsIndications, efi::Status> {
+        match self.get_variable::<OsIndications>(
+            crate::ucs2!("OsIndications"),
+            &os_indications::GLOBAL_VARIABLE_GUID,
+            None,
+        ) {
+            Ok((indications, _)) => Ok(indications),
+            Err(efi::Status::NOT_FOUND) => Ok(OsIndications::default()),
+            Err(status) => Err(status),
+        }
+    }
+
+    /// Sets the *indications* bits of the `OsIndications` variable, preserving the other bits.
+    ///
+    fn set_os_indications(&self, indications: OsIndications) -> Result<(), efi::Status> {
+        let current = self.get_os_indications()?;
+        os_indications::write_os_indications(self, current, current | indications)
+    }
+
+    /// Clears the *indications* bits of the `OsIndications` variable, preserving the other bits.
+    ///
+    fn clear_os_indications(&self, indications: OsIndications) -> Result<(), efi::Status> {
+        let current = self.get_os_indications()?;
+        os_indications::write_os_indications(self, current, current & !indications)
+    }
+
     /// Set's a UEFI variable
     ///
     /// # Safety
@@ -279,6 +458,134 @@ pub trait RuntimeServices: Sized {
     ) -> Result<(), efi::Status>;
 }
 
+/// Generic helpers of [`RuntimeServices`], implemented for every runtime services including `dyn RuntimeServices`.
+pub trait RuntimeServicesExt: RuntimeServices {
+    /// Sets a UEFI variable.
+    ///
+    /// UEFI Spec Documentation: [8.2.3. EFI_RUNTIME_SERVICES.SetVariable()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#setvariable)
+    ///
+    fn set_variable<T>(&self, name: &[u16], namespace: &efi::Guid, attributes: u32, data: &T) -> Result<(), efi::Status>
+    where
+        T: AsRef<[u8]> + 'static,
+    {
+        if !name.iter().position(|&c| c == 0).is_some() {
+            debug_assert!(false, "Name passed into set_variable is not null-terminated.");
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        // Keep a local copy of name to unburden the caller of having to pass in a mutable slice
+        let mut name_vec = name.to_vec();
+
+        unsafe { self.set_variable_unchecked(name_vec.as_mut_slice(), namespace, attributes, data.as_ref()) }
+    }
+
+    /// Gets a UEFI variable.
+    ///
+    /// Returns a tuple of (data, attributes)
+    ///
+    /// UEFI Spec Documentation: [8.2.1. EFI_RUNTIME_SERVICES.GetVariable()](https://uefi.org/specs/UEFI/2.10/08_Services_Runtime_Services.html#getvariable)
+    ///
+    fn get_variable<T>(
+        &self,
+        name: &[u16],
+        namespace: &efi::Guid,
+        size_hint: Option<usize>,
+    ) -> Result<(T, u32), efi::Status>
+    where
+        T: TryFrom<Vec<u8>> + 'static,
+    {
+        if !name.iter().position(|&c| c == 0).is_some() {
+            debug_assert!(false, "Name passed into get_variable is not null-terminated.");
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        // Keep a local copy of name to unburden the caller of having to pass in a mutable slice
+        let mut name_vec = name.to_vec();
+
+        // We can't simply allocate an empty buffer of size T because we can't assume
+        // the TryFrom representation of T will be the same as T
+        let mut data = Vec::<u8>::new();
+        if size_hint.is_some() {
+            data.resize(size_hint.unwrap(), 0);
+        }
+
+        // Do at most two calls to get_variable_unchecked.
+        //
+        // If size_hint was provided (and the size is sufficient), then only call to get_variable_unchecked is
+        // needed. Otherwise, the first check will determine the size of the buffer to allocate for the second
+        // call.
+        let mut first_attempt = true;
+        loop {
+            unsafe {
+                let status = self.get_variable_unchecked(
+                    name_vec.as_mut_slice(),
+                    namespace,
+                    if data.len() == 0 { None } else { Some(&mut data) },
+                );
+
+                match status {
+                    GetVariableStatus::Success { data_size: _, attributes } => match T::try_from(data) {
+                        Ok(d) => return Ok((d, attributes)),
+                        Err(_) => return Err(efi::Status::INVALID_PARAMETER),
+                    },
+                    GetVariableStatus::BufferTooSmall { data_size, attributes: _ } => {
+                        if first_attempt {
+                            first_attempt = false;
+                            data.resize(data_size, 10);
+                        } else {
+                            return Err(efi::Status::BUFFER_TOO_SMALL);
+                        }
+                    }
+                    GetVariableStatus::Error(e) => {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets a UEFI variable, converting *name* to a null-terminated UCS-2 name.
+    ///
+    /// Returns `efi::Status::INVALID_PARAMETER` if *name* can not be converted, see
+    /// [`variable_services::variable_name`]. Use [`ucs2!`] to convert literal names at compile time instead.
+    ///
+    fn set_variable_by_name<T>(
+        &self,
+        name: &str,
+        namespace: &efi::Guid,
+        attributes: u32,
+        data: &T,
+    ) -> Result<(), efi::Status>
+    where
+        T: AsRef<[u8]> + 'static,
+    {
+        self.set_variable(&variable_services::variable_name(name)?, namespace, attributes, data)
+    }
+
+    /// Gets a UEFI variable, converting *name* to a null-terminated UCS-2 name.
+    ///
+    /// Returns a tuple of (data, attributes), or `efi::Status::INVALID_PARAMETER` if *name* can not be converted, see
+    /// [`variable_services::variable_name`]. Use [`ucs2!`] to convert literal names at compile time instead.
+    ///
+    fn get_variable_by_name<T>(&self, name: &str, namespace: &efi::Guid) -> Result<(T, u32), efi::Status>
+    where
+        T: TryFrom<Vec<u8>> + 'static,
+    {
+        self.get_variable(&variable_services::variable_name(name)?, namespace, None)
+    }
+
+    /// Serializes the variables for which *filter* returns true, see [`variable_backup`] for the format.
+    ///
+    fn export_variables<F>(&self, filter: F) -> Result<Vec<u8>, efi::Status>
+    where
+        F: Fn(&Variable) -> bool + 'static,
+    {
+        variable_backup::export_variables(self, filter)
+    }
+}
+
+impl<R: RuntimeServices + ?Sized> RuntimeServicesExt for R {}
+
 impl RuntimeServices for StandardRuntimeServices<'_> {
     unsafe fn set_variable_unchecked(
         &self,
@@ -424,6 +731,50 @@ impl RuntimeServices for StandardRuntimeServices<'_> {
             return Ok(var_info);
         }
     }
+
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities), efi::Status> {
+        let get_time = self.efi_runtime_services().get_time;
+        if get_time as usize == 0 {
+            debug_assert!(false, "GetTime has not initialized in the Runtime Services Table.");
+            return Err(efi::Status::NOT_FOUND);
+        }
+
+        let mut time = efi::Time::default();
+        let mut capabilities = efi::TimeCapabilities { resolution: 0, accuracy: 0, sets_to_zero: efi::Boolean::FALSE };
+
+        match get_time(ptr::addr_of_mut!(time), ptr::addr_of_mut!(capabilities)) {
+            s if s.is_error() => Err(s),
+            _ => Ok((time, capabilities)),
+        }
+    }
+
+    fn query_capsule_capabilities(
+        &self,
+        capsule_headers: &[&efi::CapsuleHeader],
+    ) -> Result<CapsuleCapabilities, efi::Status> {
+        let query_capsule_capabilities = self.efi_runtime_services().query_capsule_capabilities;
+        if query_capsule_capabilities as usize == 0 {
+            debug_assert!(false, "QueryCapsuleCapabilities has not initialized in the Runtime Services Table.");
+            return Err(efi::Status::NOT_FOUND);
+        }
+
+        let mut capsule_header_array =
+            capsule_headers.iter().map(|&h| h as *const _ as *mut efi::CapsuleHeader).collect::<Vec<_>>();
+        let mut capabilities = CapsuleCapabilities { maximum_capsule_size: 0, reset_type: efi::RESET_COLD };
+
+        let status = query_capsule_capabilities(
+            capsule_header_array.as_mut_ptr(),
+            capsule_header_array.len(),
+            ptr::addr_of_mut!(capabilities.maximum_capsule_size),
+            ptr::addr_of_mut!(capabilities.reset_type),
+        );
+
+        if status.is_error() {
+            Err(status)
+        } else {
+            Ok(capabilities)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -431,6 +782,10 @@ pub(crate) mod test {
     use efi;
 
     use super::*;
+    use crate::{
+        capsule_services::test::capsule_result_bytes, hardware_error_record::test::hardware_error_record_bytes,
+        testing::FakeRuntimeServices,
+    };
     use core::{mem, slice};
 
     macro_rules! runtime_services {
@@ -467,6 +822,49 @@ pub(crate) mod test {
         rs.initialize(efi_rs);
     }
 
+    #[test]
+    fn test_raw_ptr_interop() {
+        let mut efi_rs = mem::MaybeUninit::<efi::RuntimeServices>::zeroed();
+        let mut efi_st = unsafe { mem::MaybeUninit::<efi::SystemTable>::zeroed().assume_init() };
+        efi_st.runtime_services = efi_rs.as_mut_ptr();
+
+        let rs = unsafe { StandardRuntimeServices::from_system_table(ptr::addr_of_mut!(efi_st)) };
+        assert_eq!(efi_rs.as_mut_ptr(), rs.as_raw_ptr());
+
+        let rs = unsafe { StandardRuntimeServices::from_raw_ptr(ptr::null_mut()) };
+        assert!(rs.as_raw_ptr().is_null());
+    }
+
+    #[test]
+    fn test_initialize_checked() {
+        let mut efi_rs = mem::MaybeUninit::<efi::RuntimeServices>::zeroed();
+        let header = efi_rs.as_mut_ptr() as *mut efi::TableHeader;
+        unsafe {
+            (*header).signature = efi::RUNTIME_SERVICES_SIGNATURE;
+            (*header).revision = efi::SPECIFICATION_REVISION;
+            (*header).header_size = mem::size_of::<efi::RuntimeServices>() as u32;
+        }
+
+        let rs = StandardRuntimeServices::new_uninit();
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), unsafe { rs.initialize_checked(ptr::null()) });
+        // The CRC32 is not set yet.
+        assert_eq!(Err(efi::Status::CRC_ERROR), unsafe { rs.initialize_checked(efi_rs.as_ptr()) });
+        assert!(rs.as_raw_ptr().is_null());
+
+        let bytes =
+            unsafe { slice::from_raw_parts(efi_rs.as_ptr() as *const u8, mem::size_of::<efi::RuntimeServices>()) };
+        unsafe { (*header).crc32 = boot_services::crc32::crc32(bytes) };
+        assert_eq!(Ok(()), unsafe { rs.initialize_checked(efi_rs.as_ptr()) });
+        assert_eq!(efi_rs.as_mut_ptr(), rs.as_raw_ptr());
+
+        let rs = StandardRuntimeServices::new_uninit();
+        unsafe { (*header).signature = efi::BOOT_SERVICES_SIGNATURE };
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), unsafe { rs.initialize_checked(efi_rs.as_ptr()) });
+        unsafe { (*header).signature = efi::RUNTIME_SERVICES_SIGNATURE };
+        unsafe { (*header).revision = 1 << 16 };
+        assert_eq!(Err(efi::Status::INCOMPATIBLE_VERSION), unsafe { rs.initialize_checked(efi_rs.as_ptr()) });
+    }
+
     pub const DUMMY_FIRST_NAME: [u16; 3] = [0x1000, 0x1020, 0x0000];
     pub const DUMMY_NON_NULL_TERMINATED_NAME: [u16; 3] = [0x1000, 0x1020, 0x1040];
     pub const DUMMY_EMPTY_NAME: [u16; 1] = [0x0000];
@@ -809,6 +1207,36 @@ pub(crate) mod test {
         assert_eq!(status.unwrap_err(), efi::Status::NOT_FOUND);
     }
 
+    #[test]
+    fn test_get_and_set_variable_by_name() {
+        let rs = FakeRuntimeServices::new();
+
+        rs.set_variable_by_name("BootOrder", &DUMMY_FIRST_NAMESPACE, DUMMY_ATTRIBUTES, &vec![1_u8, 0]).unwrap();
+        assert_eq!("BootOrder".encode_utf16().collect::<Vec<_>>(), rs.variables()[0].name);
+
+        let (data, attributes) = rs.get_variable_by_name::<Vec<u8>>("BootOrder", &DUMMY_FIRST_NAMESPACE).unwrap();
+        assert_eq!((vec![1, 0], DUMMY_ATTRIBUTES), (data, attributes));
+        assert_eq!(
+            Err(efi::Status::NOT_FOUND),
+            rs.get_variable_by_name::<Vec<u8>>("BootNext", &DUMMY_FIRST_NAMESPACE).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_get_and_set_variable_by_invalid_name() {
+        let rs = FakeRuntimeServices::new();
+
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            rs.set_variable_by_name("Boot\0Order", &DUMMY_FIRST_NAMESPACE, DUMMY_ATTRIBUTES, &vec![1_u8])
+        );
+        assert_eq!(
+            Err(efi::Status::INVALID_PARAMETER),
+            rs.get_variable_by_name::<Vec<u8>>("Boot\u{1F600}", &DUMMY_FIRST_NAMESPACE).map(|_| ())
+        );
+        assert!(rs.variables().is_empty());
+    }
+
     #[test]
     fn test_get_next_variable_name() {
         // Ensure we are testing a growing name buffer
@@ -880,4 +1308,137 @@ pub(crate) mod test {
         assert!(status.is_err());
         assert_eq!(status.unwrap_err(), efi::Status::INVALID_PARAMETER);
     }
+
+    extern "efiapi" fn mock_efi_get_time(
+        time: *mut efi::Time,
+        capabilities: *mut efi::TimeCapabilities,
+    ) -> efi::Status {
+        unsafe {
+            *time = efi::Time { year: 2024, month: 2, day: 29, hour: 12, timezone: 60, ..Default::default() };
+            (*capabilities).resolution = 1;
+        }
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_get_time() {
+        let rs: &StandardRuntimeServices<'_> = runtime_services!(get_time = mock_efi_get_time);
+
+        let (time, capabilities) = rs.get_time().unwrap();
+        assert_eq!((2024, 2, 29, 12, 60), (time.year, time.month, time.day, time.hour, time.timezone));
+        assert_eq!(1, capabilities.resolution);
+        assert_eq!(Ok(1_709_204_400), crate::time::to_unix_seconds(&time));
+    }
+
+    extern "efiapi" fn mock_efi_query_capsule_capabilities(
+        capsule_header_array: *mut *mut efi::CapsuleHeader,
+        capsule_count: usize,
+        maximum_capsule_size: *mut u64,
+        reset_type: *mut efi::ResetType,
+    ) -> efi::Status {
+        unsafe {
+            assert_eq!(capsule_count, 1);
+            assert_eq!((**capsule_header_array).capsule_image_size, 0x100);
+            *maximum_capsule_size = DUMMY_MAXIMUM_VARIABLE_SIZE;
+            *reset_type = efi::RESET_WARM;
+        }
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn test_query_capsule_capabilities() {
+        let rs: &StandardRuntimeServices<'_> =
+            runtime_services!(query_capsule_capabilities = mock_efi_query_capsule_capabilities);
+
+        let header = efi::CapsuleHeader {
+            capsule_guid: DUMMY_FIRST_NAMESPACE,
+            header_size: mem::size_of::<efi::CapsuleHeader>() as u32,
+            flags: 0,
+            capsule_image_size: 0x100,
+        };
+        let capabilities = rs.query_capsule_capabilities(&[&header]).unwrap();
+
+        assert_eq!(capabilities.maximum_capsule_size, DUMMY_MAXIMUM_VARIABLE_SIZE);
+        assert_eq!(capabilities.reset_type, efi::RESET_WARM);
+    }
+
+    #[test]
+    fn test_get_capsule_results() {
+        let rs = FakeRuntimeServices::new();
+        let capsule_max: Vec<u8> = "Capsule0002".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        rs.add_variable(
+            &"CapsuleMax\0".encode_utf16().collect::<Vec<_>>(),
+            &efi::CAPSULE_REPORT_GUID,
+            0x7,
+            &capsule_max,
+        );
+        for (index, status) in [(0, efi::Status::SUCCESS), (2, efi::Status::ABORTED)] {
+            rs.add_variable(
+                &capsule_services::capsule_result_variable_name(index),
+                &efi::CAPSULE_REPORT_GUID,
+                0x7,
+                &capsule_result_bytes(DUMMY_FIRST_NAMESPACE, status, &[]),
+            );
+        }
+
+        let results = rs.get_capsule_results().unwrap();
+        assert_eq!(
+            vec![efi::Status::SUCCESS, efi::Status::ABORTED],
+            results.iter().map(|r| r.capsule_status).collect::<Vec<_>>()
+        );
+        assert_eq!(Err(efi::Status::NOT_FOUND), rs.get_capsule_result(1).map(|_| ()));
+    }
+
+    #[test]
+    fn test_hardware_error_records() {
+        let rs = FakeRuntimeServices::new();
+        for index in [0x10, 0x2] {
+            rs.add_variable(
+                &hardware_error_record::hardware_error_record_variable_name(index),
+                &HARDWARE_ERROR_VARIABLE_GUID,
+                hardware_error_record::HARDWARE_ERROR_RECORD_ATTRIBUTES,
+                &hardware_error_record_bytes(index as u64, &[b"section"]),
+            );
+        }
+        rs.add_variable(
+            &"HwErrRecSupport\0".encode_utf16().collect::<Vec<_>>(),
+            &os_indications::GLOBAL_VARIABLE_GUID,
+            0x7,
+            &[1, 0],
+        );
+        rs.add_variable(
+            &hardware_error_record::hardware_error_record_variable_name(0x5),
+            &DUMMY_FIRST_NAMESPACE,
+            0x7,
+            &[0],
+        );
+
+        assert_eq!(Ok(vec![0x2, 0x10]), rs.get_hardware_error_record_indices());
+        let record = rs.get_hardware_error_record(0x10).unwrap();
+        assert_eq!(0x10, record.header.record_id);
+        assert_eq!(Some(&b"section"[..]), record.section_data(0));
+
+        rs.clear_hardware_error_record(0x10).unwrap();
+        assert_eq!(Ok(vec![0x2]), rs.get_hardware_error_record_indices());
+        assert_eq!(Err(efi::Status::NOT_FOUND), rs.get_hardware_error_record(0x10).map(|_| ()));
+    }
+
+    #[test]
+    fn test_dyn_runtime_services() {
+        let standard: &StandardRuntimeServices<'_> = runtime_services!(get_variable = mock_efi_get_variable);
+        let fake = FakeRuntimeServices::new();
+        fake.add_variable(&DUMMY_FIRST_NAME, &DUMMY_FIRST_NAMESPACE, 0x3, &DUMMY_DATA.to_ne_bytes());
+
+        // The backend is selected at runtime, the generic helpers work on the trait object.
+        for (rs, attributes) in [(standard as &dyn RuntimeServices, DUMMY_ATTRIBUTES), (&fake, 0x3)] {
+            let (data, read_attributes) =
+                rs.get_variable::<DummyVariableType>(&DUMMY_FIRST_NAME, &DUMMY_FIRST_NAMESPACE, None).unwrap();
+            assert_eq!((DUMMY_DATA, attributes), (data.value, read_attributes));
+        }
+
+        let boxed: alloc::boxed::Box<dyn RuntimeServices> = alloc::boxed::Box::new(fake);
+        boxed.set_variable(&DUMMY_SECOND_NAME, &DUMMY_SECOND_NAMESPACE, 0x7, &vec![1_u8]).unwrap();
+        let mut names = VariableNameIterator::new_from_first(&*boxed);
+        assert_eq!(Some(&DUMMY_FIRST_NAME[..]), names.next().unwrap().map(|variable| &variable.name[..]));
+    }
 }
@@ -0,0 +1,298 @@
+//! In-memory fake of the UEFI variable store.
+//!
+//! [`FakeRuntimeServices`] implements [`RuntimeServices`] on top of a configurable list of variables so that crates
+//! depending on the runtime services can simulate a variable store deterministically in their own tests.
+//!
+//! ```ignore
+//! let rs = FakeRuntimeServices::new();
+//! rs.add_variable(&[0x41, 0x00], &NAMESPACE, 0x7, &[1, 2, 3]);
+//!
+//! let (data, attributes) = rs.get_variable::<Vec<u8>>(&[0x41, 0x00], &NAMESPACE, None).unwrap();
+//! assert_eq!(vec![1, 2, 3], data);
+//! assert_eq!(0x7, attributes);
+//! ```
+
+use alloc::vec::Vec;
+use core::cell::{Ref, RefCell};
+
+use r_efi::efi;
+
+use crate::{
+    capsule_services::CapsuleCapabilities,
+    variable_services::{GetVariableStatus, VariableInfo},
+    RuntimeServices,
+};
+
+/// A variable stored in a [`FakeRuntimeServices`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeVariable {
+    /// The name of the variable, without the null terminator.
+    pub name: Vec<u16>,
+    /// The namespace of the variable.
+    pub namespace: efi::Guid,
+    /// The attributes of the variable.
+    pub attributes: u32,
+    /// The data of the variable.
+    pub data: Vec<u8>,
+}
+
+impl FakeVariable {
+    /// Create a new variable, the name may or may not be null-terminated.
+    pub fn new(name: &[u16], namespace: &efi::Guid, attributes: u32, data: &[u8]) -> Self {
+        Self { name: trim_name(name).to_vec(), namespace: *namespace, attributes, data: data.to_vec() }
+    }
+}
+
+/// Fake runtime services backed by an ordered in-memory list of [`FakeVariable`].
+///
+/// Variables are enumerated by `get_next_variable_name` in the order they were added.
+#[derive(Debug)]
+pub struct FakeRuntimeServices {
+    variables: RefCell<Vec<FakeVariable>>,
+    maximum_variable_storage_size: u64,
+    maximum_variable_size: u64,
+}
+
+impl Default for FakeRuntimeServices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeRuntimeServices {
+    /// Create an empty variable store.
+    pub fn new() -> Self {
+        Self::with_variables(Vec::new())
+    }
+
+    /// Create a variable store populated with `variables`.
+    pub fn with_variables(variables: Vec<FakeVariable>) -> Self {
+        Self {
+            variables: RefCell::new(variables),
+            maximum_variable_storage_size: 0x10000,
+            maximum_variable_size: 0x2000,
+        }
+    }
+
+    /// Set the storage size and maximum variable size reported by `query_variable_info` and enforced by
+    /// `set_variable`.
+    pub fn with_storage_limits(mut self, maximum_variable_storage_size: u64, maximum_variable_size: u64) -> Self {
+        self.maximum_variable_storage_size = maximum_variable_storage_size;
+        self.maximum_variable_size = maximum_variable_size;
+        self
+    }
+
+    /// Add a variable to the store, replacing any variable with the same name and namespace.
+    pub fn add_variable(&self, name: &[u16], namespace: &efi::Guid, attributes: u32, data: &[u8]) {
+        let variable = FakeVariable::new(name, namespace, attributes, data);
+        let mut variables = self.variables.borrow_mut();
+        match variables.iter_mut().find(|v| v.name == variable.name && v.namespace == variable.namespace) {
+            Some(v) => *v = variable,
+            None => variables.push(variable),
+        }
+    }
+
+    /// Return the variables currently in the store.
+    pub fn variables(&self) -> Ref<'_, Vec<FakeVariable>> {
+        self.variables.borrow()
+    }
+
+    fn used_storage_size(&self) -> u64 {
+        self.variables.borrow().iter().map(storage_size).sum()
+    }
+}
+
+// Size taken by a variable in the store: its null-terminated UCS-2 name and its data.
+fn storage_size(variable: &FakeVariable) -> u64 {
+    ((variable.name.len() + 1) * 2 + variable.data.len()) as u64
+}
+
+fn trim_name(name: &[u16]) -> &[u16] {
+    &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())]
+}
+
+impl RuntimeServices for FakeRuntimeServices {
+    unsafe fn set_variable_unchecked(
+        &self,
+        name: &mut [u16],
+        namespace: &efi::Guid,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), efi::Status> {
+        let name = trim_name(name);
+        if name.is_empty() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        let position = self.variables.borrow().iter().position(|v| v.name == name && v.namespace == *namespace);
+
+        // Per UEFI spec, a zero sized data or zero attributes delete the variable.
+        if data.is_empty() || attributes == 0 {
+            return match position {
+                Some(idx) => {
+                    self.variables.borrow_mut().remove(idx);
+                    Ok(())
+                }
+                None => Err(efi::Status::NOT_FOUND),
+            };
+        }
+
+        if data.len() as u64 > self.maximum_variable_size {
+            return Err(efi::Status::OUT_OF_RESOURCES);
+        }
+
+        let previous_size = position.map_or(0, |idx| storage_size(&self.variables.borrow()[idx]));
+        let new_size = storage_size(&FakeVariable::new(name, namespace, attributes, data));
+        if self.used_storage_size() - previous_size + new_size > self.maximum_variable_storage_size {
+            return Err(efi::Status::OUT_OF_RESOURCES);
+        }
+
+        self.add_variable(name, namespace, attributes, data);
+        Ok(())
+    }
+
+    unsafe fn get_variable_unchecked(
+        &self,
+        name: &mut [u16],
+        namespace: &efi::Guid,
+        data: Option<&mut [u8]>,
+    ) -> GetVariableStatus {
+        let name = trim_name(name);
+        let variables = self.variables.borrow();
+        let Some(variable) = variables.iter().find(|v| v.name == name && v.namespace == *namespace) else {
+            return GetVariableStatus::Error(efi::Status::NOT_FOUND);
+        };
+
+        let data_size = variable.data.len();
+        match data {
+            Some(buffer) if buffer.len() >= data_size => {
+                buffer[..data_size].copy_from_slice(&variable.data);
+                GetVariableStatus::Success { data_size, attributes: variable.attributes }
+            }
+            _ => GetVariableStatus::BufferTooSmall { data_size, attributes: variable.attributes },
+        }
+    }
+
+    unsafe fn get_next_variable_name_unchecked(
+        &self,
+        prev_name: &[u16],
+        prev_namespace: &efi::Guid,
+        next_name: &mut Vec<u16>,
+        next_namespace: &mut efi::Guid,
+    ) -> Result<(), efi::Status> {
+        if !prev_name.contains(&0) {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        let prev_name = trim_name(prev_name);
+        let variables = self.variables.borrow();
+        let next_idx = if prev_name.is_empty() {
+            0
+        } else {
+            match variables.iter().position(|v| v.name == prev_name && v.namespace == *prev_namespace) {
+                Some(idx) => idx + 1,
+                None => return Err(efi::Status::INVALID_PARAMETER),
+            }
+        };
+
+        let Some(next) = variables.get(next_idx) else {
+            return Err(efi::Status::NOT_FOUND);
+        };
+
+        next_name.clear();
+        next_name.extend_from_slice(&next.name);
+        next_name.push(0);
+        *next_namespace = next.namespace;
+        Ok(())
+    }
+
+    fn query_variable_info(&self, _attributes: u32) -> Result<VariableInfo, efi::Status> {
+        Ok(VariableInfo {
+            maximum_variable_storage_size: self.maximum_variable_storage_size,
+            remaining_variable_storage_size: self
+                .maximum_variable_storage_size
+                .saturating_sub(self.used_storage_size()),
+            maximum_variable_size: self.maximum_variable_size,
+        })
+    }
+
+    fn query_capsule_capabilities(
+        &self,
+        _capsule_headers: &[&efi::CapsuleHeader],
+    ) -> Result<CapsuleCapabilities, efi::Status> {
+        Err(efi::Status::UNSUPPORTED)
+    }
+
+    fn get_time(&self) -> Result<(efi::Time, efi::TimeCapabilities), efi::Status> {
+        Err(efi::Status::UNSUPPORTED)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{variable_services::VariableNameIterator, RuntimeServicesExt};
+    use fallible_streaming_iterator::FallibleStreamingIterator;
+
+    const NAMESPACE_A: efi::Guid = efi::Guid::from_fields(0xA, 0, 0, 0, 0, &[0; 6]);
+    const NAMESPACE_B: efi::Guid = efi::Guid::from_fields(0xB, 0, 0, 0, 0, &[0; 6]);
+
+    fn fake() -> FakeRuntimeServices {
+        FakeRuntimeServices::with_variables(vec![
+            FakeVariable::new(&[0x41, 0x00], &NAMESPACE_A, 0x3, &[1, 2, 3, 4]),
+            FakeVariable::new(&[0x42, 0x43], &NAMESPACE_B, 0x7, &[5]),
+        ])
+    }
+
+    #[test]
+    fn test_get_variable() {
+        let rs = fake();
+        let (data, attributes) = rs.get_variable::<Vec<u8>>(&[0x42, 0x43, 0x00], &NAMESPACE_B, None).unwrap();
+        assert_eq!(vec![5], data);
+        assert_eq!(0x7, attributes);
+
+        assert_eq!(Err(efi::Status::NOT_FOUND), rs.get_variable::<Vec<u8>>(&[0x42, 0x43, 0x00], &NAMESPACE_A, None));
+        assert_eq!(Ok((4, 0x3)), rs.get_variable_size_and_attributes(&[0x41, 0x00], &NAMESPACE_A));
+    }
+
+    #[test]
+    fn test_set_variable_update_and_delete() {
+        let rs = fake();
+        rs.set_variable(&[0x41, 0x00], &NAMESPACE_A, 0x3, &vec![9u8, 9]).unwrap();
+        assert_eq!(vec![9, 9], rs.variables()[0].data);
+
+        rs.set_variable(&[0x44, 0x00], &NAMESPACE_A, 0x3, &vec![1u8]).unwrap();
+        assert_eq!(3, rs.variables().len());
+
+        rs.set_variable(&[0x41, 0x00], &NAMESPACE_A, 0x3, &Vec::<u8>::new()).unwrap();
+        assert_eq!(2, rs.variables().len());
+        assert_eq!(Err(efi::Status::NOT_FOUND), rs.set_variable(&[0x41, 0x00], &NAMESPACE_A, 0, &Vec::<u8>::new()));
+    }
+
+    #[test]
+    fn test_set_variable_storage_limits() {
+        let rs = FakeRuntimeServices::new().with_storage_limits(16, 8);
+        assert_eq!(
+            Err(efi::Status::OUT_OF_RESOURCES),
+            rs.set_variable(&[0x41, 0x00], &NAMESPACE_A, 0x3, &vec![0u8; 9])
+        );
+        rs.set_variable(&[0x41, 0x00], &NAMESPACE_A, 0x3, &vec![0u8; 8]).unwrap();
+        assert_eq!(4, rs.query_variable_info(0x3).unwrap().remaining_variable_storage_size);
+        assert_eq!(
+            Err(efi::Status::OUT_OF_RESOURCES),
+            rs.set_variable(&[0x42, 0x00], &NAMESPACE_A, 0x3, &vec![0u8; 1])
+        );
+    }
+
+    #[test]
+    fn test_variable_name_iterator_over_fake() {
+        let rs = fake();
+        let mut iter = VariableNameIterator::new_from_first(&rs);
+
+        let mut names = Vec::new();
+        while let Some(variable) = iter.next().unwrap() {
+            names.push(variable.name.clone());
+        }
+        assert_eq!(vec![vec![0x41, 0x00], vec![0x42, 0x43, 0x00]], names);
+    }
+}
@@ -0,0 +1,257 @@
+//! Conversions of `efi::Time` to and from Unix time, and arithmetic on it.
+//!
+//! An `efi::Time` is a local time: its `timezone` is the offset in minutes of the local time from UTC, and its
+//! `daylight` field has `efi::TIME_IN_DAYLIGHT` set when the local time is one hour ahead for daylight saving time. A
+//! time in `efi::UNSPECIFIED_TIMEZONE` is converted as if it were UTC.
+//!
+//! ```ignore
+//! let (now, _) = RUNTIME_SERVICES.get_time()?;
+//! let deadline = time::checked_add(&now, Duration::from_secs(24 * 60 * 60))?;
+//! let timestamp = time::to_unix_seconds(&deadline)?;
+//! ```
+
+use core::time::Duration;
+
+use r_efi::efi;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+const NANOSECONDS_PER_SECOND: i128 = 1_000_000_000;
+
+/// Earliest year of a valid `efi::Time`.
+pub const MIN_YEAR: u16 = 1900;
+
+/// Latest year of a valid `efi::Time`.
+pub const MAX_YEAR: u16 = 9999;
+
+/// Largest offset in minutes of a time zone from UTC.
+pub const MAX_TIMEZONE_OFFSET: i16 = 24 * 60;
+
+/// Returns `efi::Status::INVALID_PARAMETER` if a field of *time* is out of the range allowed by the UEFI spec, or its
+/// day does not exist in its month.
+pub fn validate(time: &efi::Time) -> Result<(), efi::Status> {
+    let valid = (MIN_YEAR..=MAX_YEAR).contains(&time.year)
+        && (1..=12).contains(&time.month)
+        && (1..=days_in_month(time.year, time.month)).contains(&time.day)
+        && time.hour < 24
+        && time.minute < 60
+        && time.second < 60
+        && time.nanosecond < NANOSECONDS_PER_SECOND as u32
+        && is_valid_timezone(time.timezone)
+        && time.daylight & !(efi::TIME_ADJUST_DAYLIGHT | efi::TIME_IN_DAYLIGHT) == 0;
+    match valid {
+        true => Ok(()),
+        false => Err(efi::Status::INVALID_PARAMETER),
+    }
+}
+
+/// Returns the number of seconds from 1970-01-01T00:00:00 UTC to *time*, ignoring its nanoseconds.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if *time* is not valid, see [`validate`].
+pub fn to_unix_seconds(time: &efi::Time) -> Result<i64, efi::Status> {
+    validate(time)?;
+    let local = days_from_civil(time.year as i64, time.month as i64, time.day as i64) * SECONDS_PER_DAY
+        + time.hour as i64 * 3600
+        + time.minute as i64 * 60
+        + time.second as i64;
+    Ok(local - utc_offset(time.timezone, time.daylight))
+}
+
+/// Returns the local time of *seconds* since 1970-01-01T00:00:00 UTC in *timezone* with the *daylight* flags.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if *timezone* or *daylight* is not valid, or the local time is not within
+/// [`MIN_YEAR`] and [`MAX_YEAR`].
+pub fn from_unix_seconds(seconds: i64, timezone: i16, daylight: u8) -> Result<efi::Time, efi::Status> {
+    from_unix_nanoseconds(seconds as i128 * NANOSECONDS_PER_SECOND, timezone, daylight)
+}
+
+/// Returns *time* plus *duration*, in the time zone and with the daylight flags of *time*.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if *time* is not valid or the result is after [`MAX_YEAR`].
+pub fn checked_add(time: &efi::Time, duration: Duration) -> Result<efi::Time, efi::Status> {
+    from_unix_nanoseconds(to_unix_nanoseconds(time)? + duration.as_nanos() as i128, time.timezone, time.daylight)
+}
+
+/// Returns *time* minus *duration*, in the time zone and with the daylight flags of *time*.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if *time* is not valid or the result is before [`MIN_YEAR`].
+pub fn checked_sub(time: &efi::Time, duration: Duration) -> Result<efi::Time, efi::Status> {
+    from_unix_nanoseconds(to_unix_nanoseconds(time)? - duration.as_nanos() as i128, time.timezone, time.daylight)
+}
+
+/// Returns the amount of time from *earlier* to *time*, or None if *earlier* is later than *time*.
+///
+/// The times may be in different time zones. Returns `efi::Status::INVALID_PARAMETER` if either time is not valid.
+pub fn checked_duration_since(time: &efi::Time, earlier: &efi::Time) -> Result<Option<Duration>, efi::Status> {
+    let nanoseconds = to_unix_nanoseconds(time)? - to_unix_nanoseconds(earlier)?;
+    Ok(u64::try_from(nanoseconds.div_euclid(NANOSECONDS_PER_SECOND))
+        .ok()
+        .map(|seconds| Duration::new(seconds, nanoseconds.rem_euclid(NANOSECONDS_PER_SECOND) as u32)))
+}
+
+fn to_unix_nanoseconds(time: &efi::Time) -> Result<i128, efi::Status> {
+    Ok(to_unix_seconds(time)? as i128 * NANOSECONDS_PER_SECOND + time.nanosecond as i128)
+}
+
+fn from_unix_nanoseconds(nanoseconds: i128, timezone: i16, daylight: u8) -> Result<efi::Time, efi::Status> {
+    let template = efi::Time { year: MIN_YEAR, month: 1, day: 1, timezone, daylight, ..Default::default() };
+    validate(&template)?;
+
+    let seconds =
+        i64::try_from(nanoseconds.div_euclid(NANOSECONDS_PER_SECOND)).map_err(|_| efi::Status::INVALID_PARAMETER)?;
+    let local = seconds.checked_add(utc_offset(timezone, daylight)).ok_or(efi::Status::INVALID_PARAMETER)?;
+    let (year, month, day) = civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+    let second_of_day = local.rem_euclid(SECONDS_PER_DAY);
+    let time = efi::Time {
+        year: u16::try_from(year).map_err(|_| efi::Status::INVALID_PARAMETER)?,
+        month: month as u8,
+        day: day as u8,
+        hour: (second_of_day / 3600) as u8,
+        minute: (second_of_day / 60 % 60) as u8,
+        second: (second_of_day % 60) as u8,
+        nanosecond: nanoseconds.rem_euclid(NANOSECONDS_PER_SECOND) as u32,
+        ..template
+    };
+    validate(&time)?;
+    Ok(time)
+}
+
+fn is_valid_timezone(timezone: i16) -> bool {
+    timezone == efi::UNSPECIFIED_TIMEZONE || (-MAX_TIMEZONE_OFFSET..=MAX_TIMEZONE_OFFSET).contains(&timezone)
+}
+
+// Offset in seconds of the local time from UTC.
+fn utc_offset(timezone: i16, daylight: u8) -> i64 {
+    let timezone = match timezone {
+        efi::UNSPECIFIED_TIMEZONE => 0,
+        timezone => timezone as i64 * 60,
+    };
+    match daylight & efi::TIME_IN_DAYLIGHT {
+        0 => timezone,
+        _ => timezone + 3600,
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days from 1970-01-01 to the date, in the proleptic Gregorian calendar.
+// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// Date of the day *days* after 1970-01-01, the inverse of days_from_civil.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> efi::Time {
+        efi::Time { year, month, day, hour, minute, second, timezone: efi::UNSPECIFIED_TIMEZONE, ..Default::default() }
+    }
+
+    fn fields(time: &efi::Time) -> (u16, u8, u8, u8, u8, u8, u32, i16, u8) {
+        let t = time;
+        (t.year, t.month, t.day, t.hour, t.minute, t.second, t.nanosecond, t.timezone, t.daylight)
+    }
+
+    #[test]
+    fn test_unix_seconds() {
+        assert_eq!(Ok(0), to_unix_seconds(&time(1970, 1, 1, 0, 0, 0)));
+        assert_eq!(Ok(951_782_400), to_unix_seconds(&time(2000, 2, 29, 0, 0, 0)));
+        assert_eq!(Ok(1_700_000_000), to_unix_seconds(&time(2023, 11, 14, 22, 13, 20)));
+        assert_eq!(Ok(-2_208_988_800), to_unix_seconds(&time(1900, 1, 1, 0, 0, 0)));
+        assert_eq!(Ok(253_402_300_799), to_unix_seconds(&time(9999, 12, 31, 23, 59, 59)));
+
+        // 2023-11-14T23:13:20+01:00, and the same instant in daylight saving time.
+        let paris = efi::Time { timezone: 60, ..time(2023, 11, 14, 23, 13, 20) };
+        assert_eq!(Ok(1_700_000_000), to_unix_seconds(&paris));
+        let summer = efi::Time { daylight: efi::TIME_ADJUST_DAYLIGHT | efi::TIME_IN_DAYLIGHT, ..paris };
+        assert_eq!(Ok(1_700_000_000 - 3600), to_unix_seconds(&summer));
+
+        let round_trip = from_unix_seconds(1_700_000_000, 60, 0).unwrap();
+        assert_eq!(fields(&paris), fields(&round_trip));
+        let round_trip = from_unix_seconds(1_700_000_000, efi::UNSPECIFIED_TIMEZONE, 0).unwrap();
+        assert_eq!(fields(&time(2023, 11, 14, 22, 13, 20)), fields(&round_trip));
+        let round_trip = from_unix_seconds(-2_208_988_801, 60, efi::TIME_IN_DAYLIGHT).unwrap();
+        assert_eq!(Ok(-2_208_988_801), to_unix_seconds(&round_trip));
+
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), from_unix_seconds(-2_208_988_801, 0, 0).map(|t| t.year));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), from_unix_seconds(253_402_300_800, 0, 0).map(|t| t.year));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), from_unix_seconds(i64::MAX, 0, 0).map(|t| t.year));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), from_unix_seconds(0, 1441, 0).map(|t| t.year));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Ok(()), validate(&time(2024, 2, 29, 23, 59, 59)));
+        for invalid in [
+            time(2023, 2, 29, 0, 0, 0),
+            time(1900, 2, 29, 0, 0, 0),
+            time(1899, 12, 31, 0, 0, 0),
+            time(2024, 13, 1, 0, 0, 0),
+            time(2024, 4, 31, 0, 0, 0),
+            time(2024, 1, 1, 24, 0, 0),
+            time(2024, 1, 1, 0, 0, 60),
+            efi::Time { nanosecond: 1_000_000_000, ..time(2024, 1, 1, 0, 0, 0) },
+            efi::Time { timezone: -1441, ..time(2024, 1, 1, 0, 0, 0) },
+            efi::Time { daylight: 0x4, ..time(2024, 1, 1, 0, 0, 0) },
+        ] {
+            assert_eq!(Err(efi::Status::INVALID_PARAMETER), validate(&invalid), "{invalid:?}");
+            assert_eq!(Err(efi::Status::INVALID_PARAMETER), to_unix_seconds(&invalid));
+        }
+    }
+
+    #[test]
+    fn test_duration_arithmetic() {
+        let new_year = efi::Time { nanosecond: 500_000_000, timezone: -300, ..time(2023, 12, 31, 23, 59, 59) };
+
+        let later = checked_add(&new_year, Duration::from_millis(1_500)).unwrap();
+        assert_eq!((2024, 1, 1, 0, 0, 1, 0, -300, 0), fields(&later));
+        let leap_day = checked_add(&new_year, Duration::from_secs(60 * SECONDS_PER_DAY as u64)).unwrap();
+        assert_eq!((2024, 2, 29), (leap_day.year, leap_day.month, leap_day.day));
+        let earlier = checked_sub(&later, Duration::from_secs(365 * SECONDS_PER_DAY as u64)).unwrap();
+        assert_eq!(
+            (2023, 1, 1, 0, 0, 1),
+            (earlier.year, earlier.month, earlier.day, earlier.hour, earlier.minute, earlier.second)
+        );
+
+        assert_eq!(Ok(Some(Duration::from_millis(1_500))), checked_duration_since(&later, &new_year));
+        assert_eq!(Ok(None), checked_duration_since(&new_year, &later));
+        // The same instant in two time zones.
+        let utc = efi::Time { timezone: 0, ..time(2024, 1, 1, 5, 0, 1) };
+        assert_eq!(Ok(Some(Duration::ZERO)), checked_duration_since(&utc, &later));
+
+        let last = time(9999, 12, 31, 23, 59, 59);
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), checked_add(&last, Duration::from_secs(1)).map(|t| t.year));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), checked_add(&last, Duration::MAX).map(|t| t.year));
+        let first = time(1900, 1, 1, 0, 0, 0);
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), checked_sub(&first, Duration::from_nanos(1)).map(|t| t.year));
+    }
+}
@@ -0,0 +1,268 @@
+//! Backup and restore of UEFI variables in a portable binary format.
+//!
+//! [`RuntimeServices::export_variables`] captures the variables selected by a filter and
+//! [`RuntimeServices::import_variables`] writes them back, on the same or on another machine.
+//!
+//! ```ignore
+//! let backup = RUNTIME_SERVICES.export_variables(|variable| variable.namespace == VENDOR_GUID)?;
+//! ...
+//! RUNTIME_SERVICES.import_variables(&backup, ImportPolicy::SkipExisting)?;
+//! ```
+//!
+//! # Format
+//!
+//! Integers are little-endian. A backup is a header followed by `count` entries, with nothing after the last entry.
+//!
+//! | Field        | Size            | Description                                                      |
+//! |--------------|-----------------|------------------------------------------------------------------|
+//! | `magic`      | 8               | [`VARIABLE_BACKUP_MAGIC`]                                        |
+//! | `version`    | 4               | [`VARIABLE_BACKUP_VERSION`]                                      |
+//! | `count`      | 4               | Number of entries                                                |
+//!
+//! Each entry is:
+//!
+//! | Field        | Size            | Description                                                      |
+//! |--------------|-----------------|------------------------------------------------------------------|
+//! | `namespace`  | 16              | Vendor GUID of the variable, in the `EFI_GUID` byte order        |
+//! | `attributes` | 4               | Attributes of the variable                                       |
+//! | `name_size`  | 4               | Size in bytes of `name`                                          |
+//! | `data_size`  | 4               | Size in bytes of `data`                                          |
+//! | `name`       | `name_size`     | UCS-2 name of the variable, null-terminated                      |
+//! | `data`       | `data_size`     | Data of the variable                                             |
+//! | `crc32`      | 4               | CRC32 of the entry from `namespace` to the end of `data`         |
+
+use alloc::vec::Vec;
+use core::mem;
+
+use boot_services::crc32::crc32;
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use r_efi::efi;
+
+use crate::{
+    variable_services::{Variable, VariableIterator},
+    RuntimeServices, RuntimeServicesExt,
+};
+
+/// Magic bytes starting a variable backup.
+pub const VARIABLE_BACKUP_MAGIC: [u8; 8] = *b"MUVARBAK";
+
+/// Version of the variable backup format produced by [`RuntimeServices::export_variables`].
+pub const VARIABLE_BACKUP_VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = VARIABLE_BACKUP_MAGIC.len() + 2 * mem::size_of::<u32>();
+const ENTRY_HEADER_SIZE: usize = mem::size_of::<efi::Guid>() + 3 * mem::size_of::<u32>();
+
+/// What [`RuntimeServices::import_variables`] does with the variables that already exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportPolicy {
+    /// Write every variable of the backup, replacing the existing ones.
+    Overwrite,
+    /// Only write the variables of the backup that do not exist.
+    SkipExisting,
+}
+
+/// Serializes *variables* into a backup.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if a name is not null-terminated or a variable is too big for the format.
+pub fn encode_variables(variables: &[Variable]) -> Result<Vec<u8>, efi::Status> {
+    let size = |len: usize| u32::try_from(len).map_err(|_| efi::Status::INVALID_PARAMETER);
+
+    let mut backup = Vec::with_capacity(HEADER_SIZE);
+    backup.extend_from_slice(&VARIABLE_BACKUP_MAGIC);
+    backup.extend_from_slice(&VARIABLE_BACKUP_VERSION.to_le_bytes());
+    backup.extend_from_slice(&size(variables.len())?.to_le_bytes());
+    for variable in variables {
+        if variable.name.last() != Some(&0) {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let entry = backup.len();
+        backup.extend_from_slice(variable.namespace.as_bytes());
+        backup.extend_from_slice(&variable.attributes.to_le_bytes());
+        backup.extend_from_slice(&size(variable.name.len() * mem::size_of::<u16>())?.to_le_bytes());
+        backup.extend_from_slice(&size(variable.data.len())?.to_le_bytes());
+        backup.extend(variable.name.iter().flat_map(|c| c.to_le_bytes()));
+        backup.extend_from_slice(&variable.data);
+        let crc = crc32(&backup[entry..]);
+        backup.extend_from_slice(&crc.to_le_bytes());
+    }
+    Ok(backup)
+}
+
+/// Parses the variables of *backup*, checking the CRC of every entry.
+///
+/// Returns:
+/// - `efi::Status::INVALID_PARAMETER` if *backup* does not start with [`VARIABLE_BACKUP_MAGIC`].
+/// - `efi::Status::INCOMPATIBLE_VERSION` if the backup is not of version [`VARIABLE_BACKUP_VERSION`].
+/// - `efi::Status::VOLUME_CORRUPTED` if the backup is truncated, has trailing bytes or a name is not null-terminated.
+/// - `efi::Status::CRC_ERROR` if the CRC of an entry does not match.
+pub fn decode_variables(backup: &[u8]) -> Result<Vec<Variable>, efi::Status> {
+    if backup.len() < HEADER_SIZE || backup[..VARIABLE_BACKUP_MAGIC.len()] != VARIABLE_BACKUP_MAGIC {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+    let u32_at = |offset: usize| -> Result<u32, efi::Status> {
+        let bytes = backup.get(offset..offset + 4).ok_or(efi::Status::VOLUME_CORRUPTED)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    if u32_at(8)? != VARIABLE_BACKUP_VERSION {
+        return Err(efi::Status::INCOMPATIBLE_VERSION);
+    }
+    let count = u32_at(12)? as usize;
+
+    let mut variables = Vec::new();
+    let mut offset = HEADER_SIZE;
+    for _ in 0..count {
+        let attributes = u32_at(offset + 16)?;
+        let name_size = u32_at(offset + 20)? as usize;
+        let data_size = u32_at(offset + 24)? as usize;
+        let crc_offset = (offset + ENTRY_HEADER_SIZE)
+            .checked_add(name_size)
+            .and_then(|end| end.checked_add(data_size))
+            .ok_or(efi::Status::VOLUME_CORRUPTED)?;
+        if crc32(backup.get(offset..crc_offset).ok_or(efi::Status::VOLUME_CORRUPTED)?) != u32_at(crc_offset)? {
+            return Err(efi::Status::CRC_ERROR);
+        }
+
+        let name_offset = offset + ENTRY_HEADER_SIZE;
+        let name = backup[name_offset..name_offset + name_size]
+            .chunks(2)
+            .map(|c| match c {
+                &[low, high] => Ok(u16::from_le_bytes([low, high])),
+                _ => Err(efi::Status::VOLUME_CORRUPTED),
+            })
+            .collect::<Result<Vec<u16>, _>>()?;
+        if name.last() != Some(&0) {
+            return Err(efi::Status::VOLUME_CORRUPTED);
+        }
+        variables.push(Variable {
+            name,
+            namespace: efi::Guid::from_bytes(backup[offset..offset + 16].try_into().unwrap()),
+            attributes,
+            data: backup[name_offset + name_size..crc_offset].to_vec(),
+        });
+        offset = crc_offset + mem::size_of::<u32>();
+    }
+    match offset == backup.len() {
+        true => Ok(variables),
+        false => Err(efi::Status::VOLUME_CORRUPTED),
+    }
+}
+
+// See RuntimeServices::export_variables.
+pub(crate) fn export_variables<R, F>(runtime_services: &R, filter: F) -> Result<Vec<u8>, efi::Status>
+where
+    R: RuntimeServices + ?Sized,
+    F: Fn(&Variable) -> bool,
+{
+    let mut variables = Vec::new();
+    let mut iter = VariableIterator::new_from_first(runtime_services);
+    while let Some(variable) = iter.next()? {
+        if filter(variable) {
+            variables.push(Variable { name: variable.name.clone(), data: variable.data.clone(), ..*variable });
+        }
+    }
+    encode_variables(&variables)
+}
+
+// See RuntimeServices::import_variables.
+pub(crate) fn import_variables<R: RuntimeServices + ?Sized>(
+    runtime_services: &R,
+    backup: &[u8],
+    policy: ImportPolicy,
+) -> Result<usize, efi::Status> {
+    // The whole backup is checked before the first write.
+    let variables = decode_variables(backup)?;
+
+    let mut written = 0;
+    for variable in variables {
+        if policy == ImportPolicy::SkipExisting {
+            match runtime_services.get_variable_size_and_attributes(&variable.name, &variable.namespace) {
+                Ok(_) => continue,
+                Err(efi::Status::NOT_FOUND) => (),
+                Err(status) => return Err(status),
+            }
+        }
+        runtime_services.set_variable(&variable.name, &variable.namespace, variable.attributes, &variable.data)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        os_indications,
+        testing::{FakeRuntimeServices, FakeVariable},
+    };
+
+    const VENDOR_GUID: efi::Guid =
+        efi::Guid::from_fields(0x2e8a6d87, 0x0dc9, 0x4a6e, 0xa2, 0xb5, &[0x8f, 0x04, 0xa8, 0xf8, 0xc2, 0xc9]);
+    const ATTRIBUTES: u32 = efi::VARIABLE_NON_VOLATILE | efi::VARIABLE_BOOTSERVICE_ACCESS;
+
+    fn variables() -> Vec<FakeVariable> {
+        vec![
+            FakeVariable::new(crate::ucs2!("Setup"), &VENDOR_GUID, ATTRIBUTES, &[1, 2, 3]),
+            FakeVariable::new(crate::ucs2!("Timeout"), &os_indications::GLOBAL_VARIABLE_GUID, ATTRIBUTES, &[5, 0]),
+            FakeVariable::new(crate::ucs2!("Mode"), &VENDOR_GUID, ATTRIBUTES, &[4]),
+        ]
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let source = FakeRuntimeServices::with_variables(variables());
+        let backup = source.export_variables(|variable| variable.namespace == VENDOR_GUID).unwrap();
+
+        let decoded = decode_variables(&backup).unwrap();
+        assert_eq!(
+            vec![crate::ucs2!("Setup") as &[u16], crate::ucs2!("Mode")],
+            decoded.iter().map(|v| v.name.as_slice()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (VENDOR_GUID, ATTRIBUTES, vec![1, 2, 3]),
+            (decoded[0].namespace, decoded[0].attributes, decoded[0].data.clone())
+        );
+
+        let target = FakeRuntimeServices::new();
+        target.add_variable(crate::ucs2!("Setup"), &VENDOR_GUID, ATTRIBUTES, &[9]);
+        assert_eq!(Ok(1), target.import_variables(&backup, ImportPolicy::SkipExisting));
+        assert_eq!(
+            Ok((vec![9], ATTRIBUTES)),
+            target.get_variable::<Vec<u8>>(crate::ucs2!("Setup"), &VENDOR_GUID, None)
+        );
+
+        assert_eq!(Ok(2), target.import_variables(&backup, ImportPolicy::Overwrite));
+        assert_eq!(
+            Ok((vec![1, 2, 3], ATTRIBUTES)),
+            target.get_variable::<Vec<u8>>(crate::ucs2!("Setup"), &VENDOR_GUID, None)
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_backups() {
+        let source = FakeRuntimeServices::with_variables(variables());
+        let backup = source.export_variables(|_| true).unwrap();
+        assert_eq!(3, decode_variables(&backup).unwrap().len());
+
+        assert_eq!(Some(efi::Status::INVALID_PARAMETER), decode_variables(&backup[1..]).err());
+        let mut version = backup.clone();
+        version[8] = 2;
+        assert_eq!(Some(efi::Status::INCOMPATIBLE_VERSION), decode_variables(&version).err());
+        assert_eq!(Some(efi::Status::VOLUME_CORRUPTED), decode_variables(&backup[..backup.len() - 1]).err());
+        assert_eq!(Some(efi::Status::VOLUME_CORRUPTED), decode_variables(&[backup.as_slice(), &[0]].concat()).err());
+
+        let mut corrupted = backup.clone();
+        corrupted[HEADER_SIZE + ENTRY_HEADER_SIZE] ^= 1;
+        assert_eq!(Some(efi::Status::CRC_ERROR), decode_variables(&corrupted).err());
+
+        // Nothing is written from a corrupted backup.
+        let target = FakeRuntimeServices::new();
+        assert_eq!(Err(efi::Status::CRC_ERROR), target.import_variables(&corrupted, ImportPolicy::Overwrite));
+        assert!(target.variables().is_empty());
+    }
+
+    #[test]
+    fn test_encode_unterminated_name() {
+        let variable = Variable { name: vec![0x41], namespace: VENDOR_GUID, attributes: ATTRIBUTES, data: vec![] };
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), encode_variables(&[variable]));
+    }
+}
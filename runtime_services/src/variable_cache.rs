@@ -0,0 +1,248 @@
+//! Boot services time cache in front of the UEFI variable services.
+//!
+//! Variables that are read repeatedly during boot are served from memory after the first read, which saves the
+//! round trip to the variable driver (often an SMM call). Writes go through to the runtime services and update the
+//! cache on success.
+//!
+//! ```ignore
+//! let cache = VariableCache::new(&RUNTIME_SERVICES, &BOOT_SERVICES, 32);
+//! let (data, attributes) = cache.get_variable::<Vec<u8>>(&name, &namespace)?;
+//! ```
+
+use alloc::vec::Vec;
+
+use boot_services::{tpl::Tpl, BootServices, StandardBootServices};
+use r_efi::efi;
+use tpl_mutex::TplMutex;
+
+use crate::{RuntimeServices, RuntimeServicesExt};
+
+#[derive(Debug)]
+struct CachedVariable {
+    name: Vec<u16>,
+    namespace: efi::Guid,
+    attributes: u32,
+    data: Vec<u8>,
+}
+
+/// Write-through cache of UEFI variables, bounded to a maximum number of entries.
+///
+/// The cache is protected by a [`TplMutex`] locked at [`Tpl::NOTIFY`], so it can be used from event notify functions up
+/// to [`Tpl::NOTIFY`], including the ones dispatched when another call on the cache releases the lock. The lock is not
+/// held while calling into the runtime services.
+pub struct VariableCache<'a, R: RuntimeServices + ?Sized, B: BootServices = StandardBootServices<'a>> {
+    runtime_services: &'a R,
+    // Entries are ordered from the least to the most recently used.
+    entries: TplMutex<'a, Vec<CachedVariable>, B>,
+    max_entries: usize,
+}
+
+impl<'a, R: RuntimeServices + ?Sized, B: BootServices> VariableCache<'a, R, B> {
+    /// Create an empty cache that can hold up to *max_entries* variables.
+    pub fn new(runtime_services: &'a R, boot_services: &'a B, max_entries: usize) -> Self {
+        Self { runtime_services, entries: TplMutex::new(boot_services, Tpl::NOTIFY, Vec::new()), max_entries }
+    }
+
+    /// Gets a UEFI variable from the cache, or from the runtime services on a cache miss.
+    ///
+    /// Returns a tuple of (data, attributes)
+    pub fn get_variable<T>(&'a self, name: &[u16], namespace: &efi::Guid) -> Result<(T, u32), efi::Status>
+    where
+        T: TryFrom<Vec<u8>> + 'static,
+    {
+        let cached = {
+            let mut entries = self.entries.lock();
+            position(&entries, name, namespace).map(|idx| {
+                // Move the entry to the back as it is now the most recently used.
+                let entry = entries.remove(idx);
+                let cached = (entry.data.clone(), entry.attributes);
+                entries.push(entry);
+                cached
+            })
+        };
+
+        let (data, attributes) = match cached {
+            Some(cached) => cached,
+            None => {
+                let (data, attributes) = self.runtime_services.get_variable::<Vec<u8>>(name, namespace, None)?;
+                self.insert(name, namespace, attributes, data.clone());
+                (data, attributes)
+            }
+        };
+
+        T::try_from(data).map(|d| (d, attributes)).map_err(|_| efi::Status::INVALID_PARAMETER)
+    }
+
+    /// Sets a UEFI variable through the runtime services and updates the cache on success.
+    pub fn set_variable<T>(
+        &'a self,
+        name: &[u16],
+        namespace: &efi::Guid,
+        attributes: u32,
+        data: &T,
+    ) -> Result<(), efi::Status>
+    where
+        T: AsRef<[u8]> + 'static,
+    {
+        let result = self.runtime_services.set_variable(name, namespace, attributes, data);
+
+        // Deletes and appends can not be mirrored reliably, the next read will refresh the entry.
+        self.invalidate(name, namespace);
+        if result.is_ok()
+            && !data.as_ref().is_empty()
+            && attributes != 0
+            && attributes & efi::VARIABLE_APPEND_WRITE == 0
+        {
+            self.insert(name, namespace, attributes, data.as_ref().to_vec());
+        }
+        result
+    }
+
+    /// Removes a variable from the cache.
+    pub fn invalidate(&'a self, name: &[u16], namespace: &efi::Guid) {
+        let mut entries = self.entries.lock();
+        if let Some(idx) = position(&entries, name, namespace) {
+            entries.remove(idx);
+        }
+    }
+
+    /// Removes every variable from the cache.
+    pub fn invalidate_all(&'a self) {
+        self.entries.lock().clear();
+    }
+
+    /// Returns the number of variables currently cached.
+    pub fn len(&'a self) -> usize {
+        self.entries.lock().len()
+    }
+
+    /// Returns true if no variable is cached.
+    pub fn is_empty(&'a self) -> bool {
+        self.len() == 0
+    }
+
+    fn insert(&'a self, name: &[u16], namespace: &efi::Guid, attributes: u32, data: Vec<u8>) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        if let Some(idx) = position(&entries, name, namespace) {
+            entries.remove(idx);
+        } else if entries.len() >= self.max_entries {
+            // Evict the least recently used entry.
+            entries.remove(0);
+        }
+        entries.push(CachedVariable { name: trim_name(name).to_vec(), namespace: *namespace, attributes, data });
+    }
+}
+
+fn trim_name(name: &[u16]) -> &[u16] {
+    &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())]
+}
+
+fn position(entries: &[CachedVariable], name: &[u16], namespace: &efi::Guid) -> Option<usize> {
+    let name = trim_name(name);
+    entries.iter().position(|e| e.name == name && e.namespace == *namespace)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::FakeRuntimeServices;
+    use boot_services::{event::EventType, host::HostBootServices, MockBootServices};
+
+    const NAMESPACE: efi::Guid = efi::Guid::from_fields(0xA, 0, 0, 0, 0, &[0; 6]);
+    const NAME_A: [u16; 2] = [0x41, 0x00];
+    const NAME_B: [u16; 2] = [0x42, 0x00];
+    const NAME_C: [u16; 2] = [0x43, 0x00];
+
+    fn boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services
+    }
+
+    #[test]
+    fn test_get_variable_is_served_from_cache() {
+        let bs = boot_services();
+        let rs = FakeRuntimeServices::new();
+        rs.add_variable(&NAME_A, &NAMESPACE, 0x7, &[1, 2]);
+        let cache = VariableCache::new(&rs, &bs, 4);
+
+        assert_eq!(Ok((vec![1, 2], 0x7)), cache.get_variable::<Vec<u8>>(&NAME_A, &NAMESPACE));
+        assert_eq!(1, cache.len());
+
+        // Changing the store behind the cache is not visible until invalidated.
+        rs.add_variable(&NAME_A, &NAMESPACE, 0x7, &[3]);
+        assert_eq!(Ok((vec![1, 2], 0x7)), cache.get_variable::<Vec<u8>>(&NAME_A, &NAMESPACE));
+        cache.invalidate(&NAME_A, &NAMESPACE);
+        assert_eq!(Ok((vec![3], 0x7)), cache.get_variable::<Vec<u8>>(&NAME_A, &NAMESPACE));
+
+        assert_eq!(Err(efi::Status::NOT_FOUND), cache.get_variable::<Vec<u8>>(&NAME_B, &NAMESPACE));
+        assert_eq!(1, cache.len());
+    }
+
+    #[test]
+    fn test_set_variable_write_through() {
+        let bs = boot_services();
+        let rs = FakeRuntimeServices::new();
+        let cache = VariableCache::new(&rs, &bs, 4);
+
+        cache.set_variable(&NAME_A, &NAMESPACE, 0x7, &vec![4u8, 5]).unwrap();
+        assert_eq!(vec![4, 5], rs.variables()[0].data);
+        assert_eq!(1, cache.len());
+
+        cache.set_variable(&NAME_A, &NAMESPACE, 0x7, &Vec::<u8>::new()).unwrap();
+        assert!(rs.variables().is_empty());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_get_variable_from_a_notify_dispatched_on_unlock() {
+        type Cache = VariableCache<'static, FakeRuntimeServices, StandardBootServices<'static>>;
+        extern "efiapi" fn notify(_: efi::Event, cache: &Cache) {
+            let _ = cache.get_variable::<Vec<u8>>(&NAME_B, &NAMESPACE);
+        }
+
+        let host = Box::leak(Box::new(HostBootServices::new()));
+        let bs = host.standard_boot_services();
+        let rs = Box::leak(Box::new(FakeRuntimeServices::new()));
+        rs.add_variable(&NAME_A, &NAMESPACE, 0x7, &[1]);
+        rs.add_variable(&NAME_B, &NAMESPACE, 0x7, &[2]);
+        let cache: &'static Cache = Box::leak(Box::new(VariableCache::new(rs, bs, 4)));
+        let event = bs.create_event(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, Some(notify), cache).unwrap();
+
+        // The notify is queued while the cache is locked, and dispatched when the lock is released.
+        let entries = cache.entries.lock();
+        bs.signal_event(event).unwrap();
+        drop(entries);
+        assert_eq!(1, cache.len());
+        assert_eq!(Ok((vec![2], 0x7)), cache.get_variable::<Vec<u8>>(&NAME_B, &NAMESPACE));
+    }
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted() {
+        let bs = boot_services();
+        let rs = FakeRuntimeServices::new();
+        for name in [NAME_A, NAME_B, NAME_C] {
+            rs.add_variable(&name, &NAMESPACE, 0x7, &[name[0] as u8]);
+        }
+        let cache = VariableCache::new(&rs, &bs, 2);
+
+        cache.get_variable::<Vec<u8>>(&NAME_A, &NAMESPACE).unwrap();
+        cache.get_variable::<Vec<u8>>(&NAME_B, &NAMESPACE).unwrap();
+        cache.get_variable::<Vec<u8>>(&NAME_A, &NAMESPACE).unwrap();
+        cache.get_variable::<Vec<u8>>(&NAME_C, &NAMESPACE).unwrap();
+        assert_eq!(2, cache.len());
+
+        // B was evicted, so the new value in the store is read.
+        rs.add_variable(&NAME_B, &NAMESPACE, 0x7, &[0]);
+        rs.add_variable(&NAME_A, &NAMESPACE, 0x7, &[0]);
+        assert_eq!(Ok((vec![0], 0x7)), cache.get_variable::<Vec<u8>>(&NAME_B, &NAMESPACE));
+        assert_eq!(Ok((vec![0x43], 0x7)), cache.get_variable::<Vec<u8>>(&NAME_C, &NAMESPACE));
+
+        cache.invalidate_all();
+        assert!(cache.is_empty());
+    }
+}
@@ -4,7 +4,78 @@ use alloc::vec::Vec;
 use fallible_streaming_iterator::FallibleStreamingIterator;
 use r_efi::efi::{self, Guid};
 
-use crate::RuntimeServices;
+use crate::{RuntimeServices, RuntimeServicesExt};
+
+/// Creates a null-terminated UCS-2 variable name from a string literal at compile time.
+///
+/// Evaluates to a `&'static [u16; N]`, which can be passed wherever a `&[u16]` name is expected.
+///
+/// ```
+/// # use runtime_services::ucs2;
+/// const BOOT_ORDER: &[u16] = ucs2!("BootOrder");
+/// assert_eq!("BootOrder\0".encode_utf16().collect::<Vec<_>>(), BOOT_ORDER);
+/// ```
+///
+/// Compilation fails if the string contains a null character or a character outside the Basic Multilingual Plane,
+/// which UCS-2 can not encode.
+#[macro_export]
+macro_rules! ucs2 {
+    ($name:expr) => {{
+        const NAME: [u16; $crate::variable_services::ucs2_len($name)] = $crate::variable_services::ucs2_encode($name);
+        &NAME
+    }};
+}
+
+/// Converts *name* to a null-terminated UCS-2 variable name.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if *name* contains a null character or a character outside the Basic
+/// Multilingual Plane, which UCS-2 can not encode.
+pub fn variable_name(name: &str) -> Result<Vec<u16>, efi::Status> {
+    if name.chars().any(|c| c == '\0' || c as u32 > u16::MAX as u32) {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+    Ok(name.encode_utf16().chain([0]).collect())
+}
+
+// Returns the UCS-2 encoding of the UTF-8 character at *idx* of *bytes* and the length of its UTF-8 encoding.
+const fn ucs2_char(bytes: &[u8], idx: usize) -> (u16, usize) {
+    match bytes[idx] {
+        0 => panic!("UCS-2 variable names can not contain null characters."),
+        b @ 0x01..=0x7F => (b as u16, 1),
+        b @ 0xC0..=0xDF => ((((b & 0x1F) as u16) << 6) | (bytes[idx + 1] & 0x3F) as u16, 2),
+        b @ 0xE0..=0xEF => {
+            ((((b & 0x0F) as u16) << 12) | (((bytes[idx + 1] & 0x3F) as u16) << 6) | (bytes[idx + 2] & 0x3F) as u16, 3)
+        }
+        _ => panic!("UCS-2 can not encode characters outside the Basic Multilingual Plane."),
+    }
+}
+
+/// Returns the length of the null-terminated UCS-2 encoding of *name*, used by [`ucs2!`].
+#[doc(hidden)]
+pub const fn ucs2_len(name: &str) -> usize {
+    let bytes = name.as_bytes();
+    let (mut idx, mut len) = (0, 1);
+    while idx < bytes.len() {
+        idx += ucs2_char(bytes, idx).1;
+        len += 1;
+    }
+    len
+}
+
+/// Returns the null-terminated UCS-2 encoding of *name*, used by [`ucs2!`].
+#[doc(hidden)]
+pub const fn ucs2_encode<const N: usize>(name: &str) -> [u16; N] {
+    let bytes = name.as_bytes();
+    let mut ucs2 = [0; N];
+    let (mut idx, mut len) = (0, 0);
+    while idx < bytes.len() {
+        let (c, size) = ucs2_char(bytes, idx);
+        ucs2[len] = c;
+        idx += size;
+        len += 1;
+    }
+    ucs2
+}
 
 /// Status information returned by [`RuntimeServices::get_variable_unchecked`]
 #[derive(Debug)]
@@ -38,13 +109,83 @@ pub struct VariableInfo {
     pub maximum_variable_size: u64,
 }
 
+/// Usage of the variable storage for some attributes, see [`RuntimeServices::variable_storage_report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VariableStorageReport {
+    /// The attributes the storage was queried for
+    pub attributes: u32,
+    /// The maximum size of the storage space available for the EFI variables with the attributes
+    pub maximum_variable_storage_size: u64,
+    /// The remaining size of the storage space available for EFI variables with the attributes
+    pub remaining_variable_storage_size: u64,
+    /// The maximum size of an individual EFI variable with the attributes
+    pub maximum_variable_size: u64,
+}
+
+impl VariableStorageReport {
+    /// Create a report from the [`VariableInfo`] of *attributes*.
+    pub fn new(attributes: u32, info: VariableInfo) -> Self {
+        Self {
+            attributes,
+            maximum_variable_storage_size: info.maximum_variable_storage_size,
+            remaining_variable_storage_size: info.remaining_variable_storage_size,
+            maximum_variable_size: info.maximum_variable_size,
+        }
+    }
+
+    /// Returns the size of the storage space in use.
+    pub fn used_variable_storage_size(&self) -> u64 {
+        self.maximum_variable_storage_size.saturating_sub(self.remaining_variable_storage_size)
+    }
+
+    /// Returns the percentage of the storage space in use, rounded down, 100 if there is no storage space.
+    pub fn percent_used(&self) -> u8 {
+        match self.maximum_variable_storage_size {
+            0 => 100,
+            maximum => (self.used_variable_storage_size() as u128 * 100 / maximum as u128).min(100) as u8,
+        }
+    }
+
+    /// Checks that at least *bytes* of storage space remain.
+    ///
+    /// The storage of a variable also holds its name and a header, which the firmware counts in the remaining size, so
+    /// *bytes* should include them, with some margin for the header whose size depends on the implementation.
+    pub fn ensure_space(&self, bytes: u64) -> Result<(), VariableStorageError> {
+        if bytes > self.remaining_variable_storage_size {
+            return Err(VariableStorageError::InsufficientSpace {
+                requested: bytes,
+                remaining: self.remaining_variable_storage_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Error of [`RuntimeServices::ensure_space`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableStorageError {
+    /// The storage could not be queried.
+    Query(efi::Status),
+    /// The remaining storage space is smaller than the requested size.
+    InsufficientSpace { requested: u64, remaining: u64 },
+}
+
+impl From<VariableStorageError> for efi::Status {
+    fn from(error: VariableStorageError) -> Self {
+        match error {
+            VariableStorageError::Query(status) => status,
+            VariableStorageError::InsufficientSpace { .. } => efi::Status::OUT_OF_RESOURCES,
+        }
+    }
+}
+
 /// Uniquely identifies a UEFI variable
 #[derive(Debug)]
 pub struct VariableIdentifier {
     /// The name of a UEFI variable
-    name: Vec<u16>,
+    pub name: Vec<u16>,
     /// The namespace of a UEFI variable
-    namespace: efi::Guid,
+    pub namespace: efi::Guid,
 }
 
 /// Provides a [`FallibleStreamingIterator`] over UEFI variable names
@@ -76,7 +217,7 @@ pub struct VariableIdentifier {
 /// }
 /// ```
 #[derive(Debug)]
-pub struct VariableNameIterator<'a, R: RuntimeServices> {
+pub struct VariableNameIterator<'a, R: RuntimeServices + ?Sized> {
     rs: &'a R,
 
     current: VariableIdentifier,
@@ -84,7 +225,7 @@ pub struct VariableNameIterator<'a, R: RuntimeServices> {
     finished: bool,
 }
 
-impl<'a, R: RuntimeServices> VariableNameIterator<'a, R> {
+impl<'a, R: RuntimeServices + ?Sized> VariableNameIterator<'a, R> {
     /// Produce a new iterator from the beginning of the UEFI variable list
     pub fn new_from_first(runtime_services: &'a R) -> Self {
         Self {
@@ -117,7 +258,7 @@ impl<'a, R: RuntimeServices> VariableNameIterator<'a, R> {
     }
 }
 
-impl<'a, R: RuntimeServices> FallibleStreamingIterator for VariableNameIterator<'a, R> {
+impl<'a, R: RuntimeServices + ?Sized> FallibleStreamingIterator for VariableNameIterator<'a, R> {
     type Item = VariableIdentifier;
     type Error = efi::Status;
 
@@ -155,6 +296,98 @@ impl<'a, R: RuntimeServices> FallibleStreamingIterator for VariableNameIterator<
     }
 }
 
+/// A UEFI variable along with its attributes and data
+#[derive(Debug)]
+pub struct Variable {
+    /// The name of a UEFI variable
+    pub name: Vec<u16>,
+    /// The namespace of a UEFI variable
+    pub namespace: efi::Guid,
+    /// The attributes of a UEFI variable
+    pub attributes: u32,
+    /// The data of a UEFI variable
+    pub data: Vec<u8>,
+}
+
+/// Provides a [`FallibleStreamingIterator`] over UEFI variables, including their attributes and data
+///
+/// Builds on [`VariableNameIterator`] and calls [`RuntimeServices::get_variable`] for each variable name.
+///
+/// Produces an EFI status on error.
+///
+/// # Examples
+///
+/// ## Dumping all UEFI variables with a payload of at most 1KB
+/// ```ignore
+/// let mut iter = VariableIterator::new_from_first(runtime_services).with_max_data_size(0x400);
+/// while let Some(variable) = iter.next()? {
+///     some_function(&variable.name, &variable.namespace, variable.attributes, &variable.data);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct VariableIterator<'a, R: RuntimeServices + ?Sized> {
+    name_iterator: VariableNameIterator<'a, R>,
+    max_data_size: Option<usize>,
+    current: Option<Variable>,
+}
+
+impl<'a, R: RuntimeServices + ?Sized> VariableIterator<'a, R> {
+    /// Produce a new iterator from the beginning of the UEFI variable list
+    pub fn new_from_first(runtime_services: &'a R) -> Self {
+        Self {
+            name_iterator: VariableNameIterator::new_from_first(runtime_services),
+            max_data_size: None,
+            current: None,
+        }
+    }
+
+    /// Produce a new iterator, starting after a given variable
+    pub fn new_from_variable(name: &[u16], namespace: &efi::Guid, runtime_services: &'a R) -> Self {
+        Self {
+            name_iterator: VariableNameIterator::new_from_variable(name, namespace, runtime_services),
+            max_data_size: None,
+            current: None,
+        }
+    }
+
+    /// Skip the variables whose data is larger than `max_data_size` bytes
+    pub fn with_max_data_size(mut self, max_data_size: usize) -> Self {
+        self.max_data_size = Some(max_data_size);
+        self
+    }
+}
+
+impl<'a, R: RuntimeServices + ?Sized> FallibleStreamingIterator for VariableIterator<'a, R> {
+    type Item = Variable;
+    type Error = efi::Status;
+
+    fn advance(&mut self) -> Result<(), Self::Error> {
+        self.current = None;
+        loop {
+            self.name_iterator.advance()?;
+            let Some(identifier) = self.name_iterator.get() else {
+                return Ok(());
+            };
+            let rs = self.name_iterator.rs;
+
+            let (data_size, _) = rs.get_variable_size_and_attributes(&identifier.name, &identifier.namespace)?;
+            if self.max_data_size.is_some_and(|max_data_size| data_size > max_data_size) {
+                continue;
+            }
+
+            let (data, attributes) =
+                rs.get_variable::<Vec<u8>>(&identifier.name, &identifier.namespace, Some(data_size))?;
+            self.current =
+                Some(Variable { name: identifier.name.clone(), namespace: identifier.namespace, attributes, data });
+            return Ok(());
+        }
+    }
+
+    fn get(&self) -> Option<&Self::Item> {
+        self.current.as_ref()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use efi;
@@ -163,7 +396,25 @@ mod test {
     use crate::StandardRuntimeServices;
     use core::mem;
 
-    use crate::test::*;
+    use crate::{
+        test::*,
+        testing::{FakeRuntimeServices, FakeVariable},
+    };
+
+    #[test]
+    fn test_ucs2() {
+        const BOOT_ORDER: &[u16] = crate::ucs2!("BootOrder");
+        assert_eq!("BootOrder\0".encode_utf16().collect::<Vec<_>>(), BOOT_ORDER);
+        assert_eq!([0x00E9, 0x20AC, 0], *crate::ucs2!("\u{E9}\u{20AC}"));
+        assert_eq!([0], *crate::ucs2!(""));
+    }
+
+    #[test]
+    fn test_variable_name() {
+        assert_eq!(Ok(crate::ucs2!("Boot\u{E9}\u{20AC}").to_vec()), variable_name("Boot\u{E9}\u{20AC}"));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), variable_name("Boot\0"));
+        assert_eq!(Err(efi::Status::INVALID_PARAMETER), variable_name("Boot\u{1F600}"));
+    }
 
     #[test]
     fn test_variable_name_iterator_from_first() {
@@ -214,4 +465,79 @@ mod test {
         assert!(status.is_ok());
         assert!(status.unwrap().is_none());
     }
+
+    #[test]
+    fn test_variable_iterator() {
+        let rs = FakeRuntimeServices::with_variables(vec![
+            FakeVariable::new(&DUMMY_FIRST_NAME, &DUMMY_FIRST_NAMESPACE, DUMMY_ATTRIBUTES, &[1, 2, 3, 4]),
+            FakeVariable::new(&DUMMY_SECOND_NAME, &DUMMY_SECOND_NAMESPACE, 0x7, &[5; 0x20]),
+        ]);
+
+        let mut iter = VariableIterator::new_from_first(&rs);
+
+        let variable = iter.next().unwrap().unwrap();
+        assert_eq!(variable.name, DUMMY_FIRST_NAME);
+        assert_eq!(variable.namespace, DUMMY_FIRST_NAMESPACE);
+        assert_eq!(variable.attributes, DUMMY_ATTRIBUTES);
+        assert_eq!(variable.data, [1, 2, 3, 4]);
+
+        let variable = iter.next().unwrap().unwrap();
+        assert_eq!(variable.name, DUMMY_SECOND_NAME);
+        assert_eq!(variable.namespace, DUMMY_SECOND_NAMESPACE);
+        assert_eq!(variable.attributes, 0x7);
+        assert_eq!(variable.data, [5; 0x20]);
+
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_variable_iterator_with_max_data_size() {
+        let rs = FakeRuntimeServices::with_variables(vec![
+            FakeVariable::new(&DUMMY_FIRST_NAME, &DUMMY_FIRST_NAMESPACE, DUMMY_ATTRIBUTES, &[0; 0x20]),
+            FakeVariable::new(&DUMMY_SECOND_NAME, &DUMMY_SECOND_NAMESPACE, DUMMY_ATTRIBUTES, &[1, 2]),
+        ]);
+
+        let mut iter = VariableIterator::new_from_first(&rs).with_max_data_size(0x10);
+
+        let variable = iter.next().unwrap().unwrap();
+        assert_eq!(variable.name, DUMMY_SECOND_NAME);
+        assert_eq!(variable.data, [1, 2]);
+
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_variable_storage_report() {
+        let rs = FakeRuntimeServices::new().with_storage_limits(200, 64);
+        rs.add_variable(&[0x41, 0x00], &DUMMY_FIRST_NAMESPACE, 0x7, &[0u8; 46]);
+
+        let report = rs.variable_storage_report(0x7).unwrap();
+        assert_eq!(
+            VariableStorageReport {
+                attributes: 0x7,
+                maximum_variable_storage_size: 200,
+                remaining_variable_storage_size: 150,
+                maximum_variable_size: 64,
+            },
+            report
+        );
+        assert_eq!(50, report.used_variable_storage_size());
+        assert_eq!(25, report.percent_used());
+
+        let empty =
+            VariableStorageReport { maximum_variable_storage_size: 0, remaining_variable_storage_size: 0, ..report };
+        assert_eq!(100, empty.percent_used());
+    }
+
+    #[test]
+    fn test_ensure_space() {
+        let rs = FakeRuntimeServices::new().with_storage_limits(200, 64);
+        rs.add_variable(&[0x41, 0x00], &DUMMY_FIRST_NAMESPACE, 0x7, &[0u8; 46]);
+
+        assert_eq!(150, rs.ensure_space(150, 0x7).unwrap().remaining_variable_storage_size);
+        let error = rs.ensure_space(151, 0x7).unwrap_err();
+        assert_eq!(VariableStorageError::InsufficientSpace { requested: 151, remaining: 150 }, error);
+        assert_eq!(efi::Status::OUT_OF_RESOURCES, efi::Status::from(error));
+        assert_eq!(efi::Status::UNSUPPORTED, efi::Status::from(VariableStorageError::Query(efi::Status::UNSUPPORTED)));
+    }
 }
@@ -0,0 +1,251 @@
+//! Writes of related UEFI variables applied together.
+//!
+//! Updating several variables that depend on each other (e.g. a boot option and `BootOrder`) can leave them
+//! inconsistent if one of the writes fails. A [`VariableTransaction`] stages the writes in memory and commits them in
+//! order; if a write fails, the variables already written are restored to their previous value.
+//!
+//! ```ignore
+//! let mut transaction = VariableTransaction::new();
+//! transaction
+//!     .set_variable(&boot0001, &EFI_GLOBAL_VARIABLE, attributes, &load_option)
+//!     .set_variable(&boot_order, &EFI_GLOBAL_VARIABLE, attributes, &new_boot_order);
+//! transaction.commit(&RUNTIME_SERVICES).status()?;
+//! ```
+
+use alloc::vec::Vec;
+
+use r_efi::efi;
+
+use crate::{RuntimeServices, RuntimeServicesExt};
+
+#[derive(Debug)]
+struct StagedWrite {
+    // Null-terminated.
+    name: Vec<u16>,
+    namespace: efi::Guid,
+    attributes: u32,
+    data: Vec<u8>,
+}
+
+/// Outcome of a staged write after a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// The variable was written.
+    Applied,
+    /// The write failed, the writes after it were not attempted.
+    Failed(efi::Status),
+    /// The variable was written, then restored to its previous value after a later write failed.
+    RolledBack,
+    /// The variable was written, and restoring its previous value after a later write failed also failed.
+    RollbackFailed(efi::Status),
+    /// The write was not attempted because an earlier write failed.
+    NotAttempted,
+}
+
+/// Report of a staged write after a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteReport {
+    /// The name of the variable, without the null terminator.
+    pub name: Vec<u16>,
+    pub namespace: efi::Guid,
+    pub outcome: WriteOutcome,
+}
+
+/// Report of a [`VariableTransaction::commit`], with the outcome of every write in the order they were staged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionReport {
+    pub writes: Vec<WriteReport>,
+}
+
+impl TransactionReport {
+    /// Returns true if every write was applied.
+    pub fn is_committed(&self) -> bool {
+        self.writes.iter().all(|w| w.outcome == WriteOutcome::Applied)
+    }
+
+    /// Returns true if the variables were left in a state that is neither the previous nor the committed one.
+    pub fn is_inconsistent(&self) -> bool {
+        self.writes.iter().any(|w| matches!(w.outcome, WriteOutcome::RollbackFailed(_)))
+    }
+
+    /// Returns the status of the write that failed, if any.
+    pub fn status(&self) -> Result<(), efi::Status> {
+        match self.writes.iter().find_map(|w| match w.outcome {
+            WriteOutcome::Failed(status) => Some(status),
+            _ => None,
+        }) {
+            Some(status) => Err(status),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Variable writes staged in memory until committed.
+#[derive(Debug, Default)]
+pub struct VariableTransaction {
+    writes: Vec<StagedWrite>,
+}
+
+impl VariableTransaction {
+    /// Create an empty transaction.
+    pub fn new() -> Self {
+        Self { writes: Vec::new() }
+    }
+
+    /// Stages a write of a UEFI variable, the name may or may not be null-terminated.
+    ///
+    /// Like [`RuntimeServices::set_variable`], empty data or zero attributes delete the variable.
+    pub fn set_variable(&mut self, name: &[u16], namespace: &efi::Guid, attributes: u32, data: &[u8]) -> &mut Self {
+        let mut name = name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())].to_vec();
+        name.push(0);
+        self.writes.push(StagedWrite { name, namespace: *namespace, attributes, data: data.to_vec() });
+        self
+    }
+
+    /// Stages the deletion of a UEFI variable. Deleting a variable that does not exist is not an error.
+    pub fn delete_variable(&mut self, name: &[u16], namespace: &efi::Guid) -> &mut Self {
+        self.set_variable(name, namespace, 0, &[])
+    }
+
+    /// Returns the number of staged writes.
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Returns true if no write is staged.
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Writes the staged variables in order.
+    ///
+    /// The previous value of each variable is read before it is written. If a write fails, the variables already
+    /// written are restored in reverse order and the remaining writes are not attempted. Restoring a variable can
+    /// itself fail (e.g. for authenticated variables), which is reported by [`TransactionReport::is_inconsistent`].
+    pub fn commit<R: RuntimeServices + ?Sized>(self, runtime_services: &R) -> TransactionReport {
+        let mut reports: Vec<WriteReport> = self
+            .writes
+            .iter()
+            .map(|w| WriteReport {
+                name: w.name[..w.name.len() - 1].to_vec(),
+                namespace: w.namespace,
+                outcome: WriteOutcome::NotAttempted,
+            })
+            .collect();
+
+        // Previous (data, attributes) of the variables written so far, `None` if they did not exist.
+        let mut previous_values: Vec<Option<(Vec<u8>, u32)>> = Vec::with_capacity(self.writes.len());
+        for (write, report) in self.writes.iter().zip(reports.iter_mut()) {
+            let result = match runtime_services.get_variable::<Vec<u8>>(&write.name, &write.namespace, None) {
+                Ok(previous) => Ok(Some(previous)),
+                Err(efi::Status::NOT_FOUND) => Ok(None),
+                Err(status) => Err(status),
+            }
+            .and_then(|previous| {
+                match runtime_services.set_variable(&write.name, &write.namespace, write.attributes, &write.data) {
+                    Err(efi::Status::NOT_FOUND) if previous.is_none() => Ok(previous),
+                    result => result.map(|_| previous),
+                }
+            });
+
+            match result {
+                Ok(previous) => {
+                    report.outcome = WriteOutcome::Applied;
+                    previous_values.push(previous);
+                }
+                Err(status) => {
+                    report.outcome = WriteOutcome::Failed(status);
+                    break;
+                }
+            }
+        }
+
+        if previous_values.len() < self.writes.len() {
+            for (idx, previous) in previous_values.into_iter().enumerate().rev() {
+                let write = &self.writes[idx];
+                let result = match previous {
+                    Some((data, attributes)) => {
+                        runtime_services.set_variable(&write.name, &write.namespace, attributes, &data)
+                    }
+                    None => match runtime_services.set_variable(&write.name, &write.namespace, 0, &Vec::<u8>::new()) {
+                        Err(efi::Status::NOT_FOUND) => Ok(()),
+                        result => result,
+                    },
+                };
+                reports[idx].outcome = match result {
+                    Ok(()) => WriteOutcome::RolledBack,
+                    Err(status) => WriteOutcome::RollbackFailed(status),
+                };
+            }
+        }
+
+        TransactionReport { writes: reports }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{FakeRuntimeServices, FakeVariable};
+
+    const NAMESPACE: efi::Guid = efi::Guid::from_fields(0xA, 0, 0, 0, 0, &[0; 6]);
+    const NAME_A: [u16; 2] = [0x41, 0x00];
+    const NAME_B: [u16; 2] = [0x42, 0x00];
+    const NAME_C: [u16; 2] = [0x43, 0x00];
+
+    #[test]
+    fn test_commit() {
+        let rs = FakeRuntimeServices::new();
+        rs.add_variable(&NAME_A, &NAMESPACE, 0x7, &[1]);
+        rs.add_variable(&NAME_C, &NAMESPACE, 0x7, &[3]);
+
+        let mut transaction = VariableTransaction::new();
+        transaction
+            .set_variable(&NAME_A, &NAMESPACE, 0x7, &[2])
+            .set_variable(&NAME_B[..1], &NAMESPACE, 0x7, &[4])
+            .delete_variable(&NAME_C, &NAMESPACE)
+            .delete_variable(&[0x44], &NAMESPACE);
+        assert_eq!(4, transaction.len());
+
+        let report = transaction.commit(&rs);
+        assert!(report.is_committed());
+        assert_eq!(Ok(()), report.status());
+        assert_eq!(
+            vec![(vec![0x41], WriteOutcome::Applied), (vec![0x42], WriteOutcome::Applied)],
+            report.writes[..2].iter().map(|w| (w.name.clone(), w.outcome)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![FakeVariable::new(&NAME_A, &NAMESPACE, 0x7, &[2]), FakeVariable::new(&NAME_B, &NAMESPACE, 0x7, &[4])],
+            *rs.variables()
+        );
+    }
+
+    #[test]
+    fn test_failed_write_is_rolled_back() {
+        let rs = FakeRuntimeServices::new().with_storage_limits(0x100, 0x10);
+        rs.add_variable(&NAME_A, &NAMESPACE, 0x7, &[1]);
+        let original = rs.variables().clone();
+
+        let mut transaction = VariableTransaction::new();
+        transaction
+            .set_variable(&NAME_A, &NAMESPACE, 0x3, &[2])
+            .set_variable(&NAME_B, &NAMESPACE, 0x7, &[4])
+            .set_variable(&NAME_C, &NAMESPACE, 0x7, &[0; 0x11])
+            .delete_variable(&NAME_A, &NAMESPACE);
+
+        let report = transaction.commit(&rs);
+        assert!(!report.is_committed());
+        assert!(!report.is_inconsistent());
+        assert_eq!(Err(efi::Status::OUT_OF_RESOURCES), report.status());
+        assert_eq!(
+            vec![
+                WriteOutcome::RolledBack,
+                WriteOutcome::RolledBack,
+                WriteOutcome::Failed(efi::Status::OUT_OF_RESOURCES),
+                WriteOutcome::NotAttempted
+            ],
+            report.writes.iter().map(|w| w.outcome).collect::<Vec<_>>()
+        );
+        assert_eq!(original, *rs.variables());
+    }
+}
@@ -3,6 +3,11 @@
 extern crate alloc;
 
 pub mod macros;
+pub mod prelude;
+pub mod status;
+
+#[cfg(all(feature = "boot_services", feature = "perf_timer"))]
+pub mod trace;
 
 #[cfg(feature = "boot_services")]
 pub use boot_services;
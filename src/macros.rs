@@ -22,3 +22,168 @@ macro_rules! function {
         name.strip_suffix("::f").unwrap()
     }};
 }
+
+#[doc(hidden)]
+pub mod __private {
+    pub use r_efi::efi;
+
+    /// Conversion of the values accepted by [`efi_try!`](crate::efi_try) to a result.
+    pub trait IntoEfiResult {
+        type Ok;
+        fn into_efi_result(self) -> Result<Self::Ok, efi::Status>;
+    }
+
+    impl IntoEfiResult for efi::Status {
+        type Ok = ();
+        fn into_efi_result(self) -> Result<(), efi::Status> {
+            match self {
+                s if s.is_error() => Err(s),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    impl<T> IntoEfiResult for Result<T, efi::Status> {
+        type Ok = T;
+        fn into_efi_result(self) -> Result<T, efi::Status> {
+            self
+        }
+    }
+
+    #[cfg(feature = "error_context")]
+    pub use log;
+}
+
+#[cfg(feature = "error_context")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __efi_error_context {
+    ($status:expr) => {
+        $crate::macros::__private::log::error!("{}: {}", $crate::function!(), $crate::status::DisplayStatus($status))
+    };
+    ($status:expr, $($arg:tt)+) => {
+        $crate::macros::__private::log::error!(
+            "{}: {}: {}",
+            $crate::function!(),
+            $crate::status::DisplayStatus($status),
+            format_args!($($arg)+)
+        )
+    };
+}
+
+#[cfg(not(feature = "error_context"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __efi_error_context {
+    ($status:expr) => {
+        let _ = $status;
+    };
+    ($status:expr, $($arg:tt)+) => {
+        let _ = $status;
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+/// Returns early from the function if an `efi::Status` or a `Result<T, efi::Status>` is an error.
+///
+/// Evaluates to `()` for a status that is not an error (warnings included) and to `T` for an `Ok(T)`. The error is
+/// returned with `Err(status.into())`, so the function can return any error type implementing `From<efi::Status>`.
+///
+/// An optional format string gives the context of the error, logged with the function name when the `error_context`
+/// feature is enabled.
+///
+/// # Example
+/// ```
+/// use mu_rust_helpers::efi_try;
+/// use r_efi::efi;
+///
+/// fn demo_fn(status: efi::Status, value: Result<u32, efi::Status>) -> Result<u32, efi::Status> {
+///     efi_try!(status);
+///     Ok(efi_try!(value, "reading value for {}", "demo"))
+/// }
+///
+/// assert_eq!(Ok(1), demo_fn(efi::Status::WARN_UNKNOWN_GLYPH, Ok(1)));
+/// assert_eq!(Err(efi::Status::NOT_FOUND), demo_fn(efi::Status::NOT_FOUND, Ok(1)));
+/// assert_eq!(Err(efi::Status::ABORTED), demo_fn(efi::Status::SUCCESS, Err(efi::Status::ABORTED)));
+/// ```
+#[macro_export]
+macro_rules! efi_try {
+    ($value:expr $(,)?) => {
+        match $crate::macros::__private::IntoEfiResult::into_efi_result($value) {
+            Ok(value) => value,
+            Err(status) => {
+                $crate::__efi_error_context!(status);
+                return Err(status.into());
+            }
+        }
+    };
+    ($value:expr, $($arg:tt)+) => {
+        match $crate::macros::__private::IntoEfiResult::into_efi_result($value) {
+            Ok(value) => value,
+            Err(status) => {
+                $crate::__efi_error_context!(status, $($arg)+);
+                return Err(status.into());
+            }
+        }
+    };
+}
+
+/// Returns early from the function with an `efi::Status` error.
+///
+/// Like [`efi_try!`], the error is converted with `into` and an optional format string gives its context.
+///
+/// # Example
+/// ```
+/// use mu_rust_helpers::efi_bail;
+/// use r_efi::efi;
+///
+/// fn demo_fn() -> Result<(), efi::Status> {
+///     efi_bail!(efi::Status::UNSUPPORTED, "demo is not supported");
+/// }
+///
+/// assert_eq!(Err(efi::Status::UNSUPPORTED), demo_fn());
+/// ```
+#[macro_export]
+macro_rules! efi_bail {
+    ($status:expr $(,)?) => {{
+        let status: $crate::macros::__private::efi::Status = $status;
+        $crate::__efi_error_context!(status);
+        return Err(status.into());
+    }};
+    ($status:expr, $($arg:tt)+) => {{
+        let status: $crate::macros::__private::efi::Status = $status;
+        $crate::__efi_error_context!(status, $($arg)+);
+        return Err(status.into());
+    }};
+}
+
+/// Returns early from the function with an `efi::Status` error if a condition is false.
+///
+/// # Example
+/// ```
+/// use mu_rust_helpers::ensure_efi;
+/// use r_efi::efi;
+///
+/// fn demo_fn(buffer: &[u8]) -> Result<u8, efi::Status> {
+///     ensure_efi!(!buffer.is_empty(), efi::Status::INVALID_PARAMETER, "empty buffer");
+///     Ok(buffer[0])
+/// }
+///
+/// assert_eq!(Ok(1), demo_fn(&[1]));
+/// assert_eq!(Err(efi::Status::INVALID_PARAMETER), demo_fn(&[]));
+/// ```
+#[macro_export]
+macro_rules! ensure_efi {
+    ($cond:expr, $status:expr $(,)?) => {
+        if !$cond {
+            $crate::efi_bail!($status);
+        }
+    };
+    ($cond:expr, $status:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::efi_bail!($status, $($arg)+);
+        }
+    };
+}
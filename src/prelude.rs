@@ -0,0 +1,43 @@
+//! The traits, types and macros most modules using the helpers need, so a single glob import replaces the paths of
+//! each sub-crate.
+//!
+//! ```
+//! use mu_rust_helpers::prelude::*;
+//!
+//! fn read_timeout<B: BootServices, R: RuntimeServices>(bs: &B, rs: &R) -> Result<u16, efi::Status> {
+//!     let tpl = Tpl::CALLBACK;
+//!     ensure_efi!(tpl <= Tpl::NOTIFY, efi::Status::INVALID_PARAMETER);
+//!     let (timeout, _) = bs.with_tpl_try(tpl, || {
+//!         rs.get_variable::<Vec<u8>>(ucs2!("Timeout"), &GLOBAL_VARIABLE_GUID, None)
+//!     })?;
+//!     Ok(u16::from_le_bytes([timeout[0], timeout[1]]))
+//! }
+//! ```
+//!
+//! Only the items of the enabled sub-crates are exported.
+
+pub use r_efi::efi;
+
+pub use crate::{efi_bail, efi_try, ensure_efi, function, status::DisplayStatus};
+
+#[cfg(feature = "boot_services")]
+pub use boot_services::{
+    allocation::{AllocType, MemoryType},
+    boxed::BootServicesBox,
+    c_ptr::{CMutPtr, CMutRef, CPtr, CRef},
+    event::{EventTimerType, EventType},
+    protocol_handler::Protocol,
+    tpl::{Tpl, TplGuard},
+    BootServices, BootServicesExt, StandardBootServices,
+};
+
+#[cfg(feature = "runtime_services")]
+pub use runtime_services::{
+    os_indications::GLOBAL_VARIABLE_GUID, ucs2, RuntimeServices, RuntimeServicesExt, StandardRuntimeServices,
+};
+
+#[cfg(feature = "tpl_mutex")]
+pub use tpl_mutex::{TplMutex, TplMutexGuard};
+
+#[cfg(feature = "guid")]
+pub use guid::{define_guids, guid};
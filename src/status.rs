@@ -0,0 +1,125 @@
+//! Names of the EFI_STATUS codes, so logs show `NOT_FOUND` rather than `0x800000000000000E`.
+//!
+//! ```
+//! use mu_rust_helpers::status::{status_name, DisplayStatus};
+//! use r_efi::efi;
+//!
+//! assert_eq!("NOT_FOUND", status_name(efi::Status::NOT_FOUND));
+//! assert_eq!("WARN_STALE_DATA", format!("{}", DisplayStatus(efi::Status::WARN_STALE_DATA)));
+//! ```
+//!
+//! [UEFI Spec Documentation: Appendix D - Status Codes](https://uefi.org/specs/UEFI/2.10/Apx_D_Status_Codes.html)
+
+use core::fmt;
+
+use r_efi::efi;
+
+/// Returns the name of *status* in the specification without the `EFI_` prefix, e.g. `NOT_FOUND`.
+///
+/// Returns `UNKNOWN_ERROR` or `UNKNOWN_WARNING` for the codes not defined by the specification, such as OEM codes, see
+/// [`DisplayStatus`] to display their value.
+pub fn status_name(status: efi::Status) -> &'static str {
+    match status {
+        efi::Status::SUCCESS => "SUCCESS",
+        efi::Status::LOAD_ERROR => "LOAD_ERROR",
+        efi::Status::INVALID_PARAMETER => "INVALID_PARAMETER",
+        efi::Status::UNSUPPORTED => "UNSUPPORTED",
+        efi::Status::BAD_BUFFER_SIZE => "BAD_BUFFER_SIZE",
+        efi::Status::BUFFER_TOO_SMALL => "BUFFER_TOO_SMALL",
+        efi::Status::NOT_READY => "NOT_READY",
+        efi::Status::DEVICE_ERROR => "DEVICE_ERROR",
+        efi::Status::WRITE_PROTECTED => "WRITE_PROTECTED",
+        efi::Status::OUT_OF_RESOURCES => "OUT_OF_RESOURCES",
+        efi::Status::VOLUME_CORRUPTED => "VOLUME_CORRUPTED",
+        efi::Status::VOLUME_FULL => "VOLUME_FULL",
+        efi::Status::NO_MEDIA => "NO_MEDIA",
+        efi::Status::MEDIA_CHANGED => "MEDIA_CHANGED",
+        efi::Status::NOT_FOUND => "NOT_FOUND",
+        efi::Status::ACCESS_DENIED => "ACCESS_DENIED",
+        efi::Status::NO_RESPONSE => "NO_RESPONSE",
+        efi::Status::NO_MAPPING => "NO_MAPPING",
+        efi::Status::TIMEOUT => "TIMEOUT",
+        efi::Status::NOT_STARTED => "NOT_STARTED",
+        efi::Status::ALREADY_STARTED => "ALREADY_STARTED",
+        efi::Status::ABORTED => "ABORTED",
+        efi::Status::ICMP_ERROR => "ICMP_ERROR",
+        efi::Status::TFTP_ERROR => "TFTP_ERROR",
+        efi::Status::PROTOCOL_ERROR => "PROTOCOL_ERROR",
+        efi::Status::INCOMPATIBLE_VERSION => "INCOMPATIBLE_VERSION",
+        efi::Status::SECURITY_VIOLATION => "SECURITY_VIOLATION",
+        efi::Status::CRC_ERROR => "CRC_ERROR",
+        efi::Status::END_OF_MEDIA => "END_OF_MEDIA",
+        efi::Status::END_OF_FILE => "END_OF_FILE",
+        efi::Status::INVALID_LANGUAGE => "INVALID_LANGUAGE",
+        efi::Status::COMPROMISED_DATA => "COMPROMISED_DATA",
+        efi::Status::IP_ADDRESS_CONFLICT => "IP_ADDRESS_CONFLICT",
+        efi::Status::HTTP_ERROR => "HTTP_ERROR",
+        efi::Status::NETWORK_UNREACHABLE => "NETWORK_UNREACHABLE",
+        efi::Status::HOST_UNREACHABLE => "HOST_UNREACHABLE",
+        efi::Status::PROTOCOL_UNREACHABLE => "PROTOCOL_UNREACHABLE",
+        efi::Status::PORT_UNREACHABLE => "PORT_UNREACHABLE",
+        efi::Status::CONNECTION_FIN => "CONNECTION_FIN",
+        efi::Status::CONNECTION_RESET => "CONNECTION_RESET",
+        efi::Status::CONNECTION_REFUSED => "CONNECTION_REFUSED",
+        efi::Status::WARN_UNKNOWN_GLYPH => "WARN_UNKNOWN_GLYPH",
+        efi::Status::WARN_DELETE_FAILURE => "WARN_DELETE_FAILURE",
+        efi::Status::WARN_WRITE_FAILURE => "WARN_WRITE_FAILURE",
+        efi::Status::WARN_BUFFER_TOO_SMALL => "WARN_BUFFER_TOO_SMALL",
+        efi::Status::WARN_STALE_DATA => "WARN_STALE_DATA",
+        efi::Status::WARN_FILE_SYSTEM => "WARN_FILE_SYSTEM",
+        efi::Status::WARN_RESET_REQUIRED => "WARN_RESET_REQUIRED",
+        status if status.is_error() => "UNKNOWN_ERROR",
+        _ => "UNKNOWN_WARNING",
+    }
+}
+
+/// Wrapper of an [`efi::Status`] displaying its name, or its value if the specification does not define it.
+///
+/// Debug formatting is the same as Display formatting, so the wrapper can be used in `{:?}` formatted errors.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DisplayStatus(pub efi::Status);
+
+impl fmt::Display for DisplayStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match status_name(self.0) {
+            name if name.starts_with("UNKNOWN_") => write!(f, "{:#X}", self.0.as_usize()),
+            name => f.write_str(name),
+        }
+    }
+}
+
+impl fmt::Debug for DisplayStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<efi::Status> for DisplayStatus {
+    fn from(status: efi::Status) -> Self {
+        Self(status)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_status_name() {
+        assert_eq!("SUCCESS", status_name(efi::Status::SUCCESS));
+        assert_eq!("HTTP_ERROR", status_name(efi::Status::HTTP_ERROR));
+        assert_eq!("CONNECTION_REFUSED", status_name(efi::Status::CONNECTION_REFUSED));
+        assert_eq!("WARN_RESET_REQUIRED", status_name(efi::Status::WARN_RESET_REQUIRED));
+        assert_eq!("UNKNOWN_ERROR", status_name(efi::Status::from_usize(0x800000000000001D)));
+        assert_eq!("UNKNOWN_WARNING", status_name(efi::Status::from_usize(8)));
+    }
+
+    #[test]
+    fn test_display_status() {
+        assert_eq!("NOT_FOUND", format!("{}", DisplayStatus(efi::Status::NOT_FOUND)));
+        assert_eq!("Err(NOT_FOUND)", format!("{:?}", Err::<(), _>(DisplayStatus::from(efi::Status::NOT_FOUND))));
+        assert_eq!("0xC0000000000000FF", format!("{}", DisplayStatus(efi::Status::from_usize(0xC0000000000000FF))));
+        assert_eq!("0x8", format!("{}", DisplayStatus(efi::Status::from_usize(8))));
+    }
+}
@@ -0,0 +1,246 @@
+//! Timestamped trace records shared with external tools.
+//!
+//! A [`TraceBuffer`] is a ring buffer of fixed-size [`TraceRecord`] allocated from pages and published in the system
+//! configuration table under [`TRACE_BUFFER_TABLE_GUID`], so debuggers and OS tools can retrieve the trace after boot.
+//!
+//! The memory starts with a [`TraceBufferHeader`] followed by `capacity` records. The record for the n-th trace event
+//! is at index `n % capacity`, `write_count` in the header is the total number of events traced.
+//!
+//! ```ignore
+//! let trace = TraceBuffer::<Arch>::new(&BOOT_SERVICES, MemoryType::RUNTIME_SERVICES_DATA, 1024)?;
+//! trace.record(MY_DRIVER_ENTRY_ID, [0, 0]);
+//! ```
+
+use core::{
+    marker::PhantomData,
+    mem,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use boot_services::{
+    allocation::{AllocType, MemoryType},
+    BootServices,
+};
+use perf_timer::ArchFunctionality;
+use r_efi::efi;
+
+/// GUID of the configuration table pointing to the [`TraceBufferHeader`].
+pub const TRACE_BUFFER_TABLE_GUID: efi::Guid =
+    efi::Guid::from_fields(0x5c0a9e7d, 0x3b1f, 0x4c27, 0x9a, 0x41, &[0x6e, 0x2d, 0x8b, 0x17, 0xc4, 0x53]);
+
+/// Signature of the [`TraceBufferHeader`], "TRCB".
+pub const TRACE_BUFFER_SIGNATURE: u32 = u32::from_le_bytes(*b"TRCB");
+
+/// Version of the [`TraceBufferHeader`] layout.
+pub const TRACE_BUFFER_VERSION: u32 = 1;
+
+const UEFI_PAGE_SIZE: usize = 0x1000;
+
+/// Header of the trace buffer memory, followed by the records.
+#[repr(C)]
+#[derive(Debug)]
+pub struct TraceBufferHeader {
+    pub signature: u32,
+    pub version: u32,
+    /// Frequency in Hz of the counter used for the record timestamps.
+    pub frequency: u64,
+    /// Number of records in the ring buffer.
+    pub capacity: u32,
+    /// Size in bytes of a record.
+    pub record_size: u32,
+    /// Total number of records written.
+    pub write_count: AtomicU64,
+}
+
+/// A trace record.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TraceRecord {
+    /// Value of the performance counter when the record was written.
+    pub timestamp: u64,
+    /// Identifier of the trace event, its meaning is defined by the producer.
+    pub id: u32,
+    pub reserved: u32,
+    /// Data associated with the trace event.
+    pub data: [u64; 2],
+}
+
+/// Ring buffer of trace records published through the configuration table.
+pub struct TraceBuffer<A: ArchFunctionality> {
+    header: NonNull<TraceBufferHeader>,
+    _arch: PhantomData<A>,
+}
+
+// SAFETY: Records are reserved atomically, each slot is written by a single writer.
+unsafe impl<A: ArchFunctionality> Sync for TraceBuffer<A> {}
+unsafe impl<A: ArchFunctionality> Send for TraceBuffer<A> {}
+
+impl<A: ArchFunctionality> TraceBuffer<A> {
+    /// Allocate a trace buffer of *capacity* records from pages of *memory_type* and publish it in the configuration
+    /// table.
+    ///
+    /// Once the table is installed the memory is never freed so it stays available to external tools, use a memory
+    /// type preserved after boot (e.g. [`MemoryType::RUNTIME_SERVICES_DATA`]) for the trace to survive exit boot
+    /// services.
+    ///
+    /// Returns `efi::Status::INVALID_PARAMETER` if *capacity* is 0, or `efi::Status::OUT_OF_RESOURCES` if the size of
+    /// the buffer does not fit in the address space.
+    pub fn new<B: BootServices>(
+        boot_services: &B,
+        memory_type: MemoryType,
+        capacity: u32,
+    ) -> Result<Self, efi::Status> {
+        if capacity == 0 {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        let size = (capacity as usize)
+            .checked_mul(mem::size_of::<TraceRecord>())
+            .and_then(|size| size.checked_add(mem::size_of::<TraceBufferHeader>()))
+            .ok_or(efi::Status::OUT_OF_RESOURCES)?;
+        let nb_pages = size.div_ceil(UEFI_PAGE_SIZE);
+        let address = boot_services.allocate_pages(AllocType::AnyPage, memory_type, nb_pages)?;
+        let header = NonNull::new(address as *mut TraceBufferHeader).ok_or(efi::Status::OUT_OF_RESOURCES)?;
+
+        // SAFETY: The pages are page aligned and big enough for the header and the records.
+        unsafe {
+            ptr::write_bytes(address as *mut u8, 0, size);
+            ptr::write(
+                header.as_ptr(),
+                TraceBufferHeader {
+                    signature: TRACE_BUFFER_SIGNATURE,
+                    version: TRACE_BUFFER_VERSION,
+                    frequency: A::cpu_count_frequency(),
+                    capacity,
+                    record_size: mem::size_of::<TraceRecord>() as u32,
+                    write_count: AtomicU64::new(0),
+                },
+            );
+            if let Err(status) =
+                boot_services.install_configuration_table_unchecked(&TRACE_BUFFER_TABLE_GUID, address as *mut _)
+            {
+                let _ = boot_services.free_pages(address, nb_pages);
+                return Err(status);
+            }
+        }
+        Ok(Self { header, _arch: PhantomData })
+    }
+
+    fn header(&self) -> &TraceBufferHeader {
+        // SAFETY: The header is initialized in new and never freed.
+        unsafe { self.header.as_ref() }
+    }
+
+    fn records_ptr(&self) -> *mut TraceRecord {
+        // SAFETY: The records follow the header in the same allocation.
+        unsafe { self.header.as_ptr().add(1) as *mut TraceRecord }
+    }
+
+    /// Number of records the ring buffer can hold.
+    pub fn capacity(&self) -> u32 {
+        self.header().capacity
+    }
+
+    /// Write a trace record timestamped with the current performance counter value.
+    ///
+    /// The oldest record is overwritten once the buffer is full.
+    pub fn record(&self, id: u32, data: [u64; 2]) {
+        let timestamp = A::cpu_count();
+        let index = self.header().write_count.fetch_add(1, Ordering::AcqRel) % self.capacity() as u64;
+        // SAFETY: index is within the capacity of the ring buffer.
+        unsafe {
+            ptr::write_volatile(
+                self.records_ptr().add(index as usize),
+                TraceRecord { timestamp, id, reserved: 0, data },
+            )
+        };
+    }
+
+    /// Returns the records currently in the buffer, from the oldest to the most recent.
+    pub fn records(&self) -> impl Iterator<Item = TraceRecord> + '_ {
+        let capacity = self.capacity() as u64;
+        let write_count = self.header().write_count.load(Ordering::Acquire);
+        (write_count.saturating_sub(capacity)..write_count)
+            // SAFETY: the index is within the capacity of the ring buffer.
+            .map(move |n| unsafe { ptr::read_volatile(self.records_ptr().add((n % capacity) as usize)) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use boot_services::MockBootServices;
+    use mockall::predicate::*;
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    struct FakeArch;
+    impl ArchFunctionality for FakeArch {
+        fn cpu_count() -> u64 {
+            COUNTER.fetch_add(10, Ordering::Relaxed)
+        }
+        fn cpu_count_frequency() -> u64 {
+            1000
+        }
+    }
+
+    fn boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_allocate_pages().with(always(), eq(MemoryType::RUNTIME_SERVICES_DATA), eq(1)).returning(
+            |_, _, nb_pages| {
+                let pages = vec![0_u64; nb_pages * UEFI_PAGE_SIZE / mem::size_of::<u64>()];
+                Ok(Box::leak(pages.into_boxed_slice()).as_mut_ptr() as usize)
+            },
+        );
+        boot_services
+            .expect_install_configuration_table_unchecked()
+            .withf(|guid, table| *guid == TRACE_BUFFER_TABLE_GUID && !table.is_null())
+            .returning(|_, _| Ok(()));
+        boot_services
+    }
+
+    #[test]
+    fn test_trace_buffer_wraps_around() {
+        let boot_services = boot_services();
+        let trace = TraceBuffer::<FakeArch>::new(&boot_services, MemoryType::RUNTIME_SERVICES_DATA, 4).unwrap();
+
+        let header = trace.header();
+        assert_eq!(TRACE_BUFFER_SIGNATURE, header.signature);
+        assert_eq!(1000, header.frequency);
+        assert_eq!(mem::size_of::<TraceRecord>() as u32, header.record_size);
+
+        for id in 0..6 {
+            trace.record(id, [id as u64, 0]);
+        }
+        let records = trace.records().collect::<Vec<_>>();
+        assert_eq!(vec![2, 3, 4, 5], records.iter().map(|r| r.id).collect::<Vec<_>>());
+        assert!(records.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+        assert_eq!(6, header.write_count.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_trace_buffer_frees_pages_on_install_failure() {
+        let mut boot_services = MockBootServices::new();
+        let pages = Box::leak(vec![0_u64; UEFI_PAGE_SIZE / mem::size_of::<u64>()].into_boxed_slice());
+        let address = pages.as_mut_ptr() as usize;
+        boot_services.expect_allocate_pages().return_const(Ok(address));
+        boot_services
+            .expect_install_configuration_table_unchecked()
+            .returning(|_, _| Err(efi::Status::OUT_OF_RESOURCES));
+        boot_services.expect_free_pages().with(eq(address), eq(1)).times(1).return_const(Ok(()));
+
+        assert!(matches!(
+            TraceBuffer::<FakeArch>::new(&boot_services, MemoryType::RUNTIME_SERVICES_DATA, 4),
+            Err(efi::Status::OUT_OF_RESOURCES)
+        ));
+    }
+
+    #[test]
+    fn test_trace_buffer_with_zero_capacity() {
+        let boot_services = MockBootServices::new();
+        assert!(matches!(
+            TraceBuffer::<FakeArch>::new(&boot_services, MemoryType::RUNTIME_SERVICES_DATA, 0),
+            Err(efi::Status::INVALID_PARAMETER)
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+//! A bounded queue moving values out of event callbacks to the main loop of an application.
+//!
+//! Event notification functions run at [`Tpl::CALLBACK`] or [`Tpl::NOTIFY`] and should return quickly, a
+//! [`TplChannel`] lets them queue the work for the main loop running at [`Tpl::APPLICATION`]. The queue is a
+//! [`TplMutex`] locked at [`Tpl::NOTIFY`], so the main loop is not preempted by the callbacks while it takes a value,
+//! and its storage is allocated upfront, so sending never allocates.
+//!
+//! ```ignore
+//! static CHANNEL: OnceCell<TplChannel<Key>> = OnceCell::new();
+//!
+//! fn on_key(_event: efi::Event, key: &mut Key) {
+//!     let _ = CHANNEL.get().unwrap().send(*key);
+//! }
+//!
+//! let channel = CHANNEL.get_or_init(|| TplChannel::new(&BOOT_SERVICES, 16).with_wakeup_event(wakeup_event));
+//! loop {
+//!     BOOT_SERVICES.wait_for_event(&mut [wakeup_event])?;
+//!     while let Some(key) = channel.try_recv() {
+//!         handle_key(key);
+//!     }
+//! }
+//! ```
+
+use alloc::collections::VecDeque;
+use core::fmt;
+
+use boot_services::{tpl::Tpl, BootServices, StandardBootServices};
+use r_efi::efi;
+
+use crate::TplMutex;
+
+/// A bounded FIFO queue whose [`TplChannel::send`] can be called from event callbacks up to [`Tpl::NOTIFY`].
+///
+/// The channel is meant for one producer and one consumer, but as the queue is protected by a [`TplMutex`] any number
+/// of producers and consumers below [`Tpl::HIGH_LEVEL`] are safe.
+pub struct TplChannel<'a, T, B: BootServices = StandardBootServices<'a>> {
+    boot_services: &'a B,
+    queue: TplMutex<'a, VecDeque<T>, B>,
+    capacity: usize,
+    wakeup_event: Option<efi::Event>,
+}
+
+impl<'a, T, B: BootServices> TplChannel<'a, T, B> {
+    /// Create an empty channel holding at most *capacity* values.
+    ///
+    /// The storage of the values is allocated here, so like any allocation it must be called at [`Tpl::NOTIFY`] or
+    /// below.
+    pub fn new(boot_services: &'a B, capacity: usize) -> Self {
+        Self {
+            boot_services,
+            queue: TplMutex::new(boot_services, Tpl::NOTIFY, VecDeque::with_capacity(capacity)),
+            capacity,
+            wakeup_event: None,
+        }
+    }
+
+    /// Signals *event* each time a value is sent, so a main loop waiting for it wakes up.
+    pub fn with_wakeup_event(mut self, event: efi::Event) -> Self {
+        self.wakeup_event = Some(event);
+        self
+    }
+
+    /// Queues *value* and signals the wake up event, if any.
+    ///
+    /// Returns the value if the channel is full, or if the queue is locked because the call preempted another call on
+    /// the channel, which only happens when called at a TPL above [`Tpl::NOTIFY`].
+    pub fn send(&self, value: T) -> Result<(), T> {
+        let Ok(mut queue) = self.queue.try_lock() else {
+            return Err(value);
+        };
+        if queue.len() == self.capacity {
+            return Err(value);
+        }
+        queue.push_back(value);
+        drop(queue);
+        if let Some(event) = self.wakeup_event {
+            // Signaling an event only fails for an invalid event, the value is queued anyway.
+            let _ = self.boot_services.signal_event(event);
+        }
+        Ok(())
+    }
+
+    /// Takes the oldest value of the channel, None if it is empty or locked by a call it preempted.
+    pub fn try_recv(&self) -> Option<T> {
+        self.queue.try_lock().ok()?.pop_front()
+    }
+
+    /// Returns the number of values in the channel, 0 if it is locked by a call it preempted.
+    pub fn len(&self) -> usize {
+        self.queue.try_lock().map_or(0, |queue| queue.len())
+    }
+
+    /// Returns true if the channel has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of values in the channel.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+// SAFETY: The wake up event is an opaque handle only passed to the boot services, the queue is a TplMutex.
+unsafe impl<T: Send, B: BootServices> Sync for TplChannel<'_, T, B> {}
+// SAFETY: The wake up event is an opaque handle only passed to the boot services, the queue is a TplMutex.
+unsafe impl<T: Send, B: BootServices> Send for TplChannel<'_, T, B> {}
+
+impl<T, B: BootServices> fmt::Debug for TplChannel<'_, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TplChannel").field("len", &self.len()).field("capacity", &self.capacity).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use boot_services::{event::EventType, host::HostBootServices, MockBootServices};
+    use mockall::predicate::*;
+
+    fn boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().with(eq(Tpl::NOTIFY)).return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().with(eq(Tpl::APPLICATION)).return_const(());
+        boot_services
+    }
+
+    #[test]
+    fn test_send_and_receive_in_order() {
+        let boot_services = boot_services();
+        let channel = TplChannel::new(&boot_services, 2);
+        assert_eq!(None, channel.try_recv());
+
+        assert_eq!(Ok(()), channel.send(1));
+        assert_eq!(Ok(()), channel.send(2));
+        assert_eq!(Err(3), channel.send(3));
+        assert_eq!(2, channel.len());
+        assert_eq!("TplChannel { len: 2, capacity: 2, .. }", format!("{channel:?}"));
+
+        assert_eq!(Some(1), channel.try_recv());
+        assert_eq!(Ok(()), channel.send(4));
+        assert_eq!(Some(2), channel.try_recv());
+        assert_eq!(Some(4), channel.try_recv());
+        assert_eq!(None, channel.try_recv());
+        assert!(channel.is_empty());
+    }
+
+    #[test]
+    fn test_send_while_locked() {
+        let boot_services = boot_services();
+        let channel = TplChannel::new(&boot_services, 2);
+        let _queue = channel.queue.lock();
+        assert_eq!(Err(1), channel.send(1));
+        assert_eq!(None, channel.try_recv());
+    }
+
+    #[test]
+    fn test_send_from_a_notify_dispatched_on_unlock() {
+        extern "efiapi" fn notify(_: efi::Event, channel: &TplChannel<'static, u32>) {
+            let _ = channel.send(2);
+        }
+
+        let host = Box::leak(Box::new(HostBootServices::new()));
+        let boot_services = host.standard_boot_services();
+        let channel = Box::leak(Box::new(TplChannel::new(boot_services, 2)));
+        let event =
+            boot_services.create_event(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, Some(notify), &*channel).unwrap();
+        assert_eq!(Ok(()), channel.send(1));
+
+        // The notify is queued while the main loop holds the queue, and dispatched when it restores the TPL.
+        let queue = channel.queue.lock();
+        boot_services.signal_event(event).unwrap();
+        drop(queue);
+        assert_eq!(Some(1), channel.try_recv());
+        assert_eq!(Some(2), channel.try_recv());
+    }
+
+    #[test]
+    fn test_wakeup_event() {
+        let mut boot_services = boot_services();
+        boot_services.expect_signal_event().times(1).returning(|event| {
+            assert_eq!(1, event as usize);
+            Ok(())
+        });
+        let channel = TplChannel::new(&boot_services, 1).with_wakeup_event(1_usize as efi::Event);
+        assert_eq!(Ok(()), channel.send(1));
+        assert_eq!(Err(2), channel.send(2));
+        assert_eq!(1, channel.capacity());
+    }
+}
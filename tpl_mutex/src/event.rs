@@ -0,0 +1,107 @@
+//! Events whose notify function gets a [`TplMutex`] as context.
+//!
+//! A `&'static TplMutex` is a valid context of [`BootServices::create_event`], as any static reference, but the notify
+//! function must then be an `extern "efiapi"` function of that exact mutex type. [`create_event_with_mutex`] takes a
+//! plain Rust function instead, and checks the notify TPL of the event against the TPL of the mutex, so locking the
+//! mutex in the notify function can never find it locked by the code the notification preempted.
+//!
+//! ```ignore
+//! static DEVICES: TplMutex<Vec<efi::Handle>> = TplMutex::new(&BOOT_SERVICES, Tpl::NOTIFY, Vec::new());
+//!
+//! fn on_new_device(_event: efi::Event, devices: &'static TplMutex<Vec<efi::Handle>>) {
+//!     devices.lock().push(take_new_device());
+//! }
+//!
+//! create_event_with_mutex(&BOOT_SERVICES, EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, on_new_device, &DEVICES)?.leak();
+//! ```
+
+use core::any::Any;
+
+use boot_services::{
+    event::{AnyEventContext, ContextEvent, EventType},
+    tpl::Tpl,
+    BootServices, BootServicesExt,
+};
+use r_efi::efi;
+
+use crate::TplMutex;
+
+/// Notify function of an event created with [`create_event_with_mutex`].
+pub type MutexEventNotifyCallback<T, M> = fn(efi::Event, &'static TplMutex<'static, T, M>);
+
+/// Creates an event like [`BootServices::create_event`], whose notify function gets *mutex*.
+///
+/// Returns `efi::Status::INVALID_PARAMETER` if *notify_tpl* is above the TPL of *mutex*: the notification could then
+/// preempt code holding the mutex, and fail to lock it.
+///
+/// The event is closed when the returned [`ContextEvent`] is dropped, see [`BootServicesExt::create_event_with_context`].
+pub fn create_event_with_mutex<'a, B, T, M>(
+    boot_services: &'a B,
+    event_type: EventType,
+    notify_tpl: Tpl,
+    notify_function: MutexEventNotifyCallback<T, M>,
+    mutex: &'static TplMutex<'static, T, M>,
+) -> Result<ContextEvent<'a, B>, efi::Status>
+where
+    B: BootServices,
+    T: Any,
+    M: BootServices + 'static,
+{
+    if notify_tpl > mutex.tpl_lock_level {
+        return Err(efi::Status::INVALID_PARAMETER);
+    }
+    boot_services.create_event_with_context(
+        event_type,
+        notify_tpl,
+        mutex_event_notify::<T, M>,
+        (notify_function, mutex),
+    )
+}
+
+fn mutex_event_notify<T: Any, M: BootServices + 'static>(event: efi::Event, context: &mut AnyEventContext) {
+    if let Some(&(notify_function, mutex)) =
+        context.downcast_ref::<(MutexEventNotifyCallback<T, M>, &'static TplMutex<'static, T, M>)>()
+    {
+        notify_function(event, mutex);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::boxed::Box;
+    use boot_services::MockBootServices;
+
+    fn mutex(tpl: Tpl) -> &'static TplMutex<'static, u32, MockBootServices> {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        Box::leak(Box::new(TplMutex::new(Box::leak(Box::new(boot_services)), tpl, 0)))
+    }
+
+    fn increment(event: efi::Event, mutex: &'static TplMutex<'static, u32, MockBootServices>) {
+        *mutex.lock() += event as usize as u32;
+    }
+
+    #[test]
+    fn test_notify_gets_the_mutex() {
+        let mutex = mutex(Tpl::NOTIFY);
+        let mut context = AnyEventContext::new((increment as MutexEventNotifyCallback<_, _>, mutex));
+        mutex_event_notify::<u32, MockBootServices>(2_usize as efi::Event, &mut context);
+        mutex_event_notify::<u32, MockBootServices>(3_usize as efi::Event, &mut context);
+        assert_eq!(5, *mutex.lock());
+    }
+
+    #[test]
+    fn test_notify_tpl_above_the_mutex_tpl_is_rejected() {
+        let boot_services = MockBootServices::new();
+        let result = create_event_with_mutex(
+            &boot_services,
+            EventType::NOTIFY_SIGNAL,
+            Tpl::NOTIFY,
+            increment,
+            mutex(Tpl::CALLBACK),
+        );
+        assert!(matches!(result, Err(efi::Status::INVALID_PARAMETER)));
+    }
+}
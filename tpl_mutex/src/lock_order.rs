@@ -0,0 +1,162 @@
+//! Debug checks of the order [`TplMutex`](crate::TplMutex) are locked in, enabled by the `lock_order_checks` feature.
+//!
+//! Every mutex gets an id when it is first locked. The mutexes held are tracked in an acquisition stack and every
+//! (held, acquired) pair is recorded, so locking two mutexes in both orders, which can deadlock, panics the second
+//! time. Locking a mutex with a lower TPL than one already held also panics.
+//!
+//! Dropping a guard restoring the TPL while a mutex locked after it is still held also panics, as the TPL would be
+//! lowered while that mutex is held. The guards which do not restore the TPL, such as all but one of the guards of
+//! [`TplMutex::lock_many`](crate::TplMutex::lock_many), can be dropped in any order.
+//!
+//! UEFI code runs on a single thread where code at a higher TPL preempts code at a lower TPL and runs to completion,
+//! so one acquisition stack is shared by every TPL. It is updated at [`Tpl::HIGH_LEVEL`] where memory can not be
+//! allocated, so its capacity is fixed: the pairs beyond [`MAX_ORDERS`] are not recorded.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use boot_services::{tpl::Tpl, BootServices};
+
+/// Maximum number of mutexes tracked as held at the same time.
+const MAX_HELD: usize = 32;
+/// Maximum number of (held, acquired) pairs recorded.
+const MAX_ORDERS: usize = 256;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+enum Violation {
+    LowerTpl { id: u32, tpl: Tpl, held_id: u32, held_tpl: Tpl },
+    Inversion { id: u32, held_id: u32 },
+    EarlyRestore { id: u32, held_id: u32 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::LowerTpl { id, tpl, held_id, held_tpl } => write!(
+                f,
+                "TplMutex #{id} locked at TPL {} while holding TplMutex #{held_id} locked at the higher TPL {}.",
+                tpl.0, held_tpl.0
+            ),
+            Violation::Inversion { id, held_id } => write!(
+                f,
+                "Lock order inversion: TplMutex #{id} locked while holding TplMutex #{held_id}, which was previously locked while holding TplMutex #{id}."
+            ),
+            Violation::EarlyRestore { id, held_id } => write!(
+                f,
+                "TplMutex #{id} released before TplMutex #{held_id} locked after it, restoring the TPL while TplMutex #{held_id} is held."
+            ),
+        }
+    }
+}
+
+struct State {
+    // (id, lock TPL, whether the TPL is restored when released) of the mutexes held, in acquisition order.
+    held: [(u32, Tpl, bool); MAX_HELD],
+    held_len: usize,
+    orders: [(u32, u32); MAX_ORDERS],
+    orders_len: usize,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self { held: [(0, Tpl(0), false); MAX_HELD], held_len: 0, orders: [(0, 0); MAX_ORDERS], orders_len: 0 }
+    }
+
+    fn acquire(&mut self, id: u32, tpl: Tpl, restores_tpl: bool) -> Result<(), Violation> {
+        for &(held_id, held_tpl, _) in &self.held[..self.held_len] {
+            if held_tpl > tpl {
+                return Err(Violation::LowerTpl { id, tpl, held_id, held_tpl });
+            }
+            if self.orders[..self.orders_len].contains(&(id, held_id)) {
+                return Err(Violation::Inversion { id, held_id });
+            }
+        }
+        for idx in 0..self.held_len {
+            let order = (self.held[idx].0, id);
+            if self.orders_len < MAX_ORDERS && !self.orders[..self.orders_len].contains(&order) {
+                self.orders[self.orders_len] = order;
+                self.orders_len += 1;
+            }
+        }
+        if self.held_len < MAX_HELD {
+            self.held[self.held_len] = (id, tpl, restores_tpl);
+            self.held_len += 1;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, id: u32) -> Result<(), Violation> {
+        // Guards are not always dropped in the reverse order of their creation.
+        let Some(idx) = self.held[..self.held_len].iter().rposition(|&(held_id, _, _)| held_id == id) else {
+            return Ok(());
+        };
+        if self.held[idx].2 && idx + 1 < self.held_len {
+            return Err(Violation::EarlyRestore { id, held_id: self.held[idx + 1].0 });
+        }
+        self.held.copy_within(idx + 1..self.held_len, idx);
+        self.held_len -= 1;
+        Ok(())
+    }
+}
+
+// Test threads run concurrently, so each of them gets its own state.
+#[cfg(test)]
+fn with_state<B: BootServices + ?Sized, R>(_boot_services: &B, f: impl FnOnce(&mut State) -> R) -> R {
+    std::thread_local! {
+        static STATE: core::cell::RefCell<State> = const { core::cell::RefCell::new(State::new()) };
+    }
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+#[cfg(not(test))]
+fn with_state<B: BootServices + ?Sized, R>(boot_services: &B, f: impl FnOnce(&mut State) -> R) -> R {
+    struct GlobalState(core::cell::UnsafeCell<State>);
+    // SAFETY: The state is only accessed at TPL_HIGH_LEVEL, which can not be preempted.
+    unsafe impl Sync for GlobalState {}
+    static STATE: GlobalState = GlobalState(core::cell::UnsafeCell::new(State::new()));
+
+    let release_tpl = boot_services.raise_tpl(Tpl::HIGH_LEVEL);
+    // SAFETY: See GlobalState.
+    let result = f(unsafe { &mut *STATE.0.get() });
+    boot_services.restore_tpl(release_tpl);
+    result
+}
+
+/// Returns the id stored in *id*, assigning a new one the first time.
+pub(crate) fn lock_id(id: &AtomicU32) -> u32 {
+    match id.load(Ordering::Relaxed) {
+        0 => {
+            let new_id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            match id.compare_exchange(0, new_id, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => new_id,
+                Err(id) => id,
+            }
+        }
+        id => id,
+    }
+}
+
+/// Records that the mutex *id* locked at *tpl* is held, restoring the TPL when released if *restores_tpl*.
+///
+/// # Panics
+/// This function will panic if the mutex is locked after a mutex of higher TPL, or in the reverse order of a
+/// previous lock.
+#[track_caller]
+pub(crate) fn acquire<B: BootServices + ?Sized>(boot_services: &B, id: u32, tpl: Tpl, restores_tpl: bool) {
+    if let Err(violation) = with_state(boot_services, |state| state.acquire(id, tpl, restores_tpl)) {
+        panic!("{violation}");
+    }
+}
+
+/// Records that the mutex *id* is not held anymore.
+///
+/// # Panics
+/// This function will panic if the guard of the mutex restores the TPL while a mutex locked after it is still held.
+pub(crate) fn release<B: BootServices + ?Sized>(boot_services: &B, id: u32) {
+    if let Err(violation) = with_state(boot_services, |state| state.release(id)) {
+        panic!("{violation}");
+    }
+}
@@ -2,6 +2,15 @@
 
 extern crate alloc;
 
+pub mod channel;
+pub mod event;
+
+#[cfg(feature = "lock_order_checks")]
+mod lock_order;
+
+#[cfg(feature = "stats")]
+pub mod stats;
+
 use core::{
     cell::UnsafeCell,
     fmt::{self, Debug, Display},
@@ -16,6 +25,10 @@ pub struct TplMutex<'a, T: ?Sized, B: BootServices = StandardBootServices<'a>> {
     boot_services: &'a B,
     tpl_lock_level: Tpl,
     lock: AtomicBool,
+    #[cfg(feature = "lock_order_checks")]
+    id: core::sync::atomic::AtomicU32,
+    #[cfg(feature = "stats")]
+    stats: Option<&'static stats::LockStats>,
     data: UnsafeCell<T>,
 }
 
@@ -23,13 +36,41 @@ pub struct TplMutex<'a, T: ?Sized, B: BootServices = StandardBootServices<'a>> {
 #[must_use = "if unused the TplMutex will immediately unlock"]
 pub struct TplMutexGuard<'a, T: ?Sized, B: BootServices> {
     tpl_mutex: &'a TplMutex<'a, T, B>,
-    release_tpl: Tpl,
+    // None for the guards of TplMutex::lock_many but the last one, which restores the TPL for all of them.
+    release_tpl: Option<Tpl>,
+    // perf_timer counter value when the mutex was locked.
+    #[cfg(feature = "stats")]
+    locked_at: u64,
+}
+
+/// RAII implementation of the locks of [`TplMutex::lock_many`], a tuple of [`TplMutexGuard`].
+///
+/// The guards are dropped together, and the TPL is restored once after the last mutex is unlocked.
+#[must_use = "if unused the TplMutexes will immediately unlock"]
+pub struct TplMutexGuards<G> {
+    guards: G,
 }
 
 impl<'a, T, B: BootServices> TplMutex<'a, T, B> {
     /// Create an new TplMutex in an unlock state.
     pub const fn new(boot_services: &'a B, tpl_lock_level: Tpl, data: T) -> Self {
-        Self { boot_services, tpl_lock_level, lock: AtomicBool::new(false), data: UnsafeCell::new(data) }
+        Self {
+            boot_services,
+            tpl_lock_level,
+            lock: AtomicBool::new(false),
+            #[cfg(feature = "lock_order_checks")]
+            id: core::sync::atomic::AtomicU32::new(0),
+            #[cfg(feature = "stats")]
+            stats: None,
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Records the lock statistics of the mutex in *stats*, see the [`stats`] module.
+    #[cfg(feature = "stats")]
+    pub const fn with_stats(mut self, stats: &'static stats::LockStats) -> Self {
+        self.stats = Some(stats);
+        self
     }
 }
 
@@ -46,18 +87,158 @@ impl<'a, T: ?Sized, B: BootServices> TplMutex<'a, T, B> {
     ///
     /// # Errors
     /// If the mutex is already lock, then this call will return [Err].
+    ///
+    /// # Panics
+    /// With the `lock_order_checks` feature, this call will panic if the mutexes are not always locked in the same
+    /// order, or if a mutex is locked while holding a mutex of higher TPL.
+    #[cfg_attr(feature = "lock_order_checks", track_caller)]
     pub fn try_lock(&'a self) -> Result<TplMutexGuard<'a, T, B>, ()> {
-        self.lock
-            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-            .map(|_| TplMutexGuard { release_tpl: self.boot_services.raise_tpl(self.tpl_lock_level), tpl_mutex: &self })
-            .map_err(|_| ())
+        // The TPL is raised first, so the notify functions it masks can not preempt the lock once it is taken.
+        let release_tpl = self.boot_services.raise_tpl(self.tpl_lock_level);
+        if self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            self.boot_services.restore_tpl(release_tpl);
+            #[cfg(feature = "stats")]
+            if let Some(stats) = self.stats {
+                stats.contended();
+            }
+            return Err(());
+        }
+        #[cfg(feature = "lock_order_checks")]
+        lock_order::acquire(self.boot_services, lock_order::lock_id(&self.id), self.tpl_lock_level, true);
+        Ok(TplMutexGuard {
+            release_tpl: Some(release_tpl),
+            tpl_mutex: self,
+            #[cfg(feature = "stats")]
+            locked_at: self.acquired(),
+        })
+    }
+
+    // Records an acquisition in the statistics of the mutex, if any, and returns the counter value it happened at.
+    #[cfg(feature = "stats")]
+    fn acquired(&self) -> u64 {
+        self.stats.map_or(0, |stats| stats.acquired())
+    }
+}
+
+impl TplMutex<'_, ()> {
+    /// Lock every mutex of the tuple *mutexes* at once, e.g. `TplMutex::lock_many((&m1, &m2))`.
+    ///
+    /// The TPL is raised once to the highest TPL of the mutexes and restored once when the guards are dropped, and the
+    /// mutexes are locked in the order of their addresses, so nested locks can not lower the TPL too early nor be
+    /// locked in inconsistent orders.
+    ///
+    /// # Panics
+    /// This call will panic if a mutex is already locked, or passed twice.
+    #[cfg_attr(feature = "lock_order_checks", track_caller)]
+    pub fn lock_many<M: LockMany>(mutexes: M) -> TplMutexGuards<M::Guards> {
+        TplMutexGuards { guards: mutexes.lock_many() }
+    }
+}
+
+/// Tuples of [`TplMutex`] references that can be locked with [`TplMutex::lock_many`].
+pub trait LockMany {
+    /// The tuple of the guards of the mutexes.
+    type Guards;
+
+    /// See [`TplMutex::lock_many`].
+    fn lock_many(self) -> Self::Guards;
+}
+
+// Type erased access to the mutexes of a LockMany tuple.
+trait RawLock {
+    fn address(&self) -> usize;
+
+    fn tpl_lock_level(&self) -> Tpl;
+
+    // Locks the mutex, the TPL is already raised.
+    fn raw_lock(&self, tpl: Tpl, release_tpl: Option<Tpl>);
+}
+
+impl<T, B: BootServices> RawLock for TplMutex<'_, T, B> {
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn tpl_lock_level(&self) -> Tpl {
+        self.tpl_lock_level
+    }
+
+    #[cfg_attr(feature = "lock_order_checks", track_caller)]
+    #[cfg_attr(not(feature = "lock_order_checks"), allow(unused_variables))]
+    fn raw_lock(&self, tpl: Tpl, release_tpl: Option<Tpl>) {
+        if self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            panic!("Re-entrant lock");
+        }
+        #[cfg(feature = "lock_order_checks")]
+        lock_order::acquire(self.boot_services, lock_order::lock_id(&self.id), tpl, release_tpl.is_some());
+    }
+}
+
+macro_rules! impl_lock_many {
+    ($($idx:tt: $t:ident),+; $last:tt) => {
+        impl<'a, B: BootServices, $($t),+> LockMany for ($(&'a TplMutex<'a, $t, B>,)+) {
+            type Guards = ($(TplMutexGuard<'a, $t, B>,)+);
+
+            #[cfg_attr(feature = "lock_order_checks", track_caller)]
+            fn lock_many(self) -> Self::Guards {
+                let mut mutexes = [$(self.$idx as &dyn RawLock),+];
+                let tpl = mutexes.iter().map(|mutex| mutex.tpl_lock_level()).max().unwrap();
+                let release_tpl = self.0.boot_services.raise_tpl(tpl);
+
+                // The guards are dropped in tuple order, the last one restores the TPL.
+                let last = self.$last.address();
+                mutexes.sort_by_key(|mutex| mutex.address());
+                for mutex in mutexes {
+                    mutex.raw_lock(tpl, (mutex.address() == last).then_some(release_tpl));
+                }
+                ($(TplMutexGuard {
+                    tpl_mutex: self.$idx,
+                    release_tpl: ($idx == $last).then_some(release_tpl),
+                    #[cfg(feature = "stats")]
+                    locked_at: self.$idx.acquired(),
+                },)+)
+            }
+        }
+    };
+}
+
+impl_lock_many!(0: T0, 1: T1; 1);
+impl_lock_many!(0: T0, 1: T1, 2: T2; 2);
+impl_lock_many!(0: T0, 1: T1, 2: T2, 3: T3; 3);
+
+impl<G> Deref for TplMutexGuards<G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.guards
+    }
+}
+
+impl<G> DerefMut for TplMutexGuards<G> {
+    fn deref_mut(&mut self) -> &mut G {
+        &mut self.guards
+    }
+}
+
+impl<G: fmt::Debug> fmt::Debug for TplMutexGuards<G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.guards, f)
     }
 }
 
 impl<T: ?Sized, B: BootServices> Drop for TplMutexGuard<'_, T, B> {
     fn drop(&mut self) {
-        self.tpl_mutex.boot_services.restore_tpl(self.release_tpl);
+        #[cfg(feature = "stats")]
+        if let Some(stats) = self.tpl_mutex.stats {
+            stats.released(self.locked_at);
+        }
+        #[cfg(feature = "lock_order_checks")]
+        lock_order::release(self.tpl_mutex.boot_services, self.tpl_mutex.id.load(Ordering::Relaxed));
+        // The lock is released first, so the notify functions dispatched by restore_tpl can take it.
         self.tpl_mutex.lock.store(false, Ordering::Release);
+        if let Some(release_tpl) = self.release_tpl {
+            self.tpl_mutex.boot_services.restore_tpl(release_tpl);
+        }
     }
 }
 
@@ -151,6 +332,18 @@ mod test {
         assert!(matches!(guard_result, Ok(_)), "Lock should work after the guard has been dropped.");
     }
 
+    #[test]
+    fn test_failed_try_lock_restores_the_tpl() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().with(eq(Tpl::NOTIFY)).times(2).return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().with(eq(Tpl::APPLICATION)).times(2).return_const(());
+        let mutex = TplMutex::new(&boot_services, Tpl::NOTIFY, 0);
+
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_err());
+        drop(guard);
+    }
+
     #[test]
     #[should_panic(expected = "Re-entrant lock")]
     fn test_that_locking_a_locked_mutex_with_lock_fn_should_panic() {
@@ -170,6 +363,105 @@ mod test {
         assert_eq!("TplMutex { data: <locked>, .. }", format!("{mutex:?}"));
     }
 
+    #[cfg(feature = "lock_order_checks")]
+    fn any_tpl_boot_services() -> MockBootServices {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().return_const(());
+        boot_services
+    }
+
+    #[test]
+    #[cfg(feature = "lock_order_checks")]
+    #[should_panic(expected = "Lock order inversion")]
+    fn test_that_locking_in_both_orders_should_panic() {
+        let boot_services = any_tpl_boot_services();
+        let mutex_a = TplMutex::new(&boot_services, Tpl::NOTIFY, 0);
+        let mutex_b = TplMutex::new(&boot_services, Tpl::NOTIFY, 0);
+
+        for _ in 0..2 {
+            let guard_a = mutex_a.lock();
+            let guard_b = mutex_b.lock();
+            drop(guard_b);
+            drop(guard_a);
+        }
+
+        let _guard_b = mutex_b.lock();
+        let _guard_a = mutex_a.lock();
+    }
+
+    #[test]
+    #[cfg(feature = "lock_order_checks")]
+    #[should_panic(expected = "while holding TplMutex")]
+    fn test_that_locking_a_lower_tpl_mutex_should_panic() {
+        let boot_services = any_tpl_boot_services();
+        let mutex_notify = TplMutex::new(&boot_services, Tpl::NOTIFY, 0);
+        let mutex_callback = TplMutex::new(&boot_services, Tpl::CALLBACK, 0);
+
+        {
+            let _guard_callback = mutex_callback.lock();
+            let _guard_notify = mutex_notify.lock();
+        }
+
+        let _guard_notify = mutex_notify.lock();
+        let _guard_callback = mutex_callback.lock();
+    }
+
+    #[test]
+    fn test_lock_many_raises_and_restores_the_tpl_once() {
+        let mut boot_services = MockBootServices::new();
+        boot_services.expect_raise_tpl().with(eq(Tpl::NOTIFY)).times(1).return_const(Tpl::APPLICATION);
+        boot_services.expect_restore_tpl().with(eq(Tpl::APPLICATION)).times(1).return_const(());
+        let mutex_notify = TplMutex::new(&boot_services, Tpl::NOTIFY, 1);
+        let mutex_callback = TplMutex::new(&boot_services, Tpl::CALLBACK, TestStruct::default());
+        let mutex_unused = TplMutex::new(&boot_services, Tpl::NOTIFY, 0);
+
+        let mut guards = TplMutex::lock_many((&mutex_callback, &mutex_notify));
+        let (test_struct, value) = &mut *guards;
+        test_struct.field = **value + 1;
+        assert_eq!("(TestStruct { field: 2 }, 1)", format!("{guards:?}"));
+        assert!(mutex_callback.lock.load(Ordering::Relaxed) && mutex_notify.lock.load(Ordering::Relaxed));
+        assert!(!mutex_unused.lock.load(Ordering::Relaxed));
+
+        drop(guards);
+        assert!(!mutex_callback.lock.load(Ordering::Relaxed) && !mutex_notify.lock.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    #[should_panic(expected = "Re-entrant lock")]
+    fn test_lock_many_with_the_same_mutex_twice_should_panic() {
+        let boot_services = boot_services();
+        let mutex = TplMutex::new(&boot_services, Tpl::NOTIFY, 0);
+        let _guards = TplMutex::lock_many((&mutex, &mutex));
+    }
+
+    #[test]
+    #[cfg(feature = "lock_order_checks")]
+    #[should_panic(expected = "restoring the TPL while")]
+    fn test_out_of_order_release_should_panic() {
+        let boot_services = any_tpl_boot_services();
+        let mutex_callback = TplMutex::new(&boot_services, Tpl::CALLBACK, 0);
+        let mutex_notify = TplMutex::new(&boot_services, Tpl::NOTIFY, 0);
+
+        let guard_callback = mutex_callback.lock();
+        let _guard_notify = mutex_notify.lock();
+        drop(guard_callback);
+    }
+
+    #[test]
+    #[cfg(feature = "lock_order_checks")]
+    fn test_lock_many_in_any_tpl_order() {
+        let boot_services = any_tpl_boot_services();
+        let mutexes = [
+            TplMutex::new(&boot_services, Tpl::NOTIFY, 0),
+            TplMutex::new(&boot_services, Tpl::CALLBACK, 0),
+            TplMutex::new(&boot_services, Tpl::NOTIFY, 0),
+        ];
+        let guards = TplMutex::lock_many((&mutexes[0], &mutexes[1], &mutexes[2]));
+        drop(guards);
+        let _guards = TplMutex::lock_many((&mutexes[2], &mutexes[1], &mutexes[0]));
+    }
+
     #[test]
     fn test_display_and_debug_output_for_tpl_mutex_guard() {
         let boot_services = boot_services();
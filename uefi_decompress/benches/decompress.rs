@@ -0,0 +1,107 @@
+//! Decompression of the test collateral payloads and of synthetic firmware volume payloads of representative sizes.
+//!
+//! Run with `cargo bench -p uefi_decompress --features bench`. To track the performance over time, save a baseline
+//! with `-- --save-baseline <name>` and compare later runs to it with `-- --baseline <name>`.
+
+use std::num::NonZeroUsize;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use uefi_decompress::{
+    decompress_into_with_algo,
+    fuzzing::{self, Entropy},
+    parallel, DecompressionAlgorithm,
+};
+
+macro_rules! test_collateral {
+    ($fname:expr) => {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/resources/test/", $fname)
+    };
+}
+
+// Mimics the content of a firmware volume: executable images, erased flash padding and repeated headers.
+fn fv_payload(size: usize) -> Vec<u8> {
+    let mut entropy = Entropy::from_seed(size as u64);
+    let mut payload = Vec::with_capacity(size);
+    while payload.len() < size {
+        match entropy.below(8) {
+            // Machine code has a skewed byte distribution and short repeated sequences.
+            0..=4 => {
+                for _ in 0..0x1000 {
+                    match entropy.below(4) {
+                        0 if payload.len() > 0x100 => {
+                            let start = payload.len() - 1 - entropy.below(0x100);
+                            let len = 3 + entropy.below(12);
+                            for idx in start..start + len {
+                                payload.push(payload[idx]);
+                            }
+                        }
+                        _ => payload.push([0x48, 0x89, 0x8B, 0xE8, 0x00, 0xFF][entropy.below(6)]),
+                    }
+                    payload.push(entropy.below(0x100) as u8);
+                }
+            }
+            5..=6 => payload.resize(payload.len() + 0x200 * (1 + entropy.below(8)), 0xFF),
+            _ => payload.extend_from_slice(b"\x4d\x5a\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff\x00\x00"),
+        }
+    }
+    payload.truncate(size);
+    payload
+}
+
+fn decompress(c: &mut Criterion) {
+    let payloads = [
+        ("uefi", test_collateral!("uefi_compressed.bin"), DecompressionAlgorithm::UefiDecompress),
+        ("tiano", test_collateral!("tiano_compressed.bin"), DecompressionAlgorithm::TianoDecompress),
+    ];
+
+    let mut group = c.benchmark_group("decompress");
+    for (name, path, algo) in payloads {
+        let src = std::fs::read(path).expect("failed to read test file");
+        let mut dst = vec![0u8; u32::from_le_bytes(src[4..8].try_into().unwrap()) as usize];
+        group.throughput(Throughput::Bytes(dst.len() as u64));
+        group.bench_function(name, |b| b.iter(|| decompress_into_with_algo(&src, &mut dst, algo).unwrap()));
+    }
+    group.finish();
+}
+
+fn decompress_fv(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decompress_fv");
+    group.sample_size(10);
+    for (name, size) in [("64KB", 64 << 10), ("1MB", 1 << 20), ("8MB", 8 << 20)] {
+        for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+            let payload = fv_payload(size);
+            let src = fuzzing::compress(&payload, algo);
+            let mut dst = vec![0u8; size];
+            decompress_into_with_algo(&src, &mut dst, algo).unwrap();
+            assert!(dst == payload, "reference compressor round trip mismatch");
+
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_function(BenchmarkId::new(format!("{:?}", algo), name), |b| {
+                b.iter(|| decompress_into_with_algo(&src, &mut dst, algo).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+// Scaling of the parallel decompression of the 64 compressed sections of a 16MB firmware volume.
+fn decompress_parallel(c: &mut Criterion) {
+    let algo = DecompressionAlgorithm::TianoDecompress;
+    let payloads = (0..64).map(|idx| fv_payload((256 << 10) + idx)).collect::<Vec<_>>();
+    let sources = payloads.iter().map(|p| fuzzing::compress(p, algo)).collect::<Vec<_>>();
+    let sources = sources.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("decompress_parallel");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(payloads.iter().map(|p| p.len() as u64).sum()));
+    for threads in [1, 2, 4, 8] {
+        let threads = NonZeroUsize::new(threads).unwrap();
+        group.bench_function(BenchmarkId::from_parameter(threads), |b| {
+            b.iter(|| parallel::decompress_all_with_threads(&sources, algo, threads))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decompress, decompress_fv, decompress_parallel);
+criterion_main!(benches);
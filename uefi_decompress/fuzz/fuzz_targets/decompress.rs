@@ -0,0 +1,29 @@
+//! Decompresses arbitrary input with every algorithm, into a buffer and with the allocating API of the wasm build.
+//!
+//! Run from the uefi_decompress directory with the checked-in regression corpus as seed:
+//! `cargo +nightly fuzz run decompress fuzz/corpus/decompress resources/fuzz_corpus`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uefi_decompress::{decompress, decompress_into_with_algo, DecompressionAlgorithm};
+
+// Larger outputs only slow down the fuzzer without reaching new code.
+const MAX_ORIG_SIZE: usize = 0x100000;
+
+fuzz_target!(|data: &[u8]| {
+    let Some(orig_size) = data.get(4..8).map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize) else {
+        return;
+    };
+    if orig_size > MAX_ORIG_SIZE {
+        return;
+    }
+    let mut dst = vec![0u8; orig_size];
+    for algo in [
+        DecompressionAlgorithm::UefiDecompress,
+        DecompressionAlgorithm::TianoDecompress,
+        DecompressionAlgorithm::AutoDetect,
+    ] {
+        let _ = decompress_into_with_algo(data, &mut dst, algo);
+        let _ = decompress(data, algo);
+    }
+});
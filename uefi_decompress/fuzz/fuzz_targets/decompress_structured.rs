@@ -0,0 +1,29 @@
+//! Decompresses streams built by the structured generator from the fuzzer input.
+//!
+//! The input drives the choices of the generator rather than being decompressed directly, so the fuzzer explores
+//! valid Huffman tables instead of mostly hitting the table validation errors. Predictable streams are also checked
+//! against their expected output.
+//!
+//! Run from the uefi_decompress directory: `cargo +nightly fuzz run decompress_structured`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uefi_decompress::{
+    decompress_into_with_algo,
+    fuzzing::{self, Entropy},
+    DecompressionAlgorithm,
+};
+
+fuzz_target!(|data: &[u8]| {
+    for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+        let stream = fuzzing::generate(&mut Entropy::from_bytes(data), algo);
+        let orig_size = u32::from_le_bytes(stream.data[4..8].try_into().unwrap()) as usize;
+        let mut dst = vec![0u8; orig_size];
+
+        let result = decompress_into_with_algo(&stream.data, &mut dst, algo);
+        if let Some(expected) = stream.expected {
+            assert!(result.is_ok(), "failed to decompress: {:?}", result);
+            assert!(dst == expected, "unexpected output");
+        }
+    }
+});
@@ -0,0 +1,125 @@
+//! Differential tests against the EDK2 C compressor, guarding the interoperability with the C toolchains.
+//!
+//! The tests are ignored and run explicitly with the path of the BaseTools `TianoCompress` binary set in the
+//! `EDK2_TIANO_COMPRESS` environment variable. UEFI streams are produced with its `--uefi` option, unless another binary is
+//! set in `EDK2_UEFI_COMPRESS`. Each file of the corpus directory, `resources/test` or the directory set in
+//! `EDK2_DIFFERENTIAL_CORPUS`, is checked in both directions:
+//! - the streams of the C compressor decompress byte for byte to the file,
+//! - the streams of the reference compressor of the [`fuzzing`](crate::fuzzing) module are decompressed byte for byte
+//!   to the file by the C decompressor.
+//!
+//! The compressed streams themselves are not compared, as the reference compressor does not produce the same streams
+//! as the C one.
+//!
+//! ```sh
+//! EDK2_TIANO_COMPRESS=~/edk2/BaseTools/Source/C/bin/TianoCompress cargo test -p uefi_decompress differential -- --ignored
+//! ```
+
+extern crate std;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    string::{String, ToString},
+    sync::atomic::{AtomicUsize, Ordering},
+    vec,
+    vec::Vec,
+};
+
+use crate::{decompress_into_with_algo, fuzzing, DecompressionAlgorithm};
+
+// A compressor binary and the arguments selecting the algorithm.
+struct ReferenceTool {
+    path: PathBuf,
+    args: &'static [&'static str],
+}
+
+impl ReferenceTool {
+    // Returns the binary of *algo* set in the environment, None if the tests should be skipped.
+    fn from_env(algo: DecompressionAlgorithm) -> Option<Self> {
+        let tiano = env::var_os("EDK2_TIANO_COMPRESS").map(PathBuf::from);
+        match algo {
+            DecompressionAlgorithm::UefiDecompress => match env::var_os("EDK2_UEFI_COMPRESS") {
+                Some(path) => Some(Self { path: path.into(), args: &[] }),
+                None => tiano.map(|path| Self { path, args: &["--uefi"] }),
+            },
+            _ => tiano.map(|path| Self { path, args: &[] }),
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        self.run("-e", data)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Vec<u8> {
+        self.run("-d", data)
+    }
+
+    // Runs the binary in *mode* on *data* through temporary files.
+    fn run(&self, mode: &str, data: &[u8]) -> Vec<u8> {
+        static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+        let file = NEXT_FILE.fetch_add(1, Ordering::Relaxed);
+        let temp =
+            |suffix: &str| env::temp_dir().join(std::format!("uefi_decompress_{}_{file}.{suffix}", std::process::id()));
+        let (input, output) = (temp("in"), temp("out"));
+        fs::write(&input, data).expect("failed to write the input of the reference tool");
+
+        let status = Command::new(&self.path)
+            .arg(mode)
+            .args(self.args)
+            .arg("-o")
+            .arg(&output)
+            .arg(&input)
+            .status()
+            .expect("failed to run the reference tool");
+        let result = fs::read(&output);
+        let _ = fs::remove_file(&input);
+        let _ = fs::remove_file(&output);
+
+        assert!(status.success(), "{} {mode} {:?} failed: {status}", self.path.display(), self.args);
+        result.expect("failed to read the output of the reference tool")
+    }
+}
+
+// Returns the name and content of the files of the corpus.
+fn corpus() -> Vec<(String, Vec<u8>)> {
+    let directory = env::var_os("EDK2_DIFFERENTIAL_CORPUS")
+        .map_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test"), PathBuf::from);
+    let mut files = fs::read_dir(&directory)
+        .expect("failed to open corpus directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .map(|path| (path.display().to_string(), fs::read(&path).expect("failed to read corpus file")))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[test]
+#[ignore = "Needs the EDK2 C compressor, see the module documentation."]
+fn differential_reference_streams_should_decompress() {
+    for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+        let tool = ReferenceTool::from_env(algo).expect("EDK2_TIANO_COMPRESS is not set");
+        for (name, data) in corpus() {
+            let compressed = tool.compress(&data);
+            let mut test_buffer = vec![0u8; data.len()];
+            let result = decompress_into_with_algo(&compressed, &mut test_buffer, algo);
+            assert!(result.is_ok(), "{algo:?} stream of {name} failed to decompress: {result:?}");
+            assert!(test_buffer == data, "{algo:?} stream of {name} decompressed to different bytes");
+        }
+    }
+}
+
+#[test]
+#[ignore = "Needs the EDK2 C compressor, see the module documentation."]
+fn differential_reference_decompressor_should_accept_our_streams() {
+    for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+        let tool = ReferenceTool::from_env(algo).expect("EDK2_TIANO_COMPRESS is not set");
+        for (name, data) in corpus() {
+            let decompressed = tool.decompress(&fuzzing::compress(&data, algo));
+            assert!(decompressed == data, "{algo:?} stream of {name} decompressed to different bytes by the C tool");
+        }
+    }
+}
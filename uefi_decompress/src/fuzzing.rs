@@ -0,0 +1,489 @@
+//! Structured compressed stream generator used to fuzz the decompressor.
+//!
+//! Mutating real compressed files mostly exercises the "malformed table" error paths, as almost any bit flip in the
+//! table headers produces an invalid Huffman code. [`generate`] instead builds streams from scratch with valid but
+//! unusual Huffman tables so the decoding of the symbols themselves is exercised:
+//! - single symbol tables (a zero array size followed by the only symbol, every code is 0 bits long),
+//! - tables where every code length is zero,
+//! - skewed tables reaching the maximum code length of 16 bits, which require the secondary decode tree,
+//! - balanced tables.
+//!
+//! The generator is deterministic: the same [`Entropy`] always produces the same stream. Streams whose decoded content
+//! can be predicted also come with the expected output, so the decompressor can be checked for correctness and not
+//! only for the absence of panics.
+//!
+//! [`compress`] is a simple reference compressor producing valid streams from arbitrary data, used to build large
+//! benchmark payloads.
+//!
+//! This module is only available with the `fuzzing` feature, see the `fuzz` directory for the cargo-fuzz targets.
+
+use alloc::vec::Vec;
+
+use crate::{DecompressionAlgorithm, CBIT, MAXNP, NC, NT, TBIT};
+
+const MAX_CODE_LEN: usize = 16;
+
+/// Source of the decisions taken by the generator.
+pub struct Entropy<'a> {
+    data: &'a [u8],
+    state: u64,
+}
+
+impl<'a> Entropy<'a> {
+    /// Decisions are drawn from a xorshift pseudo-random generator initialized with *seed*.
+    pub fn from_seed(seed: u64) -> Self {
+        // xorshift has a fixed point at zero, which is also used to select the byte source.
+        Self { data: &[], state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+
+    /// Decisions are drawn from *data*, typically the fuzzer input. Once exhausted, every decision is 0.
+    pub fn from_bytes(data: &'a [u8]) -> Self {
+        Self { data, state: 0 }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.state != 0 {
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            return (self.state >> 32) as u32;
+        }
+        let len = usize::min(4, self.data.len());
+        let mut bytes = [0u8; 4];
+        bytes[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Returns a value in 0..*bound*, *bound* must not be zero.
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.next_u32() as usize % bound
+    }
+
+    fn bits(&mut self, count: usize) -> usize {
+        self.next_u32() as usize & ((1 << count) - 1)
+    }
+}
+
+/// A generated compressed stream.
+pub struct GeneratedStream {
+    /// The compressed stream, including the 8 bytes size header.
+    pub data: Vec<u8>,
+    /// The decompressed content, if the stream decodes to a predictable output.
+    pub expected: Option<Vec<u8>>,
+}
+
+/// Generate a compressed stream for *algo* from *entropy*.
+///
+/// [`DecompressionAlgorithm::AutoDetect`] produces a stream for one of the two algorithms.
+pub fn generate(entropy: &mut Entropy, algo: DecompressionAlgorithm) -> GeneratedStream {
+    let (p_bit, np) = match algo {
+        DecompressionAlgorithm::UefiDecompress => (4, 14),
+        DecompressionAlgorithm::TianoDecompress => (5, 20),
+        DecompressionAlgorithm::AutoDetect => [(4, 14), (5, 20)][entropy.below(2)],
+    };
+
+    let mut writer = BitWriter::default();
+    let mut output = Vec::new();
+    let mut predictable = true;
+
+    for _ in 0..1 + entropy.below(3) {
+        let c_table = HuffmanTable::new(entropy, NC, NC);
+        let p_table = HuffmanTable::new(entropy, np, (1 << p_bit) - 1);
+        let c_len_codes = c_len_codes(&c_table.lengths);
+        let t_table = HuffmanTable::for_symbols(entropy, &c_len_codes, NT);
+
+        // A zero length C table reuses the decode table of the previous block, its symbols can not be predicted.
+        predictable &= c_table.shape != Shape::ZeroLengths;
+
+        let block_size = 1 + entropy.below(0x200);
+        writer.push(block_size, 16);
+        t_table.write_lengths(&mut writer, TBIT, true);
+        write_c_lengths(&mut writer, &t_table, &c_table, &c_len_codes);
+        p_table.write_lengths(&mut writer, p_bit, false);
+
+        for _ in 0..block_size {
+            let symbol = c_table.pick(entropy, |s| s < 256 || output.len() > 1);
+            c_table.write_symbol(&mut writer, symbol);
+            if symbol < 256 {
+                output.push(symbol as u8);
+                continue;
+            }
+
+            // String pointer: find a position that stays within the decoded output when possible.
+            let len = symbol - (0x100 - 3);
+            let p_symbol =
+                p_table.pick(entropy, |p| p <= 1 && p < output.len() || p > 1 && 1 << (p - 1) < output.len());
+            p_table.write_symbol(&mut writer, p_symbol);
+            let position = if p_symbol > 1 {
+                let base = 1 << (p_symbol - 1);
+                let extra = match output.len().checked_sub(base) {
+                    Some(range) if range > 0 => entropy.below(usize::min(range, base)),
+                    _ => entropy.bits(p_symbol - 1),
+                };
+                writer.push(extra, p_symbol - 1);
+                base + extra
+            } else {
+                p_symbol
+            };
+
+            // A zero length P table reuses stale decode table entries.
+            if p_table.shape == Shape::ZeroLengths || position + 1 > output.len() {
+                predictable = false;
+            }
+            if predictable {
+                let start = output.len() - position - 1;
+                for idx in start..start + len {
+                    output.push(output[idx]);
+                }
+            }
+        }
+    }
+
+    // The decoder looks ahead up to 16 bits past the last code.
+    writer.push(0, 16);
+    let mut data = Vec::with_capacity(8 + writer.bytes.len());
+    data.extend_from_slice(&(writer.bytes.len() as u32).to_le_bytes());
+    data.extend_from_slice(
+        &(if predictable { output.len() as u32 } else { entropy.below(0x10000) as u32 }).to_le_bytes(),
+    );
+    data.extend_from_slice(&writer.bytes);
+
+    GeneratedStream { data, expected: predictable.then_some(output) }
+}
+
+/// Compress *data* for *algo* with a greedy LZ77 matcher and a balanced Huffman code per block.
+///
+/// The streams are valid but neither as small as nor byte-identical to the ones of the EDK2 C compressor.
+/// [`DecompressionAlgorithm::AutoDetect`] produces a UEFI stream.
+pub fn compress(data: &[u8], algo: DecompressionAlgorithm) -> Vec<u8> {
+    // Back-references are limited to the distances both algorithms can code.
+    const WINDOW: usize = 1 << 13;
+    const MIN_MATCH: usize = 3;
+    const MAX_MATCH: usize = NC - 0x100 + 2;
+    const MAX_CHAIN: usize = 16;
+    const BLOCK_SIZE: usize = 0x4000;
+
+    let p_bit = match algo {
+        DecompressionAlgorithm::TianoDecompress => 5,
+        _ => 4,
+    };
+
+    // (Char&Len symbol, position) pairs, the position is only used by string pointers.
+    let mut symbols = Vec::new();
+    let mut head = alloc::vec![usize::MAX; 1 << 15];
+    let mut chain = alloc::vec![usize::MAX; data.len()];
+    let hash = |idx: usize| (data[idx] as usize) << 7 ^ (data[idx + 1] as usize) << 4 ^ data[idx + 2] as usize;
+    let mut idx = 0;
+    while idx < data.len() {
+        let (mut best_len, mut best_pos) = (0, 0);
+        if idx + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(idx)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || idx - candidate > WINDOW {
+                    break;
+                }
+                let len =
+                    data[candidate..].iter().zip(&data[idx..]).take(MAX_MATCH).take_while(|(a, b)| a == b).count();
+                if len > best_len {
+                    (best_len, best_pos) = (len, idx - candidate - 1);
+                }
+                candidate = chain[candidate];
+            }
+        }
+        let advance = if best_len >= MIN_MATCH {
+            symbols.push((best_len + 0x100 - 3, best_pos));
+            best_len
+        } else {
+            symbols.push((data[idx] as usize, 0));
+            1
+        };
+        for pos in idx..idx + advance {
+            if pos + MIN_MATCH <= data.len() {
+                chain[pos] = head[hash(pos)];
+                head[hash(pos)] = pos;
+            }
+        }
+        idx += advance;
+    }
+
+    let mut entropy = Entropy::from_seed(0);
+    let mut writer = BitWriter::default();
+    for block in symbols.chunks(BLOCK_SIZE) {
+        let p_symbol = |pos: usize| (usize::BITS - pos.leading_zeros()) as usize;
+        let mut c_symbols = block.iter().map(|&(c, _)| c).collect::<Vec<_>>();
+        let mut p_symbols = block.iter().filter(|&&(c, _)| c >= 256).map(|&(_, pos)| p_symbol(pos)).collect::<Vec<_>>();
+        let c_table = table(&mut c_symbols, NC);
+        let p_table = table(&mut p_symbols, MAXNP);
+        let c_len_codes = c_len_codes(&c_table.lengths);
+        let t_table = HuffmanTable::for_symbols(&mut entropy, &c_len_codes, NT);
+
+        writer.push(block.len(), 16);
+        t_table.write_lengths(&mut writer, TBIT, true);
+        write_c_lengths(&mut writer, &t_table, &c_table, &c_len_codes);
+        p_table.write_lengths(&mut writer, p_bit, false);
+        for &(c, pos) in block {
+            c_table.write_symbol(&mut writer, c);
+            if c >= 256 {
+                let p = p_symbol(pos);
+                p_table.write_symbol(&mut writer, p);
+                if p > 1 {
+                    writer.push(pos - (1 << (p - 1)), p - 1);
+                }
+            }
+        }
+    }
+
+    // The decoder looks ahead up to 16 bits past the last code.
+    writer.push(0, 16);
+    let mut stream = Vec::with_capacity(8 + writer.bytes.len());
+    stream.extend_from_slice(&(writer.bytes.len() as u32).to_le_bytes());
+    stream.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    stream.extend_from_slice(&writer.bytes);
+    stream
+}
+
+// Balanced table coding the distinct *symbols*.
+fn table(symbols: &mut Vec<usize>, num_symbols: usize) -> HuffmanTable {
+    symbols.sort_unstable();
+    symbols.dedup();
+    match symbols.len() {
+        0 => HuffmanTable::single(0, num_symbols),
+        1 => HuffmanTable::single(symbols[0], num_symbols),
+        _ => HuffmanTable::balanced(symbols, num_symbols),
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_count: usize,
+}
+
+impl BitWriter {
+    // Appends the `count` low bits of `value`, most significant bit first.
+    fn push(&mut self, value: usize, count: usize) {
+        for bit in (0..count).rev() {
+            if self.bit_count % 8 == 0 {
+                self.bytes.push(0);
+            }
+            if value >> bit & 1 != 0 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bit_count % 8);
+            }
+            self.bit_count += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    // A single symbol, coded with 0 bits.
+    Single(usize),
+    // Code lengths are written but all of them are zero.
+    ZeroLengths,
+    // Complete canonical code described by the code lengths.
+    Lengths,
+}
+
+struct HuffmanTable {
+    shape: Shape,
+    // Code length of each symbol.
+    lengths: Vec<u8>,
+    // Canonical code of each symbol, as assigned by the decoder.
+    codes: Vec<u16>,
+}
+
+impl HuffmanTable {
+    // Creates a random table over `num_symbols` symbols, where at most `max_count` code lengths can be written.
+    fn new(entropy: &mut Entropy, num_symbols: usize, max_count: usize) -> Self {
+        let max_count = usize::min(max_count, num_symbols);
+        match entropy.below(8) {
+            0 => Self::single(entropy.below(num_symbols), num_symbols),
+            1 => Self::from_lengths(Shape::ZeroLengths, alloc::vec![0; num_symbols], num_symbols),
+            2..=4 => {
+                // Skewed code: lengths 1, 2, ..., n - 1, n - 1, up to the maximum code length.
+                let count = 2 + entropy.below(usize::min(max_count, MAX_CODE_LEN + 1) - 1);
+                let symbols = pick_symbols(entropy, count, max_count);
+                let mut lengths = alloc::vec![0; num_symbols];
+                for (idx, &symbol) in symbols.iter().enumerate() {
+                    lengths[symbol] = usize::min(idx + 1, count - 1) as u8;
+                }
+                Self::from_lengths(Shape::Lengths, lengths, num_symbols)
+            }
+            _ => {
+                let count = 2 + entropy.below(max_count - 1);
+                Self::balanced(&pick_symbols(entropy, count, max_count), num_symbols)
+            }
+        }
+    }
+
+    // Creates a table able to code `codes`, with a random shape among those that can.
+    fn for_symbols(entropy: &mut Entropy, codes: &[(usize, usize, usize)], num_symbols: usize) -> Self {
+        let mut symbols = codes.iter().map(|&(symbol, _, _)| symbol).collect::<Vec<_>>();
+        symbols.sort_unstable();
+        symbols.dedup();
+        match symbols.len() {
+            0 => Self::single(0, num_symbols),
+            1 => Self::single(symbols[0], num_symbols),
+            _ if symbols.len() <= MAX_CODE_LEN + 1 && entropy.below(2) == 0 => {
+                let mut lengths = alloc::vec![0; num_symbols];
+                for (idx, &symbol) in symbols.iter().enumerate() {
+                    lengths[symbol] = usize::min(idx + 1, symbols.len() - 1) as u8;
+                }
+                Self::from_lengths(Shape::Lengths, lengths, num_symbols)
+            }
+            _ => Self::balanced(&symbols, num_symbols),
+        }
+    }
+
+    fn single(symbol: usize, num_symbols: usize) -> Self {
+        Self { shape: Shape::Single(symbol), lengths: alloc::vec![0; num_symbols], codes: alloc::vec![0; num_symbols] }
+    }
+
+    // Complete code with every length within one bit of log2(symbols.len()).
+    fn balanced(symbols: &[usize], num_symbols: usize) -> Self {
+        let bits = symbols.len().next_power_of_two().trailing_zeros() as usize;
+        let short = (1 << bits) - symbols.len();
+        let mut lengths = alloc::vec![0; num_symbols];
+        for (idx, &symbol) in symbols.iter().enumerate() {
+            lengths[symbol] = if idx < short { bits - 1 } else { bits } as u8;
+        }
+        Self::from_lengths(Shape::Lengths, lengths, num_symbols)
+    }
+
+    fn from_lengths(shape: Shape, mut lengths: Vec<u8>, num_symbols: usize) -> Self {
+        // Canonical code assignment, matching CodeIterator::build_huffman_table.
+        let mut count = [0usize; MAX_CODE_LEN + 1];
+        for &len in lengths.iter() {
+            count[len as usize] += 1;
+        }
+        let mut next = [0usize; MAX_CODE_LEN + 2];
+        for len in 1..=MAX_CODE_LEN {
+            next[len + 1] = (next[len] + count[len]) << 1;
+        }
+        let mut codes = alloc::vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                codes[symbol] = next[len as usize] as u16;
+                next[len as usize] += 1;
+            }
+        }
+        lengths.resize(usize::max(lengths.len(), num_symbols), 0);
+        codes.resize(lengths.len(), 0);
+        Self { shape, lengths, codes }
+    }
+
+    // Picks a symbol that can be coded with this table, preferring the ones matching `filter`.
+    fn pick(&self, entropy: &mut Entropy, filter: impl Fn(usize) -> bool) -> usize {
+        if let Shape::Single(symbol) = self.shape {
+            return symbol;
+        }
+        let candidates = (0..self.lengths.len()).filter(|&s| self.lengths[s] != 0).collect::<Vec<_>>();
+        let preferred = candidates.iter().copied().filter(|&s| filter(s)).collect::<Vec<_>>();
+        match (preferred.len(), candidates.len()) {
+            (0, 0) => entropy.below(self.lengths.len()),
+            (0, n) => candidates[entropy.below(n)],
+            (n, _) => preferred[entropy.below(n)],
+        }
+    }
+
+    fn write_symbol(&self, writer: &mut BitWriter, symbol: usize) {
+        writer.push(self.codes[symbol] as usize, self.lengths[symbol] as usize);
+    }
+
+    // Writes the code length array as read by CodeIterator::read_pt_len.
+    fn write_lengths(&self, writer: &mut BitWriter, num_bits: usize, extra: bool) {
+        let count = match self.shape {
+            Shape::Single(symbol) => {
+                writer.push(0, num_bits);
+                writer.push(symbol, num_bits);
+                return;
+            }
+            Shape::ZeroLengths => usize::min(self.lengths.len(), (1 << num_bits) - 1),
+            Shape::Lengths => self.lengths.iter().rposition(|&len| len != 0).map_or(0, |idx| idx + 1),
+        };
+        writer.push(count, num_bits);
+        let mut idx = 0;
+        while idx < count {
+            let len = self.lengths[idx] as usize;
+            if len < 7 {
+                writer.push(len, 3);
+            } else {
+                writer.push(0b111, 3);
+                writer.push((1 << (len - 7)) - 1, len - 7);
+                writer.push(0, 1);
+            }
+            idx += 1;
+            if extra && idx == 3 {
+                let zeros = self.lengths[3..count].iter().take(3).take_while(|&&len| len == 0).count();
+                writer.push(zeros, 2);
+                idx += zeros;
+            }
+        }
+    }
+}
+
+// Picks `count` distinct symbols in 0..num_symbols.
+fn pick_symbols(entropy: &mut Entropy, count: usize, num_symbols: usize) -> Vec<usize> {
+    let mut symbols = (0..num_symbols).collect::<Vec<_>>();
+    for idx in 0..count {
+        let other = idx + entropy.below(num_symbols - idx);
+        symbols.swap(idx, other);
+    }
+    symbols.truncate(count);
+    symbols
+}
+
+// Run length codes of the Char&Len set code lengths, as read by CodeIterator::read_c_len.
+//
+// Each entry is (extra set symbol, extra bits value, extra bits count).
+fn c_len_codes(lengths: &[u8]) -> Vec<(usize, usize, usize)> {
+    let count = if lengths.iter().all(|&len| len == 0) {
+        // Zero length tables still write an array, a zero count would mean a single symbol table.
+        lengths.len()
+    } else {
+        lengths.iter().rposition(|&len| len != 0).unwrap() + 1
+    };
+    let mut codes = Vec::new();
+    let mut idx = 0;
+    while idx < count {
+        if lengths[idx] != 0 {
+            codes.push((lengths[idx] as usize + 2, 0, 0));
+            idx += 1;
+            continue;
+        }
+        let run = lengths[idx..count].iter().take_while(|&&len| len == 0).count();
+        match run {
+            1..=2 => codes.extend((0..run).map(|_| (0, 0, 0))),
+            3..=18 => codes.push((1, run - 3, 4)),
+            19 => codes.extend([(0, 0, 0), (1, 15, 4)]),
+            _ => codes.push((2, run - 20, CBIT)),
+        }
+        idx += run;
+    }
+    codes
+}
+
+fn write_c_lengths(
+    writer: &mut BitWriter,
+    t_table: &HuffmanTable,
+    c_table: &HuffmanTable,
+    codes: &[(usize, usize, usize)],
+) {
+    if let Shape::Single(symbol) = c_table.shape {
+        writer.push(0, CBIT);
+        writer.push(symbol, CBIT);
+        return;
+    }
+    let count = codes.iter().map(|&(symbol, value, _)| match symbol {
+        0 => 1,
+        1 => value + 3,
+        2 => value + 20,
+        _ => 1,
+    });
+    writer.push(count.sum(), CBIT);
+    for &(symbol, value, bits) in codes {
+        t_table.write_symbol(writer, symbol);
+        writer.push(value, bits);
+    }
+}
@@ -0,0 +1,287 @@
+//! Extraction of the GUID-defined sections of firmware files, with a registry of extraction handlers.
+//!
+//! The data of a GUID-defined section (`EFI_SECTION_GUID_DEFINED`) is encoded by the algorithm identified by the GUID
+//! of its header, e.g. a compression or a signature. A [`SectionExtractors`] registry maps these GUIDs to extraction
+//! handlers, so firmware volume parsers can support LZMA, Brotli or signed sections by registering a handler rather
+//! than being forked. [`SectionExtractors::new`] comes with the Tiano decompressor of this crate registered.
+//!
+//! ```ignore
+//! fn extract_lzma(section: &GuidedSection) -> Result<Vec<u8>, SectionError> {
+//!     lzma::decompress(section.data).map_err(|_| SectionError::Extraction)
+//! }
+//!
+//! let mut extractors = SectionExtractors::new();
+//! extractors.register(LZMA_CUSTOM_DECOMPRESS_GUID, extract_lzma);
+//! let pe32_sections = extractors.extract(guided_section)?;
+//! ```
+
+use alloc::vec::Vec;
+
+use r_efi::efi;
+
+use crate::{decompress, DecompressError, DecompressionAlgorithm};
+
+/// Section type of the GUID-defined sections.
+pub const EFI_SECTION_GUID_DEFINED: u8 = 0x02;
+
+/// Attribute of the sections that must be processed by the handler of their GUID before their data can be used.
+pub const EFI_GUIDED_SECTION_PROCESSING_REQUIRED: u16 = 0x01;
+
+/// Attribute of the sections whose handler reports an authentication status, such as signed sections.
+pub const EFI_GUIDED_SECTION_AUTH_STATUS_VALID: u16 = 0x02;
+
+/// GUID of the sections compressed with the EDK2 Tiano compressor, `gTianoCustomDecompressGuid`.
+pub const TIANO_CUSTOM_DECOMPRESS_GUID: efi::Guid =
+    efi::Guid::from_fields(0xa31280ad, 0x481e, 0x41b6, 0x95, 0xe8, &[0x12, 0x7f, 0x4c, 0x98, 0x47, 0x79]);
+
+/// GUID of the sections compressed with LZMA, `gLzmaCustomDecompressGuid`.
+pub const LZMA_CUSTOM_DECOMPRESS_GUID: efi::Guid =
+    efi::Guid::from_fields(0xee4e5898, 0x3914, 0x4259, 0x9d, 0x6e, &[0xdc, 0x7b, 0xd7, 0x94, 0x03, 0xcf]);
+
+/// GUID of the sections compressed with Brotli, `gBrotliCustomDecompressGuid`.
+pub const BROTLI_CUSTOM_DECOMPRESS_GUID: efi::Guid =
+    efi::Guid::from_fields(0x3d532050, 0x5cda, 0x4fd0, 0x87, 0x9e, &[0x0f, 0x7f, 0x63, 0x0d, 0x5a, 0xfb]);
+
+// Size of EFI_COMMON_SECTION_HEADER, and of EFI_COMMON_SECTION_HEADER2 for the sections of 16MB or more.
+const HEADER_SIZE: usize = 4;
+const HEADER2_SIZE: usize = 8;
+// Size of the SectionDefinitionGuid, DataOffset and Attributes fields following the common header.
+const GUID_DEFINED_FIELDS_SIZE: usize = 20;
+
+/// Error of the extraction of a GUID-defined section.
+#[derive(Debug)]
+pub enum SectionError {
+    /// The section is not a GUID-defined section, or its header is inconsistent with its size.
+    Malformed,
+    /// The section requires processing and no handler is registered for its GUID.
+    UnsupportedGuid(efi::Guid),
+    /// The data of the section could not be decompressed.
+    Decompress(DecompressError),
+    /// A registered handler could not extract the section, e.g. because its signature is invalid.
+    Extraction,
+}
+
+impl From<DecompressError> for SectionError {
+    fn from(err: DecompressError) -> Self {
+        SectionError::Decompress(err)
+    }
+}
+
+/// A GUID-defined section, as given to the extraction handlers.
+#[derive(Debug, Clone, Copy)]
+pub struct GuidedSection<'a> {
+    /// GUID of the algorithm encoding the data, `SectionDefinitionGuid`.
+    pub guid: efi::Guid,
+    /// Attributes of the section, see [`EFI_GUIDED_SECTION_PROCESSING_REQUIRED`].
+    pub attributes: u16,
+    /// Header of the section, including the GUID specific fields before the data.
+    pub header: &'a [u8],
+    /// Data of the section, from `DataOffset` to the end of the section.
+    pub data: &'a [u8],
+}
+
+impl<'a> GuidedSection<'a> {
+    /// Parse the GUID-defined section at the start of *section*.
+    ///
+    /// *section* may extend past the end of the section, e.g. to the next sections of the file.
+    pub fn parse(section: &'a [u8]) -> Result<Self, SectionError> {
+        let field = |offset: usize, len: usize| section.get(offset..offset + len).ok_or(SectionError::Malformed);
+
+        let common = field(0, HEADER_SIZE)?;
+        if common[3] != EFI_SECTION_GUID_DEFINED {
+            return Err(SectionError::Malformed);
+        }
+        let (size, header_size) = match u32::from_le_bytes([common[0], common[1], common[2], 0]) {
+            0xFF_FFFF => (u32::from_le_bytes(field(4, 4)?.try_into().unwrap()) as usize, HEADER2_SIZE),
+            size => (size as usize, HEADER_SIZE),
+        };
+
+        let guid = efi::Guid::from_bytes(field(header_size, 16)?.try_into().unwrap());
+        let data_offset = u16::from_le_bytes(field(header_size + 16, 2)?.try_into().unwrap()) as usize;
+        let attributes = u16::from_le_bytes(field(header_size + 18, 2)?.try_into().unwrap());
+        if data_offset < header_size + GUID_DEFINED_FIELDS_SIZE || data_offset > size || size > section.len() {
+            return Err(SectionError::Malformed);
+        }
+        Ok(Self { guid, attributes, header: &section[..data_offset], data: &section[data_offset..size] })
+    }
+
+    /// Returns true if the data must be processed by the handler of the GUID before it can be used.
+    pub fn processing_required(&self) -> bool {
+        self.attributes & EFI_GUIDED_SECTION_PROCESSING_REQUIRED != 0
+    }
+}
+
+/// Extracts the data of a GUID-defined section, usually the encapsulated sections.
+pub type ExtractionHandler = fn(&GuidedSection) -> Result<Vec<u8>, SectionError>;
+
+/// Registry of the [`ExtractionHandler`] of the GUID-defined sections, by GUID.
+#[derive(Debug, Clone)]
+pub struct SectionExtractors {
+    handlers: Vec<(efi::Guid, ExtractionHandler)>,
+}
+
+impl SectionExtractors {
+    /// Create a registry with the Tiano decompressor registered for [`TIANO_CUSTOM_DECOMPRESS_GUID`].
+    pub fn new() -> Self {
+        let mut extractors = Self::empty();
+        extractors.register(TIANO_CUSTOM_DECOMPRESS_GUID, extract_tiano);
+        extractors
+    }
+
+    /// Create a registry without any handler.
+    pub const fn empty() -> Self {
+        Self { handlers: Vec::new() }
+    }
+
+    /// Registers *handler* for the sections of *guid*, returning the handler it replaces if any.
+    pub fn register(&mut self, guid: efi::Guid, handler: ExtractionHandler) -> Option<ExtractionHandler> {
+        match self.handlers.iter_mut().find(|(g, _)| *g == guid) {
+            Some((_, registered)) => Some(core::mem::replace(registered, handler)),
+            None => {
+                self.handlers.push((guid, handler));
+                None
+            }
+        }
+    }
+
+    /// Unregisters the handler of *guid*, returning it if any.
+    pub fn unregister(&mut self, guid: &efi::Guid) -> Option<ExtractionHandler> {
+        let idx = self.handlers.iter().position(|(g, _)| g == guid)?;
+        Some(self.handlers.remove(idx).1)
+    }
+
+    /// Returns the handler registered for *guid*.
+    pub fn handler(&self, guid: &efi::Guid) -> Option<ExtractionHandler> {
+        self.handlers.iter().find(|(g, _)| g == guid).map(|(_, handler)| *handler)
+    }
+
+    /// Extracts the data of the GUID-defined section at the start of *section* with the handler of its GUID.
+    ///
+    /// The data of a section that does not require processing is returned as is if no handler is registered for its
+    /// GUID, as allowed by the PI specification.
+    pub fn extract(&self, section: &[u8]) -> Result<Vec<u8>, SectionError> {
+        let section = GuidedSection::parse(section)?;
+        match self.handler(&section.guid) {
+            Some(handler) => handler(&section),
+            None if !section.processing_required() => Ok(section.data.to_vec()),
+            None => Err(SectionError::UnsupportedGuid(section.guid)),
+        }
+    }
+}
+
+impl Default for SectionExtractors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Decompresses the data of a section compressed with the Tiano compressor.
+fn extract_tiano(section: &GuidedSection) -> Result<Vec<u8>, SectionError> {
+    Ok(decompress(section.data, DecompressionAlgorithm::TianoDecompress)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fuzzing;
+    use alloc::vec;
+
+    // Builds a GUID-defined section of *guid* around *data*.
+    fn guided_section(guid: &efi::Guid, attributes: u16, data: &[u8]) -> Vec<u8> {
+        let data_offset = HEADER_SIZE + GUID_DEFINED_FIELDS_SIZE;
+        let size = (data_offset + data.len()) as u32;
+        let mut section = size.to_le_bytes()[..3].to_vec();
+        section.push(EFI_SECTION_GUID_DEFINED);
+        section.extend_from_slice(guid.as_bytes());
+        section.extend_from_slice(&(data_offset as u16).to_le_bytes());
+        section.extend_from_slice(&attributes.to_le_bytes());
+        section.extend_from_slice(data);
+        section
+    }
+
+    #[test]
+    fn tiano_sections_should_be_extracted_by_default() {
+        let payload = b"EFI_SECTION_PE32".repeat(64);
+        let compressed = fuzzing::compress(&payload, DecompressionAlgorithm::TianoDecompress);
+        let mut section =
+            guided_section(&TIANO_CUSTOM_DECOMPRESS_GUID, EFI_GUIDED_SECTION_PROCESSING_REQUIRED, &compressed);
+        // Bytes of the next section of the file.
+        section.extend_from_slice(&[0xFF; 4]);
+
+        let parsed = GuidedSection::parse(&section).unwrap();
+        assert_eq!(TIANO_CUSTOM_DECOMPRESS_GUID, parsed.guid);
+        assert_eq!(compressed, parsed.data);
+        assert_eq!(payload, SectionExtractors::new().extract(&section).unwrap());
+        assert!(matches!(SectionExtractors::empty().extract(&section), Err(SectionError::UnsupportedGuid(_))));
+    }
+
+    #[test]
+    fn registered_handlers_should_extract_their_sections() {
+        fn extract_reversed(section: &GuidedSection) -> Result<Vec<u8>, SectionError> {
+            Ok(section.data.iter().rev().copied().collect())
+        }
+        fn reject(_section: &GuidedSection) -> Result<Vec<u8>, SectionError> {
+            Err(SectionError::Extraction)
+        }
+
+        let mut extractors = SectionExtractors::new();
+        assert!(extractors.register(LZMA_CUSTOM_DECOMPRESS_GUID, extract_reversed).is_none());
+        assert!(extractors.register(LZMA_CUSTOM_DECOMPRESS_GUID, reject).is_some());
+        assert!(extractors.register(BROTLI_CUSTOM_DECOMPRESS_GUID, extract_reversed).is_none());
+
+        let lzma = guided_section(&LZMA_CUSTOM_DECOMPRESS_GUID, EFI_GUIDED_SECTION_PROCESSING_REQUIRED, b"abc");
+        assert!(matches!(extractors.extract(&lzma), Err(SectionError::Extraction)));
+        let brotli = guided_section(&BROTLI_CUSTOM_DECOMPRESS_GUID, EFI_GUIDED_SECTION_PROCESSING_REQUIRED, b"abc");
+        assert_eq!(b"cba".to_vec(), extractors.extract(&brotli).unwrap());
+
+        // Sections not requiring processing can be used as is without a handler.
+        assert!(extractors.unregister(&BROTLI_CUSTOM_DECOMPRESS_GUID).is_some());
+        let brotli = guided_section(&BROTLI_CUSTOM_DECOMPRESS_GUID, EFI_GUIDED_SECTION_AUTH_STATUS_VALID, b"abc");
+        assert_eq!(b"abc".to_vec(), extractors.extract(&brotli).unwrap());
+    }
+
+    #[test]
+    fn malformed_sections_should_be_rejected() {
+        let section = guided_section(&TIANO_CUSTOM_DECOMPRESS_GUID, 0, b"data");
+        let extractors = SectionExtractors::new();
+
+        assert!(matches!(GuidedSection::parse(&section[..HEADER_SIZE + 10]), Err(SectionError::Malformed)));
+        assert!(matches!(GuidedSection::parse(&section[..section.len() - 1]), Err(SectionError::Malformed)));
+
+        let mut not_guided = section.clone();
+        not_guided[3] = 0x10;
+        assert!(matches!(GuidedSection::parse(&not_guided), Err(SectionError::Malformed)));
+
+        let mut bad_offset = section.clone();
+        bad_offset[HEADER_SIZE + 16] = 2;
+        assert!(matches!(GuidedSection::parse(&bad_offset), Err(SectionError::Malformed)));
+
+        assert!(matches!(extractors.extract(&section), Err(SectionError::Decompress(DecompressError::InvalidSrcSize))));
+    }
+
+    #[test]
+    fn huge_original_sizes_should_be_rejected() {
+        let mut compressed = fuzzing::compress(b"EFI_SECTION_PE32", DecompressionAlgorithm::TianoDecompress);
+        compressed[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let section =
+            guided_section(&TIANO_CUSTOM_DECOMPRESS_GUID, EFI_GUIDED_SECTION_PROCESSING_REQUIRED, &compressed);
+
+        let result = SectionExtractors::new().extract(&section);
+        assert!(matches!(result, Err(SectionError::Decompress(DecompressError::InvalidDstSize))), "{:?}", result);
+    }
+
+    #[test]
+    fn large_sections_should_use_the_extended_header() {
+        let data_offset = HEADER2_SIZE + GUID_DEFINED_FIELDS_SIZE;
+        let mut section = vec![0xFF, 0xFF, 0xFF, EFI_SECTION_GUID_DEFINED];
+        section.extend_from_slice(&((data_offset + 3) as u32).to_le_bytes());
+        section.extend_from_slice(LZMA_CUSTOM_DECOMPRESS_GUID.as_bytes());
+        section.extend_from_slice(&(data_offset as u16).to_le_bytes());
+        section.extend_from_slice(&0_u16.to_le_bytes());
+        section.extend_from_slice(b"xyz");
+
+        let parsed = GuidedSection::parse(&section).unwrap();
+        assert_eq!((LZMA_CUSTOM_DECOMPRESS_GUID, b"xyz".as_slice()), (parsed.guid, parsed.data));
+        assert_eq!(data_offset, parsed.header.len());
+    }
+}
@@ -1,19 +1,148 @@
 #![no_std]
 use bitvec::{field::BitField, order::Msb0, slice::BitSlice, view::BitView};
 
+#[cfg(any(test, feature = "alloc"))]
+extern crate alloc;
+
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
+
+#[cfg(any(test, feature = "guided_section"))]
+pub mod guided_section;
+
+#[cfg(any(test, feature = "std"))]
+pub mod parallel;
+
+#[cfg(test)]
+mod differential;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
+
 /// Decompress Error Definitions
 #[derive(Debug)]
 pub enum DecompressError {
     InvalidSrcSize,
     InvalidDstSize,
     MalformedSrcData,
+    /// A back-reference reaches further back than the sliding window of the algorithm.
+    WindowExceeded,
+    /// [`DecompressionAlgorithm::AutoDetect`] was given to [`decompress_into_with_options`], which needs the algorithm
+    /// of the compressed data.
+    UnknownAlgorithm,
 }
 
-/// Supported Decompression Algorithms
+/// Error of [`decompress_into_with_options`], with the length of the output decoded before the error.
 #[derive(Debug)]
+pub struct PartialDecompressError {
+    pub error: DecompressError,
+    /// Number of bytes at the start of the destination buffer decoded before the error.
+    pub produced: usize,
+}
+
+/// Sliding window size of the UEFI compressor, the largest distance of a back-reference.
+pub const UEFI_WINDOW_SIZE: usize = 1 << 13;
+
+/// Sliding window size of the EDK2 Tiano compressor, the largest distance of a back-reference.
+pub const TIANO_WINDOW_SIZE: usize = 1 << 19;
+
+// Smallest chunk of output passed to DecompressOptions::on_output, but the last one.
+const OUTPUT_CHUNK_SIZE: usize = 1 << 12;
+
+type OutputObserver<'a> = &'a mut dyn FnMut(&[u8]);
+
+/// Supported Decompression Algorithms
+#[derive(Debug, Clone, Copy)]
 pub enum DecompressionAlgorithm {
     UefiDecompress,
     TianoDecompress,
+    /// Attempt [`DecompressionAlgorithm::UefiDecompress`] first and fall back to
+    /// [`DecompressionAlgorithm::TianoDecompress`] if the source data is malformed for the UEFI algorithm.
+    ///
+    /// This is a heuristic. The two algorithms only differ by the bit width of the position set size, so a stream of
+    /// one algorithm usually desynchronizes the decoder of the other, but nothing guarantees it: a Tiano stream could
+    /// decode without error as a UEFI one, into the wrong output. Use it only when the algorithm is not known, e.g. not
+    /// for GUIDed sections whose GUID names the algorithm. [`decompress_into_with_options`] rejects it.
+    AutoDetect,
+}
+
+/// Options of [`decompress_into_with_options`].
+///
+/// The default options are strict: the source must hold exactly the compressed data, the destination exactly the
+/// original data, and back-references are bounded by [`DecompressionAlgorithm::window_size`].
+///
+/// ```ignore
+/// // Decompress a padded section into a larger buffer, hashing the output as it is decoded.
+/// let options = DecompressOptions {
+///     allow_trailing_bytes: true,
+///     require_exact_dst: false,
+///     on_output: Some(&mut |chunk| hasher.update(chunk)),
+///     ..Default::default()
+/// };
+/// let len = decompress_into_with_options(section, &mut buffer, DecompressionAlgorithm::TianoDecompress, options)?;
+/// ```
+pub struct DecompressOptions<'a> {
+    /// Accept bytes after the compressed data, such as the alignment padding of firmware file sections. They are never
+    /// read by the decoder.
+    pub allow_trailing_bytes: bool,
+    /// Require the destination to be exactly the original size, rather than at least the original size in which case
+    /// the output is written at the start of the destination and the bytes after it are left untouched.
+    pub require_exact_dst: bool,
+    /// Reject back-references further than this many bytes with [`DecompressError::WindowExceeded`], rather than
+    /// [`DecompressionAlgorithm::window_size`].
+    ///
+    /// This is meant for streams of compressor forks using another window size, or to bound the data a crafted stream
+    /// can reference.
+    pub window_size: Option<usize>,
+    /// Called with the output in order as it is decoded.
+    ///
+    /// This lets a caller measure decompressed data, e.g. feed a SHA-256 hasher, while the output is still in the
+    /// cache rather than in a second pass over the whole buffer. The output is passed in chunks of at least 4 KiB but
+    /// the last one, and only the chunks of a successful decompression make up the whole output: on error, the chunks
+    /// passed so far must be discarded.
+    pub on_output: Option<OutputObserver<'a>>,
+}
+
+impl Default for DecompressOptions<'_> {
+    fn default() -> Self {
+        Self { allow_trailing_bytes: false, require_exact_dst: true, window_size: None, on_output: None }
+    }
+}
+
+impl core::fmt::Debug for DecompressOptions<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DecompressOptions")
+            .field("allow_trailing_bytes", &self.allow_trailing_bytes)
+            .field("require_exact_dst", &self.require_exact_dst)
+            .field("window_size", &self.window_size)
+            .field("on_output", &self.on_output.is_some())
+            .finish()
+    }
+}
+
+impl DecompressionAlgorithm {
+    /// Returns the sliding window size of the compressor of the algorithm.
+    ///
+    /// [`DecompressionAlgorithm::AutoDetect`] returns the largest one, each detection pass uses the window size of the
+    /// algorithm it tries.
+    pub const fn window_size(self) -> usize {
+        match self {
+            DecompressionAlgorithm::UefiDecompress => UEFI_WINDOW_SIZE,
+            DecompressionAlgorithm::TianoDecompress | DecompressionAlgorithm::AutoDetect => TIANO_WINDOW_SIZE,
+        }
+    }
+}
+
+// Returns the size at *offset* in the header of `src`, or *err* if `src` is too short or the size does not fit a usize
+// of the target.
+fn header_size(src: &[u8], offset: usize, err: DecompressError) -> Result<usize, DecompressError> {
+    let size = src.get(offset..offset + 4).ok_or(DecompressError::InvalidSrcSize)?;
+    u32::from_le_bytes(size.try_into().unwrap()).try_into().map_err(|_| err)
+}
+
+// Returns true if decoding a stream of one algorithm with the other may have produced *err*.
+fn is_algorithm_mismatch(err: &DecompressError) -> bool {
+    matches!(err, DecompressError::MalformedSrcData | DecompressError::WindowExceeded)
 }
 
 /// Decompress the compressed data in `src` and store the output in `dst`, using the `algo` decompression algorithm.
@@ -22,66 +151,283 @@ pub fn decompress_into_with_algo(
     dst: &mut [u8],
     algo: DecompressionAlgorithm,
 ) -> Result<(), DecompressError> {
-    //sanity check the inputs
-    if src.len() < 8 {
-        Err(DecompressError::InvalidSrcSize)?;
+    decompress_into(src, dst, algo, None, None).map_err(|(err, _)| err)
+}
+
+/// Decompress the compressed data in `src` into `dst` with the `algo` decompression algorithm, validating the sizes of
+/// `src` and `dst` against the header of the compressed data as set by `options`, and returns the number of bytes
+/// written at the start of `dst`.
+///
+/// Unlike [`decompress_into_with_algo`], the compressed size of the header is checked exactly: a `src` shorter than the
+/// header and the compressed data is rejected with [`DecompressError::InvalidSrcSize`], and the decoder never reads
+/// past the compressed data.
+///
+/// On error, the first [`PartialDecompressError::produced`] bytes of `dst` hold the output decoded before the error
+/// was detected, so forensic tooling can recover what it can of corrupted compressed sections. Corruption is not
+/// always detected where it occurs, so the end of the partial output may already be corrupted.
+///
+/// The output is only as trustworthy as the guess of [`DecompressionAlgorithm::AutoDetect`], so it is rejected with
+/// [`DecompressError::UnknownAlgorithm`].
+pub fn decompress_into_with_options(
+    src: &[u8],
+    dst: &mut [u8],
+    algo: DecompressionAlgorithm,
+    options: DecompressOptions,
+) -> Result<usize, PartialDecompressError> {
+    if let DecompressionAlgorithm::AutoDetect = algo {
+        Err(PartialDecompressError { error: DecompressError::UnknownAlgorithm, produced: 0 })?;
     }
+    decompress_into_checked(src, dst, algo, options)
+}
 
-    let compressed_size = u32::from_le_bytes(src[0..4].try_into().unwrap()) as usize;
-    if compressed_size > src.len() {
-        Err(DecompressError::InvalidSrcSize)?;
+// Decompress like decompress_into_with_options, with any algorithm.
+fn decompress_into_checked(
+    src: &[u8],
+    dst: &mut [u8],
+    algo: DecompressionAlgorithm,
+    options: DecompressOptions,
+) -> Result<usize, PartialDecompressError> {
+    let error = |error| PartialDecompressError { error, produced: 0 };
+    let compressed_size = header_size(src, 0, DecompressError::InvalidSrcSize).map_err(error)?;
+    let original_size = header_size(src, 4, DecompressError::InvalidDstSize).map_err(error)?;
+
+    let src_size = compressed_size.checked_add(8).ok_or(error(DecompressError::InvalidSrcSize))?;
+    if src_size > src.len() || (src_size < src.len() && !options.allow_trailing_bytes) {
+        Err(error(DecompressError::InvalidSrcSize))?;
     }
+    if original_size > dst.len() || (original_size < dst.len() && options.require_exact_dst) {
+        Err(error(DecompressError::InvalidDstSize))?;
+    }
+    decompress_into(&src[..src_size], &mut dst[..original_size], algo, options.window_size, options.on_output)
+        .map_err(|(error, produced)| PartialDecompressError { error, produced })?;
+    Ok(original_size)
+}
 
-    let orig_size = u32::from_le_bytes(src[4..8].try_into().unwrap()) as usize;
-    if orig_size != dst.len() {
+/// Decompress the compressed data in `src` with the `algo` decompression algorithm, returning the output in a buffer of
+/// the original size read from the header.
+///
+/// Bytes after the compressed data are ignored, as with [`DecompressOptions::allow_trailing_bytes`]. If the original
+/// size is more than `src` can hold or can not be allocated, [`DecompressError::InvalidDstSize`] is returned rather
+/// than allocating for a corrupted header or aborting.
+#[cfg(any(test, feature = "alloc"))]
+pub fn decompress(src: &[u8], algo: DecompressionAlgorithm) -> Result<alloc::vec::Vec<u8>, DecompressError> {
+    let original_size = header_size(src, 4, DecompressError::InvalidDstSize)?;
+    if original_size > max_original_size(src.len()) {
         Err(DecompressError::InvalidDstSize)?;
     }
 
-    //Create a code iterator that iterates through the `src` bitstream and returns `CodeSymbol` elements.
+    let mut dst = alloc::vec::Vec::new();
+    dst.try_reserve_exact(original_size).map_err(|_| DecompressError::InvalidDstSize)?;
+    dst.resize(original_size, 0);
+    let options = DecompressOptions { allow_trailing_bytes: true, ..Default::default() };
+    decompress_into_checked(src, &mut dst, algo, options).map_err(|err| err.error)?;
+    Ok(dst)
+}
+
+// Returns the most bytes a stream of `src_size` bytes decodes to. A block takes at least 52 bits, its 16-bit size and
+// three tables of a single 0-bit code (10, 18 and at least 8 bits), and holds at most 65536 symbols of 256 bytes.
+#[cfg(any(test, feature = "alloc"))]
+fn max_original_size(src_size: usize) -> usize {
+    src_size.saturating_mul(8).div_ceil(52).saturating_mul(0x10000 * 256)
+}
+
+// Decodes `src` into `dst`, the error comes with the number of bytes decoded. Without an observer, AutoDetect is
+// resolved by decoding with the UEFI algorithm and then with the Tiano one if the stream does not match.
+fn decompress_into(
+    src: &[u8],
+    dst: &mut [u8],
+    algo: DecompressionAlgorithm,
+    window_size: Option<usize>,
+    on_output: Option<OutputObserver>,
+) -> Result<(), (DecompressError, usize)> {
+    if let (DecompressionAlgorithm::AutoDetect, None) = (algo, &on_output) {
+        // A Tiano stream decoded with the UEFI algorithm is expected to desynchronize the bitstream and be reported as
+        // malformed (and vice versa), see DecompressionAlgorithm::AutoDetect.
+        return match decode_into(src, dst, DecompressionAlgorithm::UefiDecompress, window_size, None) {
+            Err(uefi) if is_algorithm_mismatch(&uefi.0) => {
+                decode_into(src, dst, DecompressionAlgorithm::TianoDecompress, window_size, None)
+            }
+            result => result,
+        };
+    }
+    decode_into(src, dst, algo, window_size, on_output)
+}
+
+// Decodes `src` into `dst`, passing the output to `on_output` in chunks of at least OUTPUT_CHUNK_SIZE bytes but the
+// last one. The error comes with the number of bytes decoded. AutoDetect is resolved by the SymbolIterator, with an
+// extra decoding pass.
+fn decode_into(
+    src: &[u8],
+    dst: &mut [u8],
+    algo: DecompressionAlgorithm,
+    window_size: Option<usize>,
+    mut on_output: Option<OutputObserver>,
+) -> Result<(), (DecompressError, usize)> {
+    let symbols = SymbolIterator::with_window_size(src, algo, window_size).map_err(|err| (err, 0))?;
+    if symbols.original_size() != dst.len() {
+        Err((DecompressError::InvalidDstSize, 0))?;
+    }
+
     let mut dst_idx = 0;
-    for result in CodeIterator::new(&src[8..], algo) {
-        match result {
-            Ok(symbol) => match symbol {
-                CodeSymbol::OrigChar(char) => {
-                    // symbol is an original character literal - copy it directly to the output buffer.
-                    dst[dst_idx] = char;
-                    dst_idx += 1;
-                }
-                CodeSymbol::StrPointer(offset, len) => {
-                    // symbol is offset:len pair to be copied from a previously decompressed portion of the buffer.
-                    let start = dst_idx
-                        .checked_sub(offset)
-                        .and_then(|x| x.checked_sub(1))
-                        .ok_or(DecompressError::MalformedSrcData)?;
-
-                    // note: this loop is used (instead of e.g. slice::copy_within or slice::copy_non_overlapping)
-                    // because the offset:len window may overlap the current position. The "new" byte from the
-                    // overlapping region needs to be copied instead of the original byte that existed at the start of
-                    // the copy, which makes copy_within semantics inappropriate here.
-                    for src in start..start + len {
-                        dst[dst_idx] = dst[src];
-                        dst_idx += 1;
-                        if dst_idx == dst.len() {
-                            break;
-                        }
+    let mut observed = 0;
+    for symbol in symbols {
+        match symbol.map_err(|err| (err, dst_idx))? {
+            CodeSymbol::Literal(char) => {
+                // symbol is an original character literal - copy it directly to the output buffer.
+                dst[dst_idx] = char;
+                dst_idx += 1;
+            }
+            CodeSymbol::BackReference { distance, len } => {
+                // symbol is a distance:len pair to be copied from a previously decompressed portion of the buffer. The
+                // SymbolIterator guarantees the window starts in the output and ends at the end of the buffer at most.
+                let start = dst_idx - distance;
+                let end = dst_idx + len;
+                if distance >= len {
+                    // the window does not overlap the current position.
+                    dst.copy_within(start..start + len, dst_idx);
+                    dst_idx = end;
+                } else {
+                    // the window overlaps the current position, so the "new" bytes from the overlapping region
+                    // must be copied instead of the ones that existed at the start of the copy: the output repeats
+                    // the `distance` bytes before the current position. The repeated bytes are copied from `start` in
+                    // chunks doubling each time, which keeps them a whole number of repetitions apart.
+                    while dst_idx < end {
+                        let chunk = (dst_idx - start).min(end - dst_idx);
+                        dst.copy_within(start..start + chunk, dst_idx);
+                        dst_idx += chunk;
                     }
                 }
-            },
-            //CodeIterator encountered an error trying to produce the next symbol - return it to caller.
-            Err(err) => Err(err)?,
+            }
         }
-
-        // Decompression is complete.
-        if dst_idx == dst.len() {
-            break;
+        if let Some(on_output) = on_output.as_mut().filter(|_| dst_idx - observed >= OUTPUT_CHUNK_SIZE) {
+            on_output(&dst[observed..dst_idx]);
+            observed = dst_idx;
         }
     }
+    if let Some(on_output) = on_output.filter(|_| observed < dst_idx) {
+        on_output(&dst[observed..dst_idx]);
+    }
     Ok(())
 }
 
-enum CodeSymbol {
-    OrigChar(u8),
-    StrPointer(usize, usize),
+/// A symbol of the LZ77 stream of compressed data, see [`SymbolIterator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeSymbol {
+    /// A byte copied as-is to the output.
+    Literal(u8),
+    /// A copy of *len* bytes starting *distance* bytes before the current end of the output.
+    ///
+    /// The copy may overlap the bytes it produces when *len* is greater than *distance*, in which case the output
+    /// repeats the last *distance* bytes. It must be done byte by byte, or in chunks of at most *distance* bytes.
+    BackReference { distance: usize, len: usize },
+}
+
+/// Iterator over the [`CodeSymbol`] of compressed data, to consume the LZ77 stream without materializing the output.
+///
+/// The symbols are validated against the output they describe: back-references never start before the beginning of
+/// the output nor reach further than the sliding window, and the last symbol is truncated to end exactly at
+/// [`SymbolIterator::original_size`] bytes. The iteration stops after the first error.
+pub struct SymbolIterator<'a> {
+    codes: CodeIterator<'a>,
+    original_size: usize,
+    output_size: usize,
+    window_size: usize,
+}
+
+impl<'a> SymbolIterator<'a> {
+    /// Create an iterator over the symbols of the compressed data in `src`, using the `algo` decompression algorithm.
+    ///
+    /// [`DecompressionAlgorithm::AutoDetect`] checks the whole stream with the UEFI algorithm before the iteration
+    /// starts, so it costs an extra decoding pass.
+    pub fn new(src: &'a [u8], algo: DecompressionAlgorithm) -> Result<Self, DecompressError> {
+        Self::with_window_size(src, algo, None)
+    }
+
+    /// Create an iterator like [`SymbolIterator::new`], with a sliding window of `window_size` bytes rather than the
+    /// [`DecompressionAlgorithm::window_size`] of the algorithm.
+    pub fn new_with_window_size(
+        src: &'a [u8],
+        algo: DecompressionAlgorithm,
+        window_size: usize,
+    ) -> Result<Self, DecompressError> {
+        Self::with_window_size(src, algo, Some(window_size))
+    }
+
+    fn with_window_size(
+        src: &'a [u8],
+        algo: DecompressionAlgorithm,
+        window_size: Option<usize>,
+    ) -> Result<Self, DecompressError> {
+        if let DecompressionAlgorithm::AutoDetect = algo {
+            let uefi = DecompressionAlgorithm::UefiDecompress;
+            return match Self::with_window_size(src, uefi, window_size)?.find_map(Result::err) {
+                Some(err) if is_algorithm_mismatch(&err) => {
+                    Self::with_window_size(src, DecompressionAlgorithm::TianoDecompress, window_size)
+                }
+                _ => Self::with_window_size(src, uefi, window_size),
+            };
+        }
+
+        //sanity check the inputs
+        let compressed_size = header_size(src, 0, DecompressError::InvalidSrcSize)?;
+        if compressed_size > src.len() {
+            Err(DecompressError::InvalidSrcSize)?;
+        }
+
+        let original_size = header_size(src, 4, DecompressError::InvalidDstSize)?;
+        Ok(Self {
+            codes: CodeIterator::new(&src[8..], algo),
+            original_size,
+            output_size: 0,
+            window_size: window_size.unwrap_or(algo.window_size()),
+        })
+    }
+
+    /// Returns the size of the decompressed data, as recorded in the header of the compressed data.
+    pub fn original_size(&self) -> usize {
+        self.original_size
+    }
+
+    /// Returns the size of the sliding window, the largest distance of a back-reference.
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+}
+
+impl Iterator for SymbolIterator<'_> {
+    type Item = Result<CodeSymbol, DecompressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Decompression is complete.
+        if self.output_size == self.original_size {
+            return None;
+        }
+
+        let result = match self.codes.next()? {
+            Ok(CodeSymbol::BackReference { distance, .. }) if distance > self.window_size => {
+                // the back-reference reaches beyond the compressor window, the stream is crafted or of another algorithm.
+                self.codes.is_error = true;
+                Err(DecompressError::WindowExceeded)
+            }
+            Ok(CodeSymbol::BackReference { distance, .. }) if distance > self.output_size => {
+                // the window starts before the beginning of the output.
+                self.codes.is_error = true;
+                Err(DecompressError::MalformedSrcData)
+            }
+            Ok(CodeSymbol::BackReference { distance, len }) => {
+                // the copy stops at the end of the output.
+                let len = len.min(self.original_size - self.output_size);
+                self.output_size += len;
+                Ok(CodeSymbol::BackReference { distance, len })
+            }
+            Ok(CodeSymbol::Literal(char)) => {
+                self.output_size += 1;
+                Ok(CodeSymbol::Literal(char))
+            }
+            Err(err) => Err(err),
+        };
+        Some(result)
+    }
 }
 
 //Nomenclature: Char&Len set = 'C', Position set = 'P', Extra set = 'T'
@@ -132,6 +478,7 @@ impl<'a> CodeIterator<'a> {
             p_bit: match algo {
                 DecompressionAlgorithm::UefiDecompress => 4,
                 DecompressionAlgorithm::TianoDecompress => 5,
+                DecompressionAlgorithm::AutoDetect => unreachable!("AutoDetect is resolved before decoding."),
             },
         }
     }
@@ -609,7 +956,11 @@ impl<'a> Iterator for CodeIterator<'a> {
 
             //Read new block size.
             self.remaining_block_size = match self.pop_bits(16) {
-                Ok(bits) => bits.load_be::<u16>() as usize,
+                // EDK2 decrements its 16-bit block size before checking it, so a block size of 0 holds 65536 symbols.
+                Ok(bits) => match bits.load_be::<u16>() {
+                    0 => 0x10000,
+                    block_size => block_size as usize,
+                },
                 Err(err) => {
                     self.is_error = true;
                     return Some(Err(err));
@@ -678,7 +1029,7 @@ impl<'a> Iterator for CodeIterator<'a> {
         //convert the symbol to the appropriate CodeSymbol
         if decode_idx < 256 {
             // symbols from 0-255 are byte literals.
-            Some(Ok(CodeSymbol::OrigChar(decode_idx as u8)))
+            Some(Ok(CodeSymbol::Literal(decode_idx as u8)))
         } else {
             // symbols greater than 255 are string lengths.
             let len = decode_idx - (0x100 - 3);
@@ -692,7 +1043,8 @@ impl<'a> Iterator for CodeIterator<'a> {
                 }
             };
 
-            Some(Ok(CodeSymbol::StrPointer(pos, len)))
+            // the position is the distance minus one.
+            Some(Ok(CodeSymbol::BackReference { distance: pos + 1, len }))
         }
     }
 }
@@ -700,9 +1052,14 @@ impl<'a> Iterator for CodeIterator<'a> {
 #[cfg(test)]
 mod test {
     extern crate std;
-    use std::{fs::File, io::Read, iter::zip, println, time, vec, vec::Vec};
+    use std::{fs::File, io::Read, iter::zip, vec, vec::Vec};
 
-    use crate::decompress_into_with_algo;
+    use crate::{
+        decompress, decompress_into_with_algo, decompress_into_with_options,
+        fuzzing::{self, Entropy},
+        CodeSymbol, DecompressError, DecompressOptions, DecompressionAlgorithm, PartialDecompressError, SymbolIterator,
+        TIANO_WINDOW_SIZE, UEFI_WINDOW_SIZE,
+    };
 
     macro_rules! test_collateral {
         ($fname:expr) => {
@@ -756,9 +1113,354 @@ mod test {
         }
     }
 
+    #[test]
+    fn auto_detect_decompress_should_produce_expected_buffer() {
+        for (compressed, uncompressed) in [
+            (test_collateral!("uefi_compressed.bin"), test_collateral!("uefi_uncompressed.bin")),
+            (test_collateral!("tiano_compressed.bin"), test_collateral!("tiano_uncompressed.bin")),
+        ] {
+            let mut compressed_buffer = Vec::new();
+            File::open(compressed)
+                .expect("failed to open test file")
+                .read_to_end(&mut compressed_buffer)
+                .expect("failed to read test file");
+
+            let mut uncompressed_buffer = Vec::new();
+            File::open(uncompressed)
+                .expect("failed to open test file")
+                .read_to_end(&mut uncompressed_buffer)
+                .expect("failed to read test file");
+
+            let mut test_buffer = vec![0u8; uncompressed_buffer.len()];
+
+            decompress_into_with_algo(&compressed_buffer, &mut test_buffer, crate::DecompressionAlgorithm::AutoDetect)
+                .unwrap();
+            assert!(test_buffer == uncompressed_buffer, "auto detect mismatch for {}", compressed);
+        }
+    }
+
+    #[test]
+    fn back_references_should_be_bounded_by_window_size() {
+        // 4KB of noise repeated once, so the repetition is a back-reference 4KB away.
+        let mut entropy = Entropy::from_seed(0);
+        let mut data = (0..0x1000).map(|_| entropy.below(0x100) as u8).collect::<Vec<_>>();
+        data.extend_from_within(..);
+
+        for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+            let compressed = fuzzing::compress(&data, algo);
+            let mut test_buffer = vec![0; data.len()];
+            let options = |window_size| DecompressOptions { window_size: Some(window_size), ..Default::default() };
+            decompress_into_with_options(&compressed, &mut test_buffer, algo, options(0x1000)).unwrap();
+            assert_eq!(data, test_buffer);
+            assert!(matches!(
+                decompress_into_with_options(&compressed, &mut test_buffer, algo, options(0x800)),
+                Err(PartialDecompressError { error: DecompressError::WindowExceeded, .. })
+            ));
+            let symbols = SymbolIterator::new_with_window_size(&compressed, algo, 0x800).unwrap();
+            assert_eq!(0x800, symbols.window_size());
+            assert!(matches!(symbols.last(), Some(Err(DecompressError::WindowExceeded))));
+        }
+
+        let compressed = fuzzing::compress(&data, DecompressionAlgorithm::UefiDecompress);
+        let symbols = SymbolIterator::new(&compressed, DecompressionAlgorithm::UefiDecompress).unwrap();
+        assert_eq!(UEFI_WINDOW_SIZE, symbols.window_size());
+        assert_eq!(TIANO_WINDOW_SIZE, DecompressionAlgorithm::TianoDecompress.window_size());
+    }
+
+    #[test]
+    fn recovery_should_report_partial_output() {
+        let mut entropy = Entropy::from_seed(3);
+        let data = (0..0x4000).map(|_| b"FIRMWARE"[entropy.below(8)]).collect::<Vec<u8>>();
+
+        for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+            let compressed = fuzzing::compress(&data, algo);
+            let mut test_buffer = vec![0u8; data.len()];
+            decompress_into_with_options(&compressed, &mut test_buffer, algo, DecompressOptions::default()).unwrap();
+            assert_eq!(data, test_buffer);
+
+            // a truncated stream, with the compressed size of the header updated to match.
+            let mut truncated = compressed[..compressed.len() * 3 / 4].to_vec();
+            let truncated_len = (truncated.len() - 8) as u32;
+            truncated[0..4].copy_from_slice(&truncated_len.to_le_bytes());
+            let mut test_buffer = vec![0u8; data.len()];
+            let err = decompress_into_with_options(&truncated, &mut test_buffer, algo, Default::default()).unwrap_err();
+            assert!(matches!(err.error, DecompressError::MalformedSrcData));
+            assert!(err.produced > data.len() / 2 && err.produced < data.len(), "{:?}", err);
+            assert_eq!(data[..err.produced], test_buffer[..err.produced]);
+        }
+
+        let mut test_buffer = vec![0u8; 4];
+        let algo = DecompressionAlgorithm::UefiDecompress;
+        let err = decompress_into_with_options(&[0; 4], &mut test_buffer, algo, Default::default()).unwrap_err();
+        assert!(matches!(err.error, DecompressError::InvalidSrcSize));
+        assert_eq!(0, err.produced);
+
+        let compressed = fuzzing::compress(&data, algo);
+        let mut test_buffer = vec![0u8; data.len()];
+        let algo = DecompressionAlgorithm::AutoDetect;
+        let err = decompress_into_with_options(&compressed, &mut test_buffer, algo, Default::default()).unwrap_err();
+        assert!(matches!(err.error, DecompressError::UnknownAlgorithm));
+        assert_eq!(0, err.produced);
+    }
+
+    #[test]
+    fn options_should_validate_buffer_sizes() {
+        let data = std::fs::read(test_collateral!("tiano_uncompressed.bin")).expect("failed to read test file");
+        let compressed = std::fs::read(test_collateral!("tiano_compressed.bin")).expect("failed to read test file");
+        let algo = DecompressionAlgorithm::TianoDecompress;
+        let strict = DecompressOptions::default;
+        let lenient =
+            || DecompressOptions { allow_trailing_bytes: true, require_exact_dst: false, ..Default::default() };
+
+        let mut test_buffer = vec![0u8; data.len()];
+        for options in [strict(), lenient()] {
+            assert_eq!(data.len(), decompress_into_with_options(&compressed, &mut test_buffer, algo, options).unwrap());
+            assert_eq!(data, test_buffer);
+        }
+
+        // a section padded to an 8-byte alignment, the padding bytes are never decoded.
+        let mut padded = compressed.clone();
+        padded.resize(compressed.len().next_multiple_of(8) + 8, 0xFF);
+        assert!(matches!(
+            decompress_into_with_options(&padded, &mut test_buffer, algo, strict()),
+            Err(PartialDecompressError { error: DecompressError::InvalidSrcSize, produced: 0 })
+        ));
+        test_buffer.fill(0);
+        decompress_into_with_options(&padded, &mut test_buffer, algo, lenient()).unwrap();
+        assert_eq!(data, test_buffer);
+
+        // a truncated stream, whose header still records the full compressed size.
+        let truncated = &compressed[..compressed.len() - 1];
+        for options in [strict(), lenient()] {
+            assert!(matches!(
+                decompress_into_with_options(truncated, &mut test_buffer, algo, options),
+                Err(PartialDecompressError { error: DecompressError::InvalidSrcSize, .. })
+            ));
+        }
+        assert!(matches!(
+            decompress_into_with_options(&compressed[..4], &mut test_buffer, algo, lenient()),
+            Err(PartialDecompressError { error: DecompressError::InvalidSrcSize, .. })
+        ));
+
+        // a larger destination is only accepted without an exact destination, and is left untouched after the output.
+        let mut large_buffer = vec![0xA5u8; data.len() + 16];
+        assert!(matches!(
+            decompress_into_with_options(&compressed, &mut large_buffer, algo, strict()),
+            Err(PartialDecompressError { error: DecompressError::InvalidDstSize, .. })
+        ));
+        assert_eq!(data.len(), decompress_into_with_options(&compressed, &mut large_buffer, algo, lenient()).unwrap());
+        assert_eq!(data, large_buffer[..data.len()]);
+        assert!(large_buffer[data.len()..].iter().all(|&byte| byte == 0xA5));
+        assert!(matches!(
+            decompress_into_with_options(&compressed, &mut test_buffer[1..], algo, lenient()),
+            Err(PartialDecompressError { error: DecompressError::InvalidDstSize, .. })
+        ));
+    }
+
+    #[test]
+    fn observer_should_see_the_whole_output_once() {
+        for (compressed, uncompressed, algo) in [
+            (
+                test_collateral!("uefi_compressed.bin"),
+                test_collateral!("uefi_uncompressed.bin"),
+                DecompressionAlgorithm::UefiDecompress,
+            ),
+            (
+                test_collateral!("tiano_compressed.bin"),
+                test_collateral!("tiano_uncompressed.bin"),
+                DecompressionAlgorithm::TianoDecompress,
+            ),
+        ] {
+            let compressed = std::fs::read(compressed).expect("failed to read test file");
+            let data = std::fs::read(uncompressed).expect("failed to read test file");
+
+            let mut test_buffer = vec![0u8; data.len()];
+            let mut chunks = Vec::new();
+            let mut on_output = |chunk: &[u8]| chunks.push(chunk.to_vec());
+            let options = DecompressOptions { on_output: Some(&mut on_output), ..Default::default() };
+            decompress_into_with_options(&compressed, &mut test_buffer, algo, options).unwrap();
+            assert_eq!(data, test_buffer);
+            assert_eq!(data, chunks.concat());
+            assert!(chunks.len() > 1);
+            assert!(chunks[..chunks.len() - 1].iter().all(|chunk| chunk.len() >= super::OUTPUT_CHUNK_SIZE));
+        }
+    }
+
+    // Sizes of the header up to u32::MAX, which overflow `compressed_size + 8` on 32-bit targets and do not fit a usize
+    // on 16-bit ones.
+    #[test]
+    fn maximum_header_sizes_should_be_rejected() {
+        let algo = DecompressionAlgorithm::TianoDecompress;
+        let mut max_compressed = vec![0u8; 16];
+        max_compressed[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        max_compressed[4..8].copy_from_slice(&4u32.to_le_bytes());
+        let mut max_original = vec![0u8; 16];
+        max_original[0..4].copy_from_slice(&8u32.to_le_bytes());
+        max_original[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut dst = [0u8; 4];
+        let lenient =
+            || DecompressOptions { allow_trailing_bytes: true, require_exact_dst: false, ..Default::default() };
+        assert!(matches!(
+            decompress_into_with_options(&max_compressed, &mut dst, algo, lenient()),
+            Err(PartialDecompressError { error: DecompressError::InvalidSrcSize, .. })
+        ));
+        assert!(matches!(
+            decompress_into_with_algo(&max_compressed, &mut dst, algo),
+            Err(DecompressError::InvalidSrcSize)
+        ));
+
+        assert!(matches!(
+            decompress_into_with_options(&max_original, &mut dst, algo, lenient()),
+            Err(PartialDecompressError { error: DecompressError::InvalidDstSize, .. })
+        ));
+        assert!(matches!(
+            decompress_into_with_algo(&max_original, &mut dst, algo),
+            Err(DecompressError::InvalidDstSize)
+        ));
+        assert!(matches!(
+            decompress_into_with_options(&max_original[..6], &mut dst, algo, lenient()),
+            Err(PartialDecompressError { error: DecompressError::InvalidSrcSize, .. })
+        ));
+    }
+
+    #[test]
+    fn decompress_should_allocate_original_size() {
+        let data = std::fs::read(test_collateral!("tiano_uncompressed.bin")).expect("failed to read test file");
+        let compressed = std::fs::read(test_collateral!("tiano_compressed.bin")).expect("failed to read test file");
+
+        let mut padded = compressed.clone();
+        padded.resize(compressed.len() + 7, 0xFF);
+        for src in [&compressed, &padded] {
+            assert_eq!(data, decompress(src, DecompressionAlgorithm::AutoDetect).unwrap());
+        }
+        assert!(matches!(
+            decompress(&compressed[..7], DecompressionAlgorithm::AutoDetect),
+            Err(DecompressError::InvalidSrcSize)
+        ));
+    }
+
+    #[test]
+    fn symbol_iterator_should_describe_expected_buffer() {
+        for (compressed, uncompressed, algo) in [
+            (
+                test_collateral!("uefi_compressed.bin"),
+                test_collateral!("uefi_uncompressed.bin"),
+                DecompressionAlgorithm::UefiDecompress,
+            ),
+            (
+                test_collateral!("tiano_compressed.bin"),
+                test_collateral!("tiano_uncompressed.bin"),
+                DecompressionAlgorithm::TianoDecompress,
+            ),
+            (
+                test_collateral!("tiano_compressed.bin"),
+                test_collateral!("tiano_uncompressed.bin"),
+                DecompressionAlgorithm::AutoDetect,
+            ),
+        ] {
+            let compressed_buffer = std::fs::read(compressed).expect("failed to read test file");
+            let uncompressed_buffer = std::fs::read(uncompressed).expect("failed to read test file");
+
+            let symbols = SymbolIterator::new(&compressed_buffer, algo).unwrap();
+            assert_eq!(uncompressed_buffer.len(), symbols.original_size());
+
+            let mut test_buffer = Vec::new();
+            let mut back_references = 0;
+            for symbol in symbols {
+                match symbol.unwrap() {
+                    CodeSymbol::Literal(char) => test_buffer.push(char),
+                    CodeSymbol::BackReference { distance, len } => {
+                        back_references += 1;
+                        for _ in 0..len {
+                            test_buffer.push(test_buffer[test_buffer.len() - distance]);
+                        }
+                    }
+                }
+            }
+            assert!(back_references > 0);
+            assert!(test_buffer == uncompressed_buffer, "symbol mismatch for {}", compressed);
+        }
+    }
+
+    #[test]
+    fn symbol_iterator_should_match_generated_streams() {
+        const STREAM_COUNT: u64 = 100;
+        for seed in 0..STREAM_COUNT {
+            for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+                let stream = fuzzing::generate(&mut Entropy::from_seed(seed), algo);
+
+                // back-references are only yielded within the output, so this never panics on malformed streams.
+                let mut test_buffer = Vec::new();
+                let result = SymbolIterator::new(&stream.data, algo).and_then(|mut symbols| {
+                    symbols.try_for_each(|symbol| {
+                        match symbol? {
+                            CodeSymbol::Literal(char) => test_buffer.push(char),
+                            CodeSymbol::BackReference { distance, len } => {
+                                for _ in 0..len {
+                                    test_buffer.push(test_buffer[test_buffer.len() - distance]);
+                                }
+                            }
+                        }
+                        Ok(())
+                    })
+                });
+                if let Some(expected) = stream.expected {
+                    assert!(result.is_ok(), "seed {:} failed to decompress: {:?}", seed, result);
+                    assert!(test_buffer == expected, "seed {:} produced unexpected symbols", seed);
+                }
+            }
+        }
+        assert!(SymbolIterator::new(&[0; 4], DecompressionAlgorithm::UefiDecompress).is_err());
+    }
+
+    // Every `<algo>_compressed.bin` reference vector generated by the EDK2 C compressor in the test collateral must
+    // decompress to its `<algo>_uncompressed.bin` counterpart byte for byte.
+    #[test]
+    fn reference_vectors_should_have_byte_exact_parity() {
+        let mut vectors = 0;
+        for entry in std::fs::read_dir(test_collateral!("")).expect("failed to open test collateral directory") {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let Some(prefix) = name.strip_suffix("_compressed.bin") else {
+                continue;
+            };
+            let algo = match prefix.split('_').next() {
+                Some("uefi") => DecompressionAlgorithm::UefiDecompress,
+                Some("tiano") => DecompressionAlgorithm::TianoDecompress,
+                _ => DecompressionAlgorithm::AutoDetect,
+            };
+
+            let compressed_buffer = std::fs::read(&path).expect("failed to read test file");
+            let uncompressed_buffer = std::fs::read(path.with_file_name(std::format!("{}_uncompressed.bin", prefix)))
+                .expect("failed to read test file");
+            let mut test_buffer = vec![0u8; uncompressed_buffer.len()];
+            decompress_into_with_algo(&compressed_buffer, &mut test_buffer, algo).unwrap();
+            let mismatch = zip(&test_buffer, &uncompressed_buffer).position(|(test, reference)| test != reference);
+            assert_eq!(None, mismatch, "{} differs from the reference output", name);
+            vectors += 1;
+        }
+        assert!(vectors >= 2, "missing reference vectors");
+    }
+
+    #[test]
+    fn reference_compressor_should_round_trip() {
+        let collateral = std::fs::read(test_collateral!("uefi_uncompressed.bin")).expect("failed to read test file");
+        let mut entropy = Entropy::from_seed(1);
+        let random = (0..0x2000).map(|_| entropy.below(0x10) as u8).collect::<Vec<_>>();
+        for data in [&collateral[..], &random, &[0; 0x10000], &[7], &[]] {
+            for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+                let compressed = fuzzing::compress(data, algo);
+                let mut test_buffer = vec![0u8; data.len()];
+                decompress_into_with_algo(&compressed, &mut test_buffer, algo).unwrap();
+                assert!(test_buffer == data, "round trip mismatch for {} bytes", data.len());
+            }
+        }
+    }
+
     #[test]
     fn fuzz_testing_should_fail_gracefully() {
-        const FUZZ_COUNT: usize = 100;
+        const FUZZ_COUNT: u64 = 100;
         let mut compressed_file =
             File::open(test_collateral!("uefi_compressed.bin")).expect("failed to open test file");
         let mut compressed_buffer = Vec::new();
@@ -772,13 +1474,10 @@ mod test {
 
         let uncompressed_len = uncompressed_buffer.len();
 
-        for _ in 0..FUZZ_COUNT {
+        for seed in 0..FUZZ_COUNT {
             let mut fuzz_buffer = compressed_buffer.clone();
-            let fuzz_time = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_micros() as usize;
-            let fuzz_idx = fuzz_time % fuzz_buffer.len();
-            println!("fuzz_idx: {:} before: {:#x}", fuzz_idx, fuzz_buffer[fuzz_idx]);
+            let fuzz_idx = Entropy::from_seed(seed).below(fuzz_buffer.len());
             fuzz_buffer[fuzz_idx] ^= 0xff;
-            println!("fuzz_idx: {:} after: {:#x}", fuzz_idx, fuzz_buffer[fuzz_idx]);
 
             let mut test_buffer = vec![0u8; uncompressed_len];
 
@@ -791,4 +1490,68 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn generated_streams_should_decompress() {
+        const STREAM_COUNT: u64 = 100;
+        for seed in 0..STREAM_COUNT {
+            for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+                let stream = fuzzing::generate(&mut Entropy::from_seed(seed), algo);
+                let orig_size = u32::from_le_bytes(stream.data[4..8].try_into().unwrap()) as usize;
+                let mut test_buffer = vec![0u8; orig_size];
+
+                let result = decompress_into_with_algo(&stream.data, &mut test_buffer, algo);
+                if let Some(expected) = stream.expected {
+                    assert!(result.is_ok(), "seed {:} failed to decompress: {:?}", seed, result);
+                    assert!(test_buffer == expected, "seed {:} produced unexpected output", seed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zero_block_size_should_hold_65536_symbols() {
+        let data = b"FIRMWARE VOLUME ".repeat(64);
+        for algo in [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress] {
+            // The stream has a single block, its size is the first 16 bits after the header.
+            let mut compressed = fuzzing::compress(&data, algo);
+            compressed[8..10].fill(0);
+            let mut test_buffer = vec![0u8; data.len()];
+            decompress_into_with_algo(&compressed, &mut test_buffer, algo).unwrap();
+            assert_eq!(data, test_buffer);
+        }
+    }
+
+    #[test]
+    fn regression_corpus_should_fail_gracefully() {
+        let corpus = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/fuzz_corpus"))
+            .expect("failed to open corpus directory");
+        for entry in corpus {
+            let mut compressed_buffer = Vec::new();
+            File::open(entry.unwrap().path())
+                .expect("failed to open corpus file")
+                .read_to_end(&mut compressed_buffer)
+                .expect("failed to read corpus file");
+
+            let orig_size = compressed_buffer.get(4..8).map_or(0, |size| u32::from_le_bytes(size.try_into().unwrap()));
+            let mut test_buffer = vec![0u8; orig_size as usize];
+            let _ = decompress_into_with_algo(&compressed_buffer, &mut test_buffer, DecompressionAlgorithm::AutoDetect);
+        }
+    }
+
+    // Regenerates the regression corpus used by `regression_corpus_should_fail_gracefully` and as the seed corpus of
+    // the cargo-fuzz targets: `cargo test -p uefi_decompress -- --ignored generate_fuzz_corpus`
+    #[test]
+    #[ignore]
+    fn generate_fuzz_corpus() {
+        const CORPUS_SIZE: u64 = 32;
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/fuzz_corpus");
+        std::fs::create_dir_all(corpus).unwrap();
+        for seed in 0..CORPUS_SIZE {
+            let algo =
+                [DecompressionAlgorithm::UefiDecompress, DecompressionAlgorithm::TianoDecompress][seed as usize % 2];
+            let stream = fuzzing::generate(&mut Entropy::from_seed(seed), algo);
+            std::fs::write(std::format!("{}/generated_{:02}.bin", corpus, seed), stream.data).unwrap();
+        }
+    }
 }
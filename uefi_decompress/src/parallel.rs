@@ -0,0 +1,104 @@
+//! Multi-threaded decompression of many compressed buffers, for host-side tooling unpacking firmware volumes.
+//!
+//! The blocks of a single compressed stream can not be decoded independently: back-references reach into the previous
+//! blocks through the sliding window, and a block only starts at a known bit position once the previous one has been
+//! decoded. The parallelism is therefore across streams, such as the compressed sections of a firmware volume, each
+//! stream being decoded by a single thread.
+//!
+//! ```ignore
+//! let sections: Vec<&[u8]> = fv.compressed_sections().collect();
+//! for result in uefi_decompress::parallel::decompress_all(&sections, DecompressionAlgorithm::AutoDetect) {
+//!     write_section(&result?)?;
+//! }
+//! ```
+
+extern crate std;
+
+use std::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    vec::Vec,
+};
+
+use crate::{decompress, DecompressError, DecompressionAlgorithm};
+
+/// Decompresses each of *sources* into a new buffer with the *algo* algorithm.
+///
+/// Returns the results in the order of *sources*, see [`decompress_all_with_threads`]. The number of threads is the
+/// available parallelism of the host.
+pub fn decompress_all(sources: &[&[u8]], algo: DecompressionAlgorithm) -> Vec<Result<Vec<u8>, DecompressError>> {
+    let threads = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+    decompress_all_with_threads(sources, algo, threads)
+}
+
+/// Decompresses each of *sources* into a new buffer with the *algo* algorithm, using at most *threads* threads.
+///
+/// Each source is decompressed as with [`decompress`].
+///
+/// Returns the results in the order of *sources*. The threads take the next source as soon as they are done with the
+/// previous one, so sources of very different sizes are balanced between the threads.
+pub fn decompress_all_with_threads(
+    sources: &[&[u8]],
+    algo: DecompressionAlgorithm,
+    threads: NonZeroUsize,
+) -> Vec<Result<Vec<u8>, DecompressError>> {
+    let next = AtomicUsize::new(0);
+    let worker = || {
+        let mut results = Vec::new();
+        loop {
+            let idx = next.fetch_add(1, Ordering::Relaxed);
+            let Some(src) = sources.get(idx) else {
+                return results;
+            };
+            results.push((idx, decompress(src, algo)));
+        }
+    };
+
+    let mut results = Vec::with_capacity(sources.len());
+    results.resize_with(sources.len(), || Err(DecompressError::InvalidSrcSize));
+    thread::scope(|scope| {
+        // The calling thread is one of the workers.
+        let workers = (1..threads.get().min(sources.len())).map(|_| scope.spawn(worker)).collect::<Vec<_>>();
+        let done = workers.into_iter().flat_map(|w| w.join().expect("decompression thread panicked")).chain(worker());
+        for (idx, result) in done {
+            results[idx] = result;
+        }
+    });
+    results
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fuzzing::{self, Entropy};
+    use std::vec;
+
+    #[test]
+    fn decompress_all_should_match_sequential_decompression() {
+        let mut entropy = Entropy::from_seed(7);
+        let payloads = (0..13)
+            .map(|idx| (0..idx * 997).map(|_| b"EFI_FV"[entropy.below(6)]).collect::<Vec<u8>>())
+            .collect::<Vec<_>>();
+        let mut sources =
+            payloads.iter().map(|p| fuzzing::compress(p, DecompressionAlgorithm::TianoDecompress)).collect::<Vec<_>>();
+        sources[5] = vec![0; 4];
+        let sources = sources.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        for threads in [1, 3, 16] {
+            let results = decompress_all_with_threads(
+                &sources,
+                DecompressionAlgorithm::AutoDetect,
+                NonZeroUsize::new(threads).unwrap(),
+            );
+            assert_eq!(sources.len(), results.len());
+            for (idx, (result, payload)) in results.iter().zip(&payloads).enumerate() {
+                match idx {
+                    5 => assert!(matches!(result, Err(DecompressError::InvalidSrcSize))),
+                    _ => assert_eq!(payload, result.as_ref().unwrap()),
+                }
+            }
+        }
+        assert!(decompress_all(&[], DecompressionAlgorithm::UefiDecompress).is_empty());
+    }
+}
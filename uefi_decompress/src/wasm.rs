@@ -0,0 +1,29 @@
+//! JavaScript bindings of the decoder, so browser-based firmware inspection tools use the same decoder as the firmware.
+//!
+//! Build for `wasm32-unknown-unknown` with the `wasm` feature and generate the JavaScript glue with wasm-bindgen:
+//!
+//! ```sh
+//! cargo build -p uefi_decompress --release --target wasm32-unknown-unknown --features wasm
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/uefi_decompress.wasm
+//! ```
+//!
+//! ```js
+//! import init, { decompress } from "./pkg/uefi_decompress.js";
+//!
+//! await init();
+//! const section = decompress(new Uint8Array(compressedSection));
+//! ```
+
+use alloc::{format, vec::Vec};
+
+use wasm_bindgen::prelude::*;
+
+use crate::DecompressionAlgorithm;
+
+/// Decompresses `src`, compressed with either the UEFI or the Tiano algorithm, see [`crate::decompress`].
+///
+/// Errors are thrown as a JavaScript `Error` naming the [`DecompressError`](crate::DecompressError).
+#[wasm_bindgen]
+pub fn decompress(src: Vec<u8>) -> Result<Vec<u8>, JsError> {
+    crate::decompress(&src, DecompressionAlgorithm::AutoDetect).map_err(|err| JsError::new(&format!("{:?}", err)))
+}